@@ -0,0 +1,5 @@
+fn main() {
+    // Generating the gRPC code requires `protoc`, so it is only done with the `grpc` feature.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/intmax.proto").expect("fail to compile proto/intmax.proto");
+}
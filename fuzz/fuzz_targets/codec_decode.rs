@@ -0,0 +1,22 @@
+//! Decodes arbitrary bytes with the bincode codec.
+
+#![no_main]
+
+use intmax_zkp_core::{
+    codec, rollup::block::BlockInfo,
+    sparse_merkle_tree::gadgets::process::process_smt::SmtProcessProof,
+    transaction::circuits::MergeAndPurgeTransitionProofWithPublicInputs,
+};
+use libfuzzer_sys::fuzz_target;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(proof) = codec::decode::<SmtProcessProof<F>>(data) {
+        assert_eq!(codec::encode(&proof).unwrap(), data);
+    }
+    let _ = codec::decode::<MergeAndPurgeTransitionProofWithPublicInputs<F, C, 2>>(data);
+    let _ = codec::decode::<BlockInfo<F>>(data);
+});
@@ -0,0 +1,20 @@
+//! Parses and verifies arbitrary bytes as a proof of the simple signature circuit.
+
+#![no_main]
+
+use std::sync::OnceLock;
+
+use intmax_zkp_core::{
+    verification::verifier::SimpleSignatureVerifier, zkdsa::circuits::make_simple_signature_circuit,
+};
+use libfuzzer_sys::fuzz_target;
+use plonky2::{field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig};
+
+static VERIFIER: OnceLock<SimpleSignatureVerifier<GoldilocksField, PoseidonGoldilocksConfig, 2>> =
+    OnceLock::new();
+
+fuzz_target!(|data: &[u8]| {
+    let verifier = VERIFIER.get_or_init(|| make_simple_signature_circuit().verifier());
+
+    assert!(verifier.verify(data).is_err());
+});
@@ -0,0 +1,22 @@
+//! Decodes arbitrary public inputs of the user transaction and block circuits.
+
+#![no_main]
+
+use intmax_zkp_core::verification::public_inputs::{
+    BlockPublicInputs, UserTransactionPublicInputs,
+};
+use libfuzzer_sys::fuzz_target;
+use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+
+fuzz_target!(|input: (u8, u8, Vec<u64>)| {
+    let (n_txs, n_deposits, elements) = input;
+    let public_inputs = elements
+        .into_iter()
+        .map(GoldilocksField::from_noncanonical_u64)
+        .collect::<Vec<_>>();
+
+    if let Ok(decoded) = UserTransactionPublicInputs::try_decode(&public_inputs) {
+        assert_eq!(decoded.encode(), public_inputs);
+    }
+    let _ = BlockPublicInputs::try_decode(&public_inputs, n_txs as usize, n_deposits as usize);
+});
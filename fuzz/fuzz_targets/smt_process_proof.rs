@@ -0,0 +1,29 @@
+//! Malformed process proofs from the network must be rejected without panicking.
+
+#![no_main]
+
+use intmax_zkp_core::{
+    sparse_merkle_tree::gadgets::process::process_smt::SmtProcessProof,
+    verification::merkle::verify_smt_process_proof,
+};
+use libfuzzer_sys::fuzz_target;
+use plonky2::{field::goldilocks_field::GoldilocksField, hash::hash_types::HashOut};
+
+fuzz_target!(|proof: SmtProcessProof<GoldilocksField>| {
+    let siblings = proof
+        .siblings
+        .iter()
+        .map(|v| **v)
+        .collect::<Vec<HashOut<GoldilocksField>>>();
+    let _ = verify_smt_process_proof(
+        *proof.old_root,
+        *proof.old_key,
+        *proof.old_value,
+        *proof.new_root,
+        *proof.new_key,
+        *proof.new_value,
+        proof.is_old0,
+        proof.fnc.into(),
+        &siblings,
+    );
+});
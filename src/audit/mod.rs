@@ -0,0 +1,269 @@
+//! Machine-readable descriptions of the circuits for security auditors.
+//!
+//! A `CircuitReport` lists the gates of a built circuit and the layout of its public inputs,
+//! labeled with the names used in this crate, together with the target each public input is read from.
+//! plonky2 does not keep the copy constraints in `CircuitData` after the build,
+//! so the other wire connections are not included.
+
+use std::path::Path;
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    plonk::{circuit_data::CircuitData, config::GenericConfig},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::get_circuit_digest;
+
+/// A named range of the public inputs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicInputField {
+    /// e.g. `"purge diff root"`
+    pub name: String,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl PublicInputField {
+    fn new(name: impl Into<String>, offset: usize, len: usize) -> Self {
+        Self {
+            name: name.into(),
+            offset,
+            len,
+        }
+    }
+}
+
+/// Builds a layout from the names and lengths of consecutive fields.
+fn make_layout<S: Into<String>>(
+    fields: impl IntoIterator<Item = (S, usize)>,
+) -> Vec<PublicInputField> {
+    let mut offset = 0;
+    fields
+        .into_iter()
+        .map(|(name, len)| {
+            let field = PublicInputField::new(name, offset, len);
+            offset += len;
+
+            field
+        })
+        .collect()
+}
+
+/// The layout of `UserTransactionPublicInputs`.
+pub fn user_transaction_public_input_layout() -> Vec<PublicInputField> {
+    make_layout([
+        ("old user asset root", 4),
+        ("middle user asset root (after merge)", 4),
+        ("new user asset root (after purge)", 4),
+        ("purge diff root", 4),
+        ("sender address", 4),
+        ("tx hash", 4),
+        ("burned assets digest", 4),
+        ("protocol version", 1),
+    ])
+}
+
+/// The layout of `SignaturePublicInputs`.
+pub fn simple_signature_public_input_layout() -> Vec<PublicInputField> {
+    make_layout([("message", 4), ("public key", 4), ("signature", 4)])
+}
+
+/// The layout of `BlockPublicInputs`.
+pub fn block_public_input_layout(n_txs: usize, n_deposits: usize) -> Vec<PublicInputField> {
+    let mut fields = vec![];
+    for i in 0..n_txs {
+        fields.push((format!("sender #{} address", i), 4));
+        fields.push((format!("sender #{} approved", i), 1));
+    }
+    for i in 0..n_deposits {
+        fields.push((format!("deposit #{} receiver address", i), 4));
+        fields.push((format!("deposit #{} contract address", i), 4));
+        fields.push((format!("deposit #{} variable index", i), 4));
+        fields.push((format!("deposit #{} amount", i), 1));
+    }
+    for name in [
+        "old latest account tree root",
+        "new latest account tree root",
+        "old world state root",
+        "new world state root",
+        "old prev block header digest",
+        "new prev block header digest",
+        "block hash",
+        "proposed world state digest",
+        "approved world state digest",
+        "latest account digest",
+        "tx hash list digest",
+        "old forced transactions digest",
+        "new forced transactions digest",
+    ] {
+        fields.push((name.to_string(), 4));
+    }
+    fields.push(("burned assets digest".to_string(), 4));
+    fields.push(("withdrawal root".to_string(), 4));
+    fields.push(("protocol version".to_string(), 1));
+
+    make_layout(fields)
+}
+
+/// The layout of `BlockCommitmentPublicInputs`.
+pub fn block_commitment_public_input_layout() -> Vec<PublicInputField> {
+    make_layout([
+        ("block public inputs commitment", 4),
+        ("protocol version", 1),
+    ])
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GateReport {
+    /// The identifier of the gate given by plonky2, including its parameters.
+    pub id: String,
+    pub degree: usize,
+    pub num_wires: usize,
+    pub num_constants: usize,
+    pub num_constraints: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicInputReport {
+    pub index: usize,
+
+    /// e.g. `"purge diff root[2]"`
+    pub label: String,
+
+    /// The target registered as this public input, formatted with `Debug`.
+    pub target: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitReport {
+    /// e.g. `"user_transaction"`
+    pub name: String,
+    pub circuit_digest: String,
+    pub degree_bits: usize,
+    pub num_wires: usize,
+    pub num_routed_wires: usize,
+    pub num_constants: usize,
+    pub num_gate_constraints: usize,
+    pub quotient_degree_factor: usize,
+    pub gates: Vec<GateReport>,
+    pub public_input_layout: Vec<PublicInputField>,
+    pub public_inputs: Vec<PublicInputReport>,
+}
+
+impl CircuitReport {
+    /// Fails if `public_input_layout` does not cover the public inputs of the circuit exactly.
+    pub fn new<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+        name: impl Into<String>,
+        data: &CircuitData<F, C, D>,
+        public_input_layout: Vec<PublicInputField>,
+    ) -> anyhow::Result<Self> {
+        let public_input_targets = &data.prover_only.public_inputs;
+        let mut public_inputs = Vec::with_capacity(public_input_targets.len());
+        for field in public_input_layout.iter() {
+            anyhow::ensure!(
+                field.offset == public_inputs.len(),
+                "the layout has a gap or an overlap before {}",
+                field.name
+            );
+            for i in 0..field.len {
+                let index = field.offset + i;
+                let target = public_input_targets.get(index).ok_or_else(|| {
+                    anyhow::anyhow!("the layout is longer than the public inputs")
+                })?;
+                let label = if field.len == 1 {
+                    field.name.clone()
+                } else {
+                    format!("{}[{}]", field.name, i)
+                };
+                public_inputs.push(PublicInputReport {
+                    index,
+                    label,
+                    target: format!("{:?}", target),
+                });
+            }
+        }
+        anyhow::ensure!(
+            public_inputs.len() == public_input_targets.len(),
+            "the layout is shorter than the public inputs"
+        );
+
+        let gates = data
+            .common
+            .gates
+            .iter()
+            .map(|gate| GateReport {
+                id: gate.0.id(),
+                degree: gate.0.degree(),
+                num_wires: gate.0.num_wires(),
+                num_constants: gate.0.num_constants(),
+                num_constraints: gate.0.num_constraints(),
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.into(),
+            circuit_digest: format!("0x{}", get_circuit_digest(&data.verifier_only)),
+            degree_bits: data.common.degree_bits(),
+            num_wires: data.common.config.num_wires,
+            num_routed_wires: data.common.config.num_routed_wires,
+            num_constants: data.common.num_constants,
+            num_gate_constraints: data.common.num_gate_constraints,
+            quotient_degree_factor: data.common.quotient_degree_factor,
+            gates,
+            public_input_layout,
+            public_inputs,
+        })
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_report_simple_signature_circuit() {
+    use crate::zkdsa::circuits::make_simple_signature_circuit;
+
+    let circuit = make_simple_signature_circuit();
+    let report = CircuitReport::new(
+        "simple_signature",
+        &circuit.data,
+        simple_signature_public_input_layout(),
+    )
+    .unwrap();
+    assert_eq!(report.public_inputs.len(), 12);
+    assert_eq!(report.public_inputs[5].label, "public key[1]");
+    assert!(!report.gates.is_empty());
+
+    let decoded: CircuitReport = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(decoded, report);
+
+    assert!(CircuitReport::new(
+        "simple_signature",
+        &circuit.data,
+        user_transaction_public_input_layout(),
+    )
+    .is_err());
+}
+
+#[test]
+fn test_block_public_input_layout() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::verification::public_inputs::BlockPublicInputs;
+
+    let layout = block_public_input_layout(4, 2);
+    let last = layout.last().unwrap();
+    assert_eq!(
+        last.offset + last.len,
+        BlockPublicInputs::<GoldilocksField>::public_inputs_len(4, 2)
+    );
+}
@@ -0,0 +1,347 @@
+//! Build/prove/verify measurements of each circuit, enabled by the `bench` feature.
+
+use std::time::{Duration, Instant};
+
+use plonky2::{
+    field::types::Sample,
+    hash::hash_types::HashOut,
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_data::CircuitData,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fixtures::scenario::{
+        make_scenario_circuits, Scenario, ScenarioBlock, ScenarioMint, ScenarioTransaction,
+    },
+    prover::backend::CircuitWitness,
+    rollup::{circuits::make_block_proof_circuit, contract::make_counter_contract_circuit},
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::circuits::{make_user_proof_circuit, mint::make_mint_circuit},
+    zkdsa::{account::Address, circuits::make_simple_signature_circuit},
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitBenchmark {
+    /// e.g. `"user_transaction/small"`
+    pub name: String,
+    pub degree_bits: usize,
+    pub num_public_inputs: usize,
+    pub build_time: Duration,
+
+    /// `None` if the circuit was only built.
+    pub prove_time: Option<Duration>,
+    pub verify_time: Option<Duration>,
+
+    /// The size of the serialized proof with public inputs in bytes.
+    pub proof_size: Option<usize>,
+}
+
+impl CircuitBenchmark {
+    fn from_circuit_data(name: String, data: &CircuitData<F, C, D>, build_time: Duration) -> Self {
+        Self {
+            name,
+            degree_bits: data.common.degree_bits(),
+            num_public_inputs: data.common.num_public_inputs,
+            build_time,
+            prove_time: None,
+            verify_time: None,
+            proof_size: None,
+        }
+    }
+}
+
+pub fn bench_simple_signature_circuit() -> anyhow::Result<CircuitBenchmark> {
+    let start = Instant::now();
+    let circuit = make_simple_signature_circuit();
+    let build_time = start.elapsed();
+    let mut result = CircuitBenchmark::from_circuit_data(
+        "simple_signature".to_string(),
+        &circuit.data,
+        build_time,
+    );
+
+    let mut pw = PartialWitness::new();
+    circuit
+        .targets
+        .set_witness(&mut pw, HashOut::rand(), HashOut::rand());
+
+    let start = Instant::now();
+    let proof = circuit.prove(pw)?;
+    result.prove_time = Some(start.elapsed());
+
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = proof.clone().into();
+    result.proof_size = Some(proof_with_pis.to_bytes()?.len());
+
+    let start = Instant::now();
+    circuit.verify(proof)?;
+    result.verify_time = Some(start.elapsed());
+
+    Ok(result)
+}
+
+/// Proves a transaction which neither merges nor purges any asset,
+/// since the proving time does not depend on the witness.
+pub fn bench_user_transaction_circuit<
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+    const N_DIFFS: usize,
+    const N_MERGES: usize,
+>(
+    preset_name: &str,
+) -> anyhow::Result<CircuitBenchmark> {
+    let start = Instant::now();
+    let circuit = make_user_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >();
+    let build_time = start.elapsed();
+    let mut result = CircuitBenchmark::from_circuit_data(
+        format!("user_transaction/{}", preset_name),
+        &circuit.data,
+        build_time,
+    );
+
+    let mut pw = PartialWitness::new();
+    circuit.targets.set_witness(
+        &mut pw,
+        Address::rand(),
+        &[],
+        &[],
+        &[],
+        WrappedHashOut::rand(),
+        Default::default(),
+    )?;
+
+    let start = Instant::now();
+    let proof = circuit.prove(pw)?;
+    result.prove_time = Some(start.elapsed());
+
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = proof.clone().into();
+    result.proof_size = Some(proof_with_pis.to_bytes()?.len());
+
+    let start = Instant::now();
+    circuit.verify(proof)?;
+    result.verify_time = Some(start.elapsed());
+
+    Ok(result)
+}
+
+/// Proves and verifies a block of `fixtures::scenario`, in which one account pays another,
+/// mints a token and calls the counter contract. The block circuit has the parameters of the fixtures,
+/// and the build time includes its inner circuits.
+pub fn bench_block_circuit() -> anyhow::Result<CircuitBenchmark> {
+    let start = Instant::now();
+    let circuits = make_scenario_circuits();
+    let build_time = start.elapsed();
+    let mut result = CircuitBenchmark::from_circuit_data(
+        "block/fixtures".to_string(),
+        &circuits.block_circuit.data,
+        build_time,
+    );
+
+    let mut scenario = Scenario::new(&circuits, 2, 2)?;
+    let [alice, bob] = [0, 1];
+    let [token0, token1] = [0, 1];
+    scenario.genesis(&[
+        scenario.deposit(alice, token0, 10),
+        scenario.deposit(alice, token1, 1),
+    ])?;
+    let block = scenario.run_block(&ScenarioBlock {
+        deposits: vec![scenario.deposit(bob, token1, 1)],
+        transactions: vec![ScenarioTransaction {
+            sender: alice,
+            transfers: vec![scenario.transfer(bob, token0, 10)],
+            signs: true,
+        }],
+        mints: vec![ScenarioMint {
+            token: token0,
+            amount: 100,
+        }],
+        counter_inputs: vec![7],
+    })?;
+
+    let mut pw = PartialWitness::new();
+    block
+        .witness
+        .set_witness(&circuits.block_circuit, &mut pw)?;
+
+    let start = Instant::now();
+    let proof = circuits.block_circuit.prove(pw)?;
+    result.prove_time = Some(start.elapsed());
+
+    let proof_with_pis: ProofWithPublicInputs<F, C, D> = proof.clone().into();
+    result.proof_size = Some(proof_with_pis.to_bytes()?.len());
+
+    let start = Instant::now();
+    circuits.block_circuit.verify(proof)?;
+    result.verify_time = Some(start.elapsed());
+
+    Ok(result)
+}
+
+/// Measures only the build time of the block circuit (including its inner circuits) of a preset,
+/// because there are no fixtures of a consistent set of user transactions and signatures for it.
+pub fn bench_block_circuit_build<
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+    const N_DIFFS: usize,
+    const N_MERGES: usize,
+    const N_TXS: usize,
+    const N_DEPOSITS: usize,
+    const N_LOG_ISSUERS: usize,
+>(
+    preset_name: &str,
+) -> CircuitBenchmark {
+    let merge_and_purge_circuit = make_user_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >();
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let mint_circuit = make_mint_circuit::<
+        C,
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >(&simple_signature_circuit.data);
+
+    let start = Instant::now();
+    let block_circuit = make_block_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+        N_TXS,
+        N_DEPOSITS,
+    >(
+        &merge_and_purge_circuit,
+        &simple_signature_circuit,
+        &mint_circuit.data,
+        &make_counter_contract_circuit::<F, C, D>().data.common,
+    );
+    let build_time = start.elapsed();
+
+    CircuitBenchmark::from_circuit_data(
+        format!("block/{}", preset_name),
+        &block_circuit.data,
+        build_time,
+    )
+}
+
+/// Runs the benchmarks of the user transaction circuit and the build of the block circuit
+/// with the constants of a preset module of `config`, e.g. `dev_small`.
+macro_rules! bench_preset {
+    ($preset:ident) => {{
+        use crate::config::$preset::CONSTANTS;
+
+        bench_user_transaction_circuit::<
+            { CONSTANTS.n_log_max_users },
+            { CONSTANTS.n_log_max_txs },
+            { CONSTANTS.n_log_max_contracts },
+            { CONSTANTS.n_log_max_variables },
+            { CONSTANTS.n_log_txs },
+            { CONSTANTS.n_log_recipients },
+            { CONSTANTS.n_log_contracts },
+            { CONSTANTS.n_log_variables },
+            { CONSTANTS.n_diffs },
+            { CONSTANTS.n_merges },
+        >(stringify!($preset))
+        .map(|user_transaction| {
+            vec![
+                user_transaction,
+                bench_block_circuit_build::<
+                    { CONSTANTS.n_log_max_users },
+                    { CONSTANTS.n_log_max_txs },
+                    { CONSTANTS.n_log_max_contracts },
+                    { CONSTANTS.n_log_max_variables },
+                    { CONSTANTS.n_log_txs },
+                    { CONSTANTS.n_log_recipients },
+                    { CONSTANTS.n_log_contracts },
+                    { CONSTANTS.n_log_variables },
+                    { CONSTANTS.n_diffs },
+                    { CONSTANTS.n_merges },
+                    { CONSTANTS.n_txs },
+                    { CONSTANTS.n_deposits },
+                    { CONSTANTS.n_log_issuers },
+                >(stringify!($preset)),
+            ]
+        })
+    }};
+}
+
+/// Runs every benchmark with the `dev_small` and `testnet` presets, and proves the block of the fixtures.
+/// `mainnet` is too large to build on a development machine.
+pub fn bench_all() -> anyhow::Result<Vec<CircuitBenchmark>> {
+    Ok([
+        vec![bench_simple_signature_circuit()?, bench_block_circuit()?],
+        bench_preset!(dev_small)?,
+        bench_preset!(testnet)?,
+    ]
+    .concat())
+}
+
+#[test]
+fn test_bench_dev_small() {
+    let benchmarks = bench_preset!(dev_small).unwrap();
+    assert_eq!(benchmarks[0].name, "user_transaction/dev_small");
+    assert!(benchmarks[0].proof_size.is_some());
+    assert_eq!(benchmarks[1].name, "block/dev_small");
+    assert!(benchmarks[1].prove_time.is_none());
+
+    let block = bench_block_circuit().unwrap();
+    assert!(block.prove_time.is_some() && block.verify_time.is_some());
+    assert!(block.proof_size.unwrap() > 0);
+}
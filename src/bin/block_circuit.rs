@@ -21,6 +21,7 @@ use intmax_zkp_core::{
     merkle_tree::tree::{get_merkle_proof, MerkleProof},
     rollup::{
         circuits::make_block_proof_circuit,
+        contract::make_counter_contract_circuit,
         gadgets::{batch::BatchBlockProofTarget, deposit_block::DepositInfo},
     },
     sparse_merkle_tree::{
@@ -32,8 +33,9 @@ use intmax_zkp_core::{
     },
     transaction::{
         block_header::{get_block_hash, BlockHeader},
-        circuits::make_user_proof_circuit,
+        circuits::{make_user_proof_circuit, mint::make_mint_circuit},
         gadgets::merge::MergeProof,
+        merge_key::deposit_merge_key,
     },
     zkdsa::{
         account::{private_key_to_account, Address},
@@ -59,6 +61,7 @@ fn main() {
     const N_MERGES: usize = 2;
     const N_TXS: usize = 2usize.pow(N_LOG_TXS as u32);
     const N_BLOCKS: usize = 2;
+    const N_LOG_ISSUERS: usize = 3;
 
     let mut world_state_tree = PoseidonSparseMerkleTree::new(
         Arc::new(Mutex::new(NodeDataMemory::default())),
@@ -210,12 +213,16 @@ fn main() {
         proposed_world_state_digest: default_hash,
         approved_world_state_digest: default_hash,
         latest_account_digest: default_hash,
+        forced_transactions_digest: default_hash,
+        issuer_registry_root: default_hash,
+        timestamp: 0,
+        aggregator_address: default_hash,
     };
 
     let block_hash = get_block_hash(&prev_block_header);
 
     // deposit の場合は, `hash(tx_hash, block_hash)` を `merge_key` とよぶ.
-    let deposit_merge_key = PoseidonHash::two_to_one(*deposit_tx_hash, block_hash).into();
+    let deposit_merge_key = deposit_merge_key(*deposit_tx_hash, block_hash);
 
     let merge_process_proof = sender2_user_asset_tree
         .set(deposit_merge_key, merge_inclusion_proof2.value)
@@ -270,7 +277,8 @@ fn main() {
             &mut pw,
             &[],
             *sender1_input_witness.first().unwrap().0.old_root,
-        );
+        )
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -281,7 +289,8 @@ fn main() {
             &sender1_output_witness,
             sender1_input_witness.first().unwrap().0.old_root,
             sender1_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender1_tx_proof");
     let start = Instant::now();
@@ -302,7 +311,8 @@ fn main() {
     merge_and_purge_circuit
         .targets
         .merge_proof_target
-        .set_witness(&mut pw, &[merge_proof], default_hash);
+        .set_witness(&mut pw, &[merge_proof], default_hash)
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -313,7 +323,8 @@ fn main() {
             &sender2_output_witness,
             sender2_input_witness.first().unwrap().0.old_root,
             sender2_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender2_tx_proof");
     let start = Instant::now();
@@ -389,6 +400,29 @@ fn main() {
     let end = start.elapsed();
     println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
 
+    let mint_circuit = make_mint_circuit::<
+        C,
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >(&zkdsa_circuit.data);
+
+    println!("start proving: default_mint_proof");
+    let start = Instant::now();
+    let default_mint_proof = mint_circuit.prove_default(&zkdsa_circuit).unwrap();
+    let end = start.elapsed();
+    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
+
+    let contract_circuit = make_counter_contract_circuit::<F, C, D>();
+
+    println!("start proving: default_contract_call");
+    let start = Instant::now();
+    let default_contract_call = contract_circuit.call_default().unwrap();
+    let end = start.elapsed();
+    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
+
     let block_circuit = make_block_proof_circuit::<
         F,
         C,
@@ -405,7 +439,12 @@ fn main() {
         N_MERGES,
         N_TXS,
         N_DEPOSITS,
-    >(&merge_and_purge_circuit, &zkdsa_circuit);
+    >(
+        &merge_and_purge_circuit,
+        &zkdsa_circuit,
+        &mint_circuit.data,
+        &contract_circuit.data.common,
+    );
 
     let block_number = 1;
 
@@ -492,23 +531,39 @@ fn main() {
         .collect::<Vec<_>>();
 
     let mut pw = PartialWitness::new();
-    block_circuit.targets.set_witness(
-        &mut pw,
-        block_number,
-        &user_tx_proofs,
-        &deposit_process_proofs,
-        &world_state_process_proofs,
-        &world_state_revert_proofs,
-        &received_signatures,
-        &default_simple_signature,
-        &latest_account_tree_process_proofs,
-        &block_header_siblings
-            .into_iter()
-            .map(|v| *v)
-            .collect::<Vec<_>>(),
-        prev_block_hash,
-        *world_state_process_proofs.first().unwrap().old_root,
-    );
+    block_circuit
+        .targets
+        .set_witness(
+            &mut pw,
+            block_number,
+            &user_tx_proofs,
+            &deposit_process_proofs,
+            deposit_nonce,
+            &world_state_process_proofs,
+            &world_state_revert_proofs,
+            &received_signatures,
+            &default_simple_signature,
+            &latest_account_tree_process_proofs,
+            &block_header_siblings
+                .into_iter()
+                .map(|v| *v)
+                .collect::<Vec<_>>(),
+            prev_block_hash,
+            0,
+            default_hash,
+            default_hash,
+            *world_state_process_proofs.first().unwrap().old_root,
+            default_hash,
+            &[],
+            &[],
+            &[],
+            &[],
+            &default_mint_proof,
+            &[],
+            &default_contract_call,
+            None,
+        )
+        .unwrap();
 
     println!("start proving: block_proof");
     let start = Instant::now();
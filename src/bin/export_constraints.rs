@@ -0,0 +1,124 @@
+//! Writes the reports of the circuits with the small parameters as JSON files for auditors.
+//!
+//! ```sh
+//! cargo run --release --bin export_constraints -- <output directory>
+//! ```
+
+use std::path::PathBuf;
+
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+use intmax_zkp_core::{
+    audit::{
+        block_commitment_public_input_layout, block_public_input_layout,
+        simple_signature_public_input_layout, user_transaction_public_input_layout, CircuitReport,
+    },
+    rollup::{
+        circuits::{commitment::make_block_commitment_circuit, make_block_proof_circuit},
+        contract::make_counter_contract_circuit,
+    },
+    transaction::circuits::{make_user_proof_circuit, mint::make_mint_circuit},
+    zkdsa::circuits::make_simple_signature_circuit,
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+const N_LOG_MAX_USERS: usize = 3;
+const N_LOG_MAX_TXS: usize = 3;
+const N_LOG_MAX_CONTRACTS: usize = 3;
+const N_LOG_MAX_VARIABLES: usize = 3;
+const N_LOG_TXS: usize = 2;
+const N_LOG_RECIPIENTS: usize = 3;
+const N_LOG_CONTRACTS: usize = 3;
+const N_LOG_VARIABLES: usize = 3;
+const N_DIFFS: usize = 2;
+const N_MERGES: usize = 2;
+const N_TXS: usize = 4;
+const N_DEPOSITS: usize = 2;
+const N_LOG_ISSUERS: usize = 3;
+
+fn main() -> anyhow::Result<()> {
+    let output_dir = PathBuf::from(std::env::args().nth(1).unwrap_or_else(|| ".".to_string()));
+    std::fs::create_dir_all(&output_dir)?;
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    CircuitReport::new(
+        "simple_signature",
+        &simple_signature_circuit.data,
+        simple_signature_public_input_layout(),
+    )?
+    .write_json(output_dir.join("simple_signature.json"))?;
+
+    let merge_and_purge_circuit = make_user_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >();
+    CircuitReport::new(
+        "user_transaction",
+        &merge_and_purge_circuit.data,
+        user_transaction_public_input_layout(),
+    )?
+    .write_json(output_dir.join("user_transaction.json"))?;
+
+    let mint_circuit = make_mint_circuit::<
+        C,
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >(&simple_signature_circuit.data);
+
+    let block_circuit = make_block_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+        N_TXS,
+        N_DEPOSITS,
+    >(
+        &merge_and_purge_circuit,
+        &simple_signature_circuit,
+        &mint_circuit.data,
+        &make_counter_contract_circuit::<F, C, D>().data.common,
+    );
+    CircuitReport::new(
+        "block",
+        &block_circuit.data,
+        block_public_input_layout(N_TXS, N_DEPOSITS),
+    )?
+    .write_json(output_dir.join("block.json"))?;
+
+    let block_commitment_circuit = make_block_commitment_circuit(&block_circuit.data);
+    CircuitReport::new(
+        "block_commitment",
+        &block_commitment_circuit.data,
+        block_commitment_public_input_layout(),
+    )?
+    .write_json(output_dir.join("block_commitment.json"))?;
+
+    println!("wrote the reports to {}", output_dir.display());
+
+    Ok(())
+}
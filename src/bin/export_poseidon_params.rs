@@ -0,0 +1,22 @@
+//! Writes the Poseidon parameters and test vectors as a JSON file for external verifiers.
+//!
+//! ```sh
+//! cargo run --release --bin export_poseidon_params -- <output file>
+//! ```
+
+use std::path::PathBuf;
+
+use intmax_zkp_core::poseidon::params::PoseidonExport;
+
+fn main() -> anyhow::Result<()> {
+    let output_path = PathBuf::from(
+        std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| "poseidon_goldilocks.json".to_string()),
+    );
+    PoseidonExport::new().write_json(&output_path)?;
+
+    println!("wrote the Poseidon parameters to {}", output_path.display());
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+//! Compact binary (de)serialization with bincode, enabled by the `bincode` feature.
+//!
+//! Any type of this crate with serde support can be encoded,
+//! e.g. `MergeAndPurgeTransitionProofWithPublicInputs`, `SmtProcessProof`, `MergeProof` and `BlockInfo`.
+//! Hashes are encoded as 4 little-endian `u64` field elements (32 bytes) instead of hex strings.
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+pub fn encode<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    Ok(options().serialize(value)?)
+}
+
+/// Decodes bytes encoded by `encode`. Trailing bytes are rejected.
+/// The lengths of sequences are bounded by the input size,
+/// so malformed input does not allocate too much memory.
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> anyhow::Result<T> {
+    Ok(options()
+        .with_limit(bytes.len() as u64)
+        .reject_trailing_bytes()
+        .deserialize(bytes)?)
+}
+
+#[test]
+fn test_encode_smt_process_proof() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof,
+        goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree},
+    };
+
+    let mut tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    for _ in 0..16 {
+        tree.set(GoldilocksHashOut::rand(), GoldilocksHashOut::rand())
+            .unwrap();
+    }
+    let proof: SmtProcessProof<GoldilocksField> = tree
+        .set(GoldilocksHashOut::rand(), GoldilocksHashOut::rand())
+        .unwrap();
+
+    let encoded = encode(&proof).unwrap();
+    let decoded: SmtProcessProof<GoldilocksField> = decode(&encoded).unwrap();
+    assert_eq!(decoded, proof);
+
+    // size regression: a hash is 32 bytes, while its JSON is 68 bytes.
+    let n_hashes = 6 + proof.siblings.len();
+    assert!(encoded.len() <= 32 * n_hashes + 16);
+    assert!(encoded.len() * 2 < serde_json::to_vec(&proof).unwrap().len());
+
+    assert!(decode::<SmtProcessProof<GoldilocksField>>(&encoded[1..]).is_err());
+    let mut trailing = encoded;
+    trailing.push(0);
+    assert!(decode::<SmtProcessProof<GoldilocksField>>(&trailing).is_err());
+}
+
+#[test]
+fn test_encode_block_info() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::{
+        rollup::block::BlockInfo, sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut,
+    };
+
+    let mut block = BlockInfo::<GoldilocksField>::with_tree_depth(3);
+    block.header.block_number = 1;
+    block.transactions = vec![GoldilocksHashOut::rand(); 4];
+
+    let encoded = encode(&block).unwrap();
+    let decoded: BlockInfo<GoldilocksField> = decode(&encoded).unwrap();
+    assert_eq!(decoded.header, block.header);
+    assert_eq!(decoded.transactions, block.transactions);
+    assert!(encoded.len() * 2 < serde_json::to_vec(&block).unwrap().len());
+}
@@ -0,0 +1,303 @@
+//! Named sets of the tree heights and the slot counts shared by all circuits.
+//!
+//! Every circuit of a rollup must be built from the same constants,
+//! otherwise the block circuit cannot verify the user transaction proofs.
+//! The preset modules (`dev_small`, `testnet` and `mainnet`) build them together.
+
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::circuits::dynamic::RollupParameters;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RollupConstants {
+    pub n_log_max_users: usize,
+    pub n_log_max_txs: usize,
+    pub n_log_max_contracts: usize,
+    pub n_log_max_variables: usize,
+    pub n_log_txs: usize,
+    pub n_log_recipients: usize,
+    pub n_log_contracts: usize,
+    pub n_log_variables: usize,
+    pub n_diffs: usize,
+    pub n_merges: usize,
+
+    /// The number of user transactions in a block.
+    pub n_txs: usize,
+
+    /// The number of deposits in a block.
+    pub n_deposits: usize,
+
+    /// The height of the issuer registry tree, which the mint circuit opens.
+    pub n_log_issuers: usize,
+}
+
+impl RollupConstants {
+    pub const DEV_SMALL: Self = Self {
+        n_log_max_users: 3,
+        n_log_max_txs: 3,
+        n_log_max_contracts: 3,
+        n_log_max_variables: 3,
+        n_log_txs: 2,
+        n_log_recipients: 3,
+        n_log_contracts: 3,
+        n_log_variables: 3,
+        n_diffs: 2,
+        n_merges: 2,
+        n_txs: 4,
+        n_deposits: 2,
+        n_log_issuers: 3,
+    };
+
+    pub const TESTNET: Self = Self {
+        n_log_max_users: 16,
+        n_log_max_txs: 16,
+        n_log_max_contracts: 8,
+        n_log_max_variables: 8,
+        n_log_txs: 4,
+        n_log_recipients: 8,
+        n_log_contracts: 8,
+        n_log_variables: 8,
+        n_diffs: 8,
+        n_merges: 8,
+        n_txs: 16,
+        n_deposits: 16,
+        n_log_issuers: 8,
+    };
+
+    pub const MAINNET: Self = Self {
+        n_log_max_users: 32,
+        n_log_max_txs: 32,
+        n_log_max_contracts: 16,
+        n_log_max_variables: 16,
+        n_log_txs: 6,
+        n_log_recipients: 16,
+        n_log_contracts: 16,
+        n_log_variables: 16,
+        n_diffs: 16,
+        n_merges: 16,
+        n_txs: 64,
+        n_deposits: 64,
+        n_log_issuers: 16,
+    };
+
+    pub fn dev_small() -> Self {
+        Self::DEV_SMALL
+    }
+
+    pub fn testnet() -> Self {
+        Self::TESTNET
+    }
+
+    pub fn mainnet() -> Self {
+        Self::MAINNET
+    }
+
+    /// The parameters of the user transaction circuit.
+    pub fn user_transaction_parameters(&self) -> RollupParameters {
+        RollupParameters {
+            n_log_max_users: self.n_log_max_users,
+            n_log_max_txs: self.n_log_max_txs,
+            n_log_max_contracts: self.n_log_max_contracts,
+            n_log_max_variables: self.n_log_max_variables,
+            n_log_txs: self.n_log_txs,
+            n_log_recipients: self.n_log_recipients,
+            n_log_contracts: self.n_log_contracts,
+            n_log_variables: self.n_log_variables,
+            n_diffs: self.n_diffs,
+            n_merges: self.n_merges,
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.user_transaction_parameters().validate()?;
+        anyhow::ensure!(
+            self.n_log_txs < usize::BITS as usize && self.n_txs <= 1 << self.n_log_txs,
+            "n_txs must be at most 2^n_log_txs: n_txs = {}, n_log_txs = {}",
+            self.n_txs,
+            self.n_log_txs
+        );
+        anyhow::ensure!(self.n_txs != 0, "n_txs must be positive");
+        anyhow::ensure!(self.n_deposits != 0, "n_deposits must be positive");
+
+        Ok(())
+    }
+}
+
+/// Defines a module with the circuit types and the constructors of a preset.
+macro_rules! rollup_preset {
+    ($(#[$attr:meta])* $name:ident, $constants:expr) => {
+        $(#[$attr])*
+        pub mod $name {
+            use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+
+            use crate::{
+                rollup::{
+                    circuits::{make_block_proof_circuit, ProposalAndApprovalBlockCircuit},
+                    contract::{make_counter_contract_circuit, CounterContractCircuit},
+                },
+                transaction::circuits::{
+                    make_user_proof_circuit, mint, MergeAndPurgeTransitionCircuit,
+                },
+                zkdsa::circuits::{make_simple_signature_circuit, SimpleSignatureCircuit},
+            };
+
+            use super::RollupConstants;
+
+            pub const CONSTANTS: RollupConstants = $constants;
+
+            pub const D: usize = 2;
+            pub type C = PoseidonGoldilocksConfig;
+            pub type F = <C as GenericConfig<D>>::F;
+
+            pub type UserTransactionCircuit = MergeAndPurgeTransitionCircuit<
+                F,
+                C,
+                D,
+                { CONSTANTS.n_log_max_users },
+                { CONSTANTS.n_log_max_txs },
+                { CONSTANTS.n_log_max_contracts },
+                { CONSTANTS.n_log_max_variables },
+                { CONSTANTS.n_log_txs },
+                { CONSTANTS.n_log_recipients },
+                { CONSTANTS.n_log_contracts },
+                { CONSTANTS.n_log_variables },
+                { CONSTANTS.n_diffs },
+                { CONSTANTS.n_merges },
+            >;
+
+            pub type MintCircuit = mint::MintCircuit<
+                C,
+                D,
+                { CONSTANTS.n_log_max_txs },
+                { CONSTANTS.n_log_max_contracts },
+                { CONSTANTS.n_log_max_variables },
+                { CONSTANTS.n_log_issuers },
+            >;
+
+            pub type BlockCircuit = ProposalAndApprovalBlockCircuit<
+                F,
+                C,
+                D,
+                { CONSTANTS.n_log_max_users },
+                { CONSTANTS.n_log_max_txs },
+                { CONSTANTS.n_log_txs },
+                { CONSTANTS.n_log_recipients },
+                { CONSTANTS.n_log_contracts },
+                { CONSTANTS.n_log_variables },
+                { CONSTANTS.n_txs },
+                { CONSTANTS.n_deposits },
+            >;
+
+            pub struct RollupCircuits {
+                pub user_transaction: UserTransactionCircuit,
+                pub simple_signature: SimpleSignatureCircuit<F, C, D>,
+                pub mint: MintCircuit,
+                /// The contract circuit whose common data every contract circuit of `block` must have.
+                pub contract: CounterContractCircuit<F, C, D>,
+                pub block: BlockCircuit,
+            }
+
+            pub fn make_user_transaction_circuit() -> UserTransactionCircuit {
+                make_user_proof_circuit::<
+                    F,
+                    C,
+                    D,
+                    { CONSTANTS.n_log_max_users },
+                    { CONSTANTS.n_log_max_txs },
+                    { CONSTANTS.n_log_max_contracts },
+                    { CONSTANTS.n_log_max_variables },
+                    { CONSTANTS.n_log_txs },
+                    { CONSTANTS.n_log_recipients },
+                    { CONSTANTS.n_log_contracts },
+                    { CONSTANTS.n_log_variables },
+                    { CONSTANTS.n_diffs },
+                    { CONSTANTS.n_merges },
+                >()
+            }
+
+            pub fn make_mint_circuit(
+                simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+            ) -> MintCircuit {
+                mint::make_mint_circuit::<
+                    C,
+                    D,
+                    { CONSTANTS.n_log_max_txs },
+                    { CONSTANTS.n_log_max_contracts },
+                    { CONSTANTS.n_log_max_variables },
+                    { CONSTANTS.n_log_issuers },
+                >(&simple_signature_circuit.data)
+            }
+
+            pub fn make_block_circuit(
+                user_transaction_circuit: &UserTransactionCircuit,
+                simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+                mint_circuit: &MintCircuit,
+                contract_circuit: &CounterContractCircuit<F, C, D>,
+            ) -> BlockCircuit {
+                make_block_proof_circuit::<
+                    F,
+                    C,
+                    D,
+                    { CONSTANTS.n_log_max_users },
+                    { CONSTANTS.n_log_max_txs },
+                    { CONSTANTS.n_log_max_contracts },
+                    { CONSTANTS.n_log_max_variables },
+                    { CONSTANTS.n_log_txs },
+                    { CONSTANTS.n_log_recipients },
+                    { CONSTANTS.n_log_contracts },
+                    { CONSTANTS.n_log_variables },
+                    { CONSTANTS.n_diffs },
+                    { CONSTANTS.n_merges },
+                    { CONSTANTS.n_txs },
+                    { CONSTANTS.n_deposits },
+                >(
+                    user_transaction_circuit,
+                    simple_signature_circuit,
+                    &mint_circuit.data,
+                    &contract_circuit.data.common,
+                )
+            }
+
+            /// Builds the user transaction, simple signature, mint, contract and block circuits in this order.
+            pub fn make_rollup_circuits() -> RollupCircuits {
+                let user_transaction = make_user_transaction_circuit();
+                let simple_signature = make_simple_signature_circuit();
+                let mint = make_mint_circuit(&simple_signature);
+                let contract = make_counter_contract_circuit();
+                let block =
+                    make_block_circuit(&user_transaction, &simple_signature, &mint, &contract);
+
+                RollupCircuits {
+                    user_transaction,
+                    simple_signature,
+                    mint,
+                    contract,
+                    block,
+                }
+            }
+        }
+    };
+}
+
+rollup_preset!(
+    /// Small trees for unit tests and local development.
+    dev_small,
+    RollupConstants::DEV_SMALL
+);
+rollup_preset!(testnet, RollupConstants::TESTNET);
+rollup_preset!(
+    /// NOTICE: The values are tentative and may change before the launch.
+    mainnet,
+    RollupConstants::MAINNET
+);
+
+#[test]
+fn test_presets_are_valid() {
+    for constants in [
+        RollupConstants::dev_small(),
+        RollupConstants::testnet(),
+        RollupConstants::mainnet(),
+    ] {
+        constants.validate().unwrap();
+    }
+}
@@ -0,0 +1,433 @@
+//! Labels of the targets, to tell which part of the witness made the proving fail.
+//!
+//! plonky2 reports a conflicting or missing witness with the raw target (e.g. `VirtualTarget { index: 1234 }`),
+//! sometimes by panicking. `prove_with_diagnostics` turns it into `IntmaxError::ProvingFailed`
+//! with the labels of the target groups mentioned in the message, e.g. `merge slot 1 / merge process proof / old root`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOutTarget, RichField},
+    iop::{
+        target::{BoolTarget, Target},
+        witness::PartialWitness,
+    },
+    plonk::{circuit_data::CircuitData, config::GenericConfig, proof::ProofWithPublicInputs},
+};
+
+use crate::{
+    errors::IntmaxError,
+    merkle_tree::gadgets::MerkleProofTarget,
+    recursion::gadgets::RecursiveProofTarget,
+    rollup::gadgets::proposal_block::ProposalBlockProofTarget,
+    sparse_merkle_tree::gadgets::{
+        process::process_smt::SparseMerkleProcessProofTarget,
+        verify::verify_smt::SparseMerkleInclusionProofTarget,
+    },
+    transaction::{
+        circuits::MergeAndPurgeTransitionTarget,
+        gadgets::{
+            block_header::BlockHeaderTarget,
+            merge::{MergeProofTarget, MergeTransitionTarget},
+            purge::PurgeTransitionTarget,
+        },
+    },
+    zkdsa::gadgets::account::AddressTarget,
+};
+
+/// The labeled groups of targets of a circuit.
+#[derive(Clone, Debug, Default)]
+pub struct TargetProvenance {
+    groups: Vec<(String, Vec<Target>)>,
+}
+
+impl TargetProvenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, label: impl Into<String>, targets: impl IntoIterator<Item = Target>) {
+        self.groups
+            .push((label.into(), targets.into_iter().collect()));
+    }
+
+    /// Returns the labels of the groups containing `target`.
+    pub fn labels_of(&self, target: Target) -> Vec<&str> {
+        self.groups
+            .iter()
+            .filter(|(_, targets)| targets.contains(&target))
+            .map(|(label, _)| label.as_str())
+            .collect()
+    }
+
+    /// Returns the labels of the groups containing the targets mentioned in `message`.
+    pub fn find_in_message(&self, message: &str) -> Vec<&str> {
+        self.groups
+            .iter()
+            .filter(|(_, targets)| targets.iter().any(|target| mentions(message, *target)))
+            .map(|(label, _)| label.as_str())
+            .collect()
+    }
+}
+
+/// plonky2 formats a wire as `Wire { row: 1, column: 2 }` with or without the enclosing `Wire(..)`.
+fn mentions(message: &str, target: Target) -> bool {
+    let needle = match target {
+        Target::Wire(wire) => format!("{:?}", wire),
+        _ => format!("{:?}", target),
+    };
+
+    message.contains(&needle)
+}
+
+fn join(label: &str, name: &str) -> String {
+    if label.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} / {}", label, name)
+    }
+}
+
+/// A target struct which can label its targets.
+pub trait RecordProvenance {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance);
+
+    fn provenance(&self) -> TargetProvenance {
+        let mut provenance = TargetProvenance::new();
+        self.record_provenance("", &mut provenance);
+
+        provenance
+    }
+}
+
+impl RecordProvenance for Target {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        provenance.add(label, [*self]);
+    }
+}
+
+impl RecordProvenance for BoolTarget {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        provenance.add(label, [self.target]);
+    }
+}
+
+impl RecordProvenance for HashOutTarget {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        provenance.add(label, self.elements);
+    }
+}
+
+impl RecordProvenance for [HashOutTarget] {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        provenance.add(label, self.iter().flat_map(|hash| hash.elements));
+    }
+}
+
+impl RecordProvenance for AddressTarget {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.0.record_provenance(label, provenance);
+    }
+}
+
+impl<const N_LEVELS: usize> RecordProvenance for SparseMerkleProcessProofTarget<N_LEVELS> {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.old_root
+            .record_provenance(&join(label, "old root"), provenance);
+        self.new_root
+            .record_provenance(&join(label, "new root"), provenance);
+        self.old_key
+            .record_provenance(&join(label, "old key"), provenance);
+        self.old_value
+            .record_provenance(&join(label, "old value"), provenance);
+        self.new_key
+            .record_provenance(&join(label, "new key"), provenance);
+        self.new_value
+            .record_provenance(&join(label, "new value"), provenance);
+        self.siblings
+            .record_provenance(&join(label, "siblings"), provenance);
+        provenance.add(
+            join(label, "fnc"),
+            [self.is_old0.target, self.fnc[0].target, self.fnc[1].target],
+        );
+    }
+}
+
+impl<const N_LEVELS: usize> RecordProvenance for SparseMerkleInclusionProofTarget<N_LEVELS> {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.root
+            .record_provenance(&join(label, "root"), provenance);
+        self.key.record_provenance(&join(label, "key"), provenance);
+        self.value
+            .record_provenance(&join(label, "value"), provenance);
+        self.old_key
+            .record_provenance(&join(label, "old key"), provenance);
+        self.old_value
+            .record_provenance(&join(label, "old value"), provenance);
+        self.siblings
+            .record_provenance(&join(label, "siblings"), provenance);
+        provenance.add(
+            join(label, "fnc"),
+            [self.enabled.target, self.is_old0.target, self.fnc.target],
+        );
+    }
+}
+
+impl<const N_LEVELS: usize> RecordProvenance for MerkleProofTarget<N_LEVELS> {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.index
+            .record_provenance(&join(label, "index"), provenance);
+        self.value
+            .record_provenance(&join(label, "value"), provenance);
+        self.siblings
+            .record_provenance(&join(label, "siblings"), provenance);
+        self.root
+            .record_provenance(&join(label, "root"), provenance);
+    }
+}
+
+impl RecordProvenance for BlockHeaderTarget {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.block_number
+            .record_provenance(&join(label, "block number"), provenance);
+        provenance.add(
+            join(label, "digests"),
+            [
+                self.prev_block_header_digest,
+                self.transactions_digest,
+                self.deposit_digest,
+                self.proposed_world_state_digest,
+                self.approved_world_state_digest,
+                self.latest_account_digest,
+                self.forced_transactions_digest,
+                self.issuer_registry_root,
+            ]
+            .into_iter()
+            .flat_map(|hash| hash.elements),
+        );
+        self.timestamp
+            .record_provenance(&join(label, "timestamp"), provenance);
+        self.aggregator_address
+            .record_provenance(&join(label, "aggregator address"), provenance);
+    }
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+    > RecordProvenance
+    for MergeProofTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>
+{
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        let (block_header, diff_tree_proof, diff_inclusion_proof) = &self.diff_tree_inclusion_proof;
+        block_header.record_provenance(&join(label, "block header"), provenance);
+        diff_tree_proof.record_provenance(&join(label, "tx diff tree proof"), provenance);
+        diff_inclusion_proof.record_provenance(&join(label, "diff inclusion proof"), provenance);
+        self.merge_process_proof
+            .record_provenance(&join(label, "merge process proof"), provenance);
+        self.address_list_inclusion_proof
+            .record_provenance(&join(label, "address list inclusion proof"), provenance);
+        self.nonce
+            .record_provenance(&join(label, "nonce"), provenance);
+    }
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_MERGES: usize,
+    > RecordProvenance
+    for MergeTransitionTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS, N_MERGES>
+{
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.old_user_asset_root
+            .record_provenance(&join(label, "old user asset root"), provenance);
+        self.new_user_asset_root
+            .record_provenance(&join(label, "new user asset root"), provenance);
+        for (i, proof) in self.proofs.iter().enumerate() {
+            proof.record_provenance(&join(label, &format!("merge slot {}", i)), provenance);
+        }
+    }
+}
+
+impl<
+        const LOG_MAX_N_BLOCKS: usize,
+        const LOG_MAX_N_CONTRACTS: usize,
+        const LOG_MAX_N_VARIABLES: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const LOG_N_CONTRACTS: usize,
+        const LOG_N_VARIABLES: usize,
+        const N_DIFFS: usize,
+    > RecordProvenance
+    for PurgeTransitionTarget<
+        LOG_MAX_N_BLOCKS,
+        LOG_MAX_N_CONTRACTS,
+        LOG_MAX_N_VARIABLES,
+        N_LOG_RECIPIENTS,
+        LOG_N_CONTRACTS,
+        LOG_N_VARIABLES,
+        N_DIFFS,
+    >
+{
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.sender_address
+            .record_provenance(&join(label, "sender address"), provenance);
+        self.old_user_asset_root
+            .record_provenance(&join(label, "old user asset root"), provenance);
+        self.new_user_asset_root
+            .record_provenance(&join(label, "new user asset root"), provenance);
+        self.diff_root
+            .record_provenance(&join(label, "diff root"), provenance);
+        self.nonce
+            .record_provenance(&join(label, "nonce"), provenance);
+        for (i, (proof0, proof1, proof2)) in self.input_proofs.iter().enumerate() {
+            let input_label = join(label, &format!("input {}", i));
+            proof0.record_provenance(&join(&input_label, "block layer"), provenance);
+            proof1.record_provenance(&join(&input_label, "contract layer"), provenance);
+            proof2.record_provenance(&join(&input_label, "variable layer"), provenance);
+        }
+        for (i, (proof0, proof1, proof2)) in self.output_proofs.iter().enumerate() {
+            let output_label = join(label, &format!("output {}", i));
+            proof0.record_provenance(&join(&output_label, "recipient layer"), provenance);
+            proof1.record_provenance(&join(&output_label, "contract layer"), provenance);
+            proof2.record_provenance(&join(&output_label, "variable layer"), provenance);
+        }
+    }
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_DIFFS: usize,
+        const N_MERGES: usize,
+    > RecordProvenance
+    for MergeAndPurgeTransitionTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >
+{
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.merge_proof_target
+            .record_provenance(&join(label, "merge"), provenance);
+        self.purge_proof_target
+            .record_provenance(&join(label, "purge"), provenance);
+    }
+}
+
+impl<const D: usize> RecordProvenance for RecursiveProofTarget<D> {
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        provenance.add(
+            join(label, "public inputs"),
+            self.inner.0.public_inputs.iter().copied(),
+        );
+        self.enabled
+            .record_provenance(&join(label, "enabled"), provenance);
+    }
+}
+
+impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize> RecordProvenance
+    for ProposalBlockProofTarget<D, N_LOG_USERS, N_TXS>
+{
+    fn record_provenance(&self, label: &str, provenance: &mut TargetProvenance) {
+        self.old_world_state_root
+            .record_provenance(&join(label, "old world state root"), provenance);
+        self.new_world_state_root
+            .record_provenance(&join(label, "new world state root"), provenance);
+        self.block_tx_root
+            .record_provenance(&join(label, "block tx root"), provenance);
+        for (i, proof) in self.world_state_process_proofs.iter().enumerate() {
+            proof.record_provenance(
+                &join(label, &format!("world state proof {}", i)),
+                provenance,
+            );
+        }
+        for (i, proof) in self.user_tx_proofs.iter().enumerate() {
+            proof.record_provenance(&join(label, &format!("user tx proof {}", i)), provenance);
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Proves with `data` and labels the failure with the target groups of `targets`.
+/// A panic inside plonky2 is also returned as an error.
+pub fn prove_with_diagnostics<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    data: &CircuitData<F, C, D>,
+    inputs: PartialWitness<F>,
+    targets: &impl RecordProvenance,
+) -> Result<ProofWithPublicInputs<F, C, D>, IntmaxError> {
+    let message = match catch_unwind(AssertUnwindSafe(|| data.prove(inputs))) {
+        Ok(Ok(proof)) => return Ok(proof),
+        Ok(Err(err)) => format!("{:#}", err),
+        Err(payload) => panic_message(payload),
+    };
+
+    let provenance = targets.provenance();
+    let labels = provenance
+        .find_in_message(&message)
+        .into_iter()
+        .map(|label| label.to_string())
+        .collect();
+
+    Err(IntmaxError::ProvingFailed { message, labels })
+}
+
+#[test]
+fn test_find_labels_in_message() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        hash::poseidon::PoseidonHash,
+        plonk::{circuit_builder::CircuitBuilder, circuit_data::CircuitConfig},
+    };
+
+    type F = GoldilocksField;
+    const D: usize = 2;
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets =
+        MergeTransitionTarget::<3, 3, 1, 3, 2>::add_virtual_to::<F, PoseidonHash, D>(&mut builder);
+    let provenance = targets.provenance();
+
+    let nonce = targets.proofs[1].nonce.elements[2];
+    assert_eq!(provenance.labels_of(nonce), vec!["merge slot 1 / nonce"]);
+    let message = format!(
+        "Partition containing {:?} was set twice with different values",
+        nonce
+    );
+    assert_eq!(
+        provenance.find_in_message(&message),
+        vec!["merge slot 1 / nonce"]
+    );
+    assert!(provenance.find_in_message("unrelated").is_empty());
+}
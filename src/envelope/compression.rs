@@ -0,0 +1,114 @@
+//! zstd compression of proof envelopes, enabled by the `zstd` feature.
+//!
+//! An envelope is encoded with `codec::encode` before compression.
+//! Most of a proof consists of random field elements, so a dictionary trained by `train_dictionary`
+//! on the proofs of the same circuit mainly helps with the fixed layout and the small integers.
+
+use plonky2::hash::hash_types::RichField;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::ProofEnvelope;
+use crate::codec;
+
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// The upper bound of the decompressed size, to reject decompression bombs.
+pub const MAX_DECOMPRESSED_SIZE: usize = 64 << 20;
+
+/// The recommended size of a dictionary.
+pub const DEFAULT_DICTIONARY_SIZE: usize = 16 << 10;
+
+/// Trains a zstd dictionary on the encoded envelopes of the same kind.
+pub fn train_dictionary<F: RichField, T: Serialize>(
+    samples: &[ProofEnvelope<F, T>],
+    max_size: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let samples = samples
+        .iter()
+        .map(codec::encode)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(zstd::dict::from_samples(&samples, max_size)?)
+}
+
+impl<F: RichField, T: Serialize + DeserializeOwned> ProofEnvelope<F, T> {
+    pub fn compress(&self, level: i32) -> anyhow::Result<Vec<u8>> {
+        let encoded = codec::encode(self)?;
+
+        Ok(zstd::bulk::compress(&encoded, level)?)
+    }
+
+    pub fn decompress(bytes: &[u8]) -> anyhow::Result<Self> {
+        let encoded = zstd::bulk::decompress(bytes, MAX_DECOMPRESSED_SIZE)?;
+
+        codec::decode(&encoded)
+    }
+
+    /// Compresses with a dictionary from `train_dictionary`.
+    /// The same dictionary is needed to decompress.
+    pub fn compress_with_dictionary(
+        &self,
+        level: i32,
+        dictionary: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let encoded = codec::encode(self)?;
+        let mut compressor = zstd::bulk::Compressor::with_dictionary(level, dictionary)?;
+
+        Ok(compressor.compress(&encoded)?)
+    }
+
+    pub fn decompress_with_dictionary(bytes: &[u8], dictionary: &[u8]) -> anyhow::Result<Self> {
+        let mut decompressor = zstd::bulk::Decompressor::with_dictionary(dictionary)?;
+        let encoded = decompressor.decompress(bytes, MAX_DECOMPRESSED_SIZE)?;
+
+        codec::decode(&encoded)
+    }
+}
+
+#[test]
+fn test_compress_proof_envelope() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        hash::hash_types::HashOut,
+        iop::witness::PartialWitness,
+        plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::zkdsa::circuits::{
+        make_simple_signature_circuit, SimpleSignatureProofWithPublicInputs,
+    };
+
+    type Envelope = ProofEnvelope<
+        GoldilocksField,
+        SimpleSignatureProofWithPublicInputs<GoldilocksField, PoseidonGoldilocksConfig, 2>,
+    >;
+
+    let circuit = make_simple_signature_circuit();
+    let envelopes = (0..10)
+        .map(|_| {
+            let mut pw = PartialWitness::new();
+            circuit
+                .targets
+                .set_witness(&mut pw, HashOut::rand(), HashOut::rand());
+            let proof = circuit.prove(pw).unwrap();
+
+            ProofEnvelope::new(proof, &circuit.data.verifier_only)
+        })
+        .collect::<Vec<Envelope>>();
+
+    let envelope = &envelopes[0];
+    let compressed = envelope.compress(DEFAULT_COMPRESSION_LEVEL).unwrap();
+    assert!(compressed.len() < codec::encode(envelope).unwrap().len());
+    let decompressed = Envelope::decompress(&compressed).unwrap();
+    assert_eq!(&decompressed, envelope);
+
+    let dictionary = train_dictionary(&envelopes[1..], DEFAULT_DICTIONARY_SIZE).unwrap();
+    let compressed = envelope
+        .compress_with_dictionary(DEFAULT_COMPRESSION_LEVEL, &dictionary)
+        .unwrap();
+    let decompressed = Envelope::decompress_with_dictionary(&compressed, &dictionary).unwrap();
+    assert_eq!(&decompressed, envelope);
+
+    // the dictionary is required.
+    assert!(Envelope::decompress(&compressed).is_err());
+}
@@ -0,0 +1,317 @@
+//! A versioned wrapper of the proofs exchanged between users and aggregators.
+//!
+//! The envelope records which circuit produced the proof (`circuit_digest`),
+//! so that a verifier rejects the proofs of another circuit version before verifying them.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, RichField},
+    plonk::{
+        circuit_data::{CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData},
+        config::{GenericConfig, GenericHashOut},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "zstd")]
+pub mod compression;
+
+use crate::{
+    errors::IntmaxError,
+    rollup::circuits::ProposalAndApprovalBlockCircuit,
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::circuits::{
+        dynamic::DynMergeAndPurgeTransitionCircuit, MergeAndPurgeTransitionCircuit,
+    },
+    verification::verifier::{BlockVerifier, SimpleSignatureVerifier, UserTransactionVerifier},
+    zkdsa::circuits::SimpleSignatureCircuit,
+};
+
+/// The current version of the envelope format.
+pub const PROOF_ENVELOPE_VERSION: u32 = 1;
+
+/// `payload` is one of the proof types of this crate,
+/// e.g. `MergeAndPurgeTransitionProofWithPublicInputs` or `SimpleSignatureProofWithPublicInputs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct ProofEnvelope<F: RichField, T> {
+    pub version: u32,
+    pub circuit_digest: WrappedHashOut<F>,
+    pub payload: T,
+}
+
+/// Returns the digest of the circuit. The hasher of `C` must output at most 4 field elements.
+pub fn get_circuit_digest<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+) -> WrappedHashOut<F> {
+    HashOut::from_partial(&verifier_only.circuit_digest.to_vec()).into()
+}
+
+impl<F: RichField, T> ProofEnvelope<F, T> {
+    pub fn new<C: GenericConfig<D, F = F>, const D: usize>(
+        payload: T,
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+    ) -> Self
+    where
+        F: Extendable<D>,
+    {
+        Self {
+            version: PROOF_ENVELOPE_VERSION,
+            circuit_digest: get_circuit_digest(verifier_only),
+            payload,
+        }
+    }
+
+    /// Checks the version and the circuit digest without verifying the proof.
+    pub fn check<C: GenericConfig<D, F = F>, const D: usize>(
+        &self,
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+    ) -> Result<(), IntmaxError>
+    where
+        F: Extendable<D>,
+    {
+        if self.version != PROOF_ENVELOPE_VERSION {
+            return Err(IntmaxError::UnsupportedVersion {
+                expected: PROOF_ENVELOPE_VERSION,
+                actual: self.version,
+            });
+        }
+
+        verifier_only.assert_compatible(self.circuit_digest)
+    }
+
+    /// Checks the envelope, verifies the proof and returns it.
+    pub fn verify<C: GenericConfig<D, F = F>, const D: usize>(
+        self,
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+        common: &CommonCircuitData<F, D>,
+    ) -> Result<T, IntmaxError>
+    where
+        F: Extendable<D>,
+        T: Clone + Into<ProofWithPublicInputs<F, C, D>>,
+    {
+        self.check(verifier_only)?;
+
+        let verifier_data = VerifierCircuitData {
+            verifier_only: verifier_only.clone(),
+            common: common.clone(),
+        };
+        verifier_data.verify(self.payload.clone().into())?;
+
+        Ok(self.payload)
+    }
+}
+
+/// A built circuit (or its verifier) identified by the circuit digest.
+pub trait CircuitDigest {
+    type F: RichField;
+
+    fn circuit_digest(&self) -> WrappedHashOut<Self::F>;
+
+    /// Fails if this circuit differs from the one with `expected_digest`,
+    /// e.g. the user transaction circuit which wallets proved against.
+    fn assert_compatible(
+        &self,
+        expected_digest: WrappedHashOut<Self::F>,
+    ) -> Result<(), IntmaxError> {
+        let circuit_digest = self.circuit_digest();
+        if circuit_digest != expected_digest {
+            return Err(IntmaxError::CircuitDigestMismatch {
+                expected: expected_digest.to_string(),
+                actual: circuit_digest.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether `proof` was generated by this circuit, without verifying it.
+    fn is_proof_for<T>(&self, proof: &ProofEnvelope<Self::F, T>) -> bool {
+        proof.version == PROOF_ENVELOPE_VERSION && proof.circuit_digest == self.circuit_digest()
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for VerifierOnlyCircuitData<C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(self)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for SimpleSignatureCircuit<F, C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.data.verifier_only)
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_DIFFS: usize,
+        const N_MERGES: usize,
+    > CircuitDigest
+    for MergeAndPurgeTransitionCircuit<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.data.verifier_only)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for DynMergeAndPurgeTransitionCircuit<F, C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.data.verifier_only)
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_TXS: usize,
+        const N_DEPOSITS: usize,
+    > CircuitDigest
+    for ProposalAndApprovalBlockCircuit<
+        F,
+        C,
+        D,
+        N_LOG_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_TXS,
+        N_DEPOSITS,
+    >
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.data.verifier_only)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for UserTransactionVerifier<F, C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.verifier_only)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for SimpleSignatureVerifier<F, C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.verifier_only)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> CircuitDigest
+    for BlockVerifier<F, C, D>
+{
+    type F = F;
+
+    fn circuit_digest(&self) -> WrappedHashOut<F> {
+        get_circuit_digest(&self.verifier_only)
+    }
+}
+
+#[test]
+fn test_verify_proof_envelope() {
+    use plonky2::{field::types::Sample, iop::witness::PartialWitness};
+
+    use crate::zkdsa::circuits::{
+        make_simple_signature_circuit, make_simple_signature_circuit_zk,
+        SimpleSignatureProofWithPublicInputs,
+    };
+
+    let circuit = make_simple_signature_circuit();
+    let mut pw = PartialWitness::new();
+    circuit
+        .targets
+        .set_witness(&mut pw, HashOut::rand(), HashOut::rand());
+    let proof = circuit.prove(pw).unwrap();
+
+    let envelope = ProofEnvelope::new(proof.clone(), &circuit.data.verifier_only);
+    let encoded = serde_json::to_string(&envelope).unwrap();
+    let decoded: ProofEnvelope<_, SimpleSignatureProofWithPublicInputs<_, _, 2>> =
+        serde_json::from_str(&encoded).unwrap();
+    let verified = decoded
+        .clone()
+        .verify(&circuit.data.verifier_only, &circuit.data.common)
+        .unwrap();
+    assert_eq!(verified, proof);
+
+    let mut wrong_version = decoded.clone();
+    wrong_version.version += 1;
+    assert!(matches!(
+        wrong_version.check(&circuit.data.verifier_only),
+        Err(IntmaxError::UnsupportedVersion { .. })
+    ));
+
+    let other_circuit = make_simple_signature_circuit_zk();
+    assert!(matches!(
+        decoded.check(&other_circuit.data.verifier_only),
+        Err(IntmaxError::CircuitDigestMismatch { .. })
+    ));
+
+    assert!(circuit.is_proof_for(&decoded));
+    assert!(!other_circuit.is_proof_for(&decoded));
+    assert!(circuit.verifier().is_proof_for(&decoded));
+    circuit.assert_compatible(decoded.circuit_digest).unwrap();
+    assert!(other_circuit
+        .assert_compatible(circuit.circuit_digest())
+        .is_err());
+}
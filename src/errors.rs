@@ -0,0 +1,97 @@
+use thiserror::Error;
+
+/// The error type returned when the input of a circuit (usually received from the network)
+/// is malformed, instead of panicking inside `set_witness`.
+#[derive(Debug, Error)]
+pub enum IntmaxError {
+    #[error("too many {name}: expected at most {max}, but got {actual}")]
+    TooManyWitnesses {
+        name: &'static str,
+        max: usize,
+        actual: usize,
+    },
+
+    #[error("{name} must not be empty")]
+    EmptyWitness { name: &'static str },
+
+    #[error("the number of {name} must be {expected}, but got {actual}")]
+    LengthMismatch {
+        name: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// The witness is inconsistent, e.g. the roots of the process proofs are not chained.
+    #[error("invalid witness: {0}")]
+    InvalidWitness(String),
+
+    #[error("invalid public inputs: expected {expected} elements, but got {actual}")]
+    InvalidPublicInputsLength { expected: usize, actual: usize },
+
+    #[error("unsupported version: expected {expected}, but got {actual}")]
+    UnsupportedVersion { expected: u32, actual: u32 },
+
+    /// The proof was generated by another circuit.
+    #[error("circuit digest mismatch: expected 0x{expected}, but got 0x{actual}")]
+    CircuitDigestMismatch { expected: String, actual: String },
+
+    /// Two builds of the same circuit have different digests.
+    #[error("nondeterministic circuit build: 0x{first} and 0x{second}")]
+    NondeterministicBuild { first: String, second: String },
+
+    /// plonky2 failed to prove. `labels` are the target groups mentioned in `message`.
+    #[error("fail to prove{}: {message}", in_labels(.labels))]
+    ProvingFailed {
+        message: String,
+        labels: Vec<String>,
+    },
+
+    /// Errors of the sparse Merkle trees and plonky2.
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+fn in_labels(labels: &[String]) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!(" in {}", labels.join(", "))
+    }
+}
+
+/// Returns `IntmaxError::InvalidWitness` with the formatted message if the condition is false.
+macro_rules! ensure_witness {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err($crate::errors::IntmaxError::InvalidWitness(format!($($arg)+)));
+        }
+    };
+}
+
+pub(crate) use ensure_witness;
+
+/// Returns `IntmaxError::TooManyWitnesses` if `actual` exceeds `max`.
+pub fn ensure_at_most(name: &'static str, actual: usize, max: usize) -> Result<(), IntmaxError> {
+    if actual > max {
+        return Err(IntmaxError::TooManyWitnesses { name, max, actual });
+    }
+
+    Ok(())
+}
+
+/// Returns `IntmaxError::LengthMismatch` if `actual` is not equal to `expected`.
+pub fn ensure_length(
+    name: &'static str,
+    actual: usize,
+    expected: usize,
+) -> Result<(), IntmaxError> {
+    if actual != expected {
+        return Err(IntmaxError::LengthMismatch {
+            name,
+            expected,
+            actual,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Conversions between the crate's hashes and the ethers-rs primitive types,
+//! enabled by the `ethers` feature.
+//!
+//! A hash is packed into 32 bytes in big-endian order, the same as its `Display`:
+//! `elements[3]` occupies the first 8 bytes and `elements[0]` the last 8 bytes,
+//! and each element is written as a big-endian `u64`.
+//! The conversions from bytes fail if an element is not canonical (not less than the field order).
+
+use ethers_core::types::{H256, U256};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField,
+    hash::hash_types::{HashOut, RichField},
+};
+
+use crate::{
+    sparse_merkle_tree::goldilocks_poseidon::{GoldilocksHashOut, Wrapper},
+    zkdsa::account::Address,
+};
+
+/// Packs `value` into 32 big-endian bytes.
+pub fn hash_out_to_be_bytes<F: RichField>(value: HashOut<F>) -> [u8; 32] {
+    Wrapper(value).to_bytes_be()
+}
+
+/// Unpacks 32 big-endian bytes. Each 8 bytes must be a canonical field element.
+pub fn hash_out_from_be_bytes<F: RichField>(bytes: [u8; 32]) -> anyhow::Result<HashOut<F>> {
+    Ok(Wrapper::<HashOut<F>>::from_bytes_be(&bytes)?.0)
+}
+
+impl From<GoldilocksHashOut> for H256 {
+    fn from(value: GoldilocksHashOut) -> Self {
+        H256(hash_out_to_be_bytes(value.0))
+    }
+}
+
+impl TryFrom<H256> for GoldilocksHashOut {
+    type Error = anyhow::Error;
+
+    fn try_from(value: H256) -> Result<Self, Self::Error> {
+        Ok(Wrapper(hash_out_from_be_bytes(value.0)?))
+    }
+}
+
+impl From<GoldilocksHashOut> for U256 {
+    fn from(value: GoldilocksHashOut) -> Self {
+        U256::from_big_endian(&hash_out_to_be_bytes(value.0))
+    }
+}
+
+impl TryFrom<U256> for GoldilocksHashOut {
+    type Error = anyhow::Error;
+
+    fn try_from(value: U256) -> Result<Self, Self::Error> {
+        let mut bytes = [0u8; 32];
+        value.to_big_endian(&mut bytes);
+
+        Ok(Wrapper(hash_out_from_be_bytes(bytes)?))
+    }
+}
+
+impl<F: RichField> From<Address<F>> for [u8; 32] {
+    fn from(value: Address<F>) -> Self {
+        hash_out_to_be_bytes(value.0)
+    }
+}
+
+impl<F: RichField> TryFrom<[u8; 32]> for Address<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: [u8; 32]) -> Result<Self, Self::Error> {
+        Ok(Address(hash_out_from_be_bytes(value)?))
+    }
+}
+
+impl From<Address<GoldilocksField>> for H256 {
+    fn from(value: Address<GoldilocksField>) -> Self {
+        H256(value.into())
+    }
+}
+
+impl TryFrom<H256> for Address<GoldilocksField> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: H256) -> Result<Self, Self::Error> {
+        value.0.try_into()
+    }
+}
+
+#[test]
+fn test_ethers_conversions() {
+    use std::str::FromStr;
+
+    let value = GoldilocksHashOut::from_u32(1);
+    assert_eq!(H256::from(value), H256::from_low_u64_be(1));
+    assert_eq!(U256::from(value), U256::one());
+    assert_eq!(GoldilocksHashOut::try_from(U256::one()).unwrap(), value);
+
+    let value = GoldilocksHashOut::rand();
+    let h256 = H256::from(value);
+    assert_eq!(format!("{:?}", h256), format!("0x{}", value));
+    assert_eq!(GoldilocksHashOut::try_from(h256).unwrap(), value);
+
+    let address = Address::<GoldilocksField>::rand();
+    let bytes: [u8; 32] = address.into();
+    assert_eq!(H256(bytes), H256::from_str(&address.to_string()).unwrap());
+    assert_eq!(Address::try_from(bytes).unwrap(), address);
+
+    // u64::MAX is greater than the order of the Goldilocks field.
+    assert!(GoldilocksHashOut::try_from(H256::from_low_u64_be(u64::MAX)).is_err());
+}
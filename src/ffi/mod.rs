@@ -0,0 +1,472 @@
+//! C-ABI bindings for wallets and verifiers, enabled by the `ffi` feature.
+//!
+//! - Hashes and addresses are 32 bytes, i.e. the four field elements in little-endian.
+//! - Proofs are the bytes of `ProofWithPublicInputs::to_bytes`.
+//! - Witnesses are passed as JSON in the same format as the remote prover.
+//!
+//! Every function returns an `IntmaxStatus`.
+//! If it is not `Ok`, `intmax_last_error_message` returns the reason.
+//! Buffers and handles returned by this module must be released with the matching `*_free`.
+
+use std::{cell::RefCell, panic::AssertUnwindSafe};
+
+use plonky2::{
+    hash::hash_types::HashOut,
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_data::{CommonCircuitData, VerifierOnlyCircuitData},
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    config::{dev_small, mainnet, testnet, RollupConstants},
+    prover::backend::{CircuitWitness, UserTransactionWitness},
+    sparse_merkle_tree::goldilocks_poseidon::{WrappedHashOut, Wrapper},
+    transaction::circuits::dynamic::{
+        make_user_proof_circuit_with_parameters, DynMergeAndPurgeTransitionCircuit,
+        RollupParameters,
+    },
+    verification::proof::verify_block_proof,
+    zkdsa::account::{private_key_to_account, Address},
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntmaxStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidInput = 2,
+    ProvingFailed = 3,
+    VerificationFailed = 4,
+    Panic = 5,
+}
+
+/// Bytes allocated by this module.
+#[repr(C)]
+pub struct IntmaxBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl IntmaxBuffer {
+    fn empty() -> Self {
+        Self {
+            data: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+impl From<Vec<u8>> for IntmaxBuffer {
+    fn from(value: Vec<u8>) -> Self {
+        let value = value.into_boxed_slice();
+        let len = value.len();
+        let data = Box::into_raw(value) as *mut u8;
+
+        Self { data, len }
+    }
+}
+
+/// The user transaction circuit built from `RollupParameters`.
+pub struct IntmaxUserTransactionProver {
+    circuit: DynMergeAndPurgeTransitionCircuit<F, C, D>,
+}
+
+/// The verifier data of the block circuit of a preset.
+pub struct IntmaxBlockVerifier {
+    verifier_only: VerifierOnlyCircuitData<C, D>,
+    common: CommonCircuitData<F, D>,
+    constants: RollupConstants,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = RefCell::new(None);
+}
+
+#[derive(Debug)]
+struct FfiError {
+    status: IntmaxStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn new(status: IntmaxStatus, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Runs `f` without unwinding across the FFI boundary, and records the error message.
+fn run(f: impl FnOnce() -> Result<(), FfiError>) -> IntmaxStatus {
+    let result = std::panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(FfiError::new(IntmaxStatus::Panic, "panicked")));
+
+    match result {
+        Ok(()) => {
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = None);
+
+            IntmaxStatus::Ok
+        }
+        Err(err) => {
+            LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(err.message));
+
+            err.status
+        }
+    }
+}
+
+fn invalid_input(err: impl ToString) -> FfiError {
+    FfiError::new(IntmaxStatus::InvalidInput, err)
+}
+
+unsafe fn read_bytes<'a>(data: *const u8, len: usize) -> Result<&'a [u8], FfiError> {
+    if data.is_null() {
+        if len == 0 {
+            return Ok(&[]);
+        }
+
+        return Err(FfiError::new(IntmaxStatus::NullPointer, "null input"));
+    }
+
+    Ok(std::slice::from_raw_parts(data, len))
+}
+
+unsafe fn read_hash(data: *const u8) -> Result<HashOut<F>, FfiError> {
+    if data.is_null() {
+        return Err(FfiError::new(IntmaxStatus::NullPointer, "null hash"));
+    }
+
+    let bytes = std::slice::from_raw_parts(data, 32);
+    let value = WrappedHashOut::from_bytes_le(bytes.try_into().unwrap())
+        .map_err(|_| invalid_input("non-canonical field element"))?;
+
+    Ok(value.0)
+}
+
+unsafe fn write_hash(out: *mut u8, value: HashOut<F>) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(IntmaxStatus::NullPointer, "null output"));
+    }
+
+    let out = std::slice::from_raw_parts_mut(out, 32);
+    out.copy_from_slice(&Wrapper(value).to_bytes_le());
+
+    Ok(())
+}
+
+unsafe fn write_buffer(out: *mut IntmaxBuffer, value: Vec<u8>) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(IntmaxStatus::NullPointer, "null output"));
+    }
+    *out = value.into();
+
+    Ok(())
+}
+
+unsafe fn write_handle<T>(out: *mut *mut T, value: T) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(IntmaxStatus::NullPointer, "null output"));
+    }
+    *out = Box::into_raw(Box::new(value));
+
+    Ok(())
+}
+
+unsafe fn read_handle<'a, T>(handle: *const T) -> Result<&'a T, FfiError> {
+    handle
+        .as_ref()
+        .ok_or_else(|| FfiError::new(IntmaxStatus::NullPointer, "null handle"))
+}
+
+/// Writes the message of the last error on this thread, or an empty buffer.
+///
+/// # Safety
+///
+/// `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_last_error_message(out: *mut IntmaxBuffer) -> IntmaxStatus {
+    if out.is_null() {
+        return IntmaxStatus::NullPointer;
+    }
+
+    *out = LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or_else(IntmaxBuffer::empty, |message| {
+                message.as_bytes().to_vec().into()
+            })
+    });
+
+    IntmaxStatus::Ok
+}
+
+/// # Safety
+///
+/// `buffer` must be returned by this module and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_buffer_free(buffer: IntmaxBuffer) {
+    if !buffer.data.is_null() {
+        let slice = std::ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+        drop(Box::from_raw(slice));
+    }
+}
+
+/// Writes the public key and the address of the private key.
+///
+/// # Safety
+///
+/// `private_key` must be readable and the outputs must be writable for 32 bytes each.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_derive_address(
+    private_key: *const u8,
+    out_public_key: *mut u8,
+    out_address: *mut u8,
+) -> IntmaxStatus {
+    run(|| {
+        let account = private_key_to_account(read_hash(private_key)?);
+        write_hash(out_public_key, account.public_key)?;
+        write_hash(out_address, account.address.0)
+    })
+}
+
+/// Assembles a `UserTransactionWitness` and writes its JSON after validating it.
+/// The witnesses are the JSON arrays of `MergeProof` and `LayeredLayeredSmtProcessProof`.
+///
+/// # Safety
+///
+/// The hashes must be readable for 32 bytes, and each JSON must be readable for its length.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn intmax_create_user_transaction_witness(
+    sender_address: *const u8,
+    nonce: *const u8,
+    old_user_asset_root: *const u8,
+    merge_witnesses: *const u8,
+    merge_witnesses_len: usize,
+    purge_input_witnesses: *const u8,
+    purge_input_witnesses_len: usize,
+    purge_output_witnesses: *const u8,
+    purge_output_witnesses_len: usize,
+    out_witness: *mut IntmaxBuffer,
+) -> IntmaxStatus {
+    run(|| {
+        let witness = UserTransactionWitness::<F> {
+            sender_address: Address(read_hash(sender_address)?),
+            merge_witnesses: serde_json::from_slice(read_bytes(
+                merge_witnesses,
+                merge_witnesses_len,
+            )?)
+            .map_err(invalid_input)?,
+            purge_input_witnesses: serde_json::from_slice(read_bytes(
+                purge_input_witnesses,
+                purge_input_witnesses_len,
+            )?)
+            .map_err(invalid_input)?,
+            purge_output_witnesses: serde_json::from_slice(read_bytes(
+                purge_output_witnesses,
+                purge_output_witnesses_len,
+            )?)
+            .map_err(invalid_input)?,
+            nonce: read_hash(nonce)?.into(),
+            old_user_asset_root: read_hash(old_user_asset_root)?.into(),
+        };
+        witness.validate().map_err(invalid_input)?;
+
+        write_buffer(
+            out_witness,
+            serde_json::to_vec(&witness).map_err(invalid_input)?,
+        )
+    })
+}
+
+/// Builds the user transaction circuit from the JSON of `RollupParameters`.
+/// It takes a while, so reuse the prover for every transaction.
+///
+/// # Safety
+///
+/// `parameters` must be readable for `parameters_len` bytes and `out_prover` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_user_transaction_prover_new(
+    parameters: *const u8,
+    parameters_len: usize,
+    out_prover: *mut *mut IntmaxUserTransactionProver,
+) -> IntmaxStatus {
+    run(|| {
+        let parameters: RollupParameters =
+            serde_json::from_slice(read_bytes(parameters, parameters_len)?)
+                .map_err(invalid_input)?;
+        let circuit = make_user_proof_circuit_with_parameters::<F, C, D>(parameters)
+            .map_err(invalid_input)?;
+
+        write_handle(out_prover, IntmaxUserTransactionProver { circuit })
+    })
+}
+
+/// # Safety
+///
+/// `prover` must be returned by `intmax_user_transaction_prover_new` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_user_transaction_prover_free(
+    prover: *mut IntmaxUserTransactionProver,
+) {
+    if !prover.is_null() {
+        drop(Box::from_raw(prover));
+    }
+}
+
+/// Proves the JSON of `UserTransactionWitness` and writes the proof bytes.
+///
+/// # Safety
+///
+/// `prover` must be a live handle, `witness` must be readable for `witness_len` bytes
+/// and `out_proof` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_prove_user_transaction(
+    prover: *const IntmaxUserTransactionProver,
+    witness: *const u8,
+    witness_len: usize,
+    out_proof: *mut IntmaxBuffer,
+) -> IntmaxStatus {
+    run(|| {
+        let circuit = &read_handle(prover)?.circuit;
+        let witness: UserTransactionWitness<F> =
+            serde_json::from_slice(read_bytes(witness, witness_len)?).map_err(invalid_input)?;
+        witness.validate().map_err(invalid_input)?;
+
+        let mut pw = PartialWitness::new();
+        witness
+            .set_witness(circuit, &mut pw)
+            .map_err(invalid_input)?;
+        let proof = circuit
+            .prove(pw)
+            .map_err(|err| FfiError::new(IntmaxStatus::ProvingFailed, err))?;
+        let proof: ProofWithPublicInputs<F, C, D> = proof.into();
+        let bytes = proof
+            .to_bytes()
+            .map_err(|err| FfiError::new(IntmaxStatus::ProvingFailed, err))?;
+
+        write_buffer(out_proof, bytes)
+    })
+}
+
+/// Builds the block circuit of a preset: 0 for `dev_small`, 1 for `testnet` and 2 for `mainnet`.
+///
+/// # Safety
+///
+/// `out_verifier` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_block_verifier_new(
+    preset: u32,
+    out_verifier: *mut *mut IntmaxBlockVerifier,
+) -> IntmaxStatus {
+    run(|| {
+        let verifier = match preset {
+            0 => {
+                let data = dev_small::make_rollup_circuits().block.data;
+                IntmaxBlockVerifier {
+                    verifier_only: data.verifier_only,
+                    common: data.common,
+                    constants: dev_small::CONSTANTS,
+                }
+            }
+            1 => {
+                let data = testnet::make_rollup_circuits().block.data;
+                IntmaxBlockVerifier {
+                    verifier_only: data.verifier_only,
+                    common: data.common,
+                    constants: testnet::CONSTANTS,
+                }
+            }
+            2 => {
+                let data = mainnet::make_rollup_circuits().block.data;
+                IntmaxBlockVerifier {
+                    verifier_only: data.verifier_only,
+                    common: data.common,
+                    constants: mainnet::CONSTANTS,
+                }
+            }
+            _ => return Err(invalid_input(format!("unknown preset: {}", preset))),
+        };
+
+        write_handle(out_verifier, verifier)
+    })
+}
+
+/// # Safety
+///
+/// `verifier` must be returned by `intmax_block_verifier_new` and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_block_verifier_free(verifier: *mut IntmaxBlockVerifier) {
+    if !verifier.is_null() {
+        drop(Box::from_raw(verifier));
+    }
+}
+
+/// Verifies a block proof and writes its block hash and new world state root.
+///
+/// # Safety
+///
+/// `verifier` must be a live handle, `proof` must be readable for `proof_len` bytes
+/// and the outputs must be writable for 32 bytes each.
+#[no_mangle]
+pub unsafe extern "C" fn intmax_verify_block_proof(
+    verifier: *const IntmaxBlockVerifier,
+    proof: *const u8,
+    proof_len: usize,
+    out_block_hash: *mut u8,
+    out_new_world_state_root: *mut u8,
+) -> IntmaxStatus {
+    run(|| {
+        let verifier = read_handle(verifier)?;
+        let public_inputs = verify_block_proof::<F, C, D>(
+            read_bytes(proof, proof_len)?,
+            &verifier.verifier_only,
+            &verifier.common,
+            verifier.constants.n_txs,
+            verifier.constants.n_deposits,
+        )
+        .map_err(|err| FfiError::new(IntmaxStatus::VerificationFailed, err))?;
+
+        write_hash(out_block_hash, public_inputs.block_hash)?;
+        write_hash(out_new_world_state_root, public_inputs.new_world_state_root)
+    })
+}
+
+#[test]
+fn test_ffi_derive_address() {
+    use plonky2::field::types::Sample;
+
+    let private_key = HashOut::<F>::rand();
+    let mut private_key_bytes = [0u8; 32];
+    let mut public_key_bytes = [0u8; 32];
+    let mut address_bytes = [0u8; 32];
+    unsafe {
+        write_hash(private_key_bytes.as_mut_ptr(), private_key).unwrap();
+        let status = intmax_derive_address(
+            private_key_bytes.as_ptr(),
+            public_key_bytes.as_mut_ptr(),
+            address_bytes.as_mut_ptr(),
+        );
+        assert_eq!(status, IntmaxStatus::Ok);
+        assert_eq!(
+            read_hash(address_bytes.as_ptr()).unwrap(),
+            private_key_to_account(private_key).address.0
+        );
+
+        let status = intmax_derive_address(
+            [0xffu8; 32].as_ptr(),
+            public_key_bytes.as_mut_ptr(),
+            address_bytes.as_mut_ptr(),
+        );
+        assert_eq!(status, IntmaxStatus::InvalidInput);
+    }
+}
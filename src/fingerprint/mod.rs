@@ -0,0 +1,53 @@
+//! Checks that the circuits are built deterministically.
+//!
+//! The verifier data distributed to the L1 bridge and light clients is identified by the circuit digest,
+//! so a nondeterministic build or a toolchain (or plonky2) drift would make every proof rejected.
+//! Operators pin the fingerprints of the released circuits and check them with `verify_reproducible_build`.
+
+use crate::{
+    envelope::CircuitDigest,
+    errors::IntmaxError,
+    sparse_merkle_tree::goldilocks_poseidon::{parse_prefixed_hex, WrappedHashOut},
+};
+
+/// Returns the fingerprint of a built circuit, i.e. its circuit digest as a `0x`-prefixed hex string.
+pub fn circuit_fingerprint<T: CircuitDigest>(circuit: &T) -> String {
+    format!("0x{}", circuit.circuit_digest())
+}
+
+/// Builds the circuit twice and checks that both builds have the pinned fingerprint.
+/// Returns the first build, so that it can be used without building again.
+pub fn verify_reproducible_build<T: CircuitDigest>(
+    build: impl Fn() -> T,
+    pinned_fingerprint: &str,
+) -> Result<T, IntmaxError> {
+    let pinned_digest: WrappedHashOut<T::F> = parse_prefixed_hex(pinned_fingerprint)?;
+
+    let circuit = build();
+    let first = circuit.circuit_digest();
+    let second = build().circuit_digest();
+    if first != second {
+        return Err(IntmaxError::NondeterministicBuild {
+            first: first.to_string(),
+            second: second.to_string(),
+        });
+    }
+    circuit.assert_compatible(pinned_digest)?;
+
+    Ok(circuit)
+}
+
+#[test]
+fn test_verify_reproducible_build() {
+    use crate::zkdsa::circuits::make_simple_signature_circuit;
+
+    let fingerprint = circuit_fingerprint(&make_simple_signature_circuit());
+    let circuit = verify_reproducible_build(make_simple_signature_circuit, &fingerprint).unwrap();
+    assert_eq!(circuit_fingerprint(&circuit), fingerprint);
+
+    assert!(matches!(
+        verify_reproducible_build(make_simple_signature_circuit, "0x01"),
+        Err(IntmaxError::CircuitDigestMismatch { .. })
+    ));
+    assert!(verify_reproducible_build(make_simple_signature_circuit, "0xzz").is_err());
+}
@@ -0,0 +1,418 @@
+//! Sample accounts, user transactions and blocks with small circuit parameters,
+//! to write integration tests of the applications embedding this crate.
+//!
+//! ```ignore
+//! let user_tx_circuit = make_sample_user_tx_circuit();
+//! let accounts = make_sample_accounts();
+//! let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts)?;
+//! let block = make_sample_block(&user_tx_circuit, &user_txs)?;
+//! block.circuit_data.verify(block.proof)?;
+//! ```
+
+pub mod scenario;
+
+use std::sync::{Arc, Mutex};
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::{hash_types::HashOut, poseidon::PoseidonHash},
+    iop::{
+        target::Target,
+        witness::{PartialWitness, Witness},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{Hasher, PoseidonGoldilocksConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    merkle_tree::tree::get_merkle_proof,
+    rollup::gadgets::proposal_block::ProposalBlockProofTarget,
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof,
+        goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonSparseMerkleTree, WrappedHashOut,
+        },
+        proof::SparseMerkleInclusionProof,
+    },
+    transaction::{
+        block_header::{get_block_hash, BlockHeader},
+        circuits::{
+            make_user_proof_circuit, MergeAndPurgeTransitionCircuit,
+            MergeAndPurgeTransitionProofWithPublicInputs,
+        },
+        gadgets::merge::MergeProof,
+        merge_key::deposit_merge_key,
+    },
+    zkdsa::{
+        account::{private_key_to_account, Account},
+        circuits::{make_simple_signature_circuit, SimpleSignatureProofWithPublicInputs},
+    },
+};
+
+pub const D: usize = 2;
+pub type C = PoseidonGoldilocksConfig;
+pub type F = GoldilocksField;
+pub const N_LOG_MAX_USERS: usize = 3;
+pub const N_LOG_MAX_TXS: usize = 3;
+pub const N_LOG_MAX_CONTRACTS: usize = 3;
+pub const N_LOG_MAX_VARIABLES: usize = 3;
+pub const N_LOG_TXS: usize = 1;
+pub const N_LOG_RECIPIENTS: usize = 3;
+pub const N_LOG_CONTRACTS: usize = 3;
+pub const N_LOG_VARIABLES: usize = 3;
+pub const N_DIFFS: usize = 2;
+pub const N_MERGES: usize = 2;
+pub const N_LOG_ISSUERS: usize = 3;
+pub const N_TXS: usize = 2usize.pow(N_LOG_TXS as u32);
+
+pub type SampleUserTransactionCircuit = MergeAndPurgeTransitionCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_MAX_CONTRACTS,
+    N_LOG_MAX_VARIABLES,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+    N_DIFFS,
+    N_MERGES,
+>;
+
+pub fn make_sample_user_tx_circuit() -> SampleUserTransactionCircuit {
+    make_user_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >()
+}
+
+/// Two accounts with fixed private keys.
+pub fn make_sample_accounts() -> [Account<F>; 2] {
+    let sender1_private_key = HashOut {
+        elements: [
+            F::from_canonical_u64(17426287337377512978),
+            F::from_canonical_u64(8703645504073070742),
+            F::from_canonical_u64(11984317793392655464),
+            F::from_canonical_u64(9979414176933652180),
+        ],
+    };
+    let sender2_private_key = HashOut {
+        elements: [
+            F::from_canonical_u64(15657143458229430356),
+            F::from_canonical_u64(6012455030006979790),
+            F::from_canonical_u64(4280058849535143691),
+            F::from_canonical_u64(5153662694263190591),
+        ],
+    };
+
+    [
+        private_key_to_account(sender1_private_key),
+        private_key_to_account(sender2_private_key),
+    ]
+}
+
+pub struct SampleUserTransactions {
+    pub accounts: [Account<F>; 2],
+
+    /// The world state before the transactions are applied.
+    pub world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory>,
+
+    /// The first sender purges its own assets,
+    /// and the second sender merges a deposit and purges it.
+    pub proofs: Vec<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>,
+}
+
+pub fn make_sample_user_tx(
+    circuit: &SampleUserTransactionCircuit,
+    accounts: &[Account<F>; 2],
+) -> anyhow::Result<SampleUserTransactions> {
+    let [sender1_account, sender2_account] = *accounts;
+    let mut world_state_tree = PoseidonSparseMerkleTree::new(
+        Arc::new(Mutex::new(NodeDataMemory::default())),
+        Default::default(),
+    );
+
+    let key1 = (
+        GoldilocksHashOut::from_u128(12),
+        GoldilocksHashOut::from_u128(305),
+        GoldilocksHashOut::from_u128(8012),
+    );
+    let value1 = GoldilocksHashOut::from_u128(2053);
+    let key2 = (
+        GoldilocksHashOut::from_u128(12),
+        GoldilocksHashOut::from_u128(471),
+        GoldilocksHashOut::from_u128(8012),
+    );
+    let value2 = GoldilocksHashOut::from_u128(1111);
+    let key3 = (
+        GoldilocksHashOut::from_u128(407),
+        GoldilocksHashOut::from_u128(305),
+        GoldilocksHashOut::from_u128(8012),
+    );
+    let value3 = GoldilocksHashOut::from_u128(2053);
+    let key4 = (
+        GoldilocksHashOut::from_u128(832),
+        GoldilocksHashOut::from_u128(471),
+        GoldilocksHashOut::from_u128(8012),
+    );
+    let value4 = GoldilocksHashOut::from_u128(1111);
+    let zero = GoldilocksHashOut::ZERO;
+
+    // sender1 owns two assets and sends them.
+    let mut sender1_user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
+        LayeredLayeredPoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    let mut sender1_tx_diff_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
+        LayeredLayeredPoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    sender1_user_asset_tree.set(key1.0, key1.1, key1.2, value1)?;
+    sender1_user_asset_tree.set(key2.0, key2.1, key2.2, value2)?;
+    world_state_tree.set(
+        sender1_account.address.0.into(),
+        sender1_user_asset_tree.get_root(),
+    )?;
+
+    let sender1_input_witness = vec![
+        sender1_user_asset_tree.set(key2.0, key2.1, key2.2, zero)?,
+        sender1_user_asset_tree.set(key1.0, key1.1, key1.2, zero)?,
+    ];
+    let sender1_output_witness = vec![
+        sender1_tx_diff_tree.set(key3.0, key3.1, key3.2, value3)?,
+        sender1_tx_diff_tree.set(key4.0, key4.1, key4.2, value4)?,
+    ];
+
+    // sender2 receives the same assets by a deposit, merges and sends them.
+    let sender2_address = sender2_account.address.0;
+    let node_data = Arc::new(Mutex::new(NodeDataMemory::default()));
+    let mut sender2_user_asset_tree =
+        PoseidonSparseMerkleTree::new(node_data.clone(), Default::default());
+    let mut sender2_tx_diff_tree =
+        LayeredLayeredPoseidonSparseMerkleTree::new(node_data.clone(), Default::default());
+    let mut deposit_sender2_tree =
+        LayeredLayeredPoseidonSparseMerkleTree::new(node_data, Default::default());
+    deposit_sender2_tree.set(sender2_address.into(), key1.1, key1.2, value1)?;
+    deposit_sender2_tree.set(sender2_address.into(), key2.1, key2.2, value2)?;
+    let deposit_sender2_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        deposit_sender2_tree.into();
+
+    let merge_inclusion_proof2 = deposit_sender2_tree.find(&sender2_address.into())?;
+    let deposit_nonce = HashOut::ZERO;
+    let deposit_tx_hash = PoseidonHash::two_to_one(*merge_inclusion_proof2.root, deposit_nonce);
+    let merge_inclusion_proof1 = get_merkle_proof(&[deposit_tx_hash.into()], 0, N_LOG_TXS);
+
+    let default_hash = HashOut::ZERO;
+    let default_merkle_root = get_merkle_proof(&[], 0, N_LOG_TXS).root;
+    let prev_block_header = BlockHeader {
+        block_number: 0,
+        prev_block_header_digest: default_hash,
+        transactions_digest: *default_merkle_root,
+        deposit_digest: *merge_inclusion_proof1.root,
+        proposed_world_state_digest: default_hash,
+        approved_world_state_digest: default_hash,
+        latest_account_digest: default_hash,
+        forced_transactions_digest: default_hash,
+        issuer_registry_root: default_hash,
+        timestamp: 0,
+        aggregator_address: default_hash,
+    };
+    let block_hash = get_block_hash(&prev_block_header);
+    let deposit_merge_key = deposit_merge_key(deposit_tx_hash, block_hash);
+
+    let merge_process_proof =
+        sender2_user_asset_tree.set(deposit_merge_key, merge_inclusion_proof2.value)?;
+    let merge_proof = MergeProof {
+        is_deposit: true,
+        diff_tree_inclusion_proof: (
+            prev_block_header,
+            merge_inclusion_proof1,
+            merge_inclusion_proof2,
+        ),
+        merge_process_proof,
+        latest_account_tree_inclusion_proof: SparseMerkleInclusionProof::with_root(
+            Default::default(),
+        ),
+        nonce: deposit_nonce.into(),
+    };
+    world_state_tree.set(sender2_address.into(), sender2_user_asset_tree.get_root())?;
+
+    let mut sender2_user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
+        sender2_user_asset_tree.into();
+    let sender2_input_witness = vec![
+        sender2_user_asset_tree.set(deposit_merge_key, key2.1, key2.2, zero)?,
+        sender2_user_asset_tree.set(deposit_merge_key, key1.1, key1.2, zero)?,
+    ];
+    let sender2_output_witness = vec![
+        sender2_tx_diff_tree.set(key3.0, key3.1, key3.2, value3)?,
+        sender2_tx_diff_tree.set(key4.0, key4.1, key4.2, value4)?,
+    ];
+
+    let mut pw = PartialWitness::new();
+    circuit.targets.merge_proof_target.set_witness(
+        &mut pw,
+        &[],
+        *sender1_input_witness[0].0.old_root,
+    )?;
+    circuit.targets.purge_proof_target.set_witness(
+        &mut pw,
+        sender1_account.address,
+        &sender1_input_witness,
+        &sender1_output_witness,
+        sender1_input_witness[0].0.old_root,
+        WrappedHashOut::rand(),
+    )?;
+    let sender1_tx_proof = circuit.prove(pw)?;
+
+    let mut pw = PartialWitness::new();
+    circuit
+        .targets
+        .merge_proof_target
+        .set_witness(&mut pw, &[merge_proof], default_hash)?;
+    circuit.targets.purge_proof_target.set_witness(
+        &mut pw,
+        sender2_account.address,
+        &sender2_input_witness,
+        &sender2_output_witness,
+        sender2_input_witness[0].0.old_root,
+        WrappedHashOut::rand(),
+    )?;
+    let sender2_tx_proof = circuit.prove(pw)?;
+
+    Ok(SampleUserTransactions {
+        accounts: *accounts,
+        world_state_tree,
+        proofs: vec![sender1_tx_proof, sender2_tx_proof],
+    })
+}
+
+pub struct SampleBlock {
+    pub circuit_data: CircuitData<F, C, D>,
+    pub targets: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, N_TXS>,
+    pub proof: ProofWithPublicInputs<F, C, D>,
+
+    /// The world state after the block is proposed.
+    pub world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory>,
+    pub world_state_process_proofs: Vec<SmtProcessProof<F>>,
+
+    /// The signatures of the senders to the proposed world state root.
+    pub received_signatures: Vec<SimpleSignatureProofWithPublicInputs<F, C, D>>,
+}
+
+/// Proves the proposal block of `user_txs`.
+pub fn make_sample_block(
+    user_tx_circuit: &SampleUserTransactionCircuit,
+    user_txs: &SampleUserTransactions,
+) -> anyhow::Result<SampleBlock> {
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(
+            user_txs.world_state_tree.nodes_db.clone(),
+            user_txs.world_state_tree.get_root(),
+        );
+    let world_state_process_proofs = user_txs
+        .proofs
+        .iter()
+        .map(|user_tx_proof| {
+            world_state_tree.set(
+                user_tx_proof.public_inputs.sender_address.0.into(),
+                user_tx_proof.public_inputs.new_user_asset_root,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let zkdsa_circuit = make_simple_signature_circuit();
+    let received_signatures = user_txs
+        .accounts
+        .iter()
+        .map(|account| {
+            let mut pw = PartialWitness::new();
+            zkdsa_circuit.targets.set_witness(
+                &mut pw,
+                account.private_key,
+                *world_state_tree.get_root(),
+            );
+
+            zkdsa_circuit.prove(pw)
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, N_TXS> =
+        ProposalBlockProofTarget::add_virtual_to(&mut builder, &user_tx_circuit.data);
+    let circuit_data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    targets.set_witness(
+        &mut pw,
+        &world_state_process_proofs,
+        &user_txs
+            .proofs
+            .iter()
+            .map(|p| ProofWithPublicInputs::from(p.clone()))
+            .collect::<Vec<_>>(),
+        *world_state_process_proofs[0].old_root,
+    )?;
+    let proof = circuit_data.prove(pw)?;
+
+    Ok(SampleBlock {
+        circuit_data,
+        targets,
+        proof,
+        world_state_tree,
+        world_state_process_proofs,
+        received_signatures,
+    })
+}
+
+/// A circuit which only exposes arbitrary public inputs.
+/// It stands in for the inner circuit of a recursive gadget,
+/// e.g. to test the gadget against the public inputs which the real inner circuit never outputs.
+pub struct PublicInputsCircuit {
+    pub data: CircuitData<F, C, D>,
+    pub public_inputs: Vec<Target>,
+}
+
+pub fn make_public_inputs_circuit(n_public_inputs: usize) -> PublicInputsCircuit {
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let public_inputs = builder.add_virtual_targets(n_public_inputs);
+    builder.register_public_inputs(&public_inputs);
+    let data = builder.build::<C>();
+
+    PublicInputsCircuit {
+        data,
+        public_inputs,
+    }
+}
+
+impl PublicInputsCircuit {
+    pub fn prove(&self, public_inputs: &[F]) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        anyhow::ensure!(
+            public_inputs.len() == self.public_inputs.len(),
+            "expected {} public inputs, but got {}",
+            self.public_inputs.len(),
+            public_inputs.len()
+        );
+        let mut pw = PartialWitness::new();
+        for (t, v) in self.public_inputs.iter().zip(public_inputs.iter()) {
+            pw.set_target(*t, *v);
+        }
+
+        self.data.prove(pw)
+    }
+}
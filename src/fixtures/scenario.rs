@@ -0,0 +1,1121 @@
+//! An end-to-end scenario of several accounts over several blocks with the sample parameters.
+//!
+//! `Scenario` keeps the rollup state trees, the wallet of each account and the hashes of the blocks.
+//! `Scenario::run_block` proves the transactions of each sender with the user transaction circuit,
+//! builds the block with `IncrementalBlockBuilder`, collects the signatures of the senders who sign
+//! and the mints of the issuer of the tokens, calls the counter contract, proves the block
+//! and makes the Merkle proof of each withdrawal against its withdrawal root.
+//! Every proof is verified, and the assets sent by the confirmed transactions and the deposits
+//! are delivered to the wallets of the recipients, to be merged in their next transactions.
+//!
+//! ```ignore
+//! let circuits = make_scenario_circuits();
+//! let mut scenario = Scenario::new(&circuits, 2, 2)?;
+//! scenario.genesis(&[scenario.deposit(0, 0, 10), scenario.deposit(0, 1, 1)])?;
+//! scenario.run_block(&ScenarioBlock {
+//!     deposits: vec![scenario.deposit(1, 1, 1)],
+//!     transactions: vec![ScenarioTransaction {
+//!         sender: 0,
+//!         transfers: vec![scenario.transfer(1, 0, 10)],
+//!         signs: true,
+//!     }],
+//!     mints: vec![ScenarioMint { token: 0, amount: 100 }],
+//!     counter_inputs: vec![7],
+//! })?;
+//! ```
+//!
+//! NOTICE: The block circuit commits `transactions_digest` to the diff roots of the transactions,
+//! while the merge gadget opens a `tx_hash` against it and the user transaction circuit does not check
+//! the block header of a transfer. So the received assets refer to `BlockRecord::merge_header`,
+//! which is the proven header with the Merkle root of the `tx_hash` of the transactions instead.
+//! `deposit_digest` is the same in both headers, since the deposit block circuit commits the nonce.
+//!
+//! Since the world state must hold the user asset root after merge (see `IncrementalBlockBuilder::admit`),
+//! only the transaction creating an account can merge, and it must leave some assets.
+//! Each account should therefore receive one batch of assets before its first transaction,
+//! e.g. the deposits of one block or a transfer, and keep a token which it does not send.
+
+use std::time::{Duration, Instant};
+
+use plonky2::{
+    field::types::{Field, PrimeField64},
+    hash::{hash_types::HashOut, poseidon::PoseidonHash},
+    iop::witness::PartialWitness,
+    plonk::{config::Hasher, proof::ProofWithPublicInputs},
+};
+
+use crate::{
+    merkle_tree::tree::get_merkle_proof,
+    prover::backend::{BlockWitness, CircuitWitness},
+    rollup::{
+        block_builder::IncrementalBlockBuilder,
+        block_diff::{BlockDiff, RollupStateTrees},
+        circuits::{
+            make_block_proof_circuit, ProposalAndApprovalBlockCircuit,
+            ProposalAndApprovalBlockProofWithPublicInputs,
+        },
+        contract::{make_counter_contract_circuit, ContractCall, CounterContractCircuit},
+        deposit::{build_deposit_tree, Deposit, DepositTree},
+        withdrawal::{get_withdrawal_root, make_withdrawal_proofs, WithdrawalProof},
+    },
+    sparse_merkle_tree::goldilocks_poseidon::{
+        GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+        PoseidonSparseMerkleTree, WrappedHashOut,
+    },
+    transaction::{
+        asset::{Asset, ReceivedAssetProof, TokenKind},
+        block_header::{get_block_hash, get_block_header_tree_proof, BlockHeader},
+        burn::burn_address,
+        circuits::mint::{make_mint_circuit, MintCircuit},
+        issuance::{IssuerRegistry, MintRequest},
+        merge_key::mint_merge_key,
+        simulation::{simulate_transaction, SimulatedTransaction, Transfer, UserState},
+    },
+    zkdsa::{
+        account::{private_key_to_account, Account, Address},
+        circuits::{
+            make_simple_signature_circuit, SimpleSignatureCircuit,
+            SimpleSignatureProofWithPublicInputs,
+        },
+    },
+};
+
+use super::{
+    make_sample_user_tx_circuit, SampleUserTransactionCircuit, C, D, F, N_DIFFS, N_LOG_CONTRACTS,
+    N_LOG_ISSUERS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_TXS, N_LOG_MAX_USERS, N_LOG_MAX_VARIABLES,
+    N_LOG_RECIPIENTS, N_LOG_TXS, N_LOG_VARIABLES, N_MERGES, N_TXS,
+};
+
+pub const N_DEPOSITS: usize = 2;
+
+/// The depth of the block header tree of the block circuit.
+const N_LOG_MAX_BLOCKS: usize = 32;
+
+pub type SampleBlockCircuit = ProposalAndApprovalBlockCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+    N_TXS,
+    N_DEPOSITS,
+>;
+
+pub type SampleMintCircuit =
+    MintCircuit<C, D, N_LOG_MAX_TXS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_VARIABLES, N_LOG_ISSUERS>;
+
+pub struct ScenarioCircuits {
+    pub user_tx_circuit: SampleUserTransactionCircuit,
+    pub simple_signature_circuit: SimpleSignatureCircuit<F, C, D>,
+    pub mint_circuit: SampleMintCircuit,
+    pub contract_circuit: CounterContractCircuit<F, C, D>,
+    pub block_circuit: SampleBlockCircuit,
+}
+
+pub fn make_scenario_circuits() -> ScenarioCircuits {
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let mint_circuit = make_mint_circuit::<
+        C,
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >(&simple_signature_circuit.data);
+    let contract_circuit = make_counter_contract_circuit();
+    let block_circuit = make_block_proof_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+        N_TXS,
+        N_DEPOSITS,
+    >(
+        &user_tx_circuit,
+        &simple_signature_circuit,
+        &mint_circuit.data,
+        &contract_circuit.data.common,
+    );
+
+    ScenarioCircuits {
+        user_tx_circuit,
+        simple_signature_circuit,
+        mint_circuit,
+        contract_circuit,
+        block_circuit,
+    }
+}
+
+/// A transaction of the account `sender`. Only the transactions of the senders who sign are confirmed.
+#[derive(Clone, Debug)]
+pub struct ScenarioTransaction {
+    pub sender: usize,
+    pub transfers: Vec<Transfer>,
+    pub signs: bool,
+}
+
+/// A mint of `amount` of `tokens[token]` by the issuer of the tokens.
+#[derive(Clone, Copy, Debug)]
+pub struct ScenarioMint {
+    pub token: usize,
+    pub amount: u64,
+}
+
+/// The block circuit requires at least one deposit and one transaction in a block.
+#[derive(Clone, Debug, Default)]
+pub struct ScenarioBlock {
+    pub deposits: Vec<Deposit<F>>,
+    pub transactions: Vec<ScenarioTransaction>,
+    pub mints: Vec<ScenarioMint>,
+
+    /// Each input is hashed into the state of the counter contract by a contract call.
+    pub counter_inputs: Vec<u64>,
+}
+
+pub struct BlockRecord {
+    pub proof: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+
+    /// The witness which `proof` is proven from.
+    pub witness: BlockWitness<F, C, D>,
+
+    /// The header which the received assets of this block refer to.
+    pub merge_header: BlockHeader<F>,
+    pub diff: BlockDiff,
+    pub withdrawal_proofs: Vec<WithdrawalProof<F>>,
+}
+
+pub struct Scenario<'a> {
+    circuits: &'a ScenarioCircuits,
+    pub accounts: Vec<Account<F>>,
+    pub tokens: Vec<TokenKind<F>>,
+
+    /// The issuer of all `tokens`, which is not one of `accounts`.
+    pub issuer: Account<F>,
+    pub issuer_registry: IssuerRegistry,
+
+    /// The user asset tree of `issuer`, which only the mints change.
+    pub issuer_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>,
+
+    /// The state root of the counter contract, which is empty until the first call deploys it.
+    pub counter_state_root: HashOut<F>,
+
+    /// `wallets[i]` is the state of `accounts[i]`.
+    pub wallets: Vec<UserState>,
+    pub trees: RollupStateTrees<NodeDataMemory>,
+    pub genesis_header: Option<BlockHeader<F>>,
+
+    /// The blocks after the genesis block in order.
+    pub blocks: Vec<BlockRecord>,
+
+    /// `block_hashes[i]` is the hash of the block `i`.
+    block_hashes: Vec<WrappedHashOut<F>>,
+    default_simple_signature: SimpleSignatureProofWithPublicInputs<F, C, D>,
+    default_mint_proof: ProofWithPublicInputs<F, C, D>,
+    default_contract_call: ContractCall<F, C, D>,
+}
+
+fn amount_to_hash(amount: u64) -> GoldilocksHashOut {
+    HashOut::from_partial(&[F::from_canonical_u64(amount)]).into()
+}
+
+/// Whether the leaves of `keys` are at most `depth` deep in a sparse Merkle tree,
+/// which the circuits of the small parameters require.
+fn fits_in_depth(keys: &[GoldilocksHashOut], depth: usize) -> anyhow::Result<bool> {
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    for key in keys {
+        tree.set(*key, GoldilocksHashOut::from_u32(1))?;
+    }
+    for key in keys {
+        if tree.find(key)?.siblings.len() > depth {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Takes the keys `make_key(0), make_key(1), ...` which fit in `depth` together with `reserved_keys`,
+/// until there are `n` keys.
+fn pick_keys<T>(
+    n: usize,
+    depth: usize,
+    reserved_keys: &[GoldilocksHashOut],
+    make_key: impl Fn(u64) -> T,
+    to_key: impl Fn(&T) -> GoldilocksHashOut,
+) -> anyhow::Result<Vec<T>> {
+    anyhow::ensure!(
+        n + reserved_keys.len() <= 1 << depth,
+        "{} keys do not fit in the depth {}",
+        n + reserved_keys.len(),
+        depth
+    );
+    anyhow::ensure!(
+        fits_in_depth(reserved_keys, depth)?,
+        "the reserved keys do not fit in the depth {}",
+        depth
+    );
+    let mut picked = reserved_keys.to_vec();
+    let mut values = vec![];
+    for seed in 0u64.. {
+        if values.len() == n {
+            break;
+        }
+
+        let value = make_key(seed);
+        picked.push(to_key(&value));
+        if fits_in_depth(&picked, depth)? {
+            values.push(value);
+        } else {
+            picked.pop();
+        }
+    }
+
+    Ok(values)
+}
+
+impl<'a> Scenario<'a> {
+    /// Creates `n_accounts` accounts and `n_tokens` kinds of tokens with fixed keys,
+    /// and registers the issuer of the tokens.
+    pub fn new(
+        circuits: &'a ScenarioCircuits,
+        n_accounts: usize,
+        n_tokens: usize,
+    ) -> anyhow::Result<Self> {
+        // The burn address is a recipient of the diff trees, and the counter contract is in the world state.
+        let address_depth = N_LOG_MAX_USERS.min(N_LOG_RECIPIENTS);
+        let mut accounts = pick_keys(
+            n_accounts + 1,
+            address_depth,
+            &[
+                burn_address::<F>().0.into(),
+                circuits.contract_circuit.contract_address().0.into(),
+            ],
+            |seed| {
+                private_key_to_account(PoseidonHash::hash_no_pad(&[F::from_canonical_u64(seed)]))
+            },
+            |account| account.address.0.into(),
+        )?;
+        let issuer = accounts.pop().unwrap();
+        let tokens = pick_keys(
+            n_tokens,
+            N_LOG_CONTRACTS.min(N_LOG_MAX_CONTRACTS),
+            &[],
+            |seed| TokenKind {
+                contract_address: Address(PoseidonHash::hash_no_pad(&[
+                    F::ZERO,
+                    F::from_canonical_u64(seed),
+                ])),
+                variable_index: GoldilocksHashOut::from_u32(1),
+            },
+            |kind| kind.contract_address.0.into(),
+        )?;
+        let mut issuer_registry = IssuerRegistry::new();
+        for kind in tokens.iter() {
+            issuer_registry.register(kind.contract_address, issuer.public_key)?;
+        }
+        let wallets = accounts
+            .iter()
+            .map(|account| UserState {
+                address: account.address,
+                user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree::default(),
+                assets: vec![],
+                pending_assets: vec![],
+            })
+            .collect();
+
+        let mut pw = PartialWitness::new();
+        circuits.simple_signature_circuit.targets.set_witness(
+            &mut pw,
+            Default::default(),
+            Default::default(),
+        );
+        let default_simple_signature = circuits.simple_signature_circuit.prove(pw)?;
+        let default_mint_proof = circuits
+            .mint_circuit
+            .prove_default(&circuits.simple_signature_circuit)?;
+        let default_contract_call = circuits.contract_circuit.call_default()?;
+
+        Ok(Self {
+            circuits,
+            accounts,
+            tokens,
+            issuer,
+            issuer_registry,
+            issuer_asset_tree: LayeredLayeredPoseidonSparseMerkleTree::default(),
+            counter_state_root: HashOut::ZERO,
+            wallets,
+            trees: RollupStateTrees {
+                world_state_tree: PoseidonSparseMerkleTree::default(),
+                latest_account_tree: PoseidonSparseMerkleTree::default(),
+            },
+            genesis_header: None,
+            blocks: vec![],
+            block_hashes: vec![],
+            default_simple_signature,
+            default_mint_proof,
+            default_contract_call,
+        })
+    }
+
+    /// A deposit of `amount` of `tokens[token]` to `accounts[recipient]`.
+    pub fn deposit(&self, recipient: usize, token: usize, amount: u64) -> Deposit<F> {
+        let kind = self.tokens[token];
+
+        Deposit {
+            recipient: self.accounts[recipient].address,
+            contract: kind.contract_address,
+            variable: *kind.variable_index,
+            amount: F::from_canonical_u64(amount),
+        }
+    }
+
+    /// A transfer of `amount` of `tokens[token]` to `accounts[recipient]`.
+    pub fn transfer(&self, recipient: usize, token: usize, amount: u64) -> Transfer {
+        Transfer {
+            recipient: self.accounts[recipient].address,
+            asset: Asset {
+                kind: self.tokens[token],
+                amount,
+            },
+        }
+    }
+
+    /// A withdrawal of `amount` of `tokens[token]`, which burns it to be paid to the sender on L1.
+    pub fn withdrawal(&self, token: usize, amount: u64) -> Transfer {
+        Transfer::burn(Asset {
+            kind: self.tokens[token],
+            amount,
+        })
+    }
+
+    /// The merged amount of `tokens[token]` of `accounts[account]`.
+    pub fn balance(&self, account: usize, token: usize) -> u64 {
+        self.wallets[account]
+            .assets
+            .iter()
+            .filter(|owned| owned.asset.kind == self.tokens[token])
+            .map(|owned| owned.asset.amount)
+            .sum()
+    }
+
+    pub fn latest_block_number(&self) -> Option<u32> {
+        (self.block_hashes.len() as u32).checked_sub(1)
+    }
+
+    /// Creates the trusted block 0 with `deposits` and no transactions.
+    pub fn genesis(&mut self, deposits: &[Deposit<F>]) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.block_hashes.is_empty(),
+            "the genesis block is already created"
+        );
+        let deposit_tree = build_deposit_tree(deposits, N_LOG_TXS)?;
+        let default_hash = HashOut::ZERO;
+        let header = BlockHeader {
+            block_number: 0,
+            prev_block_header_digest: default_hash,
+            transactions_digest: *get_merkle_proof(&[], 0, N_LOG_TXS).root,
+            deposit_digest: *deposit_tree.deposit_digest,
+            proposed_world_state_digest: default_hash,
+            approved_world_state_digest: default_hash,
+            latest_account_digest: default_hash,
+            forced_transactions_digest: default_hash,
+            issuer_registry_root: default_hash,
+            timestamp: 0,
+            aggregator_address: default_hash,
+        };
+        self.receive_deposits(&header, deposits, &deposit_tree)?;
+        self.block_hashes.push(get_block_hash(&header).into());
+        self.genesis_header = Some(header);
+
+        Ok(())
+    }
+
+    /// Proves and verifies the next block of `plan` and delivers the received assets.
+    pub fn run_block(&mut self, plan: &ScenarioBlock) -> anyhow::Result<&BlockRecord> {
+        let block_number = self.block_hashes.len() as u32;
+        anyhow::ensure!(block_number != 0, "the genesis block is not created yet");
+        anyhow::ensure!(
+            !plan.deposits.is_empty(),
+            "the block {} has no deposits",
+            block_number
+        );
+        anyhow::ensure!(
+            !plan.transactions.is_empty(),
+            "the block {} has no transactions",
+            block_number
+        );
+        let circuits = self.circuits;
+        let deposit_tree = build_deposit_tree(&plan.deposits, N_LOG_TXS)?;
+
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        let mut builder = IncrementalBlockBuilder::new(block_number, &self.trees, N_TXS, deadline)
+            .with_issuer_registry_root(self.issuer_registry.root());
+        let mut simulated_txs = vec![];
+        for (i, tx) in plan.transactions.iter().enumerate() {
+            let nonce = HashOut::from_partial(&[
+                F::from_canonical_u32(block_number),
+                F::from_canonical_usize(i),
+            ])
+            .into();
+            let simulated = simulate_transaction(&self.wallets[tx.sender], &tx.transfers, nonce)?;
+            let mut pw = PartialWitness::new();
+            simulated
+                .witness
+                .set_witness(&circuits.user_tx_circuit, &mut pw)?;
+            let user_tx_proof = circuits.user_tx_circuit.prove(pw)?;
+            circuits.user_tx_circuit.verify(user_tx_proof.clone())?;
+            anyhow::ensure!(
+                user_tx_proof.public_inputs.tx_hash == simulated.tx_hash,
+                "the transaction #{} is not the simulated one",
+                i
+            );
+
+            builder.admit_with_burned_assets(
+                &mut self.trees,
+                user_tx_proof,
+                simulated.burned_assets.clone(),
+            )?;
+            simulated_txs.push(simulated);
+        }
+
+        let mut sealed_block = builder.seal();
+        for tx in plan.transactions.iter().filter(|tx| tx.signs) {
+            let mut pw = PartialWitness::new();
+            circuits.simple_signature_circuit.targets.set_witness(
+                &mut pw,
+                self.accounts[tx.sender].private_key,
+                *sealed_block.proposed_world_state_root,
+            );
+            let received_signature = circuits.simple_signature_circuit.prove(pw)?;
+            circuits
+                .simple_signature_circuit
+                .verify(received_signature.clone())?;
+            sealed_block.receive_signature(received_signature)?;
+        }
+        for (i, mint) in plan.mints.iter().enumerate() {
+            let mint_proof = self.prove_mint(block_number, i, mint)?;
+            sealed_block.receive_mint(mint_proof)?;
+        }
+        let mut counter_state_root = self.counter_state_root;
+        for input in plan.counter_inputs.iter() {
+            let contract_call = circuits
+                .contract_circuit
+                .call(counter_state_root, *amount_to_hash(*input))?;
+            counter_state_root = contract_call.public_inputs()?.new_state_root;
+            sealed_block.receive_contract_call(contract_call)?;
+        }
+
+        let prev_block_hash = *self.block_hashes.last().unwrap();
+        let (block_header_siblings, old_prev_block_header_digest, new_prev_block_header_digest) =
+            get_block_header_tree_proof(
+                &self.block_hashes[..self.block_hashes.len() - 1],
+                prev_block_hash,
+                N_LOG_MAX_BLOCKS,
+            );
+        let (witness, diff) = sealed_block.finalize(
+            &mut self.trees,
+            deposit_tree.deposit_process_proofs.clone(),
+            *deposit_tree.nonce,
+            self.default_simple_signature.clone(),
+            self.default_mint_proof.clone(),
+            self.default_contract_call.clone(),
+            block_header_siblings.iter().map(|v| **v).collect(),
+            *prev_block_hash,
+        )?;
+
+        let proof = prove_block(&circuits.block_circuit, &witness)?;
+        circuits.block_circuit.verify(proof.clone())?;
+        self.counter_state_root = counter_state_root;
+        let public_inputs = &proof.public_inputs;
+        anyhow::ensure!(
+            public_inputs.old_prev_block_header_digest == *old_prev_block_header_digest
+                && public_inputs.new_prev_block_header_digest == *new_prev_block_header_digest,
+            "the block {} does not follow the latest block",
+            block_number
+        );
+        anyhow::ensure!(
+            public_inputs.approved_world_state_digest == *self.trees.world_state_tree.get_root()
+                && public_inputs.latest_account_digest
+                    == *self.trees.latest_account_tree.get_root(),
+            "the block {} is not of the rollup state trees",
+            block_number
+        );
+
+        let tx_hashes = witness
+            .user_tx_proofs
+            .iter()
+            .map(|user_tx_proof| user_tx_proof.public_inputs.tx_hash)
+            .collect::<Vec<_>>();
+        let merge_header = BlockHeader {
+            block_number,
+            prev_block_header_digest: public_inputs.new_prev_block_header_digest,
+            transactions_digest: *get_merkle_proof(&tx_hashes, 0, N_LOG_TXS).root,
+            deposit_digest: *deposit_tree.deposit_digest,
+            proposed_world_state_digest: public_inputs.proposed_world_state_digest,
+            approved_world_state_digest: public_inputs.approved_world_state_digest,
+            latest_account_digest: public_inputs.latest_account_digest,
+            forced_transactions_digest: public_inputs.new_forced_transactions_digest,
+            issuer_registry_root: witness.issuer_registry_root,
+            timestamp: witness.timestamp,
+            aggregator_address: witness.aggregator_address,
+        };
+
+        for (slot, (tx, simulated)) in plan.transactions.iter().zip(&simulated_txs).enumerate() {
+            if !tx.signs {
+                continue;
+            }
+
+            self.confirm_transaction(tx.sender, simulated)?;
+            self.receive_transfers(
+                &merge_header,
+                &tx_hashes,
+                slot,
+                self.accounts[tx.sender].address,
+                simulated,
+            )?;
+        }
+        self.receive_deposits(&merge_header, &plan.deposits, &deposit_tree)?;
+
+        let withdrawals = witness.withdrawals(N_TXS, N_DIFFS)?;
+        anyhow::ensure!(
+            *get_withdrawal_root(&withdrawals) == public_inputs.withdrawal_root,
+            "the withdrawals are not of the block {}",
+            block_number
+        );
+        let withdrawal_proofs = make_withdrawal_proofs(block_number, &withdrawals);
+        for withdrawal_proof in withdrawal_proofs.iter() {
+            anyhow::ensure!(
+                withdrawal_proof.verify(public_inputs.withdrawal_root),
+                "the withdrawal #{} of the block {} is not in the withdrawal root",
+                withdrawal_proof.index(),
+                block_number
+            );
+        }
+
+        self.block_hashes.push(public_inputs.block_hash.into());
+        self.blocks.push(BlockRecord {
+            proof,
+            witness,
+            merge_header,
+            diff,
+            withdrawal_proofs,
+        });
+
+        Ok(self.blocks.last().unwrap())
+    }
+
+    /// Proves the `index`-th mint of the block `block_number` into the user asset tree of the issuer.
+    fn prove_mint(
+        &mut self,
+        block_number: u32,
+        index: usize,
+        mint: &ScenarioMint,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let circuits = self.circuits;
+        let request = MintRequest {
+            kind: self.tokens[mint.token],
+            amount: mint.amount,
+            nonce: HashOut::from_partial(&[
+                F::from_canonical_u32(block_number),
+                F::from_canonical_usize(index),
+            ])
+            .into(),
+            block_number,
+        };
+        let mut pw = PartialWitness::new();
+        circuits.simple_signature_circuit.targets.set_witness(
+            &mut pw,
+            self.issuer.private_key,
+            request.message(),
+        );
+        let issuer_signature = circuits.simple_signature_circuit.prove(pw)?;
+
+        let old_user_asset_root = self.issuer_asset_tree.get_root();
+        let asset_witness = self.issuer_asset_tree.set(
+            mint_merge_key(request.message()),
+            request.kind.contract_address.0.into(),
+            request.kind.variable_index,
+            amount_to_hash(request.amount),
+        )?;
+        let mint_proof = circuits.mint_circuit.prove(
+            &request,
+            &asset_witness,
+            &self.issuer_registry.prove(&request.kind.contract_address)?,
+            &issuer_signature,
+            old_user_asset_root,
+        )?;
+        circuits.mint_circuit.verify(mint_proof.clone())?;
+
+        Ok(mint_proof)
+    }
+
+    /// Applies the merges and purges of `simulated` to the wallet of `accounts[sender]`.
+    fn confirm_transaction(
+        &mut self,
+        sender: usize,
+        simulated: &SimulatedTransaction,
+    ) -> anyhow::Result<()> {
+        let wallet = &mut self.wallets[sender];
+        let nodes_db = wallet.user_asset_tree.nodes_db.clone();
+        let mut merge_tree =
+            PoseidonSparseMerkleTree::new(nodes_db.clone(), wallet.user_asset_tree.get_root());
+        for merge_witness in simulated.witness.merge_witnesses.iter() {
+            let merge_process_proof = &merge_witness.merge_process_proof;
+            merge_tree.set(merge_process_proof.new_key, merge_process_proof.new_value)?;
+        }
+        let mut user_asset_tree =
+            LayeredLayeredPoseidonSparseMerkleTree::new(nodes_db, merge_tree.get_root());
+        for owned in simulated.purged_assets.iter() {
+            user_asset_tree.set(
+                owned.merge_key,
+                owned.asset.kind.contract_address.0.into(),
+                owned.asset.kind.variable_index,
+                Default::default(),
+            )?;
+        }
+        anyhow::ensure!(
+            user_asset_tree.get_root() == simulated.new_user_asset_root,
+            "the wallet of {} does not reach the user asset root of the transaction",
+            wallet.address
+        );
+        anyhow::ensure!(
+            self.trees.world_state_tree.get(&wallet.address.0.into())?
+                == simulated.new_user_asset_root,
+            "the world state does not have the user asset root of {}",
+            wallet.address
+        );
+
+        wallet.user_asset_tree = user_asset_tree;
+        wallet.assets = simulated.remaining_assets.clone();
+        wallet.pending_assets.clear();
+
+        Ok(())
+    }
+
+    /// Delivers the diffs of the transaction in `slot` of the block of `merge_header`.
+    fn receive_transfers(
+        &mut self,
+        merge_header: &BlockHeader<F>,
+        tx_hashes: &[WrappedHashOut<F>],
+        slot: usize,
+        sender: Address<F>,
+        simulated: &SimulatedTransaction,
+    ) -> anyhow::Result<()> {
+        let mut diff_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+        for diff in simulated.diffs.iter() {
+            diff_tree.set(
+                diff.recipient.0.into(),
+                diff.asset.kind.contract_address.0.into(),
+                diff.asset.kind.variable_index,
+                amount_to_hash(diff.asset.amount),
+            )?;
+        }
+        let diff_tree: PoseidonSparseMerkleTree<NodeDataMemory> = diff_tree.into();
+        anyhow::ensure!(
+            diff_tree.get_root() == simulated.diff_root,
+            "the diffs are not of the diff root {}",
+            simulated.diff_root
+        );
+
+        let tx_inclusion_proof = get_merkle_proof(tx_hashes, slot, N_LOG_TXS);
+        let latest_account_tree_inclusion_proof =
+            self.trees.latest_account_tree.find(&sender.0.into())?;
+        let mut recipients: Vec<Address<F>> = vec![];
+        for diff in simulated.diffs.iter() {
+            if !recipients.contains(&diff.recipient) {
+                recipients.push(diff.recipient);
+            }
+        }
+        for recipient in recipients {
+            let received = ReceivedAssetProof {
+                is_deposit: false,
+                diff_tree_inclusion_proof: (
+                    merge_header.clone(),
+                    tx_inclusion_proof.clone(),
+                    diff_tree.find(&recipient.0.into())?,
+                ),
+                latest_account_tree_inclusion_proof: latest_account_tree_inclusion_proof.clone(),
+                assets: simulated
+                    .diffs
+                    .iter()
+                    .filter(|diff| diff.recipient == recipient)
+                    .map(|diff| diff.asset)
+                    .collect(),
+                nonce: simulated.witness.nonce,
+            };
+            self.receive(recipient, received)?;
+        }
+
+        Ok(())
+    }
+
+    fn receive_deposits(
+        &mut self,
+        merge_header: &BlockHeader<F>,
+        deposits: &[Deposit<F>],
+        deposit_tree: &DepositTree<F>,
+    ) -> anyhow::Result<()> {
+        for recipient_proof in deposit_tree.recipient_proofs.iter() {
+            let received = ReceivedAssetProof {
+                is_deposit: true,
+                diff_tree_inclusion_proof: (
+                    merge_header.clone(),
+                    recipient_proof.tx_inclusion_proof.clone(),
+                    recipient_proof.recipient_inclusion_proof.clone(),
+                ),
+                // The merge gadget only checks the root for a deposit.
+                latest_account_tree_inclusion_proof: self
+                    .trees
+                    .latest_account_tree
+                    .find(&recipient_proof.recipient.0.into())?,
+                assets: deposits
+                    .iter()
+                    .filter(|deposit| deposit.recipient == recipient_proof.recipient)
+                    .map(|deposit| Asset {
+                        kind: TokenKind {
+                            contract_address: deposit.contract,
+                            variable_index: deposit.variable.into(),
+                        },
+                        amount: deposit.amount.to_canonical_u64(),
+                    })
+                    .collect(),
+                nonce: recipient_proof.nonce,
+            };
+            self.receive(recipient_proof.recipient, received)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds `received` to the pending assets of the wallet of `recipient`, if it is an account.
+    fn receive(
+        &mut self,
+        recipient: Address<F>,
+        received: ReceivedAssetProof<F>,
+    ) -> anyhow::Result<()> {
+        let wallet = match self
+            .wallets
+            .iter_mut()
+            .find(|wallet| wallet.address == recipient)
+        {
+            Some(wallet) => wallet,
+            None => return Ok(()),
+        };
+
+        // The wallet needs the nodes under the received root to purge the assets after merging.
+        let mut received_tree = LayeredLayeredPoseidonSparseMerkleTree::new(
+            wallet.user_asset_tree.nodes_db.clone(),
+            Default::default(),
+        );
+        for asset in received.assets.iter() {
+            received_tree.set(
+                recipient.0.into(),
+                asset.kind.contract_address.0.into(),
+                asset.kind.variable_index,
+                amount_to_hash(asset.amount),
+            )?;
+        }
+        let received_tree: PoseidonSparseMerkleTree<NodeDataMemory> = received_tree.into();
+        anyhow::ensure!(
+            received_tree.get(&recipient.0.into())? == received.diff_tree_inclusion_proof.2.value,
+            "the received assets of {} are not of the diff tree",
+            recipient
+        );
+        wallet.pending_assets.push(received);
+
+        Ok(())
+    }
+}
+
+fn prove_block(
+    block_circuit: &SampleBlockCircuit,
+    witness: &BlockWitness<F, C, D>,
+) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+    let mut pw = PartialWitness::new();
+    witness.set_witness(block_circuit, &mut pw)?;
+
+    block_circuit.prove(pw)
+}
+
+#[test]
+fn test_scenario() {
+    use crate::rollup::gadgets::contract::get_contract_leaf;
+
+    let circuits = make_scenario_circuits();
+    let mut scenario = Scenario::new(&circuits, 4, 3).unwrap();
+    let [alice, bob, carol, dave] = [0, 1, 2, 3];
+    let [token0, token1, token2] = [0, 1, 2];
+    assert!(scenario.run_block(&ScenarioBlock::default()).is_err());
+
+    // Each sender keeps `token2` or `token1`, since the transaction creating an account must leave some assets.
+    scenario
+        .genesis(&[
+            scenario.deposit(alice, token0, 30),
+            scenario.deposit(alice, token1, 10),
+            scenario.deposit(alice, token2, 1),
+            scenario.deposit(bob, token0, 50),
+            scenario.deposit(bob, token1, 5),
+            scenario.deposit(bob, token2, 1),
+        ])
+        .unwrap();
+    assert_eq!(scenario.latest_block_number(), Some(0));
+
+    // block 1: alice pays carol, and bob misses the signature.
+    let block = scenario
+        .run_block(&ScenarioBlock {
+            deposits: vec![scenario.deposit(alice, token2, 1)],
+            transactions: vec![
+                ScenarioTransaction {
+                    sender: alice,
+                    transfers: vec![
+                        scenario.transfer(carol, token0, 30),
+                        scenario.transfer(carol, token1, 10),
+                    ],
+                    signs: true,
+                },
+                ScenarioTransaction {
+                    sender: bob,
+                    transfers: vec![
+                        scenario.transfer(dave, token0, 50),
+                        scenario.transfer(dave, token1, 5),
+                    ],
+                    signs: false,
+                },
+            ],
+            mints: vec![],
+            counter_inputs: vec![],
+        })
+        .unwrap();
+    assert_eq!(
+        block
+            .proof
+            .public_inputs
+            .address_list
+            .iter()
+            .map(|sender| sender.is_valid)
+            .collect::<Vec<_>>(),
+        [true, false]
+    );
+    assert!(block.withdrawal_proofs.is_empty());
+    assert_eq!(scenario.balance(alice, token2), 1);
+    assert_eq!(scenario.balance(bob, token0), 0);
+    assert_eq!(scenario.wallets[bob].pending_assets.len(), 1);
+    assert_eq!(scenario.wallets[carol].pending_assets.len(), 1);
+    assert!(scenario.wallets[dave].pending_assets.is_empty());
+
+    // block 2: carol merges the payment and withdraws, and bob retries.
+    let block = scenario
+        .run_block(&ScenarioBlock {
+            deposits: vec![scenario.deposit(alice, token2, 1)],
+            transactions: vec![
+                ScenarioTransaction {
+                    sender: carol,
+                    transfers: vec![scenario.withdrawal(token0, 30)],
+                    signs: true,
+                },
+                ScenarioTransaction {
+                    sender: bob,
+                    transfers: vec![
+                        scenario.transfer(dave, token0, 50),
+                        scenario.transfer(dave, token1, 5),
+                    ],
+                    signs: true,
+                },
+            ],
+            mints: vec![],
+            counter_inputs: vec![],
+        })
+        .unwrap();
+    assert_eq!(block.withdrawal_proofs.len(), 1);
+    assert_eq!(
+        block.withdrawal_proofs[0].withdrawal.amount,
+        F::from_canonical_u64(30)
+    );
+    assert_eq!(
+        block.withdrawal_proofs[0].withdrawal.recipient,
+        scenario.accounts[carol].address
+    );
+    // bob cannot withdraw the burn of carol.
+    let mut unbacked_witness = block.witness.clone();
+    unbacked_witness.burned_assets = vec![vec![], block.witness.burned_assets[0].clone()];
+    assert!(matches!(
+        unbacked_witness.validate(),
+        Err(crate::errors::IntmaxError::InvalidWitness(_))
+    ));
+    // Every transaction needs a signature slot, and each signature must be by its sender.
+    let mut short_witness = block.witness.clone();
+    short_witness.received_signatures.pop();
+    assert!(matches!(
+        short_witness.validate(),
+        Err(crate::errors::IntmaxError::LengthMismatch { .. })
+    ));
+    let mut forged_witness = block.witness.clone();
+    forged_witness.received_signatures.swap(0, 1);
+    assert!(matches!(
+        forged_witness.validate(),
+        Err(crate::errors::IntmaxError::InvalidWitness(_))
+    ));
+    assert_eq!(scenario.balance(carol, token1), 10);
+    assert_eq!(scenario.balance(bob, token2), 1);
+
+    // block 3: dave merges the payment of bob and withdraws a part of it.
+    let block = scenario
+        .run_block(&ScenarioBlock {
+            deposits: vec![scenario.deposit(alice, token2, 1)],
+            transactions: vec![ScenarioTransaction {
+                sender: dave,
+                transfers: vec![scenario.withdrawal(token0, 20)],
+                signs: true,
+            }],
+            mints: vec![ScenarioMint {
+                token: token1,
+                amount: 100,
+            }],
+            counter_inputs: vec![7],
+        })
+        .unwrap();
+    assert_eq!(block.withdrawal_proofs.len(), 1);
+    // The world state cannot take the minted assets without the mint proof.
+    assert_eq!(block.witness.mint_proofs.len(), 1);
+    let mut unminted_witness = block.witness.clone();
+    unminted_witness.mint_proofs.clear();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        prove_block(&circuits.block_circuit, &unminted_witness)
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+    assert_eq!(
+        scenario
+            .trees
+            .world_state_tree
+            .get(&scenario.issuer.address.0.into())
+            .unwrap(),
+        scenario.issuer_asset_tree.get_root()
+    );
+    // The counter contract is deployed after the mint.
+    let deploy = &block.witness.contract_calls[0].0;
+    assert_eq!(
+        deploy.public_inputs().unwrap().new_state_root,
+        scenario.counter_state_root
+    );
+    assert_eq!(
+        *scenario
+            .trees
+            .world_state_tree
+            .get(&deploy.contract_address().0.into())
+            .unwrap(),
+        get_contract_leaf(deploy.verifier_digest(), scenario.counter_state_root)
+    );
+    assert_eq!(scenario.balance(dave, token1), 5);
+    // The change is sent back to dave and is not merged yet.
+    assert_eq!(scenario.balance(dave, token0), 0);
+    assert_eq!(
+        scenario.wallets[dave].pending_assets[0].assets[0].amount,
+        30
+    );
+    assert_eq!(scenario.latest_block_number(), Some(3));
+    assert_eq!(scenario.blocks.len(), 3);
+}
+
+#[test]
+fn test_set_witness_streaming() {
+    use crate::errors::IntmaxError;
+
+    let circuits = make_scenario_circuits();
+    let mut scenario = Scenario::new(&circuits, 4, 3).unwrap();
+    let [alice, bob, carol, dave] = [0, 1, 2, 3];
+    let [token0, token1, token2] = [0, 1, 2];
+    scenario
+        .genesis(&[
+            scenario.deposit(alice, token0, 30),
+            scenario.deposit(alice, token2, 1),
+            scenario.deposit(bob, token0, 50),
+            scenario.deposit(bob, token2, 1),
+        ])
+        .unwrap();
+
+    // bob misses the signature, so the streamed signatures include `None`.
+    let block = scenario
+        .run_block(&ScenarioBlock {
+            deposits: vec![scenario.deposit(alice, token2, 1)],
+            transactions: vec![
+                ScenarioTransaction {
+                    sender: alice,
+                    transfers: vec![scenario.transfer(carol, token0, 30)],
+                    signs: true,
+                },
+                ScenarioTransaction {
+                    sender: bob,
+                    transfers: vec![scenario.transfer(dave, token0, 50)],
+                    signs: false,
+                },
+            ],
+            mints: vec![],
+            counter_inputs: vec![],
+        })
+        .unwrap();
+    let witness = &block.witness;
+    assert!(witness.received_signatures[1].is_none());
+
+    let set_witness_streaming =
+        |received_signatures: Vec<Option<SimpleSignatureProofWithPublicInputs<F, C, D>>>| {
+            let mut pw = PartialWitness::new();
+            circuits
+                .block_circuit
+                .targets
+                .set_witness_streaming(
+                    &mut pw,
+                    witness.block_number,
+                    witness.user_tx_proofs.iter().cloned(),
+                    &witness.deposit_process_proofs,
+                    witness.deposit_nonce,
+                    &witness.world_state_process_proofs,
+                    &witness.world_state_revert_proofs,
+                    received_signatures,
+                    &witness.default_simple_signature,
+                    &witness.latest_account_tree_process_proofs,
+                    &witness.block_header_siblings,
+                    witness.prev_block_hash,
+                    witness.timestamp,
+                    witness.aggregator_address,
+                    witness.issuer_registry_root,
+                    witness.old_world_state_root,
+                    witness.old_forced_transactions_digest,
+                    &witness.forced_transactions,
+                    &witness.burned_assets,
+                    &witness.mint_world_state_process_proofs,
+                    &witness.mint_proofs,
+                    &witness.default_mint_proof,
+                    &witness.contract_calls,
+                    &witness.default_contract_call,
+                    None,
+                )
+                .map(|()| pw)
+        };
+
+    // The streamed witness proves the same block as `set_witness`.
+    let pw = set_witness_streaming(witness.received_signatures.clone()).unwrap();
+    let proof = circuits.block_circuit.prove(pw).unwrap();
+    circuits.block_circuit.verify(proof.clone()).unwrap();
+    assert_eq!(proof.public_inputs, block.proof.public_inputs);
+
+    // The signature of alice is not accepted in the slot of bob.
+    let signed_by_other_key = vec![None, witness.received_signatures[0].clone()];
+    assert!(matches!(
+        set_witness_streaming(signed_by_other_key),
+        Err(IntmaxError::InvalidWitness(_))
+    ));
+}
@@ -0,0 +1,560 @@
+//! Protobuf messages and the tonic service of `proto/intmax.proto`, enabled by the `grpc` feature,
+//! and the conversions between them and the crate's types.
+//!
+//! The proofs are decoded with the `CommonCircuitData` of their circuit,
+//! and rejected if the typed public inputs differ from the ones in the proof bytes.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, RichField},
+    plonk::{
+        circuit_data::CommonCircuitData,
+        config::GenericConfig,
+        proof::{Proof, ProofWithPublicInputs},
+    },
+};
+
+use crate::{
+    rollup::{
+        address_list::TransactionSenderWithValidity,
+        block::{AggregatorSignature, BlockInfo},
+        circuits::{
+            ProposalAndApprovalBlockProofWithPublicInputs, ProposalAndApprovalBlockPublicInputs,
+        },
+        gadgets::deposit_block::DepositInfo,
+    },
+    sparse_merkle_tree::goldilocks_poseidon::{WrappedHashOut, Wrapper},
+    transaction::{
+        block_header::BlockHeader,
+        circuits::{
+            MergeAndPurgeTransitionProofWithPublicInputs, MergeAndPurgeTransitionPublicInputs,
+        },
+    },
+    verification::proof::parse_proof,
+    zkdsa::{
+        account::Address,
+        circuits::{SimpleSignatureProofWithPublicInputs, SimpleSignaturePublicInputs},
+    },
+};
+
+pub mod proto {
+    tonic::include_proto!("intmax.v1");
+}
+
+fn hash_to_bytes<F: RichField>(value: HashOut<F>) -> Vec<u8> {
+    Wrapper(value).to_bytes_le().to_vec()
+}
+
+fn hash_from_bytes<F: RichField>(name: &str, bytes: &[u8]) -> anyhow::Result<HashOut<F>> {
+    anyhow::ensure!(
+        bytes.len() == 32,
+        "{} must be 32 bytes, but got {}",
+        name,
+        bytes.len()
+    );
+
+    let value = WrappedHashOut::from_bytes_le(&bytes.try_into().unwrap())
+        .map_err(|err| err.context(format!("{} has a non-canonical field element", name)))?;
+
+    Ok(value.0)
+}
+
+fn field_from_u64<F: RichField>(name: &str, value: u64) -> anyhow::Result<F> {
+    let element = F::from_noncanonical_u64(value);
+    anyhow::ensure!(
+        element.to_canonical_u64() == value,
+        "{} is not a canonical field element",
+        name
+    );
+
+    Ok(element)
+}
+
+fn required<T>(name: &str, value: Option<T>) -> anyhow::Result<T> {
+    value.ok_or_else(|| anyhow::anyhow!("{} is missing", name))
+}
+
+fn encode_proof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    proof_with_pis: ProofWithPublicInputs<F, C, D>,
+) -> anyhow::Result<Vec<u8>> {
+    proof_with_pis.to_bytes()
+}
+
+fn decode_proof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    bytes: &[u8],
+    common: &CommonCircuitData<F, D>,
+    public_inputs: &[F],
+) -> anyhow::Result<Proof<F, C, D>> {
+    let proof_with_pis = parse_proof::<F, C, D>(bytes, common)?;
+    anyhow::ensure!(
+        proof_with_pis.public_inputs == public_inputs,
+        "the public inputs do not match the proof"
+    );
+
+    Ok(proof_with_pis.proof)
+}
+
+impl<F: RichField> From<MergeAndPurgeTransitionPublicInputs<F>>
+    for proto::MergeAndPurgeTransitionPublicInputs
+{
+    fn from(value: MergeAndPurgeTransitionPublicInputs<F>) -> Self {
+        Self {
+            sender_address: hash_to_bytes(value.sender_address.0),
+            old_user_asset_root: hash_to_bytes(*value.old_user_asset_root),
+            middle_user_asset_root: hash_to_bytes(*value.middle_user_asset_root),
+            new_user_asset_root: hash_to_bytes(*value.new_user_asset_root),
+            diff_root: hash_to_bytes(*value.diff_root),
+            tx_hash: hash_to_bytes(*value.tx_hash),
+            burned_assets_digest: hash_to_bytes(*value.burned_assets_digest),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::MergeAndPurgeTransitionPublicInputs>
+    for MergeAndPurgeTransitionPublicInputs<F>
+{
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::MergeAndPurgeTransitionPublicInputs) -> anyhow::Result<Self> {
+        Ok(Self {
+            sender_address: Address(hash_from_bytes("sender_address", &value.sender_address)?),
+            old_user_asset_root: hash_from_bytes(
+                "old_user_asset_root",
+                &value.old_user_asset_root,
+            )?
+            .into(),
+            middle_user_asset_root: hash_from_bytes(
+                "middle_user_asset_root",
+                &value.middle_user_asset_root,
+            )?
+            .into(),
+            new_user_asset_root: hash_from_bytes(
+                "new_user_asset_root",
+                &value.new_user_asset_root,
+            )?
+            .into(),
+            diff_root: hash_from_bytes("diff_root", &value.diff_root)?.into(),
+            tx_hash: hash_from_bytes("tx_hash", &value.tx_hash)?.into(),
+            burned_assets_digest: hash_from_bytes(
+                "burned_assets_digest",
+                &value.burned_assets_digest,
+            )?
+            .into(),
+        })
+    }
+}
+
+impl<F: RichField> From<SimpleSignaturePublicInputs<F>> for proto::SimpleSignaturePublicInputs {
+    fn from(value: SimpleSignaturePublicInputs<F>) -> Self {
+        Self {
+            message: hash_to_bytes(value.message),
+            public_key: hash_to_bytes(value.public_key),
+            signature: hash_to_bytes(value.signature),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::SimpleSignaturePublicInputs> for SimpleSignaturePublicInputs<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::SimpleSignaturePublicInputs) -> anyhow::Result<Self> {
+        Ok(Self {
+            message: hash_from_bytes("message", &value.message)?,
+            public_key: hash_from_bytes("public_key", &value.public_key)?,
+            signature: hash_from_bytes("signature", &value.signature)?,
+        })
+    }
+}
+
+impl<F: RichField> From<TransactionSenderWithValidity<F>> for proto::TransactionSenderWithValidity {
+    fn from(value: TransactionSenderWithValidity<F>) -> Self {
+        Self {
+            sender_address: hash_to_bytes(value.sender_address.0),
+            is_valid: value.is_valid,
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::TransactionSenderWithValidity>
+    for TransactionSenderWithValidity<F>
+{
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::TransactionSenderWithValidity) -> anyhow::Result<Self> {
+        Ok(Self {
+            sender_address: Address(hash_from_bytes("sender_address", &value.sender_address)?),
+            is_valid: value.is_valid,
+        })
+    }
+}
+
+impl<F: RichField> From<DepositInfo<F>> for proto::DepositInfo {
+    fn from(value: DepositInfo<F>) -> Self {
+        Self {
+            receiver_address: hash_to_bytes(value.receiver_address.0),
+            contract_address: hash_to_bytes(value.contract_address.0),
+            variable_index: hash_to_bytes(value.variable_index),
+            amount: value.amount.to_canonical_u64(),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::DepositInfo> for DepositInfo<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::DepositInfo) -> anyhow::Result<Self> {
+        Ok(Self {
+            receiver_address: Address(hash_from_bytes(
+                "receiver_address",
+                &value.receiver_address,
+            )?),
+            contract_address: Address(hash_from_bytes(
+                "contract_address",
+                &value.contract_address,
+            )?),
+            variable_index: hash_from_bytes("variable_index", &value.variable_index)?,
+            amount: field_from_u64("amount", value.amount)?,
+        })
+    }
+}
+
+impl<F: RichField> From<ProposalAndApprovalBlockPublicInputs<F>>
+    for proto::ProposalAndApprovalBlockPublicInputs
+{
+    fn from(value: ProposalAndApprovalBlockPublicInputs<F>) -> Self {
+        Self {
+            address_list: value.address_list.into_iter().map(Into::into).collect(),
+            deposit_list: value.deposit_list.into_iter().map(Into::into).collect(),
+            old_account_tree_root: hash_to_bytes(value.old_account_tree_root),
+            new_account_tree_root: hash_to_bytes(value.new_account_tree_root),
+            old_world_state_root: hash_to_bytes(value.old_world_state_root),
+            new_world_state_root: hash_to_bytes(value.new_world_state_root),
+            old_prev_block_header_digest: hash_to_bytes(value.old_prev_block_header_digest),
+            new_prev_block_header_digest: hash_to_bytes(value.new_prev_block_header_digest),
+            block_hash: hash_to_bytes(value.block_hash),
+            proposed_world_state_digest: hash_to_bytes(value.proposed_world_state_digest),
+            approved_world_state_digest: hash_to_bytes(value.approved_world_state_digest),
+            latest_account_digest: hash_to_bytes(value.latest_account_digest),
+            tx_hash_list_digest: hash_to_bytes(value.tx_hash_list_digest),
+            old_forced_transactions_digest: hash_to_bytes(value.old_forced_transactions_digest),
+            new_forced_transactions_digest: hash_to_bytes(value.new_forced_transactions_digest),
+            burned_assets_digest: hash_to_bytes(value.burned_assets_digest),
+            withdrawal_root: hash_to_bytes(value.withdrawal_root),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::ProposalAndApprovalBlockPublicInputs>
+    for ProposalAndApprovalBlockPublicInputs<F>
+{
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::ProposalAndApprovalBlockPublicInputs) -> anyhow::Result<Self> {
+        Ok(Self {
+            address_list: value
+                .address_list
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+            deposit_list: value
+                .deposit_list
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+            old_account_tree_root: hash_from_bytes(
+                "old_account_tree_root",
+                &value.old_account_tree_root,
+            )?,
+            new_account_tree_root: hash_from_bytes(
+                "new_account_tree_root",
+                &value.new_account_tree_root,
+            )?,
+            old_world_state_root: hash_from_bytes(
+                "old_world_state_root",
+                &value.old_world_state_root,
+            )?,
+            new_world_state_root: hash_from_bytes(
+                "new_world_state_root",
+                &value.new_world_state_root,
+            )?,
+            old_prev_block_header_digest: hash_from_bytes(
+                "old_prev_block_header_digest",
+                &value.old_prev_block_header_digest,
+            )?,
+            new_prev_block_header_digest: hash_from_bytes(
+                "new_prev_block_header_digest",
+                &value.new_prev_block_header_digest,
+            )?,
+            block_hash: hash_from_bytes("block_hash", &value.block_hash)?,
+            proposed_world_state_digest: hash_from_bytes(
+                "proposed_world_state_digest",
+                &value.proposed_world_state_digest,
+            )?,
+            approved_world_state_digest: hash_from_bytes(
+                "approved_world_state_digest",
+                &value.approved_world_state_digest,
+            )?,
+            latest_account_digest: hash_from_bytes(
+                "latest_account_digest",
+                &value.latest_account_digest,
+            )?,
+            tx_hash_list_digest: hash_from_bytes(
+                "tx_hash_list_digest",
+                &value.tx_hash_list_digest,
+            )?,
+            old_forced_transactions_digest: hash_from_bytes(
+                "old_forced_transactions_digest",
+                &value.old_forced_transactions_digest,
+            )?,
+            new_forced_transactions_digest: hash_from_bytes(
+                "new_forced_transactions_digest",
+                &value.new_forced_transactions_digest,
+            )?,
+            burned_assets_digest: hash_from_bytes(
+                "burned_assets_digest",
+                &value.burned_assets_digest,
+            )?,
+            withdrawal_root: hash_from_bytes("withdrawal_root", &value.withdrawal_root)?,
+        })
+    }
+}
+
+impl<F: RichField> From<BlockHeader<F>> for proto::BlockHeader {
+    fn from(value: BlockHeader<F>) -> Self {
+        Self {
+            block_number: value.block_number,
+            prev_block_header_digest: hash_to_bytes(value.prev_block_header_digest),
+            transactions_digest: hash_to_bytes(value.transactions_digest),
+            deposit_digest: hash_to_bytes(value.deposit_digest),
+            proposed_world_state_digest: hash_to_bytes(value.proposed_world_state_digest),
+            approved_world_state_digest: hash_to_bytes(value.approved_world_state_digest),
+            latest_account_digest: hash_to_bytes(value.latest_account_digest),
+            forced_transactions_digest: hash_to_bytes(value.forced_transactions_digest),
+            issuer_registry_root: hash_to_bytes(value.issuer_registry_root),
+            timestamp: value.timestamp,
+            aggregator_address: hash_to_bytes(value.aggregator_address),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::BlockHeader> for BlockHeader<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::BlockHeader) -> anyhow::Result<Self> {
+        Ok(Self {
+            block_number: value.block_number,
+            prev_block_header_digest: hash_from_bytes(
+                "prev_block_header_digest",
+                &value.prev_block_header_digest,
+            )?,
+            transactions_digest: hash_from_bytes(
+                "transactions_digest",
+                &value.transactions_digest,
+            )?,
+            deposit_digest: hash_from_bytes("deposit_digest", &value.deposit_digest)?,
+            proposed_world_state_digest: hash_from_bytes(
+                "proposed_world_state_digest",
+                &value.proposed_world_state_digest,
+            )?,
+            approved_world_state_digest: hash_from_bytes(
+                "approved_world_state_digest",
+                &value.approved_world_state_digest,
+            )?,
+            latest_account_digest: hash_from_bytes(
+                "latest_account_digest",
+                &value.latest_account_digest,
+            )?,
+            forced_transactions_digest: hash_from_bytes(
+                "forced_transactions_digest",
+                &value.forced_transactions_digest,
+            )?,
+            issuer_registry_root: hash_from_bytes(
+                "issuer_registry_root",
+                &value.issuer_registry_root,
+            )?,
+            timestamp: value.timestamp,
+            aggregator_address: hash_from_bytes("aggregator_address", &value.aggregator_address)?,
+        })
+    }
+}
+
+/// Converts a block without its proof. Set `proof` of the message if the block has been proved.
+impl<F: RichField> From<BlockInfo<F>> for proto::BlockArtifact {
+    fn from(value: BlockInfo<F>) -> Self {
+        Self {
+            header: Some(value.header.into()),
+            transactions: value
+                .transactions
+                .into_iter()
+                .map(|tx_hash| hash_to_bytes(*tx_hash))
+                .collect(),
+            deposit_list: value.deposit_list.into_iter().map(Into::into).collect(),
+            address_list: value.address_list.into_iter().map(Into::into).collect(),
+            proof: None,
+            aggregator_signature: value
+                .aggregator_signature
+                .map_or(vec![], |signature| signature.to_bytes()),
+        }
+    }
+}
+
+impl<F: RichField> TryFrom<proto::BlockArtifact> for BlockInfo<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: proto::BlockArtifact) -> anyhow::Result<Self> {
+        Ok(Self {
+            header: required("header", value.header)?.try_into()?,
+            transactions: value
+                .transactions
+                .iter()
+                .map(|tx_hash| Ok(hash_from_bytes("transactions", tx_hash)?.into()))
+                .collect::<anyhow::Result<_>>()?,
+            deposit_list: value
+                .deposit_list
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+            address_list: value
+                .address_list
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<anyhow::Result<_>>()?,
+            aggregator_signature: if value.aggregator_signature.is_empty() {
+                None
+            } else {
+                Some(AggregatorSignature::from_bytes(
+                    &value.aggregator_signature,
+                )?)
+            },
+        })
+    }
+}
+
+pub fn encode_user_transaction_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+) -> anyhow::Result<proto::MergeAndPurgeTransitionProof> {
+    let public_inputs = value.public_inputs.clone().into();
+
+    Ok(proto::MergeAndPurgeTransitionProof {
+        proof: encode_proof(value.into())?,
+        public_inputs: Some(public_inputs),
+    })
+}
+
+pub fn decode_user_transaction_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: proto::MergeAndPurgeTransitionProof,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>> {
+    let public_inputs: MergeAndPurgeTransitionPublicInputs<F> =
+        required("public_inputs", value.public_inputs)?.try_into()?;
+    let proof = decode_proof(&value.proof, common, &public_inputs.encode())?;
+
+    Ok(MergeAndPurgeTransitionProofWithPublicInputs {
+        proof,
+        public_inputs,
+    })
+}
+
+pub fn encode_simple_signature_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: SimpleSignatureProofWithPublicInputs<F, C, D>,
+) -> anyhow::Result<proto::SimpleSignatureProof> {
+    let public_inputs = value.public_inputs.clone().into();
+
+    Ok(proto::SimpleSignatureProof {
+        proof: encode_proof(value.into())?,
+        public_inputs: Some(public_inputs),
+    })
+}
+
+pub fn decode_simple_signature_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: proto::SimpleSignatureProof,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<SimpleSignatureProofWithPublicInputs<F, C, D>> {
+    let public_inputs: SimpleSignaturePublicInputs<F> =
+        required("public_inputs", value.public_inputs)?.try_into()?;
+    let proof = decode_proof(&value.proof, common, &public_inputs.encode())?;
+
+    Ok(SimpleSignatureProofWithPublicInputs {
+        proof,
+        public_inputs,
+    })
+}
+
+pub fn encode_block_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+) -> anyhow::Result<proto::ProposalAndApprovalBlockProof> {
+    let public_inputs = value.public_inputs.clone().into();
+
+    Ok(proto::ProposalAndApprovalBlockProof {
+        proof: encode_proof(value.into())?,
+        public_inputs: Some(public_inputs),
+    })
+}
+
+pub fn decode_block_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    value: proto::ProposalAndApprovalBlockProof,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+    let public_inputs: ProposalAndApprovalBlockPublicInputs<F> =
+        required("public_inputs", value.public_inputs)?.try_into()?;
+    let proof = decode_proof(&value.proof, common, &public_inputs.encode())?;
+
+    Ok(ProposalAndApprovalBlockProofWithPublicInputs {
+        proof,
+        public_inputs,
+    })
+}
+
+#[test]
+fn test_simple_signature_proof_to_proto() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        iop::witness::PartialWitness,
+    };
+
+    use crate::zkdsa::circuits::make_simple_signature_circuit;
+
+    let circuit = make_simple_signature_circuit();
+    let mut pw = PartialWitness::new();
+    circuit
+        .targets
+        .set_witness(&mut pw, HashOut::rand(), HashOut::rand());
+    let proof = circuit.prove(pw).unwrap();
+
+    let message = encode_simple_signature_proof(proof.clone()).unwrap();
+    let decoded = decode_simple_signature_proof(message.clone(), &circuit.data.common).unwrap();
+    assert_eq!(decoded, proof);
+
+    let mut tampered = message;
+    tampered.public_inputs.as_mut().unwrap().message =
+        hash_to_bytes(HashOut::<GoldilocksField>::rand());
+    assert!(decode_simple_signature_proof::<_, _, 2>(tampered, &circuit.data.common).is_err());
+
+    let header = BlockHeader::<GoldilocksField>::with_tree_depth(3);
+    let decoded: BlockHeader<_> = proto::BlockHeader::from(header.clone()).try_into().unwrap();
+    assert_eq!(decoded, header);
+}
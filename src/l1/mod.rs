@@ -0,0 +1,260 @@
+//! Ingestion of the deposit events of the L1 contract, enabled by the `l1` feature.
+//!
+//! `DepositIngestor` takes the decoded events in the order of the L1 chain,
+//! resolves the recipients with an `AccountRegistry`, batches the deposits of each L1 block
+//! and passes them to a `DepositSink` (e.g. the state manager of the aggregator)
+//! together with the deposit tree built by `build_deposit_tree_with_nonce`.
+//! The nonce of a batch is `l1_deposit_nonce` of its L1 block and its index in the L1 block,
+//! so that the batches of the same deposits have different merge keys.
+
+use std::collections::{BTreeMap, HashMap};
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::hash_types::HashOut,
+};
+
+use crate::{
+    rollup::deposit::{build_deposit_tree_with_nonce, l1_deposit_nonce, Deposit, DepositTree},
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+/// The purge circuit range-checks the amounts to 56 bits.
+pub const MAX_DEPOSIT_AMOUNT: u64 = (1 << 56) - 1;
+
+/// A deposit event decoded from an L1 log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepositEvent {
+    pub l1_block_number: u64,
+    pub log_index: u64,
+
+    /// The L1 account which receives the tokens.
+    pub recipient: [u8; 20],
+    pub contract: Address<F>,
+    pub variable: HashOut<F>,
+    pub amount: u64,
+}
+
+/// Resolves the L1 accounts to their intmax addresses.
+pub trait AccountRegistry {
+    fn resolve(&self, l1_address: &[u8; 20]) -> Option<Address<F>>;
+}
+
+impl AccountRegistry for HashMap<[u8; 20], Address<F>> {
+    fn resolve(&self, l1_address: &[u8; 20]) -> Option<Address<F>> {
+        self.get(l1_address).copied()
+    }
+}
+
+/// The deposits of an L1 block, which fit in one deposit block.
+#[derive(Clone, Debug)]
+pub struct DepositBatch {
+    pub l1_block_number: u64,
+    pub deposits: Vec<Deposit<F>>,
+    pub deposit_tree: DepositTree<F>,
+}
+
+/// Receives the batches, e.g. to update the state and prove the deposit block.
+pub trait DepositSink {
+    fn apply_deposits(&mut self, batch: DepositBatch) -> anyhow::Result<()>;
+}
+
+impl DepositSink for Vec<DepositBatch> {
+    fn apply_deposits(&mut self, batch: DepositBatch) -> anyhow::Result<()> {
+        self.push(batch);
+
+        Ok(())
+    }
+}
+
+pub struct DepositIngestor<R, S> {
+    registry: R,
+    sink: S,
+    max_deposits_per_batch: usize,
+    num_log_txs: usize,
+    last_event: Option<(u64, u64)>,
+    pending: BTreeMap<u64, Vec<DepositEvent>>,
+}
+
+impl<R: AccountRegistry, S: DepositSink> DepositIngestor<R, S> {
+    /// `max_deposits_per_batch` is `N_DEPOSITS` of the block circuit.
+    pub fn new(registry: R, sink: S, max_deposits_per_batch: usize, num_log_txs: usize) -> Self {
+        assert_ne!(max_deposits_per_batch, 0, "a batch must contain a deposit");
+
+        Self {
+            registry,
+            sink,
+            max_deposits_per_batch,
+            num_log_txs,
+            last_event: None,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn registry_mut(&mut self) -> &mut R {
+        &mut self.registry
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns the number of the events which are not flushed yet.
+    pub fn num_pending_events(&self) -> usize {
+        self.pending.values().map(|events| events.len()).sum()
+    }
+
+    /// The events must be pushed in the order of the L1 chain, each exactly once.
+    pub fn push(&mut self, event: DepositEvent) -> anyhow::Result<()> {
+        let position = (event.l1_block_number, event.log_index);
+        if let Some(last_event) = self.last_event {
+            anyhow::ensure!(
+                position > last_event,
+                "deposit event {:?} is not after {:?}",
+                position,
+                last_event
+            );
+        }
+        anyhow::ensure!(
+            event.amount <= MAX_DEPOSIT_AMOUNT,
+            "deposit amount {} exceeds {}",
+            event.amount,
+            MAX_DEPOSIT_AMOUNT
+        );
+
+        self.last_event = Some(position);
+        self.pending
+            .entry(event.l1_block_number)
+            .or_default()
+            .push(event);
+
+        Ok(())
+    }
+
+    /// Passes the deposits of the L1 blocks up to `finalized_l1_block_number` to the sink
+    /// and returns the number of the batches.
+    /// If a recipient is not registered yet, the events of its L1 block and later are kept.
+    pub fn flush(&mut self, finalized_l1_block_number: u64) -> anyhow::Result<usize> {
+        let mut num_batches = 0;
+        while let Some(entry) = self.pending.first_entry() {
+            let l1_block_number = *entry.key();
+            if l1_block_number > finalized_l1_block_number {
+                break;
+            }
+
+            let deposits = resolve_deposits(&self.registry, entry.get())?;
+            for (batch_index, deposits) in deposits.chunks(self.max_deposits_per_batch).enumerate()
+            {
+                let nonce = l1_deposit_nonce(l1_block_number, batch_index as u64);
+                let deposit_tree =
+                    build_deposit_tree_with_nonce(deposits, nonce, self.num_log_txs)?;
+                self.sink.apply_deposits(DepositBatch {
+                    l1_block_number,
+                    deposits: deposits.to_vec(),
+                    deposit_tree,
+                })?;
+                num_batches += 1;
+            }
+
+            entry.remove();
+        }
+
+        Ok(num_batches)
+    }
+}
+
+/// Resolves the recipients and sums up the amounts of the same kind of tokens to the same recipient,
+/// since the deposit tree has one leaf for each of them.
+fn resolve_deposits(
+    registry: &impl AccountRegistry,
+    events: &[DepositEvent],
+) -> anyhow::Result<Vec<Deposit<F>>> {
+    let mut indices = HashMap::new();
+    let mut deposits: Vec<(Address<F>, Address<F>, HashOut<F>, u64)> = vec![];
+    for event in events {
+        let recipient = registry.resolve(&event.recipient).ok_or_else(|| {
+            anyhow::anyhow!(
+                "the recipient 0x{} of deposit ({}, {}) is not registered",
+                hex::encode(event.recipient),
+                event.l1_block_number,
+                event.log_index
+            )
+        })?;
+
+        let kind = (
+            recipient,
+            event.contract,
+            WrappedHashOut::from(event.variable),
+        );
+        if let Some(&index) = indices.get(&kind) {
+            let amount: &mut u64 = &mut deposits[index].3;
+            *amount = amount
+                .checked_add(event.amount)
+                .filter(|amount| *amount <= MAX_DEPOSIT_AMOUNT)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("the total deposit amount to {} overflows", recipient)
+                })?;
+        } else {
+            indices.insert(kind, deposits.len());
+            deposits.push((recipient, event.contract, event.variable, event.amount));
+        }
+    }
+
+    Ok(deposits
+        .into_iter()
+        .map(|(recipient, contract, variable, amount)| Deposit {
+            recipient,
+            contract,
+            variable,
+            amount: F::from_canonical_u64(amount),
+        })
+        .collect())
+}
+
+#[test]
+fn test_ingest_deposit_events() {
+    use crate::sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut;
+
+    let alice = Address(*GoldilocksHashOut::from_u32(1));
+    let bob = Address(*GoldilocksHashOut::from_u32(2));
+    let contract = Address(*GoldilocksHashOut::from_u32(100));
+    let registry = HashMap::from([([1u8; 20], alice), ([2u8; 20], bob)]);
+
+    let mut ingestor = DepositIngestor::new(registry, vec![], 2, 3);
+    let event = |l1_block_number, log_index, recipient, amount| DepositEvent {
+        l1_block_number,
+        log_index,
+        recipient,
+        contract,
+        variable: HashOut::ZERO,
+        amount,
+    };
+    ingestor.push(event(10, 0, [1u8; 20], 5)).unwrap();
+    ingestor.push(event(10, 1, [1u8; 20], 7)).unwrap();
+    ingestor.push(event(10, 2, [2u8; 20], 1)).unwrap();
+    ingestor.push(event(11, 0, [3u8; 20], 1)).unwrap();
+    assert!(ingestor.push(event(10, 3, [1u8; 20], 1)).is_err());
+    assert!(ingestor
+        .push(event(12, 0, [1u8; 20], MAX_DEPOSIT_AMOUNT + 1))
+        .is_err());
+
+    assert_eq!(ingestor.flush(10).unwrap(), 1);
+    let batch = &ingestor.sink()[0];
+    assert_eq!(batch.l1_block_number, 10);
+    assert_eq!(batch.deposits.len(), 2);
+    assert_eq!(batch.deposits[0].amount, F::from_canonical_u64(12));
+    assert!(batch.deposit_tree.recipient_proof(bob).is_some());
+    assert_eq!(*batch.deposit_tree.nonce, l1_deposit_nonce(10, 0));
+
+    // The recipient of the deposit in the L1 block 11 is not registered yet.
+    assert!(ingestor.flush(11).is_err());
+    assert_eq!(ingestor.num_pending_events(), 1);
+    ingestor
+        .registry_mut()
+        .insert([3u8; 20], Address(*GoldilocksHashOut::from_u32(3)));
+    assert_eq!(ingestor.flush(11).unwrap(), 1);
+    assert_eq!(ingestor.num_pending_events(), 0);
+}
@@ -1,8 +1,58 @@
+//! Without the default `std` feature, only `verification` is available (with `alloc`).
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "bincode")]
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod config;
+#[cfg(feature = "std")]
+pub mod diagnostics;
+#[cfg(feature = "std")]
 pub mod ecdsa;
+#[cfg(feature = "std")]
+pub mod envelope;
+#[cfg(feature = "std")]
+pub mod errors;
+#[cfg(feature = "ethers")]
+pub mod ethers;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod fingerprint;
+#[cfg(feature = "std")]
+pub mod fixtures;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "l1")]
+pub mod l1;
+#[cfg(feature = "std")]
 pub mod merkle_tree;
+#[cfg(feature = "std")]
+pub mod monitoring;
+#[cfg(feature = "std")]
 pub mod poseidon;
+#[cfg(feature = "std")]
+pub mod prover;
+#[cfg(feature = "std")]
 pub mod recursion;
+#[cfg(feature = "std")]
 pub mod rollup;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "std")]
 pub mod sparse_merkle_tree;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
 pub mod transaction;
+pub mod verification;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
 pub mod zkdsa;
@@ -1,3 +1,5 @@
+pub mod ordered_tree;
+
 use itertools::Itertools;
 use plonky2::{
     field::extension::Extendable,
@@ -70,6 +72,68 @@ impl<const N_LEVELS: usize> MerkleProofTarget<N_LEVELS> {
     }
 }
 
+/// The same as `MerkleProofTarget`, but the number of levels is decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynMerkleProofTarget {
+    pub index: Target,
+    pub value: HashOutTarget,
+    pub siblings: Vec<HashOutTarget>,
+    pub root: HashOutTarget,
+}
+
+impl DynMerkleProofTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        n_levels: usize,
+    ) -> Self {
+        let index = builder.add_virtual_target();
+        builder.range_check(index, n_levels);
+        let value = builder.add_virtual_hash();
+        let siblings = builder.add_virtual_hashes(n_levels);
+        let root = get_merkle_root_target::<F, H, D>(builder, index, value, &siblings);
+
+        Self {
+            index,
+            value,
+            siblings,
+            root,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        index: usize,
+        value: WrappedHashOut<F>,
+        siblings: &[WrappedHashOut<F>],
+    ) -> WrappedHashOut<F> {
+        pw.set_target(self.index, F::from_canonical_usize(index));
+        pw.set_hash_target(self.value, *value);
+
+        for (sibling_t, sibling) in self
+            .siblings
+            .iter()
+            .cloned()
+            .zip_eq(siblings.iter().cloned())
+        {
+            pw.set_hash_target(sibling_t, *sibling);
+        }
+
+        get_merkle_root(index, value, siblings)
+    }
+}
+
+impl<const N_LEVELS: usize> From<MerkleProofTarget<N_LEVELS>> for DynMerkleProofTarget {
+    fn from(value: MerkleProofTarget<N_LEVELS>) -> Self {
+        Self {
+            index: value.index,
+            value: value.value,
+            siblings: value.siblings.to_vec(),
+            root: value.root,
+        }
+    }
+}
+
 pub fn get_merkle_root_target<
     F: RichField + Extendable<D>,
     H: AlgebraicHasher<F>,
@@ -0,0 +1,302 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOutTarget, RichField},
+    iop::{
+        target::{BoolTarget, Target},
+        witness::Witness,
+    },
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::{
+    merkle_tree::ordered_tree::{
+        OrderedLeaf, OrderedTreeInsertionProof, OrderedTreeNonMembershipProof,
+    },
+    sparse_merkle_tree::gadgets::common::{is_equal_hash_out, logical_or},
+};
+
+use super::{get_merkle_root_target, MerkleProofTarget};
+
+/// Splits `x` into the 32-bit limbs of its canonical value, the most significant first.
+fn split_canonical_limbs<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: Target,
+) -> [Target; 2] {
+    let (lo, hi) = builder.split_low_high(x, 32, 64);
+
+    // `hi * 2^32 + lo` is less than the field order `2^64 - 2^32 + 1` only if `lo = 0` when `hi = 2^32 - 1`.
+    let max_hi = builder.constant(F::from_canonical_u32(u32::MAX));
+    let is_max_hi = builder.is_equal(hi, max_hi);
+    let lo_of_max_hi = builder.mul(lo, is_max_hi.target);
+    builder.assert_zero(lo_of_max_hi);
+
+    [hi, lo]
+}
+
+/// `a` and `b` must be range-checked to 32 bits.
+fn is_less_than_u32<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: Target,
+    b: Target,
+) -> BoolTarget {
+    // `2^32 - 1 + b - a` has the bit 32 if and only if `a < b`.
+    let offset = builder.constant(F::from_canonical_u32(u32::MAX));
+    let diff = builder.add(b, offset);
+    let diff = builder.sub(diff, a);
+    let bits = builder.split_le(diff, 33);
+
+    bits[32]
+}
+
+/// Returns whether `ordered_key(a) < ordered_key(b)`.
+pub fn is_less_than_ordered_key<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: HashOutTarget,
+    b: HashOutTarget,
+) -> BoolTarget {
+    let a_limbs = a
+        .elements
+        .into_iter()
+        .flat_map(|e| split_canonical_limbs(builder, e))
+        .collect::<Vec<_>>();
+    let b_limbs = b
+        .elements
+        .into_iter()
+        .flat_map(|e| split_canonical_limbs(builder, e))
+        .collect::<Vec<_>>();
+
+    // From the least significant limb, `a < b` if the limb is less, or equal and the rest is less.
+    let mut result = builder._false();
+    for (a_limb, b_limb) in a_limbs.into_iter().zip(b_limbs).rev() {
+        let is_less = is_less_than_u32(builder, a_limb, b_limb);
+        let is_equal = builder.is_equal(a_limb, b_limb);
+        let is_equal_and_less = builder.and(is_equal, result);
+        result = logical_or(builder, is_less, is_equal_and_less);
+    }
+
+    result
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OrderedLeafTarget {
+    pub value: HashOutTarget,
+    pub next_value: HashOutTarget,
+    pub next_index: Target,
+}
+
+impl OrderedLeafTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self {
+            value: builder.add_virtual_hash(),
+            next_value: builder.add_virtual_hash(),
+            next_index: builder.add_virtual_target(),
+        }
+    }
+
+    pub fn set_witness<F: RichField>(&self, pw: &mut impl Witness<F>, value: &OrderedLeaf<F>) {
+        pw.set_hash_target(self.value, *value.value);
+        pw.set_hash_target(self.next_value, *value.next_value);
+        pw.set_target(self.next_index, F::from_canonical_usize(value.next_index));
+    }
+
+    pub fn hash<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> HashOutTarget {
+        let inputs = [
+            self.value.elements.to_vec(),
+            self.next_value.elements.to_vec(),
+            vec![self.next_index],
+        ]
+        .concat();
+
+        builder.hash_n_to_hash_no_pad::<H>(inputs)
+    }
+}
+
+/// Proves that `value` is not in the ordered tree with `root`.
+#[derive(Clone, Debug)]
+pub struct OrderedTreeNonMembershipTarget<const N_LEVELS: usize> {
+    pub value: HashOutTarget,                        // input
+    pub low_leaf: OrderedLeafTarget,                 // input
+    pub low_leaf_proof: MerkleProofTarget<N_LEVELS>, // input
+
+    pub root: HashOutTarget, // output
+}
+
+impl<const N_LEVELS: usize> OrderedTreeNonMembershipTarget<N_LEVELS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let constant_true = builder._true();
+        let zero = builder.zero();
+        let default_hash = HashOutTarget::from_partial(&[], zero);
+
+        let value = builder.add_virtual_hash();
+        let low_leaf = OrderedLeafTarget::add_virtual_to(builder);
+        let low_leaf_proof = MerkleProofTarget::add_virtual_to::<F, H, D>(builder);
+        let low_leaf_hash = low_leaf.hash::<F, H, D>(builder);
+        builder.connect_hashes(low_leaf_proof.value, low_leaf_hash);
+
+        // low_leaf.value < value < low_leaf.next_value, where the next value zero is the largest.
+        let is_lower = is_less_than_ordered_key(builder, low_leaf.value, value);
+        builder.connect(is_lower.target, constant_true.target);
+        let is_upper = is_less_than_ordered_key(builder, value, low_leaf.next_value);
+        let is_last = is_equal_hash_out(builder, low_leaf.next_value, default_hash);
+        let is_upper_or_last = logical_or(builder, is_upper, is_last);
+        builder.connect(is_upper_or_last.target, constant_true.target);
+
+        Self {
+            value,
+            low_leaf,
+            root: low_leaf_proof.root,
+            low_leaf_proof,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &OrderedTreeNonMembershipProof<F>,
+    ) {
+        pw.set_hash_target(self.value, *witness.value);
+        self.low_leaf.set_witness(pw, &witness.low_leaf);
+        self.low_leaf_proof.set_witness(
+            pw,
+            witness.low_leaf_proof.index,
+            witness.low_leaf_proof.value,
+            &witness.low_leaf_proof.siblings,
+        );
+    }
+}
+
+/// Links the low leaf to `value` and puts the new leaf at an empty slot.
+#[derive(Clone, Debug)]
+pub struct OrderedTreeInsertionTarget<const N_LEVELS: usize> {
+    pub non_membership_proof: OrderedTreeNonMembershipTarget<N_LEVELS>, // input
+
+    /// The Merkle proof of the empty slot, whose root is after the low leaf is updated.
+    pub new_leaf_proof: MerkleProofTarget<N_LEVELS>, // input
+
+    pub old_root: HashOutTarget, // output
+    pub new_root: HashOutTarget, // output
+}
+
+impl<const N_LEVELS: usize> OrderedTreeInsertionTarget<N_LEVELS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let zero = builder.zero();
+        let default_hash = HashOutTarget::from_partial(&[], zero);
+
+        let non_membership_proof =
+            OrderedTreeNonMembershipTarget::add_virtual_to::<F, H, D>(builder);
+        let new_leaf_proof = MerkleProofTarget::add_virtual_to::<F, H, D>(builder);
+        builder.connect_hashes(new_leaf_proof.value, default_hash);
+
+        let low_leaf = non_membership_proof.low_leaf;
+        let updated_low_leaf = OrderedLeafTarget {
+            value: low_leaf.value,
+            next_value: non_membership_proof.value,
+            next_index: new_leaf_proof.index,
+        };
+        let updated_low_leaf_hash = updated_low_leaf.hash::<F, H, D>(builder);
+        let middle_root = get_merkle_root_target::<F, H, D>(
+            builder,
+            non_membership_proof.low_leaf_proof.index,
+            updated_low_leaf_hash,
+            &non_membership_proof.low_leaf_proof.siblings,
+        );
+        builder.connect_hashes(new_leaf_proof.root, middle_root);
+
+        let new_leaf = OrderedLeafTarget {
+            value: non_membership_proof.value,
+            next_value: low_leaf.next_value,
+            next_index: low_leaf.next_index,
+        };
+        let new_leaf_hash = new_leaf.hash::<F, H, D>(builder);
+        let new_root = get_merkle_root_target::<F, H, D>(
+            builder,
+            new_leaf_proof.index,
+            new_leaf_hash,
+            &new_leaf_proof.siblings,
+        );
+
+        Self {
+            old_root: non_membership_proof.root,
+            new_root,
+            non_membership_proof,
+            new_leaf_proof,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &OrderedTreeInsertionProof<F>,
+    ) {
+        self.non_membership_proof
+            .set_witness(pw, &witness.non_membership_proof);
+        self.new_leaf_proof.set_witness(
+            pw,
+            witness.new_leaf_proof.index,
+            witness.new_leaf_proof.value,
+            &witness.new_leaf_proof.siblings,
+        );
+    }
+}
+
+#[test]
+fn test_ordered_tree_insertion_by_plonky2() {
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::{
+        merkle_tree::ordered_tree::OrderedMerkleTree,
+        sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+    const N_LEVELS: usize = 4;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let target: OrderedTreeInsertionTarget<N_LEVELS> =
+        OrderedTreeInsertionTarget::add_virtual_to::<F, H, D>(&mut builder);
+    builder.register_public_inputs(&target.old_root.elements);
+    builder.register_public_inputs(&target.new_root.elements);
+    let data = builder.build::<C>();
+
+    let mut tree = OrderedMerkleTree::<F>::new(N_LEVELS);
+    for value in [30, 10] {
+        tree.insert(WrappedHashOut::from_u32(value)).unwrap();
+    }
+    let insertion_proof = tree.insert(WrappedHashOut::from_u32(20)).unwrap();
+
+    let mut pw = PartialWitness::new();
+    target.set_witness(&mut pw, &insertion_proof);
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(proof.public_inputs[0..4], insertion_proof.old_root.elements);
+    assert_eq!(proof.public_inputs[4..8], insertion_proof.new_root.elements);
+    data.verify(proof).unwrap();
+
+    // A value in the tree cannot be inserted again.
+    let mut forged_proof = insertion_proof;
+    forged_proof.non_membership_proof.value = WrappedHashOut::from_u32(30);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut pw = PartialWitness::new();
+        target.set_witness(&mut pw, &forged_proof);
+        data.prove(pw).and_then(|proof| data.verify(proof))
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
@@ -1,2 +1,3 @@
 pub mod gadgets;
+pub mod ordered_tree;
 pub mod tree;
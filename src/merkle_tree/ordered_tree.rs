@@ -0,0 +1,319 @@
+//! A Merkle tree of leaves linked in the order of their values, an accumulator of nullifiers
+//! or registered keys.
+//!
+//! Each leaf holds a value with the next larger value in the tree and its index, and the leaves are
+//! appended from the left. A value is not in the tree if a leaf has a lower value and links to a larger
+//! one, so a non-membership proof is a single Merkle proof of the depth of the tree,
+//! instead of a sparse Merkle proof of up to 256 levels.
+//! The leaf 0 has the zero value, which is lower than any other value,
+//! and the next value zero means that there is no larger value.
+//! The values are compared by `ordered_key`.
+
+use std::collections::BTreeMap;
+
+use plonky2::{
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut;
+
+use super::tree::{get_merkle_proof, get_merkle_root, MerkleProof};
+
+/// The key of the total order of the values, i.e. the canonical elements in lexicographic order.
+pub fn ordered_key<F: RichField>(value: HashOut<F>) -> [u64; 4] {
+    value.elements.map(|e| e.to_canonical_u64())
+}
+
+/// `next_value` is zero if `value` is the largest in the tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
+pub struct OrderedLeaf<F: RichField> {
+    pub value: WrappedHashOut<F>,
+    pub next_value: WrappedHashOut<F>,
+    pub next_index: usize,
+}
+
+impl<F: RichField> OrderedLeaf<F> {
+    pub fn hash(&self) -> WrappedHashOut<F> {
+        let inputs = [
+            self.value.elements.to_vec(),
+            self.next_value.elements.to_vec(),
+            vec![F::from_canonical_usize(self.next_index)],
+        ]
+        .concat();
+
+        PoseidonHash::hash_no_pad(&inputs).into()
+    }
+
+    /// Whether `value` is between this leaf and the next one.
+    pub fn is_low_leaf_of(&self, value: WrappedHashOut<F>) -> bool {
+        let key = ordered_key(*value);
+
+        ordered_key(*self.value) < key
+            && (self.next_value == WrappedHashOut::ZERO || key < ordered_key(*self.next_value))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
+pub struct OrderedTreeNonMembershipProof<F: RichField> {
+    pub value: WrappedHashOut<F>,
+    pub low_leaf: OrderedLeaf<F>,
+
+    /// The Merkle proof of the hash of `low_leaf`.
+    pub low_leaf_proof: MerkleProof<F>,
+}
+
+impl<F: RichField> OrderedTreeNonMembershipProof<F> {
+    pub fn verify(&self, root: WrappedHashOut<F>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.low_leaf_proof.root == root,
+            "the proof is for another root"
+        );
+        anyhow::ensure!(
+            self.low_leaf_proof.value == self.low_leaf.hash(),
+            "the Merkle proof is not of the low leaf"
+        );
+        anyhow::ensure!(
+            get_merkle_root(
+                self.low_leaf_proof.index,
+                self.low_leaf_proof.value,
+                &self.low_leaf_proof.siblings
+            ) == root,
+            "the low leaf is not in the tree"
+        );
+        anyhow::ensure!(
+            self.low_leaf.is_low_leaf_of(self.value),
+            "the value {} is not between the low leaf and the next one",
+            self.value
+        );
+
+        Ok(())
+    }
+}
+
+/// The low leaf is linked to the new leaf, and then the new leaf is appended.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
+pub struct OrderedTreeInsertionProof<F: RichField> {
+    pub non_membership_proof: OrderedTreeNonMembershipProof<F>,
+
+    /// The Merkle proof of the empty slot of the new leaf after the low leaf is updated.
+    pub new_leaf_proof: MerkleProof<F>,
+
+    pub old_root: WrappedHashOut<F>,
+    pub new_root: WrappedHashOut<F>,
+}
+
+impl<F: RichField> OrderedTreeInsertionProof<F> {
+    pub fn new_leaf(&self) -> OrderedLeaf<F> {
+        let low_leaf = self.non_membership_proof.low_leaf;
+
+        OrderedLeaf {
+            value: self.non_membership_proof.value,
+            next_value: low_leaf.next_value,
+            next_index: low_leaf.next_index,
+        }
+    }
+
+    pub fn verify(&self) -> anyhow::Result<()> {
+        self.non_membership_proof.verify(self.old_root)?;
+
+        let low_leaf_proof = &self.non_membership_proof.low_leaf_proof;
+        let updated_low_leaf = OrderedLeaf {
+            next_value: self.non_membership_proof.value,
+            next_index: self.new_leaf_proof.index,
+            ..self.non_membership_proof.low_leaf
+        };
+        let middle_root = get_merkle_root(
+            low_leaf_proof.index,
+            updated_low_leaf.hash(),
+            &low_leaf_proof.siblings,
+        );
+        anyhow::ensure!(
+            self.new_leaf_proof.value == WrappedHashOut::ZERO
+                && self.new_leaf_proof.root == middle_root
+                && get_merkle_root(
+                    self.new_leaf_proof.index,
+                    WrappedHashOut::ZERO,
+                    &self.new_leaf_proof.siblings
+                ) == middle_root,
+            "the slot of the new leaf is not empty"
+        );
+        anyhow::ensure!(
+            get_merkle_root(
+                self.new_leaf_proof.index,
+                self.new_leaf().hash(),
+                &self.new_leaf_proof.siblings
+            ) == self.new_root,
+            "the new root is not of the new leaf"
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OrderedMerkleTree<F: RichField> {
+    depth: usize,
+    leaves: Vec<OrderedLeaf<F>>,
+    indices: BTreeMap<[u64; 4], usize>,
+}
+
+impl<F: RichField> OrderedMerkleTree<F> {
+    /// A tree of `2^depth` leaves with only the leaf of the zero value.
+    pub fn new(depth: usize) -> Self {
+        let mut indices = BTreeMap::new();
+        indices.insert(ordered_key(HashOut::ZERO), 0);
+
+        Self {
+            depth,
+            leaves: vec![OrderedLeaf::default()],
+            indices,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The number of the leaves including the leaf of the zero value.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.len() == 1
+    }
+
+    fn leaf_hashes(&self) -> Vec<WrappedHashOut<F>> {
+        self.leaves.iter().map(|leaf| leaf.hash()).collect()
+    }
+
+    pub fn get_root(&self) -> WrappedHashOut<F> {
+        get_merkle_proof(&self.leaf_hashes(), 0, self.depth).root
+    }
+
+    pub fn contains(&self, value: WrappedHashOut<F>) -> bool {
+        self.indices.contains_key(&ordered_key(*value))
+    }
+
+    fn low_leaf_index(&self, value: WrappedHashOut<F>) -> anyhow::Result<usize> {
+        anyhow::ensure!(!self.contains(value), "the value {} is in the tree", value);
+        let (_, index) = self
+            .indices
+            .range(..ordered_key(*value))
+            .next_back()
+            .expect("the zero value is lower than the others");
+
+        Ok(*index)
+    }
+
+    pub fn prove_non_membership(
+        &self,
+        value: WrappedHashOut<F>,
+    ) -> anyhow::Result<OrderedTreeNonMembershipProof<F>> {
+        let low_leaf_index = self.low_leaf_index(value)?;
+
+        Ok(OrderedTreeNonMembershipProof {
+            value,
+            low_leaf: self.leaves[low_leaf_index],
+            low_leaf_proof: get_merkle_proof(&self.leaf_hashes(), low_leaf_index, self.depth),
+        })
+    }
+
+    pub fn insert(
+        &mut self,
+        value: WrappedHashOut<F>,
+    ) -> anyhow::Result<OrderedTreeInsertionProof<F>> {
+        let new_leaf_index = self.leaves.len();
+        anyhow::ensure!(
+            new_leaf_index < 1 << self.depth,
+            "the tree of depth {} is full",
+            self.depth
+        );
+        let non_membership_proof = self.prove_non_membership(value)?;
+        let old_root = non_membership_proof.low_leaf_proof.root;
+
+        let low_leaf = &mut self.leaves[non_membership_proof.low_leaf_proof.index];
+        let new_leaf = OrderedLeaf {
+            value,
+            next_value: low_leaf.next_value,
+            next_index: low_leaf.next_index,
+        };
+        low_leaf.next_value = value;
+        low_leaf.next_index = new_leaf_index;
+
+        let mut leaf_hashes = self.leaf_hashes();
+        leaf_hashes.push(WrappedHashOut::ZERO);
+        let new_leaf_proof = get_merkle_proof(&leaf_hashes, new_leaf_index, self.depth);
+        let new_root = get_merkle_root(new_leaf_index, new_leaf.hash(), &new_leaf_proof.siblings);
+
+        self.leaves.push(new_leaf);
+        self.indices.insert(ordered_key(*value), new_leaf_index);
+
+        Ok(OrderedTreeInsertionProof {
+            non_membership_proof,
+            new_leaf_proof,
+            old_root,
+            new_root,
+        })
+    }
+}
+
+#[test]
+fn test_ordered_merkle_tree() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+
+    type F = GoldilocksField;
+
+    let mut tree = OrderedMerkleTree::<F>::new(3);
+    let values = [30, 10, 20, 40].map(WrappedHashOut::<F>::from_u32);
+    for value in values {
+        let old_root = tree.get_root();
+        let proof = tree.insert(value).unwrap();
+        assert_eq!(proof.old_root, old_root);
+        assert_eq!(proof.new_root, tree.get_root());
+        proof.verify().unwrap();
+    }
+    assert_eq!(tree.len(), 5);
+    assert!(tree.contains(values[0]));
+    assert!(tree.insert(values[0]).is_err());
+
+    let root = tree.get_root();
+    let proof = tree
+        .prove_non_membership(WrappedHashOut::from_u32(25))
+        .unwrap();
+    assert_eq!(proof.low_leaf.value, WrappedHashOut::from_u32(20));
+    proof.verify(root).unwrap();
+    let proof = tree
+        .prove_non_membership(WrappedHashOut::from_u32(50))
+        .unwrap();
+    assert_eq!(proof.low_leaf.next_value, WrappedHashOut::ZERO);
+    proof.verify(root).unwrap();
+
+    // The low leaf of another value does not prove non-membership.
+    let mut forged_proof = proof;
+    forged_proof.value = WrappedHashOut::from_u32(40);
+    assert!(forged_proof.verify(root).is_err());
+
+    // The values are compared by the canonical elements from the first one.
+    let value = WrappedHashOut::from(HashOut {
+        elements: [F::from_canonical_u32(40), F::NEG_ONE, F::ZERO, F::ZERO],
+    });
+    let proof = tree.prove_non_membership(value).unwrap();
+    assert_eq!(proof.low_leaf.value, values[3]);
+    tree.insert(value).unwrap().verify().unwrap();
+    assert!(tree.prove_non_membership(values[3]).is_err());
+
+    // The tree of depth 3 has 8 leaves.
+    for value in [50, 60].map(WrappedHashOut::from_u32) {
+        tree.insert(value).unwrap().verify().unwrap();
+    }
+    assert!(tree.insert(WrappedHashOut::from_u32(70)).is_err());
+}
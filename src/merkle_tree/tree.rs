@@ -1,10 +1,19 @@
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
 use plonky2::{
-    hash::{hash_types::RichField, poseidon::PoseidonHash},
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
     plonk::config::Hasher,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut;
+use crate::{poseidon::batch::hash_layer, sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut};
 
 pub fn log2_ceil(value: usize) -> u32 {
     assert!(value != 0, "The first argument must be a positive number.");
@@ -23,6 +32,38 @@ pub fn log2_ceil(value: usize) -> u32 {
     log_value
 }
 
+/// `zero_hashes(depth)[h]` is the root of a subtree of height `h` with only the zero leaves.
+/// The hashes are cached for each field, so that the proofs over mostly empty trees,
+/// e.g. the deposit trees or the blocks padded to `N_LOG_TXS`, do not recompute them.
+pub fn zero_hashes<F: RichField>(depth: usize) -> Vec<WrappedHashOut<F>> {
+    static ZERO_HASHES: OnceLock<Mutex<HashMap<TypeId, Vec<[u64; 4]>>>> = OnceLock::new();
+
+    let mut cache = ZERO_HASHES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let cached_hashes = cache
+        .entry(TypeId::of::<F>())
+        .or_insert_with(|| vec![[0; 4]]);
+    while cached_hashes.len() < depth {
+        let last_zero = HashOut {
+            elements: cached_hashes.last().unwrap().map(F::from_canonical_u64),
+        };
+        let zero = PoseidonHash::two_to_one(last_zero, last_zero);
+        cached_hashes.push(zero.elements.map(|e| e.to_canonical_u64()));
+    }
+
+    cached_hashes[..depth]
+        .iter()
+        .map(|zero| {
+            HashOut {
+                elements: zero.map(F::from_canonical_u64),
+            }
+            .into()
+        })
+        .collect()
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
 pub struct MerkleProof<F: RichField> {
@@ -52,38 +93,33 @@ pub fn get_merkle_proof<F: RichField>(
     index: usize,
     depth: usize,
 ) -> MerkleProof<F> {
-    let mut nodes = if leaves.is_empty() {
-        vec![Default::default()]
+    let mut nodes: Vec<HashOut<F>> = if leaves.is_empty() {
+        vec![HashOut::ZERO]
     } else {
-        leaves.to_vec()
+        leaves.iter().map(|leaf| **leaf).collect()
     };
     assert!(index < nodes.len());
     let num_leaves = nodes.len().next_power_of_two();
     let log_num_leaves = log2_ceil(num_leaves) as usize;
-    let value = nodes[index];
-    nodes.resize(num_leaves, WrappedHashOut::ZERO);
+    let value = nodes[index].into();
 
-    let mut siblings = vec![WrappedHashOut::ZERO]; // initialize by zero hashes
-    for _ in 1..depth {
-        let last_zero: WrappedHashOut<F> = *siblings.last().unwrap();
-        siblings.push(PoseidonHash::two_to_one(*last_zero, *last_zero).into());
-    }
+    let zero_hashes = zero_hashes::<F>(depth.max(1));
+    let mut siblings = zero_hashes.clone(); // initialize by zero hashes
 
     let mut rest_index = index;
-    for sibling in siblings.iter_mut().take(log_num_leaves) {
-        let _ = std::mem::replace(sibling, nodes[rest_index ^ 1]);
-
-        let mut new_nodes: Vec<WrappedHashOut<F>> = vec![];
-        for j in 0..(nodes.len() / 2) {
-            new_nodes.push(PoseidonHash::two_to_one(*nodes[2 * j], *nodes[2 * j + 1]).into());
+    for (level, sibling) in siblings.iter_mut().take(log_num_leaves).enumerate() {
+        // 右側の 0 埋めは高さ `level` の zero hash を 1 つ足すだけで, 葉まで展開しない.
+        if nodes.len() % 2 == 1 {
+            nodes.push(*zero_hashes[level]);
         }
+        let _ = std::mem::replace(sibling, nodes[rest_index ^ 1].into());
 
         rest_index >>= 1;
-        nodes = new_nodes;
+        nodes = hash_layer::<F, PoseidonHash>(&nodes);
     }
 
     assert_eq!(nodes.len(), 1);
-    let mut root = nodes[0];
+    let mut root: WrappedHashOut<F> = nodes[0].into();
     for sibling in siblings.iter().cloned().skip(log_num_leaves) {
         // log_num_leaves 層より上は sibling が必ず右側にくる.
         root = PoseidonHash::two_to_one(*root, *sibling).into();
@@ -103,19 +139,9 @@ pub fn get_merkle_root<F: RichField>(
     value: WrappedHashOut<F>,
     siblings: &[WrappedHashOut<F>],
 ) -> WrappedHashOut<F> {
-    let mut root = value;
-    let mut rest_index = index;
-    for sibling in siblings {
-        let (left, right) = if rest_index & 1 == 0 {
-            (root, *sibling)
-        } else {
-            (*sibling, root)
-        };
-        root = PoseidonHash::two_to_one(*left, *right).into();
-        rest_index >>= 1;
-    }
+    let siblings = siblings.iter().map(|sibling| **sibling).collect::<Vec<_>>();
 
-    root
+    crate::verification::merkle::get_merkle_root(index, *value, &siblings).into()
 }
 
 #[test]
@@ -161,3 +187,52 @@ fn test_get_block_hash_tree_proofs() {
     assert_eq!(siblings, actual_siblings);
     assert_eq!(new_root, actual_new_root);
 }
+
+#[test]
+fn test_get_merkle_proof_with_padding() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+
+    type F = GoldilocksField;
+    const N_LEVELS: usize = 6;
+
+    for n_leaves in [1, 2, 3, 5, 8, 13] {
+        let leaves = (0..n_leaves)
+            .map(|i| HashOut::from_partial(&[F::from_canonical_usize(i + 1)]).into())
+            .collect::<Vec<WrappedHashOut<F>>>();
+
+        // The root of all the `2^N_LEVELS` leaves with the zeros.
+        let mut nodes = leaves.iter().map(|leaf| **leaf).collect::<Vec<_>>();
+        nodes.resize(1 << N_LEVELS, HashOut::ZERO);
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| PoseidonHash::two_to_one(pair[0], pair[1]))
+                .collect();
+        }
+
+        for index in 0..n_leaves {
+            let proof = get_merkle_proof(&leaves, index, N_LEVELS);
+            assert_eq!(*proof.root, nodes[0]);
+            assert_eq!(
+                get_merkle_root(index, leaves[index], &proof.siblings),
+                proof.root
+            );
+        }
+    }
+}
+
+#[test]
+fn test_zero_hashes() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    type F = GoldilocksField;
+
+    let hashes = zero_hashes::<F>(4);
+    assert_eq!(hashes.len(), 4);
+    assert_eq!(hashes[0], WrappedHashOut::ZERO);
+    for pair in hashes.windows(2) {
+        assert_eq!(*pair[1], PoseidonHash::two_to_one(*pair[0], *pair[0]));
+    }
+    assert_eq!(zero_hashes::<F>(2), hashes[..2]);
+    assert_eq!(MerkleProof::<F>::new(4).siblings, hashes);
+}
@@ -0,0 +1,44 @@
+//! Metrics for aggregator operators, recorded through the `metrics` facade.
+//!
+//! Install any `metrics` recorder (e.g. `metrics-exporter-prometheus`) in the application
+//! and enable the `metrics` feature. Without the feature, every function is a no-op.
+
+use std::time::Duration;
+
+pub const PROVING_DURATION_SECONDS: &str = "intmax_proving_duration_seconds";
+pub const CIRCUIT_DEGREE_BITS: &str = "intmax_circuit_degree_bits";
+pub const TREE_OPERATIONS_TOTAL: &str = "intmax_tree_operations_total";
+pub const MEMPOOL_DEPTH: &str = "intmax_mempool_depth";
+
+/// Records the time taken by `prove` of the circuit named `circuit`.
+pub fn record_proving_duration(circuit: &'static str, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    metrics::histogram!(PROVING_DURATION_SECONDS, duration, "circuit" => circuit);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (circuit, duration);
+}
+
+/// Records the size of a built circuit as `log2` of the number of rows.
+pub fn record_circuit_size(circuit: &'static str, degree_bits: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!(CIRCUIT_DEGREE_BITS, degree_bits as f64, "circuit" => circuit);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (circuit, degree_bits);
+}
+
+/// Counts an operation of a sparse Merkle tree, e.g. `"insert"` or `"find"`.
+pub fn increment_tree_operation(operation: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(TREE_OPERATIONS_TOTAL, 1, "operation" => operation);
+    #[cfg(not(feature = "metrics"))]
+    let _ = operation;
+}
+
+/// The mempool is owned by the aggregator, so it should call this whenever the number of
+/// pending transactions changes.
+pub fn set_mempool_depth(depth: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!(MEMPOOL_DEPTH, depth as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = depth;
+}
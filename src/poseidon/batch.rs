@@ -0,0 +1,90 @@
+//! Hashing of many node pairs at once for the native trees.
+//!
+//! The pairs are split into contiguous chunks hashed on the rayon threads, and the permutations
+//! of a chunk run back to back, so that plonky2 uses its AVX2 or NEON Poseidon for Goldilocks
+//! when it is built with the target features, e.g. `RUSTFLAGS="-C target-cpu=native"`.
+//! Fewer pairs than `PARALLEL_HASH_THRESHOLD` are hashed on the current thread.
+
+use plonky2::{
+    hash::hash_types::{HashOut, RichField},
+    plonk::config::Hasher,
+};
+use rayon::prelude::*;
+
+/// The number of the pairs hashed by a task.
+pub const PARALLEL_HASH_THRESHOLD: usize = 256;
+
+/// Returns `H::two_to_one(left, right)` of each pair in the same order.
+pub fn two_to_one_batch<F: RichField, H: Hasher<F, Hash = HashOut<F>>>(
+    pairs: &[(HashOut<F>, HashOut<F>)],
+) -> Vec<HashOut<F>> {
+    if pairs.len() < PARALLEL_HASH_THRESHOLD {
+        return pairs
+            .iter()
+            .map(|(left, right)| H::two_to_one(*left, *right))
+            .collect();
+    }
+
+    pairs
+        .par_chunks(PARALLEL_HASH_THRESHOLD)
+        .flat_map_iter(|chunk| {
+            chunk
+                .iter()
+                .map(|(left, right)| H::two_to_one(*left, *right))
+        })
+        .collect()
+}
+
+/// Hashes the nodes `2 * j` and `2 * j + 1` of a layer into the node `j` of the parent layer.
+/// The length of `nodes` must be even.
+pub fn hash_layer<F: RichField, H: Hasher<F, Hash = HashOut<F>>>(
+    nodes: &[HashOut<F>],
+) -> Vec<HashOut<F>> {
+    assert_eq!(
+        nodes.len() % 2,
+        0,
+        "a layer must have an even number of nodes"
+    );
+    let hash_chunk = |chunk: &[HashOut<F>]| {
+        chunk
+            .chunks(2)
+            .map(|pair| H::two_to_one(pair[0], pair[1]))
+            .collect::<Vec<_>>()
+    };
+
+    if nodes.len() < 2 * PARALLEL_HASH_THRESHOLD {
+        return hash_chunk(nodes);
+    }
+
+    nodes
+        .par_chunks(2 * PARALLEL_HASH_THRESHOLD)
+        .flat_map_iter(hash_chunk)
+        .collect()
+}
+
+#[test]
+fn test_hash_layer() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Field},
+        hash::poseidon::PoseidonHash,
+    };
+
+    type F = GoldilocksField;
+
+    for n_pairs in [1, PARALLEL_HASH_THRESHOLD + 3] {
+        let nodes = (0..2 * n_pairs)
+            .map(|i| HashOut::from_partial(&[F::from_canonical_usize(i)]))
+            .collect::<Vec<_>>();
+        let pairs = nodes
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect::<Vec<_>>();
+        let expected = pairs
+            .iter()
+            .map(|(left, right)| PoseidonHash::two_to_one(*left, *right))
+            .collect::<Vec<_>>();
+
+        assert_eq!(hash_layer::<F, PoseidonHash>(&nodes), expected);
+        assert_eq!(two_to_one_batch::<F, PoseidonHash>(&pairs), expected);
+    }
+}
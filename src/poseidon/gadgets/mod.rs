@@ -1,3 +1,5 @@
+pub mod poseidon2;
+
 use plonky2::{
     field::extension::Extendable,
     hash::hash_types::{HashOutTarget, RichField},
@@ -0,0 +1,140 @@
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField, types::Field},
+    hash::hashing::SPONGE_WIDTH,
+    iop::target::{BoolTarget, Target},
+    plonk::circuit_builder::CircuitBuilder,
+};
+
+use crate::poseidon::poseidon2::{is_full_round, round_constants, INTERNAL_DIAG_M_1};
+
+type F = GoldilocksField;
+
+fn sbox_target<const D: usize>(builder: &mut CircuitBuilder<F, D>, x: Target) -> Target
+where
+    F: Extendable<D>,
+{
+    let x2 = builder.mul(x, x);
+    let x3 = builder.mul(x2, x);
+    let x4 = builder.mul(x2, x2);
+
+    builder.mul(x4, x3)
+}
+
+fn matmul_m4_target<const D: usize>(builder: &mut CircuitBuilder<F, D>, x: &mut [Target])
+where
+    F: Extendable<D>,
+{
+    let two = F::TWO;
+    let four = F::from_canonical_u64(4);
+    let t0 = builder.add(x[0], x[1]);
+    let t1 = builder.add(x[2], x[3]);
+    let t2 = builder.mul_const_add(two, x[1], t1);
+    let t3 = builder.mul_const_add(two, x[3], t0);
+    let t4 = builder.mul_const_add(four, t1, t3);
+    let t5 = builder.mul_const_add(four, t0, t2);
+    let t6 = builder.add(t3, t5);
+    let t7 = builder.add(t2, t4);
+    x.copy_from_slice(&[t6, t5, t7, t4]);
+}
+
+fn matmul_external_target<const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    state: &mut [Target; SPONGE_WIDTH],
+) where
+    F: Extendable<D>,
+{
+    for chunk in state.chunks_mut(4) {
+        matmul_m4_target(builder, chunk);
+    }
+    let sums: [Target; 4] = core::array::from_fn(|l| {
+        let terms = (l..SPONGE_WIDTH).step_by(4).map(|i| state[i]).collect();
+        builder.add_many(terms)
+    });
+    for (i, s) in state.iter_mut().enumerate() {
+        *s = builder.add(*s, sums[i % 4]);
+    }
+}
+
+fn matmul_internal_target<const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    state: &mut [Target; SPONGE_WIDTH],
+) where
+    F: Extendable<D>,
+{
+    let sum = builder.add_many(state.to_vec());
+    for (s, d) in state.iter_mut().zip(INTERNAL_DIAG_M_1) {
+        *s = builder.mul_const_add(F::from_canonical_u64(d), *s, sum);
+    }
+}
+
+/// The in-circuit counterpart of `poseidon2`, where the first two 4-element chunks of `inputs`
+/// are swapped if `swap` is true.
+pub fn poseidon2_permute_swapped_target<const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    inputs: [Target; SPONGE_WIDTH],
+    swap: BoolTarget,
+) -> [Target; SPONGE_WIDTH]
+where
+    F: Extendable<D>,
+{
+    let mut state = inputs;
+    for i in 0..4 {
+        state[i] = builder.select(swap, inputs[i + 4], inputs[i]);
+        state[i + 4] = builder.select(swap, inputs[i], inputs[i + 4]);
+    }
+
+    matmul_external_target(builder, &mut state);
+    for (r, constants) in round_constants().iter().enumerate() {
+        if is_full_round(r) {
+            for (s, c) in state.iter_mut().zip(constants) {
+                let x = builder.add_const(*s, *c);
+                *s = sbox_target(builder, x);
+            }
+            matmul_external_target(builder, &mut state);
+        } else {
+            let x = builder.add_const(state[0], constants[0]);
+            state[0] = sbox_target(builder, x);
+            matmul_internal_target(builder, &mut state);
+        }
+    }
+
+    state
+}
+
+#[test]
+fn test_poseidon2_target() {
+    use plonky2::{
+        hash::hash_types::HashOut,
+        iop::witness::{PartialWitness, Witness},
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{Hasher, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::poseidon::{gadgets::poseidon_two_to_one, poseidon2::Poseidon2Hash};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+
+    let left = HashOut::from_partial(&[F::from_canonical_u64(1), F::NEG_ONE]);
+    let right = HashOut::from_partial(&[F::from_canonical_u64(2)]);
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let left_t = builder.add_virtual_hash();
+    let right_t = builder.add_virtual_hash();
+    let hash_t = poseidon_two_to_one::<F, Poseidon2Hash, D>(&mut builder, left_t, right_t);
+    builder.register_public_inputs(&hash_t.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(left_t, left);
+    pw.set_hash_target(right_t, right);
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(
+        proof.public_inputs,
+        Poseidon2Hash::two_to_one(left, right).elements
+    );
+    data.verify(proof).unwrap();
+}
@@ -1 +1,4 @@
+pub mod batch;
 pub mod gadgets;
+pub mod params;
+pub mod poseidon2;
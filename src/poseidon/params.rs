@@ -0,0 +1,257 @@
+//! The Poseidon parameters over Goldilocks used by this crate, in a form external verifiers can read.
+//!
+//! The field elements are written as `0x`-prefixed hex strings of their canonical values,
+//! since they do not fit in JavaScript numbers.
+
+use std::path::Path;
+
+use plonky2::{
+    field::{
+        goldilocks_field::GoldilocksField,
+        types::{Field, PrimeField64},
+    },
+    hash::{
+        hash_types::HashOut,
+        hashing::SPONGE_WIDTH,
+        poseidon::{
+            Poseidon, PoseidonHash, ALL_ROUND_CONSTANTS, HALF_N_FULL_ROUNDS, N_PARTIAL_ROUNDS,
+        },
+    },
+    plonk::config::Hasher,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::verification::merkle::get_smt_leaf_hash;
+
+type F = GoldilocksField;
+
+pub const SPONGE_RATE: usize = 8;
+
+/// The S-box is `x -> x^7`.
+pub const SBOX_DEGREE: usize = 7;
+
+fn to_hex(value: u64) -> String {
+    format!("0x{:016x}", value)
+}
+
+fn elements_to_hex(elements: &[F]) -> Vec<String> {
+    elements
+        .iter()
+        .map(|e| to_hex(e.to_canonical_u64()))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoseidonParameters {
+    pub field_modulus: String,
+    pub width: usize,
+    pub rate: usize,
+    pub sbox_degree: usize,
+    /// The number of full rounds before and after the partial rounds.
+    pub half_n_full_rounds: usize,
+    pub n_partial_rounds: usize,
+    /// `round_constants[r][i]` is added to the state `i` at the beginning of the round `r`.
+    pub round_constants: Vec<Vec<String>>,
+    /// `mds_matrix[r][c]`, i.e. the new state `r` is `sum_c mds_matrix[r][c] * state[c]`.
+    pub mds_matrix: Vec<Vec<String>>,
+}
+
+impl PoseidonParameters {
+    pub fn goldilocks() -> Self {
+        let n_rounds = 2 * HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS;
+        let round_constants = (0..n_rounds)
+            .map(|r| {
+                ALL_ROUND_CONSTANTS[r * SPONGE_WIDTH..(r + 1) * SPONGE_WIDTH]
+                    .iter()
+                    .map(|c| to_hex(*c))
+                    .collect()
+            })
+            .collect();
+
+        // The MDS matrix is the sum of a circulant matrix and a diagonal matrix.
+        let mds_matrix_circ = <F as Poseidon>::MDS_MATRIX_CIRC;
+        let mds_matrix_diag = <F as Poseidon>::MDS_MATRIX_DIAG;
+        let mds_matrix = (0..SPONGE_WIDTH)
+            .map(|r| {
+                (0..SPONGE_WIDTH)
+                    .map(|c| {
+                        let mut value = F::from_canonical_u64(
+                            mds_matrix_circ[(c + SPONGE_WIDTH - r) % SPONGE_WIDTH],
+                        );
+                        if r == c {
+                            value += F::from_canonical_u64(mds_matrix_diag[r]);
+                        }
+
+                        to_hex(value.to_canonical_u64())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            field_modulus: to_hex(F::ORDER),
+            width: SPONGE_WIDTH,
+            rate: SPONGE_RATE,
+            sbox_degree: SBOX_DEGREE,
+            half_n_full_rounds: HALF_N_FULL_ROUNDS,
+            n_partial_rounds: N_PARTIAL_ROUNDS,
+            round_constants,
+            mds_matrix,
+        }
+    }
+}
+
+/// `function` applied to `inputs` is `outputs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoseidonTestVector {
+    /// One of `"permutation"`, `"hash_no_pad"`, `"two_to_one"` and `"smt_leaf_hash"`.
+    pub function: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl PoseidonTestVector {
+    fn new(function: &str, inputs: &[F], outputs: &[F]) -> Self {
+        Self {
+            function: function.to_string(),
+            inputs: elements_to_hex(inputs),
+            outputs: elements_to_hex(outputs),
+        }
+    }
+}
+
+pub fn make_poseidon_test_vectors() -> Vec<PoseidonTestVector> {
+    let mut test_vectors = vec![];
+
+    let permutation_inputs = [
+        [F::ZERO; SPONGE_WIDTH],
+        core::array::from_fn(F::from_canonical_usize),
+        [F::NEG_ONE; SPONGE_WIDTH],
+    ];
+    for inputs in permutation_inputs {
+        test_vectors.push(PoseidonTestVector::new(
+            "permutation",
+            &inputs,
+            &F::poseidon(inputs),
+        ));
+    }
+
+    // The transaction hashes are hashes of field elements without padding.
+    for n in [0, 1, 4, 8, 9, 16] {
+        let inputs = (0..n).map(F::from_canonical_usize).collect::<Vec<_>>();
+        test_vectors.push(PoseidonTestVector::new(
+            "hash_no_pad",
+            &inputs,
+            &PoseidonHash::hash_no_pad(&inputs).elements,
+        ));
+    }
+
+    // The internal nodes of the (sparse) Merkle trees.
+    let left = HashOut::from_partial(&[F::from_canonical_u64(1), F::from_canonical_u64(2)]);
+    let right = HashOut {
+        elements: [F::NEG_ONE; 4],
+    };
+    test_vectors.push(PoseidonTestVector::new(
+        "two_to_one",
+        &[left.elements, right.elements].concat(),
+        &PoseidonHash::two_to_one(left, right).elements,
+    ));
+
+    // The leaves of the sparse Merkle trees, i.e. the hash of `key || value || 1` with padding.
+    test_vectors.push(PoseidonTestVector::new(
+        "smt_leaf_hash",
+        &[left.elements, right.elements].concat(),
+        &get_smt_leaf_hash(left, right).elements,
+    ));
+
+    test_vectors
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoseidonExport {
+    pub parameters: PoseidonParameters,
+    pub test_vectors: Vec<PoseidonTestVector>,
+}
+
+impl PoseidonExport {
+    pub fn new() -> Self {
+        Self {
+            parameters: PoseidonParameters::goldilocks(),
+            test_vectors: make_poseidon_test_vectors(),
+        }
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn write_json(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_json()?)?;
+
+        Ok(())
+    }
+}
+
+impl Default for PoseidonExport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_poseidon_export() {
+    let export = PoseidonExport::new();
+    assert_eq!(export.parameters.round_constants.len(), 30);
+    assert_eq!(export.parameters.field_modulus, "0xffffffff00000001");
+
+    // The exported MDS matrix and round constants reproduce the permutation.
+    fn parse(value: &str) -> F {
+        F::from_canonical_u64(u64::from_str_radix(&value[2..], 16).unwrap())
+    }
+    let round_constants = export
+        .parameters
+        .round_constants
+        .iter()
+        .map(|row| row.iter().map(|c| parse(c)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mds_matrix = export
+        .parameters
+        .mds_matrix
+        .iter()
+        .map(|row| row.iter().map(|c| parse(c)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let permute = |mut state: Vec<F>| {
+        for (r, constants) in round_constants.iter().enumerate() {
+            for (s, c) in state.iter_mut().zip(constants) {
+                *s += *c;
+            }
+            let is_full_round =
+                r < HALF_N_FULL_ROUNDS || r >= HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS;
+            for (i, s) in state.iter_mut().enumerate() {
+                if is_full_round || i == 0 {
+                    *s = s.exp_u64(SBOX_DEGREE as u64);
+                }
+            }
+            state = mds_matrix
+                .iter()
+                .map(|row| row.iter().zip(state.iter()).map(|(m, s)| *m * *s).sum())
+                .collect();
+        }
+
+        state
+    };
+    for test_vector in export.test_vectors.iter() {
+        if test_vector.function == "permutation" {
+            let inputs = test_vector.inputs.iter().map(|v| parse(v)).collect();
+            let outputs = test_vector
+                .outputs
+                .iter()
+                .map(|v| parse(v))
+                .collect::<Vec<_>>();
+            assert_eq!(permute(inputs), outputs);
+        }
+    }
+
+    let decoded: PoseidonExport = serde_json::from_str(&export.to_json().unwrap()).unwrap();
+    assert_eq!(decoded, export);
+}
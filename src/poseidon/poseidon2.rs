@@ -0,0 +1,275 @@
+//! Poseidon2 over Goldilocks with the width 12, an alternative hasher to the Poseidon of plonky2.
+//!
+//! The external rounds multiply the state by `circ(2 * M4, M4, M4)` and the internal rounds by
+//! `J + diag(INTERNAL_DIAG_M_1)`, where `J` is the matrix of ones, as in the reference implementation.
+//! Both are much cheaper than the dense MDS matrix of Poseidon, so the native hashing of the trees
+//! is about twice as fast. The round constants are generated by the Grain LFSR of the reference
+//! parameter script with `R_F = 8` and `R_P = 22`.
+//!
+//! `Poseidon2Hash` can be given as the generic hasher parameters, e.g. `H: AlgebraicHasher<F>`
+//! of the gadgets, `Poseidon2NodeHash` of the trees and `get_tx_hash_with`.
+//! NOTICE: there is no Poseidon2 gate yet, so the gadget is built from arithmetic gates
+//! and a circuit with it is larger than with the `PoseidonGate`.
+
+use std::sync::OnceLock;
+
+use plonky2::{
+    field::{
+        extension::Extendable,
+        goldilocks_field::GoldilocksField,
+        types::{Field, PrimeField64},
+    },
+    hash::{
+        hash_types::{HashOut, RichField},
+        hashing::{compress, hash_n_to_hash_no_pad, PlonkyPermutation, SPONGE_WIDTH},
+    },
+    iop::target::{BoolTarget, Target},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        config::{AlgebraicHasher, Hasher},
+    },
+};
+
+use super::gadgets::poseidon2::poseidon2_permute_swapped_target;
+
+type F = GoldilocksField;
+
+pub const POSEIDON2_HALF_N_FULL_ROUNDS: usize = 4;
+pub const POSEIDON2_N_PARTIAL_ROUNDS: usize = 22;
+
+/// The diagonal of the internal matrix minus the identity.
+pub const INTERNAL_DIAG_M_1: [u64; SPONGE_WIDTH] = [
+    0xc3b6c08e23ba9300,
+    0xd84b5de94a324fb6,
+    0x0d0c371c5b35b84f,
+    0x7964f570e7188037,
+    0x5daf18bbd996604b,
+    0x6743bc47b9595257,
+    0x5528b9362c59bb70,
+    0xac45e25b7127b68b,
+    0xa2077d7dfbb606b5,
+    0xf3faac6faee378ae,
+    0x0c6388b51545e883,
+    0xd27dbb6944917b60,
+];
+
+/// The self-shrinking Grain LFSR of the Poseidon parameter scripts.
+struct Grain {
+    state: Vec<bool>,
+}
+
+impl Grain {
+    fn new(width: usize, n_full_rounds: usize, n_partial_rounds: usize) -> Self {
+        let mut state = vec![];
+        // The prime field, the S-box `x^alpha`, the bits of the field order and the numbers of states and rounds.
+        for (value, n_bits) in [
+            (1, 2),
+            (0, 4),
+            (64, 12),
+            (width, 12),
+            (n_full_rounds, 10),
+            (n_partial_rounds, 10),
+        ] {
+            state.extend((0..n_bits).rev().map(|i| (value >> i) & 1 == 1));
+        }
+        state.extend([true; 30]);
+
+        let mut grain = Self { state };
+        for _ in 0..160 {
+            grain.clock();
+        }
+
+        grain
+    }
+
+    fn clock(&mut self) -> bool {
+        let s = &self.state;
+        let new_bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.remove(0);
+        self.state.push(new_bit);
+
+        new_bit
+    }
+
+    fn next_bit(&mut self) -> bool {
+        // Only the second bit of a pair whose first bit is one is output.
+        while !self.clock() {
+            self.clock();
+        }
+
+        self.clock()
+    }
+
+    /// Returns a uniform field element by rejection sampling of 64 bits, the most significant first.
+    fn next_field_element(&mut self) -> F {
+        loop {
+            let value = (0..64).fold(0u64, |acc, _| (acc << 1) | self.next_bit() as u64);
+            if value < F::ORDER {
+                return F::from_canonical_u64(value);
+            }
+        }
+    }
+}
+
+/// `round_constants()[r]` is added at the beginning of the round `r`.
+/// Only the first element is added in the partial rounds, and the others are zero.
+pub fn round_constants() -> &'static [[F; SPONGE_WIDTH]] {
+    static ROUND_CONSTANTS: OnceLock<Vec<[F; SPONGE_WIDTH]>> = OnceLock::new();
+
+    ROUND_CONSTANTS.get_or_init(|| {
+        let mut grain = Grain::new(
+            SPONGE_WIDTH,
+            2 * POSEIDON2_HALF_N_FULL_ROUNDS,
+            POSEIDON2_N_PARTIAL_ROUNDS,
+        );
+        let n_rounds = 2 * POSEIDON2_HALF_N_FULL_ROUNDS + POSEIDON2_N_PARTIAL_ROUNDS;
+
+        (0..n_rounds)
+            .map(|r| {
+                if is_full_round(r) {
+                    core::array::from_fn(|_| grain.next_field_element())
+                } else {
+                    let mut constants = [F::ZERO; SPONGE_WIDTH];
+                    constants[0] = grain.next_field_element();
+                    constants
+                }
+            })
+            .collect()
+    })
+}
+
+pub fn is_full_round(r: usize) -> bool {
+    r < POSEIDON2_HALF_N_FULL_ROUNDS
+        || r >= POSEIDON2_HALF_N_FULL_ROUNDS + POSEIDON2_N_PARTIAL_ROUNDS
+}
+
+fn sbox(x: F) -> F {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    let x4 = x2 * x2;
+
+    x4 * x3
+}
+
+/// `M4 = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]`
+fn matmul_m4(x: &mut [F]) {
+    let t0 = x[0] + x[1];
+    let t1 = x[2] + x[3];
+    let t2 = x[1].double() + t1;
+    let t3 = x[3].double() + t0;
+    let t4 = t1.double().double() + t3;
+    let t5 = t0.double().double() + t2;
+    let t6 = t3 + t5;
+    let t7 = t2 + t4;
+    x.copy_from_slice(&[t6, t5, t7, t4]);
+}
+
+fn matmul_external(state: &mut [F; SPONGE_WIDTH]) {
+    for chunk in state.chunks_mut(4) {
+        matmul_m4(chunk);
+    }
+    let sums: [F; 4] =
+        core::array::from_fn(|l| (l..SPONGE_WIDTH).step_by(4).map(|i| state[i]).sum());
+    for (i, s) in state.iter_mut().enumerate() {
+        *s += sums[i % 4];
+    }
+}
+
+fn matmul_internal(state: &mut [F; SPONGE_WIDTH]) {
+    let sum: F = state.iter().copied().sum();
+    for (s, d) in state.iter_mut().zip(INTERNAL_DIAG_M_1) {
+        *s = *s * F::from_canonical_u64(d) + sum;
+    }
+}
+
+pub fn poseidon2(input: [F; SPONGE_WIDTH]) -> [F; SPONGE_WIDTH] {
+    let mut state = input;
+    matmul_external(&mut state);
+    for (r, constants) in round_constants().iter().enumerate() {
+        if is_full_round(r) {
+            for (s, c) in state.iter_mut().zip(constants) {
+                *s = sbox(*s + *c);
+            }
+            matmul_external(&mut state);
+        } else {
+            state[0] = sbox(state[0] + constants[0]);
+            matmul_internal(&mut state);
+        }
+    }
+
+    state
+}
+
+pub struct Poseidon2Permutation;
+
+impl PlonkyPermutation<F> for Poseidon2Permutation {
+    fn permute(input: [F; SPONGE_WIDTH]) -> [F; SPONGE_WIDTH] {
+        poseidon2(input)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Poseidon2Hash;
+
+impl Hasher<F> for Poseidon2Hash {
+    const HASH_SIZE: usize = 4 * 8;
+    type Hash = HashOut<F>;
+    type Permutation = Poseidon2Permutation;
+
+    fn hash_no_pad(input: &[F]) -> Self::Hash {
+        hash_n_to_hash_no_pad::<F, Self::Permutation>(input)
+    }
+
+    fn two_to_one(left: Self::Hash, right: Self::Hash) -> Self::Hash {
+        compress::<F, Self::Permutation>(left, right)
+    }
+}
+
+impl AlgebraicHasher<F> for Poseidon2Hash {
+    fn permute_swapped<const D: usize>(
+        inputs: [Target; SPONGE_WIDTH],
+        swap: BoolTarget,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> [Target; SPONGE_WIDTH]
+    where
+        F: RichField + Extendable<D>,
+    {
+        poseidon2_permute_swapped_target(builder, inputs, swap)
+    }
+}
+
+#[test]
+fn test_poseidon2() {
+    use plonky2::hash::poseidon::PoseidonHash;
+
+    let constants = round_constants();
+    assert_eq!(constants.len(), 30);
+    assert_eq!(constants[POSEIDON2_HALF_N_FULL_ROUNDS][1], F::ZERO);
+    assert!(constants
+        .iter()
+        .flatten()
+        .all(|c| c.to_canonical_u64() < F::ORDER));
+
+    // The internal matrix `J + diag(d)` is invertible since `det = prod(d) * (1 + sum(1 / d))`.
+    let diag = INTERNAL_DIAG_M_1.map(F::from_canonical_u64);
+    assert!(diag.iter().all(|d| *d != F::ZERO));
+    assert_ne!(
+        F::ONE + diag.iter().map(|d| d.inverse()).sum::<F>(),
+        F::ZERO
+    );
+
+    let inputs: [F; SPONGE_WIDTH] = core::array::from_fn(F::from_canonical_usize);
+    assert_eq!(poseidon2(inputs), poseidon2(inputs));
+    assert_ne!(poseidon2(inputs), poseidon2([F::ZERO; SPONGE_WIDTH]));
+
+    let left = HashOut::from_partial(&[F::ONE]);
+    let right = HashOut::from_partial(&[F::TWO]);
+    assert_ne!(
+        Poseidon2Hash::two_to_one(left, right),
+        PoseidonHash::two_to_one(left, right)
+    );
+    assert_ne!(
+        Poseidon2Hash::two_to_one(left, right),
+        Poseidon2Hash::two_to_one(right, left)
+    );
+}
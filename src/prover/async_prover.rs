@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use plonky2::iop::witness::PartialWitness;
+use tokio::runtime::{Builder, Runtime};
+use tokio_util::sync::CancellationToken;
+
+use super::Provable;
+
+/// The phases reported to the progress callback.
+///
+/// plonky2 generates the rest of the witness and runs the FRI prover inside one call,
+/// so `Proving` covers both of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProvingPhase {
+    /// The task is waiting for a free thread of the prover.
+    Queued,
+    Proving,
+    Finished,
+    Cancelled,
+}
+
+/// Runs proving tasks on a dedicated thread pool, so that the async runtime of the caller
+/// is never blocked by the prover.
+pub struct AsyncProver {
+    runtime: Option<Runtime>,
+}
+
+impl AsyncProver {
+    /// `num_threads` is the maximum number of proofs generated concurrently.
+    pub fn new(num_threads: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(num_threads != 0, "num_threads must be at least 1");
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .max_blocking_threads(num_threads)
+            .thread_name("intmax-prover")
+            .build()
+            .map_err(|err| anyhow::anyhow!("fail to build prover runtime: {}", err))?;
+
+        Ok(Self {
+            runtime: Some(runtime),
+        })
+    }
+
+    /// Proves `inputs` with `circuit`.
+    ///
+    /// If `cancel` is cancelled before the proof is generated, an error is returned immediately.
+    /// A task which has already entered the prover cannot be interrupted;
+    /// it keeps proving on its thread until plonky2 returns and the result is discarded,
+    /// so it still counts against `num_threads` in the meantime.
+    pub async fn prove<P>(
+        &self,
+        circuit: Arc<P>,
+        inputs: PartialWitness<P::F>,
+        cancel: CancellationToken,
+        on_progress: impl Fn(ProvingPhase) + Send + Sync + 'static,
+    ) -> anyhow::Result<P::Proof>
+    where
+        P: Provable + Send + Sync + 'static,
+        P::Proof: Send + 'static,
+    {
+        let on_progress = Arc::new(on_progress);
+        on_progress(ProvingPhase::Queued);
+
+        let task_cancel = cancel.clone();
+        let task_on_progress = on_progress.clone();
+        let handle = self.runtime().spawn_blocking(move || {
+            // `Provable::prove` has no stage between which the token could be checked again.
+            if task_cancel.is_cancelled() {
+                return Err(anyhow::anyhow!("proving was cancelled"));
+            }
+
+            task_on_progress(ProvingPhase::Proving);
+
+            circuit.prove(inputs)
+        });
+
+        tokio::select! {
+            _ = cancel.cancelled() => {
+                on_progress(ProvingPhase::Cancelled);
+
+                Err(anyhow::anyhow!("proving was cancelled"))
+            }
+            result = handle => {
+                let proof = result
+                    .map_err(|err| anyhow::anyhow!("fail to join proving task: {}", err))??;
+                on_progress(ProvingPhase::Finished);
+
+                Ok(proof)
+            }
+        }
+    }
+
+    fn runtime(&self) -> &Runtime {
+        self.runtime.as_ref().expect("prover runtime was shut down")
+    }
+}
+
+impl Drop for AsyncProver {
+    fn drop(&mut self) {
+        // `Runtime` cannot be dropped inside another runtime, so do not wait for running tasks.
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_async_prover() {
+    use std::{
+        sync::{
+            mpsc::{self, RecvTimeoutError, TryRecvError},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use tokio::sync::Notify;
+
+    /// Waits until released, and tells that it returned.
+    struct BlockingCircuit {
+        release: Mutex<mpsc::Receiver<()>>,
+        done: Mutex<mpsc::Sender<()>>,
+    }
+
+    impl Provable for BlockingCircuit {
+        type F = GoldilocksField;
+        type Proof = ();
+
+        fn prove(&self, _inputs: PartialWitness<Self::F>) -> anyhow::Result<()> {
+            self.release.lock().unwrap().recv()?;
+            self.done.lock().unwrap().send(())?;
+
+            Ok(())
+        }
+    }
+
+    let make_circuit = || {
+        let (release_tx, release_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let circuit = BlockingCircuit {
+            release: Mutex::new(release_rx),
+            done: Mutex::new(done_tx),
+        };
+
+        (Arc::new(circuit), release_tx, done_rx)
+    };
+    let record_phases = || {
+        let phases = Arc::new(Mutex::new(vec![]));
+        let on_progress = {
+            let phases = phases.clone();
+            move |phase: ProvingPhase| phases.lock().unwrap().push(phase)
+        };
+
+        (phases, on_progress)
+    };
+
+    let prover = AsyncProver::new(1).unwrap();
+
+    let (circuit, release, done) = make_circuit();
+    release.send(()).unwrap();
+    let (phases, on_progress) = record_phases();
+    prover
+        .prove(
+            circuit,
+            PartialWitness::new(),
+            CancellationToken::new(),
+            on_progress,
+        )
+        .await
+        .unwrap();
+    done.try_recv().unwrap();
+    assert_eq!(
+        *phases.lock().unwrap(),
+        [
+            ProvingPhase::Queued,
+            ProvingPhase::Proving,
+            ProvingPhase::Finished
+        ]
+    );
+
+    // A task cancelled before it is started never enters the prover.
+    let (circuit, _release, done) = make_circuit();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let (phases, on_progress) = record_phases();
+    assert!(prover
+        .prove(circuit, PartialWitness::new(), cancel, on_progress)
+        .await
+        .is_err());
+    assert_eq!(
+        done.recv_timeout(Duration::from_secs(10)),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    assert!(!phases.lock().unwrap().contains(&ProvingPhase::Proving));
+    assert!(!phases.lock().unwrap().contains(&ProvingPhase::Finished));
+
+    // A task cancelled while proving returns at once, but the prover keeps running until it returns.
+    let (circuit, release, done) = make_circuit();
+    let cancel = CancellationToken::new();
+    let proving = Arc::new(Notify::new());
+    let phases = Arc::new(Mutex::new(vec![]));
+    let on_progress = {
+        let phases = phases.clone();
+        let proving = proving.clone();
+        move |phase: ProvingPhase| {
+            phases.lock().unwrap().push(phase);
+            if phase == ProvingPhase::Proving {
+                proving.notify_one();
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(
+        prover.prove(circuit, PartialWitness::new(), cancel.clone(), on_progress),
+        async {
+            proving.notified().await;
+            cancel.cancel();
+        }
+    );
+    assert!(result.is_err());
+    assert_eq!(
+        *phases.lock().unwrap(),
+        [
+            ProvingPhase::Queued,
+            ProvingPhase::Proving,
+            ProvingPhase::Cancelled
+        ]
+    );
+    assert_eq!(done.try_recv(), Err(TryRecvError::Empty));
+    release.send(()).unwrap();
+    done.recv_timeout(Duration::from_secs(10)).unwrap();
+}
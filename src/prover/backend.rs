@@ -0,0 +1,542 @@
+use std::sync::Arc;
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::witness::PartialWitness,
+    plonk::{
+        config::{AlgebraicHasher, GenericConfig, Hasher},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ensure_witness, IntmaxError},
+    rollup::{
+        circuits::{
+            validate_block_header_witness, validate_block_htlc_spend,
+            ProposalAndApprovalBlockCircuit, N_FORCED_TXS,
+        },
+        contract::ContractCall,
+        gadgets::{
+            approval_block::validate_approval_witness,
+            forced_inclusion::{validate_forced_transactions, ForcedTransactionWitness},
+            proposal_block::validate_proposal_witness,
+            withdrawal::{validate_burned_assets, WithdrawalInfo},
+        },
+        withdrawal::get_block_withdrawals,
+    },
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::{LayeredLayeredSmtProcessProof, SmtProcessProof},
+        goldilocks_poseidon::{hash_out_hex, hash_out_hex_seq, WrappedHashOut},
+    },
+    transaction::{
+        asset::Asset,
+        burn::{get_burned_assets, get_burned_assets_digest},
+        circuits::{
+            dynamic::DynMergeAndPurgeTransitionCircuit, MergeAndPurgeTransitionCircuit,
+            MergeAndPurgeTransitionProofWithPublicInputs, MergeAndPurgeTransitionPublicInputs,
+        },
+        gadgets::{
+            merge::{validate_merge_witness, MergeProof},
+            purge::validate_purge_witness,
+        },
+        pow::grind_nonce,
+        tx_hash::get_tx_hash,
+    },
+    zkdsa::{
+        account::{Address, SecretKey},
+        circuits::{SimpleSignatureCircuit, SimpleSignatureProofWithPublicInputs},
+    },
+};
+
+use super::Provable;
+
+/// Everything needed to fill the partial witness of `Circuit`.
+/// Unlike `PartialWitness`, it can be serialized and sent to another machine.
+pub trait CircuitWitness<Circuit: Provable> {
+    /// The name of the circuit, used as the endpoint of remote provers.
+    const NAME: &'static str;
+
+    fn set_witness(
+        &self,
+        circuit: &Circuit,
+        pw: &mut PartialWitness<Circuit::F>,
+    ) -> Result<(), IntmaxError>;
+}
+
+/// Generates proofs of `Circuit` from witnesses constructed by the caller.
+pub trait ProverBackend<Circuit: Provable, W: CircuitWitness<Circuit>> {
+    fn prove(&self, witness: &W) -> anyhow::Result<Circuit::Proof>;
+}
+
+/// Proves on this machine with a prebuilt circuit.
+pub struct LocalProverBackend<Circuit> {
+    pub circuit: Arc<Circuit>,
+}
+
+impl<Circuit> LocalProverBackend<Circuit> {
+    pub fn new(circuit: Arc<Circuit>) -> Self {
+        Self { circuit }
+    }
+}
+
+impl<Circuit: Provable, W: CircuitWitness<Circuit>> ProverBackend<Circuit, W>
+    for LocalProverBackend<Circuit>
+{
+    fn prove(&self, witness: &W) -> anyhow::Result<Circuit::Proof> {
+        let mut pw = PartialWitness::new();
+        witness.set_witness(&self.circuit, &mut pw)?;
+
+        self.circuit.prove(pw)
+    }
+}
+
+/// The arguments of `MergeAndPurgeTransitionTarget::set_witness`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "MergeProof<F>: Serialize",
+    deserialize = "MergeProof<F>: Deserialize<'de>"
+))]
+pub struct UserTransactionWitness<F: RichField> {
+    pub sender_address: Address<F>,
+    pub merge_witnesses: Vec<MergeProof<F>>,
+    pub purge_input_witnesses: Vec<LayeredLayeredSmtProcessProof<F>>,
+    pub purge_output_witnesses: Vec<LayeredLayeredSmtProcessProof<F>>,
+    pub nonce: WrappedHashOut<F>,
+    pub old_user_asset_root: WrappedHashOut<F>,
+}
+
+impl<F: RichField> UserTransactionWitness<F> {
+    /// Checks the witness without proving, so that an invalid transaction is rejected
+    /// before spending the proving time.
+    /// Returns the new user asset root.
+    pub fn validate(&self) -> Result<WrappedHashOut<F>, IntmaxError> {
+        let middle_user_asset_root =
+            validate_merge_witness(&self.merge_witnesses, self.old_user_asset_root)?;
+        validate_purge_witness(
+            &self.purge_input_witnesses,
+            &self.purge_output_witnesses,
+            middle_user_asset_root,
+        )?;
+
+        Ok(self
+            .purge_input_witnesses
+            .last()
+            .map(|w| w.0.new_root)
+            .unwrap_or(middle_user_asset_root))
+    }
+
+    /// The public inputs of the user transaction proof of this witness, computed without proving.
+    pub fn public_inputs(&self) -> Result<MergeAndPurgeTransitionPublicInputs<F>, IntmaxError> {
+        let new_user_asset_root = self.validate()?;
+        let middle_user_asset_root =
+            validate_merge_witness(&self.merge_witnesses, self.old_user_asset_root)?;
+        let diff_root = self
+            .purge_output_witnesses
+            .last()
+            .map(|w| w.0.new_root)
+            .unwrap_or_default();
+        let tx_hash = get_tx_hash(*diff_root, *self.nonce).into();
+
+        Ok(MergeAndPurgeTransitionPublicInputs {
+            sender_address: self.sender_address,
+            old_user_asset_root: self.old_user_asset_root,
+            middle_user_asset_root,
+            new_user_asset_root,
+            diff_root,
+            tx_hash,
+            burned_assets_digest: get_burned_assets_digest(&get_burned_assets(
+                &self.purge_output_witnesses,
+            ))
+            .into(),
+        })
+    }
+
+    /// Splits the merges into transactions of at most `n_merges` merges each, so that a user with
+    /// more pending receipts than the merge slots of the circuit can consolidate them.
+    /// The leading transactions only merge and the last one also has the purges of `self`.
+    /// The nonce of the `i`-th merge-only transaction is derived from `self.nonce` and `i`.
+    /// Each transaction starts from the root left by the previous one,
+    /// so they must be included in successive blocks.
+    pub fn split_merges(&self, n_merges: usize) -> Result<Vec<Self>, IntmaxError> {
+        ensure_witness!(n_merges != 0, "the number of merge slots must not be zero");
+
+        let mut chunks = self.merge_witnesses.chunks(n_merges).collect::<Vec<_>>();
+        let last_chunk = chunks.pop().unwrap_or_default();
+        let mut transactions = vec![];
+        let mut old_user_asset_root = self.old_user_asset_root;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let nonce = PoseidonHash::two_to_one(
+                *self.nonce,
+                HashOut::from_partial(&[F::from_canonical_usize(i)]),
+            );
+            let transaction = Self {
+                sender_address: self.sender_address,
+                merge_witnesses: chunk.to_vec(),
+                purge_input_witnesses: vec![],
+                purge_output_witnesses: vec![],
+                nonce: nonce.into(),
+                old_user_asset_root,
+            };
+            old_user_asset_root = transaction.validate()?;
+            transactions.push(transaction);
+        }
+
+        transactions.push(Self {
+            merge_witnesses: last_chunk.to_vec(),
+            old_user_asset_root,
+            ..self.clone()
+        });
+
+        Ok(transactions)
+    }
+
+    /// Replaces the nonce with one derived from it whose `tx_hash` meets `difficulty`,
+    /// the proof of work the aggregator may require on admission.
+    /// Grind after `split_merges`, since the nonces of the merge-only transactions are derived from it.
+    pub fn grind_nonce(&mut self, difficulty: u32) -> Result<(), IntmaxError> {
+        let diff_root = self.public_inputs()?.diff_root;
+        let (nonce, _) = grind_nonce(*diff_root, *self.nonce, difficulty)?;
+        self.nonce = nonce.into();
+
+        Ok(())
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_DIFFS: usize,
+        const N_MERGES: usize,
+    >
+    CircuitWitness<
+        MergeAndPurgeTransitionCircuit<
+            F,
+            C,
+            D,
+            N_LOG_MAX_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_MAX_CONTRACTS,
+            N_LOG_MAX_VARIABLES,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_LOG_CONTRACTS,
+            N_LOG_VARIABLES,
+            N_DIFFS,
+            N_MERGES,
+        >,
+    > for UserTransactionWitness<F>
+{
+    const NAME: &'static str = "user_transaction";
+
+    fn set_witness(
+        &self,
+        circuit: &MergeAndPurgeTransitionCircuit<
+            F,
+            C,
+            D,
+            N_LOG_MAX_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_MAX_CONTRACTS,
+            N_LOG_MAX_VARIABLES,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_LOG_CONTRACTS,
+            N_LOG_VARIABLES,
+            N_DIFFS,
+            N_MERGES,
+        >,
+        pw: &mut PartialWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        circuit.targets.set_witness(
+            pw,
+            self.sender_address,
+            &self.merge_witnesses,
+            &self.purge_input_witnesses,
+            &self.purge_output_witnesses,
+            self.nonce,
+            self.old_user_asset_root,
+        )?;
+
+        Ok(())
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    CircuitWitness<DynMergeAndPurgeTransitionCircuit<F, C, D>> for UserTransactionWitness<F>
+{
+    const NAME: &'static str = "user_transaction";
+
+    fn set_witness(
+        &self,
+        circuit: &DynMergeAndPurgeTransitionCircuit<F, C, D>,
+        pw: &mut PartialWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        circuit.targets.set_witness(
+            pw,
+            self.sender_address,
+            &self.merge_witnesses,
+            &self.purge_input_witnesses,
+            &self.purge_output_witnesses,
+            self.nonce,
+            self.old_user_asset_root,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// The arguments of `SimpleSignatureTarget::set_witness`.
+/// NOTICE: It contains the private key, so send it only to a trusted prover.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SimpleSignatureWitness<F: RichField> {
+    pub private_key: WrappedHashOut<F>,
+    pub message: WrappedHashOut<F>,
+}
+
+impl<F: RichField> SimpleSignatureWitness<F> {
+    pub fn new(private_key: SecretKey<F>, message: HashOut<F>) -> Self {
+        Self {
+            private_key: private_key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    CircuitWitness<SimpleSignatureCircuit<F, C, D>> for SimpleSignatureWitness<F>
+{
+    const NAME: &'static str = "simple_signature";
+
+    fn set_witness(
+        &self,
+        circuit: &SimpleSignatureCircuit<F, C, D>,
+        pw: &mut PartialWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        circuit
+            .targets
+            .set_witness(pw, *self.private_key, *self.message);
+
+        Ok(())
+    }
+}
+
+/// The arguments of `OneBlockProofTarget::set_witness`.
+/// The HTLC spend slot is always disabled.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct BlockWitness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub block_number: u32,
+    pub user_tx_proofs: Vec<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>,
+    pub deposit_process_proofs: Vec<LayeredLayeredSmtProcessProof<F>>,
+    /// The nonce of the deposit transaction, i.e. `DepositTree::nonce`.
+    #[serde(with = "hash_out_hex")]
+    pub deposit_nonce: HashOut<F>,
+    pub world_state_process_proofs: Vec<SmtProcessProof<F>>,
+    pub world_state_revert_proofs: Vec<SmtProcessProof<F>>,
+    pub received_signatures: Vec<Option<SimpleSignatureProofWithPublicInputs<F, C, D>>>,
+    pub default_simple_signature: SimpleSignatureProofWithPublicInputs<F, C, D>,
+    pub latest_account_tree_process_proofs: Vec<SmtProcessProof<F>>,
+    #[serde(with = "hash_out_hex_seq")]
+    pub block_header_siblings: Vec<HashOut<F>>,
+    #[serde(with = "hash_out_hex")]
+    pub prev_block_hash: HashOut<F>,
+    /// The unix time in seconds of the block header.
+    pub timestamp: u64,
+    /// The address of the aggregator committed in the block header.
+    #[serde(with = "hash_out_hex")]
+    pub aggregator_address: HashOut<F>,
+    /// The issuer registry root committed in the block header.
+    #[serde(with = "hash_out_hex")]
+    pub issuer_registry_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub old_world_state_root: HashOut<F>,
+    /// The digest of the forced transaction queue processed by the previous blocks.
+    #[serde(with = "hash_out_hex")]
+    pub old_forced_transactions_digest: HashOut<F>,
+    pub forced_transactions: Vec<ForcedTransactionWitness<F>>,
+    /// `burned_assets[i]` are the assets burned by `user_tx_proofs[i]`,
+    /// i.e. `get_burned_assets` of its purge output witness, which are withdrawn if it is approved.
+    pub burned_assets: Vec<Vec<Asset<F>>>,
+    /// `mint_world_state_process_proofs[i]` puts the user asset root of the issuer after `mint_proofs[i]`
+    /// into the world state, after the transactions are approved.
+    pub mint_world_state_process_proofs: Vec<SmtProcessProof<F>>,
+    pub mint_proofs: Vec<ProofWithPublicInputs<F, C, D>>,
+    /// The mint proof set in the disabled mint slots, e.g. `MintCircuit::prove_default`.
+    pub default_mint_proof: ProofWithPublicInputs<F, C, D>,
+    /// The contract calls applied after the mints, paired with the process proofs of `ContractCall::apply`.
+    pub contract_calls: Vec<(ContractCall<F, C, D>, SmtProcessProof<F>)>,
+    /// The contract call set in the disabled contract call slots, e.g. `CounterContractCircuit::call_default`.
+    pub default_contract_call: ContractCall<F, C, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    BlockWitness<F, C, D>
+{
+    /// Checks the same conditions as `OneBlockProofTarget::set_witness` without proving.
+    /// The inner proofs themselves are verified by the block circuit.
+    pub fn validate(&self) -> Result<(), IntmaxError> {
+        validate_block_header_witness(
+            self.block_number,
+            &self.block_header_siblings,
+            self.timestamp,
+        )?;
+        let user_transactions = self
+            .user_tx_proofs
+            .iter()
+            .map(|p| p.public_inputs.clone())
+            .collect::<Vec<_>>();
+        validate_proposal_witness(
+            &self.world_state_process_proofs,
+            &user_transactions,
+            self.old_world_state_root,
+        )?;
+        validate_approval_witness(
+            self.block_number,
+            &self.world_state_revert_proofs,
+            &user_transactions,
+            &self
+                .received_signatures
+                .iter()
+                .map(|r| r.as_ref().map(|r| r.public_inputs.public_key))
+                .collect::<Vec<_>>(),
+            &self.latest_account_tree_process_proofs,
+        )?;
+        validate_forced_transactions(
+            self.old_world_state_root,
+            &user_transactions,
+            &self.forced_transactions,
+            N_FORCED_TXS,
+        )?;
+        validate_block_htlc_spend(self.block_number, &self.world_state_revert_proofs, None)?;
+        validate_burned_assets(
+            &user_transactions
+                .iter()
+                .map(|u| *u.burned_assets_digest)
+                .collect::<Vec<_>>(),
+            &self.burned_assets,
+        )?;
+
+        Ok(())
+    }
+
+    /// The withdrawals of the block, i.e. the burned assets of the approved transactions,
+    /// in the layout of the block circuit with `n_txs` and `n_diffs`.
+    pub fn withdrawals(
+        &self,
+        n_txs: usize,
+        n_diffs: usize,
+    ) -> anyhow::Result<Vec<Option<WithdrawalInfo<F>>>> {
+        let transactions = self
+            .user_tx_proofs
+            .iter()
+            .enumerate()
+            .map(|(i, user_tx_proof)| {
+                let is_approved = matches!(self.received_signatures.get(i), Some(Some(_)));
+                let burned_assets = match self.burned_assets.get(i) {
+                    Some(burned_assets) if is_approved => &burned_assets[..],
+                    _ => &[][..],
+                };
+
+                (user_tx_proof.public_inputs.sender_address, burned_assets)
+            })
+            .collect::<Vec<_>>();
+
+        get_block_withdrawals(&transactions, n_txs, n_diffs)
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_TXS: usize,
+        const N_DEPOSITS: usize,
+    >
+    CircuitWitness<
+        ProposalAndApprovalBlockCircuit<
+            F,
+            C,
+            D,
+            N_LOG_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_LOG_CONTRACTS,
+            N_LOG_VARIABLES,
+            N_TXS,
+            N_DEPOSITS,
+        >,
+    > for BlockWitness<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    const NAME: &'static str = "block";
+
+    fn set_witness(
+        &self,
+        circuit: &ProposalAndApprovalBlockCircuit<
+            F,
+            C,
+            D,
+            N_LOG_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_LOG_CONTRACTS,
+            N_LOG_VARIABLES,
+            N_TXS,
+            N_DEPOSITS,
+        >,
+        pw: &mut PartialWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        circuit.targets.set_witness(
+            pw,
+            self.block_number,
+            &self.user_tx_proofs,
+            &self.deposit_process_proofs,
+            self.deposit_nonce,
+            &self.world_state_process_proofs,
+            &self.world_state_revert_proofs,
+            &self.received_signatures,
+            &self.default_simple_signature,
+            &self.latest_account_tree_process_proofs,
+            &self.block_header_siblings,
+            self.prev_block_hash,
+            self.timestamp,
+            self.aggregator_address,
+            self.issuer_registry_root,
+            self.old_world_state_root,
+            self.old_forced_transactions_digest,
+            &self.forced_transactions,
+            &self.burned_assets,
+            &self.mint_world_state_process_proofs,
+            &self.mint_proofs,
+            &self.default_mint_proof,
+            &self.contract_calls,
+            &self.default_contract_call,
+            None,
+        )
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(feature = "async")]
+pub mod async_prover;
+pub mod backend;
+#[cfg(feature = "remote-prover")]
+pub mod remote;
+
+use plonky2::{
+    field::extension::Extendable, hash::hash_types::RichField, iop::witness::PartialWitness,
+    plonk::config::GenericConfig,
+};
+
+use crate::{
+    rollup::circuits::{
+        ProposalAndApprovalBlockCircuit, ProposalAndApprovalBlockProofWithPublicInputs,
+    },
+    transaction::circuits::{
+        dynamic::DynMergeAndPurgeTransitionCircuit, MergeAndPurgeTransitionCircuit,
+        MergeAndPurgeTransitionProofWithPublicInputs,
+    },
+    zkdsa::circuits::{SimpleSignatureCircuit, SimpleSignatureProofWithPublicInputs},
+};
+
+/// A built circuit which can generate a proof from a partial witness.
+pub trait Provable {
+    type F: RichField;
+    type Proof;
+
+    fn prove(&self, inputs: PartialWitness<Self::F>) -> anyhow::Result<Self::Proof>;
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_DIFFS: usize,
+        const N_MERGES: usize,
+    > Provable
+    for MergeAndPurgeTransitionCircuit<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >
+{
+    type F = F;
+    type Proof = MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>;
+
+    fn prove(&self, inputs: PartialWitness<F>) -> anyhow::Result<Self::Proof> {
+        MergeAndPurgeTransitionCircuit::prove(self, inputs)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> Provable
+    for DynMergeAndPurgeTransitionCircuit<F, C, D>
+{
+    type F = F;
+    type Proof = MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>;
+
+    fn prove(&self, inputs: PartialWitness<F>) -> anyhow::Result<Self::Proof> {
+        DynMergeAndPurgeTransitionCircuit::prove(self, inputs)
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> Provable
+    for SimpleSignatureCircuit<F, C, D>
+{
+    type F = F;
+    type Proof = SimpleSignatureProofWithPublicInputs<F, C, D>;
+
+    fn prove(&self, inputs: PartialWitness<F>) -> anyhow::Result<Self::Proof> {
+        SimpleSignatureCircuit::prove(self, inputs)
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_TXS: usize,
+        const N_DEPOSITS: usize,
+    > Provable
+    for ProposalAndApprovalBlockCircuit<
+        F,
+        C,
+        D,
+        N_LOG_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_TXS,
+        N_DEPOSITS,
+    >
+{
+    type F = F;
+    type Proof = ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>;
+
+    fn prove(&self, inputs: PartialWitness<F>) -> anyhow::Result<Self::Proof> {
+        ProposalAndApprovalBlockCircuit::prove(self, inputs)
+    }
+}
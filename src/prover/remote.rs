@@ -0,0 +1,117 @@
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    backend::{CircuitWitness, ProverBackend},
+    Provable,
+};
+
+/// Sends witnesses to a remote prover over HTTP and receives the proofs.
+///
+/// The witness is posted as JSON to `{endpoint}/{CircuitWitness::NAME}`,
+/// and the server is expected to answer with the JSON-encoded proof.
+/// See `handle_remote_proving_request` for the server side.
+pub struct HttpProverBackend<Circuit> {
+    pub endpoint: String,
+    client: reqwest::blocking::Client,
+    _circuit: PhantomData<fn() -> Circuit>,
+}
+
+impl<Circuit> HttpProverBackend<Circuit> {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            client: reqwest::blocking::Client::new(),
+            _circuit: PhantomData,
+        }
+    }
+}
+
+impl<Circuit, W> ProverBackend<Circuit, W> for HttpProverBackend<Circuit>
+where
+    Circuit: Provable,
+    Circuit::Proof: DeserializeOwned,
+    W: CircuitWitness<Circuit> + Serialize,
+{
+    fn prove(&self, witness: &W) -> anyhow::Result<Circuit::Proof> {
+        let url = format!("{}/{}", self.endpoint.trim_end_matches('/'), W::NAME);
+        let response = self
+            .client
+            .post(&url)
+            .json(witness)
+            .send()
+            .map_err(|err| anyhow::anyhow!("fail to send witness to {}: {}", url, err))?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().unwrap_or_default();
+            anyhow::bail!("remote prover returned {}: {}", status, message);
+        }
+
+        response
+            .json()
+            .map_err(|err| anyhow::anyhow!("fail to decode proof from {}: {}", url, err))
+    }
+}
+
+/// Proves a JSON-encoded witness received by a prover server with `backend`
+/// (usually `LocalProverBackend`) and returns the JSON-encoded proof.
+pub fn handle_remote_proving_request<Circuit, W>(
+    backend: &impl ProverBackend<Circuit, W>,
+    body: &[u8],
+) -> anyhow::Result<Vec<u8>>
+where
+    Circuit: Provable,
+    Circuit::Proof: Serialize,
+    W: CircuitWitness<Circuit> + DeserializeOwned,
+{
+    let witness: W = serde_json::from_slice(body)
+        .map_err(|err| anyhow::anyhow!("fail to decode {} witness: {}", W::NAME, err))?;
+    let proof = backend.prove(&witness)?;
+
+    Ok(serde_json::to_vec(&proof)?)
+}
+
+#[test]
+fn test_handle_remote_proving_request() {
+    use std::sync::Arc;
+
+    use plonky2::{field::types::Sample, hash::hash_types::HashOut};
+
+    use crate::{
+        fixtures::{C, D, F},
+        prover::backend::{LocalProverBackend, SimpleSignatureWitness},
+        zkdsa::{
+            account::private_key_to_account,
+            circuits::{
+                make_simple_signature_circuit, SimpleSignatureCircuit,
+                SimpleSignatureProofWithPublicInputs,
+            },
+        },
+    };
+
+    let circuit = Arc::new(make_simple_signature_circuit());
+    let backend = LocalProverBackend::new(circuit.clone());
+    let account = private_key_to_account(HashOut::rand());
+    let message = HashOut::rand();
+    let witness = SimpleSignatureWitness::<F>::new(account.private_key, message);
+
+    // The JSON body which `HttpProverBackend` posts.
+    let body = serde_json::to_vec(&witness).unwrap();
+    let response = handle_remote_proving_request::<
+        SimpleSignatureCircuit<F, C, D>,
+        SimpleSignatureWitness<F>,
+    >(&backend, &body)
+    .unwrap();
+    let proof: SimpleSignatureProofWithPublicInputs<F, C, D> =
+        serde_json::from_slice(&response).unwrap();
+    assert_eq!(proof.public_inputs.message, message);
+    assert_eq!(proof.public_inputs.public_key, account.public_key);
+    circuit.verify(proof).unwrap();
+
+    assert!(handle_remote_proving_request::<
+        SimpleSignatureCircuit<F, C, D>,
+        SimpleSignatureWitness<F>,
+    >(&backend, b"{}")
+    .is_err());
+}
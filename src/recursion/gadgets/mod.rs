@@ -2,12 +2,14 @@ use plonky2::{
     field::extension::Extendable,
     fri::proof::FriProofTarget,
     gadgets::polynomial::PolynomialCoeffsExtTarget,
-    hash::hash_types::{MerkleCapTarget, RichField},
+    hash::hash_types::{HashOut, HashOutTarget, MerkleCapTarget, RichField},
     iop::{target::BoolTarget, witness::Witness},
     plonk::{
         circuit_builder::CircuitBuilder,
-        circuit_data::{CircuitData, VerifierCircuitTarget},
-        config::{AlgebraicHasher, GenericConfig},
+        circuit_data::{
+            CircuitData, CommonCircuitData, VerifierCircuitTarget, VerifierOnlyCircuitData,
+        },
+        config::{AlgebraicHasher, GenericConfig, Hasher},
         proof::{ProofTarget, ProofWithPublicInputs, ProofWithPublicInputsTarget},
     },
 };
@@ -136,6 +138,61 @@ impl<const D: usize> RecursiveProofTarget<D> {
     }
 }
 
+/// The verifier data of a circuit with `common_data` as a witness,
+/// so that the circuit can verify proofs of any circuit of the same shape.
+pub fn add_virtual_verifier_data<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    common_data: &CommonCircuitData<F, D>,
+) -> VerifierCircuitTarget {
+    VerifierCircuitTarget {
+        constants_sigmas_cap: MerkleCapTarget(
+            builder.add_virtual_hashes(1 << common_data.config.fri_config.cap_height),
+        ),
+        circuit_digest: builder.add_virtual_hash(),
+    }
+}
+
+/// The digest which identifies the circuit of `verifier_only`.
+pub fn get_verifier_digest<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+) -> HashOut<F>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut inputs = vec![];
+    for cap in verifier_only.constants_sigmas_cap.0.iter() {
+        inputs.extend(cap.elements);
+    }
+    inputs.extend(verifier_only.circuit_digest.elements);
+
+    C::Hasher::hash_no_pad(&inputs)
+}
+
+/// The in-circuit version of `get_verifier_digest`.
+pub fn get_verifier_digest_target<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    verifier_data: &VerifierCircuitTarget,
+) -> HashOutTarget
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut inputs = vec![];
+    for cap in verifier_data.constants_sigmas_cap.0.iter() {
+        inputs.extend(cap.elements);
+    }
+    inputs.extend(verifier_data.circuit_digest.elements);
+
+    builder.hash_n_to_hash_no_pad::<C::Hasher>(inputs)
+}
+
 #[test]
 fn test_recursion_simple_signature() {
     use std::time::Instant;
@@ -255,3 +312,44 @@ fn test_recursion_default_simple_signature() {
         Err(x) => println!("{}", x),
     }
 }
+
+#[test]
+fn test_recursion_zk_simple_signature() {
+    use plonky2::{
+        field::types::Sample,
+        hash::hash_types::HashOut,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder,
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::zkdsa::circuits::make_simple_signature_circuit_zk;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    let zkdsa_circuit = make_simple_signature_circuit_zk();
+    assert!(zkdsa_circuit.data.common.config.zero_knowledge);
+
+    let mut pw = PartialWitness::new();
+    zkdsa_circuit
+        .targets
+        .set_witness(&mut pw, HashOut::rand(), HashOut::rand());
+    let signature = zkdsa_circuit.prove(pw).unwrap();
+    zkdsa_circuit.verify(signature.clone()).unwrap();
+
+    // The outer circuit does not need to be zk to verify zk proofs.
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let recursion_target = RecursiveProofTarget::add_virtual_to(&mut builder, &zkdsa_circuit.data);
+    let circuit_data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    recursion_target.set_witness(&mut pw, &signature.into(), true);
+    let proof = circuit_data.prove(pw).unwrap();
+    circuit_data.verify(proof).unwrap();
+}
@@ -1,16 +1,22 @@
 use itertools::Itertools;
 use plonky2::{
     field::{extension::Extendable, types::Field},
-    hash::hash_types::{HashOut, RichField},
-    plonk::config::GenericConfig,
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::{GenericConfig, Hasher},
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    errors::IntmaxError,
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
     transaction::circuits::MergeAndPurgeTransitionProofWithPublicInputs,
     zkdsa::{account::Address, circuits::SimpleSignatureProofWithPublicInputs},
 };
 
+/// The entry of the address list which the block circuit commits to (version 1).
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(bound(
     serialize = "Address<F>: Serialize",
@@ -21,6 +27,82 @@ pub struct TransactionSenderWithValidity<F: Field> {
     pub is_valid: bool,
 }
 
+/// `TransactionSenderWithValidity` with the `tx_hash` of the sender's transaction (version 2),
+/// so that L1 observers know which transaction each validity flag is for.
+/// The `tx_hash` of an empty slot is zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "Address<F>: Serialize, WrappedHashOut<F>: Serialize",
+    deserialize = "Address<F>: Deserialize<'de>, WrappedHashOut<F>: Deserialize<'de>"
+))]
+pub struct TransactionSenderWithValidityV2<F: Field> {
+    pub sender_address: Address<F>,
+    pub tx_hash: WrappedHashOut<F>,
+    pub is_valid: bool,
+}
+
+impl<F: Field> From<TransactionSenderWithValidityV2<F>> for TransactionSenderWithValidity<F> {
+    fn from(value: TransactionSenderWithValidityV2<F>) -> Self {
+        Self {
+            sender_address: value.sender_address,
+            is_valid: value.is_valid,
+        }
+    }
+}
+
+/// The address list of either version, encoded with the version byte by `encode`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "Address<F>: Serialize, WrappedHashOut<F>: Serialize",
+    deserialize = "Address<F>: Deserialize<'de>, WrappedHashOut<F>: Deserialize<'de>"
+))]
+pub enum VersionedAddressList<F: Field> {
+    V1(Vec<TransactionSenderWithValidity<F>>),
+    V2(Vec<TransactionSenderWithValidityV2<F>>),
+}
+
+impl<F: RichField> VersionedAddressList<F> {
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::V1(_) => 1,
+            Self::V2(_) => 2,
+        }
+    }
+
+    /// The entries the block circuit commits to, i.e. without the `tx_hash`.
+    pub fn to_v1(&self) -> Vec<TransactionSenderWithValidity<F>> {
+        match self {
+            Self::V1(address_list) => address_list.clone(),
+            Self::V2(address_list) => address_list.iter().map(|sender| (*sender).into()).collect(),
+        }
+    }
+
+    /// The version byte followed by `encode_address_list` or `encode_address_list_v2`.
+    pub fn encode(&self) -> Vec<u8> {
+        let body = match self {
+            Self::V1(address_list) => encode_address_list(address_list),
+            Self::V2(address_list) => encode_address_list_v2(address_list),
+        };
+
+        [vec![self.version()], body].concat()
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let (version, body) = bytes
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("the address list has no version"))?;
+        match version {
+            1 => Ok(Self::V1(decode_address_list(body)?)),
+            2 => Ok(Self::V2(decode_address_list_v2(body)?)),
+            _ => Err(IntmaxError::UnsupportedVersion {
+                expected: 2,
+                actual: *version as u32,
+            }
+            .into()),
+        }
+    }
+}
+
 pub fn make_address_list<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -29,24 +111,251 @@ pub fn make_address_list<
     user_tx_proofs: &[MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>],
     received_signatures: &[Option<SimpleSignatureProofWithPublicInputs<F, C, D>>],
     num_transactions: usize,
-) -> Vec<TransactionSenderWithValidity<F>> {
+) -> Vec<TransactionSenderWithValidityV2<F>> {
     let mut address_list = vec![];
     for (user_tx_proof, received_signature) in
         user_tx_proofs.iter().zip_eq(received_signatures.iter())
     {
-        address_list.push(TransactionSenderWithValidity {
+        address_list.push(TransactionSenderWithValidityV2 {
             sender_address: user_tx_proof.public_inputs.sender_address,
+            tx_hash: user_tx_proof.public_inputs.tx_hash,
             is_valid: received_signature.is_some(),
         });
     }
 
     address_list.resize(
         num_transactions,
-        TransactionSenderWithValidity {
+        TransactionSenderWithValidityV2 {
             sender_address: Address(HashOut::ZERO),
+            tx_hash: WrappedHashOut::ZERO,
             is_valid: false,
         },
     );
 
     address_list
 }
+
+/// The number of bytes of the length prefix of `encode_address_list`.
+const ADDRESS_LIST_LENGTH_BYTES: usize = 4;
+
+/// Packs the senders of `n_words` 32-byte words each: the number of senders as a big-endian `u32`,
+/// the words of each sender, and then the validity bitmap.
+fn pack_senders<F: RichField>(
+    n_words: usize,
+    senders: &[(Vec<WrappedHashOut<F>>, bool)],
+) -> Vec<u8> {
+    let n_senders = senders.len();
+    let mut bytes = Vec::with_capacity(
+        ADDRESS_LIST_LENGTH_BYTES + 32 * n_words * n_senders + n_senders.div_ceil(8),
+    );
+    bytes.extend_from_slice(&(n_senders as u32).to_be_bytes());
+    for (words, _) in senders {
+        debug_assert_eq!(words.len(), n_words);
+        for word in words {
+            bytes.extend_from_slice(&word.to_bytes_be());
+        }
+    }
+
+    let mut bitmap = vec![0u8; n_senders.div_ceil(8)];
+    for (i, (_, is_valid)) in senders.iter().enumerate() {
+        if *is_valid {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bytes.append(&mut bitmap);
+
+    bytes
+}
+
+/// The inverse of `pack_senders`.
+fn unpack_senders<F: RichField>(
+    n_words: usize,
+    bytes: &[u8],
+) -> anyhow::Result<Vec<(Vec<WrappedHashOut<F>>, bool)>> {
+    anyhow::ensure!(
+        bytes.len() >= ADDRESS_LIST_LENGTH_BYTES,
+        "the address list has no length"
+    );
+    let (length, bytes) = bytes.split_at(ADDRESS_LIST_LENGTH_BYTES);
+    let n_senders = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    let sender_len = 32 * n_words;
+    let bitmap_len = n_senders.div_ceil(8);
+    anyhow::ensure!(
+        bytes.len() == sender_len * n_senders + bitmap_len,
+        "the address list of {} senders must be {} bytes, but got {}",
+        n_senders,
+        ADDRESS_LIST_LENGTH_BYTES + sender_len * n_senders + bitmap_len,
+        ADDRESS_LIST_LENGTH_BYTES + bytes.len()
+    );
+    let (senders, bitmap) = bytes.split_at(sender_len * n_senders);
+    if n_senders % 8 != 0 {
+        anyhow::ensure!(
+            bitmap[bitmap_len - 1] >> (n_senders % 8) == 0,
+            "the padding bits of the validity bitmap must be zero"
+        );
+    }
+
+    senders
+        .chunks(sender_len)
+        .enumerate()
+        .map(|(i, sender)| {
+            let words = sender
+                .chunks(32)
+                .map(|word| WrappedHashOut::from_bytes_be(word.try_into().unwrap()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            Ok((words, (bitmap[i / 8] >> (i % 8)) & 1 == 1))
+        })
+        .collect()
+}
+
+/// Packs `address_list` as an L1 contract stores it: the number of senders as a big-endian `u32`,
+/// the sender addresses in 32 big-endian bytes each, and then the validity bitmap,
+/// where the `i`-th sender is the bit `i % 8` (from the least significant) of the byte `i / 8`.
+pub fn encode_address_list<F: RichField>(
+    address_list: &[TransactionSenderWithValidity<F>],
+) -> Vec<u8> {
+    let senders = address_list
+        .iter()
+        .map(|sender| {
+            (
+                vec![WrappedHashOut::from(sender.sender_address.0)],
+                sender.is_valid,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    pack_senders(1, &senders)
+}
+
+/// The inverse of `encode_address_list`. Fails unless `bytes` is exactly what it encodes,
+/// so that every address list has one encoding.
+pub fn decode_address_list<F: RichField>(
+    bytes: &[u8],
+) -> anyhow::Result<Vec<TransactionSenderWithValidity<F>>> {
+    let senders = unpack_senders(1, bytes)?;
+
+    Ok(senders
+        .into_iter()
+        .map(|(words, is_valid)| TransactionSenderWithValidity {
+            sender_address: Address(*words[0]),
+            is_valid,
+        })
+        .collect())
+}
+
+/// The same as `encode_address_list`, but the `tx_hash` in 32 big-endian bytes follows each sender address.
+pub fn encode_address_list_v2<F: RichField>(
+    address_list: &[TransactionSenderWithValidityV2<F>],
+) -> Vec<u8> {
+    let senders = address_list
+        .iter()
+        .map(|sender| {
+            (
+                vec![
+                    WrappedHashOut::from(sender.sender_address.0),
+                    sender.tx_hash,
+                ],
+                sender.is_valid,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    pack_senders(2, &senders)
+}
+
+/// The inverse of `encode_address_list_v2`.
+pub fn decode_address_list_v2<F: RichField>(
+    bytes: &[u8],
+) -> anyhow::Result<Vec<TransactionSenderWithValidityV2<F>>> {
+    let senders = unpack_senders(2, bytes)?;
+
+    Ok(senders
+        .into_iter()
+        .map(|(words, is_valid)| TransactionSenderWithValidityV2 {
+            sender_address: Address(*words[0]),
+            tx_hash: words[1],
+            is_valid,
+        })
+        .collect())
+}
+
+/// The hash of `address_list` as the block public inputs list it,
+/// i.e. the sender address followed by the validity of each sender.
+pub fn get_address_list_digest<F: RichField>(
+    address_list: &[TransactionSenderWithValidity<F>],
+) -> HashOut<F> {
+    let mut inputs = Vec::with_capacity(5 * address_list.len());
+    for sender in address_list {
+        inputs.extend_from_slice(&sender.sender_address.0.elements);
+        inputs.push(F::from_bool(sender.is_valid));
+    }
+
+    PoseidonHash::hash_no_pad(&inputs)
+}
+
+#[test]
+fn test_encode_address_list() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    let address_list = (0..10)
+        .map(|i| TransactionSenderWithValidity {
+            sender_address: Address::<GoldilocksField>::rand(),
+            is_valid: i % 3 == 0,
+        })
+        .collect::<Vec<_>>();
+    let encoded = encode_address_list(&address_list);
+    assert_eq!(encoded.len(), 4 + 32 * 10 + 2);
+    let decoded = decode_address_list::<GoldilocksField>(&encoded).unwrap();
+    assert_eq!(decoded, address_list);
+    assert_eq!(
+        get_address_list_digest(&decoded),
+        get_address_list_digest(&address_list)
+    );
+
+    assert!(decode_address_list::<GoldilocksField>(&encoded[..encoded.len() - 1]).is_err());
+    let mut padded = encoded.clone();
+    *padded.last_mut().unwrap() |= 0x80;
+    assert!(decode_address_list::<GoldilocksField>(&padded).is_err());
+
+    assert_eq!(
+        decode_address_list::<GoldilocksField>(&encode_address_list::<GoldilocksField>(&[]))
+            .unwrap(),
+        vec![]
+    );
+
+    let address_list_v2 = address_list
+        .iter()
+        .map(|sender| TransactionSenderWithValidityV2 {
+            sender_address: sender.sender_address,
+            tx_hash: WrappedHashOut::rand(),
+            is_valid: sender.is_valid,
+        })
+        .collect::<Vec<_>>();
+    let encoded_v2 = encode_address_list_v2(&address_list_v2);
+    assert_eq!(encoded_v2.len(), 4 + 64 * 10 + 2);
+    assert_eq!(
+        decode_address_list_v2::<GoldilocksField>(&encoded_v2).unwrap(),
+        address_list_v2
+    );
+    assert!(decode_address_list_v2::<GoldilocksField>(&encoded).is_err());
+
+    // The version 2 commits to the same digest without the `tx_hash`.
+    let versioned = VersionedAddressList::V2(address_list_v2);
+    let encoded_versioned = versioned.encode();
+    assert_eq!(encoded_versioned[0], 2);
+    let decoded_versioned =
+        VersionedAddressList::<GoldilocksField>::decode(&encoded_versioned).unwrap();
+    assert_eq!(decoded_versioned, versioned);
+    assert_eq!(
+        get_address_list_digest(&decoded_versioned.to_v1()),
+        get_address_list_digest(&address_list)
+    );
+    assert_eq!(
+        VersionedAddressList::<GoldilocksField>::decode(&[[1].as_slice(), &encoded].concat())
+            .unwrap(),
+        VersionedAddressList::V1(address_list)
+    );
+    assert!(VersionedAddressList::<GoldilocksField>::decode(&[3]).is_err());
+    assert!(VersionedAddressList::<GoldilocksField>::decode(&[]).is_err());
+}
@@ -0,0 +1,288 @@
+//! Balance proofs at past blocks, for audits and dispute resolution.
+//!
+//! `NodeDataMemory` never deletes the nodes of an updated tree, so the trees at any past root
+//! are still in the node data. `BalanceHistory` records the `approved_world_state_digest` of each
+//! block and proves the balance of a token at a block by the inclusion chain from that digest:
+//! the world state leaf of the address, then every merge key of its user asset tree,
+//! and then the contract and the variable of the token under each merge key.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use plonky2::{field::goldilocks_field::GoldilocksField, hash::hash_types::HashOut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::verify::verify_smt::{LayeredLayeredSmtInclusionProof, SmtInclusionProof},
+        goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonNodeHash, PoseidonSparseMerkleTree,
+        },
+        node_data::NodeData,
+        tree::get_leaves,
+    },
+    transaction::{asset::TokenKind, block_header::BlockHeader},
+    verification::merkle::verify_smt_inclusion_proof,
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoricalBalanceProof {
+    pub block_number: u32,
+    pub address: Address<F>,
+    pub kind: TokenKind<F>,
+
+    /// The user asset root of `address` in the world state tree.
+    pub world_state_inclusion_proof: SmtInclusionProof<F>,
+
+    /// The inclusion chain of `kind` under each merge key of the user asset tree,
+    /// as `LayeredLayeredSparseMerkleTree::find` returns.
+    pub asset_inclusion_proofs: Vec<LayeredLayeredSmtInclusionProof<F>>,
+}
+
+/// Checks `proof` of a key in the tree with `root` and returns the value, which is zero if not found.
+fn verify_inclusion_proof(
+    proof: &SmtInclusionProof<F>,
+    root: GoldilocksHashOut,
+    key: GoldilocksHashOut,
+) -> anyhow::Result<GoldilocksHashOut> {
+    anyhow::ensure!(proof.root == root, "the proof is for another root");
+    anyhow::ensure!(proof.key == key, "the proof is for another key");
+    let siblings = proof.siblings.iter().map(|v| **v).collect::<Vec<_>>();
+    verify_smt_inclusion_proof(
+        *proof.root,
+        *proof.key,
+        *proof.value,
+        proof.found,
+        *proof.not_found_key,
+        *proof.not_found_value,
+        proof.is_old0,
+        &siblings,
+    )?;
+
+    Ok(if proof.found {
+        proof.value
+    } else {
+        Default::default()
+    })
+}
+
+impl HistoricalBalanceProof {
+    /// Checks the proof against the `approved_world_state_digest` of the block and returns the balance.
+    pub fn verify(&self, world_state_digest: HashOut<F>) -> anyhow::Result<u128> {
+        let user_asset_root = verify_inclusion_proof(
+            &self.world_state_inclusion_proof,
+            world_state_digest.into(),
+            self.address.0.into(),
+        )?;
+
+        // All the merge keys must be given, so that no amount under another merge key is hidden.
+        let mut merge_key_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+        let mut balance = 0u128;
+        for (merge_key_proof, contract_proof, variable_proof) in &self.asset_inclusion_proofs {
+            anyhow::ensure!(
+                merge_key_proof.found,
+                "the merge key {} is not in the user asset tree",
+                merge_key_proof.key
+            );
+            anyhow::ensure!(
+                merge_key_tree.get(&merge_key_proof.key)? == Default::default(),
+                "the merge key {} is given twice",
+                merge_key_proof.key
+            );
+            let contract_root =
+                verify_inclusion_proof(merge_key_proof, user_asset_root, merge_key_proof.key)?;
+            merge_key_tree.set(merge_key_proof.key, contract_root)?;
+
+            let variable_root = verify_inclusion_proof(
+                contract_proof,
+                contract_root,
+                self.kind.contract_address.0.into(),
+            )?;
+            let amount =
+                verify_inclusion_proof(variable_proof, variable_root, self.kind.variable_index)?;
+            balance = balance
+                .checked_add(amount.to_u128())
+                .ok_or_else(|| anyhow::anyhow!("the balance overflows"))?;
+        }
+        anyhow::ensure!(
+            merge_key_tree.get_root() == user_asset_root,
+            "some merge keys of the user asset tree are missing"
+        );
+
+        Ok(balance)
+    }
+}
+
+/// The world state digests of the past blocks and the node data of the trees at them.
+/// `world_state_nodes` and `user_asset_nodes` may be the same.
+pub struct BalanceHistory<D: NodeData<K, V, I>> {
+    pub world_state_nodes: Arc<Mutex<D>>,
+    pub user_asset_nodes: Arc<Mutex<D>>,
+    world_state_digests: HashMap<u32, HashOut<F>>,
+}
+
+impl<D: NodeData<K, V, I>> BalanceHistory<D> {
+    pub fn new(world_state_nodes: Arc<Mutex<D>>, user_asset_nodes: Arc<Mutex<D>>) -> Self {
+        Self {
+            world_state_nodes,
+            user_asset_nodes,
+            world_state_digests: HashMap::new(),
+        }
+    }
+
+    /// Records the world state after the block, whose nodes must be retained from now on.
+    pub fn record_block(&mut self, header: &BlockHeader<F>) {
+        self.world_state_digests
+            .insert(header.block_number, header.approved_world_state_digest);
+    }
+
+    pub fn world_state_digest(&self, block_number: u32) -> Option<HashOut<F>> {
+        self.world_state_digests.get(&block_number).copied()
+    }
+
+    /// Proves the balance of the token `(contract_address, variable_index)` of `address`
+    /// after the block `block_number`.
+    pub fn prove_balance_at(
+        &self,
+        address: Address<F>,
+        contract_address: Address<F>,
+        variable_index: GoldilocksHashOut,
+        block_number: u32,
+    ) -> anyhow::Result<HistoricalBalanceProof> {
+        let world_state_digest = self
+            .world_state_digest(block_number)
+            .ok_or_else(|| anyhow::anyhow!("the block {} is not recorded", block_number))?;
+        let world_state_tree = PoseidonSparseMerkleTree::new(
+            self.world_state_nodes.clone(),
+            world_state_digest.into(),
+        );
+        let world_state_inclusion_proof = world_state_tree.find(&address.0.into())?;
+        let user_asset_root = if world_state_inclusion_proof.found {
+            world_state_inclusion_proof.value
+        } else {
+            Default::default()
+        };
+
+        let merge_keys =
+            get_leaves::<K, V, I, PoseidonNodeHash, D>(&self.user_asset_nodes, &user_asset_root)?;
+        let user_asset_tree = LayeredLayeredPoseidonSparseMerkleTree::new(
+            self.user_asset_nodes.clone(),
+            user_asset_root,
+        );
+        let asset_inclusion_proofs = merge_keys
+            .iter()
+            .map(|(merge_key, _)| {
+                user_asset_tree.find(merge_key, &contract_address.0.into(), &variable_index)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(HistoricalBalanceProof {
+            block_number,
+            address,
+            kind: TokenKind {
+                contract_address,
+                variable_index,
+            },
+            world_state_inclusion_proof,
+            asset_inclusion_proofs,
+        })
+    }
+}
+
+#[test]
+fn test_prove_balance_at() {
+    let nodes = Arc::new(Mutex::new(NodeDataMemory::default()));
+    let mut history = BalanceHistory::new(nodes.clone(), nodes.clone());
+    let mut world_state_tree = PoseidonSparseMerkleTree::new(nodes.clone(), Default::default());
+    let mut user_asset_tree =
+        LayeredLayeredPoseidonSparseMerkleTree::new(nodes.clone(), Default::default());
+
+    let address = Address::rand();
+    let kind = TokenKind {
+        contract_address: Address::rand(),
+        variable_index: GoldilocksHashOut::rand(),
+    };
+    let other_kind = TokenKind {
+        contract_address: Address::rand(),
+        ..kind
+    };
+    let merge_keys = [GoldilocksHashOut::rand(), GoldilocksHashOut::rand()];
+    let mut header = BlockHeader::with_tree_depth(2);
+
+    // block 1: 10 under the first merge key.
+    let set_asset = |user_asset_tree: &mut LayeredLayeredPoseidonSparseMerkleTree<_>,
+                     merge_key: GoldilocksHashOut,
+                     kind: TokenKind<F>,
+                     amount: u128| {
+        user_asset_tree
+            .set(
+                merge_key,
+                kind.contract_address.0.into(),
+                kind.variable_index,
+                GoldilocksHashOut::from_u128(amount),
+            )
+            .unwrap();
+    };
+    set_asset(&mut user_asset_tree, merge_keys[0], kind, 10);
+    world_state_tree
+        .set(address.0.into(), user_asset_tree.get_root())
+        .unwrap();
+    header.block_number = 1;
+    header.approved_world_state_digest = *world_state_tree.get_root();
+    history.record_block(&header);
+
+    // block 2: 5 more under the second merge key and another token.
+    set_asset(&mut user_asset_tree, merge_keys[1], kind, 5);
+    set_asset(&mut user_asset_tree, merge_keys[1], other_kind, 7);
+    world_state_tree
+        .set(address.0.into(), user_asset_tree.get_root())
+        .unwrap();
+    header.block_number = 2;
+    header.approved_world_state_digest = *world_state_tree.get_root();
+    history.record_block(&header);
+
+    let prove = |block_number: u32, kind: TokenKind<F>| {
+        history
+            .prove_balance_at(
+                address,
+                kind.contract_address,
+                kind.variable_index,
+                block_number,
+            )
+            .unwrap()
+    };
+    let digest = |block_number| history.world_state_digest(block_number).unwrap();
+    assert_eq!(prove(1, kind).verify(digest(1)).unwrap(), 10);
+    assert_eq!(prove(2, kind).verify(digest(2)).unwrap(), 15);
+    assert_eq!(prove(1, other_kind).verify(digest(1)).unwrap(), 0);
+    assert_eq!(prove(2, other_kind).verify(digest(2)).unwrap(), 7);
+    assert!(prove(1, kind).verify(digest(2)).is_err());
+
+    // A merge key cannot be hidden.
+    let mut proof = prove(2, kind);
+    proof.asset_inclusion_proofs.pop();
+    assert!(proof.verify(digest(2)).is_err());
+
+    // An address without assets has no balance.
+    let proof = history
+        .prove_balance_at(
+            Address::rand(),
+            kind.contract_address,
+            kind.variable_index,
+            2,
+        )
+        .unwrap();
+    assert_eq!(proof.verify(digest(2)).unwrap(), 0);
+    assert!(history
+        .prove_balance_at(address, kind.contract_address, kind.variable_index, 3)
+        .is_err());
+}
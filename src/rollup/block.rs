@@ -1,12 +1,158 @@
-use plonky2::hash::hash_types::RichField;
-use serde::{Deserialize, Serialize};
+use num_bigint::BigUint;
+use plonky2::{
+    field::{
+        goldilocks_field::GoldilocksField,
+        secp256k1_base::Secp256K1Base,
+        secp256k1_scalar::Secp256K1Scalar,
+        types::{Field, PrimeField},
+    },
+    hash::hash_types::RichField,
+};
+use plonky2_ecdsa::curve::{
+    curve_types::AffinePoint,
+    ecdsa::{sign_message, verify_message, ECDSAPublicKey, ECDSASecretKey, ECDSASignature},
+    secp256k1::Secp256K1,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_hex::{SerHexSeq, StrictPfx};
 
 use crate::{
+    ecdsa::account::{private_key_to_public_key, public_key_to_address},
     rollup::{address_list::TransactionSenderWithValidity, gadgets::deposit_block::DepositInfo},
     sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
-    transaction::block_header::BlockHeader,
+    transaction::block_header::{get_block_hash, BlockHeader},
 };
 
+/// The length of `AggregatorSignature::to_bytes`.
+pub const AGGREGATOR_SIGNATURE_BYTES_LEN: usize = 128;
+
+/// The secp256k1 ECDSA signature of the aggregator over the block hash.
+/// `public_key_to_address(public_key)` must be the aggregator address of the block header,
+/// so that anyone can attribute the block to the aggregator without a proof,
+/// e.g. to post the block with the alerts of a watchtower as the evidence to slash its bond.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AggregatorSignature {
+    pub public_key: ECDSAPublicKey<Secp256K1>,
+    pub signature: ECDSASignature<Secp256K1>,
+}
+
+/// The big-endian integer of the block hash modulo the order of secp256k1.
+pub fn block_hash_to_message(header: &BlockHeader<GoldilocksField>) -> Secp256K1Scalar {
+    let block_hash = WrappedHashOut::from(get_block_hash(header));
+
+    Secp256K1Scalar::from_noncanonical_biguint(BigUint::from_bytes_be(&block_hash.to_bytes_be()))
+}
+
+fn biguint_to_bytes(value: BigUint) -> [u8; 32] {
+    let raw = value.to_bytes_be();
+    let mut bytes = [0u8; 32];
+    bytes[32 - raw.len()..].copy_from_slice(&raw);
+
+    bytes
+}
+
+fn canonical_biguint_from_bytes(
+    name: &str,
+    bytes: &[u8],
+    order: BigUint,
+) -> anyhow::Result<BigUint> {
+    let value = BigUint::from_bytes_be(bytes);
+    anyhow::ensure!(value < order, "{} is not canonical", name);
+
+    Ok(value)
+}
+
+impl AggregatorSignature {
+    pub fn sign(
+        private_key: ECDSASecretKey<Secp256K1>,
+        header: &BlockHeader<GoldilocksField>,
+    ) -> Self {
+        Self {
+            public_key: private_key_to_public_key(private_key),
+            signature: sign_message(block_hash_to_message(header), private_key),
+        }
+    }
+
+    /// Checks that the signature is of `header` by its aggregator.
+    pub fn verify(&self, header: &BlockHeader<GoldilocksField>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            public_key_to_address(self.public_key)? == header.aggregator_address,
+            "the signature is not by the aggregator of the block {}",
+            header.block_number
+        );
+        anyhow::ensure!(
+            verify_message(
+                block_hash_to_message(header),
+                self.signature,
+                self.public_key
+            ),
+            "invalid signature of the block {}",
+            header.block_number
+        );
+
+        Ok(())
+    }
+
+    /// The big-endian `x`, `y` of the public key and `r`, `s` of the signature, 32 bytes each.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.public_key.0.x.to_canonical_biguint(),
+            self.public_key.0.y.to_canonical_biguint(),
+            self.signature.r.to_canonical_biguint(),
+            self.signature.s.to_canonical_biguint(),
+        ]
+        .into_iter()
+        .flat_map(biguint_to_bytes)
+        .collect()
+    }
+
+    /// Rejects a non-canonical integer or a public key which is not on the curve.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() == AGGREGATOR_SIGNATURE_BYTES_LEN,
+            "invalid length of an aggregator signature: expected {}, actual {}",
+            AGGREGATOR_SIGNATURE_BYTES_LEN,
+            bytes.len()
+        );
+
+        let base = |name, range: std::ops::Range<usize>| {
+            canonical_biguint_from_bytes(name, &bytes[range], Secp256K1Base::order())
+                .map(Secp256K1Base::from_noncanonical_biguint)
+        };
+        let scalar = |name, range: std::ops::Range<usize>| {
+            canonical_biguint_from_bytes(name, &bytes[range], Secp256K1Scalar::order())
+                .map(Secp256K1Scalar::from_noncanonical_biguint)
+        };
+        let public_key = AffinePoint::nonzero(base("x", 0..32)?, base("y", 32..64)?);
+        anyhow::ensure!(public_key.is_valid(), "the public key is not on the curve");
+
+        Ok(Self {
+            public_key: ECDSAPublicKey(public_key),
+            signature: ECDSASignature {
+                r: scalar("r", 64..96)?,
+                s: scalar("s", 96..128)?,
+            },
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializableAggregatorSignature(#[serde(with = "SerHexSeq::<StrictPfx>")] Vec<u8>);
+
+impl Serialize for AggregatorSignature {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializableAggregatorSignature(self.to_bytes()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AggregatorSignature {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = SerializableAggregatorSignature::deserialize(deserializer)?;
+
+        Self::from_bytes(&raw.0).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlockInfo<F: RichField> {
     #[serde(bound(
@@ -29,6 +175,10 @@ pub struct BlockInfo<F: RichField> {
         deserialize = "TransactionSenderWithValidity<F>: Deserialize<'de>"
     ))]
     pub address_list: Vec<TransactionSenderWithValidity<F>>,
+
+    /// `None` if the aggregator has not signed the block, e.g. the genesis block.
+    #[serde(default)]
+    pub aggregator_signature: Option<AggregatorSignature>,
     // diff_tree_proof
     // world_state_tree_proof
 }
@@ -40,6 +190,42 @@ impl<F: RichField> BlockInfo<F> {
             transactions: Default::default(),
             deposit_list: Default::default(),
             address_list: Default::default(),
+            aggregator_signature: None,
         }
     }
 }
+
+#[test]
+fn test_aggregator_signature() {
+    use plonky2::field::types::Sample;
+
+    use crate::ecdsa::account::private_key_to_account;
+
+    let account = private_key_to_account(ECDSASecretKey(Secp256K1Scalar::rand())).unwrap();
+    let mut block = BlockInfo::<GoldilocksField>::with_tree_depth(3);
+    block.header.block_number = 1;
+    block.header.aggregator_address = account.address;
+    let signature = AggregatorSignature::sign(account.private_key, &block.header);
+    signature.verify(&block.header).unwrap();
+    block.aggregator_signature = Some(signature);
+
+    let encoded_block = serde_json::to_string(&block).unwrap();
+    let decoded_block: BlockInfo<GoldilocksField> = serde_json::from_str(&encoded_block).unwrap();
+    assert_eq!(decoded_block.aggregator_signature, Some(signature));
+    assert_eq!(
+        AggregatorSignature::from_bytes(&signature.to_bytes()).unwrap(),
+        signature
+    );
+
+    // The signature is bound to the header and to the aggregator address.
+    let mut other_header = block.header.clone();
+    other_header.block_number = 2;
+    assert!(signature.verify(&other_header).is_err());
+    let other_account = private_key_to_account(ECDSASecretKey(Secp256K1Scalar::rand())).unwrap();
+    let forged_signature = AggregatorSignature::sign(other_account.private_key, &block.header);
+    assert!(forged_signature.verify(&block.header).is_err());
+
+    let mut invalid_bytes = signature.to_bytes();
+    invalid_bytes[0..32].copy_from_slice(&[0xff; 32]);
+    assert!(AggregatorSignature::from_bytes(&invalid_bytes).is_err());
+}
@@ -0,0 +1,794 @@
+//! Incremental block building.
+//!
+//! Instead of collecting all user transactions before setting the witness of the block circuit,
+//! the aggregator admits them one at a time. The world state process proof of a transaction is
+//! generated on admission and the transaction takes the next slot of the block.
+//! An admitted transaction can be cancelled until the block is sealed.
+//! The block is sealed when all slots are taken or the deadline has passed,
+//! and the senders then sign the proposed world state root.
+//! The forced transactions of the L1 queue are looked up when the block starts,
+//! and those admitted or stale are processed when the block is finalized.
+//! The block header has the time when the block is sealed and the address of the aggregator.
+//! The mints received by the sealed block are applied to the world state after the approval,
+//! and then the contract calls.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    hash::hash_types::{HashOut, RichField},
+    plonk::{
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::ensure_at_most,
+    prover::backend::BlockWitness,
+    rollup::{
+        block_diff::{BlockDiff, RollupStateTrees, TreeDiff},
+        circuits::{
+            registry::{BlockCircuit, CircuitRegistry},
+            N_CONTRACT_CALLS, N_MINTS,
+        },
+        contract::ContractCall,
+        forced_inclusion::{
+            make_forced_transaction_witnesses, ForcedTransactionQueue, PendingForcedTransaction,
+        },
+        gadgets::mint_block::validate_mint_block_witness,
+    },
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::{LayeredLayeredSmtProcessProof, SmtProcessProof},
+        goldilocks_poseidon::{GoldilocksHashOut, WrappedHashOut},
+        node_data::NodeData,
+    },
+    transaction::{
+        asset::Asset,
+        burn::get_burned_assets_digest,
+        circuits::{mint::MintPublicInputs, MergeAndPurgeTransitionProofWithPublicInputs},
+        pow::{check_tx_hash_work, MAX_POW_DIFFICULTY},
+    },
+    zkdsa::{
+        account::{public_key_to_address, Address},
+        circuits::SimpleSignatureProofWithPublicInputs,
+    },
+};
+
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+#[derive(Clone, Debug)]
+pub struct AdmittedTransaction<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub slot: usize,
+    pub user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+    pub world_state_process_proof: SmtProcessProof<F>,
+
+    /// The assets burned by the transaction, which are withdrawn if the sender signs.
+    pub burned_assets: Vec<Asset<F>>,
+}
+
+/// Builds the proposal of the block `block_number` with at most `capacity` transactions.
+/// `capacity` is `N_TXS` of the block circuit, or `CircuitRegistry::max_n_txs`.
+pub struct IncrementalBlockBuilder<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub block_number: u32,
+    pub capacity: usize,
+    pub deadline: Instant,
+    old_world_state_root: WrappedHashOut<F>,
+    diff: BlockDiff,
+    transactions: Vec<AdmittedTransaction<F, C, D>>,
+    old_forced_transactions_digest: HashOut<F>,
+    pending_forced_transactions: Vec<PendingForcedTransaction>,
+
+    /// The bits of proof of work required on `tx_hash`, which is 0 without the requirement.
+    pow_difficulty: u32,
+
+    aggregator_address: Address<F>,
+    issuer_registry_root: HashOut<F>,
+}
+
+impl<C: GenericConfig<D, F = GoldilocksField>, const D: usize>
+    IncrementalBlockBuilder<GoldilocksField, C, D>
+where
+    GoldilocksField: Extendable<D>,
+{
+    /// Starts the block from the current roots of `trees`,
+    /// which must not be updated except through this builder until the block is finalized.
+    pub fn new<Nd: NodeData<K, V, I>>(
+        block_number: u32,
+        trees: &RollupStateTrees<Nd>,
+        capacity: usize,
+        deadline: Instant,
+    ) -> Self {
+        Self {
+            block_number,
+            capacity,
+            deadline,
+            old_world_state_root: trees.world_state_tree.get_root(),
+            diff: BlockDiff::new(block_number, trees),
+            transactions: Vec::with_capacity(capacity),
+            old_forced_transactions_digest: HashOut::ZERO,
+            pending_forced_transactions: vec![],
+            pow_difficulty: 0,
+            aggregator_address: Default::default(),
+            issuer_registry_root: HashOut::ZERO,
+        }
+    }
+
+    /// Attributes the block to `aggregator_address`, which is zero by default.
+    pub fn with_aggregator_address(mut self, aggregator_address: Address<GoldilocksField>) -> Self {
+        self.aggregator_address = aggregator_address;
+
+        self
+    }
+
+    /// Commits `issuer_registry_root` in the block header, which is zero by default.
+    pub fn with_issuer_registry_root(
+        mut self,
+        issuer_registry_root: HashOut<GoldilocksField>,
+    ) -> Self {
+        self.issuer_registry_root = issuer_registry_root;
+
+        self
+    }
+
+    /// Requires the proof of work of `difficulty` bits on the `tx_hash` of every admitted transaction.
+    pub fn with_pow_difficulty(mut self, difficulty: u32) -> anyhow::Result<Self> {
+        ensure_at_most(
+            "bits of proof of work",
+            difficulty as usize,
+            MAX_POW_DIFFICULTY as usize,
+        )?;
+        self.pow_difficulty = difficulty;
+
+        Ok(self)
+    }
+
+    /// Looks up the forced transactions after `watermark` before any transaction is admitted.
+    /// The previous block header must commit to the digest of the first `watermark` transactions of `queue`.
+    pub fn with_forced_transactions<Nd: NodeData<K, V, I>>(
+        mut self,
+        trees: &RollupStateTrees<Nd>,
+        queue: &ForcedTransactionQueue,
+        watermark: usize,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            self.transactions.is_empty(),
+            "the forced transactions must be looked up before admitting transactions"
+        );
+        self.old_forced_transactions_digest = queue
+            .digest(watermark)
+            .ok_or_else(|| anyhow::anyhow!("the watermark {} exceeds the queue", watermark))?;
+        self.pending_forced_transactions = queue.pending(watermark, &trees.world_state_tree)?;
+
+        Ok(self)
+    }
+
+    pub fn transactions(&self) -> &[AdmittedTransaction<GoldilocksField, C, D>] {
+        &self.transactions
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.transactions.len() >= self.capacity
+    }
+
+    /// Whether the block should be sealed now.
+    pub fn is_due(&self) -> bool {
+        self.is_full() || Instant::now() >= self.deadline
+    }
+
+    /// Updates the user asset root of the sender in the world state tree
+    /// and returns the slot of the transaction, which must burn nothing.
+    pub fn admit<Nd: NodeData<K, V, I>>(
+        &mut self,
+        trees: &mut RollupStateTrees<Nd>,
+        user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<GoldilocksField, C, D>,
+    ) -> anyhow::Result<usize> {
+        self.admit_with_burned_assets(trees, user_tx_proof, vec![])
+    }
+
+    /// The same as `admit`, where the transaction burns `burned_assets` to withdraw them.
+    pub fn admit_with_burned_assets<Nd: NodeData<K, V, I>>(
+        &mut self,
+        trees: &mut RollupStateTrees<Nd>,
+        user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<GoldilocksField, C, D>,
+        burned_assets: Vec<Asset<GoldilocksField>>,
+    ) -> anyhow::Result<usize> {
+        anyhow::ensure!(
+            Instant::now() < self.deadline,
+            "the deadline of the block {} has passed",
+            self.block_number
+        );
+        let slot = self.transactions.len();
+        ensure_at_most("user transactions", slot + 1, self.capacity)?;
+
+        let public_inputs = &user_tx_proof.public_inputs;
+        check_tx_hash_work(*public_inputs.tx_hash, self.pow_difficulty)?;
+        let sender_address = public_inputs.sender_address;
+        anyhow::ensure!(
+            get_burned_assets_digest(&burned_assets) == *public_inputs.burned_assets_digest,
+            "the burned assets of {} are not of the transaction",
+            sender_address
+        );
+        anyhow::ensure!(
+            self.transactions
+                .iter()
+                .all(|tx| { tx.user_tx_proof.public_inputs.sender_address != sender_address }),
+            "{} already has a transaction in the block {}",
+            sender_address,
+            self.block_number
+        );
+
+        // The world state must hold the user asset root after merge,
+        // unless the transaction creates the account of the sender.
+        let user_asset_root = trees.world_state_tree.get(&sender_address.0.into())?;
+        if user_asset_root == WrappedHashOut::default() {
+            anyhow::ensure!(
+                public_inputs.old_user_asset_root == WrappedHashOut::default(),
+                "{} is not in the world state, but the transaction does not start from the empty user asset tree",
+                sender_address
+            );
+            anyhow::ensure!(
+                public_inputs.new_user_asset_root != WrappedHashOut::default(),
+                "the transaction creating the account of {} must leave some assets",
+                sender_address
+            );
+        } else {
+            anyhow::ensure!(
+                user_asset_root == public_inputs.middle_user_asset_root,
+                "the user asset root of {} is {}, but the transaction is merged into {}",
+                sender_address,
+                user_asset_root,
+                public_inputs.middle_user_asset_root
+            );
+        }
+
+        let world_state_process_proof = self.diff.set_user_asset_root(
+            trees,
+            sender_address.0.into(),
+            public_inputs.new_user_asset_root,
+        )?;
+        self.transactions.push(AdmittedTransaction {
+            slot,
+            user_tx_proof,
+            world_state_process_proof,
+            burned_assets,
+        });
+
+        Ok(slot)
+    }
+
+    /// Removes the admitted transaction of `tx_hash` before the block is sealed
+    /// and restores the user asset root of its sender.
+    /// The later transactions move up by one slot with their world state process proofs generated again.
+    pub fn cancel_transaction<Nd: NodeData<K, V, I>>(
+        &mut self,
+        trees: &mut RollupStateTrees<Nd>,
+        tx_hash: WrappedHashOut<GoldilocksField>,
+    ) -> anyhow::Result<AdmittedTransaction<GoldilocksField, C, D>> {
+        let slot = self
+            .transactions
+            .iter()
+            .position(|tx| tx.user_tx_proof.public_inputs.tx_hash == tx_hash)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "the transaction {} is not in the block {}",
+                    tx_hash,
+                    self.block_number
+                )
+            })?;
+        anyhow::ensure!(
+            self.diff.world_state.changes.len() == self.transactions.len(),
+            "the world state diff is not of the admitted transactions"
+        );
+
+        // Revert the transactions from the last one to the cancelled one.
+        let reverted = TreeDiff {
+            old_root: self.transactions[slot].world_state_process_proof.old_root,
+            new_root: self.diff.world_state.new_root,
+            changes: self.diff.world_state.changes.split_off(slot),
+        };
+        reverted.revert(&mut trees.world_state_tree)?;
+        self.diff.world_state.new_root = reverted.old_root;
+
+        let cancelled = self.transactions.remove(slot);
+        for tx in self.transactions.iter_mut().skip(slot) {
+            let public_inputs = &tx.user_tx_proof.public_inputs;
+            tx.slot -= 1;
+            tx.world_state_process_proof = self.diff.set_user_asset_root(
+                trees,
+                public_inputs.sender_address.0.into(),
+                public_inputs.new_user_asset_root,
+            )?;
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Closes the admission. The senders sign `SealedBlock::proposed_world_state_root`.
+    pub fn seal(self) -> SealedBlock<GoldilocksField, C, D> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        SealedBlock {
+            block_number: self.block_number,
+            timestamp,
+            aggregator_address: self.aggregator_address,
+            issuer_registry_root: self.issuer_registry_root,
+            old_world_state_root: self.old_world_state_root,
+            proposed_world_state_root: self.diff.world_state.new_root,
+            received_signatures: vec![None; self.transactions.len()],
+            mint_proofs: vec![],
+            contract_calls: vec![],
+            diff: self.diff,
+            transactions: self.transactions,
+            old_forced_transactions_digest: self.old_forced_transactions_digest,
+            pending_forced_transactions: self.pending_forced_transactions,
+        }
+    }
+}
+
+pub struct SealedBlock<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub block_number: u32,
+    /// The unix time in seconds when the block was sealed.
+    pub timestamp: u64,
+    pub aggregator_address: Address<F>,
+    pub issuer_registry_root: HashOut<F>,
+    pub old_world_state_root: WrappedHashOut<F>,
+    pub proposed_world_state_root: WrappedHashOut<F>,
+    diff: BlockDiff,
+    transactions: Vec<AdmittedTransaction<F, C, D>>,
+    received_signatures: Vec<Option<SimpleSignatureProofWithPublicInputs<F, C, D>>>,
+    mint_proofs: Vec<ProofWithPublicInputs<F, C, D>>,
+    contract_calls: Vec<ContractCall<F, C, D>>,
+    old_forced_transactions_digest: HashOut<F>,
+    pending_forced_transactions: Vec<PendingForcedTransaction>,
+}
+
+impl<C: GenericConfig<D, F = GoldilocksField>, const D: usize> SealedBlock<GoldilocksField, C, D>
+where
+    GoldilocksField: Extendable<D>,
+{
+    pub fn transactions(&self) -> &[AdmittedTransaction<GoldilocksField, C, D>] {
+        &self.transactions
+    }
+
+    /// The smallest circuit of `registry` which fits the transactions of this block.
+    pub fn select_circuit<'a>(
+        &self,
+        registry: &'a CircuitRegistry<GoldilocksField, C, D>,
+    ) -> anyhow::Result<&'a dyn BlockCircuit<GoldilocksField, C, D>> {
+        registry.select(self.transactions.len())
+    }
+
+    /// Accepts the signature of a sender to the proposed world state root
+    /// and returns the slot of the sender's transaction.
+    pub fn receive_signature(
+        &mut self,
+        received_signature: SimpleSignatureProofWithPublicInputs<GoldilocksField, C, D>,
+    ) -> anyhow::Result<usize> {
+        let signer_address: Address<GoldilocksField> =
+            public_key_to_address(received_signature.public_inputs.public_key);
+        anyhow::ensure!(
+            received_signature.public_inputs.message == *self.proposed_world_state_root,
+            "the signature of {} is not for the proposed world state root",
+            signer_address
+        );
+        let slot = self
+            .transactions
+            .iter()
+            .position(|tx| tx.user_tx_proof.public_inputs.sender_address == signer_address)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} has no transaction in the block {}",
+                    signer_address,
+                    self.block_number
+                )
+            })?;
+        self.received_signatures[slot] = Some(received_signature);
+
+        Ok(slot)
+    }
+
+    /// Accepts a mint proof signed for this block against its issuer registry
+    /// and returns the index of the mint.
+    pub fn receive_mint(
+        &mut self,
+        mint_proof: ProofWithPublicInputs<GoldilocksField, C, D>,
+    ) -> anyhow::Result<usize> {
+        let mint = MintPublicInputs::decode(&mint_proof.public_inputs)?;
+        anyhow::ensure!(
+            mint.request.block_number == self.block_number,
+            "the mint of {} is signed for the block {}",
+            mint.issuer_address,
+            mint.request.block_number
+        );
+        anyhow::ensure!(
+            *mint.issuer_registry_root == self.issuer_registry_root,
+            "the mint of {} is for another issuer registry",
+            mint.issuer_address
+        );
+        ensure_at_most("mint proofs", self.mint_proofs.len() + 1, N_MINTS)?;
+        self.mint_proofs.push(mint_proof);
+
+        Ok(self.mint_proofs.len() - 1)
+    }
+
+    /// Accepts a contract call and returns its index.
+    /// The calls are applied in the order they are received.
+    pub fn receive_contract_call(
+        &mut self,
+        contract_call: ContractCall<GoldilocksField, C, D>,
+    ) -> anyhow::Result<usize>
+    where
+        C::Hasher: AlgebraicHasher<GoldilocksField>,
+    {
+        contract_call.public_inputs()?;
+        ensure_at_most(
+            "contract calls",
+            self.contract_calls.len() + 1,
+            N_CONTRACT_CALLS,
+        )?;
+        self.contract_calls.push(contract_call);
+
+        Ok(self.contract_calls.len() - 1)
+    }
+
+    /// Reverts the user asset roots of the senders who did not sign, updates the latest account tree,
+    /// applies the mints and the contract calls
+    /// and returns the block witness together with the changes made to `trees`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn finalize<Nd: NodeData<K, V, I>>(
+        self,
+        trees: &mut RollupStateTrees<Nd>,
+        deposit_process_proofs: Vec<LayeredLayeredSmtProcessProof<GoldilocksField>>,
+        deposit_nonce: HashOut<GoldilocksField>,
+        default_simple_signature: SimpleSignatureProofWithPublicInputs<GoldilocksField, C, D>,
+        default_mint_proof: ProofWithPublicInputs<GoldilocksField, C, D>,
+        default_contract_call: ContractCall<GoldilocksField, C, D>,
+        block_header_siblings: Vec<HashOut<GoldilocksField>>,
+        prev_block_hash: HashOut<GoldilocksField>,
+    ) -> anyhow::Result<(BlockWitness<GoldilocksField, C, D>, BlockDiff)>
+    where
+        C::Hasher: AlgebraicHasher<GoldilocksField>,
+    {
+        let mut diff = self.diff;
+        let mut world_state_revert_proofs = vec![];
+        let mut latest_account_tree_process_proofs = vec![];
+        for (tx, received_signature) in self.transactions.iter().zip(&self.received_signatures) {
+            let public_inputs = &tx.user_tx_proof.public_inputs;
+            let sender_address = public_inputs.sender_address.0.into();
+            let (last_block_number, confirmed_user_asset_root) = if received_signature.is_none() {
+                let old_block_number = trees.latest_account_tree.get(&sender_address)?;
+                (old_block_number, public_inputs.old_user_asset_root)
+            } else {
+                (
+                    GoldilocksHashOut::from_u32(self.block_number),
+                    public_inputs.new_user_asset_root,
+                )
+            };
+            latest_account_tree_process_proofs.push(diff.set_latest_account(
+                trees,
+                sender_address,
+                last_block_number,
+            )?);
+            world_state_revert_proofs.push(diff.set_user_asset_root(
+                trees,
+                sender_address,
+                confirmed_user_asset_root,
+            )?);
+        }
+
+        // The mints are applied to the approved world state.
+        let approved_world_state_root = trees.world_state_tree.get_root();
+        let mut mints = Vec::with_capacity(self.mint_proofs.len());
+        let mut mint_world_state_process_proofs = Vec::with_capacity(self.mint_proofs.len());
+        for mint_proof in self.mint_proofs.iter() {
+            let mint = MintPublicInputs::decode(&mint_proof.public_inputs)?;
+            mint_world_state_process_proofs.push(diff.set_user_asset_root(
+                trees,
+                mint.issuer_address.0.into(),
+                mint.new_user_asset_root,
+            )?);
+            mints.push(mint);
+        }
+        if let Err(err) = validate_mint_block_witness(
+            &mint_world_state_process_proofs,
+            &mints,
+            self.issuer_registry_root,
+            self.block_number,
+            *approved_world_state_root,
+        ) {
+            diff.revert(trees)?;
+            return Err(err.into());
+        }
+
+        // The contract calls are applied to the world state after the mints.
+        let mut contract_calls = Vec::with_capacity(self.contract_calls.len());
+        for contract_call in self.contract_calls {
+            let contract_address: GoldilocksHashOut = contract_call.contract_address().0.into();
+            let new_leaf = match trees
+                .world_state_tree
+                .get(&contract_address)
+                .and_then(|current_leaf| contract_call.new_leaf(*current_leaf))
+            {
+                Ok(new_leaf) => new_leaf,
+                Err(err) => {
+                    diff.revert(trees)?;
+                    return Err(err);
+                }
+            };
+            let process_proof = diff.world_state.record(
+                &mut trees.world_state_tree,
+                contract_address,
+                new_leaf.into(),
+            )?;
+            contract_calls.push((contract_call, process_proof));
+        }
+
+        let forced_transactions = make_forced_transaction_witnesses(
+            &self.pending_forced_transactions,
+            &self
+                .transactions
+                .iter()
+                .map(|tx| tx.user_tx_proof.public_inputs.clone())
+                .collect::<Vec<_>>(),
+        );
+        let mut user_tx_proofs = Vec::with_capacity(self.transactions.len());
+        let mut world_state_process_proofs = Vec::with_capacity(self.transactions.len());
+        let mut burned_assets = Vec::with_capacity(self.transactions.len());
+        for tx in self.transactions {
+            user_tx_proofs.push(tx.user_tx_proof);
+            world_state_process_proofs.push(tx.world_state_process_proof);
+            burned_assets.push(tx.burned_assets);
+        }
+        let witness = BlockWitness {
+            block_number: self.block_number,
+            user_tx_proofs,
+            deposit_process_proofs,
+            deposit_nonce,
+            world_state_process_proofs,
+            world_state_revert_proofs,
+            received_signatures: self.received_signatures,
+            default_simple_signature,
+            latest_account_tree_process_proofs,
+            block_header_siblings,
+            prev_block_hash,
+            timestamp: self.timestamp,
+            aggregator_address: self.aggregator_address.0,
+            issuer_registry_root: self.issuer_registry_root,
+            old_world_state_root: *self.old_world_state_root,
+            old_forced_transactions_digest: self.old_forced_transactions_digest,
+            forced_transactions,
+            burned_assets,
+            mint_world_state_process_proofs,
+            mint_proofs: self.mint_proofs,
+            default_mint_proof,
+            contract_calls,
+            default_contract_call,
+        };
+        if let Err(err) = witness.validate() {
+            diff.revert(trees)?;
+            return Err(err.into());
+        }
+
+        Ok((witness, diff))
+    }
+}
+
+#[test]
+fn test_incremental_block_builder() {
+    use std::time::Duration;
+
+    use plonky2::iop::witness::PartialWitness;
+
+    use crate::{
+        fixtures::{
+            make_sample_accounts, make_sample_user_tx, make_sample_user_tx_circuit, C, D,
+            N_LOG_ISSUERS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_TXS, N_LOG_MAX_VARIABLES,
+        },
+        rollup::{contract::make_counter_contract_circuit, gadgets::contract::get_contract_leaf},
+        sparse_merkle_tree::goldilocks_poseidon::PoseidonSparseMerkleTree,
+        transaction::circuits::mint::make_mint_circuit,
+        zkdsa::circuits::make_simple_signature_circuit,
+    };
+
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    let mut trees = RollupStateTrees {
+        world_state_tree: user_txs.world_state_tree,
+        latest_account_tree: PoseidonSparseMerkleTree::default(),
+    };
+    let old_world_state_root = trees.world_state_tree.get_root();
+
+    // No transaction is admitted after the deadline.
+    let mut expired_builder = IncrementalBlockBuilder::new(1, &trees, 2, Instant::now());
+    assert!(expired_builder.is_due());
+    assert!(expired_builder
+        .admit(&mut trees, user_txs.proofs[0].clone())
+        .is_err());
+
+    let deadline = Instant::now() + Duration::from_secs(3600);
+
+    // The sample transactions are not ground for the proof of work.
+    let mut pow_builder = IncrementalBlockBuilder::new(1, &trees, 2, deadline)
+        .with_pow_difficulty(MAX_POW_DIFFICULTY)
+        .unwrap();
+    assert!(pow_builder
+        .admit(&mut trees, user_txs.proofs[0].clone())
+        .is_err());
+
+    // The cancelled transactions leave the world state and the slots.
+    let user_asset_root0 = trees
+        .world_state_tree
+        .get(&accounts[0].address.0.into())
+        .unwrap();
+    let mut cancel_builder = IncrementalBlockBuilder::new(1, &trees, 2, deadline);
+    for user_tx_proof in user_txs.proofs[0..2].iter() {
+        cancel_builder
+            .admit(&mut trees, user_tx_proof.clone())
+            .unwrap();
+    }
+    let cancelled = cancel_builder
+        .cancel_transaction(&mut trees, user_txs.proofs[0].public_inputs.tx_hash)
+        .unwrap();
+    assert_eq!(cancelled.slot, 0);
+    assert_eq!(cancel_builder.transactions().len(), 1);
+    let moved = &cancel_builder.transactions()[0];
+    assert_eq!(moved.slot, 0);
+    assert_eq!(
+        moved.world_state_process_proof.old_root,
+        old_world_state_root
+    );
+    assert_eq!(
+        trees
+            .world_state_tree
+            .get(&accounts[0].address.0.into())
+            .unwrap(),
+        user_asset_root0
+    );
+    assert!(cancel_builder
+        .cancel_transaction(&mut trees, user_txs.proofs[0].public_inputs.tx_hash)
+        .is_err());
+    cancel_builder
+        .cancel_transaction(&mut trees, user_txs.proofs[1].public_inputs.tx_hash)
+        .unwrap();
+    assert_eq!(trees.world_state_tree.get_root(), old_world_state_root);
+
+    let aggregator_address = Address(*GoldilocksHashOut::from_u32(7));
+    let issuer_registry_root = *GoldilocksHashOut::from_u32(11);
+    let mut builder = IncrementalBlockBuilder::new(1, &trees, 2, deadline)
+        .with_aggregator_address(aggregator_address)
+        .with_issuer_registry_root(issuer_registry_root);
+    assert_eq!(
+        builder
+            .admit(&mut trees, user_txs.proofs[0].clone())
+            .unwrap(),
+        0
+    );
+    assert!(builder
+        .admit(&mut trees, user_txs.proofs[0].clone())
+        .is_err());
+    assert!(!builder.is_due());
+    assert_eq!(
+        builder
+            .admit(&mut trees, user_txs.proofs[1].clone())
+            .unwrap(),
+        1
+    );
+    assert!(builder.is_due());
+
+    let mut sealed_block = builder.seal();
+    assert_eq!(
+        sealed_block.proposed_world_state_root,
+        trees.world_state_tree.get_root()
+    );
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let sign = |private_key, message| {
+        let mut pw = PartialWitness::new();
+        simple_signature_circuit
+            .targets
+            .set_witness(&mut pw, private_key, message);
+        simple_signature_circuit.prove(pw).unwrap()
+    };
+    assert!(sealed_block
+        .receive_signature(sign(accounts[1].private_key, *old_world_state_root))
+        .is_err());
+    assert_eq!(
+        sealed_block
+            .receive_signature(sign(
+                accounts[0].private_key,
+                *sealed_block.proposed_world_state_root
+            ))
+            .unwrap(),
+        0
+    );
+
+    // The default mint proof is signed for the block 0, so it cannot be included.
+    let mint_circuit = make_mint_circuit::<
+        C,
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >(&simple_signature_circuit.data);
+    let default_mint_proof = mint_circuit
+        .prove_default(&simple_signature_circuit)
+        .unwrap();
+    assert!(sealed_block
+        .receive_mint(default_mint_proof.clone())
+        .is_err());
+
+    // The counter contract is deployed after the approval.
+    let contract_circuit = make_counter_contract_circuit::<GoldilocksField, C, D>();
+    let default_contract_call = contract_circuit.call_default().unwrap();
+    let deploy = contract_circuit
+        .call(HashOut::ZERO, *GoldilocksHashOut::from_u32(13))
+        .unwrap();
+    assert_eq!(
+        sealed_block.receive_contract_call(deploy.clone()).unwrap(),
+        0
+    );
+
+    // The second sender does not sign, so its user asset root is reverted.
+    let default_simple_signature = sign(Default::default(), Default::default());
+    let (witness, diff) = sealed_block
+        .finalize(
+            &mut trees,
+            vec![],
+            HashOut::default(),
+            default_simple_signature,
+            default_mint_proof,
+            default_contract_call,
+            vec![HashOut::default(); 32],
+            HashOut::default(),
+        )
+        .unwrap();
+    assert_eq!(witness.user_tx_proofs.len(), 2);
+    assert_eq!(witness.aggregator_address, aggregator_address.0);
+    assert_eq!(witness.issuer_registry_root, issuer_registry_root);
+    assert_ne!(witness.timestamp, 0);
+    assert!(witness.received_signatures[0].is_some());
+    assert!(witness.received_signatures[1].is_none());
+    assert_eq!(
+        trees
+            .world_state_tree
+            .get(&accounts[1].address.0.into())
+            .unwrap(),
+        user_txs.proofs[1].public_inputs.old_user_asset_root
+    );
+    assert_eq!(
+        trees
+            .latest_account_tree
+            .get(&accounts[0].address.0.into())
+            .unwrap(),
+        GoldilocksHashOut::from_u32(1)
+    );
+    assert_eq!(witness.contract_calls.len(), 1);
+    assert_eq!(
+        *trees
+            .world_state_tree
+            .get(&deploy.contract_address().0.into())
+            .unwrap(),
+        get_contract_leaf(
+            deploy.verifier_digest(),
+            deploy.public_inputs().unwrap().new_state_root
+        )
+    );
+
+    diff.revert(&mut trees).unwrap();
+    assert_eq!(trees.world_state_tree.get_root(), old_world_state_root);
+}
@@ -0,0 +1,251 @@
+//! The changes a block makes to the world state tree and the latest account tree.
+//!
+//! A `BlockDiff` is recorded while the block is built and can be applied to or reverted from
+//! any copy of the trees, e.g. to roll back an orphaned block or to sync a replica.
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use serde::{Deserialize, Serialize};
+
+use crate::sparse_merkle_tree::{
+    gadgets::process::process_smt::SmtProcessProof,
+    goldilocks_poseidon::{GoldilocksHashOut, PoseidonSparseMerkleTree},
+    node_data::NodeData,
+};
+
+type F = GoldilocksField;
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+/// The value of `key` changed from `old_value` to `new_value`.
+/// The default value means that the leaf does not exist.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeafChange {
+    pub key: GoldilocksHashOut,
+    pub old_value: GoldilocksHashOut,
+    pub new_value: GoldilocksHashOut,
+}
+
+/// The changes of one tree in the order they were made.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeDiff {
+    pub old_root: GoldilocksHashOut,
+    pub new_root: GoldilocksHashOut,
+    pub changes: Vec<LeafChange>,
+}
+
+impl TreeDiff {
+    pub fn with_root(root: GoldilocksHashOut) -> Self {
+        Self {
+            old_root: root,
+            new_root: root,
+            changes: vec![],
+        }
+    }
+
+    /// Sets `key` to `new_value` in `tree` and records the change.
+    pub fn record<D: NodeData<K, V, I>>(
+        &mut self,
+        tree: &mut PoseidonSparseMerkleTree<D>,
+        key: GoldilocksHashOut,
+        new_value: GoldilocksHashOut,
+    ) -> anyhow::Result<SmtProcessProof<F>> {
+        anyhow::ensure!(
+            tree.get_root() == self.new_root,
+            "the tree root does not match the diff"
+        );
+        let old_value = tree.get(&key)?;
+        let proof = tree.set(key, new_value)?;
+        self.changes.push(LeafChange {
+            key,
+            old_value,
+            new_value,
+        });
+        self.new_root = proof.new_root;
+
+        Ok(proof)
+    }
+
+    pub fn apply<D: NodeData<K, V, I>>(
+        &self,
+        tree: &mut PoseidonSparseMerkleTree<D>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            tree.get_root() == self.old_root,
+            "the tree root does not match the old root of the diff"
+        );
+        for change in self.changes.iter() {
+            tree.set(change.key, change.new_value)?;
+        }
+        anyhow::ensure!(
+            tree.get_root() == self.new_root,
+            "the tree root does not match the new root of the diff after applying it"
+        );
+
+        Ok(())
+    }
+
+    pub fn revert<D: NodeData<K, V, I>>(
+        &self,
+        tree: &mut PoseidonSparseMerkleTree<D>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            tree.get_root() == self.new_root,
+            "the tree root does not match the new root of the diff"
+        );
+        for change in self.changes.iter().rev() {
+            tree.set(change.key, change.old_value)?;
+        }
+        anyhow::ensure!(
+            tree.get_root() == self.old_root,
+            "the tree root does not match the old root of the diff after reverting it"
+        );
+
+        Ok(())
+    }
+}
+
+/// The trees a block updates.
+#[derive(Debug)]
+pub struct RollupStateTrees<D: NodeData<K, V, I>> {
+    pub world_state_tree: PoseidonSparseMerkleTree<D>,
+    pub latest_account_tree: PoseidonSparseMerkleTree<D>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockDiff {
+    pub block_number: u32,
+    pub world_state: TreeDiff,
+    pub latest_account: TreeDiff,
+}
+
+impl BlockDiff {
+    /// An empty diff of the block `block_number` starting from the current roots of `trees`.
+    pub fn new<D: NodeData<K, V, I>>(block_number: u32, trees: &RollupStateTrees<D>) -> Self {
+        Self {
+            block_number,
+            world_state: TreeDiff::with_root(trees.world_state_tree.get_root()),
+            latest_account: TreeDiff::with_root(trees.latest_account_tree.get_root()),
+        }
+    }
+
+    /// Updates the user asset root of `address` and records the change.
+    pub fn set_user_asset_root<D: NodeData<K, V, I>>(
+        &mut self,
+        trees: &mut RollupStateTrees<D>,
+        address: GoldilocksHashOut,
+        user_asset_root: GoldilocksHashOut,
+    ) -> anyhow::Result<SmtProcessProof<F>> {
+        self.world_state
+            .record(&mut trees.world_state_tree, address, user_asset_root)
+    }
+
+    /// Updates the latest block number of `address` and records the change.
+    pub fn set_latest_account<D: NodeData<K, V, I>>(
+        &mut self,
+        trees: &mut RollupStateTrees<D>,
+        address: GoldilocksHashOut,
+        latest_block_number: GoldilocksHashOut,
+    ) -> anyhow::Result<SmtProcessProof<F>> {
+        self.latest_account
+            .record(&mut trees.latest_account_tree, address, latest_block_number)
+    }
+
+    pub fn apply<D: NodeData<K, V, I>>(
+        &self,
+        trees: &mut RollupStateTrees<D>,
+    ) -> anyhow::Result<()> {
+        self.world_state.apply(&mut trees.world_state_tree)?;
+        if let Err(err) = self.latest_account.apply(&mut trees.latest_account_tree) {
+            // Leave the trees unchanged.
+            self.world_state.revert(&mut trees.world_state_tree)?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    pub fn revert<D: NodeData<K, V, I>>(
+        &self,
+        trees: &mut RollupStateTrees<D>,
+    ) -> anyhow::Result<()> {
+        self.latest_account.revert(&mut trees.latest_account_tree)?;
+        if let Err(err) = self.world_state.revert(&mut trees.world_state_tree) {
+            self.latest_account.apply(&mut trees.latest_account_tree)?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// The addresses whose user asset root or latest block number is changed, without duplicates.
+    pub fn touched_addresses(&self) -> Vec<GoldilocksHashOut> {
+        let mut addresses: Vec<GoldilocksHashOut> = vec![];
+        for change in self
+            .world_state
+            .changes
+            .iter()
+            .chain(self.latest_account.changes.iter())
+        {
+            if !addresses.contains(&change.key) {
+                addresses.push(change.key);
+            }
+        }
+
+        addresses
+    }
+}
+
+#[test]
+fn test_block_diff() {
+    use crate::sparse_merkle_tree::goldilocks_poseidon::NodeDataMemory;
+
+    let mut trees = RollupStateTrees::<NodeDataMemory> {
+        world_state_tree: PoseidonSparseMerkleTree::default(),
+        latest_account_tree: PoseidonSparseMerkleTree::default(),
+    };
+    let address1 = GoldilocksHashOut::from_u32(1);
+    let address2 = GoldilocksHashOut::from_u32(2);
+    trees
+        .world_state_tree
+        .set(address1, GoldilocksHashOut::from_u32(10))
+        .unwrap();
+    let old_world_state_root = trees.world_state_tree.get_root();
+    let old_latest_account_root = trees.latest_account_tree.get_root();
+
+    let mut diff = BlockDiff::new(1, &trees);
+    diff.set_user_asset_root(&mut trees, address1, GoldilocksHashOut::from_u32(11))
+        .unwrap();
+    diff.set_user_asset_root(&mut trees, address2, GoldilocksHashOut::from_u32(20))
+        .unwrap();
+    diff.set_latest_account(&mut trees, address1, GoldilocksHashOut::from_u32(1))
+        .unwrap();
+    assert_eq!(diff.touched_addresses(), vec![address1, address2]);
+    let new_world_state_root = trees.world_state_tree.get_root();
+    let new_latest_account_root = trees.latest_account_tree.get_root();
+
+    let encoded = serde_json::to_string(&diff).unwrap();
+    let decoded: BlockDiff = serde_json::from_str(&encoded).unwrap();
+    assert_eq!(decoded, diff);
+
+    decoded.revert(&mut trees).unwrap();
+    assert_eq!(trees.world_state_tree.get_root(), old_world_state_root);
+    assert_eq!(
+        trees.latest_account_tree.get_root(),
+        old_latest_account_root
+    );
+    assert_eq!(
+        trees.world_state_tree.get(&address1).unwrap(),
+        GoldilocksHashOut::from_u32(10)
+    );
+
+    // The diff cannot be reverted twice.
+    assert!(decoded.revert(&mut trees).is_err());
+
+    decoded.apply(&mut trees).unwrap();
+    assert_eq!(trees.world_state_tree.get_root(), new_world_state_root);
+    assert_eq!(
+        trees.latest_account_tree.get_root(),
+        new_latest_account_root
+    );
+}
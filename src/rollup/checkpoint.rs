@@ -0,0 +1,195 @@
+//! Light-client checkpoints.
+//!
+//! The checkpoint circuit proves that `N_HEADERS` block headers are consecutive,
+//! so that a light client which trusts the block `start_block_hash` can accept the block `end_block_hash`
+//! by verifying one proof instead of checking every block header.
+
+use plonky2::{
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
+    hash::hash_types::{HashOut, RichField},
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::IntmaxError,
+    rollup::gadgets::header_chain::{HeaderChainTarget, HeaderChainWitness},
+};
+
+pub const CHECKPOINT_PUBLIC_INPUTS_LEN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointPublicInputs<F: Field> {
+    pub start_block_number: u32,
+    pub start_block_hash: HashOut<F>,
+    pub end_block_number: u32,
+    pub end_block_hash: HashOut<F>,
+}
+
+impl<F: PrimeField64> CheckpointPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = vec![F::from_canonical_u32(self.start_block_number)];
+        public_inputs.extend(self.start_block_hash.elements);
+        public_inputs.push(F::from_canonical_u32(self.end_block_number));
+        public_inputs.extend(self.end_block_hash.elements);
+
+        public_inputs
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != CHECKPOINT_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: CHECKPOINT_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        // The block numbers are range-checked to 32 bits in the circuit.
+        Ok(Self {
+            start_block_number: public_inputs[0].to_canonical_u64() as u32,
+            start_block_hash: HashOut::from_partial(&public_inputs[1..5]),
+            end_block_number: public_inputs[5].to_canonical_u64() as u32,
+            end_block_hash: HashOut::from_partial(&public_inputs[6..10]),
+        })
+    }
+}
+
+pub struct CheckpointCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_BLOCKS: usize,
+    const N_HEADERS: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub targets: HeaderChainTarget<N_LOG_MAX_BLOCKS, N_HEADERS>,
+}
+
+pub fn make_checkpoint_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+    const N_LOG_MAX_BLOCKS: usize,
+    const N_HEADERS: usize,
+>() -> CheckpointCircuit<F, C, D, N_LOG_MAX_BLOCKS, N_HEADERS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets = HeaderChainTarget::add_virtual_to::<F, C::Hasher, D>(&mut builder);
+    builder.register_public_input(targets.start_block_number); // public_inputs[0]
+    builder.register_public_inputs(&targets.start_block_hash.elements); // public_inputs[1..5]
+    builder.register_public_input(targets.end_block_number); // public_inputs[5]
+    builder.register_public_inputs(&targets.end_block_hash.elements); // public_inputs[6..10]
+    let data = builder.build::<C>();
+
+    CheckpointCircuit { data, targets }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_BLOCKS: usize,
+        const N_HEADERS: usize,
+    > CheckpointCircuit<F, C, D, N_LOG_MAX_BLOCKS, N_HEADERS>
+{
+    pub fn prove(
+        &self,
+        witness: &HeaderChainWitness<F>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.targets.set_witness(&mut pw, witness)?;
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<CheckpointPublicInputs<F>> {
+        let public_inputs = CheckpointPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_checkpoint_circuit() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::goldilocks_poseidon::{GoldilocksHashOut, WrappedHashOut},
+        transaction::block_header::{get_block_hash, BlockHeader},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+    const N_LOG_MAX_BLOCKS: usize = 32;
+    const N_HEADERS: usize = 3;
+
+    // The blocks 0..5, each of which commits to the hashes of the previous blocks.
+    let mut block_hashes: Vec<WrappedHashOut<F>> = vec![];
+    let mut block_headers = vec![];
+    for block_number in 0..5 {
+        let prev_block_header_digest = get_merkle_proof(
+            &[block_hashes.clone(), vec![Default::default()]].concat(),
+            block_hashes.len(),
+            N_LOG_MAX_BLOCKS,
+        )
+        .root;
+        let block_header = BlockHeader {
+            block_number,
+            prev_block_header_digest: *prev_block_header_digest,
+            transactions_digest: *GoldilocksHashOut::from_u32(10 + block_number),
+            ..BlockHeader::with_tree_depth(2)
+        };
+        block_hashes.push(get_block_hash(&block_header).into());
+        block_headers.push(block_header);
+    }
+    let block_hashes = block_hashes.iter().map(|v| **v).collect::<Vec<_>>();
+
+    let witness = HeaderChainWitness::new(
+        &block_hashes[0..2],
+        block_headers[2..5].to_vec(),
+        N_LOG_MAX_BLOCKS,
+    )
+    .unwrap();
+    let circuit = make_checkpoint_circuit::<F, C, D, N_LOG_MAX_BLOCKS, N_HEADERS>();
+    let proof = circuit.prove(&witness).unwrap();
+    let public_inputs = circuit.verify(proof).unwrap();
+    assert_eq!(
+        public_inputs,
+        CheckpointPublicInputs {
+            start_block_number: 2,
+            start_block_hash: block_hashes[2],
+            end_block_number: 4,
+            end_block_hash: block_hashes[4],
+        }
+    );
+
+    // A gap in the chain.
+    let mut invalid_block_headers = block_headers[2..5].to_vec();
+    invalid_block_headers[1] = block_headers[1].clone();
+    assert!(
+        HeaderChainWitness::new(&block_hashes[0..2], invalid_block_headers, N_LOG_MAX_BLOCKS)
+            .is_err()
+    );
+    let mut invalid_witness = witness;
+    invalid_witness.block_headers[1].transactions_digest = HashOut::ZERO;
+    assert!(circuit.prove(&invalid_witness).is_err());
+}
@@ -0,0 +1,149 @@
+//! A single-hash commitment of the block public inputs.
+//!
+//! The public inputs of the block circuit grow with `N_TXS`, `N_DEPOSITS` and every digest
+//! added to the block header. The block commitment circuit verifies a block proof recursively
+//! and exposes only `BlockPublicInputs::commitment` of it with the protocol version,
+//! so recursive circuits and the L1 verifier consume a fixed number of public inputs
+//! and open the commitment with the structured public inputs posted next to the proof.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    recursion::gadgets::RecursiveProofTarget,
+    verification::{
+        public_inputs::{protocol_version, BlockCommitmentPublicInputs},
+        verifier::BlockCommitmentVerifier,
+    },
+};
+
+pub struct BlockCommitmentCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub block_proof: RecursiveProofTarget<D>,
+}
+
+/// `block_circuit_data` is the data of a block circuit, whose last public input is the protocol version.
+/// The resulting circuit depends on `N_TXS` and `N_DEPOSITS` only through the verifier data.
+pub fn make_block_commitment_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+>(
+    block_circuit_data: &CircuitData<F, C, D>,
+) -> BlockCommitmentCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let block_proof = RecursiveProofTarget::add_virtual_to(&mut builder, block_circuit_data);
+    let constant_true = builder._true();
+    builder.connect(block_proof.enabled.target, constant_true.target);
+
+    let (version, block_public_inputs) = block_proof
+        .inner
+        .public_inputs
+        .split_last()
+        .expect("the block circuit must have public inputs");
+    let commitment = builder.hash_n_to_hash_no_pad::<C::Hasher>(block_public_inputs.to_vec());
+    let expected_version = builder.constant(protocol_version());
+    builder.connect(*version, expected_version);
+
+    builder.register_public_inputs(&commitment.elements);
+    builder.register_public_input(expected_version);
+    let data = builder.build::<C>();
+
+    BlockCommitmentCircuit { data, block_proof }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    BlockCommitmentCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    /// Proves the commitment of `block_proof`, e.g. converted from `ProposalAndApprovalBlockProofWithPublicInputs`.
+    pub fn prove(
+        &self,
+        block_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.block_proof.set_witness(&mut pw, block_proof, true);
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<BlockCommitmentPublicInputs<F>> {
+        let public_inputs = BlockCommitmentPublicInputs::try_decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+
+    /// Returns the verifier without the prover data.
+    pub fn verifier(&self) -> BlockCommitmentVerifier<F, C, D> {
+        BlockCommitmentVerifier {
+            verifier_only: self.data.verifier_only.clone(),
+            common: self.data.common.clone(),
+        }
+    }
+}
+
+#[test]
+fn test_block_commitment_circuit() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        hash::{hash_types::HashOut, poseidon::PoseidonHash},
+        iop::witness::Witness,
+        plonk::config::{Hasher, PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    // A stand-in for the block circuit with the same trailing protocol version.
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let block_hash = builder.add_virtual_hash();
+    let digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(block_hash.elements.to_vec());
+    builder.register_public_inputs(&block_hash.elements);
+    builder.register_public_inputs(&digest.elements);
+    let version = builder.constant(protocol_version());
+    builder.register_public_input(version);
+    let block_circuit_data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(block_hash, HashOut::rand());
+    let block_proof = block_circuit_data.prove(pw).unwrap();
+
+    let circuit = make_block_commitment_circuit(&block_circuit_data);
+    let proof = circuit.prove(&block_proof).unwrap();
+    let public_inputs = circuit.verify(proof.clone()).unwrap();
+    let (_, block_public_inputs) = block_proof.public_inputs.split_last().unwrap();
+    assert_eq!(
+        public_inputs.commitment,
+        PoseidonHash::hash_no_pad(block_public_inputs)
+    );
+
+    let verified = circuit
+        .verifier()
+        .verify(&proof.to_bytes().unwrap())
+        .unwrap();
+    assert_eq!(verified, public_inputs);
+}
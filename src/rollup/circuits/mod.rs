@@ -1,32 +1,69 @@
+pub mod commitment;
+pub mod registry;
+
+use std::time::Instant;
+
 use itertools::Itertools;
 use plonky2::{
     field::extension::Extendable,
-    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
     iop::{
         target::{BoolTarget, Target},
         witness::{PartialWitness, Witness},
     },
     plonk::{
         circuit_builder::CircuitBuilder,
-        circuit_data::{CircuitConfig, CircuitData},
-        config::{AlgebraicHasher, GenericConfig},
+        circuit_data::{CircuitConfig, CircuitData, CommonCircuitData},
+        config::{AlgebraicHasher, GenericConfig, Hasher},
         proof::{Proof, ProofWithPublicInputs},
     },
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    errors::{ensure_at_most, ensure_length, ensure_witness, IntmaxError},
     merkle_tree::gadgets::{get_merkle_root_target, MerkleProofTarget},
-    rollup::gadgets::{
-        approval_block::ApprovalBlockProofTarget,
-        deposit_block::{DepositBlockProofTarget, DepositInfo, DepositInfoTarget},
-        proposal_block::ProposalBlockProofTarget,
+    monitoring,
+    rollup::{
+        contract::ContractCall,
+        gadgets::{
+            approval_block::{validate_approval_witness, ApprovalBlockProofTarget},
+            contract::ContractCallTarget,
+            deposit_block::{DepositBlockProofTarget, DepositInfo, DepositInfoTarget},
+            forced_inclusion::{
+                validate_forced_transactions, BlockTransactionTarget, ForcedInclusionProofTarget,
+                ForcedTransactionWitness,
+            },
+            mint_block::MintBlockProofTarget,
+            proposal_block::ProposalBlockProofTarget,
+            withdrawal::{
+                validate_burned_assets, BurnTransactionTarget, WithdrawalBlockProofTarget,
+            },
+        },
     },
     sparse_merkle_tree::{
-        gadgets::process::process_smt::SmtProcessProof, goldilocks_poseidon::WrappedHashOut,
+        gadgets::process::process_smt::SmtProcessProof, goldilocks_poseidon::hash_out_hex,
     },
     transaction::{
-        circuits::{MergeAndPurgeTransitionCircuit, MergeAndPurgeTransitionProofWithPublicInputs},
-        gadgets::block_header::{get_block_hash_target, BlockHeaderTarget},
+        asset::Asset,
+        block_header::N_LOG_MAX_TIMESTAMP,
+        burn::get_block_burned_assets_digest_target,
+        circuits::{
+            MergeAndPurgeTransitionCircuit, MergeAndPurgeTransitionProofWithPublicInputs,
+            MergeAndPurgeTransitionPublicInputs, BURNED_ASSETS_DIGEST_OFFSET,
+            SENDER_ADDRESS_OFFSET, TX_HASH_OFFSET,
+        },
+        gadgets::{
+            block_header::{get_block_hash_target, BlockHeaderTarget},
+            htlc::{validate_htlc_spend_witness, HtlcSpendTarget, HtlcSpendWitness},
+        },
+    },
+    verification::{
+        public_inputs::{protocol_version, BlockPublicInputs, PROTOCOL_VERSION},
+        verifier::BlockVerifier,
     },
     zkdsa::{
         account::Address,
@@ -46,9 +83,19 @@ use super::{
 // const D: usize = 2;
 const N_LOG_MAX_BLOCKS: usize = 32;
 
+/// The maximum number of forced transactions a block processes from the L1 queue.
+pub const N_FORCED_TXS: usize = 2;
+
+/// The maximum number of mints a block applies to the world state.
+pub const N_MINTS: usize = 2;
+
+/// The maximum number of contract calls a block applies to the world state.
+pub const N_CONTRACT_CALLS: usize = 2;
+
 pub struct OneBlockProofTarget<
     const D: usize,
     const N_LOG_USERS: usize, // N_LOG_MAX_USERS
+    const N_LOG_MAX_TXS: usize,
     const N_LOG_TXS: usize,
     const N_LOG_RECIPIENTS: usize,
     const N_LOG_CONTRACTS: usize,
@@ -56,10 +103,21 @@ pub struct OneBlockProofTarget<
     const N_TXS: usize,
     const N_DEPOSITS: usize,
 > {
-    pub deposit_block_target:
-        DepositBlockProofTarget<D, N_LOG_RECIPIENTS, N_LOG_CONTRACTS, N_LOG_VARIABLES, N_DEPOSITS>,
+    pub deposit_block_target: DepositBlockProofTarget<
+        D,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DEPOSITS,
+    >,
     pub proposal_block_target: ProposalBlockProofTarget<D, N_LOG_USERS, N_TXS>,
     pub approval_block_target: ApprovalBlockProofTarget<D, N_LOG_USERS, N_TXS>,
+    pub forced_inclusion_target: ForcedInclusionProofTarget<N_LOG_USERS, N_FORCED_TXS>,
+    pub htlc_spend_target: HtlcSpendTarget<N_LOG_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>,
+    pub mint_block_target: MintBlockProofTarget<D, N_LOG_USERS, N_MINTS>,
+    pub contract_calls: Vec<ContractCallTarget<D, N_LOG_USERS>>,
+    pub withdrawal_block_target: WithdrawalBlockProofTarget,
     pub block_number: Target,
     pub prev_block_header_proof: MerkleProofTarget<N_LOG_MAX_BLOCKS>,
     pub prev_block_hash: HashOutTarget,
@@ -69,6 +127,7 @@ pub struct OneBlockProofTarget<
 impl<
         const D: usize,
         const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
         const N_LOG_TXS: usize,
         const N_LOG_RECIPIENTS: usize,
         const N_LOG_CONTRACTS: usize,
@@ -79,6 +138,7 @@ impl<
     OneBlockProofTarget<
         D,
         N_LOG_USERS,
+        N_LOG_MAX_TXS,
         N_LOG_TXS,
         N_LOG_RECIPIENTS,
         N_LOG_CONTRACTS,
@@ -94,6 +154,7 @@ impl<
         block_number: u32,
         user_tx_proofs: &[MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>],
         deposit_process_proofs: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        deposit_nonce: HashOut<F>,
         world_state_process_proofs: &[SmtProcessProof<F>],
         world_state_revert_proofs: &[SmtProcessProof<F>],
         received_signatures: &[Option<SimpleSignatureProofWithPublicInputs<F, C, D>>],
@@ -101,12 +162,28 @@ impl<
         latest_account_tree_process_proofs: &[SmtProcessProof<F>],
         block_header_siblings: &[HashOut<F>],
         prev_block_hash: HashOut<F>,
+        timestamp: u64,
+        aggregator_address: HashOut<F>,
+        issuer_registry_root: HashOut<F>,
         old_world_state_root: HashOut<F>,
-    ) where
+        old_forced_transactions_digest: HashOut<F>,
+        forced_transactions: &[ForcedTransactionWitness<F>],
+        burned_assets: &[Vec<Asset<F>>],
+        mint_world_state_process_proofs: &[SmtProcessProof<F>],
+        mint_proofs: &[ProofWithPublicInputs<F, C, D>],
+        default_mint_proof: &ProofWithPublicInputs<F, C, D>,
+        contract_calls: &[(ContractCall<F, C, D>, SmtProcessProof<F>)],
+        default_contract_call: &ContractCall<F, C, D>,
+        htlc_spend: Option<&HtlcSpendWitness<F>>,
+    ) -> Result<(), IntmaxError>
+    where
         C::Hasher: AlgebraicHasher<F>,
     {
-        self.deposit_block_target
-            .set_witness::<F, C::Hasher>(pw, deposit_process_proofs);
+        self.deposit_block_target.set_witness::<F, C::Hasher>(
+            pw,
+            deposit_process_proofs,
+            deposit_nonce,
+        )?;
         self.proposal_block_target.set_witness(
             pw,
             world_state_process_proofs,
@@ -115,23 +192,378 @@ impl<
                 .map(|p| ProofWithPublicInputs::from(p.clone()))
                 .collect::<Vec<_>>(),
             old_world_state_root,
-        );
+        )?;
+        let user_transactions = user_tx_proofs
+            .iter()
+            .map(|p| p.public_inputs.clone())
+            .collect::<Vec<_>>();
         self.approval_block_target.set_witness(
             pw,
             block_number,
             world_state_revert_proofs,
-            &user_tx_proofs
-                .iter()
-                .map(|p| p.public_inputs.clone())
-                .collect::<Vec<_>>(),
+            &user_transactions,
             &received_signatures
                 .iter()
                 .map(|p| p.clone().map(ProofWithPublicInputs::from))
                 .collect::<Vec<_>>(),
             &ProofWithPublicInputs::from(default_simple_signature.clone()),
             latest_account_tree_process_proofs,
-        );
+        )?;
+        validate_forced_transactions(
+            old_world_state_root,
+            &user_transactions,
+            forced_transactions,
+            N_FORCED_TXS,
+        )?;
+        self.forced_inclusion_target.set_witness(
+            pw,
+            old_forced_transactions_digest,
+            forced_transactions,
+        )?;
+        let htlc_world_state_root =
+            self.set_htlc_spend_witness(pw, block_number, world_state_revert_proofs, htlc_spend)?;
+        let mint_world_state_root = self.set_mint_block_witness(
+            pw,
+            block_number,
+            issuer_registry_root,
+            htlc_world_state_root,
+            mint_world_state_process_proofs,
+            mint_proofs,
+            default_mint_proof,
+        )?;
+        self.set_contract_calls_witness(
+            pw,
+            mint_world_state_root,
+            contract_calls,
+            default_contract_call,
+        )?;
+        self.set_withdrawal_witness(pw, &user_transactions, burned_assets)?;
+
+        self.set_block_header_witness(
+            pw,
+            block_number,
+            block_header_siblings,
+            prev_block_hash,
+            timestamp,
+            aggregator_address,
+            issuer_registry_root,
+        )?;
+
+        // let address_list = make_address_list(user_tx_proofs, received_signatures, N_TXS);
+
+        // ProposalAndApprovalBlockPublicInputs {
+        //     address_list,
+        //     deposit_list: todo!(),
+        //     old_account_tree_root: todo!(),
+        //     new_account_tree_root: todo!(),
+        //     old_world_state_root,
+        //     new_world_state_root: todo!(),
+        //     old_prev_block_header_digest: todo!(),
+        //     new_prev_block_header_digest: todo!(),
+        //     block_hash: todo!(),
+        // }
+
+        Ok(())
+    }
+
+    /// Sets the same witness as `set_witness`, but user transaction proofs and received
+    /// signatures are pulled from iterators one by one and dropped as soon as they are written into `pw`.
+    ///
+    /// Inner proofs never need to be held in memory all at once, so apart from `pw` itself,
+    /// at most two user transaction proofs (the current one and the last one, which is reused for padding)
+    /// and one received signature are alive at any time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_witness_streaming<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        block_number: u32,
+        user_tx_proofs: impl IntoIterator<Item = MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>,
+        deposit_process_proofs: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        deposit_nonce: HashOut<F>,
+        world_state_process_proofs: &[SmtProcessProof<F>],
+        world_state_revert_proofs: &[SmtProcessProof<F>],
+        received_signatures: impl IntoIterator<
+            Item = Option<SimpleSignatureProofWithPublicInputs<F, C, D>>,
+        >,
+        default_simple_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+        latest_account_tree_process_proofs: &[SmtProcessProof<F>],
+        block_header_siblings: &[HashOut<F>],
+        prev_block_hash: HashOut<F>,
+        timestamp: u64,
+        aggregator_address: HashOut<F>,
+        issuer_registry_root: HashOut<F>,
+        old_world_state_root: HashOut<F>,
+        old_forced_transactions_digest: HashOut<F>,
+        forced_transactions: &[ForcedTransactionWitness<F>],
+        burned_assets: &[Vec<Asset<F>>],
+        mint_world_state_process_proofs: &[SmtProcessProof<F>],
+        mint_proofs: &[ProofWithPublicInputs<F, C, D>],
+        default_mint_proof: &ProofWithPublicInputs<F, C, D>,
+        contract_calls: &[(ContractCall<F, C, D>, SmtProcessProof<F>)],
+        default_contract_call: &ContractCall<F, C, D>,
+        htlc_spend: Option<&HtlcSpendWitness<F>>,
+    ) -> Result<(), IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        self.deposit_block_target.set_witness::<F, C::Hasher>(
+            pw,
+            deposit_process_proofs,
+            deposit_nonce,
+        )?;
+        self.proposal_block_target.set_world_state_witness(
+            pw,
+            world_state_process_proofs,
+            old_world_state_root,
+        )?;
+
+        // user tx proof は public inputs だけを残して破棄する.
+        let mut user_transactions = Vec::with_capacity(N_TXS);
+        let mut last_user_tx_proof = None;
+        for user_tx_proof in user_tx_proofs {
+            let index = user_transactions.len();
+            ensure_at_most("user transaction proofs", index + 1, N_TXS)?;
+            user_transactions.push(user_tx_proof.public_inputs.clone());
+            let user_tx_proof = ProofWithPublicInputs::from(user_tx_proof);
+            self.proposal_block_target
+                .set_user_tx_proof_witness(pw, index, &user_tx_proof, true);
+            last_user_tx_proof = Some(user_tx_proof);
+        }
+
+        let last_user_tx_proof = last_user_tx_proof.ok_or(IntmaxError::EmptyWitness {
+            name: "user transaction proofs",
+        })?;
+        for index in user_transactions.len()..N_TXS {
+            self.proposal_block_target.set_user_tx_proof_witness(
+                pw,
+                index,
+                &last_user_tx_proof,
+                false,
+            );
+        }
+        drop(last_user_tx_proof);
+
+        self.approval_block_target.set_transition_witness(
+            pw,
+            block_number,
+            world_state_revert_proofs,
+            &user_transactions,
+            latest_account_tree_process_proofs,
+        )?;
+
+        let default_simple_signature =
+            ProofWithPublicInputs::from(default_simple_signature.clone());
+        let mut received_signers = Vec::with_capacity(user_transactions.len());
+        for received_signature in received_signatures {
+            let index = received_signers.len();
+            ensure_at_most("received signatures", index + 1, user_transactions.len())?;
+            received_signers.push(
+                received_signature
+                    .as_ref()
+                    .map(|r| r.public_inputs.public_key),
+            );
+            let received_signature = received_signature.map(ProofWithPublicInputs::from);
+            self.approval_block_target.set_received_signature_witness(
+                pw,
+                index,
+                received_signature.as_ref(),
+                &default_simple_signature,
+            );
+        }
+        validate_approval_witness(
+            block_number,
+            world_state_revert_proofs,
+            &user_transactions,
+            &received_signers,
+            latest_account_tree_process_proofs,
+        )?;
+        for index in received_signers.len()..N_TXS {
+            self.approval_block_target.set_received_signature_witness(
+                pw,
+                index,
+                None,
+                &default_simple_signature,
+            );
+        }
+        validate_forced_transactions(
+            old_world_state_root,
+            &user_transactions,
+            forced_transactions,
+            N_FORCED_TXS,
+        )?;
+        self.forced_inclusion_target.set_witness(
+            pw,
+            old_forced_transactions_digest,
+            forced_transactions,
+        )?;
+        let htlc_world_state_root =
+            self.set_htlc_spend_witness(pw, block_number, world_state_revert_proofs, htlc_spend)?;
+        let mint_world_state_root = self.set_mint_block_witness(
+            pw,
+            block_number,
+            issuer_registry_root,
+            htlc_world_state_root,
+            mint_world_state_process_proofs,
+            mint_proofs,
+            default_mint_proof,
+        )?;
+        self.set_contract_calls_witness(
+            pw,
+            mint_world_state_root,
+            contract_calls,
+            default_contract_call,
+        )?;
+        self.set_withdrawal_witness(pw, &user_transactions, burned_assets)?;
 
+        self.set_block_header_witness(
+            pw,
+            block_number,
+            block_header_siblings,
+            prev_block_hash,
+            timestamp,
+            aggregator_address,
+            issuer_registry_root,
+        )?;
+
+        Ok(())
+    }
+
+    /// `burned_assets[i]` must be the assets burned by the `i`-th user transaction.
+    fn set_withdrawal_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        user_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+        burned_assets: &[Vec<Asset<F>>],
+    ) -> Result<(), IntmaxError> {
+        validate_burned_assets(
+            &user_transactions
+                .iter()
+                .map(|u| *u.burned_assets_digest)
+                .collect::<Vec<_>>(),
+            burned_assets,
+        )?;
+
+        self.withdrawal_block_target.set_witness(pw, burned_assets)
+    }
+
+    /// The spend is applied to the world state approved by `world_state_revert_proofs`.
+    /// Returns the world state root after the spend.
+    fn set_htlc_spend_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        block_number: u32,
+        world_state_revert_proofs: &[SmtProcessProof<F>],
+        htlc_spend: Option<&HtlcSpendWitness<F>>,
+    ) -> Result<HashOut<F>, IntmaxError> {
+        let new_world_state_root =
+            validate_block_htlc_spend(block_number, world_state_revert_proofs, htlc_spend)?;
+        if let Some(htlc_spend) = htlc_spend {
+            self.htlc_spend_target.set_witness(pw, htlc_spend)?;
+        } else {
+            self.htlc_spend_target
+                .set_disabled_witness(pw, new_world_state_root)?;
+        }
+
+        Ok(new_world_state_root)
+    }
+
+    /// The mints are applied to the world state after the HTLC spend.
+    /// Returns the world state root after the mints.
+    #[allow(clippy::too_many_arguments)]
+    fn set_mint_block_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        block_number: u32,
+        issuer_registry_root: HashOut<F>,
+        old_world_state_root: HashOut<F>,
+        world_state_process_proofs: &[SmtProcessProof<F>],
+        mint_proofs: &[ProofWithPublicInputs<F, C, D>],
+        default_mint_proof: &ProofWithPublicInputs<F, C, D>,
+    ) -> Result<HashOut<F>, IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        if let Some(first) = world_state_process_proofs.first() {
+            ensure_witness!(
+                *first.old_root == old_world_state_root,
+                "the mints must start from the world state root after the HTLC spend"
+            );
+        }
+
+        self.mint_block_target.set_witness(
+            pw,
+            world_state_process_proofs,
+            mint_proofs,
+            default_mint_proof,
+            issuer_registry_root,
+            block_number,
+            old_world_state_root,
+        )?;
+
+        Ok(world_state_process_proofs
+            .last()
+            .map(|p| *p.new_root)
+            .unwrap_or(old_world_state_root))
+    }
+
+    /// The contract calls are applied in order to the world state after the mints.
+    /// `contract_calls` are paired with the process proofs returned by `ContractCall::apply`,
+    /// and the disabled slots are set with `default_contract_call`.
+    fn set_contract_calls_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        old_world_state_root: HashOut<F>,
+        contract_calls: &[(ContractCall<F, C, D>, SmtProcessProof<F>)],
+        default_contract_call: &ContractCall<F, C, D>,
+    ) -> Result<(), IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        ensure_at_most("contract calls", contract_calls.len(), N_CONTRACT_CALLS)?;
+        let mut world_state_root = old_world_state_root;
+        for (i, (target, (contract_call, process_proof))) in
+            self.contract_calls.iter().zip(contract_calls).enumerate()
+        {
+            ensure_witness!(
+                *process_proof.old_root == world_state_root,
+                "contract call #{} must start from the world state root after the previous one",
+                i
+            );
+            target.set_witness(
+                pw,
+                &contract_call.proof,
+                &contract_call.verifier_only,
+                process_proof,
+                true,
+            );
+            world_state_root = *process_proof.new_root;
+        }
+
+        let default_process_proof = SmtProcessProof::with_root(world_state_root.into());
+        for target in self.contract_calls.iter().skip(contract_calls.len()) {
+            target.set_witness(
+                pw,
+                &default_contract_call.proof,
+                &default_contract_call.verifier_only,
+                &default_process_proof,
+                false,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn set_block_header_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        block_number: u32,
+        block_header_siblings: &[HashOut<F>],
+        prev_block_hash: HashOut<F>,
+        timestamp: u64,
+        aggregator_address: HashOut<F>,
+        issuer_registry_root: HashOut<F>,
+    ) -> Result<(), IntmaxError> {
+        validate_block_header_witness(block_number, block_header_siblings, timestamp)?;
         self.prev_block_header_proof.set_witness(
             pw,
             block_number as usize - 1,
@@ -149,23 +581,75 @@ impl<
         );
 
         pw.set_hash_target(self.prev_block_hash, prev_block_hash);
+        pw.set_target(
+            self.block_header.timestamp,
+            F::from_canonical_u64(timestamp),
+        );
+        pw.set_hash_target(self.block_header.aggregator_address, aggregator_address);
+        pw.set_hash_target(self.block_header.issuer_registry_root, issuer_registry_root);
 
-        // let address_list = make_address_list(user_tx_proofs, received_signatures, N_TXS);
-
-        // ProposalAndApprovalBlockPublicInputs {
-        //     address_list,
-        //     deposit_list: todo!(),
-        //     old_account_tree_root: todo!(),
-        //     new_account_tree_root: todo!(),
-        //     old_world_state_root,
-        //     new_world_state_root: todo!(),
-        //     old_prev_block_header_digest: todo!(),
-        //     new_prev_block_header_digest: todo!(),
-        //     block_hash: todo!(),
-        // }
+        Ok(())
     }
 }
 
+/// Checks that `htlc_spend` can be applied in block `block_number` to the world state
+/// approved by `world_state_revert_proofs`, and returns the world state root after the spend.
+pub fn validate_block_htlc_spend<F: RichField>(
+    block_number: u32,
+    world_state_revert_proofs: &[SmtProcessProof<F>],
+    htlc_spend: Option<&HtlcSpendWitness<F>>,
+) -> Result<HashOut<F>, IntmaxError> {
+    let approved_world_state_root = world_state_revert_proofs
+        .last()
+        .ok_or(IntmaxError::EmptyWitness {
+            name: "world state revert proofs",
+        })?
+        .new_root;
+    let htlc_spend = match htlc_spend {
+        Some(htlc_spend) => htlc_spend,
+        None => return Ok(*approved_world_state_root),
+    };
+
+    ensure_witness!(
+        htlc_spend.block_number == block_number,
+        "the HTLC spend is for block {}, not {}",
+        htlc_spend.block_number,
+        block_number
+    );
+    ensure_witness!(
+        htlc_spend.world_state_process_proofs[0].old_root == approved_world_state_root,
+        "the HTLC spend must start from the approved world state root"
+    );
+    let (_, new_world_state_root) = validate_htlc_spend_witness(htlc_spend)?;
+
+    Ok(*new_world_state_root)
+}
+
+/// Checks the block header fields set by `OneBlockProofTarget` without proving.
+/// `block_header_siblings` are the siblings of the previous block header in the block header tree.
+pub fn validate_block_header_witness<F: RichField>(
+    block_number: u32,
+    block_header_siblings: &[HashOut<F>],
+    timestamp: u64,
+) -> Result<(), IntmaxError> {
+    ensure_witness!(block_number != 0, "block number must be positive");
+    ensure_length(
+        "block header siblings",
+        block_header_siblings.len(),
+        N_LOG_MAX_BLOCKS,
+    )?;
+    ensure_at_most(
+        "bits of the timestamp",
+        (u64::BITS - timestamp.leading_zeros()) as usize,
+        N_LOG_MAX_TIMESTAMP,
+    )?;
+
+    Ok(())
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
+/// `mint_circuit_data` is the data of the mint circuit (`make_mint_circuit`), whose proofs are verified recursively.
+/// Every contract circuit called in a block must have `contract_common_data`.
 pub fn make_block_proof_circuit<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -199,11 +683,76 @@ pub fn make_block_proof_circuit<
         N_MERGES,
     >,
     simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+    mint_circuit_data: &CircuitData<F, C, D>,
+    contract_common_data: &CommonCircuitData<F, D>,
+) -> ProposalAndApprovalBlockCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+    N_TXS,
+    N_DEPOSITS,
+>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    make_block_proof_circuit_with_config(
+        merge_and_purge_circuit,
+        simple_signature_circuit,
+        mint_circuit_data,
+        contract_common_data,
+        CircuitConfig::standard_recursion_config(),
+    )
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn make_block_proof_circuit_with_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+    const N_DIFFS: usize,
+    const N_MERGES: usize,
+    const N_TXS: usize,
+    const N_DEPOSITS: usize,
+>(
+    merge_and_purge_circuit: &MergeAndPurgeTransitionCircuit<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >,
+    simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+    mint_circuit_data: &CircuitData<F, C, D>,
+    contract_common_data: &CommonCircuitData<F, D>,
+    config: CircuitConfig,
 ) -> ProposalAndApprovalBlockCircuit<
     F,
     C,
     D,
     N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
     N_LOG_TXS,
     N_LOG_RECIPIENTS,
     N_LOG_CONTRACTS,
@@ -214,13 +763,13 @@ pub fn make_block_proof_circuit<
 where
     C::Hasher: AlgebraicHasher<F>,
 {
-    let config = CircuitConfig::standard_recursion_config();
     let mut builder = CircuitBuilder::<F, D>::new(config);
     // builder.debug_gate_row = Some(529); // xors in SparseMerkleProcessProof in DepositBlock
 
     // deposit block
     let deposit_block_target: DepositBlockProofTarget<
         D,
+        N_LOG_TXS,
         N_LOG_RECIPIENTS,
         N_LOG_CONTRACTS,
         N_LOG_VARIABLES,
@@ -242,7 +791,9 @@ where
     {
         // publish ID list
         // public_inputs[(5*i)..(5*i+5)]
-        builder.register_public_inputs(&user_tx_proof.inner.public_inputs[16..20]); // sender_address
+        builder.register_public_inputs(
+            &user_tx_proof.inner.public_inputs[SENDER_ADDRESS_OFFSET..SENDER_ADDRESS_OFFSET + 4],
+        ); // sender_address
         builder.register_public_input(received_signature.enabled.target); // not_cancel_flag
     }
 
@@ -266,11 +817,83 @@ where
     // block header
     let block_number = builder.add_virtual_target();
     builder.range_check(block_number, N_LOG_MAX_BLOCKS);
+    builder.connect(approval_block_target.current_block_number, block_number);
     let transactions_digest = proposal_block_target.block_tx_root;
     let deposit_digest = deposit_block_target.deposit_digest;
     let proposed_world_state_digest = proposal_block_target.new_world_state_root;
-    let approved_world_state_digest = approval_block_target.new_world_state_root;
     let latest_account_digest = approval_block_target.new_account_tree_root;
+    let timestamp = builder.add_virtual_target();
+    builder.range_check(timestamp, N_LOG_MAX_TIMESTAMP);
+    let aggregator_address = builder.add_virtual_hash();
+    // The issuer registry which the mints are verified against, committed in the block hash.
+    let issuer_registry_root = builder.add_virtual_hash();
+
+    // HTLC spend
+    // 承認後の world state で escrow を spend する. timelock はこの block の block number で検証される.
+    let htlc_spend_target: HtlcSpendTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+    > = HtlcSpendTarget::add_virtual_to::<F, C::Hasher, D>(&mut builder, block_number);
+    builder.connect_hashes(
+        htlc_spend_target.old_world_state_root,
+        approval_block_target.new_world_state_root,
+    );
+
+    // mints
+    // HTLC spend 後の world state に mint を適用する. mint はこの block の block number と issuer registry に対して検証される.
+    let mint_block_target: MintBlockProofTarget<D, N_LOG_MAX_USERS, N_MINTS> =
+        MintBlockProofTarget::add_virtual_to(&mut builder, mint_circuit_data);
+    builder.connect(mint_block_target.block_number, block_number);
+    builder.connect_hashes(mint_block_target.issuer_registry_root, issuer_registry_root);
+    builder.connect_hashes(
+        mint_block_target.old_world_state_root,
+        htlc_spend_target.new_world_state_root,
+    );
+
+    // contract calls
+    // mint 後の world state で contract を順に呼び出す.
+    let mut contract_calls = Vec::with_capacity(N_CONTRACT_CALLS);
+    let mut contract_world_state_root = mint_block_target.new_world_state_root;
+    for _ in 0..N_CONTRACT_CALLS {
+        let contract_call = ContractCallTarget::add_virtual_to::<F, C>(
+            &mut builder,
+            contract_common_data,
+            contract_world_state_root,
+        );
+        contract_world_state_root = contract_call.new_world_state_root;
+        contract_calls.push(contract_call);
+    }
+    let approved_world_state_digest = contract_world_state_root;
+
+    // forced transactions
+    let block_transactions = proposal_block_target
+        .user_tx_proofs
+        .iter()
+        .map(|user_tx_proof| {
+            let public_inputs = &user_tx_proof.inner.public_inputs;
+            BlockTransactionTarget {
+                sender_address: HashOutTarget {
+                    elements: public_inputs[SENDER_ADDRESS_OFFSET..SENDER_ADDRESS_OFFSET + 4]
+                        .try_into()
+                        .unwrap(),
+                },
+                tx_hash: HashOutTarget {
+                    elements: public_inputs[TX_HASH_OFFSET..TX_HASH_OFFSET + 4]
+                        .try_into()
+                        .unwrap(),
+                },
+                enabled: user_tx_proof.enabled,
+            }
+        })
+        .collect::<Vec<_>>();
+    let forced_inclusion_target: ForcedInclusionProofTarget<N_LOG_MAX_USERS, N_FORCED_TXS> =
+        ForcedInclusionProofTarget::add_virtual_to::<F, C::Hasher, D>(
+            &mut builder,
+            proposal_block_target.old_world_state_root,
+            &block_transactions,
+        );
 
     // `block_number -　1` までの block header で block header tree を作る.
     let prev_block_header_proof: MerkleProofTarget<N_LOG_MAX_BLOCKS> =
@@ -282,6 +905,10 @@ where
         prev_block_hash,
         &prev_block_header_proof.siblings,
     );
+    // block header tree の最後の leaf は直前の block header なので, block number は 1 以上.
+    let one = builder.one();
+    let prev_block_number = builder.sub(block_number, one);
+    builder.connect(prev_block_header_proof.index, prev_block_number);
 
     let block_header = BlockHeaderTarget {
         block_number,
@@ -291,22 +918,92 @@ where
         proposed_world_state_digest,
         approved_world_state_digest,
         latest_account_digest,
+        forced_transactions_digest: forced_inclusion_target.new_forced_transactions_digest,
+        issuer_registry_root,
+        timestamp,
+        aggregator_address,
     };
     let block_hash = get_block_hash_target::<F, C::Hasher, D>(&mut builder, &block_header);
 
     builder.register_public_inputs(&prev_block_header_proof.root.elements); // old_root
     builder.register_public_inputs(&prev_block_header_digest.elements); // new_root
     builder.register_public_inputs(&block_hash.elements);
+    // The L1 verifier binds the posted block header to the proof with these digests.
+    builder.register_public_inputs(&block_header.proposed_world_state_digest.elements);
+    builder.register_public_inputs(&block_header.approved_world_state_digest.elements);
+    builder.register_public_inputs(&block_header.latest_account_digest.elements);
+    // Commit to the ordered `tx_hash` list, where the disabled slots are zero,
+    // so that a transaction can be looked up by its hash against the proof.
+    let mut tx_hash_list = Vec::with_capacity(4 * N_TXS);
+    for user_tx_proof in proposal_block_target.user_tx_proofs.iter() {
+        for tx_hash_t in &user_tx_proof.inner.public_inputs[TX_HASH_OFFSET..TX_HASH_OFFSET + 4] {
+            tx_hash_list.push(builder.mul(*tx_hash_t, user_tx_proof.enabled.target));
+        }
+    }
+    let tx_hash_list_digest = builder.hash_n_to_hash_no_pad::<C::Hasher>(tx_hash_list);
+    builder.register_public_inputs(&tx_hash_list_digest.elements);
+    builder.register_public_inputs(
+        &forced_inclusion_target
+            .old_forced_transactions_digest
+            .elements,
+    );
+    builder.register_public_inputs(&block_header.forced_transactions_digest.elements);
+    // The burns of the transactions not approved by the senders are reverted with them.
+    let burn_transactions = proposal_block_target
+        .user_tx_proofs
+        .iter()
+        .zip_eq(approval_block_target.received_signatures.iter())
+        .map(|(user_tx_proof, received_signature)| {
+            let public_inputs = &user_tx_proof.inner.public_inputs;
+            BurnTransactionTarget {
+                sender_address: AddressTarget(HashOutTarget {
+                    elements: public_inputs[SENDER_ADDRESS_OFFSET..SENDER_ADDRESS_OFFSET + 4]
+                        .try_into()
+                        .unwrap(),
+                }),
+                burned_assets_digest: HashOutTarget {
+                    elements: public_inputs
+                        [BURNED_ASSETS_DIGEST_OFFSET..BURNED_ASSETS_DIGEST_OFFSET + 4]
+                        .try_into()
+                        .unwrap(),
+                },
+                is_approved: builder.and(user_tx_proof.enabled, received_signature.enabled),
+            }
+        })
+        .collect::<Vec<_>>();
+    let burned_assets_digest = get_block_burned_assets_digest_target(
+        &mut builder,
+        &burn_transactions
+            .iter()
+            .map(|tx| (tx.burned_assets_digest, tx.is_approved))
+            .collect::<Vec<_>>(),
+    );
+    builder.register_public_inputs(&burned_assets_digest.elements);
+    // The burns are withdrawn to the senders on L1.
+    let withdrawal_block_target = WithdrawalBlockProofTarget::add_virtual_to::<F, C::Hasher, D>(
+        &mut builder,
+        &burn_transactions,
+        N_DIFFS,
+    );
+    builder.register_public_inputs(&withdrawal_block_target.withdrawal_root.elements);
+    let version = builder.constant(protocol_version());
+    builder.register_public_input(version);
     let block_circuit_data = builder.build::<C>();
+    monitoring::record_circuit_size("block", block_circuit_data.common.degree_bits());
     assert_eq!(
         block_circuit_data.prover_only.public_inputs.len(),
-        5 * N_TXS + 13 * N_DEPOSITS + 28
+        5 * N_TXS + 13 * N_DEPOSITS + 61
     );
 
     let targets = OneBlockProofTarget {
         proposal_block_target,
         approval_block_target,
         deposit_block_target,
+        forced_inclusion_target,
+        htlc_spend_target,
+        mint_block_target,
+        contract_calls,
+        withdrawal_block_target,
         block_number,
         prev_block_header_proof,
         prev_block_hash,
@@ -324,6 +1021,7 @@ pub struct ProposalAndApprovalBlockCircuit<
     C: GenericConfig<D, F = F>,
     const D: usize,
     const N_LOG_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
     const N_LOG_TXS: usize,
     const N_LOG_RECIPIENTS: usize,
     const N_LOG_CONTRACTS: usize,
@@ -335,6 +1033,7 @@ pub struct ProposalAndApprovalBlockCircuit<
     pub targets: OneBlockProofTarget<
         D,
         N_LOG_USERS,
+        N_LOG_MAX_TXS,
         N_LOG_TXS,
         N_LOG_RECIPIENTS,
         N_LOG_CONTRACTS,
@@ -344,17 +1043,47 @@ pub struct ProposalAndApprovalBlockCircuit<
     >,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "DepositInfo<F>: Serialize",
+    deserialize = "DepositInfo<F>: Deserialize<'de>"
+))]
 pub struct ProposalAndApprovalBlockPublicInputs<F: RichField> {
     pub address_list: Vec<TransactionSenderWithValidity<F>>,
     pub deposit_list: Vec<DepositInfo<F>>,
+    #[serde(with = "hash_out_hex")]
     pub old_account_tree_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub new_account_tree_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub old_world_state_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub new_world_state_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub old_prev_block_header_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub new_prev_block_header_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub block_hash: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub proposed_world_state_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub approved_world_state_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub latest_account_digest: HashOut<F>,
+    /// The hash of the `tx_hash` of each slot in order, where the disabled slots are zero.
+    #[serde(with = "hash_out_hex")]
+    pub tx_hash_list_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub old_forced_transactions_digest: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub new_forced_transactions_digest: HashOut<F>,
+    /// `get_block_burned_assets_digest` of the approved transactions.
+    #[serde(with = "hash_out_hex")]
+    pub burned_assets_digest: HashOut<F>,
+    /// `get_withdrawal_root` of the withdrawals of the block.
+    #[serde(with = "hash_out_hex")]
+    pub withdrawal_root: HashOut<F>,
 }
 
 impl<F: RichField> ProposalAndApprovalBlockPublicInputs<F> {
@@ -391,9 +1120,94 @@ impl<F: RichField> ProposalAndApprovalBlockPublicInputs<F> {
         public_inputs.append(&mut self.old_prev_block_header_digest.elements.into());
         public_inputs.append(&mut self.new_prev_block_header_digest.elements.into());
         public_inputs.append(&mut self.block_hash.elements.into());
+        public_inputs.append(&mut self.proposed_world_state_digest.elements.into());
+        public_inputs.append(&mut self.approved_world_state_digest.elements.into());
+        public_inputs.append(&mut self.latest_account_digest.elements.into());
+        public_inputs.append(&mut self.tx_hash_list_digest.elements.into());
+        public_inputs.append(&mut self.old_forced_transactions_digest.elements.into());
+        public_inputs.append(&mut self.new_forced_transactions_digest.elements.into());
+        public_inputs.append(&mut self.burned_assets_digest.elements.into());
+        public_inputs.append(&mut self.withdrawal_root.elements.into());
+        public_inputs.push(protocol_version());
 
         public_inputs
     }
+
+    /// The inverse of `encode` for the block circuit with `n_txs` and `n_deposits`.
+    /// Rejects the public inputs of the wrong length or another protocol version.
+    pub fn decode(
+        public_inputs: &[F],
+        n_txs: usize,
+        n_deposits: usize,
+    ) -> Result<Self, IntmaxError> {
+        let expected = BlockPublicInputs::<F>::public_inputs_len(n_txs, n_deposits);
+        if public_inputs.len() != expected {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected,
+                actual: public_inputs.len(),
+            });
+        }
+
+        let version = *public_inputs.last().unwrap();
+        if version != protocol_version() {
+            return Err(IntmaxError::UnsupportedVersion {
+                expected: PROTOCOL_VERSION,
+                actual: u32::try_from(version.to_canonical_u64()).unwrap_or(u32::MAX),
+            });
+        }
+
+        BlockPublicInputs::try_decode(public_inputs, n_txs, n_deposits)
+            .map(Self::from)
+            .map_err(IntmaxError::from)
+    }
+
+    /// The same as `BlockPublicInputs::commitment`.
+    pub fn commitment(&self) -> HashOut<F> {
+        let mut public_inputs = self.encode();
+        public_inputs.pop();
+
+        PoseidonHash::hash_no_pad(&public_inputs)
+    }
+}
+
+impl<F: RichField> From<BlockPublicInputs<F>> for ProposalAndApprovalBlockPublicInputs<F> {
+    fn from(value: BlockPublicInputs<F>) -> Self {
+        Self {
+            address_list: value
+                .address_list
+                .into_iter()
+                .map(|(sender_address, is_valid)| TransactionSenderWithValidity {
+                    sender_address: Address(sender_address),
+                    is_valid,
+                })
+                .collect(),
+            deposit_list: value
+                .deposit_list
+                .into_iter()
+                .map(|deposit| DepositInfo {
+                    receiver_address: Address(deposit.receiver_address),
+                    contract_address: Address(deposit.contract_address),
+                    variable_index: deposit.variable_index,
+                    amount: deposit.amount,
+                })
+                .collect(),
+            old_account_tree_root: value.old_account_tree_root,
+            new_account_tree_root: value.new_account_tree_root,
+            old_world_state_root: value.old_world_state_root,
+            new_world_state_root: value.new_world_state_root,
+            old_prev_block_header_digest: value.old_prev_block_header_digest,
+            new_prev_block_header_digest: value.new_prev_block_header_digest,
+            block_hash: value.block_hash,
+            proposed_world_state_digest: value.proposed_world_state_digest,
+            approved_world_state_digest: value.approved_world_state_digest,
+            latest_account_digest: value.latest_account_digest,
+            tx_hash_list_digest: value.tx_hash_list_digest,
+            old_forced_transactions_digest: value.old_forced_transactions_digest,
+            new_forced_transactions_digest: value.new_forced_transactions_digest,
+            burned_assets_digest: value.burned_assets_digest,
+            withdrawal_root: value.withdrawal_root,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -407,9 +1221,21 @@ pub struct ProposalAndApprovalBlockPublicInputsTarget<const N_TXS: usize, const
     pub old_prev_block_header_digest: HashOutTarget,
     pub new_prev_block_header_digest: HashOutTarget,
     pub block_hash: HashOutTarget,
+    pub proposed_world_state_digest: HashOutTarget,
+    pub approved_world_state_digest: HashOutTarget,
+    pub latest_account_digest: HashOutTarget,
+    pub tx_hash_list_digest: HashOutTarget,
+    pub old_forced_transactions_digest: HashOutTarget,
+    pub new_forced_transactions_digest: HashOutTarget,
+    pub burned_assets_digest: HashOutTarget,
+    pub withdrawal_root: HashOutTarget,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "ProposalAndApprovalBlockPublicInputs<F>: Serialize",
+    deserialize = "ProposalAndApprovalBlockPublicInputs<F>: Deserialize<'de>"
+))]
 pub struct ProposalAndApprovalBlockProofWithPublicInputs<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -525,9 +1351,76 @@ pub fn parse_proposal_and_approval_public_inputs<const N_TXS: usize, const N_DEP
             *public_inputs_t.next().unwrap(),
         ],
     };
+    let proposed_world_state_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let approved_world_state_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let latest_account_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let tx_hash_list_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let old_forced_transactions_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let new_forced_transactions_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let burned_assets_digest = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
+    let withdrawal_root = HashOutTarget {
+        elements: [
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+            *public_inputs_t.next().unwrap(),
+        ],
+    };
 
-    let rest_public_inputs = public_inputs_t.collect::<Vec<_>>();
-    dbg!(rest_public_inputs);
+    #[cfg(feature = "tracing")]
+    {
+        let rest_public_inputs = public_inputs_t.collect::<Vec<_>>();
+        tracing::trace!(?rest_public_inputs, "rest of the block public inputs");
+    }
 
     ProposalAndApprovalBlockPublicInputsTarget {
         address_list: address_list.try_into().unwrap(),
@@ -539,6 +1432,14 @@ pub fn parse_proposal_and_approval_public_inputs<const N_TXS: usize, const N_DEP
         old_prev_block_header_digest,
         new_prev_block_header_digest,
         block_hash,
+        proposed_world_state_digest,
+        approved_world_state_digest,
+        latest_account_digest,
+        tx_hash_list_digest,
+        old_forced_transactions_digest,
+        new_forced_transactions_digest,
+        burned_assets_digest,
+        withdrawal_root,
     }
 }
 
@@ -547,6 +1448,7 @@ impl<
         C: GenericConfig<D, F = F>,
         const D: usize,
         const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
         const N_LOG_TXS: usize,
         const N_LOG_RECIPIENTS: usize,
         const N_LOG_CONTRACTS: usize,
@@ -559,6 +1461,7 @@ impl<
         C,
         D,
         N_LOG_USERS,
+        N_LOG_MAX_TXS,
         N_LOG_TXS,
         N_LOG_RECIPIENTS,
         N_LOG_CONTRACTS,
@@ -575,59 +1478,47 @@ impl<
         parse_proposal_and_approval_public_inputs::<N_TXS, N_DEPOSITS>(&public_inputs_t)
     }
 
+    /// Returns the verifier without the prover data.
+    pub fn verifier(&self) -> BlockVerifier<F, C, D> {
+        BlockVerifier {
+            verifier_only: self.data.verifier_only.clone(),
+            common: self.data.common.clone(),
+            n_txs: N_TXS,
+            n_deposits: N_DEPOSITS,
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn prove(
         &self,
         inputs: PartialWitness<F>,
     ) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+        let start = Instant::now();
         let proof_with_pis = self.data.prove(inputs)?;
-        let mut public_inputs = proof_with_pis.public_inputs.iter();
-        let address_list = (0..N_TXS)
-            .map(|_| TransactionSenderWithValidity {
-                sender_address: Address::read(&mut public_inputs),
-                is_valid: public_inputs.next().unwrap().is_nonzero(),
-            })
-            .collect::<Vec<_>>();
-        let deposit_list = (0..N_DEPOSITS)
-            .map(|_| DepositInfo {
-                receiver_address: Address::read(&mut public_inputs),
-                contract_address: Address::read(&mut public_inputs),
-                variable_index: *WrappedHashOut::read(&mut public_inputs),
-                amount: *public_inputs.next().unwrap(),
-            })
-            .collect::<Vec<_>>();
-        let old_account_tree_root = *WrappedHashOut::read(&mut public_inputs);
-        let new_account_tree_root = *WrappedHashOut::read(&mut public_inputs);
-
-        let old_world_state_root = *WrappedHashOut::read(&mut public_inputs);
-        let new_world_state_root = *WrappedHashOut::read(&mut public_inputs);
-        let old_prev_block_header_digest = *WrappedHashOut::read(&mut public_inputs);
-        let new_prev_block_header_digest = *WrappedHashOut::read(&mut public_inputs);
-        let block_hash = *WrappedHashOut::read(&mut public_inputs);
-
-        assert_eq!(public_inputs.next(), None);
+        monitoring::record_proving_duration("block", start.elapsed());
+        let public_inputs = ProposalAndApprovalBlockPublicInputs::decode(
+            &proof_with_pis.public_inputs,
+            N_TXS,
+            N_DEPOSITS,
+        )?;
 
         Ok(ProposalAndApprovalBlockProofWithPublicInputs {
             proof: proof_with_pis.proof,
-            public_inputs: ProposalAndApprovalBlockPublicInputs {
-                address_list,
-                deposit_list,
-                old_account_tree_root,
-                new_account_tree_root,
-                old_world_state_root,
-                new_world_state_root,
-                old_prev_block_header_digest,
-                new_prev_block_header_digest,
-                block_hash,
-            },
+            public_inputs,
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn verify(
         &self,
         proof_with_pis: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
     ) -> anyhow::Result<()> {
         let public_inputs = proof_with_pis.public_inputs.encode();
-        assert_eq!(public_inputs.len(), 5 * N_TXS + 13 * N_DEPOSITS + 28);
+        ensure_length(
+            "block public inputs",
+            public_inputs.len(),
+            BlockPublicInputs::<F>::public_inputs_len(N_TXS, N_DEPOSITS),
+        )?;
 
         self.data.verify(ProofWithPublicInputs {
             proof: proof_with_pis.proof,
@@ -635,3 +1526,75 @@ impl<
         })
     }
 }
+
+#[test]
+fn test_decode_block_public_inputs() {
+    use plonky2::field::{
+        goldilocks_field::GoldilocksField,
+        types::{Field, Sample},
+    };
+
+    type F = GoldilocksField;
+    const N_TXS: usize = 2;
+    const N_DEPOSITS: usize = 2;
+
+    let public_inputs = ProposalAndApprovalBlockPublicInputs::<F> {
+        address_list: vec![
+            TransactionSenderWithValidity {
+                sender_address: Address::rand(),
+                is_valid: true,
+            },
+            TransactionSenderWithValidity {
+                sender_address: Address::rand(),
+                is_valid: false,
+            },
+        ],
+        deposit_list: (0..N_DEPOSITS)
+            .map(|_| DepositInfo {
+                receiver_address: Address::rand(),
+                contract_address: Address::rand(),
+                variable_index: HashOut::rand(),
+                amount: F::rand(),
+            })
+            .collect(),
+        old_account_tree_root: HashOut::rand(),
+        new_account_tree_root: HashOut::rand(),
+        old_world_state_root: HashOut::rand(),
+        new_world_state_root: HashOut::rand(),
+        old_prev_block_header_digest: HashOut::rand(),
+        new_prev_block_header_digest: HashOut::rand(),
+        block_hash: HashOut::rand(),
+        proposed_world_state_digest: HashOut::rand(),
+        approved_world_state_digest: HashOut::rand(),
+        latest_account_digest: HashOut::rand(),
+        tx_hash_list_digest: HashOut::rand(),
+        old_forced_transactions_digest: HashOut::rand(),
+        new_forced_transactions_digest: HashOut::rand(),
+        burned_assets_digest: HashOut::rand(),
+        withdrawal_root: HashOut::rand(),
+    };
+    let encoded = public_inputs.encode();
+    let expected = BlockPublicInputs::<F>::public_inputs_len(N_TXS, N_DEPOSITS);
+    assert_eq!(encoded.len(), expected);
+    assert_eq!(
+        ProposalAndApprovalBlockPublicInputs::decode(&encoded, N_TXS, N_DEPOSITS).unwrap(),
+        public_inputs
+    );
+
+    assert!(matches!(
+        ProposalAndApprovalBlockPublicInputs::decode(&encoded[1..], N_TXS, N_DEPOSITS),
+        Err(IntmaxError::InvalidPublicInputsLength { expected: e, actual: a })
+            if e == expected && a == expected - 1
+    ));
+    assert!(matches!(
+        ProposalAndApprovalBlockPublicInputs::decode(&encoded, N_TXS + 1, N_DEPOSITS),
+        Err(IntmaxError::InvalidPublicInputsLength { .. })
+    ));
+
+    let mut other_version = encoded;
+    *other_version.last_mut().unwrap() = F::from_canonical_u32(PROTOCOL_VERSION + 1);
+    assert!(matches!(
+        ProposalAndApprovalBlockPublicInputs::decode(&other_version, N_TXS, N_DEPOSITS),
+        Err(IntmaxError::UnsupportedVersion { .. })
+    ));
+}
@@ -0,0 +1,247 @@
+//! Block circuits of several sizes.
+//!
+//! Proving a block costs about the same regardless of how many of its `N_TXS` slots are used,
+//! so an aggregator builds circuits for a few values of `N_TXS` and proves each block with
+//! the smallest circuit that fits its transactions.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    iop::witness::PartialWitness,
+    plonk::config::{AlgebraicHasher, GenericConfig},
+};
+
+use crate::{
+    prover::backend::{BlockWitness, CircuitWitness},
+    rollup::circuits::{
+        ProposalAndApprovalBlockCircuit, ProposalAndApprovalBlockProofWithPublicInputs,
+    },
+    verification::verifier::BlockVerifier,
+};
+
+/// A block circuit whose `N_TXS` and `N_DEPOSITS` are known only at runtime.
+pub trait BlockCircuit<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    fn n_txs(&self) -> usize;
+
+    fn n_deposits(&self) -> usize;
+
+    fn verifier(&self) -> BlockVerifier<F, C, D>;
+
+    fn prove_block(
+        &self,
+        witness: &BlockWitness<F, C, D>,
+    ) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>>;
+
+    fn verify_block(
+        &self,
+        proof: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()>;
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_TXS: usize,
+        const N_DEPOSITS: usize,
+    > BlockCircuit<F, C, D>
+    for ProposalAndApprovalBlockCircuit<
+        F,
+        C,
+        D,
+        N_LOG_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_TXS,
+        N_DEPOSITS,
+    >
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    fn n_txs(&self) -> usize {
+        N_TXS
+    }
+
+    fn n_deposits(&self) -> usize {
+        N_DEPOSITS
+    }
+
+    fn verifier(&self) -> BlockVerifier<F, C, D> {
+        ProposalAndApprovalBlockCircuit::verifier(self)
+    }
+
+    fn prove_block(
+        &self,
+        witness: &BlockWitness<F, C, D>,
+    ) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        CircuitWitness::<Self>::set_witness(witness, self, &mut pw)?;
+
+        self.prove(pw)
+    }
+
+    fn verify_block(
+        &self,
+        proof: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()> {
+        self.verify(proof)
+    }
+}
+
+/// The block circuits sorted by `n_txs`.
+pub struct CircuitRegistry<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    circuits: Vec<Box<dyn BlockCircuit<F, C, D> + Send + Sync>>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> Default
+    for CircuitRegistry<F, C, D>
+{
+    fn default() -> Self {
+        Self { circuits: vec![] }
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    CircuitRegistry<F, C, D>
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `circuit`. Each `n_txs` can be registered only once.
+    pub fn register(
+        &mut self,
+        circuit: impl BlockCircuit<F, C, D> + Send + Sync + 'static,
+    ) -> anyhow::Result<()> {
+        let n_txs = circuit.n_txs();
+        anyhow::ensure!(n_txs != 0, "the block circuit must have at least one slot");
+        let index = match self.circuits.binary_search_by_key(&n_txs, |c| c.n_txs()) {
+            Ok(_) => anyhow::bail!("a block circuit with {} slots is already registered", n_txs),
+            Err(index) => index,
+        };
+        self.circuits.insert(index, Box::new(circuit));
+
+        Ok(())
+    }
+
+    /// The registered `n_txs` in ascending order.
+    pub fn sizes(&self) -> Vec<usize> {
+        self.circuits.iter().map(|c| c.n_txs()).collect()
+    }
+
+    /// The maximum number of transactions in a block, which is 0 if no circuit is registered.
+    pub fn max_n_txs(&self) -> usize {
+        self.circuits.last().map(|c| c.n_txs()).unwrap_or_default()
+    }
+
+    /// Returns the smallest circuit with at least `n_txs` slots.
+    pub fn select(&self, n_txs: usize) -> anyhow::Result<&dyn BlockCircuit<F, C, D>> {
+        self.circuits
+            .iter()
+            .find(|c| c.n_txs() >= n_txs)
+            .map(|c| c.as_ref() as &dyn BlockCircuit<F, C, D>)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no block circuit fits {} transactions: the largest one has {} slots",
+                    n_txs,
+                    self.max_n_txs()
+                )
+            })
+    }
+
+    /// Returns the circuit with exactly `n_txs` slots, e.g. to verify a block proved with it.
+    pub fn get(&self, n_txs: usize) -> Option<&dyn BlockCircuit<F, C, D>> {
+        self.circuits
+            .iter()
+            .find(|c| c.n_txs() == n_txs)
+            .map(|c| c.as_ref() as &dyn BlockCircuit<F, C, D>)
+    }
+
+    /// Proves `witness` with the smallest circuit that fits its user transactions.
+    pub fn prove(
+        &self,
+        witness: &BlockWitness<F, C, D>,
+    ) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+        self.select(witness.user_tx_proofs.len())?
+            .prove_block(witness)
+    }
+
+    /// Verifies `proof` with the circuit of the same size as its address list.
+    pub fn verify(
+        &self,
+        proof: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()> {
+        let n_txs = proof.public_inputs.address_list.len();
+        self.get(n_txs)
+            .ok_or_else(|| anyhow::anyhow!("no block circuit with {} slots", n_txs))?
+            .verify_block(proof)
+    }
+}
+
+#[test]
+fn test_circuit_registry_selection() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    struct MockBlockCircuit(usize);
+
+    impl BlockCircuit<F, C, D> for MockBlockCircuit {
+        fn n_txs(&self) -> usize {
+            self.0
+        }
+
+        fn n_deposits(&self) -> usize {
+            self.0
+        }
+
+        fn verifier(&self) -> BlockVerifier<F, C, D> {
+            unimplemented!()
+        }
+
+        fn prove_block(
+            &self,
+            _witness: &BlockWitness<F, C, D>,
+        ) -> anyhow::Result<ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>> {
+            unimplemented!()
+        }
+
+        fn verify_block(
+            &self,
+            _proof: ProposalAndApprovalBlockProofWithPublicInputs<F, C, D>,
+        ) -> anyhow::Result<()> {
+            unimplemented!()
+        }
+    }
+
+    let mut registry = CircuitRegistry::<F, C, D>::new();
+    assert!(registry.select(1).is_err());
+    registry.register(MockBlockCircuit(16)).unwrap();
+    registry.register(MockBlockCircuit(4)).unwrap();
+    registry.register(MockBlockCircuit(64)).unwrap();
+    assert!(registry.register(MockBlockCircuit(16)).is_err());
+    assert!(registry.register(MockBlockCircuit(0)).is_err());
+    assert_eq!(registry.sizes(), vec![4, 16, 64]);
+    assert_eq!(registry.max_n_txs(), 64);
+
+    assert_eq!(registry.select(1).unwrap().n_txs(), 4);
+    assert_eq!(registry.select(4).unwrap().n_txs(), 4);
+    assert_eq!(registry.select(5).unwrap().n_txs(), 16);
+    assert_eq!(registry.select(64).unwrap().n_txs(), 64);
+    assert!(registry.select(65).is_err());
+    assert!(registry.get(8).is_none());
+}
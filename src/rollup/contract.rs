@@ -0,0 +1,412 @@
+//! Programmable contract state transitions.
+//!
+//! Besides the user asset roots, the world state can hold contracts. The leaf of a contract is
+//! `get_contract_leaf(verifier_digest, state_root)`, where `verifier_digest` identifies the circuit
+//! of the contract and `state_root` is whatever the contract commits to. The leaf is at
+//! `get_contract_address(verifier_digest)`. A contract call replaces the leaf given a proof of that
+//! circuit from the old state root to the new one, and the first call deploys the contract from the
+//! empty state. Every contract circuit must share the same `CommonCircuitData`, since the contract
+//! circuit and the block circuit verify them with the verifier data as a witness.
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::witness::{PartialWitness, Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData, CommonCircuitData, VerifierOnlyCircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ensure_at_most, IntmaxError},
+    rollup::gadgets::contract::{
+        get_contract_address, get_contract_leaf, get_contract_verifier_digest, ContractCallTarget,
+        ContractTransitionPublicInputs,
+    },
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof,
+        goldilocks_poseidon::{GoldilocksHashOut, PoseidonSparseMerkleTree, WrappedHashOut},
+        node_data::NodeData,
+    },
+    zkdsa::account::Address,
+};
+
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+/// `old_world_state_root` and `new_world_state_root`.
+pub const CONTRACT_PUBLIC_INPUTS_LEN: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ContractCall<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+
+    /// The proof of the contract circuit of `verifier_only`.
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> ContractCall<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn public_inputs(&self) -> Result<ContractTransitionPublicInputs<F>, IntmaxError> {
+        ContractTransitionPublicInputs::decode(&self.proof.public_inputs)
+    }
+
+    pub fn verifier_digest(&self) -> HashOut<F> {
+        get_contract_verifier_digest(&self.verifier_only)
+    }
+
+    /// The key of the world state leaf of the contract.
+    pub fn contract_address(&self) -> Address<F> {
+        get_contract_address(self.verifier_digest())
+    }
+
+    /// The world state leaf of the contract after the call,
+    /// where `current_leaf` is the default if the contract is not deployed.
+    pub fn new_leaf(&self, current_leaf: HashOut<F>) -> anyhow::Result<HashOut<F>> {
+        let public_inputs = self.public_inputs()?;
+        let verifier_digest = self.verifier_digest();
+        if current_leaf == HashOut::ZERO {
+            anyhow::ensure!(
+                public_inputs.old_state_root == HashOut::ZERO,
+                "the contract {} is not deployed, so the call must start from the empty state",
+                self.contract_address()
+            );
+        } else {
+            anyhow::ensure!(
+                current_leaf == get_contract_leaf(verifier_digest, public_inputs.old_state_root),
+                "the contract {} is in another state",
+                self.contract_address()
+            );
+        }
+
+        Ok(get_contract_leaf(
+            verifier_digest,
+            public_inputs.new_state_root,
+        ))
+    }
+}
+
+impl<C: GenericConfig<D, F = GoldilocksField>, const D: usize> ContractCall<GoldilocksField, C, D>
+where
+    GoldilocksField: Extendable<D>,
+    C::Hasher: AlgebraicHasher<GoldilocksField>,
+{
+    /// Replaces the leaf of the contract in `world_state_tree` and returns the process proof for the contract circuit.
+    /// The proof itself is checked only by the contract circuit.
+    pub fn apply<Nd: NodeData<K, V, I>>(
+        &self,
+        world_state_tree: &mut PoseidonSparseMerkleTree<Nd>,
+    ) -> anyhow::Result<SmtProcessProof<GoldilocksField>> {
+        let contract_key: WrappedHashOut<GoldilocksField> = self.contract_address().0.into();
+        let current_leaf = world_state_tree.get(&contract_key)?;
+        let new_leaf = self.new_leaf(*current_leaf)?;
+
+        world_state_tree.set(contract_key, new_leaf.into())
+    }
+}
+
+/// A contract whose state root is hashed with an input on each call.
+/// The rollup presets take its common data as the one every contract circuit must have,
+/// and its proofs pad the disabled contract calls of a block.
+pub struct CounterContractCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub old_state_root: HashOutTarget,
+    pub input: HashOutTarget,
+}
+
+pub fn make_counter_contract_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>() -> CounterContractCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let old_state_root = builder.add_virtual_hash();
+    let input = builder.add_virtual_hash();
+    let new_state_root = builder
+        .hash_n_to_hash_no_pad::<C::Hasher>([old_state_root.elements, input.elements].concat());
+    builder.register_public_inputs(&old_state_root.elements); // public_inputs[0..4]
+    builder.register_public_inputs(&new_state_root.elements); // public_inputs[4..8]
+    let data = builder.build::<C>();
+
+    CounterContractCircuit {
+        data,
+        old_state_root,
+        input,
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    CounterContractCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn call(
+        &self,
+        old_state_root: HashOut<F>,
+        input: HashOut<F>,
+    ) -> anyhow::Result<ContractCall<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(self.old_state_root, old_state_root);
+        pw.set_hash_target(self.input, input);
+
+        Ok(ContractCall {
+            verifier_only: self.data.verifier_only.clone(),
+            proof: self.data.prove(pw)?,
+        })
+    }
+
+    pub fn contract_address(&self) -> Address<F> {
+        get_contract_address(get_contract_verifier_digest(&self.data.verifier_only))
+    }
+
+    /// The call deploying the counter with the zero input, whose proof pads the disabled contract calls.
+    pub fn call_default(&self) -> anyhow::Result<ContractCall<F, C, D>> {
+        self.call(HashOut::ZERO, HashOut::ZERO)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContractPublicInputs<F: RichField> {
+    pub old_world_state_root: HashOut<F>,
+    pub new_world_state_root: HashOut<F>,
+}
+
+impl<F: RichField> ContractPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        [
+            self.old_world_state_root.elements,
+            self.new_world_state_root.elements,
+        ]
+        .concat()
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != CONTRACT_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: CONTRACT_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self {
+            old_world_state_root: HashOut::from_partial(&public_inputs[0..4]),
+            new_world_state_root: HashOut::from_partial(&public_inputs[4..8]),
+        })
+    }
+}
+
+/// Proves up to `N_CALLS` contract calls chained on the world state root.
+pub struct ContractCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_CALLS: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub calls: Vec<ContractCallTarget<D, N_LOG_USERS>>,
+    pub old_world_state_root: HashOutTarget,
+    pub new_world_state_root: HashOutTarget,
+}
+
+pub fn make_contract_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_CALLS: usize,
+>(
+    contract_common_data: &CommonCircuitData<F, D>,
+) -> ContractCircuit<F, C, D, N_LOG_USERS, N_CALLS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let old_world_state_root = builder.add_virtual_hash();
+    let mut new_world_state_root = old_world_state_root;
+    let mut calls = vec![];
+    for _ in 0..N_CALLS {
+        let call = ContractCallTarget::add_virtual_to::<F, C>(
+            &mut builder,
+            contract_common_data,
+            new_world_state_root,
+        );
+        new_world_state_root = call.new_world_state_root;
+        calls.push(call);
+    }
+    builder.register_public_inputs(&old_world_state_root.elements); // public_inputs[0..4]
+    builder.register_public_inputs(&new_world_state_root.elements); // public_inputs[4..8]
+    let data = builder.build::<C>();
+
+    ContractCircuit {
+        data,
+        calls,
+        old_world_state_root,
+        new_world_state_root,
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_CALLS: usize,
+    > ContractCircuit<F, C, D, N_LOG_USERS, N_CALLS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    /// `calls` are paired with the process proofs returned by `ContractCall::apply` in order.
+    /// The remaining slots are disabled with the proof of the last call.
+    pub fn prove(
+        &self,
+        old_world_state_root: HashOut<F>,
+        calls: &[(ContractCall<F, C, D>, SmtProcessProof<F>)],
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let (last_call, last_process_proof) = calls.last().ok_or(IntmaxError::EmptyWitness {
+            name: "contract calls",
+        })?;
+        ensure_at_most("contract calls", calls.len(), N_CALLS)?;
+
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(self.old_world_state_root, old_world_state_root);
+        for (target, (call, process_proof)) in self.calls.iter().zip(calls.iter()) {
+            target.set_witness(
+                &mut pw,
+                &call.proof,
+                &call.verifier_only,
+                process_proof,
+                true,
+            );
+        }
+
+        let default_process_proof = SmtProcessProof::with_root(last_process_proof.new_root);
+        for target in self.calls.iter().skip(calls.len()) {
+            target.set_witness(
+                &mut pw,
+                &last_call.proof,
+                &last_call.verifier_only,
+                &default_process_proof,
+                false,
+            );
+        }
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<ContractPublicInputs<F>> {
+        let public_inputs = ContractPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_contract_circuit() {
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::{Arc, Mutex},
+    };
+
+    use plonky2::{field::types::Sample, plonk::config::PoseidonGoldilocksConfig};
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::NodeDataMemory;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+    const N_LOG_USERS: usize = 3;
+    const N_CALLS: usize = 3;
+
+    let contract_circuit = make_counter_contract_circuit::<F, C, D>();
+
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(
+            Arc::new(Mutex::new(NodeDataMemory::default())),
+            Default::default(),
+        );
+    world_state_tree
+        .set(HashOut::rand().into(), HashOut::rand().into())
+        .unwrap();
+    let old_world_state_root = *world_state_tree.get_root();
+
+    let deploy = contract_circuit
+        .call(HashOut::ZERO, HashOut::rand())
+        .unwrap();
+    let deploy_process_proof = deploy.apply(&mut world_state_tree).unwrap();
+    assert_eq!(*deploy_process_proof.new_key, deploy.contract_address().0);
+    let state_root = deploy.public_inputs().unwrap().new_state_root;
+    let update = contract_circuit.call(state_root, HashOut::rand()).unwrap();
+    let update_process_proof = update.apply(&mut world_state_tree).unwrap();
+
+    // The state has moved on.
+    assert!(deploy.apply(&mut world_state_tree).is_err());
+
+    let circuit =
+        make_contract_circuit::<F, C, D, N_LOG_USERS, N_CALLS>(&contract_circuit.data.common);
+
+    // The contract cannot be deployed at another address, e.g. the one of a user.
+    let mut another_world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(
+            Arc::new(Mutex::new(NodeDataMemory::default())),
+            Default::default(),
+        );
+    let another_process_proof = another_world_state_tree
+        .set(
+            Address::<F>::rand().0.into(),
+            get_contract_leaf(
+                deploy.verifier_digest(),
+                deploy.public_inputs().unwrap().new_state_root,
+            )
+            .into(),
+        )
+        .unwrap();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        circuit.prove(
+            *another_process_proof.old_root,
+            &[(deploy.clone(), another_process_proof.clone())],
+        )
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    let proof = circuit
+        .prove(
+            old_world_state_root,
+            &[
+                (deploy, deploy_process_proof),
+                (update, update_process_proof),
+            ],
+        )
+        .unwrap();
+    let public_inputs = circuit.verify(proof).unwrap();
+    assert_eq!(public_inputs.old_world_state_root, old_world_state_root);
+    assert_eq!(
+        public_inputs.new_world_state_root,
+        *world_state_tree.get_root()
+    );
+    assert_eq!(
+        ContractPublicInputs::decode(&public_inputs.encode()).unwrap(),
+        public_inputs
+    );
+}
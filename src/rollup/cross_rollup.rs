@@ -0,0 +1,321 @@
+//! Transfers between two intmax rollups verified by proofs only.
+//!
+//! The sender locks the tokens in the rollup A by sending them to the lock account
+//! `cross_rollup_lock_address(destination, recipient)`, which nobody can spend from.
+//! The aggregator of the rollup B deposits the same tokens to `recipient`,
+//! and the cross-rollup transfer circuit proves that the lock under the world state root of A
+//! matches the deposit under the deposit digest of B.
+//! `lock_key` is the merge key of the lock, with which B rejects the second mint of the same lock.
+
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig, Hasher},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::IntmaxError,
+    rollup::gadgets::cross_rollup::{CrossRollupTransferTarget, CrossRollupTransferWitness},
+    zkdsa::account::Address,
+};
+
+/// The lock account in the rollup A for `recipient` in the rollup `destination`.
+pub fn cross_rollup_lock_address<F: RichField>(
+    destination: HashOut<F>,
+    recipient: Address<F>,
+) -> Address<F> {
+    Address(PoseidonHash::two_to_one(destination, recipient.0))
+}
+
+pub const CROSS_ROLLUP_TRANSFER_PUBLIC_INPUTS_LEN: usize = 29;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CrossRollupTransferPublicInputs<F: Field> {
+    pub source_world_state_root: HashOut<F>,
+    pub destination: HashOut<F>,
+    pub destination_deposit_digest: HashOut<F>,
+    pub lock_key: HashOut<F>,
+    pub recipient: Address<F>,
+    pub contract_address: Address<F>,
+    pub variable_index: HashOut<F>,
+    pub amount: F,
+}
+
+impl<F: Field> CrossRollupTransferPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = vec![];
+        public_inputs.extend(self.source_world_state_root.elements);
+        public_inputs.extend(self.destination.elements);
+        public_inputs.extend(self.destination_deposit_digest.elements);
+        public_inputs.extend(self.lock_key.elements);
+        public_inputs.extend(self.recipient.0.elements);
+        public_inputs.extend(self.contract_address.0.elements);
+        public_inputs.extend(self.variable_index.elements);
+        public_inputs.push(self.amount);
+
+        public_inputs
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != CROSS_ROLLUP_TRANSFER_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: CROSS_ROLLUP_TRANSFER_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self {
+            source_world_state_root: HashOut::from_partial(&public_inputs[0..4]),
+            destination: HashOut::from_partial(&public_inputs[4..8]),
+            destination_deposit_digest: HashOut::from_partial(&public_inputs[8..12]),
+            lock_key: HashOut::from_partial(&public_inputs[12..16]),
+            recipient: Address(HashOut::from_partial(&public_inputs[16..20])),
+            contract_address: Address(HashOut::from_partial(&public_inputs[20..24])),
+            variable_index: HashOut::from_partial(&public_inputs[24..28]),
+            amount: public_inputs[28],
+        })
+    }
+}
+
+pub struct CrossRollupTransferCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub targets: CrossRollupTransferTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+    >,
+}
+
+pub fn make_cross_rollup_transfer_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+>() -> CrossRollupTransferCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_MAX_CONTRACTS,
+    N_LOG_MAX_VARIABLES,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets = CrossRollupTransferTarget::add_virtual_to::<F, C::Hasher, D>(&mut builder);
+    builder.register_public_inputs(&targets.source_world_state_root.elements); // public_inputs[0..4]
+    builder.register_public_inputs(&targets.destination.elements); // public_inputs[4..8]
+    builder.register_public_inputs(&targets.destination_deposit_digest.elements); // public_inputs[8..12]
+    builder.register_public_inputs(&targets.lock_key.elements); // public_inputs[12..16]
+    builder.register_public_inputs(&targets.recipient.0.elements); // public_inputs[16..20]
+    builder.register_public_inputs(&targets.contract_address.0.elements); // public_inputs[20..24]
+    builder.register_public_inputs(&targets.variable_index.elements); // public_inputs[24..28]
+    builder.register_public_input(targets.amount); // public_inputs[28]
+    let data = builder.build::<C>();
+
+    CrossRollupTransferCircuit { data, targets }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+    >
+    CrossRollupTransferCircuit<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+    >
+{
+    pub fn prove(
+        &self,
+        witness: &CrossRollupTransferWitness<F>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.targets.set_witness(&mut pw, witness)?;
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<CrossRollupTransferPublicInputs<F>> {
+        let public_inputs = CrossRollupTransferPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_cross_rollup_transfer_circuit() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        rollup::deposit::l1_deposit_nonce,
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonSparseMerkleTree, WrappedHashOut,
+        },
+        transaction::merge_key::deposit_tx_hash,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+    const N_LOG_MAX_USERS: usize = 3;
+    const N_LOG_MAX_TXS: usize = 3;
+    const N_LOG_MAX_CONTRACTS: usize = 3;
+    const N_LOG_MAX_VARIABLES: usize = 3;
+    const N_LOG_TXS: usize = 2;
+    const N_LOG_RECIPIENTS: usize = 3;
+    const N_LOG_CONTRACTS: usize = 3;
+    const N_LOG_VARIABLES: usize = 3;
+
+    let destination = *GoldilocksHashOut::from_u32(2);
+    let recipient = Address(*GoldilocksHashOut::from_u32(10));
+    let contract = GoldilocksHashOut::from_u32(100);
+    let variable = GoldilocksHashOut::from_u32(0);
+    let amount = GoldilocksHashOut::from_u32(25);
+    let lock_key = GoldilocksHashOut::from_u32(12345);
+
+    // The lock in the rollup A.
+    let mut lock_asset_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    lock_asset_tree
+        .set(lock_key, contract, variable, amount)
+        .unwrap();
+    let mut world_state_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let lock_address = cross_rollup_lock_address(destination, recipient);
+    world_state_tree
+        .set(lock_address.0.into(), lock_asset_tree.get_root())
+        .unwrap();
+
+    // The deposit in the rollup B, made by an L1 deposit transaction with a nonzero nonce.
+    let nonce = l1_deposit_nonce(17, 1);
+    let mut deposit_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    deposit_tree
+        .set(recipient.0.into(), contract, variable, amount)
+        .unwrap();
+    let tx_hash: WrappedHashOut<F> = deposit_tx_hash(*deposit_tree.get_root(), nonce).into();
+    let deposit_tx_inclusion_proof = get_merkle_proof(&[tx_hash], 0, N_LOG_TXS);
+
+    let witness = CrossRollupTransferWitness {
+        destination,
+        nonce,
+        lock_account_inclusion_proof: world_state_tree.find(&lock_address.0.into()).unwrap(),
+        lock_asset_inclusion_proof: lock_asset_tree
+            .find(&lock_key, &contract, &variable)
+            .unwrap(),
+        deposit_tx_inclusion_proof: deposit_tx_inclusion_proof.clone(),
+        deposit_inclusion_proof: deposit_tree
+            .find(&recipient.0.into(), &contract, &variable)
+            .unwrap(),
+    };
+
+    let circuit = make_cross_rollup_transfer_circuit::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+    >();
+    let proof = circuit.prove(&witness).unwrap();
+    let public_inputs = circuit.verify(proof).unwrap();
+    assert_eq!(
+        public_inputs.source_world_state_root,
+        *world_state_tree.get_root()
+    );
+    assert_eq!(
+        public_inputs.destination_deposit_digest,
+        *deposit_tx_inclusion_proof.root
+    );
+    assert_eq!(public_inputs.lock_key, *lock_key);
+    assert_eq!(public_inputs.recipient, recipient);
+    assert_eq!(public_inputs.amount, F::from_canonical_u32(25));
+
+    // The deposit is not of another nonce.
+    let mut invalid_witness = witness.clone();
+    invalid_witness.nonce = HashOut::ZERO;
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        circuit
+            .prove(&invalid_witness)
+            .and_then(|proof| circuit.verify(proof))
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    // A lock for another recipient does not authorize the deposit.
+    let mut invalid_witness = witness;
+    invalid_witness.destination = *GoldilocksHashOut::from_u32(3);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        circuit
+            .prove(&invalid_witness)
+            .and_then(|proof| circuit.verify(proof))
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
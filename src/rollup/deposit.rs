@@ -1,43 +1,209 @@
+use std::collections::HashSet;
+
 use plonky2::{
-    field::goldilocks_field::GoldilocksField,
-    hash::{hash_types::HashOut, poseidon::PoseidonHash},
-    plonk::config::Hasher,
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::hash_types::{HashOut, RichField},
 };
 
 use crate::{
     merkle_tree::tree::{get_merkle_proof, MerkleProof},
     rollup::gadgets::deposit_block::DepositInfo,
     sparse_merkle_tree::{
-        gadgets::verify::verify_smt::SmtInclusionProof,
+        gadgets::{process::process_smt::SmtProcessProof, verify::verify_smt::SmtInclusionProof},
         goldilocks_poseidon::{
             LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory, PoseidonSparseMerkleTree,
+            WrappedHashOut,
         },
     },
+    transaction::merge_key::{deposit_merge_key, deposit_tx_hash},
     zkdsa::account::Address,
 };
 
-#[allow(clippy::type_complexity)]
-pub fn make_partial_deposit_proof(
-    deposit_list: &[DepositInfo<GoldilocksField>],
+type F = GoldilocksField;
+
+/// A deposit from L1 of `amount` tokens of the kind `(contract, variable)` to `recipient`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deposit<F: Field> {
+    pub recipient: Address<F>,
+    pub contract: Address<F>,
+    pub variable: HashOut<F>,
+    pub amount: F,
+}
+
+impl<F: Field> From<Deposit<F>> for DepositInfo<F> {
+    fn from(value: Deposit<F>) -> Self {
+        Self {
+            receiver_address: value.recipient,
+            contract_address: value.contract,
+            variable_index: value.variable,
+            amount: value.amount,
+        }
+    }
+}
+
+impl<F: Field> From<DepositInfo<F>> for Deposit<F> {
+    fn from(value: DepositInfo<F>) -> Self {
+        Self {
+            recipient: value.receiver_address,
+            contract: value.contract_address,
+            variable: value.variable_index,
+            amount: value.amount,
+        }
+    }
+}
+
+/// The proofs with which `recipient` merges its deposits,
+/// i.e. `diff_tree_inclusion_proof.1` and `diff_tree_inclusion_proof.2` of `MergeProof`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecipientDepositProof<F: RichField> {
+    pub recipient: Address<F>,
+    pub deposit_tx_hash: WrappedHashOut<F>,
+
+    /// `nonce` of `MergeProof`.
+    pub nonce: WrappedHashOut<F>,
+
+    /// The inclusion proof of `deposit_tx_hash` in the deposit digest.
+    pub tx_inclusion_proof: MerkleProof<F>,
+
+    /// The inclusion proof of the asset root of `recipient` in the deposit tree.
+    pub recipient_inclusion_proof: SmtInclusionProof<F>,
+}
+
+impl<F: RichField> RecipientDepositProof<F> {
+    /// `block_hash` is the hash of the block header including the deposits.
+    pub fn merge_key(&self, block_hash: HashOut<F>) -> WrappedHashOut<F> {
+        deposit_merge_key(*self.deposit_tx_hash, block_hash)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DepositTree<F: RichField> {
+    /// The root of the layered tree `recipient -> contract -> variable -> amount`.
+    pub deposit_tree_root: WrappedHashOut<F>,
+
+    /// `deposit_digest` of the block header.
+    pub deposit_digest: WrappedHashOut<F>,
+
+    /// The nonce of the deposit transaction, which is zero unless it is given.
+    pub nonce: WrappedHashOut<F>,
+
+    /// The leaves of the deposit digest. All the deposits of a block are in one deposit transaction.
+    pub deposit_tx_hashes: Vec<WrappedHashOut<F>>,
+
+    /// The witness of the deposit block circuit, in the order of `deposits`.
+    pub deposit_process_proofs: Vec<(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)>,
+
+    /// In the order of the first deposit to each recipient.
+    pub recipient_proofs: Vec<RecipientDepositProof<F>>,
+}
+
+impl<F: RichField> DepositTree<F> {
+    pub fn recipient_proof(&self, recipient: Address<F>) -> Option<&RecipientDepositProof<F>> {
+        self.recipient_proofs
+            .iter()
+            .find(|proof| proof.recipient == recipient)
+    }
+}
+
+/// The nonce of the `batch_index`-th deposit transaction of the deposits in the L1 block `l1_block_number`.
+pub fn l1_deposit_nonce(l1_block_number: u64, batch_index: u64) -> HashOut<F> {
+    HashOut::from_partial(&[
+        F::from_canonical_u64(l1_block_number),
+        F::from_canonical_u64(batch_index),
+    ])
+}
+
+/// Builds the deposit tree of a block with the zero nonce.
+/// The same kind of tokens cannot be deposited to a recipient twice in a block.
+pub fn build_deposit_tree(
+    deposits: &[Deposit<F>],
     num_log_txs: usize,
-) -> MerkleProof<GoldilocksField> {
+) -> anyhow::Result<DepositTree<F>> {
+    build_deposit_tree_with_nonce(deposits, HashOut::ZERO, num_log_txs)
+}
+
+/// Builds the deposit tree of a block whose deposit transaction has `nonce`,
+/// so that the same deposits of two deposit transactions have different `deposit_tx_hash` and merge keys.
+pub fn build_deposit_tree_with_nonce(
+    deposits: &[Deposit<F>],
+    nonce: HashOut<F>,
+    num_log_txs: usize,
+) -> anyhow::Result<DepositTree<F>> {
     let mut inner_deposit_tree =
         LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
-    for leaf in deposit_list {
-        inner_deposit_tree
-            .set(
-                leaf.receiver_address.to_hash_out().into(),
-                leaf.contract_address.to_hash_out().into(),
-                leaf.variable_index.into(),
-                HashOut::from_partial(&[leaf.amount]).into(),
-            )
-            .unwrap();
+    let mut token_kinds = HashSet::new();
+    let mut recipients: Vec<Address<F>> = vec![];
+    let mut deposit_process_proofs = Vec::with_capacity(deposits.len());
+    for deposit in deposits {
+        let inserted = token_kinds.insert((
+            deposit.recipient,
+            deposit.contract,
+            WrappedHashOut::from(deposit.variable),
+        ));
+        anyhow::ensure!(
+            inserted,
+            "duplicate deposit of the same kind of tokens to {}",
+            deposit.recipient
+        );
+
+        let process_proof = inner_deposit_tree.set(
+            deposit.recipient.to_hash_out().into(),
+            deposit.contract.to_hash_out().into(),
+            deposit.variable.into(),
+            HashOut::from_partial(&[deposit.amount]).into(),
+        )?;
+        deposit_process_proofs.push(process_proof);
+
+        if !recipients.contains(&deposit.recipient) {
+            recipients.push(deposit.recipient);
+        }
     }
 
-    let deposit_nonce = HashOut::ZERO;
-    let deposit_diff_root = PoseidonHash::two_to_one(*inner_deposit_tree.get_root(), deposit_nonce);
+    let deposit_tree_root = inner_deposit_tree.get_root();
+    let deposit_tx_hash: WrappedHashOut<F> = deposit_tx_hash(*deposit_tree_root, nonce).into();
+    let deposit_tx_hashes = vec![deposit_tx_hash];
+    let tx_inclusion_proof = get_merkle_proof(&deposit_tx_hashes, 0, num_log_txs);
+
+    let inner_deposit_tree: PoseidonSparseMerkleTree<NodeDataMemory> = inner_deposit_tree.into();
+    let recipient_proofs = recipients
+        .into_iter()
+        .map(|recipient| {
+            let recipient_inclusion_proof =
+                inner_deposit_tree.find(&recipient.to_hash_out().into())?;
+            debug_assert!(recipient_inclusion_proof.found);
+
+            Ok(RecipientDepositProof {
+                recipient,
+                deposit_tx_hash,
+                nonce: nonce.into(),
+                tx_inclusion_proof: tx_inclusion_proof.clone(),
+                recipient_inclusion_proof,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(DepositTree {
+        deposit_tree_root,
+        deposit_digest: tx_inclusion_proof.root,
+        nonce: nonce.into(),
+        deposit_tx_hashes,
+        deposit_process_proofs,
+        recipient_proofs,
+    })
+}
+
+fn to_deposits(deposit_list: &[DepositInfo<F>]) -> Vec<Deposit<F>> {
+    deposit_list.iter().cloned().map(Deposit::from).collect()
+}
+
+#[allow(clippy::type_complexity)]
+pub fn make_partial_deposit_proof(
+    deposit_list: &[DepositInfo<GoldilocksField>],
+    num_log_txs: usize,
+) -> MerkleProof<GoldilocksField> {
+    let deposit_tree = build_deposit_tree(&to_deposits(deposit_list), num_log_txs).unwrap();
 
-    get_merkle_proof(&[deposit_diff_root.into()], 0, num_log_txs)
+    get_merkle_proof(&deposit_tree.deposit_tx_hashes, 0, num_log_txs)
 }
 
 #[allow(clippy::type_complexity)]
@@ -49,30 +215,87 @@ pub fn make_deposit_proof(
     MerkleProof<GoldilocksField>,
     SmtInclusionProof<GoldilocksField>,
 ) {
-    let mut inner_deposit_tree =
-        LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
-    for leaf in deposit_list {
-        inner_deposit_tree
-            .set(
-                leaf.receiver_address.to_hash_out().into(),
-                leaf.contract_address.to_hash_out().into(),
-                leaf.variable_index.into(),
-                HashOut::from_partial(&[leaf.amount]).into(),
-            )
-            .unwrap();
-    }
+    let deposit_tree = build_deposit_tree(&to_deposits(deposit_list), num_log_txs).unwrap();
+    let proof = deposit_tree
+        .recipient_proof(receiver_address)
+        .expect("no deposit to the receiver")
+        .clone();
 
-    let deposit_nonce = HashOut::ZERO;
-    let deposit_diff_root = PoseidonHash::two_to_one(*inner_deposit_tree.get_root(), deposit_nonce);
+    (proof.tx_inclusion_proof, proof.recipient_inclusion_proof)
+}
 
-    let deposit_proof1 = get_merkle_proof(&[deposit_diff_root.into()], 0, num_log_txs);
+#[test]
+fn test_build_deposit_tree() {
+    use crate::sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut;
 
-    let inner_deposit_tree: PoseidonSparseMerkleTree<NodeDataMemory> = inner_deposit_tree.into();
-    let deposit_proof2 = inner_deposit_tree
-        .find(&receiver_address.to_hash_out().into())
-        .unwrap();
+    let recipient1 = Address(*GoldilocksHashOut::from_u32(1));
+    let recipient2 = Address(*GoldilocksHashOut::from_u32(2));
+    let contract = Address(*GoldilocksHashOut::from_u32(100));
+    let deposits = [
+        Deposit {
+            recipient: recipient1,
+            contract,
+            variable: *GoldilocksHashOut::from_u32(0),
+            amount: F::from_canonical_u32(10),
+        },
+        Deposit {
+            recipient: recipient2,
+            contract,
+            variable: *GoldilocksHashOut::from_u32(0),
+            amount: F::from_canonical_u32(20),
+        },
+        Deposit {
+            recipient: recipient1,
+            contract,
+            variable: *GoldilocksHashOut::from_u32(1),
+            amount: F::from_canonical_u32(30),
+        },
+    ];
+    let deposit_tree = build_deposit_tree(&deposits, 3).unwrap();
+    assert_eq!(deposit_tree.deposit_process_proofs.len(), 3);
+    assert_eq!(deposit_tree.recipient_proofs.len(), 2);
+    assert_eq!(
+        deposit_tree
+            .deposit_process_proofs
+            .last()
+            .unwrap()
+            .0
+            .new_root,
+        deposit_tree.deposit_tree_root
+    );
+
+    let proof = deposit_tree.recipient_proof(recipient1).unwrap();
+    assert_eq!(
+        proof.recipient_inclusion_proof.root,
+        deposit_tree.deposit_tree_root
+    );
+    assert_eq!(proof.tx_inclusion_proof.root, deposit_tree.deposit_digest);
+    assert_eq!(proof.tx_inclusion_proof.value, proof.deposit_tx_hash);
+    assert_eq!(
+        *proof.deposit_tx_hash,
+        deposit_tx_hash(*proof.recipient_inclusion_proof.root, HashOut::ZERO)
+    );
+    assert!(deposit_tree
+        .recipient_proof(Address(*GoldilocksHashOut::from_u32(3)))
+        .is_none());
 
-    debug_assert!(deposit_proof2.found);
+    // The same deposits with another nonce are another deposit transaction.
+    let nonce = l1_deposit_nonce(10, 1);
+    let other_deposit_tree = build_deposit_tree_with_nonce(&deposits, nonce, 3).unwrap();
+    assert_eq!(
+        other_deposit_tree.deposit_tree_root,
+        deposit_tree.deposit_tree_root
+    );
+    let other_proof = other_deposit_tree.recipient_proof(recipient1).unwrap();
+    assert_eq!(*other_proof.nonce, nonce);
+    assert_ne!(other_proof.deposit_tx_hash, proof.deposit_tx_hash);
+    let block_hash = HashOut::from_partial(&[F::ONE]);
+    assert_ne!(
+        other_proof.merge_key(block_hash),
+        proof.merge_key(block_hash)
+    );
 
-    (deposit_proof1, deposit_proof2)
+    let mut duplicate_deposits = deposits.to_vec();
+    duplicate_deposits.push(deposits[0]);
+    assert!(build_deposit_tree(&duplicate_deposits, 3).is_err());
 }
@@ -0,0 +1,640 @@
+//! Splits the proposal phase of a block into sub-blocks which are proved on several machines
+//! and merged by an aggregation circuit.
+//!
+//! A sub-block proof verifies `N_SUB_TXS` consecutive user transaction slots together with
+//! their world state process proofs. The aggregation circuit checks that the world state roots
+//! of `N_SUB_BLOCKS` sub-block proofs are chained and recomputes the block tx root from the
+//! sub-block tx roots, which equals the root computed by `ProposalBlockProofTarget` with
+//! `N_TXS = N_SUB_BLOCKS * N_SUB_TXS` as long as both numbers are powers of 2.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::witness::{PartialWitness, Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::{Proof, ProofWithPublicInputs},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ensure_at_most, ensure_length, IntmaxError},
+    merkle_tree::gadgets::get_merkle_root_target_from_leaves,
+    prover::{
+        backend::{CircuitWitness, ProverBackend},
+        Provable,
+    },
+    recursion::gadgets::RecursiveProofTarget,
+    rollup::gadgets::proposal_block::ProposalBlockProofTarget,
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof, goldilocks_poseidon::hash_out_hex,
+    },
+    transaction::circuits::MergeAndPurgeTransitionProofWithPublicInputs,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SubBlockPublicInputs<F: RichField> {
+    #[serde(with = "hash_out_hex")]
+    pub old_world_state_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub new_world_state_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub block_tx_root: HashOut<F>,
+}
+
+impl<F: RichField> SubBlockPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = vec![];
+        public_inputs.append(&mut self.old_world_state_root.elements.into());
+        public_inputs.append(&mut self.new_world_state_root.elements.into());
+        public_inputs.append(&mut self.block_tx_root.elements.into());
+
+        public_inputs
+    }
+
+    pub fn try_decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != 12 {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: 12,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self::decode(public_inputs))
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Self {
+        assert_eq!(public_inputs.len(), 12);
+        let old_world_state_root = HashOut::from_partial(&public_inputs[0..4]);
+        let new_world_state_root = HashOut::from_partial(&public_inputs[4..8]);
+        let block_tx_root = HashOut::from_partial(&public_inputs[8..12]);
+
+        Self {
+            old_world_state_root,
+            new_world_state_root,
+            block_tx_root,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SubBlockPublicInputsTarget {
+    pub old_world_state_root: HashOutTarget,
+    pub new_world_state_root: HashOutTarget,
+    pub block_tx_root: HashOutTarget,
+}
+
+pub fn parse_sub_block_public_inputs(
+    public_inputs_t: &[plonky2::iop::target::Target],
+) -> SubBlockPublicInputsTarget {
+    let old_world_state_root = HashOutTarget {
+        elements: public_inputs_t[0..4].try_into().unwrap(),
+    };
+    let new_world_state_root = HashOutTarget {
+        elements: public_inputs_t[4..8].try_into().unwrap(),
+    };
+    let block_tx_root = HashOutTarget {
+        elements: public_inputs_t[8..12].try_into().unwrap(),
+    };
+
+    SubBlockPublicInputsTarget {
+        old_world_state_root,
+        new_world_state_root,
+        block_tx_root,
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SubBlockProofWithPublicInputs<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub proof: Proof<F, C, D>,
+    pub public_inputs: SubBlockPublicInputs<F>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    From<SubBlockProofWithPublicInputs<F, C, D>> for ProofWithPublicInputs<F, C, D>
+{
+    fn from(value: SubBlockProofWithPublicInputs<F, C, D>) -> ProofWithPublicInputs<F, C, D> {
+        ProofWithPublicInputs {
+            proof: value.proof,
+            public_inputs: value.public_inputs.encode(),
+        }
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    From<ProofWithPublicInputs<F, C, D>> for SubBlockProofWithPublicInputs<F, C, D>
+{
+    fn from(value: ProofWithPublicInputs<F, C, D>) -> SubBlockProofWithPublicInputs<F, C, D> {
+        SubBlockProofWithPublicInputs {
+            proof: value.proof,
+            public_inputs: SubBlockPublicInputs::decode(&value.public_inputs),
+        }
+    }
+}
+
+pub struct SubBlockCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_SUB_TXS: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub targets: ProposalBlockProofTarget<D, N_LOG_USERS, N_SUB_TXS>,
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
+pub fn make_sub_block_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_SUB_TXS: usize,
+>(
+    user_tx_circuit_data: &CircuitData<F, C, D>,
+) -> SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    make_sub_block_circuit_with_config(
+        user_tx_circuit_data,
+        CircuitConfig::standard_recursion_config(),
+    )
+}
+
+pub fn make_sub_block_circuit_with_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_SUB_TXS: usize,
+>(
+    user_tx_circuit_data: &CircuitData<F, C, D>,
+    config: CircuitConfig,
+) -> SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets: ProposalBlockProofTarget<D, N_LOG_USERS, N_SUB_TXS> =
+        ProposalBlockProofTarget::add_virtual_to(&mut builder, user_tx_circuit_data);
+    builder.register_public_inputs(&targets.old_world_state_root.elements); // public_inputs[0..4]
+    builder.register_public_inputs(&targets.new_world_state_root.elements); // public_inputs[4..8]
+    builder.register_public_inputs(&targets.block_tx_root.elements); // public_inputs[8..12]
+    let data = builder.build::<C>();
+
+    SubBlockCircuit { data, targets }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_SUB_TXS: usize,
+    > SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>
+{
+    pub fn prove(
+        &self,
+        inputs: PartialWitness<F>,
+    ) -> anyhow::Result<SubBlockProofWithPublicInputs<F, C, D>> {
+        let proof_with_pis = self.data.prove(inputs)?;
+
+        Ok(proof_with_pis.into())
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: SubBlockProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()> {
+        self.data.verify(proof_with_pis.into())
+    }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_SUB_TXS: usize,
+    > Provable for SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>
+{
+    type F = F;
+    type Proof = SubBlockProofWithPublicInputs<F, C, D>;
+
+    fn prove(&self, inputs: PartialWitness<F>) -> anyhow::Result<Self::Proof> {
+        SubBlockCircuit::prove(self, inputs)
+    }
+}
+
+/// A job sent from the coordinator to a worker.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct SubBlockWitness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+{
+    /// It must not be empty. An empty sub-block has one no-op proof.
+    pub world_state_process_proofs: Vec<SmtProcessProof<F>>,
+    pub user_tx_proofs: Vec<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>,
+
+    /// The proof set to the disabled slots.
+    /// It must be the last user transaction of the whole block,
+    /// so that the block tx root is the same as the one of `ProposalBlockProofTarget`.
+    pub padding_user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+    #[serde(with = "hash_out_hex")]
+    pub old_world_state_root: HashOut<F>,
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_USERS: usize,
+        const N_SUB_TXS: usize,
+    > CircuitWitness<SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>> for SubBlockWitness<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    const NAME: &'static str = "sub_block";
+
+    fn set_witness(
+        &self,
+        circuit: &SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>,
+        pw: &mut PartialWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        ensure_at_most(
+            "user transaction proofs",
+            self.user_tx_proofs.len(),
+            N_SUB_TXS,
+        )?;
+        circuit.targets.set_world_state_witness(
+            pw,
+            &self.world_state_process_proofs,
+            self.old_world_state_root,
+        )?;
+
+        let padding_user_tx_proof = ProofWithPublicInputs::from(self.padding_user_tx_proof.clone());
+        for index in 0..N_SUB_TXS {
+            if let Some(user_tx_proof) = self.user_tx_proofs.get(index) {
+                let user_tx_proof = ProofWithPublicInputs::from(user_tx_proof.clone());
+                circuit
+                    .targets
+                    .set_user_tx_proof_witness(pw, index, &user_tx_proof, true);
+            } else {
+                circuit
+                    .targets
+                    .set_user_tx_proof_witness(pw, index, &padding_user_tx_proof, false);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits the inputs of `ProposalBlockProofTarget::set_witness` into `n_sub_blocks` jobs
+/// of `n_sub_txs` slots.
+pub fn split_into_sub_blocks<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    world_state_process_proofs: &[SmtProcessProof<F>],
+    user_tx_proofs: &[MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>],
+    old_world_state_root: HashOut<F>,
+    n_sub_blocks: usize,
+    n_sub_txs: usize,
+) -> anyhow::Result<Vec<SubBlockWitness<F, C, D>>> {
+    anyhow::ensure!(
+        world_state_process_proofs.len() == user_tx_proofs.len(),
+        "the number of world state process proofs must be equal to that of user transactions"
+    );
+    anyhow::ensure!(
+        user_tx_proofs.len() <= n_sub_blocks * n_sub_txs,
+        "too many user transactions: expected at most {}",
+        n_sub_blocks * n_sub_txs
+    );
+    let padding_user_tx_proof = user_tx_proofs
+        .last()
+        .ok_or_else(|| anyhow::anyhow!("user transactions must not be empty"))?;
+
+    let mut jobs = Vec::with_capacity(n_sub_blocks);
+    let mut latest_root = old_world_state_root;
+    for i in 0..n_sub_blocks {
+        let start = (i * n_sub_txs).min(user_tx_proofs.len());
+        let end = ((i + 1) * n_sub_txs).min(user_tx_proofs.len());
+        let mut sub_world_state_process_proofs = world_state_process_proofs[start..end].to_vec();
+        if sub_world_state_process_proofs.is_empty() {
+            sub_world_state_process_proofs.push(SmtProcessProof::with_root(latest_root.into()));
+        }
+
+        jobs.push(SubBlockWitness {
+            world_state_process_proofs: sub_world_state_process_proofs,
+            user_tx_proofs: user_tx_proofs[start..end].to_vec(),
+            padding_user_tx_proof: padding_user_tx_proof.clone(),
+            old_world_state_root: latest_root,
+        });
+
+        if end != start {
+            latest_root = *world_state_process_proofs[end - 1].new_root;
+        }
+    }
+
+    Ok(jobs)
+}
+
+#[derive(Clone)]
+pub struct SubBlockAggregationTarget<const D: usize, const N_SUB_BLOCKS: usize> {
+    pub sub_block_proofs: [RecursiveProofTarget<D>; N_SUB_BLOCKS],
+    pub old_world_state_root: HashOutTarget,
+    pub new_world_state_root: HashOutTarget,
+    pub block_tx_root: HashOutTarget,
+}
+
+impl<const D: usize, const N_SUB_BLOCKS: usize> SubBlockAggregationTarget<D, N_SUB_BLOCKS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        builder: &mut CircuitBuilder<F, D>,
+        sub_block_circuit_data: &CircuitData<F, C, D>,
+    ) -> Self
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let constant_true = builder._true();
+
+        let mut sub_block_proofs = vec![];
+        for _ in 0..N_SUB_BLOCKS {
+            let target = RecursiveProofTarget::add_virtual_to(builder, sub_block_circuit_data);
+            // 全ての sub-block が揃っていなければならない.
+            builder.connect(target.enabled.target, constant_true.target);
+            sub_block_proofs.push(target);
+        }
+
+        let public_inputs = sub_block_proofs
+            .iter()
+            .map(|p| parse_sub_block_public_inputs(&p.inner.0.public_inputs))
+            .collect::<Vec<_>>();
+
+        // sub-block の world state root は正しい遷移になるように並んでいる.
+        for (prev, cur) in public_inputs.iter().zip(public_inputs.iter().skip(1)) {
+            builder.connect_hashes(prev.new_world_state_root, cur.old_world_state_root);
+        }
+
+        let old_world_state_root = public_inputs.first().unwrap().old_world_state_root;
+        let new_world_state_root = public_inputs.last().unwrap().new_world_state_root;
+        let block_tx_root = get_merkle_root_target_from_leaves::<F, C::Hasher, D>(
+            builder,
+            public_inputs.iter().map(|p| p.block_tx_root).collect(),
+        );
+
+        Self {
+            sub_block_proofs: sub_block_proofs
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("fail to convert vector to constant size array"))
+                .unwrap(),
+            old_world_state_root,
+            new_world_state_root,
+            block_tx_root,
+        }
+    }
+
+    pub fn set_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        sub_block_proofs: &[ProofWithPublicInputs<F, C, D>],
+    ) -> Result<(), IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        ensure_length("sub-block proofs", sub_block_proofs.len(), N_SUB_BLOCKS)?;
+        for (t, proof) in self.sub_block_proofs.iter().zip(sub_block_proofs.iter()) {
+            t.set_witness(pw, proof, true);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SubBlockAggregationCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_SUB_BLOCKS: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub targets: SubBlockAggregationTarget<D, N_SUB_BLOCKS>,
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
+pub fn make_sub_block_aggregation_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_SUB_BLOCKS: usize,
+>(
+    sub_block_circuit_data: &CircuitData<F, C, D>,
+) -> SubBlockAggregationCircuit<F, C, D, N_SUB_BLOCKS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    make_sub_block_aggregation_circuit_with_config(
+        sub_block_circuit_data,
+        CircuitConfig::standard_recursion_config(),
+    )
+}
+
+/// The public inputs have the same layout as the one of the sub-block circuit.
+pub fn make_sub_block_aggregation_circuit_with_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_SUB_BLOCKS: usize,
+>(
+    sub_block_circuit_data: &CircuitData<F, C, D>,
+    config: CircuitConfig,
+) -> SubBlockAggregationCircuit<F, C, D, N_SUB_BLOCKS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets: SubBlockAggregationTarget<D, N_SUB_BLOCKS> =
+        SubBlockAggregationTarget::add_virtual_to(&mut builder, sub_block_circuit_data);
+    builder.register_public_inputs(&targets.old_world_state_root.elements);
+    builder.register_public_inputs(&targets.new_world_state_root.elements);
+    builder.register_public_inputs(&targets.block_tx_root.elements);
+    let data = builder.build::<C>();
+
+    SubBlockAggregationCircuit { data, targets }
+}
+
+impl<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_SUB_BLOCKS: usize,
+    > SubBlockAggregationCircuit<F, C, D, N_SUB_BLOCKS>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn prove(
+        &self,
+        sub_block_proofs: &[SubBlockProofWithPublicInputs<F, C, D>],
+    ) -> anyhow::Result<SubBlockProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.targets.set_witness(
+            &mut pw,
+            &sub_block_proofs
+                .iter()
+                .cloned()
+                .map(ProofWithPublicInputs::from)
+                .collect::<Vec<_>>(),
+        )?;
+        let proof_with_pis = self.data.prove(pw)?;
+
+        Ok(proof_with_pis.into())
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: SubBlockProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()> {
+        self.data.verify(proof_with_pis.into())
+    }
+}
+
+/// Proves sub-blocks with `workers` in parallel and aggregates them on this machine.
+///
+/// Jobs are assigned to the workers in round-robin order. A worker is any `ProverBackend`,
+/// e.g. `LocalProverBackend` or a remote prover behind `HttpProverBackend`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn prove_proposal_block_distributed<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+    const N_SUB_TXS: usize,
+    const N_SUB_BLOCKS: usize,
+    B: ProverBackend<SubBlockCircuit<F, C, D, N_LOG_USERS, N_SUB_TXS>, SubBlockWitness<F, C, D>>
+        + Sync,
+>(
+    workers: &[B],
+    aggregation_circuit: &SubBlockAggregationCircuit<F, C, D, N_SUB_BLOCKS>,
+    world_state_process_proofs: &[SmtProcessProof<F>],
+    user_tx_proofs: &[MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>],
+    old_world_state_root: HashOut<F>,
+) -> anyhow::Result<SubBlockProofWithPublicInputs<F, C, D>>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    anyhow::ensure!(!workers.is_empty(), "workers must not be empty");
+    let jobs = split_into_sub_blocks(
+        world_state_process_proofs,
+        user_tx_proofs,
+        old_world_state_root,
+        N_SUB_BLOCKS,
+        N_SUB_TXS,
+    )?;
+
+    let sub_block_proofs = std::thread::scope(|s| {
+        let handles = jobs
+            .iter()
+            .enumerate()
+            .map(|(i, job)| {
+                let worker = &workers[i % workers.len()];
+                s.spawn(move || worker.prove(job))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .enumerate()
+            .map(|(i, handle)| {
+                handle
+                    .join()
+                    .map_err(|_| anyhow::anyhow!("worker for sub-block {} panicked", i))?
+                    .map_err(|err| anyhow::anyhow!("fail to prove sub-block {}: {}", i, err))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })?;
+
+    aggregation_circuit.prove(&sub_block_proofs)
+}
+
+#[test]
+fn test_sub_block_aggregation() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::fixtures::{
+        make_sample_accounts, make_sample_block, make_sample_user_tx, make_sample_user_tx_circuit,
+        C, D, F, N_LOG_MAX_USERS,
+    };
+
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    let block = make_sample_block(&user_tx_circuit, &user_txs).unwrap();
+    let old_world_state_root = *user_txs.world_state_tree.get_root();
+
+    // Each of the two user transactions is proved in its own sub-block.
+    let sub_block_circuit =
+        make_sub_block_circuit::<F, C, D, N_LOG_MAX_USERS, 1>(&user_tx_circuit.data);
+    let aggregation_circuit =
+        make_sub_block_aggregation_circuit::<F, C, D, 2>(&sub_block_circuit.data);
+    let jobs = split_into_sub_blocks(
+        &block.world_state_process_proofs,
+        &user_txs.proofs,
+        old_world_state_root,
+        2,
+        1,
+    )
+    .unwrap();
+    let sub_block_proofs = jobs
+        .iter()
+        .map(|job| {
+            let mut pw = PartialWitness::new();
+            job.set_witness(&sub_block_circuit, &mut pw).unwrap();
+
+            sub_block_circuit.prove(pw).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sub_block_proofs[0].public_inputs.new_world_state_root,
+        sub_block_proofs[1].public_inputs.old_world_state_root
+    );
+
+    let aggregated_proof = aggregation_circuit.prove(&sub_block_proofs).unwrap();
+    aggregation_circuit
+        .verify(aggregated_proof.clone())
+        .unwrap();
+    assert_eq!(
+        aggregated_proof.public_inputs.old_world_state_root,
+        old_world_state_root
+    );
+    assert_eq!(
+        aggregated_proof.public_inputs.new_world_state_root,
+        *block.world_state_tree.get_root()
+    );
+
+    // In the reversed order, the old world state root of the second sub-block
+    // is not the new world state root of the first one.
+    let unchained_proofs = [sub_block_proofs[1].clone(), sub_block_proofs[0].clone()];
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        aggregation_circuit.prove(&unchained_proofs)
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    // All the sub-blocks are required.
+    assert!(matches!(
+        aggregation_circuit.targets.set_witness(
+            &mut PartialWitness::new(),
+            &[ProofWithPublicInputs::from(sub_block_proofs[0].clone())],
+        ),
+        Err(IntmaxError::LengthMismatch { .. })
+    ));
+}
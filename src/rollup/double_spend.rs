@@ -0,0 +1,359 @@
+//! An off-circuit watchdog replaying the published blocks.
+//!
+//! The validity proofs already forbid double-spends, so `DoubleSpendAuditor` is a second line of defense
+//! against bugs in the circuits: it tracks which assets each user has received, merged and purged,
+//! and reports every transaction which spends an asset twice or creates tokens from nothing.
+
+use std::collections::{HashMap, HashSet};
+
+use plonky2::field::{goldilocks_field::GoldilocksField, types::PrimeField64};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::{
+        deposit::{build_deposit_tree_with_nonce, Deposit},
+        gadgets::deposit_block::DepositInfo,
+    },
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::{
+        asset::{Asset, TokenKind},
+        block_header::{get_block_hash, BlockHeader},
+        merge_key::{deposit_merge_key, transfer_merge_key},
+    },
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+/// A leaf `merge_key -> contract -> variable -> amount` of a user asset tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetLeaf {
+    pub merge_key: WrappedHashOut<F>,
+    pub asset: Asset<F>,
+}
+
+/// A leaf `recipient -> contract -> variable -> amount` of the diff tree of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SentAsset {
+    pub recipient: Address<F>,
+    pub asset: Asset<F>,
+}
+
+/// The transaction data published with a block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedTransaction {
+    pub sender: Address<F>,
+    pub tx_hash: WrappedHashOut<F>,
+
+    /// The merge keys of the received assets the sender merged into its user asset tree.
+    pub merge_keys: Vec<WrappedHashOut<F>>,
+
+    /// The leaves removed from the user asset tree of the sender.
+    pub inputs: Vec<AssetLeaf>,
+
+    /// The leaves of the diff tree.
+    pub outputs: Vec<SentAsset>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedBlock {
+    pub header: BlockHeader<F>,
+    pub deposits: Vec<DepositInfo<F>>,
+
+    /// The nonce of the deposit transaction of `deposits`.
+    #[serde(default)]
+    pub deposit_nonce: WrappedHashOut<F>,
+
+    pub transactions: Vec<PublishedTransaction>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditFindingKind {
+    /// The same received assets are merged twice.
+    DoubleMerge { merge_key: WrappedHashOut<F> },
+    /// Assets which were never sent to the sender are merged.
+    UnbackedMerge { merge_key: WrappedHashOut<F> },
+    /// An asset leaf is purged again after it was purged.
+    DoubleSpend { leaf: AssetLeaf },
+    /// An asset leaf which the sender does not own is purged.
+    UnbackedSpend { leaf: AssetLeaf },
+    /// The sent amount of `kind` is not equal to the purged amount.
+    UnbalancedTransaction {
+        kind: TokenKind<F>,
+        input_amount: u64,
+        output_amount: u64,
+    },
+    /// The same transaction hash is included twice.
+    DuplicateTransaction,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditFinding {
+    pub block_number: u32,
+    pub tx_hash: WrappedHashOut<F>,
+    pub sender: Address<F>,
+    pub kind: AuditFindingKind,
+}
+
+type LeafKey = (Address<F>, WrappedHashOut<F>, TokenKind<F>);
+
+#[derive(Debug, Default)]
+pub struct DoubleSpendAuditor {
+    /// The assets sent to `(recipient, merge_key)` which are not merged yet.
+    receivable: HashMap<(Address<F>, WrappedHashOut<F>), Vec<Asset<F>>>,
+    merged: HashSet<(Address<F>, WrappedHashOut<F>)>,
+    unspent: HashMap<LeafKey, u64>,
+    spent: HashSet<LeafKey>,
+    tx_hashes: HashSet<WrappedHashOut<F>>,
+}
+
+impl DoubleSpendAuditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The amount of `kind` `owner` has merged and not purged yet.
+    pub fn balance(&self, owner: Address<F>, kind: TokenKind<F>) -> u64 {
+        self.unspent
+            .iter()
+            .filter(|((o, _, k), _)| *o == owner && *k == kind)
+            .map(|(_, amount)| *amount)
+            .sum()
+    }
+
+    /// Replays the blocks in order and returns the findings of all of them.
+    pub fn replay<'a>(
+        &mut self,
+        blocks: impl IntoIterator<Item = &'a PublishedBlock>,
+    ) -> anyhow::Result<Vec<AuditFinding>> {
+        let mut findings = vec![];
+        for block in blocks {
+            findings.append(&mut self.replay_block(block)?);
+        }
+
+        Ok(findings)
+    }
+
+    /// The transactions are replayed even if they have findings, as they are in the chain.
+    pub fn replay_block(&mut self, block: &PublishedBlock) -> anyhow::Result<Vec<AuditFinding>> {
+        let block_number = block.header.block_number;
+        let block_hash = get_block_hash(&block.header);
+
+        if !block.deposits.is_empty() {
+            let deposits = block
+                .deposits
+                .iter()
+                .cloned()
+                .map(Deposit::from)
+                .collect::<Vec<_>>();
+            let deposit_tree = build_deposit_tree_with_nonce(&deposits, *block.deposit_nonce, 0)?;
+            let merge_key = deposit_merge_key(*deposit_tree.deposit_tx_hashes[0], block_hash);
+            for deposit in deposits.iter() {
+                self.receivable
+                    .entry((deposit.recipient, merge_key))
+                    .or_default()
+                    .push(Asset {
+                        kind: TokenKind {
+                            contract_address: deposit.contract,
+                            variable_index: deposit.variable.into(),
+                        },
+                        amount: deposit.amount.to_canonical_u64(),
+                    });
+            }
+        }
+
+        let mut findings = vec![];
+        for tx in block.transactions.iter() {
+            let mut report = |kind| {
+                findings.push(AuditFinding {
+                    block_number,
+                    tx_hash: tx.tx_hash,
+                    sender: tx.sender,
+                    kind,
+                })
+            };
+
+            if !self.tx_hashes.insert(tx.tx_hash) {
+                report(AuditFindingKind::DuplicateTransaction);
+            }
+
+            for merge_key in tx.merge_keys.iter() {
+                if !self.merged.insert((tx.sender, *merge_key)) {
+                    report(AuditFindingKind::DoubleMerge {
+                        merge_key: *merge_key,
+                    });
+                    continue;
+                }
+
+                let assets = self.receivable.remove(&(tx.sender, *merge_key));
+                if assets.is_none() {
+                    report(AuditFindingKind::UnbackedMerge {
+                        merge_key: *merge_key,
+                    });
+                }
+                for asset in assets.unwrap_or_default() {
+                    *self
+                        .unspent
+                        .entry((tx.sender, *merge_key, asset.kind))
+                        .or_default() += asset.amount;
+                }
+            }
+
+            let mut input_amounts: Vec<(TokenKind<F>, u64)> = vec![];
+            for leaf in tx.inputs.iter() {
+                let key = (tx.sender, leaf.merge_key, leaf.asset.kind);
+                match self.unspent.get(&key) {
+                    Some(amount) if *amount == leaf.asset.amount => {
+                        self.unspent.remove(&key);
+                        self.spent.insert(key);
+                    }
+                    _ if self.spent.contains(&key) => {
+                        report(AuditFindingKind::DoubleSpend { leaf: *leaf });
+                    }
+                    _ => {
+                        report(AuditFindingKind::UnbackedSpend { leaf: *leaf });
+                    }
+                }
+                add_amount(&mut input_amounts, leaf.asset.kind, leaf.asset.amount);
+            }
+
+            let mut output_amounts: Vec<(TokenKind<F>, u64)> = vec![];
+            for output in tx.outputs.iter() {
+                add_amount(&mut output_amounts, output.asset.kind, output.asset.amount);
+                self.receivable
+                    .entry((output.recipient, transfer_merge_key(*tx.tx_hash)))
+                    .or_default()
+                    .push(output.asset);
+            }
+
+            let mut kinds = input_amounts.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            for (kind, _) in output_amounts.iter() {
+                if !kinds.contains(kind) {
+                    kinds.push(*kind);
+                }
+            }
+            for kind in kinds {
+                let input_amount = get_amount(&input_amounts, kind);
+                let output_amount = get_amount(&output_amounts, kind);
+                if input_amount != output_amount {
+                    report(AuditFindingKind::UnbalancedTransaction {
+                        kind,
+                        input_amount,
+                        output_amount,
+                    });
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+}
+
+fn add_amount(amounts: &mut Vec<(TokenKind<F>, u64)>, kind: TokenKind<F>, amount: u64) {
+    if let Some((_, total)) = amounts.iter_mut().find(|(k, _)| *k == kind) {
+        *total += amount;
+    } else {
+        amounts.push((kind, amount));
+    }
+}
+
+fn get_amount(amounts: &[(TokenKind<F>, u64)], kind: TokenKind<F>) -> u64 {
+    amounts
+        .iter()
+        .find(|(k, _)| *k == kind)
+        .map(|(_, amount)| *amount)
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_double_spend_auditor() {
+    use plonky2::field::types::Field;
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut;
+
+    let alice = Address(*GoldilocksHashOut::from_u32(1));
+    let bob = Address(*GoldilocksHashOut::from_u32(2));
+    let kind = TokenKind {
+        contract_address: Address(*GoldilocksHashOut::from_u32(3)),
+        variable_index: GoldilocksHashOut::from_u32(0),
+    };
+
+    let mut block1 = PublishedBlock {
+        header: BlockHeader::with_tree_depth(2),
+        deposits: vec![DepositInfo {
+            receiver_address: alice,
+            contract_address: kind.contract_address,
+            variable_index: *kind.variable_index,
+            amount: F::from_canonical_u64(10),
+        }],
+        deposit_nonce: GoldilocksHashOut::from_u32(1),
+        transactions: vec![],
+    };
+    block1.header.block_number = 1;
+    let deposit_tree =
+        build_deposit_tree_with_nonce(&[block1.deposits[0].into()], *block1.deposit_nonce, 0)
+            .unwrap();
+    let deposit_key = deposit_merge_key(
+        *deposit_tree.deposit_tx_hashes[0],
+        get_block_hash(&block1.header),
+    );
+
+    // Alice merges the deposit and sends it to Bob.
+    let tx = PublishedTransaction {
+        sender: alice,
+        tx_hash: GoldilocksHashOut::from_u32(100),
+        merge_keys: vec![deposit_key],
+        inputs: vec![AssetLeaf {
+            merge_key: deposit_key,
+            asset: Asset { kind, amount: 10 },
+        }],
+        outputs: vec![SentAsset {
+            recipient: bob,
+            asset: Asset { kind, amount: 10 },
+        }],
+    };
+    let mut block2 = PublishedBlock {
+        header: BlockHeader::with_tree_depth(2),
+        deposits: vec![],
+        deposit_nonce: Default::default(),
+        transactions: vec![tx.clone()],
+    };
+    block2.header.block_number = 2;
+
+    let mut auditor = DoubleSpendAuditor::new();
+    assert!(auditor.replay([&block1, &block2]).unwrap().is_empty());
+    assert_eq!(auditor.balance(alice, kind), 0);
+
+    // Alice spends the same leaf again and Bob merges an unknown transfer.
+    let double_spend = PublishedTransaction {
+        tx_hash: GoldilocksHashOut::from_u32(101),
+        merge_keys: vec![],
+        ..tx.clone()
+    };
+    let unbacked_merge = PublishedTransaction {
+        sender: bob,
+        tx_hash: GoldilocksHashOut::from_u32(102),
+        merge_keys: vec![tx.tx_hash, GoldilocksHashOut::from_u32(999)],
+        inputs: vec![],
+        outputs: vec![],
+    };
+    let mut block3 = PublishedBlock {
+        header: BlockHeader::with_tree_depth(2),
+        deposits: vec![],
+        deposit_nonce: Default::default(),
+        transactions: vec![double_spend, unbacked_merge],
+    };
+    block3.header.block_number = 3;
+    let findings = auditor.replay_block(&block3).unwrap();
+    assert_eq!(
+        findings.iter().map(|f| f.kind.clone()).collect::<Vec<_>>(),
+        vec![
+            AuditFindingKind::DoubleSpend { leaf: tx.inputs[0] },
+            AuditFindingKind::UnbackedMerge {
+                merge_key: GoldilocksHashOut::from_u32(999)
+            },
+        ]
+    );
+    assert_eq!(findings[0].block_number, 3);
+    assert_eq!(auditor.balance(bob, kind), 10);
+}
@@ -0,0 +1,221 @@
+//! The forced transaction lane.
+//!
+//! A user whose transactions the aggregator ignores queues the transaction on L1.
+//! The L1 contract keeps `get_forced_transactions_digest` of every prefix of the queue,
+//! and each block header commits to the digest of the prefix processed so far.
+//! The block circuit only allows the digest to advance over forced transactions which are
+//! either in the block or stale, i.e. the world state leaf of the sender before the block is
+//! not the `middle_user_asset_root` of the transaction. The contract can then reject blocks
+//! which leave a forced transaction unprocessed for too long.
+
+use plonky2::{field::goldilocks_field::GoldilocksField, hash::hash_types::HashOut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::{
+        circuits::N_FORCED_TXS,
+        gadgets::forced_inclusion::{
+            get_current_user_asset_root, get_forced_transactions_digest, ForcedTransaction,
+            ForcedTransactionWitness,
+        },
+    },
+    sparse_merkle_tree::{
+        gadgets::verify::verify_smt::SmtInclusionProof,
+        goldilocks_poseidon::{hash_out_hex_seq, GoldilocksHashOut, PoseidonSparseMerkleTree},
+        node_data::NodeData,
+    },
+    transaction::circuits::MergeAndPurgeTransitionPublicInputs,
+};
+
+type F = GoldilocksField;
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+/// The off-chain mirror of the forced transaction queue of the L1 contract.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForcedTransactionQueue {
+    transactions: Vec<ForcedTransaction<F>>,
+
+    /// `digests[i]` is the digest of the first `i` transactions.
+    #[serde(with = "hash_out_hex_seq")]
+    digests: Vec<HashOut<F>>,
+}
+
+impl Default for ForcedTransactionQueue {
+    fn default() -> Self {
+        Self {
+            transactions: vec![],
+            digests: vec![HashOut::ZERO],
+        }
+    }
+}
+
+/// A forced transaction with the world state leaf of its sender before the block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingForcedTransaction {
+    pub transaction: ForcedTransaction<F>,
+    pub world_state_inclusion_proof: SmtInclusionProof<F>,
+}
+
+impl PendingForcedTransaction {
+    /// Whether the transaction can no longer be included.
+    pub fn is_stale(&self) -> bool {
+        *get_current_user_asset_root(&self.world_state_inclusion_proof)
+            != self.transaction.middle_user_asset_root
+    }
+}
+
+impl ForcedTransactionQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `transaction` as the L1 contract does and returns its index.
+    pub fn push(&mut self, transaction: ForcedTransaction<F>) -> usize {
+        let digest = get_forced_transactions_digest(*self.digests.last().unwrap(), &transaction);
+        self.transactions.push(transaction);
+        self.digests.push(digest);
+
+        self.transactions.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// The digest of the first `watermark` transactions.
+    pub fn digest(&self, watermark: usize) -> Option<HashOut<F>> {
+        self.digests.get(watermark).copied()
+    }
+
+    /// The number of transactions processed when the block header commits to `digest`.
+    pub fn watermark(&self, digest: HashOut<F>) -> Option<usize> {
+        self.digests.iter().position(|d| *d == digest)
+    }
+
+    /// Looks up the senders of the next forced transactions after `watermark`
+    /// in `world_state_tree`, which must be the world state before the block.
+    pub fn pending<Nd: NodeData<K, V, I>>(
+        &self,
+        watermark: usize,
+        world_state_tree: &PoseidonSparseMerkleTree<Nd>,
+    ) -> anyhow::Result<Vec<PendingForcedTransaction>> {
+        anyhow::ensure!(
+            watermark <= self.len(),
+            "the watermark {} exceeds the queue length {}",
+            watermark,
+            self.len()
+        );
+
+        self.transactions[watermark..]
+            .iter()
+            .take(N_FORCED_TXS)
+            .map(|transaction| {
+                let world_state_inclusion_proof =
+                    world_state_tree.find(&transaction.sender_address.0.into())?;
+
+                Ok(PendingForcedTransaction {
+                    transaction: *transaction,
+                    world_state_inclusion_proof,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Processes `pending` in order until a transaction which is neither in `block_transactions` nor stale,
+/// which the aggregator has to include in a later block.
+pub fn make_forced_transaction_witnesses(
+    pending: &[PendingForcedTransaction],
+    block_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+) -> Vec<ForcedTransactionWitness<F>> {
+    let mut witnesses = vec![];
+    for pending_transaction in pending {
+        let transaction = pending_transaction.transaction;
+        let is_included = block_transactions.iter().any(|tx| {
+            tx.sender_address == transaction.sender_address && *tx.tx_hash == transaction.tx_hash
+        });
+        let world_state_inclusion_proof = if is_included {
+            None
+        } else if pending_transaction.is_stale() {
+            Some(pending_transaction.world_state_inclusion_proof.clone())
+        } else {
+            break;
+        };
+
+        witnesses.push(ForcedTransactionWitness {
+            transaction,
+            world_state_inclusion_proof,
+        });
+    }
+
+    witnesses
+}
+
+#[test]
+fn test_forced_transaction_queue() {
+    use std::sync::{Arc, Mutex};
+
+    use plonky2::field::types::Sample;
+
+    use crate::{sparse_merkle_tree::node_data::NodeDataMemory, zkdsa::account::Address};
+
+    let mut world_state_tree = PoseidonSparseMerkleTree::new(
+        Arc::new(Mutex::new(NodeDataMemory::default())),
+        Default::default(),
+    );
+    let senders = [Address::rand(), Address::rand(), Address::rand()];
+    let user_asset_roots = [HashOut::rand(), HashOut::rand(), HashOut::rand()];
+    for (sender, root) in senders.iter().zip(user_asset_roots) {
+        world_state_tree.set(sender.0.into(), root.into()).unwrap();
+    }
+
+    let forced_transactions = [
+        // included in the block
+        ForcedTransaction {
+            sender_address: senders[0],
+            middle_user_asset_root: user_asset_roots[0],
+            tx_hash: HashOut::rand(),
+        },
+        // stale
+        ForcedTransaction {
+            sender_address: senders[1],
+            middle_user_asset_root: HashOut::rand(),
+            tx_hash: HashOut::rand(),
+        },
+        // censored
+        ForcedTransaction {
+            sender_address: senders[2],
+            middle_user_asset_root: user_asset_roots[2],
+            tx_hash: HashOut::rand(),
+        },
+    ];
+    let mut queue = ForcedTransactionQueue::new();
+    for forced_transaction in forced_transactions {
+        queue.push(forced_transaction);
+    }
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.watermark(queue.digest(2).unwrap()), Some(2));
+    assert!(queue.pending(4, &world_state_tree).is_err());
+
+    let block_transaction = MergeAndPurgeTransitionPublicInputs {
+        sender_address: senders[0],
+        tx_hash: forced_transactions[0].tx_hash.into(),
+        ..Default::default()
+    };
+    let pending = queue.pending(0, &world_state_tree).unwrap();
+    assert_eq!(pending.len(), N_FORCED_TXS.min(3));
+    let witnesses = make_forced_transaction_witnesses(&pending, &[block_transaction.clone()]);
+    assert_eq!(witnesses.len(), 2);
+    assert!(witnesses[0].world_state_inclusion_proof.is_none());
+    assert!(witnesses[1].world_state_inclusion_proof.is_some());
+
+    // The censored transaction stops the watermark.
+    let pending = queue.pending(2, &world_state_tree).unwrap();
+    assert!(make_forced_transaction_witnesses(&pending, &[block_transaction]).is_empty());
+}
@@ -1,6 +1,9 @@
 use itertools::Itertools;
 use plonky2::{
-    field::extension::Extendable,
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
     hash::hash_types::{HashOut, HashOutTarget, RichField},
     iop::{
         target::{BoolTarget, Target},
@@ -15,16 +18,28 @@ use plonky2::{
 };
 
 use crate::{
+    errors::{ensure_at_most, ensure_length, ensure_witness, IntmaxError},
     recursion::gadgets::RecursiveProofTarget,
     sparse_merkle_tree::gadgets::{
         common::{enforce_equal_if_enabled, is_equal_hash_out},
-        process::process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+        process::{
+            process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+            utils::enforce_upsert_role_if_enabled,
+        },
     },
     transaction::circuits::{
         MergeAndPurgeTransitionPublicInputs, MergeAndPurgeTransitionPublicInputsTarget,
     },
+    zkdsa::{
+        account::{public_key_to_address, PublicKey},
+        circuits::{parse_simple_signature_public_inputs, SimpleSignaturePublicInputs},
+        gadgets::account::AddressTarget,
+    },
 };
 
+/// Block numbers are stored in the latest account tree as u32.
+const N_LOG_MAX_BLOCKS: usize = 32;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct SignedMessage<F: RichField> {
     message: HashOut<F>,
@@ -168,17 +183,73 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
         received_signatures: &[Option<ProofWithPublicInputs<F, C, D>>],
         default_simple_signature: &ProofWithPublicInputs<F, C, D>,
         latest_account_tree_process_proofs: &[SmtProcessProof<F>],
-    ) where
+    ) -> Result<(), IntmaxError>
+    where
         C::Hasher: AlgebraicHasher<F>,
     {
-        assert!(!user_transactions.is_empty());
-        assert!(user_transactions.len() <= self.user_transactions.len());
-        assert_eq!(world_state_revert_proofs.len(), user_transactions.len());
-        assert_eq!(received_signatures.len(), user_transactions.len());
-        assert_eq!(
+        let received_signers = received_signatures
+            .iter()
+            .map(|r| {
+                r.as_ref()
+                    .map(|r| {
+                        SimpleSignaturePublicInputs::try_decode(&r.public_inputs)
+                            .map(|v| v.public_key)
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        validate_approval_witness(
+            current_block_number,
+            world_state_revert_proofs,
+            user_transactions,
+            &received_signers,
+            latest_account_tree_process_proofs,
+        )?;
+        self.set_transition_witness(
+            pw,
+            current_block_number,
+            world_state_revert_proofs,
+            user_transactions,
+            latest_account_tree_process_proofs,
+        )?;
+
+        for (i, r) in received_signatures.iter().enumerate() {
+            self.set_received_signature_witness(pw, i, r.as_ref(), default_simple_signature);
+        }
+        for i in received_signatures.len()..self.received_signatures.len() {
+            self.set_received_signature_witness(pw, i, None, default_simple_signature);
+        }
+
+        Ok(())
+    }
+
+    /// Sets everything except the received signatures.
+    pub fn set_transition_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        current_block_number: u32,
+        world_state_revert_proofs: &[SmtProcessProof<F>],
+        user_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+        latest_account_tree_process_proofs: &[SmtProcessProof<F>],
+    ) -> Result<(), IntmaxError> {
+        let last_user_transaction = user_transactions.last().ok_or(IntmaxError::EmptyWitness {
+            name: "user transactions",
+        })?;
+        ensure_at_most(
+            "user transactions",
+            user_transactions.len(),
+            self.user_transactions.len(),
+        )?;
+        ensure_length(
+            "world state revert proofs",
+            world_state_revert_proofs.len(),
+            user_transactions.len(),
+        )?;
+        ensure_length(
+            "latest account tree process proofs",
             latest_account_tree_process_proofs.len(),
-            user_transactions.len()
-        );
+            user_transactions.len(),
+        )?;
 
         pw.set_target(
             self.current_block_number,
@@ -206,27 +277,10 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
         for (r_t, r) in self.user_transactions.iter().zip(user_transactions.iter()) {
             r_t.set_witness(pw, r);
         }
-        let last_user_transaction = user_transactions.last().unwrap();
         for r_t in self.user_transactions.iter().skip(user_transactions.len()) {
             r_t.set_witness(pw, last_user_transaction);
         }
 
-        for (r_t, r) in self
-            .received_signatures
-            .iter()
-            .zip(received_signatures.iter())
-        {
-            let r: Option<&_> = r.into();
-            r_t.set_witness(pw, r.unwrap_or(default_simple_signature), r.is_some());
-        }
-        for r_t in self
-            .received_signatures
-            .iter()
-            .skip(received_signatures.len())
-        {
-            r_t.set_witness(pw, default_simple_signature, false);
-        }
-
         for enabled_t in self.enabled_list.iter().take(user_transactions.len()) {
             pw.set_bool_target(*enabled_t, true);
         }
@@ -253,7 +307,127 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
         {
             p_t.set_witness(pw, &default_proof);
         }
+
+        Ok(())
+    }
+
+    /// Sets the signature of the `index`-th slot.
+    /// If no signature was received, `default_simple_signature` is used as a disabled proof.
+    pub fn set_received_signature_witness<
+        F: RichField + Extendable<D>,
+        C: GenericConfig<D, F = F>,
+    >(
+        &self,
+        pw: &mut impl Witness<F>,
+        index: usize,
+        received_signature: Option<&ProofWithPublicInputs<F, C, D>>,
+        default_simple_signature: &ProofWithPublicInputs<F, C, D>,
+    ) where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        self.received_signatures[index].set_witness(
+            pw,
+            received_signature.unwrap_or(default_simple_signature),
+            received_signature.is_some(),
+        );
+    }
+}
+
+/// Checks the same conditions as `ApprovalBlockProofTarget` without proving,
+/// except the received signature proofs themselves.
+/// `received_signers[i]` is the public key of the signature received for `user_transactions[i]`.
+pub fn validate_approval_witness<F: RichField>(
+    current_block_number: u32,
+    world_state_revert_proofs: &[SmtProcessProof<F>],
+    user_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+    received_signers: &[Option<PublicKey<F>>],
+    latest_account_tree_process_proofs: &[SmtProcessProof<F>],
+) -> Result<(), IntmaxError> {
+    if user_transactions.is_empty() {
+        return Err(IntmaxError::EmptyWitness {
+            name: "user transactions",
+        });
+    }
+    ensure_length(
+        "received signatures",
+        received_signers.len(),
+        user_transactions.len(),
+    )?;
+    ensure_length(
+        "world state revert proofs",
+        world_state_revert_proofs.len(),
+        user_transactions.len(),
+    )?;
+    ensure_length(
+        "latest account tree process proofs",
+        latest_account_tree_process_proofs.len(),
+        user_transactions.len(),
+    )?;
+
+    let current_block_number = current_block_number as u64;
+    for (i, (((w, u), signer), a)) in world_state_revert_proofs
+        .iter()
+        .zip(user_transactions)
+        .zip(received_signers)
+        .zip(latest_account_tree_process_proofs)
+        .enumerate()
+    {
+        if let Some(public_key) = signer {
+            ensure_witness!(
+                public_key_to_address(*public_key) == u.sender_address,
+                "received signature #{} is not signed by the sender of the user transaction",
+                i
+            );
+        }
+        let is_signed = signer.is_some();
+        if is_signed && i != 0 {
+            ensure_witness!(
+                w.old_root == world_state_revert_proofs[i - 1].new_root,
+                "world state revert proof #{} must start from the root of the previous one",
+                i
+            );
+            ensure_witness!(
+                a.old_root == latest_account_tree_process_proofs[i - 1].new_root,
+                "latest account tree process proof #{} must start from the root of the previous one",
+                i
+            );
+        }
+
+        ensure_witness!(
+            a.old_value.elements[1..].iter().all(|e| *e == F::ZERO)
+                && a.new_value.elements[1..].iter().all(|e| *e == F::ZERO),
+            "latest account tree process proof #{} must hold only a block number",
+            i
+        );
+        let old_last_block_number = a.old_value.elements[0].to_canonical_u64();
+        let new_last_block_number = a.new_value.elements[0].to_canonical_u64();
+        ensure_witness!(
+            old_last_block_number <= u32::MAX as u64,
+            "the last block number of latest account tree process proof #{} must fit in u32",
+            i
+        );
+        if is_signed {
+            ensure_witness!(
+                old_last_block_number < current_block_number,
+                "the sender of user transaction #{} has already signed in block {}",
+                i,
+                old_last_block_number
+            );
+            ensure_witness!(
+                new_last_block_number == current_block_number && a.fnc.is_upsert(),
+                "latest account tree process proof #{} must set the current block number",
+                i
+            );
+        } else {
+            ensure_witness!(
+                new_last_block_number == old_last_block_number,
+                "latest account tree process proof #{} must not change the last block number",
+                i
+            );
+        }
     }
+
+    Ok(())
 }
 
 /// Returns `(old_world_state_root, new_world_state_root, old_account_tree_root, new_account_tree_root)`
@@ -272,6 +446,7 @@ pub fn verify_valid_approval_block<
     enabled_list: &[BoolTarget],
 ) -> (HashOutTarget, HashOutTarget, HashOutTarget, HashOutTarget) {
     let zero = builder.zero();
+    builder.range_check(current_block_number, N_LOG_MAX_BLOCKS);
 
     // world state process proof と latest account process proof は正しい遷移になるように並んでいる.
     let mut prev_world_state_root = world_state_revert_proofs[0].new_root;
@@ -320,6 +495,17 @@ pub fn verify_valid_approval_block<
         };
         builder.connect(enabled_signature.target, is_not_reverted.target);
 
+        // 承認の署名は user transaction の sender によるものである.
+        let signer_public_key =
+            parse_simple_signature_public_inputs(&r.inner.0.public_inputs).public_key;
+        let signer_address = AddressTarget::from_public_key(signer_public_key);
+        enforce_equal_if_enabled(
+            builder,
+            signer_address.0,
+            u.sender_address,
+            enabled_signature,
+        );
+
         enforce_equal_if_enabled(builder, w.old_root, u.new_user_asset_root, enabled);
         let is_reverted = is_equal_hash_out(builder, w.new_root, u.middle_user_asset_root);
         let is_not_reverted = is_equal_hash_out(builder, w.new_root, w.old_root);
@@ -340,12 +526,25 @@ pub fn verify_valid_approval_block<
         builder.connect(a.new_value.elements[2], zero);
         builder.connect(a.new_value.elements[3], zero);
 
+        // 署名した user の last block number は単調増加する.
+        builder.range_check(old_last_block_number, N_LOG_MAX_BLOCKS);
+        enforce_less_than_if_enabled(
+            builder,
+            old_last_block_number,
+            current_block_number,
+            enabled_signature,
+            N_LOG_MAX_BLOCKS,
+        );
+
         let expected_new_last_block_number = builder._if(
             enabled_signature,
             current_block_number,
             old_last_block_number,
         );
         builder.connect(expected_new_last_block_number, new_last_block_number);
+
+        // 署名した user の latest account は, 初めての承認なら insert, そうでなければ update される.
+        enforce_upsert_role_if_enabled(builder, a.fnc, enabled_signature);
     }
 
     (
@@ -356,6 +555,23 @@ pub fn verify_valid_approval_block<
     )
 }
 
+/// if enabled { assert!(left < right) }
+/// `left` and `right` must be range-checked to `n_log` bits.
+fn enforce_less_than_if_enabled<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    left: Target,
+    right: Target,
+    enabled: BoolTarget,
+    n_log: usize,
+) {
+    // `right - left - 1` wraps around the field order unless `left < right`.
+    let one = builder.one();
+    let diff = builder.sub(right, left);
+    let diff = builder.sub(diff, one);
+    let diff = builder.mul(diff, enabled.target);
+    builder.range_check(diff, n_log);
+}
+
 #[test]
 fn test_approval_block() {
     use std::{
@@ -387,6 +603,7 @@ fn test_approval_block() {
             block_header::{get_block_hash, BlockHeader},
             circuits::make_user_proof_circuit,
             gadgets::merge::MergeProof,
+            merge_key::deposit_merge_key,
         },
         zkdsa::{account::private_key_to_account, circuits::make_simple_signature_circuit},
     };
@@ -554,11 +771,15 @@ fn test_approval_block() {
         proposed_world_state_digest: default_hash,
         approved_world_state_digest: default_hash,
         latest_account_digest: default_hash,
+        forced_transactions_digest: default_hash,
+        issuer_registry_root: default_hash,
+        timestamp: 0,
+        aggregator_address: default_hash,
     };
 
     let block_hash = get_block_hash(&prev_block_header);
 
-    let deposit_merge_key = PoseidonHash::two_to_one(deposit_tx_hash, block_hash).into();
+    let deposit_merge_key = deposit_merge_key(deposit_tx_hash, block_hash);
 
     let merge_process_proof = sender2_user_asset_tree
         .set(deposit_merge_key, merge_inclusion_proof2.value)
@@ -613,7 +834,8 @@ fn test_approval_block() {
             &mut pw,
             &[],
             *sender1_input_witness.first().unwrap().0.old_root,
-        );
+        )
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -624,7 +846,8 @@ fn test_approval_block() {
             &sender1_output_witness,
             sender1_input_witness.first().unwrap().0.old_root,
             sender1_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender1_tx_proof");
     let start = Instant::now();
@@ -645,7 +868,8 @@ fn test_approval_block() {
     merge_and_purge_circuit
         .targets
         .merge_proof_target
-        .set_witness(&mut pw, &[merge_proof], default_hash);
+        .set_witness(&mut pw, &[merge_proof], default_hash)
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -656,7 +880,8 @@ fn test_approval_block() {
             &sender2_output_witness,
             sender2_input_witness.first().unwrap().0.old_root,
             sender2_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender2_tx_proof");
     let start = Instant::now();
@@ -785,21 +1010,23 @@ fn test_approval_block() {
     }
 
     let mut pw = PartialWitness::new();
-    approval_block_target.set_witness(
-        &mut pw,
-        block_number,
-        &world_state_revert_proofs,
-        &user_tx_proofs
-            .iter()
-            .map(|p| p.public_inputs.clone())
-            .collect::<Vec<_>>(),
-        &received_signatures
-            .iter()
-            .map(|p| p.clone().map(ProofWithPublicInputs::from))
-            .collect::<Vec<_>>(),
-        &ProofWithPublicInputs::from(default_simple_signature),
-        &latest_account_tree_process_proofs,
-    );
+    approval_block_target
+        .set_witness(
+            &mut pw,
+            block_number,
+            &world_state_revert_proofs,
+            &user_tx_proofs
+                .iter()
+                .map(|p| p.public_inputs.clone())
+                .collect::<Vec<_>>(),
+            &received_signatures
+                .iter()
+                .map(|p| p.clone().map(ProofWithPublicInputs::from))
+                .collect::<Vec<_>>(),
+            &ProofWithPublicInputs::from(default_simple_signature),
+            &latest_account_tree_process_proofs,
+        )
+        .unwrap();
 
     println!("start proving: block_proof");
     let start = Instant::now();
@@ -812,3 +1039,169 @@ fn test_approval_block() {
         Err(x) => println!("{}", x),
     }
 }
+
+#[test]
+fn test_enforce_less_than_if_enabled() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Field},
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let left = builder.add_virtual_target();
+    let right = builder.add_virtual_target();
+    let enabled = builder.add_virtual_bool_target_safe();
+    builder.range_check(left, N_LOG_MAX_BLOCKS);
+    builder.range_check(right, N_LOG_MAX_BLOCKS);
+    enforce_less_than_if_enabled(&mut builder, left, right, enabled, N_LOG_MAX_BLOCKS);
+    let data = builder.build::<C>();
+
+    let prove = |l: u32, r: u32, e: bool| {
+        let mut pw = PartialWitness::new();
+        pw.set_target(left, F::from_canonical_u32(l));
+        pw.set_target(right, F::from_canonical_u32(r));
+        pw.set_bool_target(enabled, e);
+
+        data.prove(pw)
+    };
+
+    data.verify(prove(1, 2, true).unwrap()).unwrap();
+    data.verify(prove(0, u32::MAX, true).unwrap()).unwrap();
+    data.verify(prove(2, 2, false).unwrap()).unwrap();
+
+    // plonky2 panics in the witness generation with debug assertions, otherwise the proof is invalid.
+    let is_rejected = |l: u32, r: u32| match catch_unwind(AssertUnwindSafe(|| prove(l, r, true))) {
+        Ok(Ok(proof)) => data.verify(proof).is_err(),
+        _ => true,
+    };
+    assert!(is_rejected(2, 2));
+    assert!(is_rejected(3, 2));
+}
+
+#[test]
+fn test_approval_block_signed_by_other_key() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{circuit_builder::CircuitBuilder, circuit_data::CircuitConfig},
+    };
+
+    use crate::{
+        fixtures::{
+            make_sample_accounts, make_sample_block, make_sample_user_tx,
+            make_sample_user_tx_circuit, C, D, F, N_LOG_MAX_USERS, N_TXS,
+        },
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree,
+        },
+        zkdsa::circuits::make_simple_signature_circuit,
+    };
+
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    let mut block = make_sample_block(&user_tx_circuit, &user_txs).unwrap();
+
+    let zkdsa_circuit = make_simple_signature_circuit();
+    let mut pw = PartialWitness::new();
+    zkdsa_circuit
+        .targets
+        .set_witness(&mut pw, Default::default(), Default::default());
+    let default_simple_signature = ProofWithPublicInputs::from(zkdsa_circuit.prove(pw).unwrap());
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let approval_block_target: ApprovalBlockProofTarget<D, N_LOG_MAX_USERS, N_TXS> =
+        ApprovalBlockProofTarget::add_virtual_to(&mut builder, &zkdsa_circuit.data);
+    let circuit_data = builder.build::<C>();
+
+    // Both senders approve the block.
+    let block_number = 1;
+    let user_transactions = user_txs
+        .proofs
+        .iter()
+        .map(|p| p.public_inputs.clone())
+        .collect::<Vec<_>>();
+    let mut latest_account_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    let mut world_state_revert_proofs = vec![];
+    let mut latest_account_tree_process_proofs = vec![];
+    for u in user_transactions.iter() {
+        latest_account_tree_process_proofs.push(
+            latest_account_tree
+                .set(
+                    u.sender_address.0.into(),
+                    GoldilocksHashOut::from_u32(block_number),
+                )
+                .unwrap(),
+        );
+        world_state_revert_proofs.push(
+            block
+                .world_state_tree
+                .set(u.sender_address.0.into(), u.new_user_asset_root)
+                .unwrap(),
+        );
+    }
+
+    let received_signatures = block
+        .received_signatures
+        .iter()
+        .map(|p| Some(ProofWithPublicInputs::from(p.clone())))
+        .collect::<Vec<_>>();
+    let prove = |received_signatures: &[Option<ProofWithPublicInputs<F, C, D>>]| {
+        let mut pw = PartialWitness::new();
+        approval_block_target
+            .set_transition_witness(
+                &mut pw,
+                block_number,
+                &world_state_revert_proofs,
+                &user_transactions,
+                &latest_account_tree_process_proofs,
+            )
+            .unwrap();
+        for (i, r) in received_signatures.iter().enumerate() {
+            approval_block_target.set_received_signature_witness(
+                &mut pw,
+                i,
+                r.as_ref(),
+                &default_simple_signature,
+            );
+        }
+
+        circuit_data.prove(pw)
+    };
+
+    circuit_data
+        .verify(prove(&received_signatures).unwrap())
+        .unwrap();
+
+    // The first sender's slot carries the signature of the second sender to the same message.
+    let signed_by_other_key = vec![
+        received_signatures[1].clone(),
+        received_signatures[1].clone(),
+    ];
+    let mut pw = PartialWitness::new();
+    assert!(matches!(
+        approval_block_target.set_witness(
+            &mut pw,
+            block_number,
+            &world_state_revert_proofs,
+            &user_transactions,
+            &signed_by_other_key,
+            &default_simple_signature,
+            &latest_account_tree_process_proofs,
+        ),
+        Err(IntmaxError::InvalidWitness(_))
+    ));
+
+    let result = catch_unwind(AssertUnwindSafe(|| prove(&signed_by_other_key)));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
@@ -10,7 +10,9 @@ use plonky2::{
     },
 };
 
-use crate::recursion::gadgets::RecursiveProofTarget;
+use crate::{
+    recursion::gadgets::RecursiveProofTarget, verification::public_inputs::protocol_version,
+};
 
 #[derive(Clone)]
 pub struct BatchBlockProofTarget<const D: usize, const N_BLOCKS: usize> {
@@ -25,11 +27,14 @@ impl<const D: usize, const N_BLOCKS: usize> BatchBlockProofTarget<D, N_BLOCKS> {
     where
         C::Hasher: AlgebraicHasher<F>,
     {
+        // The block proofs of other protocol versions are rejected.
+        let version = builder.constant(protocol_version());
         let mut block_proofs = vec![];
 
         for _ in 0..N_BLOCKS {
             let target: RecursiveProofTarget<D> =
                 RecursiveProofTarget::add_virtual_to::<F, C>(builder, block_circuit_data);
+            builder.connect(*target.inner.public_inputs.last().unwrap(), version);
             block_proofs.push(target);
         }
 
@@ -59,3 +64,44 @@ impl<const D: usize, const N_BLOCKS: usize> BatchBlockProofTarget<D, N_BLOCKS> {
         }
     }
 }
+
+#[test]
+fn test_batch_block_protocol_version_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::types::{Field, Sample},
+        hash::hash_types::HashOut,
+        iop::witness::PartialWitness,
+        plonk::circuit_data::CircuitConfig,
+    };
+
+    use crate::{
+        fixtures::{make_public_inputs_circuit, C, D, F},
+        verification::public_inputs::{BlockCommitmentPublicInputs, PROTOCOL_VERSION},
+    };
+
+    // The block proofs only expose a commitment followed by the protocol version.
+    let block_circuit = make_public_inputs_circuit(BlockCommitmentPublicInputs::<F>::LEN);
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets: BatchBlockProofTarget<D, 2> =
+        BatchBlockProofTarget::add_virtual_to(&mut builder, &block_circuit.data);
+    let data = builder.build::<C>();
+
+    let prove = |version: u32| {
+        let mut public_inputs = HashOut::<F>::rand().elements.to_vec();
+        public_inputs.push(F::from_canonical_u32(version));
+        let block_proof = block_circuit.prove(&public_inputs).unwrap();
+        let mut pw = PartialWitness::new();
+        targets.set_witness(&mut pw, &[block_proof]);
+
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| data.verify(proof))
+        }))
+    };
+
+    prove(PROTOCOL_VERSION).unwrap().unwrap();
+
+    // The block proof of another protocol version is rejected.
+    assert!(!matches!(prove(PROTOCOL_VERSION + 1), Ok(Ok(_))));
+}
@@ -0,0 +1,249 @@
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::{target::BoolTarget, witness::Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CommonCircuitData, VerifierCircuitTarget, VerifierOnlyCircuitData},
+        config::{AlgebraicHasher, GenericConfig, Hasher},
+        proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget},
+    },
+};
+
+use crate::{
+    errors::IntmaxError,
+    recursion::gadgets::{
+        add_virtual_verifier_data, get_verifier_digest, get_verifier_digest_target, Wrapper,
+    },
+    sparse_merkle_tree::gadgets::{
+        common::{conditionally_select, enforce_equal_if_enabled, logical_or, poseidon_two_to_one},
+        process::{
+            process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+            utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
+        },
+    },
+    zkdsa::{account::Address, gadgets::account::AddressTarget},
+};
+
+/// The leading public inputs of every contract circuit: `old_state_root` and `new_state_root`.
+/// The public inputs after them are specific to the contract.
+pub const CONTRACT_TRANSITION_PUBLIC_INPUTS_LEN: usize = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ContractTransitionPublicInputs<F: RichField> {
+    pub old_state_root: HashOut<F>,
+    pub new_state_root: HashOut<F>,
+}
+
+impl<F: RichField> ContractTransitionPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        [self.old_state_root.elements, self.new_state_root.elements].concat()
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() < CONTRACT_TRANSITION_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: CONTRACT_TRANSITION_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self {
+            old_state_root: HashOut::from_partial(&public_inputs[0..4]),
+            new_state_root: HashOut::from_partial(&public_inputs[4..8]),
+        })
+    }
+}
+
+/// The digest of the verifier data of a contract circuit, which the world state leaf of the contract commits to.
+pub fn get_contract_verifier_digest<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+) -> HashOut<F>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    get_verifier_digest(verifier_only)
+}
+
+/// `"contract"` as a big-endian integer, which is hashed first into the address of a contract.
+pub const CONTRACT_ADDRESS_DOMAIN_TAG: u64 = u64::from_be_bytes(*b"contract");
+
+/// The address of the contract whose circuit has `verifier_digest`, i.e. the key of its world state leaf.
+/// A user address is the two-to-one hash of the private key with itself,
+/// so the domain tag keeps a contract from taking over the leaf of a user.
+pub fn get_contract_address<F: RichField>(verifier_digest: HashOut<F>) -> Address<F> {
+    let mut inputs = vec![F::from_canonical_u64(CONTRACT_ADDRESS_DOMAIN_TAG)];
+    inputs.extend(verifier_digest.elements);
+
+    Address(PoseidonHash::hash_no_pad(&inputs))
+}
+
+pub fn get_contract_address_target<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    verifier_digest: HashOutTarget,
+) -> AddressTarget {
+    let mut inputs = vec![builder.constant(F::from_canonical_u64(CONTRACT_ADDRESS_DOMAIN_TAG))];
+    inputs.extend(verifier_digest.elements);
+
+    AddressTarget(builder.hash_n_to_hash_no_pad::<H>(inputs))
+}
+
+/// The world state leaf of a contract with the state `state_root`.
+pub fn get_contract_leaf<F: RichField>(
+    verifier_digest: HashOut<F>,
+    state_root: HashOut<F>,
+) -> HashOut<F> {
+    PoseidonHash::two_to_one(verifier_digest, state_root)
+}
+
+/// A call of a contract, which updates its world state leaf with a proof of its contract circuit.
+/// An insert deploys the contract from the empty state.
+/// The leaf is at `get_contract_address` of the verifier digest, so a circuit has only one contract.
+#[derive(Clone)]
+pub struct ContractCallTarget<const D: usize, const N_LOG_USERS: usize> {
+    pub contract_proof: Wrapper<ProofWithPublicInputsTarget<D>>,
+    pub verifier_data: VerifierCircuitTarget,
+    pub world_state_process_proof: SparseMerkleProcessProofTarget<N_LOG_USERS>,
+    pub enabled: BoolTarget,
+
+    pub old_world_state_root: HashOutTarget, // input
+
+    pub new_world_state_root: HashOutTarget, // output
+}
+
+impl<const D: usize, const N_LOG_USERS: usize> ContractCallTarget<D, N_LOG_USERS> {
+    /// Every contract circuit must have `contract_common_data`, e.g. be padded to the same degree.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        builder: &mut CircuitBuilder<F, D>,
+        contract_common_data: &CommonCircuitData<F, D>,
+        old_world_state_root: HashOutTarget,
+    ) -> Self
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        assert!(
+            contract_common_data.num_public_inputs >= CONTRACT_TRANSITION_PUBLIC_INPUTS_LEN,
+            "a contract circuit must begin its public inputs with the old and new state roots"
+        );
+        let zero = builder.zero();
+        let default_hash = HashOutTarget {
+            elements: [zero; 4],
+        };
+        let constant_true = builder._true();
+        let constant_false = builder._false();
+
+        // The verifier data is a witness, unlike `RecursiveProofTarget`.
+        let contract_proof = builder.add_virtual_proof_with_pis::<C>(contract_common_data);
+        let verifier_data = add_virtual_verifier_data(builder, contract_common_data);
+        builder.verify_proof::<C>(contract_proof.clone(), &verifier_data, contract_common_data);
+        let enabled = builder.add_virtual_bool_target_safe();
+
+        let verifier_digest = get_verifier_digest_target::<F, C, D>(builder, &verifier_data);
+        let old_state_root = HashOutTarget::from_partial(&contract_proof.public_inputs[0..4], zero);
+        let new_state_root = HashOutTarget::from_partial(&contract_proof.public_inputs[4..8], zero);
+
+        let world_state_process_proof =
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, C::Hasher, D>(builder);
+        builder.connect_hashes(world_state_process_proof.old_root, old_world_state_root);
+        let contract_address =
+            get_contract_address_target::<F, C::Hasher, D>(builder, verifier_digest);
+        enforce_equal_if_enabled(
+            builder,
+            world_state_process_proof.new_key,
+            contract_address.0,
+            enabled,
+        );
+        let ProcessMerkleProofRoleTarget {
+            is_no_op,
+            is_insert_op,
+            is_update_op,
+            is_remove_op,
+            ..
+        } = get_process_merkle_proof_role(builder, world_state_process_proof.fnc);
+
+        // 無効な call は world state を変えず, 有効な call は contract を作成するか更新する.
+        let is_no_op_or_enabled = logical_or(builder, is_no_op, enabled);
+        builder.connect(is_no_op_or_enabled.target, constant_true.target);
+        let is_enabled_but_not_changed = builder.and(enabled, is_no_op);
+        builder.connect(is_enabled_but_not_changed.target, constant_false.target);
+        builder.connect(is_remove_op.target, constant_false.target);
+
+        let is_update_op_and_enabled = builder.and(is_update_op, enabled);
+        let old_leaf =
+            poseidon_two_to_one::<F, C::Hasher, D>(builder, verifier_digest, old_state_root);
+        enforce_equal_if_enabled(
+            builder,
+            world_state_process_proof.old_value,
+            old_leaf,
+            is_update_op_and_enabled,
+        );
+        let is_insert_op_and_enabled = builder.and(is_insert_op, enabled);
+        enforce_equal_if_enabled(
+            builder,
+            old_state_root,
+            default_hash,
+            is_insert_op_and_enabled,
+        );
+        let new_leaf =
+            poseidon_two_to_one::<F, C::Hasher, D>(builder, verifier_digest, new_state_root);
+        enforce_equal_if_enabled(
+            builder,
+            world_state_process_proof.new_value,
+            new_leaf,
+            enabled,
+        );
+
+        let new_world_state_root = conditionally_select(
+            builder,
+            world_state_process_proof.new_root,
+            old_world_state_root,
+            enabled,
+        );
+
+        Self {
+            contract_proof: Wrapper(contract_proof),
+            verifier_data,
+            world_state_process_proof,
+            enabled,
+            old_world_state_root,
+            new_world_state_root,
+        }
+    }
+
+    /// A disabled call still needs a valid proof of a contract circuit,
+    /// and `world_state_process_proof` must be a no-op from the old world state root.
+    pub fn set_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        contract_proof: &ProofWithPublicInputs<F, C, D>,
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+        world_state_process_proof: &SmtProcessProof<F>,
+        enabled: bool,
+    ) where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        pw.set_proof_with_pis_target(&self.contract_proof, contract_proof);
+        pw.set_cap_target(
+            &self.verifier_data.constants_sigmas_cap,
+            &verifier_only.constants_sigmas_cap,
+        );
+        pw.set_hash_target(
+            self.verifier_data.circuit_digest,
+            verifier_only.circuit_digest,
+        );
+        self.world_state_process_proof
+            .set_witness(pw, world_state_process_proof);
+        pw.set_bool_target(self.enabled, enabled);
+    }
+}
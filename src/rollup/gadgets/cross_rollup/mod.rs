@@ -0,0 +1,274 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::{target::Target, witness::Witness},
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::{
+    errors::{ensure_witness, IntmaxError},
+    merkle_tree::{gadgets::MerkleProofTarget, tree::MerkleProof},
+    poseidon::gadgets::poseidon_two_to_one,
+    sparse_merkle_tree::gadgets::verify::verify_smt::{
+        LayeredLayeredSmtInclusionProof, SmtInclusionProof, SparseMerkleInclusionProofTarget,
+    },
+    transaction::merge_key::deposit_tx_hash_target,
+    zkdsa::gadgets::account::AddressTarget,
+};
+
+/// The witness of a transfer of tokens from the rollup A to the rollup B.
+#[derive(Clone, Debug)]
+pub struct CrossRollupTransferWitness<F: RichField> {
+    /// The identifier of the rollup B.
+    pub destination: HashOut<F>,
+
+    /// The inclusion proof of the lock account `cross_rollup_lock_address(destination, recipient)`
+    /// in the world state tree of the rollup A.
+    pub lock_account_inclusion_proof: SmtInclusionProof<F>,
+
+    /// The inclusion proof of `lock_key -> contract -> variable -> amount` in the asset tree of the lock account.
+    pub lock_asset_inclusion_proof: LayeredLayeredSmtInclusionProof<F>,
+
+    /// The nonce of the deposit transaction in the rollup B, e.g. `l1_deposit_nonce`.
+    pub nonce: HashOut<F>,
+
+    /// The inclusion proof of the deposit transaction in the deposit digest of the rollup B.
+    pub deposit_tx_inclusion_proof: MerkleProof<F>,
+
+    /// The inclusion proof of `recipient -> contract -> variable -> amount` in the deposit tree.
+    pub deposit_inclusion_proof: LayeredLayeredSmtInclusionProof<F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CrossRollupTransferTarget<
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+> {
+    pub destination: HashOutTarget, // input
+    pub lock_account_inclusion_proof: SparseMerkleInclusionProofTarget<N_LOG_MAX_USERS>, // input
+    pub lock_asset_inclusion_proof: (
+        SparseMerkleInclusionProofTarget<N_LOG_MAX_TXS>,
+        SparseMerkleInclusionProofTarget<N_LOG_MAX_CONTRACTS>,
+        SparseMerkleInclusionProofTarget<N_LOG_MAX_VARIABLES>,
+    ), // input
+    pub nonce: HashOutTarget,       // input
+    pub deposit_tx_inclusion_proof: MerkleProofTarget<N_LOG_TXS>, // input
+    pub deposit_inclusion_proof: (
+        SparseMerkleInclusionProofTarget<N_LOG_RECIPIENTS>,
+        SparseMerkleInclusionProofTarget<N_LOG_CONTRACTS>,
+        SparseMerkleInclusionProofTarget<N_LOG_VARIABLES>,
+    ), // input
+
+    pub source_world_state_root: HashOutTarget, // output
+    pub destination_deposit_digest: HashOutTarget, // output
+    pub lock_key: HashOutTarget,                // output
+    pub recipient: AddressTarget,               // output
+    pub contract_address: AddressTarget,        // output
+    pub variable_index: HashOutTarget,          // output
+    pub amount: Target,                         // output
+}
+
+/// The in-circuit counterpart of `cross_rollup_lock_address`.
+pub fn cross_rollup_lock_address_target<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    destination: HashOutTarget,
+    recipient: AddressTarget,
+) -> AddressTarget {
+    AddressTarget(poseidon_two_to_one::<F, H, D>(
+        builder,
+        destination,
+        recipient.0,
+    ))
+}
+
+fn enforce_inclusion<F: RichField + Extendable<D>, const D: usize, const N_LEVELS: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    proof_t: &SparseMerkleInclusionProofTarget<N_LEVELS>,
+) {
+    let constant_true = builder._true();
+    let constant_false = builder._false();
+    builder.connect(proof_t.enabled.target, constant_true.target);
+    builder.connect(proof_t.fnc.target, constant_false.target);
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+    >
+    CrossRollupTransferTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+    >
+{
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let destination = builder.add_virtual_hash();
+        let lock_account_inclusion_proof =
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder);
+        let lock_asset_inclusion_proof = (
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+        );
+        let nonce = builder.add_virtual_hash();
+        let deposit_tx_inclusion_proof = MerkleProofTarget::add_virtual_to::<F, H, D>(builder);
+        let deposit_inclusion_proof = (
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
+        );
+
+        enforce_inclusion(builder, &lock_account_inclusion_proof);
+        enforce_inclusion(builder, &lock_asset_inclusion_proof.0);
+        enforce_inclusion(builder, &lock_asset_inclusion_proof.1);
+        enforce_inclusion(builder, &lock_asset_inclusion_proof.2);
+        enforce_inclusion(builder, &deposit_inclusion_proof.0);
+        enforce_inclusion(builder, &deposit_inclusion_proof.1);
+        enforce_inclusion(builder, &deposit_inclusion_proof.2);
+
+        // The tokens are locked in the rollup A for the recipient in the rollup B.
+        let recipient = AddressTarget(deposit_inclusion_proof.0.key);
+        let lock_address =
+            cross_rollup_lock_address_target::<F, H, D>(builder, destination, recipient);
+        builder.connect_hashes(lock_account_inclusion_proof.key, lock_address.0);
+        builder.connect_hashes(
+            lock_account_inclusion_proof.value,
+            lock_asset_inclusion_proof.0.root,
+        );
+        builder.connect_hashes(
+            lock_asset_inclusion_proof.0.value,
+            lock_asset_inclusion_proof.1.root,
+        );
+        builder.connect_hashes(
+            lock_asset_inclusion_proof.1.value,
+            lock_asset_inclusion_proof.2.root,
+        );
+
+        // The same tokens are deposited to the recipient in the rollup B.
+        let zero = builder.zero();
+        let deposit_tx_hash =
+            deposit_tx_hash_target::<F, H, D>(builder, deposit_inclusion_proof.0.root, nonce);
+        builder.connect_hashes(deposit_tx_inclusion_proof.value, deposit_tx_hash);
+        builder.connect_hashes(
+            deposit_inclusion_proof.0.value,
+            deposit_inclusion_proof.1.root,
+        );
+        builder.connect_hashes(
+            deposit_inclusion_proof.1.value,
+            deposit_inclusion_proof.2.root,
+        );
+        builder.connect_hashes(
+            lock_asset_inclusion_proof.1.key,
+            deposit_inclusion_proof.1.key,
+        );
+        builder.connect_hashes(
+            lock_asset_inclusion_proof.2.key,
+            deposit_inclusion_proof.2.key,
+        );
+        builder.connect_hashes(
+            lock_asset_inclusion_proof.2.value,
+            deposit_inclusion_proof.2.value,
+        );
+        for e in deposit_inclusion_proof.2.value.elements.iter().skip(1) {
+            builder.connect(*e, zero);
+        }
+
+        Self {
+            destination,
+            source_world_state_root: lock_account_inclusion_proof.root,
+            destination_deposit_digest: deposit_tx_inclusion_proof.root,
+            lock_key: lock_asset_inclusion_proof.0.key,
+            recipient,
+            contract_address: AddressTarget(deposit_inclusion_proof.1.key),
+            variable_index: deposit_inclusion_proof.2.key,
+            amount: deposit_inclusion_proof.2.value.elements[0],
+            nonce,
+            lock_account_inclusion_proof,
+            lock_asset_inclusion_proof,
+            deposit_tx_inclusion_proof,
+            deposit_inclusion_proof,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &CrossRollupTransferWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        let proofs = [
+            &witness.lock_account_inclusion_proof,
+            &witness.lock_asset_inclusion_proof.0,
+            &witness.lock_asset_inclusion_proof.1,
+            &witness.lock_asset_inclusion_proof.2,
+            &witness.deposit_inclusion_proof.0,
+            &witness.deposit_inclusion_proof.1,
+            &witness.deposit_inclusion_proof.2,
+        ];
+        for (i, proof) in proofs.into_iter().enumerate() {
+            ensure_witness!(proof.found, "inclusion proof #{} is not found", i);
+        }
+
+        pw.set_hash_target(self.destination, witness.destination);
+        self.lock_account_inclusion_proof.set_witness(
+            pw,
+            &witness.lock_account_inclusion_proof,
+            true,
+        );
+        self.lock_asset_inclusion_proof.0.set_witness(
+            pw,
+            &witness.lock_asset_inclusion_proof.0,
+            true,
+        );
+        self.lock_asset_inclusion_proof.1.set_witness(
+            pw,
+            &witness.lock_asset_inclusion_proof.1,
+            true,
+        );
+        self.lock_asset_inclusion_proof.2.set_witness(
+            pw,
+            &witness.lock_asset_inclusion_proof.2,
+            true,
+        );
+        pw.set_hash_target(self.nonce, witness.nonce);
+        self.deposit_tx_inclusion_proof.set_witness(
+            pw,
+            witness.deposit_tx_inclusion_proof.index,
+            witness.deposit_tx_inclusion_proof.value,
+            &witness.deposit_tx_inclusion_proof.siblings,
+        );
+        self.deposit_inclusion_proof
+            .0
+            .set_witness(pw, &witness.deposit_inclusion_proof.0, true);
+        self.deposit_inclusion_proof
+            .1
+            .set_witness(pw, &witness.deposit_inclusion_proof.1, true);
+        self.deposit_inclusion_proof
+            .2
+            .set_witness(pw, &witness.deposit_inclusion_proof.2, true);
+
+        Ok(())
+    }
+}
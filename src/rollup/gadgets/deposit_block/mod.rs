@@ -7,6 +7,9 @@ use plonky2::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    errors::{ensure_at_most, IntmaxError},
+    merkle_tree::tree::zero_hashes,
+    poseidon::gadgets::poseidon_two_to_one,
     sparse_merkle_tree::{
         gadgets::{
             common::conditionally_select,
@@ -20,6 +23,7 @@ use crate::{
         },
         goldilocks_poseidon::GoldilocksHashOut,
     },
+    transaction::merge_key::deposit_tx_hash_target,
     zkdsa::{account::Address, gadgets::account::AddressTarget},
 };
 
@@ -119,6 +123,7 @@ impl DepositInfoTarget {
 #[derive(Clone, Debug)]
 pub struct DepositBlockProofTarget<
     const D: usize,
+    const N_LOG_TXS: usize,
     const N_LOG_RECIPIENTS: usize,
     const N_LOG_CONTRACTS: usize,
     const N_LOG_VARIABLES: usize,
@@ -130,16 +135,32 @@ pub struct DepositBlockProofTarget<
         SparseMerkleProcessProofTarget<N_LOG_VARIABLES>,
     ); N_DEPOSITS], // input
 
+    /// The nonce of the deposit transaction, e.g. `l1_deposit_nonce` of the L1 event.
+    pub nonce: HashOutTarget, // input
+
+    pub deposit_tree_root: HashOutTarget, // output
+
+    /// The Merkle root of `[deposit_tx_hash(deposit_tree_root, nonce)]` with `N_LOG_TXS` levels,
+    /// i.e. `DepositTree::deposit_digest`.
     pub deposit_digest: HashOutTarget, // output
 }
 
 impl<
         const D: usize,
+        const N_LOG_TXS: usize,
         const N_LOG_RECIPIENTS: usize,
         const N_LOG_CONTRACTS: usize,
         const N_LOG_VARIABLES: usize,
         const N_DEPOSITS: usize,
-    > DepositBlockProofTarget<D, N_LOG_RECIPIENTS, N_LOG_CONTRACTS, N_LOG_VARIABLES, N_DEPOSITS>
+    >
+    DepositBlockProofTarget<
+        D,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DEPOSITS,
+    >
 {
     pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>>(
         builder: &mut CircuitBuilder<F, D>,
@@ -155,10 +176,22 @@ impl<
             deposit_process_proofs.push(targets);
         }
 
-        let deposit_digest = deposit_process_proofs.last().unwrap().0.new_root;
+        let deposit_tree_root = deposit_process_proofs.last().unwrap().0.new_root;
+
+        // All the deposits of a block are in one deposit transaction,
+        // which is the leftmost leaf of the deposit digest and the rest are zero.
+        let nonce = builder.add_virtual_hash();
+        let mut deposit_digest =
+            deposit_tx_hash_target::<F, H, D>(builder, deposit_tree_root, nonce);
+        for zero_hash in zero_hashes::<F>(N_LOG_TXS.max(1)) {
+            let zero_hash = builder.constant_hash(*zero_hash);
+            deposit_digest = poseidon_two_to_one::<F, H, D>(builder, deposit_digest, zero_hash);
+        }
 
         Self {
             deposit_process_proofs: deposit_process_proofs.try_into().unwrap(),
+            nonce,
+            deposit_tree_root,
             deposit_digest,
         }
     }
@@ -167,9 +200,20 @@ impl<
         &self,
         pw: &mut impl Witness<F>,
         deposit_process_proofs: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
-    ) {
-        assert!(!deposit_process_proofs.is_empty());
-        assert!(deposit_process_proofs.len() <= self.deposit_process_proofs.len());
+        nonce: HashOut<F>,
+    ) -> Result<(), IntmaxError> {
+        let latest_root = deposit_process_proofs
+            .last()
+            .ok_or(IntmaxError::EmptyWitness {
+                name: "deposit process proofs",
+            })?
+            .0
+            .new_root;
+        ensure_at_most(
+            "deposit process proofs",
+            deposit_process_proofs.len(),
+            self.deposit_process_proofs.len(),
+        )?;
         for (proof_t, proof) in self
             .deposit_process_proofs
             .iter()
@@ -180,7 +224,6 @@ impl<
             proof_t.2.set_witness(pw, &proof.2);
         }
 
-        let latest_root = deposit_process_proofs.last().unwrap().0.new_root;
         let default_proof = SmtProcessProof::with_root(Default::default());
         let default_proof0 = SmtProcessProof::with_root(latest_root);
         for proof_t in self
@@ -192,6 +235,10 @@ impl<
             proof_t.1.set_witness(pw, &default_proof);
             proof_t.2.set_witness(pw, &default_proof);
         }
+
+        pw.set_hash_target(self.nonce, nonce);
+
+        Ok(())
     }
 }
 
@@ -272,7 +319,12 @@ fn test_deposit_block() {
 
     use crate::{
         merkle_tree::tree::get_merkle_proof,
-        rollup::gadgets::deposit_block::DepositInfo,
+        rollup::{
+            deposit::{
+                build_deposit_tree, build_deposit_tree_with_nonce, l1_deposit_nonce, Deposit,
+            },
+            gadgets::deposit_block::DepositInfo,
+        },
         sparse_merkle_tree::{
             goldilocks_poseidon::{
                 GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
@@ -284,6 +336,7 @@ fn test_deposit_block() {
             block_header::{get_block_hash, BlockHeader},
             circuits::make_user_proof_circuit,
             gadgets::merge::MergeProof,
+            merge_key::deposit_merge_key,
         },
         zkdsa::{
             account::{private_key_to_account, Address},
@@ -455,11 +508,15 @@ fn test_deposit_block() {
         proposed_world_state_digest: default_hash,
         approved_world_state_digest: default_hash,
         latest_account_digest: default_hash,
+        forced_transactions_digest: default_hash,
+        issuer_registry_root: default_hash,
+        timestamp: 0,
+        aggregator_address: default_hash,
     };
 
     let block_hash = get_block_hash(&prev_block_header);
 
-    let deposit_merge_key = PoseidonHash::two_to_one(deposit_tx_hash, block_hash).into();
+    let deposit_merge_key = deposit_merge_key(deposit_tx_hash, block_hash);
 
     let merge_process_proof = sender2_user_asset_tree
         .set(deposit_merge_key, merge_inclusion_proof2.value)
@@ -514,7 +571,8 @@ fn test_deposit_block() {
             &mut pw,
             &[],
             *sender1_input_witness.first().unwrap().0.old_root,
-        );
+        )
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -525,7 +583,8 @@ fn test_deposit_block() {
             &sender1_output_witness,
             sender1_input_witness.first().unwrap().0.old_root,
             sender1_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender1_tx_proof");
     let start = Instant::now();
@@ -546,7 +605,8 @@ fn test_deposit_block() {
     merge_and_purge_circuit
         .targets
         .merge_proof_target
-        .set_witness(&mut pw, &[merge_proof], default_hash);
+        .set_witness(&mut pw, &[merge_proof], default_hash)
+        .unwrap();
     merge_and_purge_circuit
         .targets
         .purge_proof_target
@@ -557,7 +617,8 @@ fn test_deposit_block() {
             &sender2_output_witness,
             sender2_input_witness.first().unwrap().0.old_root,
             sender2_nonce,
-        );
+        )
+        .unwrap();
 
     println!("start proving: sender2_tx_proof");
     let start = Instant::now();
@@ -629,11 +690,13 @@ fn test_deposit_block() {
     // deposit block
     let deposit_block_target: DepositBlockProofTarget<
         D,
+        N_LOG_TXS,
         N_LOG_RECIPIENTS,
         N_LOG_CONTRACTS,
         N_LOG_VARIABLES,
         N_DEPOSITS,
     > = DepositBlockProofTarget::add_virtual_to::<F, <C as GenericConfig<D>>::Hasher>(&mut builder);
+    builder.register_public_inputs(&deposit_block_target.deposit_digest.elements);
     let circuit_data = builder.build::<C>();
 
     let block_number = 1;
@@ -705,14 +768,47 @@ fn test_deposit_block() {
         })
         .collect::<Vec<_>>();
 
+    // The deposit digest commits the nonce of the deposit transaction,
+    // so it is the same as that of the deposit tree built outside the circuit.
+    let deposit_nonce = l1_deposit_nonce(10, 1);
+    let expected_deposit_tree = build_deposit_tree_with_nonce(
+        &deposit_list
+            .iter()
+            .cloned()
+            .map(Deposit::from)
+            .collect::<Vec<_>>(),
+        deposit_nonce,
+        N_LOG_TXS,
+    )
+    .unwrap();
+
     let mut pw = PartialWitness::new();
-    deposit_block_target.set_witness::<F, H>(&mut pw, &deposit_process_proofs);
+    deposit_block_target
+        .set_witness::<F, H>(&mut pw, &deposit_process_proofs, deposit_nonce)
+        .unwrap();
 
     println!("start proving: block_proof");
     let start = Instant::now();
     let proof = circuit_data.prove(pw).unwrap();
     let end = start.elapsed();
     println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
+    assert_eq!(
+        proof.public_inputs,
+        expected_deposit_tree.deposit_digest.elements.to_vec()
+    );
+    assert_ne!(
+        expected_deposit_tree.deposit_digest,
+        build_deposit_tree(
+            &deposit_list
+                .iter()
+                .cloned()
+                .map(Deposit::from)
+                .collect::<Vec<_>>(),
+            N_LOG_TXS
+        )
+        .unwrap()
+        .deposit_digest
+    );
 
     match circuit_data.verify(proof) {
         Ok(()) => println!("Ok!"),
@@ -0,0 +1,331 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::{target::BoolTarget, witness::Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        config::{AlgebraicHasher, Hasher},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
+    sparse_merkle_tree::{
+        gadgets::{
+            common::{
+                conditionally_select, enforce_equal_if_enabled, is_equal_hash_out, logical_and_not,
+                logical_or, poseidon_two_to_one,
+            },
+            verify::verify_smt::{SmtInclusionProof, SparseMerkleInclusionProofTarget},
+        },
+        goldilocks_poseidon::{hash_out_hex, WrappedHashOut},
+    },
+    transaction::circuits::MergeAndPurgeTransitionPublicInputs,
+    zkdsa::{account::Address, gadgets::account::AddressTarget},
+};
+
+/// A user transaction queued on L1 by its sender, who publishes the user transaction proof
+/// so that the aggregator cannot claim not to have received it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ForcedTransaction<F: RichField> {
+    pub sender_address: Address<F>,
+    /// The user asset root after merge, which the transaction requires to be the world state leaf of the sender.
+    #[serde(with = "hash_out_hex")]
+    pub middle_user_asset_root: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
+    pub tx_hash: HashOut<F>,
+}
+
+impl<F: RichField> ForcedTransaction<F> {
+    pub fn hash(&self) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&self.encode())
+    }
+
+    pub fn encode(&self) -> Vec<F> {
+        [
+            self.sender_address.0.elements.to_vec(),
+            self.middle_user_asset_root.elements.to_vec(),
+            self.tx_hash.elements.to_vec(),
+        ]
+        .concat()
+    }
+}
+
+/// The digest of the forced transaction queue after `transaction` is pushed,
+/// which the L1 contract keeps for every length of the queue.
+pub fn get_forced_transactions_digest<F: RichField>(
+    prev_digest: HashOut<F>,
+    transaction: &ForcedTransaction<F>,
+) -> HashOut<F> {
+    PoseidonHash::two_to_one(prev_digest, transaction.hash())
+}
+
+/// How a block processes a forced transaction.
+/// `world_state_inclusion_proof` is `None` if the transaction is in the block. Otherwise, it shows that
+/// the world state leaf of the sender before the block is not `middle_user_asset_root`,
+/// so the transaction cannot be included any more.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ForcedTransactionWitness<F: RichField> {
+    pub transaction: ForcedTransaction<F>,
+    pub world_state_inclusion_proof: Option<SmtInclusionProof<F>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ForcedTransactionTarget {
+    pub sender_address: AddressTarget,
+    pub middle_user_asset_root: HashOutTarget,
+    pub tx_hash: HashOutTarget,
+}
+
+impl ForcedTransactionTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self {
+            sender_address: AddressTarget::add_virtual_to(builder),
+            middle_user_asset_root: builder.add_virtual_hash(),
+            tx_hash: builder.add_virtual_hash(),
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        value: &ForcedTransaction<F>,
+    ) {
+        self.sender_address.set_witness(pw, value.sender_address);
+        pw.set_hash_target(self.middle_user_asset_root, value.middle_user_asset_root);
+        pw.set_hash_target(self.tx_hash, value.tx_hash);
+    }
+
+    pub fn hash<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> HashOutTarget {
+        builder.hash_n_to_hash_no_pad::<H>(
+            [
+                self.sender_address.0.elements,
+                self.middle_user_asset_root.elements,
+                self.tx_hash.elements,
+            ]
+            .concat(),
+        )
+    }
+}
+
+/// A user transaction of the block, given by its public inputs.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockTransactionTarget {
+    pub sender_address: HashOutTarget,
+    pub tx_hash: HashOutTarget,
+    pub enabled: BoolTarget,
+}
+
+/// Processes up to `N_FORCED_TXS` forced transactions from the front of the queue.
+/// Each of them is either one of the transactions of the block
+/// or stale with respect to `old_world_state_root`.
+#[derive(Clone, Debug)]
+pub struct ForcedInclusionProofTarget<const N_LOG_USERS: usize, const N_FORCED_TXS: usize> {
+    pub forced_transactions: [ForcedTransactionTarget; N_FORCED_TXS],
+    pub world_state_inclusion_proofs: [SparseMerkleInclusionProofTarget<N_LOG_USERS>; N_FORCED_TXS],
+    pub enabled: [BoolTarget; N_FORCED_TXS],
+
+    /// The digest of the queue processed by the previous blocks.
+    pub old_forced_transactions_digest: HashOutTarget, // input
+
+    /// The digest of the queue processed up to this block, which the block header commits to.
+    pub new_forced_transactions_digest: HashOutTarget, // output
+}
+
+impl<const N_LOG_USERS: usize, const N_FORCED_TXS: usize>
+    ForcedInclusionProofTarget<N_LOG_USERS, N_FORCED_TXS>
+{
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        old_world_state_root: HashOutTarget,
+        block_transactions: &[BlockTransactionTarget],
+    ) -> Self {
+        let zero = builder.zero();
+        let default_hash = HashOutTarget {
+            elements: [zero; 4],
+        };
+        let constant_false = builder._false();
+
+        let old_forced_transactions_digest = builder.add_virtual_hash();
+        let mut forced_transactions = vec![];
+        let mut world_state_inclusion_proofs = vec![];
+        let mut enabled = vec![];
+        let mut digest = old_forced_transactions_digest;
+        for _ in 0..N_FORCED_TXS {
+            let forced_transaction = ForcedTransactionTarget::add_virtual_to(builder);
+            let enabled_t = builder.add_virtual_bool_target_safe();
+
+            let mut is_included = constant_false;
+            for block_transaction in block_transactions {
+                let is_same_sender = is_equal_hash_out(
+                    builder,
+                    block_transaction.sender_address,
+                    forced_transaction.sender_address.0,
+                );
+                let is_same_tx = is_equal_hash_out(
+                    builder,
+                    block_transaction.tx_hash,
+                    forced_transaction.tx_hash,
+                );
+                let is_same = builder.and(is_same_sender, is_same_tx);
+                let is_same = builder.and(is_same, block_transaction.enabled);
+                is_included = logical_or(builder, is_included, is_same);
+            }
+
+            // 含まれていない forced transaction は, block の前の world state で sender の leaf が
+            // `middle_user_asset_root` でないことを示す.
+            let is_excluded = logical_and_not(builder, enabled_t, is_included);
+            let world_state_inclusion_proof =
+                SparseMerkleInclusionProofTarget::add_virtual_to_with_enabled::<F, H, D>(
+                    builder,
+                    is_excluded,
+                );
+            enforce_equal_if_enabled(
+                builder,
+                world_state_inclusion_proof.root,
+                old_world_state_root,
+                is_excluded,
+            );
+            enforce_equal_if_enabled(
+                builder,
+                world_state_inclusion_proof.key,
+                forced_transaction.sender_address.0,
+                is_excluded,
+            );
+            let current_user_asset_root = conditionally_select(
+                builder,
+                default_hash,
+                world_state_inclusion_proof.value,
+                world_state_inclusion_proof.fnc,
+            );
+            let is_current = is_equal_hash_out(
+                builder,
+                current_user_asset_root,
+                forced_transaction.middle_user_asset_root,
+            );
+            let is_censored = builder.and(is_excluded, is_current);
+            builder.connect(is_censored.target, constant_false.target);
+
+            let transaction_hash = forced_transaction.hash::<F, H, D>(builder);
+            let next_digest = poseidon_two_to_one::<F, H, D>(builder, digest, transaction_hash);
+            digest = conditionally_select(builder, next_digest, digest, enabled_t);
+
+            forced_transactions.push(forced_transaction);
+            world_state_inclusion_proofs.push(world_state_inclusion_proof);
+            enabled.push(enabled_t);
+        }
+
+        Self {
+            forced_transactions: forced_transactions.try_into().unwrap(),
+            world_state_inclusion_proofs: world_state_inclusion_proofs.try_into().unwrap(),
+            enabled: enabled.try_into().unwrap(),
+            old_forced_transactions_digest,
+            new_forced_transactions_digest: digest,
+        }
+    }
+
+    /// `forced_transactions` are the transactions of the queue following `old_forced_transactions_digest` in order.
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        old_forced_transactions_digest: HashOut<F>,
+        forced_transactions: &[ForcedTransactionWitness<F>],
+    ) -> Result<(), IntmaxError> {
+        ensure_at_most(
+            "forced transactions",
+            forced_transactions.len(),
+            N_FORCED_TXS,
+        )?;
+
+        pw.set_hash_target(
+            self.old_forced_transactions_digest,
+            old_forced_transactions_digest,
+        );
+        let default_witness = ForcedTransactionWitness::default();
+        for (i, (forced_transaction_t, world_state_inclusion_proof_t)) in self
+            .forced_transactions
+            .iter()
+            .zip(self.world_state_inclusion_proofs.iter())
+            .enumerate()
+        {
+            let witness = forced_transactions.get(i).unwrap_or(&default_witness);
+            forced_transaction_t.set_witness(pw, &witness.transaction);
+            pw.set_bool_target(self.enabled[i], i < forced_transactions.len());
+            match &witness.world_state_inclusion_proof {
+                Some(proof) => world_state_inclusion_proof_t.set_proof_witness(pw, proof),
+                None => world_state_inclusion_proof_t.set_disabled_witness(pw),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the user asset root of `world_state_inclusion_proof`, which is the default hash if it is not found.
+pub fn get_current_user_asset_root<F: RichField>(
+    world_state_inclusion_proof: &SmtInclusionProof<F>,
+) -> WrappedHashOut<F> {
+    if world_state_inclusion_proof.found {
+        world_state_inclusion_proof.value
+    } else {
+        WrappedHashOut::default()
+    }
+}
+
+/// Checks the same conditions as `ForcedInclusionProofTarget` without proving.
+/// `user_transactions` are the transactions of the block, and `old_world_state_root` is the world state root before it.
+pub fn validate_forced_transactions<F: RichField>(
+    old_world_state_root: HashOut<F>,
+    user_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+    forced_transactions: &[ForcedTransactionWitness<F>],
+    max_forced_transactions: usize,
+) -> Result<(), IntmaxError> {
+    ensure_at_most(
+        "forced transactions",
+        forced_transactions.len(),
+        max_forced_transactions,
+    )?;
+
+    for (i, witness) in forced_transactions.iter().enumerate() {
+        let transaction = &witness.transaction;
+        let is_included = user_transactions.iter().any(|u| {
+            u.sender_address == transaction.sender_address && *u.tx_hash == transaction.tx_hash
+        });
+        if is_included {
+            continue;
+        }
+
+        let proof = witness
+            .world_state_inclusion_proof
+            .as_ref()
+            .ok_or_else(|| {
+                IntmaxError::InvalidWitness(format!(
+                    "forced transaction #{} is neither in the block nor shown to be stale",
+                    i
+                ))
+            })?;
+        ensure_witness!(
+            *proof.root == old_world_state_root && *proof.key == transaction.sender_address.0,
+            "the world state inclusion proof of forced transaction #{} must be for its sender before the block",
+            i
+        );
+        ensure_witness!(
+            *get_current_user_asset_root(proof) != transaction.middle_user_asset_root,
+            "forced transaction #{} is not included in the block",
+            i
+        );
+    }
+
+    Ok(())
+}
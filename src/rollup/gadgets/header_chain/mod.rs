@@ -0,0 +1,423 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::{target::Target, witness::Witness},
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::{
+    errors::{ensure_length, ensure_witness, IntmaxError},
+    merkle_tree::{
+        gadgets::get_merkle_root_target,
+        tree::{get_merkle_proof, get_merkle_root},
+    },
+    transaction::{
+        block_header::{get_block_hash, BlockHeader},
+        gadgets::block_header::{get_block_hash_target, BlockHeaderTarget},
+    },
+};
+
+/// `N_HEADERS` consecutive block headers.
+/// `block_header_siblings[i]` are the siblings of the leaf `block_headers[i].block_number`
+/// in the block header tree, with which the hash of `block_headers[i]` is appended
+/// to `block_headers[i].prev_block_header_digest` and becomes `block_headers[i + 1].prev_block_header_digest`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderChainWitness<F: RichField> {
+    pub block_headers: Vec<BlockHeader<F>>,
+    pub block_header_siblings: Vec<Vec<HashOut<F>>>,
+}
+
+impl<F: RichField> HeaderChainWitness<F> {
+    /// `prev_block_hashes` are the hashes of the blocks before `block_headers[0]`, from the genesis block.
+    pub fn new(
+        prev_block_hashes: &[HashOut<F>],
+        block_headers: Vec<BlockHeader<F>>,
+        n_log_max_blocks: usize,
+    ) -> Result<Self, IntmaxError> {
+        let first_block_header = block_headers.first().ok_or(IntmaxError::EmptyWitness {
+            name: "block headers",
+        })?;
+        ensure_witness!(
+            first_block_header.block_number as usize == prev_block_hashes.len(),
+            "block #{} does not follow {} blocks",
+            first_block_header.block_number,
+            prev_block_hashes.len()
+        );
+
+        let mut block_hashes = prev_block_hashes
+            .iter()
+            .map(|block_hash| (*block_hash).into())
+            .collect::<Vec<_>>();
+        let mut block_header_siblings = vec![];
+        for block_header in block_headers.iter().take(block_headers.len() - 1) {
+            let proof = get_merkle_proof(
+                &[block_hashes.clone(), vec![Default::default()]].concat(),
+                block_hashes.len(),
+                n_log_max_blocks,
+            );
+            block_header_siblings.push(proof.siblings.into_iter().map(|v| *v).collect());
+            block_hashes.push(get_block_hash(block_header).into());
+        }
+
+        let witness = Self {
+            block_headers,
+            block_header_siblings,
+        };
+        witness.validate()?;
+
+        Ok(witness)
+    }
+
+    /// Checks the same conditions as `HeaderChainTarget` without proving.
+    pub fn validate(&self) -> Result<(), IntmaxError> {
+        ensure_length(
+            "block header siblings",
+            self.block_header_siblings.len(),
+            self.block_headers.len().saturating_sub(1),
+        )?;
+        for (i, siblings) in self.block_header_siblings.iter().enumerate() {
+            let block_header = &self.block_headers[i];
+            let next_block_header = &self.block_headers[i + 1];
+            ensure_witness!(
+                next_block_header.block_number == block_header.block_number + 1,
+                "block header #{} is not followed by the next block",
+                i
+            );
+
+            let index = block_header.block_number as usize;
+            let siblings = siblings.iter().map(|v| (*v).into()).collect::<Vec<_>>();
+            ensure_witness!(
+                *get_merkle_root(index, Default::default(), &siblings)
+                    == block_header.prev_block_header_digest,
+                "block header #{} siblings do not match its block header digest",
+                i
+            );
+            ensure_witness!(
+                *get_merkle_root(index, get_block_hash(block_header).into(), &siblings)
+                    == next_block_header.prev_block_header_digest,
+                "block header #{} is not the parent of the next block header",
+                i
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HeaderChainTarget<const N_LOG_MAX_BLOCKS: usize, const N_HEADERS: usize> {
+    pub block_headers: Vec<BlockHeaderTarget>, // input
+    pub block_header_siblings: Vec<[HashOutTarget; N_LOG_MAX_BLOCKS]>, // input
+
+    pub start_block_number: Target,      // output
+    pub start_block_hash: HashOutTarget, // output
+    pub end_block_number: Target,        // output
+    pub end_block_hash: HashOutTarget,   // output
+}
+
+impl<const N_LOG_MAX_BLOCKS: usize, const N_HEADERS: usize>
+    HeaderChainTarget<N_LOG_MAX_BLOCKS, N_HEADERS>
+{
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        assert_ne!(N_HEADERS, 0, "the chain must have a block header");
+
+        let zero = builder.zero();
+        let one = builder.one();
+        let default_hash = HashOutTarget {
+            elements: [zero; 4],
+        };
+
+        let block_headers = (0..N_HEADERS)
+            .map(|_| BlockHeaderTarget::add_virtual_to::<F, H, D>(builder))
+            .collect::<Vec<_>>();
+        let block_hashes = block_headers
+            .iter()
+            .map(|block_header| get_block_hash_target::<F, H, D>(builder, block_header))
+            .collect::<Vec<_>>();
+
+        let mut block_header_siblings = vec![];
+        for i in 0..(N_HEADERS - 1) {
+            let block_header = &block_headers[i];
+            let next_block_header = &block_headers[i + 1];
+            let next_block_number = builder.add(block_header.block_number, one);
+            builder.connect(next_block_header.block_number, next_block_number);
+
+            // The block header tree is appended only at the leaf of `block_number`.
+            let siblings: [HashOutTarget; N_LOG_MAX_BLOCKS] = builder
+                .add_virtual_hashes(N_LOG_MAX_BLOCKS)
+                .try_into()
+                .unwrap();
+            let old_root = get_merkle_root_target::<F, H, D>(
+                builder,
+                block_header.block_number,
+                default_hash,
+                &siblings,
+            );
+            builder.connect_hashes(old_root, block_header.prev_block_header_digest);
+            let new_root = get_merkle_root_target::<F, H, D>(
+                builder,
+                block_header.block_number,
+                block_hashes[i],
+                &siblings,
+            );
+            builder.connect_hashes(new_root, next_block_header.prev_block_header_digest);
+
+            block_header_siblings.push(siblings);
+        }
+
+        Self {
+            start_block_number: block_headers[0].block_number,
+            start_block_hash: block_hashes[0],
+            end_block_number: block_headers[N_HEADERS - 1].block_number,
+            end_block_hash: block_hashes[N_HEADERS - 1],
+            block_headers,
+            block_header_siblings,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &HeaderChainWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        ensure_length("block headers", witness.block_headers.len(), N_HEADERS)?;
+        witness.validate()?;
+
+        for (block_header_t, block_header) in
+            self.block_headers.iter().zip(witness.block_headers.iter())
+        {
+            block_header_t.set_witness(pw, block_header);
+        }
+        for (siblings_t, siblings) in self
+            .block_header_siblings
+            .iter()
+            .zip(witness.block_header_siblings.iter())
+        {
+            ensure_length("block header siblings", siblings.len(), N_LOG_MAX_BLOCKS)?;
+            for (sibling_t, sibling) in siblings_t.iter().zip(siblings.iter()) {
+                pw.set_hash_target(*sibling_t, *sibling);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The witness that `ancestor` is an ancestor of `descendant`, i.e. the hash of `ancestor` is in
+/// the block header tree `descendant.prev_block_header_digest` at the leaf `ancestor.block_number`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderAncestryWitness<F: RichField> {
+    pub ancestor: BlockHeader<F>,
+    pub descendant: BlockHeader<F>,
+    pub siblings: Vec<HashOut<F>>,
+}
+
+impl<F: RichField> HeaderAncestryWitness<F> {
+    /// `prev_block_hashes` are the hashes of the blocks before `descendant`, from the genesis block.
+    pub fn new(
+        prev_block_hashes: &[HashOut<F>],
+        ancestor: BlockHeader<F>,
+        descendant: BlockHeader<F>,
+        n_log_max_blocks: usize,
+    ) -> Result<Self, IntmaxError> {
+        let index = ancestor.block_number as usize;
+        ensure_witness!(
+            index < prev_block_hashes.len(),
+            "block #{} is not before {} blocks",
+            index,
+            prev_block_hashes.len()
+        );
+        let block_hashes = prev_block_hashes
+            .iter()
+            .map(|block_hash| (*block_hash).into())
+            .collect::<Vec<_>>();
+        let siblings = get_merkle_proof(&block_hashes, index, n_log_max_blocks)
+            .siblings
+            .into_iter()
+            .map(|v| *v)
+            .collect();
+
+        let witness = Self {
+            ancestor,
+            descendant,
+            siblings,
+        };
+        witness.validate()?;
+
+        Ok(witness)
+    }
+
+    /// Checks the same conditions as `HeaderAncestryTarget` without proving.
+    pub fn validate(&self) -> Result<(), IntmaxError> {
+        ensure_witness!(
+            self.ancestor.block_number < self.descendant.block_number,
+            "block #{} is not before block #{}",
+            self.ancestor.block_number,
+            self.descendant.block_number
+        );
+        let siblings = self
+            .siblings
+            .iter()
+            .map(|v| (*v).into())
+            .collect::<Vec<_>>();
+        let root = get_merkle_root(
+            self.ancestor.block_number as usize,
+            get_block_hash(&self.ancestor).into(),
+            &siblings,
+        );
+        ensure_witness!(
+            *root == self.descendant.prev_block_header_digest,
+            "block #{} is not an ancestor of block #{}",
+            self.ancestor.block_number,
+            self.descendant.block_number
+        );
+
+        Ok(())
+    }
+}
+
+/// Proves that `ancestor` is an ancestor of `descendant`.
+/// The callers connect `ancestor_block_hash` and `descendant_block_hash` to the blocks they refer to.
+#[derive(Clone, Debug)]
+pub struct HeaderAncestryTarget<const N_LOG_MAX_BLOCKS: usize> {
+    pub ancestor: BlockHeaderTarget,                 // input
+    pub descendant: BlockHeaderTarget,               // input
+    pub siblings: [HashOutTarget; N_LOG_MAX_BLOCKS], // input
+
+    pub ancestor_block_hash: HashOutTarget,   // output
+    pub descendant_block_hash: HashOutTarget, // output
+}
+
+impl<const N_LOG_MAX_BLOCKS: usize> HeaderAncestryTarget<N_LOG_MAX_BLOCKS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let ancestor = BlockHeaderTarget::add_virtual_to::<F, H, D>(builder);
+        let descendant = BlockHeaderTarget::add_virtual_to::<F, H, D>(builder);
+        let siblings: [HashOutTarget; N_LOG_MAX_BLOCKS] = builder
+            .add_virtual_hashes(N_LOG_MAX_BLOCKS)
+            .try_into()
+            .unwrap();
+
+        // ancestor.block_number < descendant.block_number
+        let one = builder.one();
+        let diff = builder.sub(descendant.block_number, ancestor.block_number);
+        let diff = builder.sub(diff, one);
+        builder.range_check(diff, 32);
+
+        let ancestor_block_hash = get_block_hash_target::<F, H, D>(builder, &ancestor);
+        let descendant_block_hash = get_block_hash_target::<F, H, D>(builder, &descendant);
+        let root = get_merkle_root_target::<F, H, D>(
+            builder,
+            ancestor.block_number,
+            ancestor_block_hash,
+            &siblings,
+        );
+        builder.connect_hashes(root, descendant.prev_block_header_digest);
+
+        Self {
+            ancestor,
+            descendant,
+            siblings,
+            ancestor_block_hash,
+            descendant_block_hash,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &HeaderAncestryWitness<F>,
+    ) -> Result<(), IntmaxError> {
+        ensure_length("siblings", witness.siblings.len(), N_LOG_MAX_BLOCKS)?;
+        witness.validate()?;
+
+        self.ancestor.set_witness(pw, &witness.ancestor);
+        self.descendant.set_witness(pw, &witness.descendant);
+        for (sibling_t, sibling) in self.siblings.iter().zip(witness.siblings.iter()) {
+            pw.set_hash_target(*sibling_t, *sibling);
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_header_ancestry() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::{GoldilocksHashOut, WrappedHashOut};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+    const N_LOG_MAX_BLOCKS: usize = 32;
+
+    let mut block_hashes: Vec<WrappedHashOut<F>> = vec![];
+    let mut block_headers = vec![];
+    for block_number in 0..4 {
+        let prev_block_header_digest = get_merkle_proof(
+            &[block_hashes.clone(), vec![Default::default()]].concat(),
+            block_hashes.len(),
+            N_LOG_MAX_BLOCKS,
+        )
+        .root;
+        let block_header = BlockHeader {
+            block_number,
+            prev_block_header_digest: *prev_block_header_digest,
+            transactions_digest: *GoldilocksHashOut::from_u32(10 + block_number),
+            ..BlockHeader::with_tree_depth(2)
+        };
+        block_hashes.push(get_block_hash(&block_header).into());
+        block_headers.push(block_header);
+    }
+    let block_hashes = block_hashes.iter().map(|v| **v).collect::<Vec<_>>();
+
+    let witness = HeaderAncestryWitness::new(
+        &block_hashes[0..3],
+        block_headers[1].clone(),
+        block_headers[3].clone(),
+        N_LOG_MAX_BLOCKS,
+    )
+    .unwrap();
+    assert!(HeaderAncestryWitness::new(
+        &block_hashes[0..1],
+        block_headers[1].clone(),
+        block_headers[1].clone(),
+        N_LOG_MAX_BLOCKS,
+    )
+    .is_err());
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let target = HeaderAncestryTarget::<N_LOG_MAX_BLOCKS>::add_virtual_to::<
+        F,
+        <C as plonky2::plonk::config::GenericConfig<D>>::Hasher,
+        D,
+    >(&mut builder);
+    builder.register_public_inputs(&target.ancestor_block_hash.elements);
+    builder.register_public_inputs(&target.descendant_block_hash.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    target.set_witness(&mut pw, &witness).unwrap();
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(proof.public_inputs[0..4], block_hashes[1].elements);
+    assert_eq!(proof.public_inputs[4..8], block_hashes[3].elements);
+    data.verify(proof).unwrap();
+
+    // The block 2 is not an ancestor of the block 1.
+    let invalid_witness = HeaderAncestryWitness {
+        ancestor: block_headers[2].clone(),
+        descendant: block_headers[1].clone(),
+        siblings: witness.siblings,
+    };
+    assert!(target
+        .set_witness(&mut PartialWitness::new(), &invalid_witness)
+        .is_err());
+}
@@ -0,0 +1,480 @@
+//! The mints of a block, which put the user asset roots after the mints into the world state tree.
+//! Each mint is signed for `block_number`, so a signed mint is included in only one block.
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::{target::Target, witness::Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitData,
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
+    recursion::gadgets::RecursiveProofTarget,
+    sparse_merkle_tree::{
+        gadgets::{
+            common::{enforce_equal_if_enabled, logical_and_not, logical_or},
+            process::{
+                process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+                utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
+            },
+        },
+        goldilocks_poseidon::WrappedHashOut,
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::circuits::mint::{parse_mint_public_inputs, MintPublicInputs},
+};
+
+#[derive(Clone)]
+pub struct MintBlockProofTarget<const D: usize, const N_LOG_USERS: usize, const N_MINTS: usize> {
+    pub world_state_process_proofs: [SparseMerkleProcessProofTarget<N_LOG_USERS>; N_MINTS], // input
+
+    pub mint_proofs: [RecursiveProofTarget<D>; N_MINTS], // input
+
+    /// The issuer registry root which all the mints are verified against,
+    /// i.e. `issuer_registry_root` of the block header.
+    pub issuer_registry_root: HashOutTarget, // input
+
+    /// The block number of the block header, which all the mints are signed for.
+    pub block_number: Target, // input
+
+    pub old_world_state_root: HashOutTarget, // input
+
+    pub new_world_state_root: HashOutTarget, // output
+}
+
+impl<const D: usize, const N_LOG_USERS: usize, const N_MINTS: usize>
+    MintBlockProofTarget<D, N_LOG_USERS, N_MINTS>
+{
+    pub fn add_virtual_to<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        builder: &mut CircuitBuilder<F, D>,
+        mint_circuit_data: &CircuitData<F, C, D>,
+    ) -> Self
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let world_state_process_proofs = (0..N_MINTS)
+            .map(|_| SparseMerkleProcessProofTarget::add_virtual_to::<F, C::Hasher, D>(builder))
+            .collect::<Vec<_>>();
+        let mint_proofs = (0..N_MINTS)
+            .map(|_| RecursiveProofTarget::add_virtual_to(builder, mint_circuit_data))
+            .collect::<Vec<_>>();
+        let issuer_registry_root = builder.add_virtual_hash();
+        let block_number = builder.add_virtual_target();
+        let old_world_state_root = builder.add_virtual_hash();
+
+        let new_world_state_root = verify_valid_mint_block::<F, D, N_LOG_USERS>(
+            builder,
+            &world_state_process_proofs,
+            &mint_proofs,
+            issuer_registry_root,
+            block_number,
+            old_world_state_root,
+        );
+
+        Self {
+            world_state_process_proofs: world_state_process_proofs.try_into().unwrap(),
+            mint_proofs: mint_proofs
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("fail to convert vector to constant size array"))
+                .unwrap(),
+            issuer_registry_root,
+            block_number,
+            old_world_state_root,
+            new_world_state_root,
+        }
+    }
+
+    /// Pads the remaining slots with no-op proofs and `default_mint_proof` as a disabled proof.
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        world_state_process_proofs: &[SmtProcessProof<F>],
+        mint_proofs: &[ProofWithPublicInputs<F, C, D>],
+        default_mint_proof: &ProofWithPublicInputs<F, C, D>,
+        issuer_registry_root: HashOut<F>,
+        block_number: u32,
+        old_world_state_root: HashOut<F>,
+    ) -> Result<(), IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        pw.set_hash_target(self.issuer_registry_root, issuer_registry_root);
+        pw.set_target(self.block_number, F::from_canonical_u32(block_number));
+        pw.set_hash_target(self.old_world_state_root, old_world_state_root);
+
+        ensure_at_most(
+            "world state process proofs",
+            world_state_process_proofs.len(),
+            self.world_state_process_proofs.len(),
+        )?;
+        let latest_root = world_state_process_proofs
+            .last()
+            .map(|p| *p.new_root)
+            .unwrap_or(old_world_state_root);
+        for (p_t, p) in self
+            .world_state_process_proofs
+            .iter()
+            .zip(world_state_process_proofs.iter())
+        {
+            p_t.set_witness(pw, p);
+        }
+        let default_proof = SmtProcessProof::with_root(latest_root.into());
+        for p_t in self
+            .world_state_process_proofs
+            .iter()
+            .skip(world_state_process_proofs.len())
+        {
+            p_t.set_witness(pw, &default_proof);
+        }
+
+        ensure_at_most("mint proofs", mint_proofs.len(), self.mint_proofs.len())?;
+        for (i, mint_proof_t) in self.mint_proofs.iter().enumerate() {
+            if let Some(mint_proof) = mint_proofs.get(i) {
+                mint_proof_t.set_witness(pw, mint_proof, true);
+            } else {
+                mint_proof_t.set_witness(pw, default_mint_proof, false);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the same conditions as `verify_valid_mint_block` without proving.
+/// The `i`-th world state process proof must update the user asset root of the issuer of the `i`-th mint,
+/// or insert it if the issuer starts from the empty user asset tree.
+pub fn validate_mint_block_witness(
+    world_state_process_proofs: &[SmtProcessProof<GoldilocksField>],
+    mints: &[MintPublicInputs],
+    issuer_registry_root: HashOut<GoldilocksField>,
+    block_number: u32,
+    old_world_state_root: HashOut<GoldilocksField>,
+) -> Result<WrappedHashOut<GoldilocksField>, IntmaxError> {
+    ensure_at_most("mints", mints.len(), world_state_process_proofs.len())?;
+
+    let mut new_world_state_root: WrappedHashOut<GoldilocksField> = old_world_state_root.into();
+    for (i, w) in world_state_process_proofs.iter().enumerate() {
+        ensure_witness!(
+            w.old_root == new_world_state_root,
+            "world state process proof #{} old_root mismatch",
+            i
+        );
+        new_world_state_root = w.new_root;
+
+        let m = if let Some(m) = mints.get(i) {
+            m
+        } else {
+            ensure_witness!(
+                w.fnc == ProcessMerkleProofRole::ProcessNoOp && w.new_root == w.old_root,
+                "world state process proof #{} must be no-op since there is no mint",
+                i
+            );
+            continue;
+        };
+
+        ensure_witness!(
+            *m.issuer_registry_root == issuer_registry_root,
+            "mint #{} is for another issuer registry",
+            i
+        );
+        ensure_witness!(
+            m.request.block_number == block_number,
+            "mint #{} is signed for the block {}",
+            i,
+            m.request.block_number
+        );
+        ensure_witness!(
+            *w.new_key == m.issuer_address.0 && w.new_value == m.new_user_asset_root,
+            "world state process proof #{} must set the user asset root of the issuer after the mint",
+            i
+        );
+        match w.fnc {
+            ProcessMerkleProofRole::ProcessInsert => ensure_witness!(
+                m.old_user_asset_root == WrappedHashOut::default(),
+                "mint #{} must start from the empty user asset tree to create an account",
+                i
+            ),
+            ProcessMerkleProofRole::ProcessUpdate => ensure_witness!(
+                w.old_value == m.old_user_asset_root,
+                "world state process proof #{} old_value must be the user asset root before the mint",
+                i
+            ),
+            _ => {
+                return Err(IntmaxError::InvalidWitness(format!(
+                    "world state process proof #{} must update or insert the issuer",
+                    i
+                )))
+            }
+        }
+    }
+
+    Ok(new_world_state_root)
+}
+
+/// Returns `new_world_state_root`.
+pub fn verify_valid_mint_block<
+    F: RichField + Extendable<D>,
+    const D: usize,
+    const N_LOG_USERS: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    world_state_process_proofs: &[SparseMerkleProcessProofTarget<N_LOG_USERS>],
+    mint_proofs: &[RecursiveProofTarget<D>],
+    issuer_registry_root: HashOutTarget,
+    block_number: Target,
+    old_world_state_root: HashOutTarget,
+) -> HashOutTarget {
+    assert_eq!(
+        world_state_process_proofs.len(),
+        mint_proofs.len(),
+        "the numbers of world state process proofs and mint proofs must be the same"
+    );
+    let constant_true = builder._true();
+    let constant_false = builder._false();
+    let zero = builder.zero();
+    let default_hash = HashOutTarget {
+        elements: [zero; 4],
+    };
+
+    // 有効な mint は先頭に詰めて並び, 残りの slot は world state root を変えない no-op である.
+    let mut new_world_state_root = old_world_state_root;
+    for (i, (w, m)) in world_state_process_proofs
+        .iter()
+        .zip(mint_proofs.iter())
+        .enumerate()
+    {
+        if i > 0 {
+            let is_enabled_after_disabled =
+                logical_and_not(builder, m.enabled, mint_proofs[i - 1].enabled);
+            builder.connect(is_enabled_after_disabled.target, constant_false.target);
+        }
+
+        builder.connect_hashes(w.old_root, new_world_state_root);
+        let is_disabled = builder.not(m.enabled);
+        enforce_equal_if_enabled(builder, w.new_root, w.old_root, is_disabled);
+        new_world_state_root = w.new_root;
+
+        let public_inputs = parse_mint_public_inputs(&m.inner.0.public_inputs);
+        let ProcessMerkleProofRoleTarget {
+            is_no_op,
+            is_insert_op,
+            is_update_op,
+            ..
+        } = get_process_merkle_proof_role(builder, w.fnc);
+
+        // 無効な slot は no-op, 有効な slot は issuer の leaf の insert か update である.
+        let is_no_op_or_enabled = logical_or(builder, is_no_op, m.enabled);
+        builder.connect(is_no_op_or_enabled.target, constant_true.target);
+        let is_insert_or_update_op = logical_or(builder, is_insert_op, is_update_op);
+        let is_upsert_or_disabled = logical_or(builder, is_insert_or_update_op, is_disabled);
+        builder.connect(is_upsert_or_disabled.target, constant_true.target);
+
+        // mint は同じ issuer registry に対して検証されている
+        enforce_equal_if_enabled(
+            builder,
+            public_inputs.issuer_registry_root,
+            issuer_registry_root,
+            m.enabled,
+        );
+
+        // mint はこの block に対して署名されている
+        let mint_block_number = builder.sub(public_inputs.block_number, block_number);
+        let mint_block_number = builder.mul(mint_block_number, m.enabled.target);
+        builder.connect(mint_block_number, zero);
+
+        // world state には mint 後の issuer の user asset root が格納される
+        enforce_equal_if_enabled(builder, w.new_key, public_inputs.issuer_address, m.enabled);
+        enforce_equal_if_enabled(
+            builder,
+            w.new_value,
+            public_inputs.new_user_asset_root,
+            m.enabled,
+        );
+
+        let is_update_op_and_enabled = builder.and(is_update_op, m.enabled);
+        enforce_equal_if_enabled(
+            builder,
+            w.old_value,
+            public_inputs.old_user_asset_root,
+            is_update_op_and_enabled,
+        );
+        let is_insert_op_and_enabled = builder.and(is_insert_op, m.enabled);
+        enforce_equal_if_enabled(
+            builder,
+            public_inputs.old_user_asset_root,
+            default_hash,
+            is_insert_op_and_enabled,
+        );
+    }
+
+    new_world_state_root
+}
+
+#[test]
+fn test_mint_block() {
+    use plonky2::{
+        field::types::{Field, Sample},
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    use crate::{
+        sparse_merkle_tree::goldilocks_poseidon::{
+            LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory, PoseidonSparseMerkleTree,
+        },
+        transaction::{
+            asset::TokenKind,
+            circuits::mint::make_mint_circuit,
+            issuance::{IssuerRegistry, MintRequest},
+            merge_key::mint_merge_key,
+        },
+        zkdsa::{
+            account::{private_key_to_account, Address},
+            circuits::make_simple_signature_circuit,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+    const N_LOG_USERS: usize = 16;
+    const N_MINTS: usize = 2;
+    let block_number = 3;
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let issuer = private_key_to_account(HashOut::rand());
+    let contract_address = Address::rand();
+    let mut registry = IssuerRegistry::new();
+    registry
+        .register(contract_address, issuer.public_key)
+        .unwrap();
+
+    let request = MintRequest {
+        kind: TokenKind {
+            contract_address,
+            variable_index: WrappedHashOut::ZERO,
+        },
+        amount: 1000,
+        nonce: WrappedHashOut::rand(),
+        block_number,
+    };
+    let mut pw = PartialWitness::new();
+    simple_signature_circuit
+        .targets
+        .set_witness(&mut pw, issuer.private_key, request.message());
+    let issuer_signature = simple_signature_circuit.prove(pw).unwrap();
+
+    let mut user_asset_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let asset_witness = user_asset_tree
+        .set(
+            mint_merge_key(request.message()),
+            contract_address.0.into(),
+            request.kind.variable_index,
+            HashOut::from_partial(&[F::from_canonical_u64(request.amount)]).into(),
+        )
+        .unwrap();
+    let mint_circuit = make_mint_circuit::<C, D, 16, 16, 16, 8>(&simple_signature_circuit.data);
+    let mint_proof = mint_circuit
+        .prove(
+            &request,
+            &asset_witness,
+            &registry.prove(&contract_address).unwrap(),
+            &issuer_signature,
+            WrappedHashOut::default(),
+        )
+        .unwrap();
+    let mint = mint_circuit.verify(mint_proof.clone()).unwrap();
+    let default_mint_proof = mint_circuit
+        .prove_default(&simple_signature_circuit)
+        .unwrap();
+
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    world_state_tree
+        .set(HashOut::rand().into(), HashOut::rand().into())
+        .unwrap();
+    let old_world_state_root = *world_state_tree.get_root();
+    let world_state_process_proof = world_state_tree
+        .set(issuer.address.0.into(), mint.new_user_asset_root)
+        .unwrap();
+    assert_eq!(
+        validate_mint_block_witness(
+            &[world_state_process_proof.clone()],
+            &[mint],
+            registry.root(),
+            block_number,
+            old_world_state_root
+        )
+        .unwrap(),
+        world_state_tree.get_root()
+    );
+
+    // The mints of another issuer registry are rejected.
+    assert!(validate_mint_block_witness(
+        &[world_state_process_proof.clone()],
+        &[mint],
+        HashOut::rand(),
+        block_number,
+        old_world_state_root
+    )
+    .is_err());
+
+    // The mints signed for another block are rejected, so that the signature cannot be replayed.
+    assert!(validate_mint_block_witness(
+        &[world_state_process_proof.clone()],
+        &[mint],
+        registry.root(),
+        block_number + 1,
+        old_world_state_root
+    )
+    .is_err());
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let target = MintBlockProofTarget::<D, N_LOG_USERS, N_MINTS>::add_virtual_to(
+        &mut builder,
+        &mint_circuit.data,
+    );
+    builder.register_public_inputs(&target.new_world_state_root.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    target
+        .set_witness(
+            &mut pw,
+            &[world_state_process_proof],
+            &[mint_proof.clone()],
+            &default_mint_proof,
+            registry.root(),
+            block_number,
+            old_world_state_root,
+        )
+        .unwrap();
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(
+        HashOut::from_partial(&proof.public_inputs),
+        *world_state_tree.get_root()
+    );
+    data.verify(proof).unwrap();
+
+    let mut pw = PartialWitness::new();
+    target
+        .set_witness(
+            &mut pw,
+            &[world_state_process_proof],
+            &[mint_proof],
+            &default_mint_proof,
+            registry.root(),
+            block_number + 1,
+            old_world_state_root,
+        )
+        .unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| data.prove(pw)));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
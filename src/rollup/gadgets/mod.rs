@@ -1,6 +1,13 @@
 pub mod address_list;
 pub mod approval_block;
 pub mod batch;
+pub mod contract;
+pub mod cross_rollup;
 // pub mod block;
 pub mod deposit_block;
+pub mod forced_inclusion;
+pub mod header_chain;
+pub mod mint_block;
 pub mod proposal_block;
+pub mod sharded_world_state;
+pub mod withdrawal;
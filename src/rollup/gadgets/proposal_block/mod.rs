@@ -12,16 +12,24 @@ use plonky2::{
 };
 
 use crate::{
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
     merkle_tree::gadgets::get_merkle_root_target_from_leaves,
     recursion::gadgets::RecursiveProofTarget,
-    sparse_merkle_tree::gadgets::{
-        common::{enforce_equal_if_enabled, logical_or},
-        process::{
-            process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
-            utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
+    sparse_merkle_tree::{
+        gadgets::{
+            common::{enforce_equal_if_enabled, is_equal_hash_out, logical_and_not, logical_or},
+            process::{
+                process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+                utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
+            },
         },
+        goldilocks_poseidon::WrappedHashOut,
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::circuits::{
+        parse_merge_and_purge_public_inputs, MergeAndPurgeTransitionPublicInputs,
     },
-    transaction::circuits::parse_merge_and_purge_public_inputs,
+    verification::public_inputs::{protocol_version, UserTransactionPublicInputs},
 };
 
 #[derive(Clone)]
@@ -69,9 +77,15 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
             world_state_process_proofs.push(a);
         }
 
+        // The user transaction proofs of other protocol versions are rejected.
+        let version = builder.constant(protocol_version());
         let mut user_tx_proofs = vec![];
         for _ in 0..N_TXS {
             let b = RecursiveProofTarget::add_virtual_to(builder, user_tx_circuit_data);
+            builder.connect(
+                b.inner.public_inputs[UserTransactionPublicInputs::<F>::LEN - 1],
+                version,
+            );
             user_tx_proofs.push(b);
         }
 
@@ -103,13 +117,51 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
         world_state_process_proofs: &[SmtProcessProof<F>],
         user_tx_proofs: &[ProofWithPublicInputs<F, C, D>],
         old_world_state_root: HashOut<F>,
-    ) where
+    ) -> Result<(), IntmaxError>
+    where
         C::Hasher: AlgebraicHasher<F>,
     {
+        self.set_world_state_witness(pw, world_state_process_proofs, old_world_state_root)?;
+
+        let last_user_tx_proof = user_tx_proofs.last().ok_or(IntmaxError::EmptyWitness {
+            name: "user transaction proofs",
+        })?;
+        ensure_at_most(
+            "user transaction proofs",
+            user_tx_proofs.len(),
+            self.user_tx_proofs.len(),
+        )?;
+        for (i, r) in user_tx_proofs.iter().enumerate() {
+            self.set_user_tx_proof_witness(pw, i, r, true);
+        }
+
+        for i in user_tx_proofs.len()..self.user_tx_proofs.len() {
+            self.set_user_tx_proof_witness(pw, i, last_user_tx_proof, false);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the world state process proofs and pads the remaining slots with no-op proofs.
+    pub fn set_world_state_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        world_state_process_proofs: &[SmtProcessProof<F>],
+        old_world_state_root: HashOut<F>,
+    ) -> Result<(), IntmaxError> {
         pw.set_hash_target(self.old_world_state_root, old_world_state_root);
 
-        assert!(!world_state_process_proofs.is_empty());
-        assert!(world_state_process_proofs.len() <= self.world_state_process_proofs.len());
+        let latest_root = world_state_process_proofs
+            .last()
+            .ok_or(IntmaxError::EmptyWitness {
+                name: "world state process proofs",
+            })?
+            .new_root;
+        ensure_at_most(
+            "world state process proofs",
+            world_state_process_proofs.len(),
+            self.world_state_process_proofs.len(),
+        )?;
         for (p_t, p) in self
             .world_state_process_proofs
             .iter()
@@ -118,8 +170,6 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
             p_t.set_witness(pw, p);
         }
 
-        let latest_root = world_state_process_proofs.last().unwrap().new_root;
-
         let default_proof = SmtProcessProof::with_root(latest_root);
         for p_t in self
             .world_state_process_proofs
@@ -129,16 +179,115 @@ impl<const D: usize, const N_LOG_USERS: usize, const N_TXS: usize>
             p_t.set_witness(pw, &default_proof);
         }
 
-        assert!(!user_tx_proofs.is_empty());
-        assert!(user_tx_proofs.len() <= self.user_tx_proofs.len());
-        for (r_t, r) in self.user_tx_proofs.iter().zip(user_tx_proofs.iter()) {
-            r_t.set_witness(pw, r, true);
+        Ok(())
+    }
+
+    /// Sets the user transaction proof of the `index`-th slot.
+    /// A disabled slot still needs a valid proof of the user transaction circuit.
+    pub fn set_user_tx_proof_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        index: usize,
+        user_tx_proof: &ProofWithPublicInputs<F, C, D>,
+        enabled: bool,
+    ) where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        self.user_tx_proofs[index].set_witness(pw, user_tx_proof, enabled);
+    }
+}
+
+/// Checks the same conditions as `verify_valid_proposal_block` without proving.
+/// The world state process proofs must be ordered so that the roots are chained,
+/// and the `i`-th proof must update the asset root of the sender of the `i`-th user transaction.
+/// An insert proof creates the account of a sender who starts from the empty user asset tree,
+/// e.g. merging only transfers received on L2.
+pub fn validate_proposal_witness<F: RichField>(
+    world_state_process_proofs: &[SmtProcessProof<F>],
+    user_transactions: &[MergeAndPurgeTransitionPublicInputs<F>],
+    old_world_state_root: HashOut<F>,
+) -> Result<WrappedHashOut<F>, IntmaxError> {
+    ensure_at_most(
+        "user transactions",
+        user_transactions.len(),
+        world_state_process_proofs.len(),
+    )?;
+
+    let mut new_world_state_root: WrappedHashOut<F> = old_world_state_root.into();
+    for (i, w) in world_state_process_proofs.iter().enumerate() {
+        let prev_world_state_root = new_world_state_root;
+        if w.fnc != ProcessMerkleProofRole::ProcessNoOp {
+            ensure_witness!(
+                w.old_root == prev_world_state_root,
+                "world state process proof #{} old_root mismatch",
+                i
+            );
         }
+        new_world_state_root = w.new_root;
+
+        // The slot without user transaction is disabled.
+        let u = if let Some(u) = user_transactions.get(i) {
+            u
+        } else {
+            ensure_witness!(
+                w.fnc == ProcessMerkleProofRole::ProcessNoOp,
+                "world state process proof #{} must be no-op since there is no user transaction",
+                i
+            );
+            ensure_witness!(
+                w.old_root == prev_world_state_root && w.new_root == w.old_root,
+                "world state process proof #{} must keep the world state root since there is no user transaction",
+                i
+            );
+            continue;
+        };
 
-        for r_t in self.user_tx_proofs.iter().skip(user_tx_proofs.len()) {
-            r_t.set_witness(pw, user_tx_proofs.last().unwrap(), false);
+        // The old leaf of an insert proof is another user.
+        if w.fnc != ProcessMerkleProofRole::ProcessInsert {
+            ensure_witness!(
+                w.old_value == u.middle_user_asset_root,
+                "world state process proof #{} old_value must be the user asset root after merge",
+                i
+            );
+        }
+        match w.fnc {
+            ProcessMerkleProofRole::ProcessInsert => {
+                ensure_witness!(
+                    u.old_user_asset_root == WrappedHashOut::default(),
+                    "user transaction #{} must start from the empty user asset tree to create an account",
+                    i
+                );
+                ensure_witness!(
+                    *w.new_key == u.sender_address.0,
+                    "world state process proof #{} must insert the sender of the user transaction",
+                    i
+                );
+                ensure_witness!(
+                    w.new_value == u.new_user_asset_root
+                        && u.new_user_asset_root != WrappedHashOut::default(),
+                    "world state process proof #{} must insert the non-empty user asset root after purge",
+                    i
+                );
+            }
+            ProcessMerkleProofRole::ProcessUpdate => ensure_witness!(
+                w.new_value == u.new_user_asset_root,
+                "world state process proof #{} new_value must be the user asset root after purge",
+                i
+            ),
+            ProcessMerkleProofRole::ProcessDelete => ensure_witness!(
+                u.new_user_asset_root == WrappedHashOut::default(),
+                "user transaction #{} must purge all assets to remove the user",
+                i
+            ),
+            ProcessMerkleProofRole::ProcessNoOp => ensure_witness!(
+                u.new_user_asset_root == u.middle_user_asset_root,
+                "user transaction #{} must not change the user asset root in no-op",
+                i
+            ),
         }
     }
+
+    Ok(new_world_state_root)
 }
 
 /// Returns `(block_tx_root, old_world_state_root, new_world_state_root)`
@@ -174,6 +323,31 @@ pub fn verify_valid_proposal_block<
         new_world_state_root = proof.new_root;
     }
 
+    // 有効な user transaction は先頭に詰めて並び, 残りの slot は world state root を変えない no-op である.
+    assert_eq!(
+        world_state_process_proofs.len(),
+        user_tx_proofs.len(),
+        "the numbers of world state process proofs and user transaction proofs must be the same"
+    );
+    let mut prev_world_state_root = old_world_state_root;
+    for (i, (w, u)) in world_state_process_proofs
+        .iter()
+        .zip(user_tx_proofs.iter())
+        .enumerate()
+    {
+        if i > 0 {
+            let is_enabled_after_disabled =
+                logical_and_not(builder, u.enabled, user_tx_proofs[i - 1].enabled);
+            builder.connect(is_enabled_after_disabled.target, constant_false.target);
+        }
+
+        let is_disabled = builder.not(u.enabled);
+        enforce_equal_if_enabled(builder, w.old_root, prev_world_state_root, is_disabled);
+        enforce_equal_if_enabled(builder, w.new_root, w.old_root, is_disabled);
+
+        prev_world_state_root = w.new_root;
+    }
+
     // 各 user asset root は world state tree に含まれていることの検証.
     for (w, u) in world_state_process_proofs
         .iter()
@@ -196,10 +370,40 @@ pub fn verify_valid_proposal_block<
         builder.connect(is_no_op_or_enabled.target, constant_true.target);
 
         // 古い world state には古い user asset root が格納されている
-        enforce_equal_if_enabled(builder, old_user_asset_root, w.old_value, u.enabled);
+        let is_not_insert_op = builder.not(is_insert_op);
+        let is_not_insert_op_and_enabled = builder.and(is_not_insert_op, u.enabled);
+        enforce_equal_if_enabled(
+            builder,
+            old_user_asset_root,
+            w.old_value,
+            is_not_insert_op_and_enabled,
+        );
 
-        // purge では world state への insert は行われない
-        builder.connect(is_insert_op.target, constant_false.target);
+        // insert は account の作成で, 空の user asset tree から始めた sender の leaf を追加する.
+        // 承認の署名がなければ approval block で revert される.
+        let is_insert_op_and_enabled = builder.and(is_insert_op, u.enabled);
+        enforce_equal_if_enabled(
+            builder,
+            public_inputs.old_user_asset_root,
+            default_hash,
+            is_insert_op_and_enabled,
+        );
+        enforce_equal_if_enabled(
+            builder,
+            w.new_key,
+            public_inputs.sender_address,
+            is_insert_op_and_enabled,
+        );
+        enforce_equal_if_enabled(
+            builder,
+            new_user_asset_root,
+            w.new_value,
+            is_insert_op_and_enabled,
+        );
+        let is_empty_user_asset_root =
+            is_equal_hash_out(builder, new_user_asset_root, default_hash);
+        let is_empty_insert = builder.and(is_insert_op_and_enabled, is_empty_user_asset_root);
+        builder.connect(is_empty_insert.target, constant_false.target);
 
         let is_update_op_and_enabled = builder.and(is_update_op, u.enabled);
         enforce_equal_if_enabled(
@@ -239,441 +443,336 @@ pub fn verify_valid_proposal_block<
 
 #[test]
 fn test_proposal_block() {
-    use std::{
-        sync::{Arc, Mutex},
-        time::Instant,
+    use crate::fixtures::{
+        make_sample_accounts, make_sample_block, make_sample_user_tx, make_sample_user_tx_circuit,
     };
 
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    for user_tx_proof in user_txs.proofs.iter() {
+        user_tx_circuit.verify(user_tx_proof.clone()).unwrap();
+    }
+
+    let block = make_sample_block(&user_tx_circuit, &user_txs).unwrap();
+    assert_eq!(
+        block.world_state_process_proofs.last().unwrap().new_root,
+        block.world_state_tree.get_root()
+    );
+    block.circuit_data.verify(block.proof).unwrap();
+}
+
+#[test]
+fn test_validate_account_creation() {
     use plonky2::{
-        field::{goldilocks_field::GoldilocksField, types::Field},
-        hash::{hash_types::HashOut, poseidon::PoseidonHash},
-        iop::witness::PartialWitness,
-        plonk::{
-            circuit_builder::CircuitBuilder,
-            circuit_data::CircuitConfig,
-            config::{GenericConfig, Hasher, PoseidonGoldilocksConfig},
-        },
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        hash::hash_types::HashOut,
     };
 
     use crate::{
-        merkle_tree::tree::get_merkle_proof,
-        sparse_merkle_tree::{
-            goldilocks_poseidon::{
-                GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
-                PoseidonSparseMerkleTree, WrappedHashOut,
-            },
-            proof::SparseMerkleInclusionProof,
-        },
-        transaction::{
-            block_header::{get_block_hash, BlockHeader},
-            circuits::make_user_proof_circuit,
-            gadgets::merge::MergeProof,
-        },
-        zkdsa::{account::private_key_to_account, circuits::make_simple_signature_circuit},
+        sparse_merkle_tree::goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree},
+        zkdsa::account::Address,
     };
 
-    const D: usize = 2;
-    type C = PoseidonGoldilocksConfig;
-    type F = <C as GenericConfig<D>>::F;
-    const N_LOG_MAX_USERS: usize = 3;
-    const N_LOG_MAX_TXS: usize = 3;
-    const N_LOG_MAX_CONTRACTS: usize = 3;
-    const N_LOG_MAX_VARIABLES: usize = 3;
-    const N_LOG_TXS: usize = 1; // XXX
-    const N_LOG_RECIPIENTS: usize = 3;
-    const N_LOG_CONTRACTS: usize = 3;
-    const N_LOG_VARIABLES: usize = 3;
-    const N_DIFFS: usize = 2;
-    const N_MERGES: usize = 2;
-    const N_TXS: usize = 2usize.pow(N_LOG_TXS as u32);
-
-    let mut world_state_tree = PoseidonSparseMerkleTree::new(
-        Arc::new(Mutex::new(NodeDataMemory::default())),
-        Default::default(),
+    type F = GoldilocksField;
+
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    world_state_tree
+        .set(HashOut::rand().into(), HashOut::rand().into())
+        .unwrap();
+    let old_world_state_root = *world_state_tree.get_root();
+
+    let sender_address = Address::<F>::rand();
+    let user_transaction = MergeAndPurgeTransitionPublicInputs {
+        sender_address,
+        middle_user_asset_root: HashOut::rand().into(),
+        new_user_asset_root: HashOut::rand().into(),
+        ..Default::default()
+    };
+    let insert_proof = world_state_tree
+        .set(
+            sender_address.0.into(),
+            user_transaction.new_user_asset_root,
+        )
+        .unwrap();
+    assert_eq!(insert_proof.fnc, ProcessMerkleProofRole::ProcessInsert);
+    assert_eq!(
+        validate_proposal_witness(
+            &[insert_proof.clone()],
+            &[user_transaction.clone()],
+            old_world_state_root
+        )
+        .unwrap(),
+        insert_proof.new_root
     );
 
-    let merge_and_purge_circuit = make_user_proof_circuit::<
-        F,
-        C,
-        D,
-        N_LOG_MAX_USERS,
-        N_LOG_MAX_TXS,
-        N_LOG_MAX_CONTRACTS,
-        N_LOG_MAX_VARIABLES,
-        N_LOG_TXS,
-        N_LOG_RECIPIENTS,
-        N_LOG_CONTRACTS,
-        N_LOG_VARIABLES,
-        N_DIFFS,
-        N_MERGES,
-    >();
-
-    // dbg!(&purge_proof_circuit_data.common);
-
-    let sender1_private_key = HashOut {
-        elements: [
-            GoldilocksField::from_canonical_u64(17426287337377512978),
-            GoldilocksField::from_canonical_u64(8703645504073070742),
-            GoldilocksField::from_canonical_u64(11984317793392655464),
-            GoldilocksField::from_canonical_u64(9979414176933652180),
-        ],
+    // An account with assets before the transaction is not created.
+    let not_empty = MergeAndPurgeTransitionPublicInputs {
+        old_user_asset_root: HashOut::rand().into(),
+        ..user_transaction
     };
-    let sender1_account = private_key_to_account(sender1_private_key);
-    let sender1_address = sender1_account.address.0;
+    assert!(
+        validate_proposal_witness(&[insert_proof], &[not_empty], old_world_state_root).is_err()
+    );
+}
 
-    let mut sender1_user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
-        LayeredLayeredPoseidonSparseMerkleTree::new(Default::default(), Default::default());
+#[test]
+fn test_proposal_block_slots_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
 
-    let mut sender1_tx_diff_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
-        LayeredLayeredPoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    use plonky2::{
+        field::types::Sample, iop::witness::PartialWitness, plonk::circuit_data::CircuitConfig,
+    };
 
-    let key1 = (
-        GoldilocksHashOut::from_u128(12),
-        GoldilocksHashOut::from_u128(305),
-        GoldilocksHashOut::from_u128(8012),
-    );
-    let value1 = GoldilocksHashOut::from_u128(2053);
-    let key2 = (
-        GoldilocksHashOut::from_u128(12),
-        GoldilocksHashOut::from_u128(471),
-        GoldilocksHashOut::from_u128(8012),
-    );
-    let value2 = GoldilocksHashOut::from_u128(1111);
+    use crate::{
+        fixtures::{make_public_inputs_circuit, C, D, F, N_LOG_MAX_USERS},
+        sparse_merkle_tree::goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree},
+        transaction::circuits::MERGE_AND_PURGE_PUBLIC_INPUTS_LEN,
+        zkdsa::account::Address,
+    };
 
-    let key3 = (
-        GoldilocksHashOut::from_u128(407),
-        GoldilocksHashOut::from_u128(305),
-        GoldilocksHashOut::from_u128(8012),
-    );
-    let value3 = GoldilocksHashOut::from_u128(2053);
-    let key4 = (
-        GoldilocksHashOut::from_u128(832),
-        GoldilocksHashOut::from_u128(471),
-        GoldilocksHashOut::from_u128(8012),
-    );
-    let value4 = GoldilocksHashOut::from_u128(1111);
-
-    let zero = GoldilocksHashOut::from_u128(0);
-    sender1_user_asset_tree
-        .set(key1.0, key1.1, key1.2, value1)
-        .unwrap();
-    sender1_user_asset_tree
-        .set(key2.0, key2.1, key2.2, value2)
-        .unwrap();
+    // The user transaction proofs only expose their public inputs.
+    let user_tx_circuit = make_public_inputs_circuit(MERGE_AND_PURGE_PUBLIC_INPUTS_LEN);
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, 2> =
+        ProposalBlockProofTarget::add_virtual_to(&mut builder, &user_tx_circuit.data);
+    builder.register_public_inputs(&targets.new_world_state_root.elements);
+    let data = builder.build::<C>();
 
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    let sender_address = Address::<F>::rand();
+    let middle_user_asset_root: WrappedHashOut<F> = HashOut::rand().into();
     world_state_tree
+        .set(sender_address.0.into(), middle_user_asset_root)
+        .unwrap();
+    let old_world_state_root = world_state_tree.get_root();
+    let user_transaction = MergeAndPurgeTransitionPublicInputs {
+        sender_address,
+        old_user_asset_root: middle_user_asset_root,
+        middle_user_asset_root,
+        new_user_asset_root: HashOut::rand().into(),
+        ..Default::default()
+    };
+    let user_tx_proof = user_tx_circuit.prove(&user_transaction.encode()).unwrap();
+    let update_proof = world_state_tree
         .set(
-            sender1_account.address.0.into(),
-            sender1_user_asset_tree.get_root(),
+            sender_address.0.into(),
+            user_transaction.new_user_asset_root,
         )
         .unwrap();
-
-    let proof1 = sender1_user_asset_tree
-        .set(key2.0, key2.1, key2.2, zero)
-        .unwrap();
-    let proof2 = sender1_user_asset_tree
-        .set(key1.0, key1.1, key1.2, zero)
-        .unwrap();
-
-    let proof3 = sender1_tx_diff_tree
-        .set(key3.0, key3.1, key3.2, value3)
-        .unwrap();
-    let proof4 = sender1_tx_diff_tree
-        .set(key4.0, key4.1, key4.2, value4)
+    let other_proof = world_state_tree
+        .set(HashOut::rand().into(), HashOut::rand().into())
         .unwrap();
 
-    let sender1_input_witness = vec![proof1, proof2];
-    let sender1_output_witness = vec![proof3, proof4];
+    // The world state process proofs and whether the slots are enabled, which are set without validation.
+    let prove = |world_state_process_proofs: [&SmtProcessProof<F>; 2], enabled: [bool; 2]| {
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(targets.old_world_state_root, *old_world_state_root);
+        for (i, (p_t, p)) in targets
+            .world_state_process_proofs
+            .iter()
+            .zip(world_state_process_proofs)
+            .enumerate()
+        {
+            p_t.set_witness(&mut pw, p);
+            targets.set_user_tx_proof_witness(&mut pw, i, &user_tx_proof, enabled[i]);
+        }
 
-    let sender2_private_key = HashOut {
-        elements: [
-            GoldilocksField::from_canonical_u64(15657143458229430356),
-            GoldilocksField::from_canonical_u64(6012455030006979790),
-            GoldilocksField::from_canonical_u64(4280058849535143691),
-            GoldilocksField::from_canonical_u64(5153662694263190591),
-        ],
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| {
+                data.verify(proof.clone())?;
+                Ok(proof.public_inputs)
+            })
+        }))
     };
-    dbg!(&sender2_private_key);
-    let sender2_account = private_key_to_account(sender2_private_key);
-    let sender2_address = sender2_account.address.0;
-
-    let node_data = Arc::new(Mutex::new(NodeDataMemory::default()));
-    let mut sender2_user_asset_tree =
-        PoseidonSparseMerkleTree::new(node_data.clone(), Default::default());
 
-    let mut sender2_tx_diff_tree =
-        LayeredLayeredPoseidonSparseMerkleTree::new(node_data.clone(), Default::default());
-
-    let mut deposit_sender2_tree =
-        LayeredLayeredPoseidonSparseMerkleTree::new(node_data, Default::default());
-
-    deposit_sender2_tree
-        .set(sender2_address.into(), key1.1, key1.2, value1)
-        .unwrap();
-    deposit_sender2_tree
-        .set(sender2_address.into(), key2.1, key2.2, value2)
+    let no_op_after_update = SmtProcessProof::with_root(update_proof.new_root);
+    let public_inputs = prove([&update_proof, &no_op_after_update], [true, false])
+        .unwrap()
         .unwrap();
+    assert_eq!(
+        HashOut::from_partial(&public_inputs[0..4]),
+        *update_proof.new_root
+    );
 
-    let deposit_sender2_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
-        deposit_sender2_tree.into();
-
-    let merge_inclusion_proof2 = deposit_sender2_tree.find(&sender2_address.into()).unwrap();
-
-    let deposit_nonce = HashOut::ZERO;
-    let deposit_tx_hash = PoseidonHash::two_to_one(*merge_inclusion_proof2.root, deposit_nonce);
+    // An enabled slot after a disabled one is rejected.
+    let no_op = SmtProcessProof::with_root(old_world_state_root);
+    assert!(!matches!(
+        prove([&no_op, &update_proof], [false, true]),
+        Ok(Ok(_))
+    ));
+
+    // A disabled slot which changes the world state root is rejected.
+    assert_eq!(other_proof.old_root, update_proof.new_root);
+    assert_ne!(other_proof.new_root, other_proof.old_root);
+    assert!(!matches!(
+        prove([&update_proof, &other_proof], [true, false]),
+        Ok(Ok(_))
+    ));
+}
 
-    let merge_inclusion_proof1 = get_merkle_proof(&[deposit_tx_hash.into()], 0, N_LOG_TXS);
+#[test]
+fn test_account_creation_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
 
-    let default_hash = HashOut::ZERO;
-    let default_inclusion_proof = SparseMerkleInclusionProof::with_root(Default::default());
-    let default_merkle_root = get_merkle_proof(&[], 0, N_LOG_TXS).root;
-    let prev_block_header = BlockHeader {
-        block_number: 0,
-        prev_block_header_digest: default_hash,
-        transactions_digest: *default_merkle_root,
-        deposit_digest: *merge_inclusion_proof1.root,
-        proposed_world_state_digest: default_hash,
-        approved_world_state_digest: default_hash,
-        latest_account_digest: default_hash,
+    use plonky2::{
+        field::types::Sample, iop::witness::PartialWitness, plonk::circuit_data::CircuitConfig,
     };
 
-    let block_hash = get_block_hash(&prev_block_header);
-
-    let deposit_merge_key = PoseidonHash::two_to_one(deposit_tx_hash, block_hash).into();
-
-    let merge_process_proof = sender2_user_asset_tree
-        .set(deposit_merge_key, merge_inclusion_proof2.value)
-        .unwrap();
-
-    let merge_proof = MergeProof {
-        is_deposit: true,
-        diff_tree_inclusion_proof: (
-            prev_block_header,
-            merge_inclusion_proof1,
-            merge_inclusion_proof2,
-        ),
-        merge_process_proof,
-        latest_account_tree_inclusion_proof: default_inclusion_proof,
-        nonce: deposit_nonce.into(),
+    use crate::{
+        fixtures::{make_public_inputs_circuit, C, D, F, N_LOG_MAX_USERS},
+        sparse_merkle_tree::goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree},
+        transaction::circuits::MERGE_AND_PURGE_PUBLIC_INPUTS_LEN,
+        zkdsa::account::Address,
     };
 
-    world_state_tree
-        .set(sender2_address.into(), sender2_user_asset_tree.get_root())
-        .unwrap();
-
-    let mut sender2_user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
-        sender2_user_asset_tree.into();
-    let proof1 = sender2_user_asset_tree
-        .set(deposit_merge_key, key2.1, key2.2, zero)
-        .unwrap();
-    let proof2 = sender2_user_asset_tree
-        .set(deposit_merge_key, key1.1, key1.2, zero)
-        .unwrap();
+    let user_tx_circuit = make_public_inputs_circuit(MERGE_AND_PURGE_PUBLIC_INPUTS_LEN);
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, 2> =
+        ProposalBlockProofTarget::add_virtual_to(&mut builder, &user_tx_circuit.data);
+    builder.register_public_inputs(&targets.new_world_state_root.elements);
+    let data = builder.build::<C>();
 
-    let proof3 = sender2_tx_diff_tree
-        .set(key3.0, key3.1, key3.2, value3)
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    world_state_tree
+        .set(HashOut::rand().into(), HashOut::rand().into())
         .unwrap();
-    let proof4 = sender2_tx_diff_tree
-        .set(key4.0, key4.1, key4.2, value4)
+    let old_world_state_root = *world_state_tree.get_root();
+
+    // The sender merges only transfers received on L2 and has no leaf in the world state yet.
+    let sender_address = Address::<F>::rand();
+    let user_transaction = MergeAndPurgeTransitionPublicInputs {
+        sender_address,
+        middle_user_asset_root: HashOut::rand().into(),
+        new_user_asset_root: HashOut::rand().into(),
+        ..Default::default()
+    };
+    let insert_proof = world_state_tree
+        .set(
+            sender_address.0.into(),
+            user_transaction.new_user_asset_root,
+        )
         .unwrap();
+    assert_eq!(insert_proof.fnc, ProcessMerkleProofRole::ProcessInsert);
+    let no_op = SmtProcessProof::with_root(insert_proof.new_root);
+
+    // The first slot creates the account and the second slot is disabled.
+    let prove = |user_transaction: &MergeAndPurgeTransitionPublicInputs<F>| {
+        let user_tx_proof = user_tx_circuit.prove(&user_transaction.encode()).unwrap();
+        let mut pw = PartialWitness::new();
+        targets
+            .set_world_state_witness(&mut pw, &[insert_proof.clone()], old_world_state_root)
+            .unwrap();
+        targets.set_user_tx_proof_witness(&mut pw, 0, &user_tx_proof, true);
+        targets.set_user_tx_proof_witness(&mut pw, 1, &user_tx_proof, false);
+
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| {
+                data.verify(proof.clone())?;
+                Ok(proof.public_inputs)
+            })
+        }))
+    };
 
-    let sender2_input_witness = vec![proof1, proof2];
-    let sender2_output_witness = vec![proof3, proof4];
-    // dbg!(
-    //     serde_json::to_string(&sender2_input_witness).unwrap(),
-    //     serde_json::to_string(&sender2_output_witness).unwrap()
-    // );
-
-    let sender1_nonce = WrappedHashOut::rand();
-
-    let mut pw = PartialWitness::new();
-    merge_and_purge_circuit
-        .targets
-        .merge_proof_target
-        .set_witness(
-            &mut pw,
-            &[],
-            *sender1_input_witness.first().unwrap().0.old_root,
-        );
-    merge_and_purge_circuit
-        .targets
-        .purge_proof_target
-        .set_witness(
-            &mut pw,
-            sender1_account.address,
-            &sender1_input_witness,
-            &sender1_output_witness,
-            sender1_input_witness.first().unwrap().0.old_root,
-            sender1_nonce,
-        );
-
-    println!("start proving: sender1_tx_proof");
-    let start = Instant::now();
-    let sender1_tx_proof = merge_and_purge_circuit.prove(pw).unwrap();
-    let end = start.elapsed();
-    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
-
-    // dbg!(&sender1_tx_proof.public_inputs);
-
-    match merge_and_purge_circuit.verify(sender1_tx_proof.clone()) {
-        Ok(()) => println!("Ok!"),
-        Err(x) => println!("{}", x),
-    }
+    let public_inputs = prove(&user_transaction).unwrap().unwrap();
+    assert_eq!(HashOut::from_partial(&public_inputs[0..4]), *no_op.new_root);
 
-    let sender2_nonce = WrappedHashOut::rand();
-
-    let mut pw = PartialWitness::new();
-    merge_and_purge_circuit
-        .targets
-        .merge_proof_target
-        .set_witness(&mut pw, &[merge_proof], default_hash);
-    merge_and_purge_circuit
-        .targets
-        .purge_proof_target
-        .set_witness(
-            &mut pw,
-            sender2_account.address,
-            &sender2_input_witness,
-            &sender2_output_witness,
-            sender2_input_witness.first().unwrap().0.old_root,
-            sender2_nonce,
-        );
+    // An account with assets before the transaction is not created.
+    let not_empty = MergeAndPurgeTransitionPublicInputs {
+        old_user_asset_root: HashOut::rand().into(),
+        ..user_transaction.clone()
+    };
+    assert!(validate_proposal_witness(
+        &[insert_proof.clone()],
+        &[not_empty.clone()],
+        old_world_state_root
+    )
+    .is_err());
+    assert!(!matches!(prove(&not_empty), Ok(Ok(_))));
+
+    // An account is not created with the empty user asset tree.
+    // The sparse Merkle tree never inserts the empty value,
+    // so the insert proof of the non-empty root is paired with the user transaction purging all assets.
+    let empty = MergeAndPurgeTransitionPublicInputs {
+        new_user_asset_root: WrappedHashOut::default(),
+        ..user_transaction
+    };
+    assert!(validate_proposal_witness(
+        &[insert_proof.clone()],
+        &[empty.clone()],
+        old_world_state_root
+    )
+    .is_err());
+    assert!(!matches!(prove(&empty), Ok(Ok(_))));
+}
 
-    println!("start proving: sender2_tx_proof");
-    let start = Instant::now();
-    let sender2_tx_proof = merge_and_purge_circuit.prove(pw).unwrap();
-    let end = start.elapsed();
-    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
+#[test]
+fn test_proposal_block_protocol_version_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
 
-    // dbg!(&sender2_tx_proof.public_inputs);
+    use plonky2::{
+        field::types::{Field, Sample},
+        iop::witness::PartialWitness,
+        plonk::circuit_data::CircuitConfig,
+    };
 
-    match merge_and_purge_circuit.verify(sender2_tx_proof.clone()) {
-        Ok(()) => println!("Ok!"),
-        Err(x) => println!("{}", x),
-    }
+    use crate::{
+        fixtures::{make_public_inputs_circuit, C, D, F, N_LOG_MAX_USERS},
+        sparse_merkle_tree::goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree},
+        transaction::circuits::MERGE_AND_PURGE_PUBLIC_INPUTS_LEN,
+        verification::public_inputs::PROTOCOL_VERSION,
+        zkdsa::account::Address,
+    };
 
-    let mut world_state_process_proofs = vec![];
-    let mut user_tx_proofs = vec![];
+    let user_tx_circuit = make_public_inputs_circuit(MERGE_AND_PURGE_PUBLIC_INPUTS_LEN);
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, 2> =
+        ProposalBlockProofTarget::add_virtual_to(&mut builder, &user_tx_circuit.data);
+    let data = builder.build::<C>();
 
-    let sender1_world_state_process_proof = world_state_tree
-        .set(sender1_address.into(), sender1_user_asset_tree.get_root())
+    let mut world_state_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    let sender_address = Address::<F>::rand();
+    let middle_user_asset_root: WrappedHashOut<F> = HashOut::rand().into();
+    world_state_tree
+        .set(sender_address.0.into(), middle_user_asset_root)
         .unwrap();
-
-    // dbg!(serde_json::to_string(&sender1_world_state_process_proof).unwrap());
-
-    let sender2_world_state_process_proof = world_state_tree
-        .set(sender2_address.into(), sender2_user_asset_tree.get_root())
+    let old_world_state_root = *world_state_tree.get_root();
+    let user_transaction = MergeAndPurgeTransitionPublicInputs {
+        sender_address,
+        old_user_asset_root: middle_user_asset_root,
+        middle_user_asset_root,
+        new_user_asset_root: HashOut::rand().into(),
+        ..Default::default()
+    };
+    let update_proof = world_state_tree
+        .set(
+            sender_address.0.into(),
+            user_transaction.new_user_asset_root,
+        )
         .unwrap();
 
-    world_state_process_proofs.push(sender1_world_state_process_proof);
-    user_tx_proofs.push(sender1_tx_proof.clone());
-    world_state_process_proofs.push(sender2_world_state_process_proof);
-    user_tx_proofs.push(sender2_tx_proof.clone());
-
-    let zkdsa_circuit = make_simple_signature_circuit();
-
-    let mut pw = PartialWitness::new();
-    zkdsa_circuit.targets.set_witness(
-        &mut pw,
-        sender1_account.private_key,
-        *world_state_tree.get_root(),
-    );
-
-    println!("start proving: sender1_received_signature");
-    let start = Instant::now();
-    let sender1_received_signature = zkdsa_circuit.prove(pw).unwrap();
-    let end = start.elapsed();
-    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
-
-    // dbg!(&sender1_received_signature.public_inputs);
-
-    let mut pw = PartialWitness::new();
-    zkdsa_circuit.targets.set_witness(
-        &mut pw,
-        sender2_account.private_key,
-        *world_state_tree.get_root(),
-    );
-
-    println!("start proving: sender2_received_signature");
-    let start = Instant::now();
-    let sender2_received_signature = zkdsa_circuit.prove(pw).unwrap();
-    let end = start.elapsed();
-    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
-
-    // dbg!(&sender2_received_signature.public_inputs);
-
-    // proposal block
-    let config = CircuitConfig::standard_recursion_config();
-    let mut builder = CircuitBuilder::<F, D>::new(config);
-    let proposal_block_target: ProposalBlockProofTarget<D, N_LOG_MAX_USERS, N_TXS> =
-        ProposalBlockProofTarget::add_virtual_to(&mut builder, &merge_and_purge_circuit.data);
-    let circuit_data = builder.build::<C>();
-
-    let block_number = 1;
-
-    let accounts_in_block: Vec<(Option<_>, _)> = vec![
-        (Some(sender1_received_signature), sender1_tx_proof),
-        (Some(sender2_received_signature), sender2_tx_proof),
-    ];
-
-    let mut latest_account_tree: PoseidonSparseMerkleTree<NodeDataMemory> =
-        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
-
-    // NOTICE: merge proof の中に deposit が混ざっていると, revert proof がうまく出せない場合がある.
-    // deposit してそれを消費して old: 0 -> middle: non-zero -> new: 0 となった場合は,
-    // u.enabled かつ w.fnc == NoOp だが revert ではない.
-    let mut world_state_revert_proofs = vec![];
-    let mut latest_account_tree_process_proofs = vec![];
-    let mut received_signatures = vec![];
-    for (opt_received_signature, user_tx_proof) in accounts_in_block {
-        let user_address = user_tx_proof.public_inputs.sender_address;
-        let (last_block_number, confirmed_user_asset_root) = if opt_received_signature.is_none() {
-            let old_block_number = latest_account_tree.get(&user_address.0.into()).unwrap();
-            (
-                old_block_number.to_u32(),
-                user_tx_proof.public_inputs.old_user_asset_root,
-            )
-        } else {
-            (
-                block_number,
-                user_tx_proof.public_inputs.new_user_asset_root,
-            )
-        };
-        latest_account_tree_process_proofs.push(
-            latest_account_tree
-                .set(
-                    user_address.0.into(),
-                    GoldilocksHashOut::from_u32(last_block_number),
-                )
-                .unwrap(),
-        );
-
-        let proof = world_state_tree
-            .set(user_address.0.into(), confirmed_user_asset_root)
+    let prove = |public_inputs: &[F]| {
+        let user_tx_proof = user_tx_circuit.prove(public_inputs).unwrap();
+        let mut pw = PartialWitness::new();
+        targets
+            .set_world_state_witness(&mut pw, &[update_proof.clone()], old_world_state_root)
             .unwrap();
-        world_state_revert_proofs.push(proof);
-        received_signatures.push(opt_received_signature);
-    }
+        targets.set_user_tx_proof_witness(&mut pw, 0, &user_tx_proof, true);
+        targets.set_user_tx_proof_witness(&mut pw, 1, &user_tx_proof, false);
 
-    let mut pw = PartialWitness::new();
-    proposal_block_target.set_witness(
-        &mut pw,
-        &world_state_process_proofs,
-        &user_tx_proofs
-            .iter()
-            .map(|p| ProofWithPublicInputs::from(p.clone()))
-            .collect::<Vec<_>>(),
-        *world_state_process_proofs.first().unwrap().old_root,
-    );
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| data.verify(proof))
+        }))
+    };
 
-    println!("start proving: block_proof");
-    let start = Instant::now();
-    let proof = circuit_data.prove(pw).unwrap();
-    let end = start.elapsed();
-    println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
+    let mut public_inputs = user_transaction.encode();
+    prove(&public_inputs).unwrap().unwrap();
 
-    match circuit_data.verify(proof) {
-        Ok(()) => println!("Ok!"),
-        Err(x) => println!("{}", x),
-    }
+    // The user transaction proof of another protocol version is rejected.
+    *public_inputs.last_mut().unwrap() = F::from_canonical_u32(PROTOCOL_VERSION + 1);
+    assert!(!matches!(prove(&public_inputs), Ok(Ok(_))));
 }
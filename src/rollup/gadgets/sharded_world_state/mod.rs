@@ -0,0 +1,157 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::{target::Target, witness::Witness},
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::{
+    errors::{ensure_at_most, ensure_length, ensure_witness, IntmaxError},
+    merkle_tree::gadgets::get_merkle_root_target_from_leaves,
+    rollup::sharded_world_state::get_shard_index,
+    sparse_merkle_tree::{
+        gadgets::process::{
+            batch_process_smt::SparseMerkleBatchProcessTarget, process_smt::SmtProcessProof,
+            utils::get_process_merkle_proof_role,
+        },
+        proof::ProcessMerkleProofRole,
+    },
+    zkdsa::account::Address,
+};
+
+/// The in-circuit counterpart of `get_shard_index`,
+/// i.e. the first `n_log_shards` bits of the path of `address` in the world state tree.
+pub fn get_shard_index_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    address: HashOutTarget,
+    n_log_shards: usize,
+) -> Target {
+    let bits = builder.split_le(address.elements[0], 64);
+
+    builder.le_sum(bits[0..n_log_shards].iter())
+}
+
+/// The updates of the shard `shard_index` of the world state,
+/// which can be proved independently of the other shards.
+#[derive(Clone, Debug)]
+pub struct ShardUpdateTarget<
+    const N_LOG_SHARDS: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_UPDATES: usize,
+> {
+    pub shard_index: Target, // input
+    pub process_proofs: SparseMerkleBatchProcessTarget<N_LOG_MAX_USERS, N_UPDATES>, // input
+
+    pub old_shard_root: HashOutTarget, // output
+    pub new_shard_root: HashOutTarget, // output
+}
+
+impl<const N_LOG_SHARDS: usize, const N_LOG_MAX_USERS: usize, const N_UPDATES: usize>
+    ShardUpdateTarget<N_LOG_SHARDS, N_LOG_MAX_USERS, N_UPDATES>
+{
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        assert_ne!(N_UPDATES, 0, "a shard update must have a process proof");
+
+        let zero = builder.zero();
+        let shard_index = builder.add_virtual_target();
+        builder.range_check(shard_index, N_LOG_SHARDS);
+
+        let process_proofs = SparseMerkleBatchProcessTarget::add_virtual_to::<F, H, D>(builder);
+        for proof_t in process_proofs.process_proofs.iter() {
+            // The updated addresses belong to this shard.
+            let role = get_process_merkle_proof_role(builder, proof_t.fnc);
+            let key_shard_index = get_shard_index_target(builder, proof_t.new_key, N_LOG_SHARDS);
+            let diff = builder.sub(key_shard_index, shard_index);
+            let diff = builder.mul(diff, role.is_not_no_op.target);
+            builder.connect(diff, zero);
+        }
+
+        Self {
+            shard_index,
+            old_shard_root: process_proofs.old_root,
+            new_shard_root: process_proofs.new_root,
+            process_proofs,
+        }
+    }
+
+    /// The unused slots are filled with no-op proofs.
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        shard_index: usize,
+        process_proofs: &[SmtProcessProof<F>],
+        old_shard_root: HashOut<F>,
+    ) -> Result<(), IntmaxError> {
+        ensure_at_most("shard process proofs", process_proofs.len(), N_UPDATES)?;
+        ensure_witness!(
+            shard_index < 1 << N_LOG_SHARDS,
+            "shard index {} is out of range",
+            shard_index
+        );
+
+        for (i, proof) in process_proofs.iter().enumerate() {
+            ensure_witness!(
+                proof.fnc == ProcessMerkleProofRole::ProcessNoOp
+                    || get_shard_index(Address(*proof.new_key), N_LOG_SHARDS) == shard_index,
+                "shard process proof #{} updates an address of another shard",
+                i
+            );
+        }
+
+        pw.set_target(self.shard_index, F::from_canonical_usize(shard_index));
+        self.process_proofs
+            .set_witness(pw, process_proofs, old_shard_root)?;
+
+        Ok(())
+    }
+}
+
+/// Combines the roots of the `2^N_LOG_SHARDS` shards into the world state root.
+#[derive(Clone, Debug)]
+pub struct ShardedWorldStateRootTarget<const N_LOG_SHARDS: usize> {
+    pub old_shard_roots: Vec<HashOutTarget>, // input
+    pub new_shard_roots: Vec<HashOutTarget>, // input
+
+    pub old_world_state_root: HashOutTarget, // output
+    pub new_world_state_root: HashOutTarget, // output
+}
+
+impl<const N_LOG_SHARDS: usize> ShardedWorldStateRootTarget<N_LOG_SHARDS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let old_shard_roots = builder.add_virtual_hashes(1 << N_LOG_SHARDS);
+        let new_shard_roots = builder.add_virtual_hashes(1 << N_LOG_SHARDS);
+        let old_world_state_root =
+            get_merkle_root_target_from_leaves::<F, H, D>(builder, old_shard_roots.clone());
+        let new_world_state_root =
+            get_merkle_root_target_from_leaves::<F, H, D>(builder, new_shard_roots.clone());
+
+        Self {
+            old_shard_roots,
+            new_shard_roots,
+            old_world_state_root,
+            new_world_state_root,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        old_shard_roots: &[HashOut<F>],
+        new_shard_roots: &[HashOut<F>],
+    ) -> Result<(), IntmaxError> {
+        ensure_length("old shard roots", old_shard_roots.len(), 1 << N_LOG_SHARDS)?;
+        ensure_length("new shard roots", new_shard_roots.len(), 1 << N_LOG_SHARDS)?;
+        for (root_t, root) in self.old_shard_roots.iter().zip(old_shard_roots) {
+            pw.set_hash_target(*root_t, *root);
+        }
+        for (root_t, root) in self.new_shard_roots.iter().zip(new_shard_roots) {
+            pw.set_hash_target(*root_t, *root);
+        }
+
+        Ok(())
+    }
+}
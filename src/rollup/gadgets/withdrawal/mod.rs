@@ -0,0 +1,394 @@
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::{
+        target::{BoolTarget, Target},
+        witness::Witness,
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        config::{AlgebraicHasher, Hasher},
+    },
+};
+
+use crate::{
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
+    merkle_tree::gadgets::get_merkle_root_target_from_leaves,
+    sparse_merkle_tree::gadgets::common::{conditionally_select, enforce_equal_if_enabled},
+    transaction::{asset::Asset, burn::get_burned_assets_digest},
+    zkdsa::{account::Address, gadgets::account::AddressTarget},
+};
+
+/// A withdrawal of `amount` tokens of the kind `(contract_address, variable_index)`,
+/// which is an asset burned by the transaction of `recipient`.
+/// The L1 contract pays it to the L1 account registered to `recipient`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WithdrawalInfo<F: Field> {
+    pub recipient: Address<F>,
+    pub contract_address: Address<F>,
+    pub variable_index: HashOut<F>,
+    pub amount: F,
+}
+
+impl<F: RichField> WithdrawalInfo<F> {
+    /// The withdrawal of `asset` burned by the transaction of `sender_address`.
+    pub fn from_burn(sender_address: Address<F>, asset: &Asset<F>) -> Self {
+        Self {
+            recipient: sender_address,
+            contract_address: asset.kind.contract_address,
+            variable_index: *asset.kind.variable_index,
+            amount: F::from_canonical_u64(asset.amount),
+        }
+    }
+
+    /// The leaf of the withdrawal tree.
+    pub fn hash(&self) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&self.encode())
+    }
+
+    pub fn encode(&self) -> Vec<F> {
+        [
+            self.recipient.0.elements.to_vec(),
+            self.contract_address.0.elements.to_vec(),
+            self.variable_index.elements.to_vec(),
+            vec![self.amount],
+        ]
+        .concat()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WithdrawalInfoTarget {
+    pub recipient: AddressTarget,
+    pub contract_address: AddressTarget,
+    pub variable_index: HashOutTarget,
+    pub amount: Target,
+}
+
+impl WithdrawalInfoTarget {
+    /// The amount is not range-checked, since it is bound to a burn of a proven transaction.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        recipient: AddressTarget,
+    ) -> Self {
+        let contract_address = AddressTarget::add_virtual_to(builder);
+        let variable_index = builder.add_virtual_hash();
+        let amount = builder.add_virtual_target();
+
+        Self {
+            recipient,
+            contract_address,
+            variable_index,
+            amount,
+        }
+    }
+
+    /// Sets the burned asset. The recipient is given by the circuit.
+    pub fn set_witness<F: RichField>(&self, pw: &mut impl Witness<F>, asset: &Asset<F>) {
+        self.contract_address
+            .set_witness(pw, asset.kind.contract_address);
+        pw.set_hash_target(self.variable_index, *asset.kind.variable_index);
+        pw.set_target(self.amount, F::from_canonical_u64(asset.amount));
+    }
+
+    fn set_empty_witness<F: RichField>(&self, pw: &mut impl Witness<F>) {
+        self.contract_address.set_witness(pw, Address::default());
+        pw.set_hash_target(self.variable_index, HashOut::ZERO);
+        pw.set_target(self.amount, F::ZERO);
+    }
+
+    /// The in-circuit counterpart of `WithdrawalInfo::hash`.
+    pub fn hash<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> HashOutTarget {
+        let inputs = [
+            self.recipient.0.elements.to_vec(),
+            self.contract_address.0.elements.to_vec(),
+            self.variable_index.elements.to_vec(),
+            vec![self.amount],
+        ]
+        .concat();
+
+        builder.hash_n_to_hash_no_pad::<H>(inputs)
+    }
+}
+
+/// A transaction slot of the block, which the withdrawals are taken from.
+#[derive(Clone, Copy, Debug)]
+pub struct BurnTransactionTarget {
+    pub sender_address: AddressTarget,
+
+    /// `get_burned_assets_digest` of the assets burned by the transaction.
+    pub burned_assets_digest: HashOutTarget,
+    pub is_approved: BoolTarget,
+}
+
+/// The withdrawals of a block, which are the assets burned by its approved transactions
+/// paid to their senders. The `k`-th withdrawal of the transaction in slot `i` is the leaf
+/// `i * n_burns_per_tx.next_power_of_two() + k` of the withdrawal tree,
+/// whose leaves are padded to a power of two. The leaves of the empty withdrawal slots
+/// and of the transactions not approved are zero.
+#[derive(Clone, Debug)]
+pub struct WithdrawalBlockProofTarget {
+    /// `withdrawals[i]` are the withdrawals of the transaction in slot `i`.
+    pub withdrawals: Vec<Vec<WithdrawalInfoTarget>>, // input
+    pub enabled: Vec<Vec<BoolTarget>>,  // input
+    pub withdrawal_root: HashOutTarget, // output
+}
+
+impl WithdrawalBlockProofTarget {
+    /// The withdrawals of an approved transaction must be exactly its burns in order,
+    /// i.e. their hash chain must be its `burned_assets_digest`.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        transactions: &[BurnTransactionTarget],
+        n_burns_per_tx: usize,
+    ) -> Self {
+        let zero_hash = builder.constant_hash(HashOut::ZERO);
+        let n_leaves_per_tx = n_burns_per_tx.next_power_of_two();
+
+        let mut withdrawals = vec![];
+        let mut enabled = vec![];
+        let mut leaves = vec![];
+        for tx in transactions {
+            let mut tx_withdrawals = Vec::with_capacity(n_burns_per_tx);
+            let mut tx_enabled = Vec::with_capacity(n_burns_per_tx);
+            // The same hash chain as `get_burned_assets_digest_target`.
+            let mut digest = zero_hash;
+            for _ in 0..n_burns_per_tx {
+                let withdrawal = WithdrawalInfoTarget::add_virtual_to(builder, tx.sender_address);
+                let enabled_t = builder.add_virtual_bool_target_safe();
+                let inputs = [
+                    digest.elements.to_vec(),
+                    withdrawal.contract_address.0.elements.to_vec(),
+                    withdrawal.variable_index.elements.to_vec(),
+                    vec![withdrawal.amount],
+                ]
+                .concat();
+                let new_digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+                digest = conditionally_select(builder, new_digest, digest, enabled_t);
+
+                let is_withdrawn = builder.and(enabled_t, tx.is_approved);
+                let leaf_hash = withdrawal.hash::<F, H, D>(builder);
+                leaves.push(conditionally_select(
+                    builder,
+                    leaf_hash,
+                    zero_hash,
+                    is_withdrawn,
+                ));
+                tx_withdrawals.push(withdrawal);
+                tx_enabled.push(enabled_t);
+            }
+            enforce_equal_if_enabled(builder, digest, tx.burned_assets_digest, tx.is_approved);
+            leaves.resize(leaves.len() + n_leaves_per_tx - n_burns_per_tx, zero_hash);
+            withdrawals.push(tx_withdrawals);
+            enabled.push(tx_enabled);
+        }
+        // The tree has at least one level, as `get_merkle_proof` does.
+        leaves.resize(
+            (transactions.len().next_power_of_two() * n_leaves_per_tx).max(2),
+            zero_hash,
+        );
+
+        let withdrawal_root = get_merkle_root_target_from_leaves::<F, H, D>(builder, leaves);
+
+        Self {
+            withdrawals,
+            enabled,
+            withdrawal_root,
+        }
+    }
+
+    /// `burned_assets[i]` are the assets burned by the transaction in slot `i`,
+    /// and the slots without them burn nothing.
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        burned_assets: &[Vec<Asset<F>>],
+    ) -> Result<(), IntmaxError> {
+        ensure_at_most(
+            "burning transactions",
+            burned_assets.len(),
+            self.withdrawals.len(),
+        )?;
+        for (i, (tx_withdrawals, tx_enabled)) in
+            self.withdrawals.iter().zip(self.enabled.iter()).enumerate()
+        {
+            let tx_burned_assets = burned_assets.get(i).map_or(&[][..], |assets| &assets[..]);
+            ensure_at_most(
+                "burned assets of a transaction",
+                tx_burned_assets.len(),
+                tx_withdrawals.len(),
+            )?;
+            for (k, (withdrawal_t, enabled_t)) in
+                tx_withdrawals.iter().zip(tx_enabled.iter()).enumerate()
+            {
+                if let Some(asset) = tx_burned_assets.get(k) {
+                    withdrawal_t.set_witness(pw, asset);
+                    pw.set_bool_target(*enabled_t, true);
+                } else {
+                    withdrawal_t.set_empty_witness(pw);
+                    pw.set_bool_target(*enabled_t, false);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `burned_assets[i]` are the assets committed in `burned_assets_digests[i]`,
+/// where the transactions without them burn nothing.
+pub fn validate_burned_assets<F: RichField>(
+    burned_assets_digests: &[HashOut<F>],
+    burned_assets: &[Vec<Asset<F>>],
+) -> Result<(), IntmaxError> {
+    ensure_at_most(
+        "burning transactions",
+        burned_assets.len(),
+        burned_assets_digests.len(),
+    )?;
+    for (i, burned_assets_digest) in burned_assets_digests.iter().enumerate() {
+        let tx_burned_assets = burned_assets.get(i).map_or(&[][..], |assets| &assets[..]);
+        ensure_witness!(
+            get_burned_assets_digest(tx_burned_assets) == *burned_assets_digest,
+            "the burned assets of user transaction #{} are not of its burned assets digest",
+            i
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_withdrawal_block_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::{
+        rollup::withdrawal::{get_block_withdrawals, get_withdrawal_root},
+        sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut,
+        transaction::asset::TokenKind,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::Hasher;
+    type F = GoldilocksField;
+    const N_TXS: usize = 2;
+    const N_BURNS_PER_TX: usize = 2;
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let transactions_t = (0..N_TXS)
+        .map(|_| BurnTransactionTarget {
+            sender_address: AddressTarget::add_virtual_to(&mut builder),
+            burned_assets_digest: builder.add_virtual_hash(),
+            is_approved: builder.add_virtual_bool_target_safe(),
+        })
+        .collect::<Vec<_>>();
+    let withdrawal_block_target = WithdrawalBlockProofTarget::add_virtual_to::<F, H, D>(
+        &mut builder,
+        &transactions_t,
+        N_BURNS_PER_TX,
+    );
+    builder.register_public_inputs(&withdrawal_block_target.withdrawal_root.elements);
+    let data = builder.build::<C>();
+
+    let senders = [1, 2].map(|i| Address(*GoldilocksHashOut::from_u32(i)));
+    let burned_assets = (1..=2)
+        .map(|i| Asset {
+            kind: TokenKind {
+                contract_address: Address(*GoldilocksHashOut::from_u32(100)),
+                variable_index: GoldilocksHashOut::from_u32(i),
+            },
+            amount: 10 * i as u64,
+        })
+        .collect::<Vec<_>>();
+    // The first transaction burns two assets, and the second one burns nothing.
+    let burned_assets_digests = [get_burned_assets_digest(&burned_assets), HashOut::ZERO];
+
+    let prove = |is_approved: [bool; N_TXS], withdrawn_assets: &[Vec<Asset<F>>]| {
+        let mut pw = PartialWitness::new();
+        for (((tx_t, sender), digest), is_approved) in transactions_t
+            .iter()
+            .zip(senders.iter())
+            .zip(burned_assets_digests.iter())
+            .zip(is_approved)
+        {
+            tx_t.sender_address.set_witness(&mut pw, *sender);
+            pw.set_hash_target(tx_t.burned_assets_digest, *digest);
+            pw.set_bool_target(tx_t.is_approved, is_approved);
+        }
+        withdrawal_block_target
+            .set_witness(&mut pw, withdrawn_assets)
+            .unwrap();
+
+        catch_unwind(AssertUnwindSafe(|| data.prove(pw)))
+    };
+
+    let all_burned_assets = vec![burned_assets.clone()];
+    let proof = prove([true, true], &all_burned_assets).unwrap().unwrap();
+    let withdrawals = get_block_withdrawals(
+        &[(senders[0], &burned_assets[..]), (senders[1], &[][..])],
+        N_TXS,
+        N_BURNS_PER_TX,
+    )
+    .unwrap();
+    assert_eq!(
+        withdrawals[0],
+        Some(WithdrawalInfo::from_burn(senders[0], &burned_assets[0]))
+    );
+    assert_eq!(
+        proof.public_inputs,
+        get_withdrawal_root(&withdrawals).elements.to_vec()
+    );
+    data.verify(proof).unwrap();
+
+    // The burns of the transaction not approved are not withdrawn.
+    let proof = prove([false, true], &all_burned_assets).unwrap().unwrap();
+    let no_withdrawals = get_block_withdrawals::<F>(&[], N_TXS, N_BURNS_PER_TX).unwrap();
+    assert_eq!(
+        proof.public_inputs,
+        get_withdrawal_root(&no_withdrawals).elements.to_vec()
+    );
+
+    // A withdrawal which no transaction burns cannot be proven.
+    let result = prove(
+        [true, true],
+        &[burned_assets.clone(), burned_assets[..1].to_vec()],
+    );
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    // Nor a withdrawal of more than the burned amount.
+    let mut forged_assets = burned_assets.clone();
+    forged_assets[1].amount += 1;
+    let result = prove([true, true], &[forged_assets]);
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    // Nor omitting a burn.
+    let result = prove([true, true], &[burned_assets[..1].to_vec()]);
+    assert!(!matches!(result, Ok(Ok(_))));
+
+    assert!(validate_burned_assets(&burned_assets_digests, &all_burned_assets).is_ok());
+    assert!(matches!(
+        validate_burned_assets(&burned_assets_digests, &[burned_assets[..1].to_vec()]),
+        Err(IntmaxError::InvalidWitness(_))
+    ));
+    let mut pw = PartialWitness::new();
+    assert!(matches!(
+        withdrawal_block_target.set_witness(&mut pw, &[burned_assets.repeat(2)]),
+        Err(IntmaxError::TooManyWitnesses { .. })
+    ));
+}
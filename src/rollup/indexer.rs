@@ -0,0 +1,303 @@
+//! The indices of the published blocks for block explorers and wallet backends.
+//!
+//! `Indexer` ingests the blocks in order and indexes the transactions by sender,
+//! the received diffs (transfers and deposits) by recipient, and the blocks by number and hash.
+//! The indices are kept in an `IndexStore`, which is in memory with `IndexMemory`
+//! and can be backed by a database like `NodeData` of the sparse Merkle trees.
+
+use std::collections::HashMap;
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::{
+        block::BlockInfo, gadgets::deposit_block::DepositInfo, state_manager::IncomingTransfer,
+    },
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::block_header::get_block_hash,
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+/// A user transaction in the block `block_number`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedTransaction {
+    pub block_number: u32,
+    pub tx_hash: WrappedHashOut<F>,
+    pub sender: Address<F>,
+
+    /// Whether the sender signed the proposed world state root, without which the transaction is not applied.
+    pub is_valid: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceivedDiff {
+    /// A leaf of the diff tree of the transaction `tx_hash`.
+    Transfer {
+        tx_hash: WrappedHashOut<F>,
+    },
+    Deposit(DepositInfo<F>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedDiff {
+    pub block_number: u32,
+    pub recipient: Address<F>,
+    pub diff: ReceivedDiff,
+}
+
+/// The storage of the indices. Each list is kept in the order of insertion.
+pub trait IndexStore {
+    fn get_block(&self, block_number: u32) -> anyhow::Result<Option<BlockInfo<F>>>;
+
+    fn get_block_number(&self, block_hash: &WrappedHashOut<F>) -> anyhow::Result<Option<u32>>;
+
+    fn get_latest_block_number(&self) -> anyhow::Result<Option<u32>>;
+
+    fn get_transactions_by_sender(
+        &self,
+        sender: &Address<F>,
+    ) -> anyhow::Result<Vec<IndexedTransaction>>;
+
+    fn get_diffs_by_recipient(&self, recipient: &Address<F>) -> anyhow::Result<Vec<IndexedDiff>>;
+
+    /// Inserts `block` with everything indexed from it at once.
+    fn insert_block(
+        &mut self,
+        block: BlockInfo<F>,
+        block_hash: WrappedHashOut<F>,
+        transactions: Vec<IndexedTransaction>,
+        diffs: Vec<IndexedDiff>,
+    ) -> anyhow::Result<()>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IndexMemory {
+    blocks: HashMap<u32, BlockInfo<F>>,
+    block_numbers: HashMap<WrappedHashOut<F>, u32>,
+    latest_block_number: Option<u32>,
+    transactions: HashMap<Address<F>, Vec<IndexedTransaction>>,
+    diffs: HashMap<Address<F>, Vec<IndexedDiff>>,
+}
+
+impl IndexStore for IndexMemory {
+    fn get_block(&self, block_number: u32) -> anyhow::Result<Option<BlockInfo<F>>> {
+        Ok(self.blocks.get(&block_number).cloned())
+    }
+
+    fn get_block_number(&self, block_hash: &WrappedHashOut<F>) -> anyhow::Result<Option<u32>> {
+        Ok(self.block_numbers.get(block_hash).copied())
+    }
+
+    fn get_latest_block_number(&self) -> anyhow::Result<Option<u32>> {
+        Ok(self.latest_block_number)
+    }
+
+    fn get_transactions_by_sender(
+        &self,
+        sender: &Address<F>,
+    ) -> anyhow::Result<Vec<IndexedTransaction>> {
+        Ok(self.transactions.get(sender).cloned().unwrap_or_default())
+    }
+
+    fn get_diffs_by_recipient(&self, recipient: &Address<F>) -> anyhow::Result<Vec<IndexedDiff>> {
+        Ok(self.diffs.get(recipient).cloned().unwrap_or_default())
+    }
+
+    fn insert_block(
+        &mut self,
+        block: BlockInfo<F>,
+        block_hash: WrappedHashOut<F>,
+        transactions: Vec<IndexedTransaction>,
+        diffs: Vec<IndexedDiff>,
+    ) -> anyhow::Result<()> {
+        let block_number = block.header.block_number;
+        for transaction in transactions {
+            self.transactions
+                .entry(transaction.sender)
+                .or_default()
+                .push(transaction);
+        }
+        for diff in diffs {
+            self.diffs.entry(diff.recipient).or_default().push(diff);
+        }
+        self.block_numbers.insert(block_hash, block_number);
+        self.blocks.insert(block_number, block);
+        self.latest_block_number = Some(block_number);
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Indexer<S: IndexStore = IndexMemory> {
+    pub store: S,
+}
+
+impl<S: IndexStore> Indexer<S> {
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// Indexes `block` with the transfers of its diff trees, as given to `StateManager::apply_block`.
+    /// The blocks must be ingested in order from any block number.
+    pub fn ingest(
+        &mut self,
+        block: &BlockInfo<F>,
+        incoming_transfers: &[IncomingTransfer],
+    ) -> anyhow::Result<()> {
+        let block_number = block.header.block_number;
+        if let Some(latest_block_number) = self.store.get_latest_block_number()? {
+            anyhow::ensure!(
+                block_number == latest_block_number + 1,
+                "the block {} is ingested after the block {}",
+                block_number,
+                latest_block_number
+            );
+        }
+
+        // The address list is padded with the zero address after the transactions of the block.
+        let transactions = block
+            .address_list
+            .iter()
+            .zip(block.transactions.iter())
+            .filter(|(sender, _)| sender.sender_address != Address::default())
+            .map(|(sender, tx_hash)| IndexedTransaction {
+                block_number,
+                tx_hash: *tx_hash,
+                sender: sender.sender_address,
+                is_valid: sender.is_valid,
+            })
+            .collect();
+        let mut diffs = incoming_transfers
+            .iter()
+            .map(|transfer| IndexedDiff {
+                block_number,
+                recipient: transfer.recipient,
+                diff: ReceivedDiff::Transfer {
+                    tx_hash: transfer.tx_hash,
+                },
+            })
+            .collect::<Vec<_>>();
+        diffs.extend(block.deposit_list.iter().map(|deposit| IndexedDiff {
+            block_number,
+            recipient: deposit.receiver_address,
+            diff: ReceivedDiff::Deposit(*deposit),
+        }));
+
+        let block_hash = get_block_hash(&block.header).into();
+        self.store
+            .insert_block(block.clone(), block_hash, transactions, diffs)
+    }
+
+    pub fn latest_block_number(&self) -> anyhow::Result<Option<u32>> {
+        self.store.get_latest_block_number()
+    }
+
+    pub fn block_by_number(&self, block_number: u32) -> anyhow::Result<Option<BlockInfo<F>>> {
+        self.store.get_block(block_number)
+    }
+
+    pub fn block_by_hash(
+        &self,
+        block_hash: &WrappedHashOut<F>,
+    ) -> anyhow::Result<Option<BlockInfo<F>>> {
+        match self.store.get_block_number(block_hash)? {
+            Some(block_number) => self.store.get_block(block_number),
+            None => Ok(None),
+        }
+    }
+
+    /// The transactions of `sender` in the order of the blocks, including those without the signature.
+    pub fn transactions_by_sender(
+        &self,
+        sender: &Address<F>,
+    ) -> anyhow::Result<Vec<IndexedTransaction>> {
+        self.store.get_transactions_by_sender(sender)
+    }
+
+    /// The transfers and deposits to `recipient` in the order of the blocks.
+    pub fn diffs_by_recipient(&self, recipient: &Address<F>) -> anyhow::Result<Vec<IndexedDiff>> {
+        self.store.get_diffs_by_recipient(recipient)
+    }
+}
+
+#[test]
+fn test_indexer() {
+    use plonky2::field::types::Field;
+
+    use crate::{
+        rollup::address_list::TransactionSenderWithValidity,
+        sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut,
+    };
+
+    let sender = Address(*GoldilocksHashOut::from_u32(1));
+    let recipient = Address(*GoldilocksHashOut::from_u32(2));
+    let tx_hash = GoldilocksHashOut::from_u32(3);
+
+    let mut block = BlockInfo::with_tree_depth(2);
+    block.header.block_number = 1;
+    block.transactions = vec![tx_hash];
+    block.address_list = vec![
+        TransactionSenderWithValidity {
+            sender_address: sender,
+            is_valid: true,
+        },
+        TransactionSenderWithValidity {
+            sender_address: Address::default(),
+            is_valid: false,
+        },
+    ];
+    let deposit = DepositInfo {
+        receiver_address: recipient,
+        contract_address: Address(*GoldilocksHashOut::from_u32(4)),
+        variable_index: *GoldilocksHashOut::from_u32(0),
+        amount: F::from_canonical_u32(10),
+    };
+    block.deposit_list = vec![deposit];
+
+    let mut indexer = Indexer::<IndexMemory>::default();
+    indexer
+        .ingest(&block, &[IncomingTransfer { recipient, tx_hash }])
+        .unwrap();
+    assert_eq!(indexer.latest_block_number().unwrap(), Some(1));
+    assert_eq!(
+        indexer.transactions_by_sender(&sender).unwrap(),
+        vec![IndexedTransaction {
+            block_number: 1,
+            tx_hash,
+            sender,
+            is_valid: true,
+        }]
+    );
+    assert!(indexer
+        .transactions_by_sender(&Address::default())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        indexer
+            .diffs_by_recipient(&recipient)
+            .unwrap()
+            .into_iter()
+            .map(|diff| diff.diff)
+            .collect::<Vec<_>>(),
+        vec![
+            ReceivedDiff::Transfer { tx_hash },
+            ReceivedDiff::Deposit(deposit)
+        ]
+    );
+
+    let block_hash = get_block_hash(&block.header).into();
+    let found = indexer.block_by_hash(&block_hash).unwrap().unwrap();
+    assert_eq!(found.header, block.header);
+    assert!(indexer.block_by_number(1).unwrap().is_some());
+    assert!(indexer.block_by_number(2).unwrap().is_none());
+
+    // The blocks must be ingested in order.
+    assert!(indexer.ingest(&block, &[]).is_err());
+    block.header.block_number = 2;
+    indexer.ingest(&block, &[]).unwrap();
+    assert_eq!(indexer.transactions_by_sender(&sender).unwrap().len(), 2);
+}
@@ -0,0 +1,367 @@
+//! Bookkeeping of the submitted user transactions until they are confirmed or expire.
+//!
+//! A transaction admitted to a block is excluded if its sender does not sign the proposed world
+//! state root, and its user asset root is reverted to the one before merge.
+//! `Mempool::settle_block` looks up the senders in the address list of the block,
+//! refunds the fee reserved on admission for each excluded transaction,
+//! and then keeps the transaction for the next block or expires it according to `RetryPolicy`.
+//! Wallets poll `Mempool::status` to know whether they must sign, wait or rebuild the transaction.
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    hash::hash_types::RichField,
+    plonk::config::GenericConfig,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::address_list::TransactionSenderWithValidity,
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::circuits::MergeAndPurgeTransitionProofWithPublicInputs, zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// The number of blocks a transaction may be excluded from before it expires.
+    pub max_exclusions: u32,
+
+    /// The number of blocks a transaction may wait for admission after its submission.
+    pub ttl_blocks: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_exclusions: 3,
+            ttl_blocks: 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExpiryReason {
+    /// The transaction was excluded `RetryPolicy::max_exclusions` times.
+    TooManyExclusions,
+
+    /// The transaction was not admitted within `RetryPolicy::ttl_blocks`.
+    Stale,
+
+    /// The transaction merges assets, which were reverted with the exclusion,
+    /// so its proof no longer starts from the user asset root of the sender.
+    MustBeRebuilt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    /// Waiting for admission after `exclusions` exclusions.
+    Pending {
+        exclusions: u32,
+    },
+    /// The sender must sign the proposed world state root of the block.
+    Admitted {
+        block_number: u32,
+    },
+    Confirmed {
+        block_number: u32,
+    },
+    Expired {
+        reason: ExpiryReason,
+    },
+}
+
+/// The fee reserved for the transaction `tx_hash`, which is given back to `sender`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Refund {
+    pub tx_hash: WrappedHashOut<F>,
+    pub sender: Address<F>,
+    pub block_number: u32,
+    pub amount: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct MempoolEntry<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+    pub submitted_block_number: u32,
+    pub status: TransactionStatus,
+
+    /// The number of the blocks the transaction was excluded from so far.
+    pub exclusions: u32,
+
+    /// The fee reserved while the transaction is admitted, which is 0 otherwise.
+    pub reserved_fee: u64,
+}
+
+pub struct Mempool<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub policy: RetryPolicy,
+    entries: Vec<MempoolEntry<F, C, D>>,
+}
+
+impl<C: GenericConfig<D, F = GoldilocksField>, const D: usize> Mempool<GoldilocksField, C, D>
+where
+    GoldilocksField: Extendable<D>,
+{
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            entries: vec![],
+        }
+    }
+
+    fn entry_mut(
+        &mut self,
+        tx_hash: WrappedHashOut<F>,
+    ) -> anyhow::Result<&mut MempoolEntry<GoldilocksField, C, D>> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.user_tx_proof.public_inputs.tx_hash == tx_hash)
+            .ok_or_else(|| anyhow::anyhow!("the transaction {} is not in the mempool", tx_hash))
+    }
+
+    /// Adds the transaction submitted while the block `block_number` is built.
+    pub fn submit(
+        &mut self,
+        user_tx_proof: MergeAndPurgeTransitionProofWithPublicInputs<GoldilocksField, C, D>,
+        block_number: u32,
+    ) -> anyhow::Result<()> {
+        let tx_hash = user_tx_proof.public_inputs.tx_hash;
+        anyhow::ensure!(
+            self.status(tx_hash).is_none(),
+            "the transaction {} is already submitted",
+            tx_hash
+        );
+        self.entries.push(MempoolEntry {
+            user_tx_proof,
+            submitted_block_number: block_number,
+            status: TransactionStatus::Pending { exclusions: 0 },
+            exclusions: 0,
+            reserved_fee: 0,
+        });
+
+        Ok(())
+    }
+
+    pub fn status(&self, tx_hash: WrappedHashOut<F>) -> Option<TransactionStatus> {
+        self.entries
+            .iter()
+            .find(|entry| entry.user_tx_proof.public_inputs.tx_hash == tx_hash)
+            .map(|entry| entry.status)
+    }
+
+    /// The transactions waiting for admission, the oldest first.
+    pub fn pending(&self) -> impl Iterator<Item = &MempoolEntry<GoldilocksField, C, D>> {
+        self.entries
+            .iter()
+            .filter(|entry| matches!(entry.status, TransactionStatus::Pending { .. }))
+    }
+
+    /// Records that the transaction is admitted to the block `block_number` with `fee` reserved.
+    pub fn mark_admitted(
+        &mut self,
+        tx_hash: WrappedHashOut<F>,
+        block_number: u32,
+        fee: u64,
+    ) -> anyhow::Result<()> {
+        let entry = self.entry_mut(tx_hash)?;
+        anyhow::ensure!(
+            matches!(entry.status, TransactionStatus::Pending { .. }),
+            "the transaction {} is not pending: {:?}",
+            tx_hash,
+            entry.status
+        );
+        entry.status = TransactionStatus::Admitted { block_number };
+        entry.reserved_fee = fee;
+
+        Ok(())
+    }
+
+    /// Returns an admitted transaction to the pending ones without counting an exclusion,
+    /// e.g. after `IncrementalBlockBuilder::cancel_transaction`.
+    pub fn unmark_admitted(&mut self, tx_hash: WrappedHashOut<F>) -> anyhow::Result<Refund> {
+        let entry = self.entry_mut(tx_hash)?;
+        let block_number = match entry.status {
+            TransactionStatus::Admitted { block_number } => block_number,
+            status => anyhow::bail!("the transaction {} is not admitted: {:?}", tx_hash, status),
+        };
+        entry.status = TransactionStatus::Pending {
+            exclusions: entry.exclusions,
+        };
+
+        Ok(take_refund(entry, block_number))
+    }
+
+    /// Confirms the transactions of the senders marked valid in `address_list` of the block `block_number`
+    /// and returns the refunds of the excluded ones.
+    /// The senders without a transaction admitted to the block are ignored.
+    pub fn settle_block(
+        &mut self,
+        block_number: u32,
+        address_list: &[TransactionSenderWithValidity<F>],
+    ) -> Vec<Refund> {
+        let policy = self.policy;
+        let mut refunds = vec![];
+        for entry in self.entries.iter_mut() {
+            if entry.status != (TransactionStatus::Admitted { block_number }) {
+                continue;
+            }
+
+            let public_inputs = &entry.user_tx_proof.public_inputs;
+            let is_valid = address_list.iter().any(|sender| {
+                sender.sender_address == public_inputs.sender_address && sender.is_valid
+            });
+            if is_valid {
+                entry.status = TransactionStatus::Confirmed { block_number };
+                entry.reserved_fee = 0;
+                continue;
+            }
+
+            entry.exclusions += 1;
+            let exclusions = entry.exclusions;
+            entry.status =
+                if public_inputs.old_user_asset_root != public_inputs.middle_user_asset_root {
+                    TransactionStatus::Expired {
+                        reason: ExpiryReason::MustBeRebuilt,
+                    }
+                } else if exclusions >= policy.max_exclusions {
+                    TransactionStatus::Expired {
+                        reason: ExpiryReason::TooManyExclusions,
+                    }
+                } else {
+                    TransactionStatus::Pending { exclusions }
+                };
+            refunds.push(take_refund(entry, block_number));
+        }
+
+        refunds
+    }
+
+    /// Expires the pending transactions submitted `RetryPolicy::ttl_blocks` or more blocks before `block_number`.
+    pub fn expire_stale(&mut self, block_number: u32) -> Vec<WrappedHashOut<F>> {
+        let ttl_blocks = self.policy.ttl_blocks;
+        let mut expired = vec![];
+        for entry in self.entries.iter_mut() {
+            if matches!(entry.status, TransactionStatus::Pending { .. })
+                && block_number.saturating_sub(entry.submitted_block_number) >= ttl_blocks
+            {
+                entry.status = TransactionStatus::Expired {
+                    reason: ExpiryReason::Stale,
+                };
+                expired.push(entry.user_tx_proof.public_inputs.tx_hash);
+            }
+        }
+
+        expired
+    }
+
+    /// Removes the confirmed and expired transactions and returns how many were removed.
+    pub fn prune(&mut self) -> usize {
+        let n_entries = self.entries.len();
+        self.entries.retain(|entry| {
+            matches!(
+                entry.status,
+                TransactionStatus::Pending { .. } | TransactionStatus::Admitted { .. }
+            )
+        });
+
+        n_entries - self.entries.len()
+    }
+}
+
+fn take_refund<C: GenericConfig<D, F = GoldilocksField>, const D: usize>(
+    entry: &mut MempoolEntry<GoldilocksField, C, D>,
+    block_number: u32,
+) -> Refund
+where
+    GoldilocksField: Extendable<D>,
+{
+    let amount = std::mem::take(&mut entry.reserved_fee);
+
+    Refund {
+        tx_hash: entry.user_tx_proof.public_inputs.tx_hash,
+        sender: entry.user_tx_proof.public_inputs.sender_address,
+        block_number,
+        amount,
+    }
+}
+
+#[test]
+fn test_mempool() {
+    use crate::fixtures::{make_sample_accounts, make_sample_user_tx, make_sample_user_tx_circuit};
+
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    let tx_hashes = user_txs
+        .proofs
+        .iter()
+        .map(|proof| proof.public_inputs.tx_hash)
+        .collect::<Vec<_>>();
+
+    let mut mempool = Mempool::new(RetryPolicy {
+        max_exclusions: 2,
+        ttl_blocks: 4,
+    });
+    for proof in user_txs.proofs[0..2].iter() {
+        mempool.submit(proof.clone(), 1).unwrap();
+    }
+    assert!(mempool.submit(user_txs.proofs[0].clone(), 1).is_err());
+    assert_eq!(mempool.pending().count(), 2);
+
+    // Neither sender signs the block 1.
+    for tx_hash in tx_hashes.iter() {
+        mempool.mark_admitted(*tx_hash, 1, 10).unwrap();
+    }
+    assert!(mempool.mark_admitted(tx_hashes[0], 1, 10).is_err());
+    let address_list = accounts
+        .iter()
+        .map(|account| TransactionSenderWithValidity {
+            sender_address: account.address,
+            is_valid: false,
+        })
+        .collect::<Vec<_>>();
+    let refunds = mempool.settle_block(1, &address_list);
+    assert_eq!(refunds.len(), 2);
+    assert!(refunds.iter().all(|refund| refund.amount == 10));
+    assert_eq!(
+        mempool.status(tx_hashes[0]),
+        Some(TransactionStatus::Pending { exclusions: 1 })
+    );
+    // The second transaction merges a deposit.
+    assert_eq!(
+        mempool.status(tx_hashes[1]),
+        Some(TransactionStatus::Expired {
+            reason: ExpiryReason::MustBeRebuilt
+        })
+    );
+
+    // A cancelled transaction is refunded without counting an exclusion.
+    mempool.mark_admitted(tx_hashes[0], 2, 10).unwrap();
+    assert_eq!(mempool.unmark_admitted(tx_hashes[0]).unwrap().amount, 10);
+    assert_eq!(
+        mempool.status(tx_hashes[0]),
+        Some(TransactionStatus::Pending { exclusions: 1 })
+    );
+
+    mempool.mark_admitted(tx_hashes[0], 2, 10).unwrap();
+    let mut address_list = address_list;
+    address_list[0].is_valid = true;
+    assert!(mempool.settle_block(2, &address_list).is_empty());
+    assert_eq!(
+        mempool.status(tx_hashes[0]),
+        Some(TransactionStatus::Confirmed { block_number: 2 })
+    );
+    assert_eq!(mempool.prune(), 2);
+    assert_eq!(mempool.status(tx_hashes[0]), None);
+
+    // A transaction which is not admitted in time expires.
+    mempool.submit(user_txs.proofs[0].clone(), 3).unwrap();
+    assert!(mempool.expire_stale(6).is_empty());
+    assert_eq!(mempool.expire_stale(7), vec![tx_hashes[0]]);
+    assert_eq!(
+        mempool.status(tx_hashes[0]),
+        Some(TransactionStatus::Expired {
+            reason: ExpiryReason::Stale
+        })
+    );
+}
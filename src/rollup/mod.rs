@@ -1,5 +1,22 @@
 pub mod address_list;
+pub mod balance_history;
 pub mod block;
+pub mod block_builder;
+pub mod block_diff;
+pub mod checkpoint;
 pub mod circuits;
+pub mod contract;
+pub mod cross_rollup;
 pub mod deposit;
+pub mod distributed;
+pub mod double_spend;
+pub mod forced_inclusion;
 pub mod gadgets;
+pub mod indexer;
+pub mod mempool;
+pub mod pruned_state_manager;
+pub mod sharded_world_state;
+pub mod snapshot;
+pub mod state_manager;
+pub mod watchtower;
+pub mod withdrawal;
@@ -0,0 +1,260 @@
+//! The verification-only mode of the state manager.
+//!
+//! `PrunedStateManager` keeps no node data of the trees. It keeps the verified block headers,
+//! the hashes of the blocks (the leaves of the block header tree), and the proofs of the blocks,
+//! and accepts each new block by verifying its proof and checking that the block extends the
+//! latest one. It can then tell whether a block header is canonical, like a light client.
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    hash::hash_types::HashOut,
+    plonk::config::GenericConfig,
+};
+
+use crate::{
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::block_header::{get_block_hash, get_block_header_tree_proof, BlockHeader},
+    verification::{public_inputs::BlockPublicInputs, verifier::BlockVerifier},
+};
+
+type F = GoldilocksField;
+
+#[derive(Clone, Debug)]
+pub struct VerifiedBlock {
+    pub header: BlockHeader<F>,
+    pub public_inputs: BlockPublicInputs<F>,
+
+    /// The block proof serialized by `ProofWithPublicInputs::to_bytes`.
+    pub proof: Vec<u8>,
+}
+
+pub struct PrunedStateManager<C: GenericConfig<D, F = F>, const D: usize>
+where
+    F: Extendable<D>,
+{
+    pub verifier: BlockVerifier<F, C, D>,
+
+    /// The depth of the block header tree, i.e. `N_LOG_MAX_BLOCKS` of the block circuit.
+    pub block_header_tree_depth: usize,
+
+    genesis_header: BlockHeader<F>,
+
+    /// `block_hashes[i]` is the hash of the block `i`.
+    block_hashes: Vec<WrappedHashOut<F>>,
+
+    /// The blocks after the genesis block in order.
+    blocks: Vec<VerifiedBlock>,
+}
+
+impl<C: GenericConfig<D, F = F>, const D: usize> PrunedStateManager<C, D>
+where
+    F: Extendable<D>,
+{
+    /// Starts from the trusted header of the block 0.
+    pub fn new(
+        verifier: BlockVerifier<F, C, D>,
+        block_header_tree_depth: usize,
+        genesis_header: BlockHeader<F>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            genesis_header.block_number == 0,
+            "the genesis block must be the block 0, but got {}",
+            genesis_header.block_number
+        );
+        let block_hashes = vec![get_block_hash(&genesis_header).into()];
+
+        Ok(Self {
+            verifier,
+            block_header_tree_depth,
+            genesis_header,
+            block_hashes,
+            blocks: vec![],
+        })
+    }
+
+    pub fn latest_header(&self) -> &BlockHeader<F> {
+        self.blocks
+            .last()
+            .map(|block| &block.header)
+            .unwrap_or(&self.genesis_header)
+    }
+
+    pub fn latest_block_number(&self) -> u32 {
+        self.latest_header().block_number
+    }
+
+    pub fn get_block(&self, block_number: u32) -> Option<&VerifiedBlock> {
+        self.blocks.get((block_number as usize).checked_sub(1)?)
+    }
+
+    /// Whether `header` is the verified block of its block number.
+    pub fn is_canonical(&self, header: &BlockHeader<F>) -> bool {
+        self.block_hashes
+            .get(header.block_number as usize)
+            .is_some_and(|block_hash| **block_hash == get_block_hash(header))
+    }
+
+    /// Verifies `proof` of the next block `header` and appends the block.
+    pub fn verify_block(&mut self, header: BlockHeader<F>, proof: Vec<u8>) -> anyhow::Result<()> {
+        let public_inputs = self.verifier.verify(&proof)?;
+
+        self.append_block(header, public_inputs, proof)
+    }
+
+    /// Appends the block whose proof has the verified `public_inputs`.
+    fn append_block(
+        &mut self,
+        header: BlockHeader<F>,
+        public_inputs: BlockPublicInputs<F>,
+        proof: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let latest_header = self.latest_header();
+        anyhow::ensure!(
+            header.block_number == latest_header.block_number + 1,
+            "the block {} does not follow the block {}",
+            header.block_number,
+            latest_header.block_number
+        );
+        anyhow::ensure!(
+            public_inputs.block_hash == get_block_hash(&header),
+            "the proof is for another block header"
+        );
+
+        // The block header tree has the hashes of the blocks before the block,
+        // the last of which is the latest block.
+        let (_, old_root, new_root) = get_block_header_tree_proof(
+            &self.block_hashes[..self.block_hashes.len() - 1],
+            *self.block_hashes.last().unwrap(),
+            self.block_header_tree_depth,
+        );
+        anyhow::ensure!(
+            *old_root == public_inputs.old_prev_block_header_digest
+                && *new_root == public_inputs.new_prev_block_header_digest
+                && *new_root == header.prev_block_header_digest,
+            "the block does not follow the latest block"
+        );
+        anyhow::ensure!(
+            public_inputs.old_world_state_root == latest_header.approved_world_state_digest,
+            "the block starts from another world state"
+        );
+        anyhow::ensure!(
+            public_inputs.old_account_tree_root == latest_header.latest_account_digest,
+            "the block starts from another latest account tree"
+        );
+        anyhow::ensure!(
+            public_inputs.old_forced_transactions_digest
+                == latest_header.forced_transactions_digest,
+            "the block starts from another forced transaction queue"
+        );
+
+        self.block_hashes.push(public_inputs.block_hash.into());
+        self.blocks.push(VerifiedBlock {
+            header,
+            public_inputs,
+            proof,
+        });
+
+        Ok(())
+    }
+
+    /// The world state root after the latest block.
+    pub fn world_state_root(&self) -> HashOut<F> {
+        self.latest_header().approved_world_state_digest
+    }
+}
+
+#[test]
+fn test_pruned_state_manager() {
+    use plonky2::{
+        field::types::Sample,
+        plonk::{
+            circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+            config::PoseidonGoldilocksConfig,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    const N_LOG_MAX_BLOCKS: usize = 3;
+
+    // Only the public inputs are checked without the proof.
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let public_inputs =
+        builder.add_virtual_targets(BlockPublicInputs::<F>::public_inputs_len(0, 0));
+    builder.register_public_inputs(&public_inputs);
+    let data = builder.build::<C>();
+    let verifier = BlockVerifier {
+        verifier_only: data.verifier_only,
+        common: data.common,
+        n_txs: 0,
+        n_deposits: 0,
+    };
+
+    let genesis_header = BlockHeader::with_tree_depth(N_LOG_MAX_BLOCKS);
+    let mut state_manager =
+        PrunedStateManager::new(verifier, N_LOG_MAX_BLOCKS, genesis_header.clone()).unwrap();
+    let block_hashes = [WrappedHashOut::from(get_block_hash(&genesis_header))];
+    let (_, old_root, new_root) =
+        get_block_header_tree_proof(&[], block_hashes[0], N_LOG_MAX_BLOCKS);
+    let header = BlockHeader {
+        block_number: 1,
+        prev_block_header_digest: *new_root,
+        approved_world_state_digest: HashOut::rand(),
+        latest_account_digest: HashOut::rand(),
+        ..genesis_header.clone()
+    };
+    let public_inputs = BlockPublicInputs {
+        address_list: vec![],
+        deposit_list: vec![],
+        old_account_tree_root: genesis_header.latest_account_digest,
+        new_account_tree_root: header.latest_account_digest,
+        old_world_state_root: genesis_header.approved_world_state_digest,
+        new_world_state_root: header.proposed_world_state_digest,
+        old_prev_block_header_digest: *old_root,
+        new_prev_block_header_digest: *new_root,
+        block_hash: get_block_hash(&header),
+        proposed_world_state_digest: header.proposed_world_state_digest,
+        approved_world_state_digest: header.approved_world_state_digest,
+        latest_account_digest: header.latest_account_digest,
+        tx_hash_list_digest: HashOut::ZERO,
+        old_forced_transactions_digest: genesis_header.forced_transactions_digest,
+        new_forced_transactions_digest: header.forced_transactions_digest,
+        burned_assets_digest: HashOut::ZERO,
+        withdrawal_root: HashOut::ZERO,
+    };
+
+    // The block must start from the world state of the latest block.
+    let other_public_inputs = BlockPublicInputs {
+        old_world_state_root: HashOut::rand(),
+        ..public_inputs.clone()
+    };
+    assert!(state_manager
+        .append_block(header.clone(), other_public_inputs, vec![])
+        .is_err());
+
+    state_manager
+        .append_block(header.clone(), public_inputs.clone(), vec![])
+        .unwrap();
+    assert_eq!(state_manager.latest_block_number(), 1);
+    assert_eq!(
+        state_manager.world_state_root(),
+        header.approved_world_state_digest
+    );
+    assert!(state_manager.is_canonical(&genesis_header));
+    assert!(state_manager.is_canonical(&header));
+    assert!(!state_manager.is_canonical(&BlockHeader {
+        latest_account_digest: HashOut::rand(),
+        ..header.clone()
+    }));
+    assert!(!state_manager.is_canonical(&BlockHeader {
+        block_number: 2,
+        ..header.clone()
+    }));
+    assert_eq!(state_manager.get_block(1).unwrap().header, header);
+    assert!(state_manager.get_block(0).is_none());
+
+    // The same block cannot be appended again.
+    assert!(state_manager
+        .append_block(header, public_inputs, vec![])
+        .is_err());
+}
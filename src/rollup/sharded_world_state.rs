@@ -0,0 +1,187 @@
+//! The world state split into `2^n_log_shards` independent subtrees.
+//!
+//! An address belongs to the shard given by the first `n_log_shards` bits of its path,
+//! and the world state root is the Merkle root of the shard roots.
+//! The block builders prove the updates of each shard with `ShardUpdateTarget` in parallel
+//! and combine the shard roots with `ShardedWorldStateRootTarget`.
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::PrimeField64},
+    hash::hash_types::RichField,
+};
+
+use crate::{
+    merkle_tree::tree::get_merkle_proof,
+    sparse_merkle_tree::{
+        gadgets::{process::process_smt::SmtProcessProof, verify::verify_smt::SmtInclusionProof},
+        goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree, WrappedHashOut},
+    },
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+pub fn get_shard_index<F: RichField>(address: Address<F>, n_log_shards: usize) -> usize {
+    (address.0.elements[0].to_canonical_u64() & ((1 << n_log_shards) - 1)) as usize
+}
+
+/// The native counterpart of `ShardedWorldStateRootTarget`.
+pub fn get_sharded_world_state_root<F: RichField>(
+    shard_roots: &[WrappedHashOut<F>],
+    n_log_shards: usize,
+) -> WrappedHashOut<F> {
+    assert_eq!(shard_roots.len(), 1 << n_log_shards);
+
+    get_merkle_proof(shard_roots, 0, n_log_shards).root
+}
+
+#[derive(Debug)]
+pub struct ShardedWorldStateTree {
+    pub n_log_shards: usize,
+    pub shards: Vec<PoseidonSparseMerkleTree<NodeDataMemory>>,
+}
+
+impl ShardedWorldStateTree {
+    pub fn new(n_log_shards: usize) -> Self {
+        let shards = (0..(1 << n_log_shards))
+            .map(|_| PoseidonSparseMerkleTree::default())
+            .collect();
+
+        Self {
+            n_log_shards,
+            shards,
+        }
+    }
+
+    pub fn shard_roots(&self) -> Vec<WrappedHashOut<F>> {
+        self.shards.iter().map(|shard| shard.get_root()).collect()
+    }
+
+    pub fn get_root(&self) -> WrappedHashOut<F> {
+        get_sharded_world_state_root(&self.shard_roots(), self.n_log_shards)
+    }
+
+    pub fn find(&self, address: Address<F>) -> anyhow::Result<SmtInclusionProof<F>> {
+        let shard_index = get_shard_index(address, self.n_log_shards);
+
+        self.shards[shard_index].find(&address.0.into())
+    }
+
+    /// Returns the shard index and the process proof in the shard.
+    pub fn set(
+        &mut self,
+        address: Address<F>,
+        user_asset_root: WrappedHashOut<F>,
+    ) -> anyhow::Result<(usize, SmtProcessProof<F>)> {
+        let shard_index = get_shard_index(address, self.n_log_shards);
+        let proof = self.shards[shard_index].set(address.0.into(), user_asset_root)?;
+
+        Ok((shard_index, proof))
+    }
+
+    /// Applies `updates` and returns the process proofs grouped by shard, in the order of `updates`.
+    pub fn set_batch(
+        &mut self,
+        updates: &[(Address<F>, WrappedHashOut<F>)],
+    ) -> anyhow::Result<Vec<Vec<SmtProcessProof<F>>>> {
+        let mut proofs = vec![vec![]; self.shards.len()];
+        for (address, user_asset_root) in updates {
+            let (shard_index, proof) = self.set(*address, *user_asset_root)?;
+            proofs[shard_index].push(proof);
+        }
+
+        Ok(proofs)
+    }
+}
+
+#[test]
+fn test_sharded_world_state() {
+    use plonky2::{
+        field::types::Field,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder,
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::{
+        rollup::gadgets::sharded_world_state::{ShardUpdateTarget, ShardedWorldStateRootTarget},
+        sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    const N_LOG_SHARDS: usize = 1;
+    const N_LOG_MAX_USERS: usize = 4;
+    const N_UPDATES: usize = 2;
+
+    let mut tree = ShardedWorldStateTree::new(N_LOG_SHARDS);
+    let old_shard_roots = tree.shard_roots();
+    let updates = [1, 2, 3].map(|i| {
+        (
+            Address(*GoldilocksHashOut::from_u32(i)),
+            GoldilocksHashOut::from_u32(100 + i),
+        )
+    });
+    let proofs = tree.set_batch(&updates).unwrap();
+    assert_eq!(proofs[0].len(), 1);
+    assert_eq!(proofs[1].len(), 2);
+    assert_eq!(
+        tree.find(updates[2].0).unwrap().value,
+        GoldilocksHashOut::from_u32(103)
+    );
+    let new_shard_roots = tree.shard_roots();
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let shard_targets =
+        (0..(1 << N_LOG_SHARDS))
+            .map(|_| {
+                ShardUpdateTarget::<N_LOG_SHARDS, N_LOG_MAX_USERS, N_UPDATES>::add_virtual_to::<
+                    F,
+                    H,
+                    D,
+                >(&mut builder)
+            })
+            .collect::<Vec<_>>();
+    let root_target =
+        ShardedWorldStateRootTarget::<N_LOG_SHARDS>::add_virtual_to::<F, H, D>(&mut builder);
+    for (i, shard_target) in shard_targets.iter().enumerate() {
+        let shard_index = builder.constant(F::from_canonical_usize(i));
+        builder.connect(shard_target.shard_index, shard_index);
+        builder.connect_hashes(shard_target.old_shard_root, root_target.old_shard_roots[i]);
+        builder.connect_hashes(shard_target.new_shard_root, root_target.new_shard_roots[i]);
+    }
+    builder.register_public_inputs(&root_target.old_world_state_root.elements);
+    builder.register_public_inputs(&root_target.new_world_state_root.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    for (i, shard_target) in shard_targets.iter().enumerate() {
+        shard_target
+            .set_witness(&mut pw, i, &proofs[i], *old_shard_roots[i])
+            .unwrap();
+    }
+    root_target
+        .set_witness(
+            &mut pw,
+            &old_shard_roots.iter().map(|v| **v).collect::<Vec<_>>(),
+            &new_shard_roots.iter().map(|v| **v).collect::<Vec<_>>(),
+        )
+        .unwrap();
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(proof.public_inputs[4..8], tree.get_root().elements);
+    data.verify(proof).unwrap();
+
+    // The proofs of the shard 1 cannot be used for the shard 0.
+    assert!(shard_targets[0]
+        .set_witness(
+            &mut PartialWitness::new(),
+            0,
+            &proofs[1],
+            *old_shard_roots[1]
+        )
+        .is_err());
+}
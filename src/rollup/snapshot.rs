@@ -0,0 +1,235 @@
+//! State sync from a snapshot of a tree at a block.
+//!
+//! A new aggregator bootstraps from the leaves of the world state tree (or the latest account tree)
+//! instead of replaying every block. `export_snapshot` splits the leaves into chunks,
+//! each leaf with its inclusion proof against the root committed by the block header,
+//! so that `SnapshotImporter` can check every chunk on arrival, in any order and from any peer,
+//! while rebuilding the node data. The rebuilt root must be the committed root at the end,
+//! which shows that no leaf is missing.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use plonky2::{field::goldilocks_field::GoldilocksField, hash::hash_types::HashOut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::verify::verify_smt::SmtInclusionProof,
+        goldilocks_poseidon::{GoldilocksHashOut, PoseidonSparseMerkleTree},
+        node_data::NodeData,
+    },
+    verification::merkle::verify_smt_inclusion_proof,
+};
+
+type F = GoldilocksField;
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+pub const DEFAULT_SNAPSHOT_CHUNK_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub block_number: u32,
+    pub root: GoldilocksHashOut,
+    pub num_leaves: usize,
+    pub num_chunks: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub index: usize,
+
+    /// The inclusion proofs of the leaves against the root of the snapshot.
+    pub leaves: Vec<SmtInclusionProof<F>>,
+}
+
+/// Splits the leaves of `tree` after the block `block_number` into chunks of `chunk_size` leaves.
+pub fn export_snapshot<D: NodeData<K, V, I>>(
+    tree: &PoseidonSparseMerkleTree<D>,
+    block_number: u32,
+    chunk_size: usize,
+) -> anyhow::Result<(SnapshotManifest, Vec<SnapshotChunk>)> {
+    anyhow::ensure!(chunk_size != 0, "the chunk size must be positive");
+    let leaves = tree.leaves()?;
+    let chunks = leaves
+        .chunks(chunk_size)
+        .enumerate()
+        .map(|(index, leaves)| {
+            let leaves = leaves
+                .iter()
+                .map(|(key, _)| tree.find(key))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            Ok(SnapshotChunk { index, leaves })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let manifest = SnapshotManifest {
+        block_number,
+        root: tree.get_root(),
+        num_leaves: leaves.len(),
+        num_chunks: chunks.len(),
+    };
+
+    Ok((manifest, chunks))
+}
+
+/// Rebuilds a tree from the chunks of a snapshot.
+pub struct SnapshotImporter<D: NodeData<K, V, I>> {
+    pub manifest: SnapshotManifest,
+    tree: PoseidonSparseMerkleTree<D>,
+    imported_chunks: HashSet<usize>,
+    num_imported_leaves: usize,
+}
+
+impl<D: NodeData<K, V, I>> SnapshotImporter<D> {
+    /// `committed_root` is the root in the block header of the snapshot,
+    /// e.g. `approved_world_state_digest` for the world state tree.
+    /// The tree is rebuilt in `nodes_db`, which should be empty.
+    pub fn new(
+        manifest: SnapshotManifest,
+        committed_root: HashOut<F>,
+        nodes_db: Arc<Mutex<D>>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            *manifest.root == committed_root,
+            "the snapshot of the block {} is not of the committed root",
+            manifest.block_number
+        );
+
+        Ok(Self {
+            manifest,
+            tree: PoseidonSparseMerkleTree::new(nodes_db, Default::default()),
+            imported_chunks: HashSet::new(),
+            num_imported_leaves: 0,
+        })
+    }
+
+    /// Checks every leaf of `chunk` against the root before inserting any of them.
+    pub fn import_chunk(&mut self, chunk: &SnapshotChunk) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            chunk.index < self.manifest.num_chunks,
+            "the snapshot has no chunk {}",
+            chunk.index
+        );
+        anyhow::ensure!(
+            !self.imported_chunks.contains(&chunk.index),
+            "the chunk {} is already imported",
+            chunk.index
+        );
+        for proof in chunk.leaves.iter() {
+            anyhow::ensure!(proof.found, "the leaf {} is not found", proof.key);
+            anyhow::ensure!(
+                proof.root == self.manifest.root,
+                "the leaf {} is in another tree",
+                proof.key
+            );
+            let siblings = proof.siblings.iter().map(|v| **v).collect::<Vec<_>>();
+            verify_smt_inclusion_proof(
+                *proof.root,
+                *proof.key,
+                *proof.value,
+                proof.found,
+                *proof.not_found_key,
+                *proof.not_found_value,
+                proof.is_old0,
+                &siblings,
+            )?;
+        }
+
+        for proof in chunk.leaves.iter() {
+            self.tree.set(proof.key, proof.value)?;
+        }
+        self.imported_chunks.insert(chunk.index);
+        self.num_imported_leaves += chunk.leaves.len();
+
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.imported_chunks.len() == self.manifest.num_chunks
+    }
+
+    /// Returns the rebuilt tree after all the chunks are imported.
+    pub fn finish(self) -> anyhow::Result<PoseidonSparseMerkleTree<D>> {
+        anyhow::ensure!(
+            self.is_complete(),
+            "{} of {} chunks are imported",
+            self.imported_chunks.len(),
+            self.manifest.num_chunks
+        );
+        anyhow::ensure!(
+            self.num_imported_leaves == self.manifest.num_leaves,
+            "the snapshot has {} leaves, but {} are imported",
+            self.manifest.num_leaves,
+            self.num_imported_leaves
+        );
+        anyhow::ensure!(
+            self.tree.get_root() == self.manifest.root,
+            "the rebuilt tree does not have the root of the snapshot"
+        );
+
+        Ok(self.tree)
+    }
+}
+
+#[test]
+fn test_snapshot_sync() {
+    use crate::sparse_merkle_tree::goldilocks_poseidon::NodeDataMemory;
+
+    let mut world_state_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    for _ in 0..10 {
+        world_state_tree
+            .set(GoldilocksHashOut::rand(), GoldilocksHashOut::rand())
+            .unwrap();
+    }
+    let committed_root = *world_state_tree.get_root();
+
+    let (manifest, chunks) = export_snapshot(&world_state_tree, 5, 3).unwrap();
+    assert_eq!(manifest.num_leaves, 10);
+    assert_eq!(chunks.len(), 4);
+    assert!(
+        SnapshotImporter::<NodeDataMemory>::new(manifest, HashOut::ZERO, Default::default())
+            .is_err()
+    );
+
+    let mut importer =
+        SnapshotImporter::<NodeDataMemory>::new(manifest, committed_root, Default::default())
+            .unwrap();
+    // The chunks can be imported in any order.
+    for chunk in chunks.iter().rev().skip(1) {
+        importer.import_chunk(chunk).unwrap();
+    }
+    assert!(importer.import_chunk(&chunks[1]).is_err());
+    assert!(!importer.is_complete());
+
+    // A leaf which is not in the snapshot is rejected.
+    let mut forged_chunk = chunks[0].clone();
+    forged_chunk.leaves[0].value = GoldilocksHashOut::rand();
+    assert!(importer.import_chunk(&forged_chunk).is_err());
+
+    importer.import_chunk(&chunks[0]).unwrap();
+    let tree = importer.finish().unwrap();
+    assert_eq!(*tree.get_root(), committed_root);
+    for (key, value) in world_state_tree.leaves().unwrap() {
+        assert_eq!(tree.get(&key).unwrap(), value);
+    }
+
+    // An incomplete snapshot cannot be finished.
+    let mut importer =
+        SnapshotImporter::<NodeDataMemory>::new(manifest, committed_root, Default::default())
+            .unwrap();
+    importer.import_chunk(&chunks[0]).unwrap();
+    assert!(importer.finish().is_err());
+
+    let empty_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let (manifest, chunks) = export_snapshot(&empty_tree, 0, 3).unwrap();
+    assert!(chunks.is_empty());
+    let importer =
+        SnapshotImporter::<NodeDataMemory>::new(manifest, HashOut::ZERO, Default::default())
+            .unwrap();
+    assert_eq!(importer.finish().unwrap().get_root(), empty_tree.get_root());
+}
@@ -0,0 +1,318 @@
+//! The rollup state kept by aggregators and indexers.
+//!
+//! `StateManager` applies blocks to the world state tree and the latest account tree,
+//! keeps their diffs for rollbacks, and notifies subscribers when a block touches their addresses,
+//! so that wallets and exchange backends do not have to scan every block.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use plonky2::field::goldilocks_field::GoldilocksField;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::{
+        block::BlockInfo,
+        block_diff::{BlockDiff, RollupStateTrees},
+        gadgets::deposit_block::DepositInfo,
+    },
+    sparse_merkle_tree::{
+        goldilocks_poseidon::{GoldilocksHashOut, WrappedHashOut},
+        node_data::NodeData,
+    },
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+/// A transfer to `recipient`, i.e. a leaf of the diff tree of the transaction `tx_hash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingTransfer {
+    pub recipient: Address<F>,
+    pub tx_hash: GoldilocksHashOut,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressActivity {
+    IncomingTransfer {
+        tx_hash: GoldilocksHashOut,
+    },
+    IncomingDeposit(DepositInfo<F>),
+    /// The user asset root in the world state tree is changed.
+    WorldStateUpdated {
+        old_user_asset_root: GoldilocksHashOut,
+        new_user_asset_root: GoldilocksHashOut,
+    },
+    /// The address sent a transaction in the block,
+    /// which is valid only if the sender signed the proposed world state root.
+    SignatureValidity {
+        is_valid: bool,
+    },
+    /// The block touching the address was rolled back.
+    Reverted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressActivityEvent {
+    pub block_number: u32,
+    pub address: Address<F>,
+    pub activity: AddressActivity,
+}
+
+pub type SubscriptionId = usize;
+
+struct Subscription {
+    id: SubscriptionId,
+    addresses: Vec<Address<F>>,
+    sender: Sender<AddressActivityEvent>,
+}
+
+pub struct StateManager<D: NodeData<GoldilocksHashOut, GoldilocksHashOut, GoldilocksHashOut>> {
+    pub trees: RollupStateTrees<D>,
+
+    /// The diffs of the applied blocks, the latest last.
+    pub block_diffs: Vec<BlockDiff>,
+
+    subscriptions: Vec<Subscription>,
+    next_subscription_id: SubscriptionId,
+}
+
+impl<D: NodeData<GoldilocksHashOut, GoldilocksHashOut, GoldilocksHashOut>> StateManager<D> {
+    pub fn new(trees: RollupStateTrees<D>) -> Self {
+        Self {
+            trees,
+            block_diffs: vec![],
+            subscriptions: vec![],
+            next_subscription_id: 0,
+        }
+    }
+
+    /// Returns a receiver of the activities of `addresses` in the blocks applied from now on.
+    /// Dropping the receiver cancels the subscription.
+    pub fn subscribe(
+        &mut self,
+        addresses: Vec<Address<F>>,
+    ) -> (SubscriptionId, Receiver<AddressActivityEvent>) {
+        let (sender, receiver) = channel();
+        let id = self.next_subscription_id;
+        self.next_subscription_id += 1;
+        self.subscriptions.push(Subscription {
+            id,
+            addresses,
+            sender,
+        });
+
+        (id, receiver)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscriptions
+            .retain(|subscription| subscription.id != id);
+    }
+
+    pub fn num_subscriptions(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    /// Applies `diff` of `block` and notifies the subscribers.
+    pub fn apply_block(
+        &mut self,
+        block: &BlockInfo<F>,
+        diff: BlockDiff,
+        incoming_transfers: &[IncomingTransfer],
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            diff.block_number == block.header.block_number,
+            "the diff of the block {} is given for the block {}",
+            diff.block_number,
+            block.header.block_number
+        );
+        diff.apply(&mut self.trees)?;
+
+        let block_number = diff.block_number;
+        let mut events = vec![];
+        for transfer in incoming_transfers {
+            events.push((
+                transfer.recipient,
+                AddressActivity::IncomingTransfer {
+                    tx_hash: transfer.tx_hash,
+                },
+            ));
+        }
+        for deposit in block.deposit_list.iter() {
+            events.push((
+                deposit.receiver_address,
+                AddressActivity::IncomingDeposit(*deposit),
+            ));
+        }
+        for change in diff.world_state.changes.iter() {
+            events.push((
+                Address(*change.key),
+                AddressActivity::WorldStateUpdated {
+                    old_user_asset_root: change.old_value,
+                    new_user_asset_root: change.new_value,
+                },
+            ));
+        }
+        for sender in block.address_list.iter() {
+            if sender.sender_address == Address::default() {
+                continue;
+            }
+
+            events.push((
+                sender.sender_address,
+                AddressActivity::SignatureValidity {
+                    is_valid: sender.is_valid,
+                },
+            ));
+        }
+        self.block_diffs.push(diff);
+
+        for (address, activity) in events {
+            self.notify(AddressActivityEvent {
+                block_number,
+                address,
+                activity,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Reverts the latest block and notifies the subscribers of the touched addresses.
+    pub fn rollback_block(&mut self) -> anyhow::Result<BlockDiff> {
+        let diff = self
+            .block_diffs
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("no block to roll back"))?;
+        if let Err(err) = diff.revert(&mut self.trees) {
+            self.block_diffs.push(diff);
+            return Err(err);
+        }
+
+        for address in diff.touched_addresses() {
+            self.notify(AddressActivityEvent {
+                block_number: diff.block_number,
+                address: Address(*address),
+                activity: AddressActivity::Reverted,
+            });
+        }
+
+        Ok(diff)
+    }
+
+    pub fn get_user_asset_root(&self, address: Address<F>) -> anyhow::Result<GoldilocksHashOut> {
+        self.trees
+            .world_state_tree
+            .get(&WrappedHashOut::from(address.0))
+    }
+
+    fn notify(&mut self, event: AddressActivityEvent) {
+        // The subscriptions whose receiver is dropped are removed.
+        self.subscriptions.retain(|subscription| {
+            !subscription.addresses.contains(&event.address)
+                || subscription.sender.send(event.clone()).is_ok()
+        });
+    }
+}
+
+#[test]
+fn test_address_activity_subscription() {
+    use plonky2::field::types::Field;
+
+    use crate::{
+        rollup::address_list::TransactionSenderWithValidity,
+        sparse_merkle_tree::goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree},
+    };
+
+    let mut state_manager = StateManager::new(RollupStateTrees::<NodeDataMemory> {
+        world_state_tree: PoseidonSparseMerkleTree::default(),
+        latest_account_tree: PoseidonSparseMerkleTree::default(),
+    });
+    let sender = Address(*GoldilocksHashOut::from_u32(1));
+    let recipient = Address(*GoldilocksHashOut::from_u32(2));
+    let other = Address(*GoldilocksHashOut::from_u32(3));
+    let (_, sender_events) = state_manager.subscribe(vec![sender]);
+    let (_, recipient_events) = state_manager.subscribe(vec![recipient]);
+    let (_, other_events) = state_manager.subscribe(vec![other]);
+
+    let mut block = BlockInfo::with_tree_depth(2);
+    block.header.block_number = 1;
+    block.address_list = vec![TransactionSenderWithValidity {
+        sender_address: sender,
+        is_valid: true,
+    }];
+    block.deposit_list = vec![DepositInfo {
+        receiver_address: recipient,
+        contract_address: Address(*GoldilocksHashOut::from_u32(4)),
+        variable_index: *GoldilocksHashOut::from_u32(0),
+        amount: F::from_canonical_u32(10),
+    }];
+    let tx_hash = GoldilocksHashOut::from_u32(5);
+    let new_user_asset_root = GoldilocksHashOut::from_u32(100);
+    let mut diff = BlockDiff::new(1, &state_manager.trees);
+    diff.set_user_asset_root(
+        &mut state_manager.trees,
+        sender.0.into(),
+        new_user_asset_root,
+    )
+    .unwrap();
+    // The diff is applied by the state manager.
+    diff.revert(&mut state_manager.trees).unwrap();
+
+    state_manager
+        .apply_block(&block, diff, &[IncomingTransfer { recipient, tx_hash }])
+        .unwrap();
+    assert_eq!(
+        state_manager.get_user_asset_root(sender).unwrap(),
+        new_user_asset_root
+    );
+    assert_eq!(
+        sender_events
+            .try_iter()
+            .map(|e| e.activity)
+            .collect::<Vec<_>>(),
+        vec![
+            AddressActivity::WorldStateUpdated {
+                old_user_asset_root: Default::default(),
+                new_user_asset_root,
+            },
+            AddressActivity::SignatureValidity { is_valid: true },
+        ]
+    );
+    let recipient_events = recipient_events.try_iter().collect::<Vec<_>>();
+    assert_eq!(recipient_events.len(), 2);
+    assert_eq!(
+        recipient_events[0].activity,
+        AddressActivity::IncomingTransfer { tx_hash }
+    );
+    assert_eq!(other_events.try_iter().count(), 0);
+
+    state_manager.rollback_block().unwrap();
+    assert_eq!(
+        sender_events
+            .try_iter()
+            .map(|e| e.activity)
+            .collect::<Vec<_>>(),
+        vec![AddressActivity::Reverted]
+    );
+    assert_eq!(
+        state_manager.get_user_asset_root(sender).unwrap(),
+        Default::default()
+    );
+    assert!(state_manager.rollback_block().is_err());
+
+    // The subscription of a dropped receiver is removed when it is notified.
+    drop(sender_events);
+    let mut diff = BlockDiff::new(1, &state_manager.trees);
+    diff.set_user_asset_root(
+        &mut state_manager.trees,
+        sender.0.into(),
+        new_user_asset_root,
+    )
+    .unwrap();
+    diff.revert(&mut state_manager.trees).unwrap();
+    state_manager.apply_block(&block, diff, &[]).unwrap();
+    assert_eq!(state_manager.num_subscriptions(), 2);
+    state_manager.unsubscribe(0);
+    assert_eq!(state_manager.num_subscriptions(), 2);
+}
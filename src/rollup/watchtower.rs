@@ -0,0 +1,591 @@
+//! Independent monitoring of the blocks an aggregator publishes.
+//!
+//! `Watchtower` keeps a replica of the world state tree and the latest account tree and checks
+//! each posted block against both its proof and its posted data: the proof must verify,
+//! its public inputs must commit to the posted header, address list, deposits and transactions,
+//! and applying the posted `BlockDiff` to the replica must give the roots of the header.
+//! The block must also be signed by the aggregator of its header, so that the alerts can be attributed.
+//! Every mismatch is reported as a `WatchtowerAlert`. A block with alerts is not applied.
+
+use std::sync::{Arc, Mutex};
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField},
+    plonk::config::GenericConfig,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rollup::{
+        block::BlockInfo,
+        block_diff::{BlockDiff, LeafChange},
+    },
+    sparse_merkle_tree::{
+        goldilocks_poseidon::{GoldilocksHashOut, PoseidonSparseMerkleTree, WrappedHashOut},
+        node_data::NodeData,
+    },
+    transaction::block_header::{get_block_hash, get_block_header_tree_proof, BlockHeader},
+    verification::{public_inputs::BlockPublicInputs, verifier::BlockVerifier},
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+type K = GoldilocksHashOut;
+type V = GoldilocksHashOut;
+type I = GoldilocksHashOut;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertKind {
+    /// The block proof does not verify.
+    InvalidProof {
+        reason: String,
+    },
+
+    /// The block does not follow the latest checked block.
+    UnexpectedBlockNumber {
+        expected: u32,
+        actual: u32,
+    },
+
+    /// The proof is for another block header.
+    BlockHashMismatch,
+
+    /// The header does not extend the block header tree of the previous blocks.
+    BlockHeaderTreeMismatch,
+
+    /// The posted address list differs from the proof at `slot`,
+    /// or in length if `slot` is the shorter length.
+    AddressListMismatch {
+        slot: usize,
+    },
+
+    DepositListMismatch,
+
+    /// The posted transactions are not the `tx_hash` list of the proof.
+    TxHashListMismatch,
+
+    /// The posted diff changes `key` from a value it does not have in the replica.
+    StaleLeafChange {
+        key: GoldilocksHashOut,
+    },
+
+    /// The world state root is `expected` from the posted data, but the header or the proof has `actual`.
+    WorldStateMismatch {
+        expected: GoldilocksHashOut,
+        actual: GoldilocksHashOut,
+    },
+
+    /// The latest account root is `expected` from the posted data, but the header or the proof has `actual`.
+    LatestAccountMismatch {
+        expected: GoldilocksHashOut,
+        actual: GoldilocksHashOut,
+    },
+
+    /// The user asset root of a sender without the signature is changed.
+    UnapprovedWorldStateChange {
+        address: Address<F>,
+    },
+
+    /// The forced transaction queue of the proof does not continue from the latest block.
+    ForcedTransactionsMismatch,
+
+    /// The block is not signed by the aggregator.
+    MissingAggregatorSignature,
+
+    /// The aggregator signature is not of the header or not by its aggregator.
+    InvalidAggregatorSignature {
+        reason: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchtowerAlert {
+    pub block_number: u32,
+    pub kind: AlertKind,
+}
+
+pub struct Watchtower<Nd: NodeData<K, V, I>, C: GenericConfig<D, F = F>, const D: usize>
+where
+    F: Extendable<D>,
+{
+    pub verifier: BlockVerifier<F, C, D>,
+
+    /// The depth of the block header tree, i.e. `N_LOG_MAX_BLOCKS` of the block circuit.
+    pub block_header_tree_depth: usize,
+
+    /// The node data of the replica, which the applied diffs are written to.
+    nodes_db: Arc<Mutex<Nd>>,
+    world_state_root: GoldilocksHashOut,
+    latest_account_root: GoldilocksHashOut,
+    latest_header: BlockHeader<F>,
+
+    /// `block_hashes[i]` is the hash of the block `i`.
+    block_hashes: Vec<WrappedHashOut<F>>,
+}
+
+impl<Nd: NodeData<K, V, I>, C: GenericConfig<D, F = F>, const D: usize> Watchtower<Nd, C, D>
+where
+    F: Extendable<D>,
+{
+    /// Starts from the trusted `genesis_header` of the block 0,
+    /// whose trees are in `nodes_db`.
+    pub fn new(
+        verifier: BlockVerifier<F, C, D>,
+        block_header_tree_depth: usize,
+        genesis_header: BlockHeader<F>,
+        nodes_db: Arc<Mutex<Nd>>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            genesis_header.block_number == 0,
+            "the genesis block must be the block 0, but got {}",
+            genesis_header.block_number
+        );
+
+        Ok(Self {
+            verifier,
+            block_header_tree_depth,
+            nodes_db,
+            world_state_root: genesis_header.approved_world_state_digest.into(),
+            latest_account_root: genesis_header.latest_account_digest.into(),
+            block_hashes: vec![get_block_hash(&genesis_header).into()],
+            latest_header: genesis_header,
+        })
+    }
+
+    pub fn latest_header(&self) -> &BlockHeader<F> {
+        &self.latest_header
+    }
+
+    /// The world state tree of the replica after the latest block.
+    pub fn world_state_tree(&self) -> PoseidonSparseMerkleTree<Nd> {
+        PoseidonSparseMerkleTree::new(self.nodes_db.clone(), self.world_state_root)
+    }
+
+    pub fn latest_account_tree(&self) -> PoseidonSparseMerkleTree<Nd> {
+        PoseidonSparseMerkleTree::new(self.nodes_db.clone(), self.latest_account_root)
+    }
+
+    /// Checks the next posted `block` with its `diff` and serialized `proof`,
+    /// and applies it to the replica if there are no alerts.
+    /// An error means that the watchtower itself failed, e.g. to read the node data.
+    pub fn check_block(
+        &mut self,
+        block: &BlockInfo<F>,
+        diff: &BlockDiff,
+        proof: &[u8],
+    ) -> anyhow::Result<Vec<WatchtowerAlert>> {
+        let public_inputs = match self.verifier.verify(proof) {
+            Ok(public_inputs) => public_inputs,
+            Err(err) => {
+                return Ok(vec![WatchtowerAlert {
+                    block_number: block.header.block_number,
+                    kind: AlertKind::InvalidProof {
+                        reason: err.to_string(),
+                    },
+                }])
+            }
+        };
+
+        self.check_public_inputs(block, diff, &public_inputs)
+    }
+
+    /// Checks `block` against the verified `public_inputs` of its proof.
+    fn check_public_inputs(
+        &mut self,
+        block: &BlockInfo<F>,
+        diff: &BlockDiff,
+        public_inputs: &BlockPublicInputs<F>,
+    ) -> anyhow::Result<Vec<WatchtowerAlert>> {
+        let header = &block.header;
+        let mut alerts = vec![];
+
+        let expected_block_number = self.latest_header.block_number + 1;
+        if header.block_number != expected_block_number {
+            alerts.push(AlertKind::UnexpectedBlockNumber {
+                expected: expected_block_number,
+                actual: header.block_number,
+            });
+        } else if diff.block_number != expected_block_number {
+            alerts.push(AlertKind::UnexpectedBlockNumber {
+                expected: expected_block_number,
+                actual: diff.block_number,
+            });
+        }
+        if public_inputs.block_hash != get_block_hash(header) {
+            alerts.push(AlertKind::BlockHashMismatch);
+        }
+        match &block.aggregator_signature {
+            None => alerts.push(AlertKind::MissingAggregatorSignature),
+            Some(signature) => {
+                if let Err(err) = signature.verify(header) {
+                    alerts.push(AlertKind::InvalidAggregatorSignature {
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+
+        // The block header tree has the hashes of the blocks before the block,
+        // the last of which is the latest block.
+        let (_, old_root, new_root) = get_block_header_tree_proof(
+            &self.block_hashes[..self.block_hashes.len() - 1],
+            *self.block_hashes.last().unwrap(),
+            self.block_header_tree_depth,
+        );
+        if *old_root != public_inputs.old_prev_block_header_digest
+            || *new_root != public_inputs.new_prev_block_header_digest
+            || *new_root != header.prev_block_header_digest
+        {
+            alerts.push(AlertKind::BlockHeaderTreeMismatch);
+        }
+        if public_inputs.old_forced_transactions_digest
+            != self.latest_header.forced_transactions_digest
+            || public_inputs.new_forced_transactions_digest != header.forced_transactions_digest
+        {
+            alerts.push(AlertKind::ForcedTransactionsMismatch);
+        }
+
+        // The address list posted to L1 must be the one the proof commits to.
+        let n_senders = block
+            .address_list
+            .len()
+            .min(public_inputs.address_list.len());
+        let mismatched_slot = (0..n_senders)
+            .find(|&slot| {
+                let sender = &block.address_list[slot];
+                public_inputs.address_list[slot] != (sender.sender_address.0, sender.is_valid)
+            })
+            .or(
+                (block.address_list.len() != public_inputs.address_list.len()).then_some(n_senders),
+            );
+        if let Some(slot) = mismatched_slot {
+            alerts.push(AlertKind::AddressListMismatch { slot });
+        }
+
+        let is_same_deposit_list = block.deposit_list.len() == public_inputs.deposit_list.len()
+            && block
+                .deposit_list
+                .iter()
+                .zip(public_inputs.deposit_list.iter())
+                .all(|(deposit, expected)| {
+                    deposit.receiver_address.0 == expected.receiver_address
+                        && deposit.contract_address.0 == expected.contract_address
+                        && deposit.variable_index == expected.variable_index
+                        && deposit.amount == expected.amount
+                });
+        if !is_same_deposit_list {
+            alerts.push(AlertKind::DepositListMismatch);
+        }
+
+        let tx_hashes = block.transactions.iter().map(|v| **v).collect::<Vec<_>>();
+        if public_inputs.verify_tx_hash_list(&tx_hashes).is_err() {
+            alerts.push(AlertKind::TxHashListMismatch);
+        }
+
+        for change in diff.world_state.changes.iter() {
+            let is_unapproved = block
+                .address_list
+                .iter()
+                .any(|sender| sender.sender_address.0 == *change.key && !sender.is_valid);
+            if is_unapproved {
+                alerts.push(AlertKind::UnapprovedWorldStateChange {
+                    address: Address(*change.key),
+                });
+            }
+        }
+
+        // Recompute the roots from the posted changes.
+        let (new_world_state_root, stale_keys) =
+            self.recompute_root(self.world_state_root, &diff.world_state.changes)?;
+        alerts.extend(
+            stale_keys
+                .into_iter()
+                .map(|key| AlertKind::StaleLeafChange { key }),
+        );
+        for actual in [
+            WrappedHashOut::from(header.approved_world_state_digest),
+            public_inputs.approved_world_state_digest.into(),
+        ] {
+            if actual != new_world_state_root {
+                alerts.push(AlertKind::WorldStateMismatch {
+                    expected: new_world_state_root,
+                    actual,
+                });
+            }
+        }
+        if public_inputs.old_world_state_root != *self.world_state_root {
+            alerts.push(AlertKind::WorldStateMismatch {
+                expected: self.world_state_root,
+                actual: public_inputs.old_world_state_root.into(),
+            });
+        }
+
+        let (new_latest_account_root, stale_keys) =
+            self.recompute_root(self.latest_account_root, &diff.latest_account.changes)?;
+        alerts.extend(
+            stale_keys
+                .into_iter()
+                .map(|key| AlertKind::StaleLeafChange { key }),
+        );
+        for actual in [
+            WrappedHashOut::from(header.latest_account_digest),
+            public_inputs.new_account_tree_root.into(),
+        ] {
+            if actual != new_latest_account_root {
+                alerts.push(AlertKind::LatestAccountMismatch {
+                    expected: new_latest_account_root,
+                    actual,
+                });
+            }
+        }
+        if public_inputs.old_account_tree_root != *self.latest_account_root {
+            alerts.push(AlertKind::LatestAccountMismatch {
+                expected: self.latest_account_root,
+                actual: public_inputs.old_account_tree_root.into(),
+            });
+        }
+
+        if alerts.is_empty() {
+            self.world_state_root = new_world_state_root;
+            self.latest_account_root = new_latest_account_root;
+            self.block_hashes.push(get_block_hash(header).into());
+            self.latest_header = header.clone();
+        }
+
+        Ok(alerts
+            .into_iter()
+            .map(|kind| WatchtowerAlert {
+                block_number: header.block_number,
+                kind,
+            })
+            .collect())
+    }
+
+    /// Applies `changes` to the tree with `old_root` in the node data, without touching the replica roots.
+    /// Returns the new root and the keys whose old values do not match.
+    fn recompute_root(
+        &self,
+        old_root: GoldilocksHashOut,
+        changes: &[LeafChange],
+    ) -> anyhow::Result<(GoldilocksHashOut, Vec<GoldilocksHashOut>)> {
+        let mut tree = PoseidonSparseMerkleTree::new(self.nodes_db.clone(), old_root);
+        let mut stale_keys = vec![];
+        for change in changes {
+            if tree.get(&change.key)? != change.old_value {
+                stale_keys.push(change.key);
+            }
+            tree.set(change.key, change.new_value)?;
+        }
+
+        Ok((tree.get_root(), stale_keys))
+    }
+}
+
+#[test]
+fn test_watchtower() {
+    use plonky2::{
+        field::types::Sample,
+        hash::hash_types::HashOut,
+        plonk::{
+            circuit_builder::CircuitBuilder, circuit_data::CircuitConfig,
+            config::PoseidonGoldilocksConfig,
+        },
+    };
+
+    use plonky2::field::secp256k1_scalar::Secp256K1Scalar;
+    use plonky2_ecdsa::curve::ecdsa::ECDSASecretKey;
+
+    use crate::{
+        ecdsa::account::private_key_to_account,
+        rollup::{
+            address_list::TransactionSenderWithValidity, block::AggregatorSignature,
+            block_diff::RollupStateTrees,
+        },
+        sparse_merkle_tree::goldilocks_poseidon::NodeDataMemory,
+        verification::public_inputs::get_tx_hash_list_digest,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    const N_LOG_MAX_BLOCKS: usize = 3;
+
+    // Only the public inputs are checked without the proof.
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let targets = builder.add_virtual_targets(BlockPublicInputs::<F>::public_inputs_len(2, 0));
+    builder.register_public_inputs(&targets);
+    let data = builder.build::<C>();
+    let verifier = BlockVerifier {
+        verifier_only: data.verifier_only,
+        common: data.common,
+        n_txs: 2,
+        n_deposits: 0,
+    };
+
+    let nodes_db = Arc::new(Mutex::new(NodeDataMemory::default()));
+    let genesis_header = BlockHeader::with_tree_depth(N_LOG_MAX_BLOCKS);
+    let mut watchtower = Watchtower::<_, C, D>::new(
+        verifier,
+        N_LOG_MAX_BLOCKS,
+        genesis_header.clone(),
+        nodes_db.clone(),
+    )
+    .unwrap();
+
+    // The aggregator applies the transaction of `sender`, and `unapproved_sender` does not sign.
+    let sender = Address::rand();
+    let unapproved_sender = Address::rand();
+    let mut trees = RollupStateTrees {
+        world_state_tree: PoseidonSparseMerkleTree::new(nodes_db.clone(), Default::default()),
+        latest_account_tree: PoseidonSparseMerkleTree::new(nodes_db, Default::default()),
+    };
+    let mut diff = BlockDiff::new(1, &trees);
+    diff.set_user_asset_root(&mut trees, sender.0.into(), GoldilocksHashOut::rand())
+        .unwrap();
+    diff.set_latest_account(&mut trees, sender.0.into(), GoldilocksHashOut::from_u32(1))
+        .unwrap();
+
+    let block_hashes = [WrappedHashOut::from(get_block_hash(&genesis_header))];
+    let (_, old_root, new_root) =
+        get_block_header_tree_proof(&[], block_hashes[0], N_LOG_MAX_BLOCKS);
+    let aggregator = private_key_to_account(ECDSASecretKey(Secp256K1Scalar::rand())).unwrap();
+    let mut block = BlockInfo::<F>::with_tree_depth(N_LOG_MAX_BLOCKS);
+    block.header = BlockHeader {
+        block_number: 1,
+        prev_block_header_digest: *new_root,
+        proposed_world_state_digest: HashOut::rand(),
+        approved_world_state_digest: *trees.world_state_tree.get_root(),
+        latest_account_digest: *trees.latest_account_tree.get_root(),
+        timestamp: 1,
+        aggregator_address: aggregator.address,
+        ..genesis_header.clone()
+    };
+    block.transactions = vec![GoldilocksHashOut::rand(), GoldilocksHashOut::rand()];
+    block.address_list = vec![
+        TransactionSenderWithValidity {
+            sender_address: sender,
+            is_valid: true,
+        },
+        TransactionSenderWithValidity {
+            sender_address: unapproved_sender,
+            is_valid: false,
+        },
+    ];
+    block.aggregator_signature = Some(AggregatorSignature::sign(
+        aggregator.private_key,
+        &block.header,
+    ));
+    let public_inputs = BlockPublicInputs {
+        address_list: block
+            .address_list
+            .iter()
+            .map(|sender| (sender.sender_address.0, sender.is_valid))
+            .collect(),
+        deposit_list: vec![],
+        old_account_tree_root: genesis_header.latest_account_digest,
+        new_account_tree_root: block.header.latest_account_digest,
+        old_world_state_root: genesis_header.approved_world_state_digest,
+        new_world_state_root: block.header.proposed_world_state_digest,
+        old_prev_block_header_digest: *old_root,
+        new_prev_block_header_digest: *new_root,
+        block_hash: get_block_hash(&block.header),
+        proposed_world_state_digest: block.header.proposed_world_state_digest,
+        approved_world_state_digest: block.header.approved_world_state_digest,
+        latest_account_digest: block.header.latest_account_digest,
+        tx_hash_list_digest: get_tx_hash_list_digest(
+            &block.transactions.iter().map(|v| **v).collect::<Vec<_>>(),
+            2,
+        ),
+        old_forced_transactions_digest: genesis_header.forced_transactions_digest,
+        new_forced_transactions_digest: block.header.forced_transactions_digest,
+        burned_assets_digest: HashOut::ZERO,
+        withdrawal_root: HashOut::ZERO,
+    };
+    let kinds = |alerts: Vec<WatchtowerAlert>| {
+        alerts
+            .into_iter()
+            .map(|alert| alert.kind)
+            .collect::<Vec<_>>()
+    };
+
+    // The posted address list claims that the second sender signed.
+    let mut forged_block = block.clone();
+    forged_block.address_list[1].is_valid = true;
+    assert_eq!(
+        kinds(
+            watchtower
+                .check_public_inputs(&forged_block, &diff, &public_inputs)
+                .unwrap()
+        ),
+        vec![AlertKind::AddressListMismatch { slot: 1 }]
+    );
+
+    // The block is signed by another key than the aggregator of the header, or not signed.
+    let mut forged_block = block.clone();
+    let other_account = private_key_to_account(ECDSASecretKey(Secp256K1Scalar::rand())).unwrap();
+    forged_block.aggregator_signature = Some(AggregatorSignature::sign(
+        other_account.private_key,
+        &block.header,
+    ));
+    assert!(matches!(
+        kinds(
+            watchtower
+                .check_public_inputs(&forged_block, &diff, &public_inputs)
+                .unwrap()
+        )[..],
+        [AlertKind::InvalidAggregatorSignature { .. }]
+    ));
+    forged_block.aggregator_signature = None;
+    assert_eq!(
+        kinds(
+            watchtower
+                .check_public_inputs(&forged_block, &diff, &public_inputs)
+                .unwrap()
+        ),
+        vec![AlertKind::MissingAggregatorSignature]
+    );
+
+    // The world state of the sender without the signature is changed.
+    let mut forged_diff = diff.clone();
+    forged_diff.world_state.changes.push(LeafChange {
+        key: unapproved_sender.0.into(),
+        old_value: Default::default(),
+        new_value: GoldilocksHashOut::rand(),
+    });
+    let alerts = kinds(
+        watchtower
+            .check_public_inputs(&block, &forged_diff, &public_inputs)
+            .unwrap(),
+    );
+    assert!(alerts.contains(&AlertKind::UnapprovedWorldStateChange {
+        address: unapproved_sender
+    }));
+    assert!(alerts
+        .iter()
+        .any(|kind| matches!(kind, AlertKind::WorldStateMismatch { .. })));
+    assert_eq!(watchtower.latest_header().block_number, 0);
+
+    // The honest block is applied to the replica.
+    assert!(watchtower
+        .check_public_inputs(&block, &diff, &public_inputs)
+        .unwrap()
+        .is_empty());
+    assert_eq!(watchtower.latest_header(), &block.header);
+    assert_eq!(
+        watchtower.world_state_tree().get(&sender.0.into()).unwrap(),
+        trees.world_state_tree.get(&sender.0.into()).unwrap()
+    );
+
+    // The same block cannot follow itself.
+    assert!(kinds(
+        watchtower
+            .check_public_inputs(&block, &diff, &public_inputs)
+            .unwrap()
+    )
+    .contains(&AlertKind::UnexpectedBlockNumber {
+        expected: 2,
+        actual: 1
+    }));
+
+    let alerts = watchtower.check_block(&block, &diff, &[]).unwrap();
+    assert!(matches!(alerts[0].kind, AlertKind::InvalidProof { .. }));
+}
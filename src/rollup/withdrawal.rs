@@ -0,0 +1,183 @@
+//! The per-block withdrawal queue.
+//!
+//! A withdrawal is an asset burned by an approved transaction, paid to the sender of the transaction.
+//! The block circuit accumulates the withdrawals of the block into `withdrawal_root` with
+//! `WithdrawalBlockProofTarget` and exposes it as a public input. The L1 contract stores the root
+//! of each verified block and pays a withdrawal given its `WithdrawalProof`,
+//! with `(block_number, index)` as the nullifier.
+
+use plonky2::hash::hash_types::{HashOut, RichField};
+
+use crate::{
+    merkle_tree::tree::{get_merkle_proof, get_merkle_root, MerkleProof},
+    rollup::gadgets::withdrawal::WithdrawalInfo,
+    sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+    transaction::asset::Asset,
+    zkdsa::account::Address,
+};
+
+/// The proof with which the recipient claims `withdrawal` on L1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawalProof<F: RichField> {
+    pub block_number: u32,
+    pub withdrawal: WithdrawalInfo<F>,
+
+    /// The inclusion proof of the hash of `withdrawal` in the withdrawal root of `block_number`.
+    pub merkle_proof: MerkleProof<F>,
+}
+
+impl<F: RichField> WithdrawalProof<F> {
+    /// The index of the withdrawal in the block, which is used as the nullifier with `block_number`.
+    pub fn index(&self) -> usize {
+        self.merkle_proof.index
+    }
+
+    pub fn verify(&self, withdrawal_root: HashOut<F>) -> bool {
+        let leaf: WrappedHashOut<F> = self.withdrawal.hash().into();
+
+        self.merkle_proof.value == leaf
+            && *get_merkle_root(self.merkle_proof.index, leaf, &self.merkle_proof.siblings)
+                == withdrawal_root
+    }
+}
+
+/// The withdrawals of a block in the layout of `WithdrawalBlockProofTarget`,
+/// where `transactions[i]` are the sender and the burned assets of the approved transaction in slot `i`.
+/// The transactions not approved must be given without burned assets. `None` is a zero leaf.
+pub fn get_block_withdrawals<F: RichField>(
+    transactions: &[(Address<F>, &[Asset<F>])],
+    n_txs: usize,
+    n_burns_per_tx: usize,
+) -> anyhow::Result<Vec<Option<WithdrawalInfo<F>>>> {
+    anyhow::ensure!(
+        transactions.len() <= n_txs,
+        "too many transactions: {} > {}",
+        transactions.len(),
+        n_txs
+    );
+
+    let n_leaves_per_tx = n_burns_per_tx.next_power_of_two();
+    let n_leaves = (n_txs.next_power_of_two() * n_leaves_per_tx).max(2);
+    let mut withdrawals = vec![None; n_leaves];
+    for (i, (sender_address, burned_assets)) in transactions.iter().enumerate() {
+        anyhow::ensure!(
+            burned_assets.len() <= n_burns_per_tx,
+            "the transaction #{} burns too many assets: {} > {}",
+            i,
+            burned_assets.len(),
+            n_burns_per_tx
+        );
+        for (k, asset) in burned_assets.iter().enumerate() {
+            withdrawals[i * n_leaves_per_tx + k] =
+                Some(WithdrawalInfo::from_burn(*sender_address, asset));
+        }
+    }
+
+    Ok(withdrawals)
+}
+
+fn get_withdrawal_leaves<F: RichField>(
+    withdrawals: &[Option<WithdrawalInfo<F>>],
+) -> (Vec<WrappedHashOut<F>>, usize) {
+    let leaves = withdrawals
+        .iter()
+        .map(|withdrawal| withdrawal.map_or(HashOut::ZERO, |w| w.hash()).into())
+        .collect();
+    let n_log_withdrawals = withdrawals.len().next_power_of_two().trailing_zeros() as usize;
+
+    (leaves, n_log_withdrawals)
+}
+
+/// The native counterpart of `WithdrawalBlockProofTarget::withdrawal_root`
+/// for the withdrawals given by `get_block_withdrawals`.
+pub fn get_withdrawal_root<F: RichField>(
+    withdrawals: &[Option<WithdrawalInfo<F>>],
+) -> WrappedHashOut<F> {
+    let (leaves, n_log_withdrawals) = get_withdrawal_leaves(withdrawals);
+
+    get_merkle_proof(&leaves, 0, n_log_withdrawals).root
+}
+
+/// Returns the proofs of the withdrawals given by `get_block_withdrawals` in the block `block_number`,
+/// in the order of the leaves.
+pub fn make_withdrawal_proofs<F: RichField>(
+    block_number: u32,
+    withdrawals: &[Option<WithdrawalInfo<F>>],
+) -> Vec<WithdrawalProof<F>> {
+    let (leaves, n_log_withdrawals) = get_withdrawal_leaves(withdrawals);
+
+    withdrawals
+        .iter()
+        .enumerate()
+        .filter_map(|(index, withdrawal)| {
+            withdrawal.map(|withdrawal| WithdrawalProof {
+                block_number,
+                withdrawal,
+                merkle_proof: get_merkle_proof(&leaves, index, n_log_withdrawals),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_withdrawal_proofs() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+
+    use crate::{
+        sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut, transaction::asset::TokenKind,
+    };
+
+    type F = GoldilocksField;
+
+    let burned_assets = (1..=3)
+        .map(|i| Asset {
+            kind: TokenKind {
+                contract_address: Address(*GoldilocksHashOut::from_u32(100)),
+                variable_index: GoldilocksHashOut::from_u32(i),
+            },
+            amount: 10 * i as u64,
+        })
+        .collect::<Vec<_>>();
+    let senders = [1, 2].map(|i| Address::<F>(*GoldilocksHashOut::from_u32(i)));
+
+    // Three transaction slots with two burns each, padded to 4 * 2 leaves.
+    let withdrawals = get_block_withdrawals(
+        &[
+            (senders[0], &burned_assets[..2]),
+            (senders[1], &[][..]),
+            (senders[1], &burned_assets[2..]),
+        ],
+        3,
+        2,
+    )
+    .unwrap();
+    assert_eq!(withdrawals.len(), 8);
+    assert_eq!(
+        withdrawals[4],
+        Some(WithdrawalInfo::from_burn(senders[1], &burned_assets[2]))
+    );
+    assert_eq!(withdrawals[4].unwrap().amount, F::from_canonical_u32(30));
+    assert_eq!(withdrawals.iter().flatten().count(), 3);
+
+    let withdrawal_root = get_withdrawal_root(&withdrawals);
+    let withdrawal_proofs = make_withdrawal_proofs(7, &withdrawals);
+    assert_eq!(
+        withdrawal_proofs
+            .iter()
+            .map(|proof| proof.index())
+            .collect::<Vec<_>>(),
+        [0, 1, 4]
+    );
+    assert!(withdrawal_proofs
+        .iter()
+        .all(|proof| proof.verify(*withdrawal_root)));
+    let mut forged_proof = withdrawal_proofs[1].clone();
+    forged_proof.withdrawal.amount = F::from_canonical_u32(1000);
+    assert!(!forged_proof.verify(*withdrawal_root));
+    let mut forged_proof = withdrawal_proofs[1].clone();
+    forged_proof.withdrawal.recipient = senders[1];
+    assert!(!forged_proof.verify(*withdrawal_root));
+
+    assert!(get_block_withdrawals(&[(senders[0], &burned_assets[..])], 3, 2).is_err());
+    assert!(get_block_withdrawals(&[(senders[0], &burned_assets[..1]); 4], 3, 2).is_err());
+}
@@ -0,0 +1,262 @@
+//! JSON-RPC 2.0 interface of an aggregator, enabled by the `rpc` feature.
+//!
+//! The aggregator state is abstracted by `AggregatorBackend`, and `handle_json_rpc_request`
+//! decodes a request body, calls the backend and encodes the response,
+//! so it can be mounted on any HTTP server (the same as `handle_remote_proving_request`).
+//! Parameters and results use the serde representation of the crate's types.
+//!
+//! | method                         | params                 | result                         |
+//! |--------------------------------|------------------------|--------------------------------|
+//! | `intmax_submitUserTransaction` | `{ "proof": … }`       | the transaction hash           |
+//! | `intmax_getBlock`              | `{ "block_number": … }`| `BlockInfo` or `null`          |
+//! | `intmax_getInclusionProof`     | `{ "address": … }`     | `SmtInclusionProof`            |
+//! | `intmax_getWorldStateRoot`     | none                   | the latest world state root    |
+
+use plonky2::{
+    field::extension::Extendable, hash::hash_types::RichField, plonk::config::GenericConfig,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    rollup::block::BlockInfo,
+    sparse_merkle_tree::{
+        gadgets::verify::verify_smt::SmtInclusionProof, goldilocks_poseidon::WrappedHashOut,
+    },
+    transaction::circuits::MergeAndPurgeTransitionProofWithPublicInputs,
+    zkdsa::account::Address,
+};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Returned when the backend fails, e.g. the submitted proof is invalid.
+pub const SERVER_ERROR: i64 = -32000;
+
+/// The aggregator operations exposed over JSON-RPC.
+pub trait AggregatorBackend<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>
+{
+    /// Verifies and queues a user transaction proof for the next block.
+    /// Returns the transaction hash.
+    fn submit_user_transaction(
+        &self,
+        proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<WrappedHashOut<F>>;
+
+    fn get_block(&self, block_number: u32) -> anyhow::Result<Option<BlockInfo<F>>>;
+
+    /// Returns the inclusion proof of the user asset root of `address` in the world state tree.
+    fn get_inclusion_proof(&self, address: Address<F>) -> anyhow::Result<SmtInclusionProof<F>>;
+
+    fn get_world_state_root(&self) -> anyhow::Result<WrappedHashOut<F>>;
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    /// `None` for notifications.
+    #[serde(default)]
+    pub id: Option<Value>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+    pub id: Value,
+}
+
+impl JsonRpcResponse {
+    fn new(id: Value, result: Result<Value, JsonRpcError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Self {
+            jsonrpc: "2.0".to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+fn rpc_error(code: i64, message: impl ToString) -> JsonRpcError {
+    JsonRpcError {
+        code,
+        message: message.to_string(),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, JsonRpcError> {
+    serde_json::from_value(params).map_err(|err| rpc_error(INVALID_PARAMS, err))
+}
+
+fn to_result(value: anyhow::Result<impl Serialize>) -> Result<Value, JsonRpcError> {
+    let value = value.map_err(|err| rpc_error(SERVER_ERROR, err))?;
+
+    serde_json::to_value(value).map_err(|err| rpc_error(SERVER_ERROR, err))
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct SubmitUserTransactionParams<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+}
+
+#[derive(Deserialize)]
+struct GetBlockParams {
+    block_number: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct GetInclusionProofParams<F: RichField> {
+    address: Address<F>,
+}
+
+/// Calls the method of `request` on `backend`.
+pub fn dispatch<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    backend: &impl AggregatorBackend<F, C, D>,
+    request: JsonRpcRequest,
+) -> Result<Value, JsonRpcError> {
+    if request.jsonrpc != "2.0" {
+        return Err(rpc_error(INVALID_REQUEST, "jsonrpc must be \"2.0\""));
+    }
+
+    match request.method.as_str() {
+        "intmax_submitUserTransaction" => {
+            let params: SubmitUserTransactionParams<F, C, D> = parse_params(request.params)?;
+            to_result(backend.submit_user_transaction(params.proof))
+        }
+        "intmax_getBlock" => {
+            let params: GetBlockParams = parse_params(request.params)?;
+            to_result(backend.get_block(params.block_number))
+        }
+        "intmax_getInclusionProof" => {
+            let params: GetInclusionProofParams<F> = parse_params(request.params)?;
+            to_result(backend.get_inclusion_proof(params.address))
+        }
+        "intmax_getWorldStateRoot" => to_result(backend.get_world_state_root()),
+        method => Err(rpc_error(
+            METHOD_NOT_FOUND,
+            format!("unknown method: {}", method),
+        )),
+    }
+}
+
+/// Handles the body of a JSON-RPC request and returns the body of the response.
+/// Returns `None` for a notification, which must not be answered.
+pub fn handle_json_rpc_request<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    backend: &impl AggregatorBackend<F, C, D>,
+    body: &[u8],
+) -> Option<Vec<u8>> {
+    let response = match serde_json::from_slice::<JsonRpcRequest>(body) {
+        Ok(request) => {
+            let id = request.id.clone();
+            let result = dispatch(backend, request);
+            JsonRpcResponse::new(id?, result)
+        }
+        Err(err) => JsonRpcResponse::new(Value::Null, Err(rpc_error(PARSE_ERROR, err))),
+    };
+
+    Some(serde_json::to_vec(&response).expect("fail to serialize JSON-RPC response"))
+}
+
+#[test]
+fn test_handle_json_rpc_request() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut;
+
+    type F = GoldilocksField;
+    type C = PoseidonGoldilocksConfig;
+    const D: usize = 2;
+
+    struct MockBackend;
+
+    impl AggregatorBackend<F, C, D> for MockBackend {
+        fn submit_user_transaction(
+            &self,
+            proof: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+        ) -> anyhow::Result<WrappedHashOut<F>> {
+            Ok(proof.public_inputs.tx_hash)
+        }
+
+        fn get_block(&self, block_number: u32) -> anyhow::Result<Option<BlockInfo<F>>> {
+            Ok((block_number == 0).then(|| BlockInfo::with_tree_depth(3)))
+        }
+
+        fn get_inclusion_proof(
+            &self,
+            _address: Address<F>,
+        ) -> anyhow::Result<SmtInclusionProof<F>> {
+            anyhow::bail!("not implemented")
+        }
+
+        fn get_world_state_root(&self) -> anyhow::Result<WrappedHashOut<F>> {
+            Ok(GoldilocksHashOut::from_u32(1))
+        }
+    }
+
+    let call = |body: &str| -> JsonRpcResponse {
+        let response = handle_json_rpc_request(&MockBackend, body.as_bytes()).unwrap();
+        serde_json::from_slice(&response).unwrap()
+    };
+
+    let response = call(r#"{"jsonrpc":"2.0","method":"intmax_getWorldStateRoot","id":1}"#);
+    assert_eq!(
+        response.result,
+        Some(serde_json::to_value(GoldilocksHashOut::from_u32(1)).unwrap())
+    );
+    assert_eq!(response.id, Value::from(1));
+
+    let response =
+        call(r#"{"jsonrpc":"2.0","method":"intmax_getBlock","params":{"block_number":1},"id":2}"#);
+    assert_eq!(response.result, Some(Value::Null));
+
+    let response = call(r#"{"jsonrpc":"2.0","method":"intmax_getBlock","params":{},"id":3}"#);
+    assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+    let response = call(
+        r#"{"jsonrpc":"2.0","method":"intmax_getInclusionProof","params":{"address":"0x01"},"id":4}"#,
+    );
+    assert_eq!(response.error.unwrap().code, SERVER_ERROR);
+
+    let response = call(r#"{"jsonrpc":"2.0","method":"unknown","id":5}"#);
+    assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+
+    let response = call("not json");
+    assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+    let notification = r#"{"jsonrpc":"2.0","method":"intmax_getWorldStateRoot"}"#;
+    assert!(handle_json_rpc_request(&MockBackend, notification.as_bytes()).is_none());
+}
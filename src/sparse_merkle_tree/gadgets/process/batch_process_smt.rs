@@ -0,0 +1,149 @@
+//! Sequential updates of one tree proved by a single target.
+//!
+//! The `N_OPS` process proofs share their intermediate roots: the new root target of each update
+//! is the old root target of the next one, so that a chain of updates needs no extra root witness
+//! or equality check between the updates. Since the keys are witnesses, the levels where the paths
+//! of two updates meet are not known when the circuit is built, and each update still hashes its
+//! whole old and new paths.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::witness::Witness,
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::errors::{ensure_at_most, ensure_witness, IntmaxError};
+
+use super::process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget};
+
+#[derive(Clone, Debug)]
+pub struct SparseMerkleBatchProcessTarget<const N_LEVELS: usize, const N_OPS: usize> {
+    /// The updates in order, where `process_proofs[i].new_root` is `process_proofs[i + 1].old_root`.
+    pub process_proofs: Vec<SparseMerkleProcessProofTarget<N_LEVELS>>, // input
+
+    pub old_root: HashOutTarget, // input
+    pub new_root: HashOutTarget, // output
+}
+
+impl<const N_LEVELS: usize, const N_OPS: usize> SparseMerkleBatchProcessTarget<N_LEVELS, N_OPS> {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        assert_ne!(N_OPS, 0, "a batch must have a process proof");
+
+        let old_root = builder.add_virtual_hash();
+        let mut root = old_root;
+        let process_proofs = (0..N_OPS)
+            .map(|_| {
+                let new_root = builder.add_virtual_hash();
+                let proof_t = SparseMerkleProcessProofTarget::add_virtual_to_with_roots::<F, H, D>(
+                    builder, root, new_root,
+                );
+                root = new_root;
+
+                proof_t
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            process_proofs,
+            old_root,
+            new_root: root,
+        }
+    }
+
+    /// `process_proofs` must update the tree with `old_root` in order.
+    /// The unused slots are filled with no-op proofs, and the new root is returned.
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        process_proofs: &[SmtProcessProof<F>],
+        old_root: HashOut<F>,
+    ) -> Result<HashOut<F>, IntmaxError> {
+        ensure_at_most("batch process proofs", process_proofs.len(), N_OPS)?;
+
+        let mut root = old_root.into();
+        for (i, proof) in process_proofs.iter().enumerate() {
+            ensure_witness!(
+                proof.old_root == root,
+                "batch process proof #{} old_root mismatch",
+                i
+            );
+            ensure_witness!(
+                proof.siblings.len() <= N_LEVELS,
+                "batch process proof #{} has too many siblings",
+                i
+            );
+            root = proof.new_root;
+        }
+
+        let default_proof = SmtProcessProof::with_root(root);
+        for (i, proof_t) in self.process_proofs.iter().enumerate() {
+            proof_t.set_witness(pw, process_proofs.get(i).unwrap_or(&default_proof));
+        }
+
+        Ok(*root)
+    }
+}
+
+#[test]
+fn test_batch_process_proof() {
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::{
+        GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree,
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+    const N_LEVELS: usize = 8;
+    const N_OPS: usize = 4;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let target: SparseMerkleBatchProcessTarget<N_LEVELS, N_OPS> =
+        SparseMerkleBatchProcessTarget::add_virtual_to::<F, H, D>(&mut builder);
+    builder.register_public_inputs(&target.old_root.elements);
+    builder.register_public_inputs(&target.new_root.elements);
+    let data = builder.build::<C>();
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let key = GoldilocksHashOut::from_u128(1);
+    tree.set(key, GoldilocksHashOut::from_u128(2)).unwrap();
+    let old_root = tree.get_root();
+
+    // insert, update and remove in one batch, with an unused slot.
+    let other_key = GoldilocksHashOut::from_u128(3);
+    let process_proofs = vec![
+        tree.set(other_key, GoldilocksHashOut::from_u128(4))
+            .unwrap(),
+        tree.set(key, GoldilocksHashOut::from_u128(5)).unwrap(),
+        tree.set(other_key, Default::default()).unwrap(),
+    ];
+
+    let mut pw = PartialWitness::new();
+    let new_root = target
+        .set_witness(&mut pw, &process_proofs, *old_root)
+        .unwrap();
+    assert_eq!(new_root, *tree.get_root());
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(proof.public_inputs[0..4], old_root.elements);
+    assert_eq!(proof.public_inputs[4..8], new_root.elements);
+    data.verify(proof).unwrap();
+
+    // The updates must be in order.
+    let mut pw = PartialWitness::new();
+    let reordered_proofs = vec![process_proofs[1].clone(), process_proofs[0].clone()];
+    assert!(target
+        .set_witness(&mut pw, &reordered_proofs, *old_root)
+        .is_err());
+}
@@ -1,3 +1,4 @@
+pub mod batch_process_smt;
 pub mod process_smt;
 pub mod utils;
 
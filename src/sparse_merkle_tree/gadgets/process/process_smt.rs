@@ -38,11 +38,25 @@ impl<const N_LEVELS: usize> SparseMerkleProcessProofTarget<N_LEVELS> {
     pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
     ) -> Self {
-        let siblings = builder.add_virtual_hashes(N_LEVELS);
         let old_root = builder.add_virtual_hash();
+        let new_root = builder.add_virtual_hash();
+
+        Self::add_virtual_to_with_roots::<F, H, D>(builder, old_root, new_root)
+    }
+
+    /// The same as `add_virtual_to`, but the roots are given, e.g. the roots of the adjacent updates.
+    pub fn add_virtual_to_with_roots<
+        F: RichField + Extendable<D>,
+        H: AlgebraicHasher<F>,
+        const D: usize,
+    >(
+        builder: &mut CircuitBuilder<F, D>,
+        old_root: HashOutTarget,
+        new_root: HashOutTarget,
+    ) -> Self {
+        let siblings = builder.add_virtual_hashes(N_LEVELS);
         let old_key = builder.add_virtual_hash();
         let old_value = builder.add_virtual_hash();
-        let new_root = builder.add_virtual_hash();
         let new_key = builder.add_virtual_hash();
         let new_value = builder.add_virtual_hash();
         let is_old0 = builder.add_virtual_bool_target_safe();
@@ -98,6 +112,104 @@ impl<const N_LEVELS: usize> SparseMerkleProcessProofTarget<N_LEVELS> {
     }
 }
 
+/// The same as `SparseMerkleProcessProofTarget`, but the number of levels is decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynSparseMerkleProcessProofTarget {
+    pub siblings: Vec<HashOutTarget>,
+    pub old_root: HashOutTarget,
+    pub new_root: HashOutTarget,
+    pub old_key: HashOutTarget,
+    pub old_value: HashOutTarget,
+    pub new_key: HashOutTarget,
+    pub new_value: HashOutTarget,
+    pub is_old0: BoolTarget,
+    pub fnc: [BoolTarget; 2],
+}
+
+impl DynSparseMerkleProcessProofTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        n_levels: usize,
+    ) -> Self {
+        let siblings = builder.add_virtual_hashes(n_levels);
+        let old_root = builder.add_virtual_hash();
+        let old_key = builder.add_virtual_hash();
+        let old_value = builder.add_virtual_hash();
+        let new_root = builder.add_virtual_hash();
+        let new_key = builder.add_virtual_hash();
+        let new_value = builder.add_virtual_hash();
+        let is_old0 = builder.add_virtual_bool_target_safe();
+        let fnc0 = builder.add_virtual_bool_target_safe();
+        let fnc1 = builder.add_virtual_bool_target_safe();
+
+        verify_smt_process_proof::<F, H, D>(
+            builder,
+            &siblings,
+            old_root,
+            old_key,
+            old_value,
+            new_root,
+            new_key,
+            new_value,
+            is_old0,
+            [fnc0, fnc1],
+        );
+
+        Self {
+            siblings,
+            old_root,
+            new_root,
+            old_key,
+            old_value,
+            new_key,
+            new_value,
+            is_old0,
+            fnc: [fnc0, fnc1],
+        }
+    }
+
+    pub fn set_witness<F: Field>(&self, pw: &mut impl Witness<F>, witness: &SmtProcessProof<F>) {
+        assert!(witness.siblings.len() <= self.siblings.len());
+        for (i, sibling_t) in self.siblings.iter().enumerate() {
+            let sibling = witness
+                .siblings
+                .get(i)
+                .map(|sibling| **sibling)
+                .unwrap_or(HashOut::<F>::ZERO);
+            pw.set_hash_target(*sibling_t, sibling);
+        }
+        pw.set_hash_target(self.old_root, *witness.old_root);
+        pw.set_hash_target(self.new_root, *witness.new_root);
+        pw.set_hash_target(self.old_key, *witness.old_key);
+        pw.set_hash_target(self.old_value, *witness.old_value);
+        pw.set_hash_target(self.new_key, *witness.new_key);
+        pw.set_hash_target(self.new_value, *witness.new_value);
+        pw.set_bool_target(self.is_old0, witness.is_old0);
+
+        let fnc: [bool; 2] = witness.fnc.into();
+        pw.set_bool_target(self.fnc[0], fnc[0]);
+        pw.set_bool_target(self.fnc[1], fnc[1]);
+    }
+}
+
+impl<const N_LEVELS: usize> From<SparseMerkleProcessProofTarget<N_LEVELS>>
+    for DynSparseMerkleProcessProofTarget
+{
+    fn from(value: SparseMerkleProcessProofTarget<N_LEVELS>) -> Self {
+        Self {
+            siblings: value.siblings.to_vec(),
+            old_root: value.old_root,
+            new_root: value.new_root,
+            old_key: value.old_key,
+            old_value: value.old_value,
+            new_key: value.new_key,
+            new_value: value.new_value,
+            is_old0: value.is_old0,
+            fnc: value.fnc,
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn verify_smt_process_proof<
     F: RichField + Extendable<D>,
@@ -16,6 +16,8 @@ pub struct ProcessMerkleProofRoleTarget {
     pub is_update_op: BoolTarget,
     pub is_remove_op: BoolTarget,
     pub is_insert_or_update_op: BoolTarget,
+    /// The same as `is_insert_or_update_op`, for the callers which do not care whether the key existed.
+    pub is_upsert_op: BoolTarget,
     pub is_remove_or_update_op: BoolTarget,
     pub is_insert_or_no_op: BoolTarget,
     pub is_remove_or_no_op: BoolTarget,
@@ -46,6 +48,7 @@ pub fn get_process_merkle_proof_role<F: RichField + Extendable<D>, const D: usiz
         is_update_op,
         is_remove_op,
         is_insert_or_update_op,
+        is_upsert_op: is_insert_or_update_op,
         is_remove_or_update_op,
         is_insert_or_no_op,
         is_remove_or_no_op,
@@ -55,6 +58,20 @@ pub fn get_process_merkle_proof_role<F: RichField + Extendable<D>, const D: usiz
     }
 }
 
+/// if enabled { assert!(fnc is ProcessInsert or ProcessUpdate) }
+pub fn enforce_upsert_role_if_enabled<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    fnc: [BoolTarget; 2],
+    enabled: BoolTarget,
+) {
+    let ProcessMerkleProofRoleTarget { is_upsert_op, .. } =
+        get_process_merkle_proof_role(builder, fnc);
+    let is_not_upsert_op = builder.not(is_upsert_op);
+    let is_invalid = builder.and(is_not_upsert_op, enabled);
+    let constant_false = builder._false();
+    builder.connect(is_invalid.target, constant_false.target);
+}
+
 pub fn verify_smt_transition<F: RichField + Extendable<D>, const D: usize>(
     builder: &mut CircuitBuilder<F, D>,
     cur_smt_fnc: [BoolTarget; 2],
@@ -51,3 +51,105 @@ fn test_verify_inclusion_proof_by_plonky2() {
         Err(x) => println!("{}", x),
     }
 }
+
+#[test]
+fn test_verify_inclusion_proof_with_enabled_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::iop::witness::{PartialWitness, Witness};
+    use plonky2::plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitConfig,
+        config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+
+    use super::super::{
+        gadgets::verify::verify_smt::{
+            DynSparseMerkleInclusionProofTarget, SmtInclusionProof,
+            SparseMerkleInclusionProofTarget,
+        },
+        goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+    const N_LEVELS: usize = 16;
+
+    let mut tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+        PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+    let key1 = GoldilocksHashOut::from_u128(1);
+    let key2 = GoldilocksHashOut::from_u128(12);
+    tree.insert(key1, GoldilocksHashOut::from_u128(2)).unwrap();
+    tree.insert(key2, GoldilocksHashOut::from_u128(1)).unwrap();
+
+    let witness = tree.find(&key2).unwrap();
+    let mut bad_witness = witness.clone();
+    bad_witness.value = GoldilocksHashOut::from_u128(3);
+
+    // The slots of both targets are enabled by the flags given from outside.
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let enabled = builder.add_virtual_bool_target_safe();
+    let target: SparseMerkleInclusionProofTarget<N_LEVELS> =
+        SparseMerkleInclusionProofTarget::add_virtual_to_with_enabled::<F, H, D>(
+            &mut builder,
+            enabled,
+        );
+    let dyn_enabled = builder.add_virtual_bool_target_safe();
+    let dyn_target = DynSparseMerkleInclusionProofTarget::add_virtual_to_with_enabled::<F, H, D>(
+        &mut builder,
+        N_LEVELS,
+        dyn_enabled,
+    );
+    let data = builder.build::<C>();
+
+    // `None` is the disabled slot filled with zeros.
+    let prove = |w: Option<&SmtInclusionProof<F>>,
+                 enabled_w: bool,
+                 dyn_w: Option<&SmtInclusionProof<F>>,
+                 dyn_enabled_w: bool| {
+        let mut pw = PartialWitness::new();
+        pw.set_bool_target(enabled, enabled_w);
+        if let Some(w) = w {
+            target.set_proof_witness(&mut pw, w);
+        } else {
+            target.set_disabled_witness(&mut pw);
+        }
+        pw.set_bool_target(dyn_enabled, dyn_enabled_w);
+        if let Some(w) = dyn_w {
+            dyn_target.set_proof_witness(&mut pw, w);
+        } else {
+            dyn_target.set_disabled_witness(&mut pw);
+        }
+
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| data.verify(proof))
+        }))
+    };
+
+    prove(Some(&witness), true, Some(&witness), true)
+        .unwrap()
+        .unwrap();
+    prove(None, false, None, false).unwrap().unwrap();
+    prove(Some(&witness), true, None, false).unwrap().unwrap();
+    prove(None, false, Some(&witness), true).unwrap().unwrap();
+
+    // A disabled slot constrains nothing, even if its proof is invalid.
+    prove(Some(&bad_witness), false, Some(&bad_witness), false)
+        .unwrap()
+        .unwrap();
+
+    // An enabled slot with an invalid proof is rejected.
+    assert!(!matches!(
+        prove(Some(&bad_witness), true, None, false),
+        Ok(Ok(_))
+    ));
+    assert!(!matches!(
+        prove(None, false, Some(&bad_witness), true),
+        Ok(Ok(_))
+    ));
+    assert!(!matches!(prove(None, true, None, false), Ok(Ok(_))));
+    assert!(!matches!(prove(None, false, None, true), Ok(Ok(_))));
+}
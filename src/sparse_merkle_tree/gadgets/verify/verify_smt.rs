@@ -39,8 +39,29 @@ pub struct SparseMerkleInclusionProofTarget<const N_LEVELS: usize> {
 }
 
 impl<const N_LEVELS: usize> SparseMerkleInclusionProofTarget<N_LEVELS> {
+    /// `enabled` is a witness given by `set_witness`, so connect it if the prover must not choose it.
     pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
         builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        Self::add_virtual_to_with::<F, H, D>(builder, None)
+    }
+
+    /// The proof is checked only if `enabled`, e.g. the flag of a slot, and constrains nothing otherwise.
+    /// Set its witness by `set_proof_witness` or `set_disabled_witness`, which leave `enabled` as is.
+    pub fn add_virtual_to_with_enabled<
+        F: RichField + Extendable<D>,
+        H: AlgebraicHasher<F>,
+        const D: usize,
+    >(
+        builder: &mut CircuitBuilder<F, D>,
+        enabled: BoolTarget,
+    ) -> Self {
+        Self::add_virtual_to_with::<F, H, D>(builder, Some(enabled))
+    }
+
+    fn add_virtual_to_with<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        enabled: Option<BoolTarget>,
     ) -> Self {
         let siblings = builder.add_virtual_hashes(N_LEVELS);
         let root = builder.add_virtual_hash();
@@ -48,7 +69,7 @@ impl<const N_LEVELS: usize> SparseMerkleInclusionProofTarget<N_LEVELS> {
         let old_value = builder.add_virtual_hash();
         let key = builder.add_virtual_hash();
         let value = builder.add_virtual_hash();
-        let enabled = builder.add_virtual_bool_target_safe();
+        let enabled = enabled.unwrap_or_else(|| builder.add_virtual_bool_target_safe());
         let is_old0 = builder.add_virtual_bool_target_safe();
         let fnc = builder.add_virtual_bool_target_safe();
 
@@ -74,6 +95,16 @@ impl<const N_LEVELS: usize> SparseMerkleInclusionProofTarget<N_LEVELS> {
         pw: &mut impl Witness<F>,
         witness: &SmtInclusionProof<F>,
         enabled: bool,
+    ) {
+        self.set_proof_witness(pw, witness);
+        pw.set_bool_target(self.enabled, enabled);
+    }
+
+    /// Sets `witness` except `enabled`.
+    pub fn set_proof_witness<F: Field>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &SmtInclusionProof<F>,
     ) {
         assert!(witness.siblings.len() < N_LEVELS);
         for i in 0..witness.siblings.len() {
@@ -87,10 +118,148 @@ impl<const N_LEVELS: usize> SparseMerkleInclusionProofTarget<N_LEVELS> {
         pw.set_hash_target(self.old_value, *witness.not_found_value);
         pw.set_hash_target(self.key, *witness.key);
         pw.set_hash_target(self.value, *witness.value);
-        pw.set_bool_target(self.enabled, enabled);
         pw.set_bool_target(self.is_old0, witness.is_old0);
         pw.set_bool_target(self.fnc, !witness.found); // whether if this is a non-inclusion proof
     }
+
+    /// Fills the proof of a disabled slot with zeros, which needs no dummy proof.
+    pub fn set_disabled_witness<F: Field>(&self, pw: &mut impl Witness<F>) {
+        DynSparseMerkleInclusionProofTarget::from(self.clone()).set_disabled_witness(pw);
+    }
+}
+
+/// The same as `SparseMerkleInclusionProofTarget`, but the number of levels is decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynSparseMerkleInclusionProofTarget {
+    pub siblings: Vec<HashOutTarget>,
+    pub root: HashOutTarget,
+    pub old_key: HashOutTarget,
+    pub old_value: HashOutTarget,
+    pub key: HashOutTarget,
+    pub value: HashOutTarget,
+    pub enabled: BoolTarget,
+    pub is_old0: BoolTarget,
+    pub fnc: BoolTarget,
+}
+
+impl DynSparseMerkleInclusionProofTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        n_levels: usize,
+    ) -> Self {
+        Self::add_virtual_to_with::<F, H, D>(builder, n_levels, None)
+    }
+
+    /// See `SparseMerkleInclusionProofTarget::add_virtual_to_with_enabled`.
+    pub fn add_virtual_to_with_enabled<
+        F: RichField + Extendable<D>,
+        H: AlgebraicHasher<F>,
+        const D: usize,
+    >(
+        builder: &mut CircuitBuilder<F, D>,
+        n_levels: usize,
+        enabled: BoolTarget,
+    ) -> Self {
+        Self::add_virtual_to_with::<F, H, D>(builder, n_levels, Some(enabled))
+    }
+
+    fn add_virtual_to_with<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        n_levels: usize,
+        enabled: Option<BoolTarget>,
+    ) -> Self {
+        let siblings = builder.add_virtual_hashes(n_levels);
+        let root = builder.add_virtual_hash();
+        let old_key = builder.add_virtual_hash();
+        let old_value = builder.add_virtual_hash();
+        let key = builder.add_virtual_hash();
+        let value = builder.add_virtual_hash();
+        let enabled = enabled.unwrap_or_else(|| builder.add_virtual_bool_target_safe());
+        let is_old0 = builder.add_virtual_bool_target_safe();
+        let fnc = builder.add_virtual_bool_target_safe();
+
+        verify_smt_inclusion_proof::<F, H, D>(
+            builder, &siblings, root, old_key, old_value, key, value, enabled, is_old0, fnc,
+        );
+
+        Self {
+            siblings,
+            root,
+            old_key,
+            old_value,
+            key,
+            value,
+            enabled,
+            is_old0,
+            fnc,
+        }
+    }
+
+    pub fn set_witness<F: Field>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &SmtInclusionProof<F>,
+        enabled: bool,
+    ) {
+        self.set_proof_witness(pw, witness);
+        pw.set_bool_target(self.enabled, enabled);
+    }
+
+    /// Sets `witness` except `enabled`.
+    pub fn set_proof_witness<F: Field>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &SmtInclusionProof<F>,
+    ) {
+        assert!(witness.siblings.len() < self.siblings.len());
+        for (i, sibling_t) in self.siblings.iter().enumerate() {
+            let sibling = witness
+                .siblings
+                .get(i)
+                .map(|sibling| **sibling)
+                .unwrap_or(HashOut::<F>::ZERO);
+            pw.set_hash_target(*sibling_t, sibling);
+        }
+        pw.set_hash_target(self.root, *witness.root);
+        pw.set_hash_target(self.old_key, *witness.not_found_key);
+        pw.set_hash_target(self.old_value, *witness.not_found_value);
+        pw.set_hash_target(self.key, *witness.key);
+        pw.set_hash_target(self.value, *witness.value);
+        pw.set_bool_target(self.is_old0, witness.is_old0);
+        pw.set_bool_target(self.fnc, !witness.found);
+    }
+
+    /// Fills the proof of a disabled slot with zeros, which needs no dummy proof.
+    pub fn set_disabled_witness<F: Field>(&self, pw: &mut impl Witness<F>) {
+        for sibling_t in self.siblings.iter() {
+            pw.set_hash_target(*sibling_t, HashOut::<F>::ZERO);
+        }
+        pw.set_hash_target(self.root, HashOut::<F>::ZERO);
+        pw.set_hash_target(self.old_key, HashOut::<F>::ZERO);
+        pw.set_hash_target(self.old_value, HashOut::<F>::ZERO);
+        pw.set_hash_target(self.key, HashOut::<F>::ZERO);
+        pw.set_hash_target(self.value, HashOut::<F>::ZERO);
+        pw.set_bool_target(self.is_old0, false);
+        pw.set_bool_target(self.fnc, false);
+    }
+}
+
+impl<const N_LEVELS: usize> From<SparseMerkleInclusionProofTarget<N_LEVELS>>
+    for DynSparseMerkleInclusionProofTarget
+{
+    fn from(value: SparseMerkleInclusionProofTarget<N_LEVELS>) -> Self {
+        Self {
+            siblings: value.siblings.to_vec(),
+            root: value.root,
+            old_key: value.old_key,
+            old_value: value.old_value,
+            key: value.key,
+            value: value.value,
+            enabled: value.enabled,
+            is_old0: value.is_old0,
+            fnc: value.fnc,
+        }
+    }
 }
 
 #[derive(Clone)]
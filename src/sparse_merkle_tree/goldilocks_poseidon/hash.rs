@@ -79,11 +79,17 @@ fn test_fmt_goldilocks_hashout() {
     assert_eq!(decoded_value, value);
 }
 
+/// Human-readable formats (e.g. JSON) use a `0x`-prefixed hex string,
+/// and binary formats (e.g. bincode) use the field elements.
 impl<F: RichField> Serialize for WrappedHashOut<F> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
+        if !serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+
         let raw = format!("0x{}", self);
 
         serializer.serialize_str(&raw)
@@ -95,9 +101,97 @@ impl<'de, F: RichField> Deserialize<'de> for WrappedHashOut<F> {
     where
         D: Deserializer<'de>,
     {
+        if !deserializer.is_human_readable() {
+            return HashOut::deserialize(deserializer).map(Wrapper);
+        }
+
         let raw = String::deserialize(deserializer)?;
 
-        Ok(WrappedHashOut::from_str(&raw[2..]).unwrap())
+        parse_prefixed_hex(&raw).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, F: RichField> arbitrary::Arbitrary<'a> for WrappedHashOut<F> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut elements = [F::ZERO; 4];
+        for element in elements.iter_mut() {
+            *element = F::from_noncanonical_u64(u.arbitrary()?);
+        }
+
+        Ok(Wrapper(HashOut { elements }))
+    }
+}
+
+/// Parses a big-endian hex string of at most 32 bytes with an optional `0x` prefix.
+pub(crate) fn parse_prefixed_hex<F: RichField>(s: &str) -> anyhow::Result<WrappedHashOut<F>> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    anyhow::ensure!(
+        s.len() <= 64,
+        "hex string must be at most 32 bytes: 0x{}",
+        s
+    );
+
+    Ok(WrappedHashOut::from_str(s)?)
+}
+
+/// `#[serde(with = "hash_out_hex")]` for `HashOut<F>` fields.
+/// A hash is encoded as a `0x`-prefixed 32-byte hex string, the same as `WrappedHashOut`.
+/// With the `legacy-serde` feature, the plonky2 format (an array of field elements) is kept,
+/// which is always used by binary formats.
+pub mod hash_out_hex {
+    use plonky2::hash::hash_types::{HashOut, RichField};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{parse_prefixed_hex, Wrapper};
+
+    pub fn serialize<F: RichField, S: Serializer>(
+        value: &HashOut<F>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if cfg!(feature = "legacy-serde") || !serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            Wrapper(*value).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, F: RichField, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashOut<F>, D::Error> {
+        if cfg!(feature = "legacy-serde") || !deserializer.is_human_readable() {
+            HashOut::deserialize(deserializer)
+        } else {
+            let raw = String::deserialize(deserializer)?;
+            let value = parse_prefixed_hex(&raw).map_err(serde::de::Error::custom)?;
+
+            Ok(value.0)
+        }
+    }
+}
+
+/// `#[serde(with = "hash_out_hex_seq")]` for `Vec<HashOut<F>>` fields.
+pub mod hash_out_hex_seq {
+    use plonky2::hash::hash_types::{HashOut, RichField};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(bound = "")]
+    struct Item<F: RichField>(#[serde(with = "super::hash_out_hex")] HashOut<F>);
+
+    pub fn serialize<F: RichField, S: Serializer>(
+        value: &[HashOut<F>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(value.iter().map(|v| Item(*v)))
+    }
+
+    pub fn deserialize<'de, F: RichField, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<HashOut<F>>, D::Error> {
+        let raw = Vec::<Item<F>>::deserialize(deserializer)?;
+
+        Ok(raw.into_iter().map(|v| v.0).collect())
     }
 }
 
@@ -117,6 +211,10 @@ fn test_serde_goldilocks_hashout() {
     assert_eq!(encoded_value.len(), 68); // include 0x-prefix and quotation marks
     let decoded_value: GoldilocksHashOut = serde_json::from_str(&encoded_value).unwrap();
     assert_eq!(decoded_value, value);
+
+    assert!(serde_json::from_str::<GoldilocksHashOut>("\"0x\"").is_ok());
+    assert!(serde_json::from_str::<GoldilocksHashOut>("\"0xzz\"").is_err());
+    assert!(serde_json::from_str::<GoldilocksHashOut>(&format!("\"0x{}00\"", value)).is_err());
 }
 
 impl<F: RichField> WrappedHashOut<F> {
@@ -143,6 +241,137 @@ impl<F: RichField> WrappedHashOut<F> {
     }
 }
 
+impl<F: RichField> WrappedHashOut<F> {
+    /// Each element as a little-endian `u64`, `elements[0]` first. The same as `HashOut::to_bytes`.
+    pub fn to_bytes_le(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, element) in bytes.chunks_mut(8).zip(self.0.elements.iter()) {
+            chunk.copy_from_slice(&element.to_canonical_u64().to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// The reverse of `to_bytes_le`, i.e. each element as a big-endian `u64`, `elements[3]` first.
+    /// The same as `Display` and the conversions to the ethers types.
+    pub fn to_bytes_be(&self) -> [u8; 32] {
+        let mut bytes = self.to_bytes_le();
+        bytes.reverse();
+
+        bytes
+    }
+
+    /// The inverse of `to_bytes_le`. Fails if an element is not canonical (not less than the field order).
+    pub fn from_bytes_le(bytes: &[u8; 32]) -> anyhow::Result<Self> {
+        let mut elements = [F::ZERO; 4];
+        for (element, chunk) in elements.iter_mut().zip(bytes.chunks(8)) {
+            let value = u64::from_le_bytes(chunk.try_into().unwrap());
+            *element = F::from_noncanonical_u64(value);
+            anyhow::ensure!(
+                element.to_canonical_u64() == value,
+                "non-canonical field element: {}",
+                value
+            );
+        }
+
+        Ok(Wrapper(HashOut { elements }))
+    }
+
+    /// The inverse of `to_bytes_be`. Fails if an element is not canonical.
+    pub fn from_bytes_be(bytes: &[u8; 32]) -> anyhow::Result<Self> {
+        let mut bytes = *bytes;
+        bytes.reverse();
+
+        Self::from_bytes_le(&bytes)
+    }
+}
+
+impl<F: RichField> From<u64> for WrappedHashOut<F> {
+    fn from(value: u64) -> Self {
+        Self::from_u64(value)
+    }
+}
+
+/// Big-endian, the same as `to_bytes_be`.
+impl<F: RichField> TryFrom<[u8; 32]> for WrappedHashOut<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: [u8; 32]) -> Result<Self, Self::Error> {
+        Self::from_bytes_be(&value)
+    }
+}
+
+/// Big-endian, the same as `to_bytes_be`. `value` must be exactly 32 bytes.
+impl<F: RichField> TryFrom<&[u8]> for WrappedHashOut<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let bytes: [u8; 32] = value
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("a hash must be 32 bytes, but got {}", value.len()))?;
+
+        Self::from_bytes_be(&bytes)
+    }
+}
+
+/// A big-endian hex string of at most 32 bytes with an optional `0x` prefix.
+/// Unlike `FromStr`, fails if an element is not canonical.
+impl<F: RichField> TryFrom<&str> for WrappedHashOut<F> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let s = value.strip_prefix("0x").unwrap_or(value);
+        anyhow::ensure!(
+            s.len() <= 64,
+            "hex string must be at most 32 bytes: 0x{}",
+            s
+        );
+        let mut bytes = hex::decode(format!("{:0>64}", s))?;
+        bytes.reverse();
+
+        Self::from_bytes_le(&bytes.try_into().unwrap())
+    }
+}
+
+#[test]
+fn test_bytes_goldilocks_hashout() {
+    use plonky2::field::types::Field64;
+
+    let value = GoldilocksHashOut::rand();
+    assert_eq!(value.to_bytes_le().to_vec(), value.0.to_bytes());
+    assert_eq!(
+        GoldilocksHashOut::from_bytes_le(&value.to_bytes_le()).unwrap(),
+        value
+    );
+    assert_eq!(
+        GoldilocksHashOut::try_from(value.to_bytes_be()).unwrap(),
+        value
+    );
+    assert_eq!(hex::encode(value.to_bytes_be()), format!("{}", value));
+    assert_eq!(
+        GoldilocksHashOut::try_from(&value.to_bytes_be()[..]).unwrap(),
+        value
+    );
+    assert!(GoldilocksHashOut::try_from(&value.to_bytes_be()[1..]).is_err());
+    assert_eq!(
+        GoldilocksHashOut::try_from(format!("0x{}", value).as_str()).unwrap(),
+        value
+    );
+    assert_eq!(
+        GoldilocksHashOut::try_from("0x01").unwrap(),
+        GoldilocksHashOut::from(1u64)
+    );
+    assert_eq!(GoldilocksHashOut::from(1u64 << 40).to_u64(), 1 << 40);
+
+    // The field order is not canonical.
+    let order = GoldilocksField::ORDER;
+    let mut bytes = [0u8; 32];
+    bytes[24..].copy_from_slice(&order.to_be_bytes());
+    assert!(GoldilocksHashOut::try_from(bytes).is_err());
+    assert!(GoldilocksHashOut::try_from(hex::encode(bytes).as_str()).is_err());
+    assert!(GoldilocksHashOut::try_from("0xzz").is_err());
+}
+
 impl<F: PrimeField64> WrappedHashOut<F> {
     /// ```txt
     /// [
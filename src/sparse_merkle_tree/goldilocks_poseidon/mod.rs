@@ -8,6 +8,8 @@ use plonky2::{
     plonk::config::{GenericHashOut, Hasher},
 };
 
+use crate::poseidon::poseidon2::Poseidon2Hash;
+
 use super::{
     goldilocks_poseidon,
     layered_layered_tree::LayeredLayeredSparseMerkleTree,
@@ -19,7 +21,8 @@ use super::{
 };
 
 mod hash;
-pub use self::hash::{GoldilocksHashOut, WrappedHashOut, Wrapper};
+pub(crate) use self::hash::parse_prefixed_hex;
+pub use self::hash::{hash_out_hex, hash_out_hex_seq, GoldilocksHashOut, WrappedHashOut, Wrapper};
 
 fn le_bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
     bytes
@@ -147,6 +150,31 @@ impl NodeHash<K, V, I> for PoseidonNodeHash {
 
 pub type PoseidonSparseMerkleTree<D> = SparseMerkleTree<K, V, I, PoseidonNodeHash, D>;
 
+/// The node hash of `PoseidonNodeHash` with Poseidon2, whose roots are different from the Poseidon ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Poseidon2NodeHash {}
+
+impl NodeHash<K, V, I> for Poseidon2NodeHash {
+    fn calc_node_hash(node: Node<K, V, I>) -> I {
+        match node {
+            Node::Internal(left, right) => {
+                goldilocks_poseidon::Wrapper(Poseidon2Hash::two_to_one(*left, *right))
+            }
+            Node::Leaf(key, value) => {
+                let inputs = [
+                    key.elements.to_vec(),
+                    value.elements.to_vec(),
+                    vec![GoldilocksField(1)],
+                ]
+                .concat();
+                goldilocks_poseidon::Wrapper(Poseidon2Hash::hash_pad(&inputs))
+            }
+        }
+    }
+}
+
+pub type Poseidon2SparseMerkleTree<D> = SparseMerkleTree<K, V, I, Poseidon2NodeHash, D>;
+
 pub type LayeredPoseidonSparseMerkleTree<D> = LayeredSparseMerkleTree<K, V, I, PoseidonNodeHash, D>;
 
 pub type LayeredLayeredPoseidonSparseMerkleTree<D> =
@@ -3,6 +3,7 @@ use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum ProcessMerkleProofRole {
     ProcessNoOp,   // [0, 0]
     ProcessUpdate, // [0, 1]
@@ -10,6 +11,13 @@ pub enum ProcessMerkleProofRole {
     ProcessDelete, // [1, 1]
 }
 
+impl ProcessMerkleProofRole {
+    /// Whether the key is set to a non-default value, either inserted or updated.
+    pub fn is_upsert(&self) -> bool {
+        matches!(self, Self::ProcessInsert | Self::ProcessUpdate)
+    }
+}
+
 impl From<[bool; 2]> for ProcessMerkleProofRole {
     fn from(value: [bool; 2]) -> Self {
         match value {
@@ -83,6 +91,7 @@ impl From<ProcessMerkleProofRole> for [u8; 2] {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SparseMerkleProcessProof<K, V, I> {
     pub old_root: I,
     pub old_key: K,
@@ -125,6 +134,7 @@ impl<K, V, I> SparseMerkleProcessProof<K, V, I> {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct SparseMerkleInclusionProof<K, V, I> {
     /// `root` is the value of the root node when given key is searched for.
     pub root: I,
@@ -1,9 +1,12 @@
 use std::{
+    collections::HashSet,
     fmt::Debug,
     hash::Hash,
     sync::{Arc, Mutex},
 };
 
+use crate::monitoring;
+
 use super::{
     node_data::{Node, NodeData},
     node_hash::NodeHash,
@@ -89,36 +92,44 @@ impl<K: KeyLike, V: ValueLike, I: HashLike, H: NodeHash<K, V, I>, D: NodeData<K,
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn update(
         &mut self,
         key: &K,
         new_value: &V,
     ) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        monitoring::increment_tree_operation("update");
         let result = update::<K, V, I, H, D>(&mut self.nodes_db, &self.root, key, *new_value)?;
         self.root = result.new_root;
 
         Ok(result)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn insert(
         &mut self,
         key: K,
         value: V,
     ) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        monitoring::increment_tree_operation("insert");
         let result = insert::<K, V, I, H, D>(&mut self.nodes_db, &self.root, key, value)?;
         self.root = result.new_root;
 
         Ok(result)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn remove(&mut self, key: &K) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        monitoring::increment_tree_operation("remove");
         let result = remove::<K, V, I, H, D>(&mut self.nodes_db, &self.root, key)?;
         self.root = result.new_root;
 
         Ok(result)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn set(&mut self, key: K, value: V) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        monitoring::increment_tree_operation("set");
         let result =
             calc_process_proof::<K, V, I, H, D>(&mut self.nodes_db, &self.root, key, value)?;
         self.root = result.new_root;
@@ -126,13 +137,113 @@ impl<K: KeyLike, V: ValueLike, I: HashLike, H: NodeHash<K, V, I>, D: NodeData<K,
         Ok(result)
     }
 
+    /// Inserts or updates `key` depending on whether it exists, so the proof role is
+    /// `ProcessInsert` or `ProcessUpdate`. Unlike `set`, the default value is rejected since it removes the key.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn upsert(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        anyhow::ensure!(
+            !V::default().eq(&value),
+            "fail to upsert the default value at the key {:?}",
+            key
+        );
+
+        self.set(key, value)
+    }
+
+    /// Sets `key` to `new_value` only if the current value is `expected_old_value`,
+    /// so that a value read before is not overwritten after another component updated it.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn set_if_equals(
+        &mut self,
+        key: K,
+        expected_old_value: V,
+        new_value: V,
+    ) -> anyhow::Result<SparseMerkleProcessProof<K, V, I>> {
+        let old_value = self.get(&key)?;
+        anyhow::ensure!(
+            old_value == expected_old_value,
+            "the value at the key {:?} is {:?}, but {:?} is expected",
+            key,
+            old_value,
+            expected_old_value
+        );
+
+        self.set(key, new_value)
+    }
+
+    /// Sets many keys at once without the process proofs, e.g. to apply the world state updates
+    /// of a block touching thousands of keys. The two children of a node are built in parallel,
+    /// and the nodes are written to `nodes_db` once. The root is the same as by `set` of each entry.
+    /// The keys must be distinct, and the values must not be the default one.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn set_batch(&mut self, entries: &[(K, V)]) -> anyhow::Result<()>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+        I: Send + Sync,
+        D: Send,
+    {
+        monitoring::increment_tree_operation("set_batch");
+        let mut keys = HashSet::new();
+        for (key, value) in entries {
+            anyhow::ensure!(
+                !V::default().eq(value),
+                "fail to set the default value at the key {:?} in a batch",
+                key
+            );
+            anyhow::ensure!(keys.insert(*key), "the key {:?} is duplicated", key);
+        }
+
+        let leaves = entries
+            .iter()
+            .map(|(key, value)| BatchLeaf {
+                key_bits: key.to_bits(),
+                key: *key,
+                value: *value,
+            })
+            .collect::<Vec<_>>();
+        let (new_root, changes) =
+            set_batch_rec::<K, V, I, H, D>(&self.nodes_db, self.root, leaves, 0)?;
+
+        {
+            let mut nodes_db = self
+                .nodes_db
+                .lock()
+                .map_err(|err| anyhow::anyhow!("mutex poison error: {}", err))?;
+            nodes_db
+                .multi_delete(&changes.delete_keys)
+                .map_err(|_| anyhow::anyhow!("fail to delete multiple entries"))?;
+            nodes_db
+                .multi_insert(changes.insert_entries)
+                .map_err(|_| anyhow::anyhow!("fail to insert multiple entries"))?;
+        }
+        self.root = new_root;
+
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn find(&self, key: &K) -> anyhow::Result<SparseMerkleInclusionProof<K, V, I>> {
+        monitoring::increment_tree_operation("find");
         calc_inclusion_proof::<K, V, I, H, D>(&self.nodes_db, &self.root, key)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
     pub fn get(&self, key: &K) -> anyhow::Result<V> {
+        monitoring::increment_tree_operation("get");
         get::<K, V, I, H, D>(&self.nodes_db, &self.root, key)
     }
+
+    /// All the leaves in the order of the key bits, e.g. to export the tree.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+    pub fn leaves(&self) -> anyhow::Result<Vec<(K, V)>> {
+        monitoring::increment_tree_operation("leaves");
+        get_leaves::<K, V, I, H, D>(&self.nodes_db, &self.root)
+    }
 }
 
 pub(crate) fn update<
@@ -578,6 +689,115 @@ pub fn calc_process_proof<
     }
 }
 
+/// The subtrees with fewer leaves of a batch are built on the current thread.
+const PARALLEL_SET_BATCH_THRESHOLD: usize = 64;
+
+struct BatchLeaf<K, V> {
+    key_bits: Vec<bool>,
+    key: K,
+    value: V,
+}
+
+struct BatchChanges<K, V, I> {
+    insert_entries: Vec<(I, Node<K, V, I>)>,
+    delete_keys: Vec<I>,
+}
+
+impl<K, V, I> Default for BatchChanges<K, V, I> {
+    fn default() -> Self {
+        Self {
+            insert_entries: vec![],
+            delete_keys: vec![],
+        }
+    }
+}
+
+/// Puts `leaves` into the subtree `root` at `level`, and returns the new root of the subtree.
+fn set_batch_rec<
+    K: KeyLike + Send + Sync,
+    V: ValueLike + Send + Sync,
+    I: HashLike + Send + Sync,
+    H: NodeHash<K, V, I>,
+    D: NodeData<K, V, I> + Send,
+>(
+    nodes_db: &Arc<Mutex<D>>,
+    root: I,
+    mut leaves: Vec<BatchLeaf<K, V>>,
+    level: usize,
+) -> anyhow::Result<(I, BatchChanges<K, V, I>)> {
+    if leaves.is_empty() {
+        return Ok((root, BatchChanges::default()));
+    }
+
+    let mut changes = BatchChanges::default();
+    let old_children = if I::default().eq(&root) {
+        None
+    } else {
+        let root_node = nodes_db
+            .lock()
+            .map_err(|err| anyhow::anyhow!("mutex poison error: {}", err))?
+            .get(&root)
+            .map_err(|err| anyhow::anyhow!("fail to fetch a node: {:?}", err))?
+            .ok_or_else(|| anyhow::anyhow!("searching node is not found"))?;
+        changes.delete_keys.push(root);
+        match root_node {
+            Node::Internal(left, right) => Some((left, right)),
+            Node::Leaf(key, value) => {
+                // The old leaf goes down with the new ones unless it is overwritten.
+                if leaves.iter().all(|leaf| leaf.key != key) {
+                    leaves.push(BatchLeaf {
+                        key_bits: key.to_bits(),
+                        key,
+                        value,
+                    });
+                }
+
+                None
+            }
+        }
+    };
+
+    let (left, right) = match old_children {
+        Some(children) => children,
+        None if leaves.len() == 1 => {
+            let leaf = leaves.pop().unwrap();
+            let new_node = Node::Leaf(leaf.key, leaf.value);
+            let new_root = H::calc_node_hash(new_node.clone());
+            changes.insert_entries.push((new_root, new_node));
+
+            return Ok((new_root, changes));
+        }
+        None => (I::default(), I::default()),
+    };
+
+    anyhow::ensure!(
+        leaves.iter().all(|leaf| level < leaf.key_bits.len()),
+        "the keys cannot be separated"
+    );
+    let n_leaves = leaves.len();
+    let (left_leaves, right_leaves): (Vec<_>, Vec<_>) =
+        leaves.into_iter().partition(|leaf| !leaf.key_bits[level]);
+    let build_left = || set_batch_rec::<K, V, I, H, D>(nodes_db, left, left_leaves, level + 1);
+    let build_right = || set_batch_rec::<K, V, I, H, D>(nodes_db, right, right_leaves, level + 1);
+    let (left_result, right_result) = if n_leaves < PARALLEL_SET_BATCH_THRESHOLD {
+        (build_left(), build_right())
+    } else {
+        rayon::join(build_left, build_right)
+    };
+    let (new_left, left_changes) = left_result?;
+    let (new_right, right_changes) = right_result?;
+    for child_changes in [left_changes, right_changes] {
+        changes.insert_entries.extend(child_changes.insert_entries);
+        changes.delete_keys.extend(child_changes.delete_keys);
+    }
+
+    let new_node = Node::Internal(new_left, new_right);
+    let new_root = H::calc_node_hash(new_node.clone());
+    changes.insert_entries.push((new_root, new_node));
+
+    Ok((new_root, changes))
+}
+
 pub(crate) fn find<
     K: KeyLike,
     V: ValueLike,
@@ -697,3 +917,130 @@ pub fn get<K: KeyLike, V: ValueLike, I: HashLike, H: NodeHash<K, V, I>, D: NodeD
         Ok(V::default())
     }
 }
+
+pub fn get_leaves<
+    K: KeyLike,
+    V: ValueLike,
+    I: HashLike,
+    H: NodeHash<K, V, I>,
+    D: NodeData<K, V, I>,
+>(
+    nodes_db: &Arc<Mutex<D>>,
+    root: &I,
+) -> anyhow::Result<Vec<(K, V)>> {
+    let nodes_db = nodes_db
+        .lock()
+        .map_err(|err| anyhow::anyhow!("mutex poison error: {}", err))?;
+    let mut leaves = vec![];
+    let mut pending = vec![*root];
+    while let Some(node_hash) = pending.pop() {
+        if I::default().eq(&node_hash) {
+            continue;
+        }
+
+        let node = nodes_db
+            .get(&node_hash)
+            .map_err(|err| anyhow::anyhow!("fail to fetch a node: {:?}", err))?;
+        match node {
+            Some(Node::Internal(left, right)) => {
+                pending.push(right);
+                pending.push(left);
+            }
+            Some(Node::Leaf(key, value)) => leaves.push((key, value)),
+            None => return Err(anyhow::anyhow!("searching node is not found")),
+        }
+    }
+
+    Ok(leaves)
+}
+
+#[test]
+fn test_upsert() {
+    use super::goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree};
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let key = GoldilocksHashOut::from_u32(1);
+    let proof = tree.upsert(key, GoldilocksHashOut::from_u32(2)).unwrap();
+    assert_eq!(proof.fnc, ProcessMerkleProofRole::ProcessInsert);
+    assert!(proof.fnc.is_upsert());
+    let proof = tree.upsert(key, GoldilocksHashOut::from_u32(3)).unwrap();
+    assert_eq!(proof.fnc, ProcessMerkleProofRole::ProcessUpdate);
+    assert_eq!(tree.get(&key).unwrap(), GoldilocksHashOut::from_u32(3));
+
+    // The default value would remove the key.
+    assert!(tree.upsert(key, Default::default()).is_err());
+    assert!(!tree.set(key, Default::default()).unwrap().fnc.is_upsert());
+}
+
+#[test]
+fn test_set_if_equals() {
+    use super::goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree};
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let key = GoldilocksHashOut::from_u32(1);
+    tree.set_if_equals(key, Default::default(), GoldilocksHashOut::from_u32(2))
+        .unwrap();
+
+    // Another component updated the key after it was read.
+    let read_value = tree.get(&key).unwrap();
+    tree.set(key, GoldilocksHashOut::from_u32(3)).unwrap();
+    let root = tree.get_root();
+    assert!(tree
+        .set_if_equals(key, read_value, GoldilocksHashOut::from_u32(4))
+        .is_err());
+    assert_eq!(tree.get_root(), root);
+
+    let proof = tree
+        .set_if_equals(
+            key,
+            GoldilocksHashOut::from_u32(3),
+            GoldilocksHashOut::from_u32(4),
+        )
+        .unwrap();
+    assert_eq!(proof.old_value, GoldilocksHashOut::from_u32(3));
+    assert_eq!(tree.get(&key).unwrap(), GoldilocksHashOut::from_u32(4));
+}
+
+#[test]
+fn test_set_batch() {
+    use super::goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree};
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let mut expected_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    for i in 0..10 {
+        let (key, value) = (GoldilocksHashOut::rand(), GoldilocksHashOut::from_u128(i));
+        tree.set(key, value).unwrap();
+        expected_tree.set(key, value).unwrap();
+    }
+
+    // Updates some of the keys and inserts more than `PARALLEL_SET_BATCH_THRESHOLD` keys.
+    let mut entries = tree
+        .leaves()
+        .unwrap()
+        .into_iter()
+        .take(5)
+        .map(|(key, _)| (key, GoldilocksHashOut::rand()))
+        .collect::<Vec<_>>();
+    for i in 0..100 {
+        entries.push((
+            GoldilocksHashOut::rand(),
+            GoldilocksHashOut::from_u128(i + 1),
+        ));
+    }
+    tree.set_batch(&entries).unwrap();
+    for (key, value) in entries.iter() {
+        expected_tree.set(*key, *value).unwrap();
+    }
+    assert_eq!(tree.get_root(), expected_tree.get_root());
+    for (key, value) in entries.iter() {
+        assert_eq!(tree.get(key).unwrap(), *value);
+    }
+    tree.set(entries[0].0, entries[1].1).unwrap();
+    expected_tree.set(entries[0].0, entries[1].1).unwrap();
+    assert_eq!(tree.get_root(), expected_tree.get_root());
+
+    assert!(tree.set_batch(&[entries[0], entries[0]]).is_err());
+    assert!(tree
+        .set_batch(&[(entries[0].0, GoldilocksHashOut::default())])
+        .is_err());
+}
@@ -0,0 +1,196 @@
+//! proptest strategies and invariant checkers, enabled by the `testing` feature,
+//! so that downstream crates can fuzz their integrations against the same invariants.
+
+use std::collections::HashMap;
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::hash_types::HashOut,
+};
+use proptest::{collection::vec, prelude::*};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof,
+        goldilocks_poseidon::{
+            GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree, Wrapper,
+        },
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::asset::{Asset, TokenKind},
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+pub fn field_element() -> impl Strategy<Value = F> {
+    any::<u64>().prop_map(F::from_noncanonical_u64)
+}
+
+pub fn hash_out() -> impl Strategy<Value = GoldilocksHashOut> {
+    prop::array::uniform4(field_element()).prop_map(|elements| Wrapper(HashOut { elements }))
+}
+
+/// Keys are often chosen from a small set, so that updates and removals also happen.
+pub fn key() -> impl Strategy<Value = GoldilocksHashOut> {
+    prop_oneof![(0u32..16).prop_map(GoldilocksHashOut::from_u32), hash_out()]
+}
+
+/// A non-zero value. The zero value means the absence of a leaf.
+pub fn value() -> impl Strategy<Value = GoldilocksHashOut> {
+    hash_out().prop_filter("value must be non-zero", |value| {
+        *value != GoldilocksHashOut::ZERO
+    })
+}
+
+/// Pairs of a key and a value to set. A zero value removes the key.
+pub fn tree_operations(
+    max_len: usize,
+) -> impl Strategy<Value = Vec<(GoldilocksHashOut, GoldilocksHashOut)>> {
+    let value_or_zero = prop_oneof![1 => Just(GoldilocksHashOut::ZERO), 3 => value()];
+
+    vec((key(), value_or_zero), 0..=max_len)
+}
+
+/// The process proofs obtained by applying `tree_operations` to an empty tree.
+pub fn process_proofs(max_len: usize) -> impl Strategy<Value = Vec<SmtProcessProof<F>>> {
+    tree_operations(max_len).prop_map(|operations| {
+        let mut tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+            PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+
+        operations
+            .into_iter()
+            .map(|(key, value)| tree.set(key, value).unwrap())
+            .collect()
+    })
+}
+
+pub fn address() -> impl Strategy<Value = Address<F>> {
+    hash_out().prop_map(|value| Address(value.0))
+}
+
+/// Contract addresses and variable indices are chosen from a small set,
+/// so that the same kind of tokens appear many times.
+pub fn token_kind() -> impl Strategy<Value = TokenKind<F>> {
+    (0u32..4, 0u32..4).prop_map(|(contract_address, variable_index)| TokenKind {
+        contract_address: Address(GoldilocksHashOut::from_u32(contract_address).0),
+        variable_index: GoldilocksHashOut::from_u32(variable_index),
+    })
+}
+
+pub fn asset() -> impl Strategy<Value = Asset<F>> {
+    (token_kind(), 1..=(u32::MAX as u64)).prop_map(|(kind, amount)| Asset { kind, amount })
+}
+
+/// A transaction as the pairs of a recipient and the sent asset.
+pub fn transaction(max_len: usize) -> impl Strategy<Value = Vec<(Address<F>, Asset<F>)>> {
+    vec((address(), asset()), 1..=max_len)
+}
+
+/// Checks that each process proof starts from the root of the previous one,
+/// and that the last root is `final_root`.
+pub fn check_root_consistency(
+    initial_root: GoldilocksHashOut,
+    proofs: &[SmtProcessProof<F>],
+    final_root: GoldilocksHashOut,
+) -> anyhow::Result<()> {
+    let mut root = initial_root;
+    for (i, proof) in proofs.iter().enumerate() {
+        anyhow::ensure!(
+            proof.old_root == root,
+            "the old root of the process proof {} is not the previous root",
+            i
+        );
+        if proof.fnc == ProcessMerkleProofRole::ProcessNoOp {
+            anyhow::ensure!(
+                proof.new_root == proof.old_root,
+                "the root of the no-op process proof {} changed",
+                i
+            );
+        }
+        root = proof.new_root;
+    }
+    anyhow::ensure!(root == final_root, "the last root is not the final root");
+
+    Ok(())
+}
+
+/// Checks that the total amount of each kind of tokens is the same in `inputs` and `outputs`.
+pub fn check_conservation(inputs: &[Asset<F>], outputs: &[Asset<F>]) -> anyhow::Result<()> {
+    let total = |assets: &[Asset<F>]| {
+        let mut total: HashMap<TokenKind<F>, u128> = HashMap::new();
+        for asset in assets {
+            *total.entry(asset.kind).or_default() += asset.amount as u128;
+        }
+        total.retain(|_, amount| *amount != 0);
+
+        total
+    };
+
+    let total_inputs = total(inputs);
+    let total_outputs = total(outputs);
+    for (kind, amount) in total_inputs.iter() {
+        let output_amount = total_outputs.get(kind).copied().unwrap_or_default();
+        anyhow::ensure!(
+            *amount == output_amount,
+            "the amount of {:?} is not conserved: {} in, {} out",
+            kind,
+            amount,
+            output_amount
+        );
+    }
+    anyhow::ensure!(
+        total_outputs
+            .keys()
+            .all(|kind| total_inputs.contains_key(kind)),
+        "the outputs contain tokens which are not in the inputs"
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn test_tree_operations_are_consistent(operations in tree_operations(16)) {
+        let mut tree: PoseidonSparseMerkleTree<NodeDataMemory> =
+            PoseidonSparseMerkleTree::new(Default::default(), Default::default());
+        let mut model = HashMap::new();
+        let mut proofs = vec![];
+        for (key, value) in operations {
+            proofs.push(tree.set(key, value).unwrap());
+            if value == GoldilocksHashOut::ZERO {
+                model.remove(&key);
+            } else {
+                model.insert(key, value);
+            }
+        }
+
+        check_root_consistency(GoldilocksHashOut::ZERO, &proofs, tree.get_root()).unwrap();
+        for (key, value) in model {
+            prop_assert_eq!(tree.get(&key).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_split_assets_are_conserved(transaction in transaction(8)) {
+        let inputs = transaction.iter().map(|(_, asset)| *asset).collect::<Vec<_>>();
+        let outputs = inputs
+            .iter()
+            .flat_map(|asset| {
+                let half = asset.amount / 2;
+                [
+                    Asset { kind: asset.kind, amount: half },
+                    Asset { kind: asset.kind, amount: asset.amount - half },
+                ]
+            })
+            .collect::<Vec<_>>();
+        check_conservation(&inputs, &outputs).unwrap();
+
+        let mut outputs = outputs;
+        outputs[0].amount += 1;
+        prop_assert!(check_conservation(&inputs, &outputs).is_err());
+    }
+}
@@ -6,7 +6,10 @@ use crate::{
     sparse_merkle_tree::{
         gadgets::verify::verify_smt::SmtInclusionProof, goldilocks_poseidon::WrappedHashOut,
     },
-    transaction::block_header::BlockHeader,
+    transaction::{
+        block_header::{get_block_hash, BlockHeader},
+        merge_key::{deposit_merge_key, transfer_merge_key},
+    },
     zkdsa::account::Address,
 };
 
@@ -63,3 +66,16 @@ pub struct ReceivedAssetProof<F: RichField> {
     pub assets: Vec<Asset<F>>,
     pub nonce: WrappedHashOut<F>,
 }
+
+impl<F: RichField> ReceivedAssetProof<F> {
+    /// The key of the user asset tree under which the received assets are merged.
+    pub fn merge_key(&self) -> WrappedHashOut<F> {
+        let (block_header, tx_inclusion_proof, _) = &self.diff_tree_inclusion_proof;
+        let tx_hash = *tx_inclusion_proof.value;
+        if self.is_deposit {
+            deposit_merge_key(tx_hash, get_block_hash(block_header))
+        } else {
+            transfer_merge_key(tx_hash)
+        }
+    }
+}
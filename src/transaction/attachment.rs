@@ -0,0 +1,274 @@
+//! Proof-carrying user transactions.
+//!
+//! A user transaction can carry a proof of an external circuit, e.g. an identity attestation,
+//! a KYC credential or the result of a game. The attachment digest commits to the circuit and
+//! the public inputs of the proof, and the sender folds it into the nonce with
+//! `get_attached_nonce`, so that the transaction hash, which is the leaf of the diff tree of the
+//! block, commits to the attachment. The attached transaction circuit verifies both the user
+//! transaction proof and the attached proof, and exposes the attachment digest next to the
+//! transaction hash, so that an application can gate a transfer on the proven statement.
+//! Transactions without an attachment use a random nonce as before.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData, CommonCircuitData, VerifierOnlyCircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::IntmaxError,
+    recursion::gadgets::{get_verifier_digest, RecursiveProofTarget},
+    transaction::{
+        circuits::parse_merge_and_purge_public_inputs,
+        gadgets::attachment::{get_attached_nonce, get_attachment_digest, AttachmentTarget},
+        tx_hash::{get_tx_hash, get_tx_hash_target},
+    },
+    zkdsa::account::Address,
+};
+
+/// `sender_address`, `tx_hash` and `attachment_digest`.
+pub const ATTACHED_TRANSACTION_PUBLIC_INPUTS_LEN: usize = 12;
+
+#[derive(Clone)]
+pub struct Attachment<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+
+    /// The proof of the circuit of `verifier_only`.
+    pub proof: ProofWithPublicInputs<F, C, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> Attachment<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn digest(&self) -> HashOut<F> {
+        get_attachment_digest(
+            get_verifier_digest(&self.verifier_only),
+            &self.proof.public_inputs,
+        )
+    }
+
+    /// The nonce of the user transaction carrying this attachment.
+    pub fn nonce(&self, salt: HashOut<F>) -> HashOut<F> {
+        get_attached_nonce(salt, self.digest())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttachedTransactionPublicInputs<F: RichField> {
+    pub sender_address: Address<F>,
+    pub tx_hash: HashOut<F>,
+    pub attachment_digest: HashOut<F>,
+}
+
+impl<F: RichField> AttachedTransactionPublicInputs<F> {
+    pub fn encode(&self) -> Vec<F> {
+        [
+            self.sender_address.0.elements,
+            self.tx_hash.elements,
+            self.attachment_digest.elements,
+        ]
+        .concat()
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != ATTACHED_TRANSACTION_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: ATTACHED_TRANSACTION_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self {
+            sender_address: Address(HashOut::from_partial(&public_inputs[0..4])),
+            tx_hash: HashOut::from_partial(&public_inputs[4..8]),
+            attachment_digest: HashOut::from_partial(&public_inputs[8..12]),
+        })
+    }
+
+    /// Whether the attachment is a proof of the circuit with `verifier_digest` and `public_inputs`,
+    /// e.g. the credential an application requires.
+    pub fn is_attached(&self, verifier_digest: HashOut<F>, public_inputs: &[F]) -> bool {
+        self.attachment_digest == get_attachment_digest(verifier_digest, public_inputs)
+    }
+}
+
+pub struct AttachedTransactionCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub user_tx_proof: RecursiveProofTarget<D>,
+    pub attachment: AttachmentTarget<D>,
+    pub sender_address: HashOutTarget,
+    pub tx_hash: HashOutTarget,
+}
+
+/// Every attached circuit must have `attachment_common_data`.
+pub fn make_attached_transaction_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+>(
+    user_tx_circuit_data: &CircuitData<F, C, D>,
+    attachment_common_data: &CommonCircuitData<F, D>,
+) -> AttachedTransactionCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let user_tx_proof = RecursiveProofTarget::add_virtual_to(&mut builder, user_tx_circuit_data);
+    let constant_true = builder._true();
+    builder.connect(user_tx_proof.enabled.target, constant_true.target);
+    let user_tx_public_inputs =
+        parse_merge_and_purge_public_inputs(&user_tx_proof.inner.public_inputs);
+
+    let attachment = AttachmentTarget::add_virtual_to::<F, C>(&mut builder, attachment_common_data);
+
+    // tx_hash は attachment を含む nonce から計算されたものでなければならない.
+    let tx_hash = get_tx_hash_target::<F, C::Hasher, D>(
+        &mut builder,
+        user_tx_public_inputs.diff_root,
+        attachment.nonce,
+    );
+    builder.connect_hashes(tx_hash, user_tx_public_inputs.tx_hash);
+
+    let sender_address = user_tx_public_inputs.sender_address;
+    builder.register_public_inputs(&sender_address.elements); // public_inputs[0..4]
+    builder.register_public_inputs(&tx_hash.elements); // public_inputs[4..8]
+    builder.register_public_inputs(&attachment.attachment_digest.elements); // public_inputs[8..12]
+    let data = builder.build::<C>();
+
+    AttachedTransactionCircuit {
+        data,
+        user_tx_proof,
+        attachment,
+        sender_address,
+        tx_hash,
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    AttachedTransactionCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    /// `user_tx_proof` must use `attachment.nonce(salt)` as the nonce.
+    pub fn prove(
+        &self,
+        user_tx_proof: &ProofWithPublicInputs<F, C, D>,
+        attachment: &Attachment<F, C, D>,
+        salt: HashOut<F>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.user_tx_proof.set_witness(&mut pw, user_tx_proof, true);
+        self.attachment
+            .set_witness(&mut pw, &attachment.proof, &attachment.verifier_only, salt);
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<AttachedTransactionPublicInputs<F>> {
+        let public_inputs = AttachedTransactionPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_attached_transaction_circuit() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        hash::poseidon::PoseidonHash,
+        iop::witness::Witness,
+        plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::{
+        sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+        transaction::circuits::{
+            MergeAndPurgeTransitionPublicInputs, MERGE_AND_PURGE_PUBLIC_INPUTS_LEN,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    // A stand-in for the user transaction circuit with the same public inputs.
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let public_inputs_t: Vec<_> = (0..MERGE_AND_PURGE_PUBLIC_INPUTS_LEN)
+        .map(|_| builder.add_virtual_target())
+        .collect();
+    builder.register_public_inputs(&public_inputs_t);
+    let user_tx_circuit_data = builder.build::<C>();
+    let prove_user_tx = |nonce: HashOut<F>| {
+        let diff_root = HashOut::rand();
+        let public_inputs = MergeAndPurgeTransitionPublicInputs {
+            sender_address: Address::rand(),
+            diff_root: diff_root.into(),
+            tx_hash: get_tx_hash(diff_root, nonce).into(),
+            ..Default::default()
+        };
+        let mut pw = PartialWitness::new();
+        for (target, value) in public_inputs_t.iter().zip(public_inputs.encode()) {
+            pw.set_target(*target, value);
+        }
+
+        (user_tx_circuit_data.prove(pw).unwrap(), public_inputs)
+    };
+
+    // A credential circuit proving the knowledge of a preimage of the public key.
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let secret = builder.add_virtual_hash();
+    let public_key = builder.hash_n_to_hash_no_pad::<PoseidonHash>(secret.elements.to_vec());
+    builder.register_public_inputs(&public_key.elements);
+    let credential_data = builder.build::<C>();
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(secret, HashOut::rand());
+    let attachment = Attachment {
+        verifier_only: credential_data.verifier_only.clone(),
+        proof: credential_data.prove(pw).unwrap(),
+    };
+
+    let salt = HashOut::rand();
+    let (user_tx_proof, user_tx_public_inputs) = prove_user_tx(attachment.nonce(salt));
+
+    let circuit = make_attached_transaction_circuit(&user_tx_circuit_data, &credential_data.common);
+    let proof = circuit.prove(&user_tx_proof, &attachment, salt).unwrap();
+    let public_inputs = circuit.verify(proof).unwrap();
+    assert_eq!(
+        public_inputs.sender_address,
+        user_tx_public_inputs.sender_address
+    );
+    assert_eq!(
+        WrappedHashOut::from(public_inputs.tx_hash),
+        user_tx_public_inputs.tx_hash
+    );
+    assert!(public_inputs.is_attached(
+        get_verifier_digest(&credential_data.verifier_only),
+        &attachment.proof.public_inputs
+    ));
+    assert!(!public_inputs.is_attached(
+        get_verifier_digest(&credential_data.verifier_only),
+        &HashOut::<F>::rand().elements
+    ));
+    assert_eq!(
+        AttachedTransactionPublicInputs::decode(&public_inputs.encode()).unwrap(),
+        public_inputs
+    );
+}
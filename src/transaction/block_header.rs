@@ -1,13 +1,4 @@
-use std::hash::Hash;
-
-use plonky2::{
-    field::types::Field,
-    hash::{
-        hash_types::{HashOut, RichField},
-        poseidon::PoseidonHash,
-    },
-    plonk::config::Hasher,
-};
+use plonky2::hash::hash_types::{HashOut, RichField};
 use serde::{Deserialize, Serialize};
 use serde_hex::{SerHex, StrictPfx};
 
@@ -16,16 +7,9 @@ use crate::{
     sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
 };
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-pub struct BlockHeader<F: Field> {
-    pub block_number: u32,
-    pub prev_block_header_digest: HashOut<F>, // block header tree root
-    pub transactions_digest: HashOut<F>,      // state diff tree root
-    pub deposit_digest: HashOut<F>,           // deposit tree root
-    pub proposed_world_state_digest: HashOut<F>,
-    pub approved_world_state_digest: HashOut<F>,
-    pub latest_account_digest: HashOut<F>, // latest account tree
-}
+pub use crate::verification::block_header::{
+    get_block_hash, BlockHeader, BLOCK_HEADER_BYTES_LEN, N_LOG_MAX_TIMESTAMP,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
@@ -38,6 +22,11 @@ pub struct SerializableBlockHeader<F: RichField> {
     pub proposed_world_state_digest: WrappedHashOut<F>,
     pub approved_world_state_digest: WrappedHashOut<F>,
     pub latest_account_digest: WrappedHashOut<F>,
+    pub forced_transactions_digest: WrappedHashOut<F>,
+    pub issuer_registry_root: WrappedHashOut<F>,
+    #[serde(with = "SerHex::<StrictPfx>")]
+    pub timestamp: u64,
+    pub aggregator_address: WrappedHashOut<F>,
 }
 
 impl<F: RichField> From<SerializableBlockHeader<F>> for BlockHeader<F> {
@@ -50,6 +39,10 @@ impl<F: RichField> From<SerializableBlockHeader<F>> for BlockHeader<F> {
             proposed_world_state_digest: *value.proposed_world_state_digest,
             approved_world_state_digest: *value.approved_world_state_digest,
             latest_account_digest: *value.latest_account_digest,
+            forced_transactions_digest: *value.forced_transactions_digest,
+            issuer_registry_root: *value.issuer_registry_root,
+            timestamp: value.timestamp,
+            aggregator_address: *value.aggregator_address,
         }
     }
 }
@@ -72,6 +65,10 @@ impl<F: RichField> From<BlockHeader<F>> for SerializableBlockHeader<F> {
             proposed_world_state_digest: value.proposed_world_state_digest.into(),
             approved_world_state_digest: value.approved_world_state_digest.into(),
             latest_account_digest: value.latest_account_digest.into(),
+            forced_transactions_digest: value.forced_transactions_digest.into(),
+            issuer_registry_root: value.issuer_registry_root.into(),
+            timestamp: value.timestamp,
+            aggregator_address: value.aggregator_address.into(),
         }
     }
 }
@@ -97,29 +94,14 @@ impl<F: RichField> BlockHeader<F> {
             proposed_world_state_digest: default_hash,
             approved_world_state_digest: default_hash,
             latest_account_digest: default_hash,
+            forced_transactions_digest: default_hash,
+            issuer_registry_root: default_hash,
+            timestamp: 0,
+            aggregator_address: default_hash,
         }
     }
 }
 
-pub fn get_block_hash<F: RichField>(block_header: &BlockHeader<F>) -> HashOut<F> {
-    let a = PoseidonHash::two_to_one(
-        HashOut::from_partial(&[F::from_canonical_u32(block_header.block_number)]),
-        block_header.latest_account_digest,
-    );
-    let b = PoseidonHash::two_to_one(
-        block_header.deposit_digest,
-        block_header.transactions_digest,
-    );
-    let c = PoseidonHash::two_to_one(a, b);
-    let d = PoseidonHash::two_to_one(
-        block_header.proposed_world_state_digest,
-        block_header.approved_world_state_digest,
-    );
-    let e = PoseidonHash::two_to_one(c, d);
-
-    PoseidonHash::two_to_one(block_header.prev_block_header_digest, e)
-}
-
 pub fn get_block_header_tree_proof<F: RichField>(
     block_hashes: &[WrappedHashOut<F>],
     new_block_hash: WrappedHashOut<F>,
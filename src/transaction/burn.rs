@@ -0,0 +1,296 @@
+//! Burning of assets, e.g. to reduce the supply or to bridge them out of the rollup.
+//!
+//! A burn is a leaf of the diff tree addressed to `burn_address`. Nobody knows the private key of it,
+//! so a transaction sent from it is never approved and the burned assets can never be merged.
+//! The purge circuit commits the burned `(contract_address, variable_index, amount)` of the transaction
+//! to `burned_assets_digest`, and the block circuit chains the digests of the approved transactions,
+//! so that L1 can check each burned asset of the block against the published burns.
+//! The burns are withdrawn to the senders with the withdrawal root of the block (see `rollup::withdrawal`).
+
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::target::BoolTarget,
+    plonk::{circuit_builder::CircuitBuilder, config::Hasher},
+};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::{
+            common::{conditionally_select, is_equal_hash_out},
+            process::{process_smt::SmtProcessProof, utils::get_process_merkle_proof_role},
+        },
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::{
+        asset::{Asset, TokenKind},
+        gadgets::purge::DynLayeredLayeredProcessProofTarget,
+    },
+    zkdsa::account::Address,
+};
+
+/// `[1, 0, 0, 0]`, which is not a public key `hash(private_key, private_key)` of any known private key.
+pub fn burn_address<F: Field>() -> Address<F> {
+    Address(HashOut {
+        elements: [F::ONE, F::ZERO, F::ZERO, F::ZERO],
+    })
+}
+
+pub fn is_burn_address<F: Field>(address: &Address<F>) -> bool {
+    *address == burn_address()
+}
+
+/// The assets sent to `burn_address` in the diff tree of `output_witness`, in the order of the slots.
+/// A no-op slot burns nothing even if its key is `burn_address`.
+pub fn get_burned_assets<F: RichField>(
+    output_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+) -> Vec<Asset<F>> {
+    output_witness
+        .iter()
+        .filter(|(w0, _, w2)| {
+            (w0.fnc == ProcessMerkleProofRole::ProcessInsert
+                || w0.fnc == ProcessMerkleProofRole::ProcessUpdate)
+                && w2.fnc == ProcessMerkleProofRole::ProcessInsert
+                && is_burn_address(&Address(*w0.new_key))
+        })
+        .map(|(_, w1, w2)| Asset {
+            kind: TokenKind {
+                contract_address: Address(*w1.new_key),
+                variable_index: w2.new_key,
+            },
+            amount: w2.new_value.elements[0].to_canonical_u64(),
+        })
+        .collect()
+}
+
+/// The hash chain `digest = hash(digest, contract_address, variable_index, amount)` over `burned_assets`
+/// starting from zero, which is zero if nothing is burned.
+pub fn get_burned_assets_digest<F: RichField>(burned_assets: &[Asset<F>]) -> HashOut<F> {
+    burned_assets.iter().fold(HashOut::ZERO, |digest, asset| {
+        let inputs = [
+            digest.elements.to_vec(),
+            asset.kind.contract_address.0.elements.to_vec(),
+            asset.kind.variable_index.elements.to_vec(),
+            vec![F::from_canonical_u64(asset.amount)],
+        ]
+        .concat();
+
+        PoseidonHash::hash_no_pad(&inputs)
+    })
+}
+
+/// The hash chain `digest = hash(digest, tx_digest)` over the `burned_assets_digest` of the approved
+/// transactions of a block in the order of the slots, skipping the transactions which burn nothing.
+pub fn get_block_burned_assets_digest<F: RichField>(tx_digests: &[HashOut<F>]) -> HashOut<F> {
+    tx_digests
+        .iter()
+        .filter(|tx_digest| **tx_digest != HashOut::ZERO)
+        .fold(HashOut::ZERO, |digest, tx_digest| {
+            PoseidonHash::hash_no_pad(&[digest.elements, tx_digest.elements].concat())
+        })
+}
+
+/// The circuit counterpart of `get_burned_assets_digest(&get_burned_assets(..))`.
+/// The amounts must be range-checked by the caller.
+pub fn get_burned_assets_digest_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    output_proofs_t: &[DynLayeredLayeredProcessProofTarget],
+) -> HashOutTarget {
+    let burn_address_t = builder.constant_hash(burn_address::<F>().0);
+    let mut digest = builder.constant_hash(HashOut::ZERO);
+    for (proof0_t, proof1_t, proof2_t) in output_proofs_t {
+        let is_burn_key = is_equal_hash_out(builder, proof0_t.new_key, burn_address_t);
+        // The key of a no-op slot is free, so only an inserted leaf is a burn.
+        let is_upsert = get_process_merkle_proof_role(builder, proof0_t.fnc).is_insert_or_update_op;
+        let is_insert = get_process_merkle_proof_role(builder, proof2_t.fnc).is_insert_op;
+        let is_burn = builder.and(is_burn_key, is_upsert);
+        let is_burn = builder.and(is_burn, is_insert);
+
+        let inputs = [
+            digest.elements.to_vec(),
+            proof1_t.new_key.elements.to_vec(),
+            proof2_t.new_key.elements.to_vec(),
+            vec![proof2_t.new_value.elements[0]],
+        ]
+        .concat();
+        let new_digest = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        digest = conditionally_select(builder, new_digest, digest, is_burn);
+    }
+
+    digest
+}
+
+/// The circuit counterpart of `get_block_burned_assets_digest`,
+/// where `tx_digests` are paired with whether the transaction is approved.
+pub fn get_block_burned_assets_digest_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    tx_digests: &[(HashOutTarget, BoolTarget)],
+) -> HashOutTarget {
+    let zero = builder.constant_hash(HashOut::ZERO);
+    let mut digest = zero;
+    for (tx_digest, is_approved) in tx_digests {
+        let burns_nothing = is_equal_hash_out(builder, *tx_digest, zero);
+        let burns_nothing = builder.not(burns_nothing);
+        let is_included = builder.and(*is_approved, burns_nothing);
+        let new_digest = builder
+            .hash_n_to_hash_no_pad::<PoseidonHash>([digest.elements, tx_digest.elements].concat());
+        digest = conditionally_select(builder, new_digest, digest, is_included);
+    }
+
+    digest
+}
+
+#[test]
+fn test_burned_assets_digest() {
+    use plonky2::{
+        iop::witness::{PartialWitness, Witness},
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::sparse_merkle_tree::{
+        gadgets::process::process_smt::DynSparseMerkleProcessProofTarget,
+        goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+
+    let mut tx_diff_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let contract_address = GoldilocksHashOut::rand();
+    let variable_index = GoldilocksHashOut::from_u32(1);
+    let mut output_witness = vec![
+        tx_diff_tree
+            .set(
+                burn_address::<F>().0.into(),
+                contract_address,
+                variable_index,
+                GoldilocksHashOut::from_u32(30),
+            )
+            .unwrap(),
+        tx_diff_tree
+            .set(
+                GoldilocksHashOut::from_u32(2),
+                contract_address,
+                variable_index,
+                GoldilocksHashOut::from_u32(12),
+            )
+            .unwrap(),
+        tx_diff_tree
+            .set(
+                burn_address::<F>().0.into(),
+                contract_address,
+                GoldilocksHashOut::from_u32(2),
+                GoldilocksHashOut::from_u32(5),
+            )
+            .unwrap(),
+    ];
+
+    // A no-op slot keyed to the burn address, which claims to burn 100.
+    let diff_root = tx_diff_tree.get_root();
+    let mut no_op_witness = (
+        SmtProcessProof::with_root(diff_root),
+        SmtProcessProof::with_root(Default::default()),
+        SmtProcessProof::with_root(Default::default()),
+    );
+    no_op_witness.0.old_key = burn_address::<F>().0.into();
+    no_op_witness.0.new_key = burn_address::<F>().0.into();
+    no_op_witness.1.old_key = contract_address;
+    no_op_witness.1.new_key = contract_address;
+    no_op_witness.2.old_value = GoldilocksHashOut::from_u32(100);
+    no_op_witness.2.new_value = GoldilocksHashOut::from_u32(100);
+    output_witness.push(no_op_witness);
+
+    let burned_assets = get_burned_assets(&output_witness);
+    assert_eq!(
+        burned_assets,
+        vec![
+            Asset {
+                kind: TokenKind {
+                    contract_address: Address(*contract_address),
+                    variable_index,
+                },
+                amount: 30,
+            },
+            Asset {
+                kind: TokenKind {
+                    contract_address: Address(*contract_address),
+                    variable_index: GoldilocksHashOut::from_u32(2),
+                },
+                amount: 5,
+            },
+        ]
+    );
+    let digest = get_burned_assets_digest(&burned_assets);
+    assert_ne!(digest, get_burned_assets_digest(&burned_assets[..1]));
+    assert_eq!(get_burned_assets_digest::<F>(&[]), HashOut::ZERO);
+    assert_eq!(get_burned_assets::<F>(&output_witness[1..2]), vec![]);
+    assert!(!is_burn_address(&Address::<F>::default()));
+
+    // A transaction which burns nothing is skipped in the block.
+    assert_eq!(
+        get_block_burned_assets_digest(&[HashOut::ZERO, digest, HashOut::ZERO]),
+        get_block_burned_assets_digest(&[digest])
+    );
+    assert_ne!(get_block_burned_assets_digest(&[digest]), digest);
+
+    // The circuit commits the same assets.
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let output_proofs_t = (0..output_witness.len())
+        .map(|_| {
+            (
+                DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(&mut builder, 16),
+                DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(&mut builder, 16),
+                DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(&mut builder, 16),
+            )
+        })
+        .collect::<Vec<_>>();
+    let digest_t = get_burned_assets_digest_target(&mut builder, &output_proofs_t);
+    builder.register_public_inputs(&digest_t.elements);
+    let is_approved_t = builder.add_virtual_bool_target_safe();
+    let block_digest_t = get_block_burned_assets_digest_target(
+        &mut builder,
+        &[(digest_t, is_approved_t), (digest_t, is_approved_t)],
+    );
+    builder.register_public_inputs(&block_digest_t.elements);
+    let data = builder.build::<C>();
+
+    let prove = |is_approved: bool| {
+        let mut pw = PartialWitness::new();
+        for ((p0_t, p1_t, p2_t), (w0, w1, w2)) in output_proofs_t.iter().zip(output_witness.iter())
+        {
+            p0_t.set_witness(&mut pw, w0);
+            p1_t.set_witness(&mut pw, w1);
+            p2_t.set_witness(&mut pw, w2);
+        }
+        pw.set_bool_target(is_approved_t, is_approved);
+
+        data.prove(pw).unwrap()
+    };
+
+    let proof = prove(true);
+    assert_eq!(
+        proof.public_inputs,
+        [
+            digest.elements,
+            get_block_burned_assets_digest(&[digest, digest]).elements
+        ]
+        .concat()
+    );
+
+    // The burns of the transactions not approved are not included.
+    let proof = prove(false);
+    assert_eq!(
+        proof.public_inputs,
+        [digest.elements, HashOut::ZERO.elements].concat()
+    );
+}
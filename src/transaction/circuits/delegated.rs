@@ -0,0 +1,223 @@
+//! Delegated proving of user transactions.
+//!
+//! A user on a weak device sends its `UserTransactionWitness` to a third-party prover
+//! together with a simple signature to `witness_binding_message` of the public inputs the witness yields.
+//! The delegated circuit verifies the user transaction proof and the signature recursively,
+//! so the prover cannot prove a transaction other than the one signed by the sender.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::witness::PartialWitness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig, Hasher},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ensure_witness, IntmaxError},
+    prover::backend::UserTransactionWitness,
+    recursion::gadgets::RecursiveProofTarget,
+    transaction::circuits::{
+        MergeAndPurgeTransitionProofWithPublicInputs, MergeAndPurgeTransitionPublicInputs,
+        SENDER_ADDRESS_OFFSET,
+    },
+    zkdsa::circuits::{parse_simple_signature_public_inputs, SimpleSignatureProofWithPublicInputs},
+};
+
+/// The message the sender signs to authorize the transaction with `public_inputs`.
+/// The public inputs commit to the merge and purge witnesses: the merged assets, the purged assets
+/// and the diff tree with the nonce.
+pub fn witness_binding_message<F: RichField>(
+    public_inputs: &MergeAndPurgeTransitionPublicInputs<F>,
+) -> HashOut<F> {
+    PoseidonHash::hash_no_pad(&public_inputs.encode())
+}
+
+/// What the user sends to the prover.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct DelegatedProvingRequest<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub witness: UserTransactionWitness<F>,
+    pub binding_signature: SimpleSignatureProofWithPublicInputs<F, C, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    DelegatedProvingRequest<F, C, D>
+{
+    /// Checks that the signature binds the witness without proving.
+    pub fn validate(&self) -> Result<(), IntmaxError> {
+        let public_inputs = self.witness.public_inputs()?;
+        validate_binding_signature(&public_inputs, &self.binding_signature)
+    }
+}
+
+fn validate_binding_signature<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    public_inputs: &MergeAndPurgeTransitionPublicInputs<F>,
+    binding_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+) -> Result<(), IntmaxError> {
+    ensure_witness!(
+        binding_signature.public_inputs.public_key == public_inputs.sender_address.0,
+        "the binding signature is not signed by the sender {}",
+        public_inputs.sender_address
+    );
+    ensure_witness!(
+        binding_signature.public_inputs.message == witness_binding_message(public_inputs),
+        "the binding signature is not for the transaction {}",
+        public_inputs.tx_hash
+    );
+
+    Ok(())
+}
+
+pub struct DelegatedUserTransactionCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub user_tx_proof: RecursiveProofTarget<D>,
+    pub binding_signature: RecursiveProofTarget<D>,
+}
+
+/// The public inputs are the same as those of the user transaction circuit.
+pub fn make_delegated_user_tx_circuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+>(
+    user_tx_circuit_data: &CircuitData<F, C, D>,
+    simple_signature_circuit_data: &CircuitData<F, C, D>,
+) -> DelegatedUserTransactionCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let user_tx_proof = RecursiveProofTarget::add_virtual_to(&mut builder, user_tx_circuit_data);
+    let binding_signature =
+        RecursiveProofTarget::add_virtual_to(&mut builder, simple_signature_circuit_data);
+
+    let constant_true = builder._true();
+    builder.connect(user_tx_proof.enabled.target, constant_true.target);
+    builder.connect(binding_signature.enabled.target, constant_true.target);
+
+    let user_tx_public_inputs = user_tx_proof.inner.public_inputs.clone();
+    let signature_public_inputs =
+        parse_simple_signature_public_inputs(&binding_signature.inner.public_inputs);
+    let message = builder.hash_n_to_hash_no_pad::<C::Hasher>(user_tx_public_inputs.clone());
+    builder.connect_hashes(signature_public_inputs.message, message);
+    let sender_address = HashOutTarget {
+        elements: user_tx_public_inputs[SENDER_ADDRESS_OFFSET..SENDER_ADDRESS_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    };
+    builder.connect_hashes(signature_public_inputs.public_key, sender_address);
+
+    builder.register_public_inputs(&user_tx_public_inputs);
+    let data = builder.build::<C>();
+
+    DelegatedUserTransactionCircuit {
+        data,
+        user_tx_proof,
+        binding_signature,
+    }
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    DelegatedUserTransactionCircuit<F, C, D>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn prove(
+        &self,
+        user_tx_proof: &MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+        binding_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        validate_binding_signature(&user_tx_proof.public_inputs, binding_signature)?;
+
+        let user_tx_proof: ProofWithPublicInputs<F, C, D> = user_tx_proof.clone().into();
+        let binding_signature: ProofWithPublicInputs<F, C, D> = binding_signature.clone().into();
+        let mut pw = PartialWitness::new();
+        self.user_tx_proof
+            .set_witness(&mut pw, &user_tx_proof, true);
+        self.binding_signature
+            .set_witness(&mut pw, &binding_signature, true);
+
+        self.data.prove(pw)
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<MergeAndPurgeTransitionPublicInputs<F>> {
+        let public_inputs =
+            MergeAndPurgeTransitionPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_delegated_user_transaction() {
+    use plonky2::field::types::Sample;
+
+    use crate::{
+        fixtures::{make_sample_accounts, make_sample_user_tx, make_sample_user_tx_circuit},
+        zkdsa::circuits::make_simple_signature_circuit,
+    };
+
+    let user_tx_circuit = make_sample_user_tx_circuit();
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    let user_tx_proof = user_txs.proofs[0].clone();
+    assert_eq!(
+        user_tx_proof.public_inputs.sender_address,
+        accounts[0].address
+    );
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let sign = |private_key, message| {
+        let mut pw = PartialWitness::new();
+        simple_signature_circuit
+            .targets
+            .set_witness(&mut pw, private_key, message);
+        simple_signature_circuit.prove(pw).unwrap()
+    };
+    let binding_signature = sign(
+        accounts[0].private_key,
+        witness_binding_message(&user_tx_proof.public_inputs),
+    );
+
+    let circuit =
+        make_delegated_user_tx_circuit(&user_tx_circuit.data, &simple_signature_circuit.data);
+    let proof = circuit.prove(&user_tx_proof, &binding_signature).unwrap();
+    assert_eq!(circuit.verify(proof).unwrap(), user_tx_proof.public_inputs);
+
+    // The prover cannot use the signature for another transaction.
+    let other_signature = sign(accounts[0].private_key, HashOut::rand());
+    assert!(circuit.prove(&user_tx_proof, &other_signature).is_err());
+    let other_sender_signature = sign(
+        accounts[1].private_key,
+        witness_binding_message(&user_tx_proof.public_inputs),
+    );
+    assert!(circuit
+        .prove(&user_tx_proof, &other_sender_signature)
+        .is_err());
+}
@@ -0,0 +1,312 @@
+use std::time::Instant;
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    iop::witness::{PartialWitness, Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::IntmaxError,
+    monitoring,
+    sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof, goldilocks_poseidon::WrappedHashOut,
+    },
+    transaction::{
+        burn::{get_burned_assets, get_burned_assets_digest},
+        gadgets::{
+            merge::{DynMergeTransitionTarget, MergeProof},
+            purge::DynPurgeTransitionTarget,
+        },
+        tx_hash::get_tx_hash_target,
+    },
+    verification::public_inputs::protocol_version,
+    zkdsa::account::Address,
+};
+
+use super::{
+    parse_merge_and_purge_public_inputs, MergeAndPurgeTransitionProofWithPublicInputs,
+    MergeAndPurgeTransitionPublicInputs, MergeAndPurgeTransitionPublicInputsTarget,
+};
+
+/// The const generics of `make_user_proof_circuit` as runtime values.
+/// A circuit built from the same parameters is the same as the const-generic one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RollupParameters {
+    pub n_log_max_users: usize,
+    pub n_log_max_txs: usize,
+    pub n_log_max_contracts: usize,
+    pub n_log_max_variables: usize,
+    pub n_log_txs: usize,
+    pub n_log_recipients: usize,
+    pub n_log_contracts: usize,
+    pub n_log_variables: usize,
+    pub n_diffs: usize,
+    pub n_merges: usize,
+}
+
+impl RollupParameters {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let tree_heights = [
+            ("n_log_max_users", self.n_log_max_users),
+            ("n_log_max_txs", self.n_log_max_txs),
+            ("n_log_max_contracts", self.n_log_max_contracts),
+            ("n_log_max_variables", self.n_log_max_variables),
+            ("n_log_txs", self.n_log_txs),
+            ("n_log_recipients", self.n_log_recipients),
+            ("n_log_contracts", self.n_log_contracts),
+            ("n_log_variables", self.n_log_variables),
+        ];
+        for (name, height) in tree_heights {
+            anyhow::ensure!(height != 0, "{} must be positive", name);
+            // The keys of the sparse Merkle trees are 256 bits.
+            anyhow::ensure!(height <= 256, "{} must be at most 256: {}", name, height);
+        }
+        anyhow::ensure!(self.n_diffs != 0, "n_diffs must be positive");
+
+        Ok(())
+    }
+}
+
+/// The same as `MergeAndPurgeTransitionTarget`, but built from `RollupParameters`.
+pub struct DynMergeAndPurgeTransitionTarget {
+    pub merge_proof_target: DynMergeTransitionTarget,
+    pub purge_proof_target: DynPurgeTransitionTarget,
+}
+
+impl DynMergeAndPurgeTransitionTarget {
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        sender_address: Address<F>,
+        merge_witnesses: &[MergeProof<F>],
+        purge_input_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        purge_output_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        nonce: WrappedHashOut<F>,
+        old_user_asset_root: WrappedHashOut<F>,
+    ) -> Result<MergeAndPurgeTransitionPublicInputs<F>, IntmaxError> {
+        let middle_user_asset_root =
+            self.merge_proof_target
+                .set_witness(pw, merge_witnesses, *old_user_asset_root)?;
+        let (new_user_asset_root, diff_root, tx_hash) = self.purge_proof_target.set_witness(
+            pw,
+            sender_address,
+            purge_input_witnesses,
+            purge_output_witnesses,
+            middle_user_asset_root,
+            nonce,
+        )?;
+
+        Ok(MergeAndPurgeTransitionPublicInputs {
+            sender_address,
+            old_user_asset_root,
+            middle_user_asset_root,
+            new_user_asset_root,
+            diff_root,
+            tx_hash,
+            burned_assets_digest: get_burned_assets_digest(&get_burned_assets(
+                purge_output_witnesses,
+            ))
+            .into(),
+        })
+    }
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
+pub fn make_user_proof_circuit_with_parameters<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    parameters: RollupParameters,
+) -> anyhow::Result<DynMergeAndPurgeTransitionCircuit<F, C, D>>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    make_user_proof_circuit_with_parameters_and_config(
+        parameters,
+        CircuitConfig::standard_recursion_config(),
+    )
+}
+
+/// Builds the user transaction circuit without monomorphizing `make_user_proof_circuit`
+/// for each combination of the parameters.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn make_user_proof_circuit_with_parameters_and_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    parameters: RollupParameters,
+    config: CircuitConfig,
+) -> anyhow::Result<DynMergeAndPurgeTransitionCircuit<F, C, D>>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    parameters.validate()?;
+
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+
+    let merge_proof_target = DynMergeTransitionTarget::add_virtual_to::<F, C::Hasher, D>(
+        &mut builder,
+        parameters.n_log_max_users,
+        parameters.n_log_max_txs,
+        parameters.n_log_txs,
+        parameters.n_log_recipients,
+        parameters.n_merges,
+    );
+
+    let purge_proof_target = DynPurgeTransitionTarget::add_virtual_to::<F, C::Hasher, D>(
+        &mut builder,
+        [
+            parameters.n_log_max_txs,
+            parameters.n_log_max_contracts,
+            parameters.n_log_max_variables,
+        ],
+        [
+            parameters.n_log_recipients,
+            parameters.n_log_contracts,
+            parameters.n_log_variables,
+        ],
+        parameters.n_diffs,
+    );
+    builder.connect_hashes(
+        merge_proof_target.new_user_asset_root,
+        purge_proof_target.old_user_asset_root,
+    );
+
+    let tx_hash = get_tx_hash_target::<F, C::Hasher, D>(
+        &mut builder,
+        purge_proof_target.diff_root,
+        purge_proof_target.nonce,
+    );
+
+    // The same layout as `make_user_proof_circuit`.
+    builder.register_public_inputs(&merge_proof_target.old_user_asset_root.elements);
+    builder.register_public_inputs(&merge_proof_target.new_user_asset_root.elements);
+    builder.register_public_inputs(&purge_proof_target.new_user_asset_root.elements);
+    builder.register_public_inputs(&purge_proof_target.diff_root.elements);
+    builder.register_public_inputs(&purge_proof_target.sender_address.0.elements);
+    builder.register_public_inputs(&tx_hash.elements);
+    builder.register_public_inputs(&purge_proof_target.burned_assets_digest.elements);
+    let version = builder.constant(protocol_version());
+    builder.register_public_input(version);
+
+    let targets = DynMergeAndPurgeTransitionTarget {
+        merge_proof_target,
+        purge_proof_target,
+    };
+
+    let data = builder.build::<C>();
+    monitoring::record_circuit_size("user_transaction", data.common.degree_bits());
+
+    Ok(DynMergeAndPurgeTransitionCircuit {
+        data,
+        targets,
+        parameters,
+    })
+}
+
+pub struct DynMergeAndPurgeTransitionCircuit<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub data: CircuitData<F, C, D>,
+    pub targets: DynMergeAndPurgeTransitionTarget,
+    pub parameters: RollupParameters,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    DynMergeAndPurgeTransitionCircuit<F, C, D>
+{
+    pub fn parse_public_inputs(&self) -> MergeAndPurgeTransitionPublicInputsTarget {
+        let public_inputs_t = self.data.prover_only.public_inputs.clone();
+
+        parse_merge_and_purge_public_inputs(&public_inputs_t)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn prove(
+        &self,
+        inputs: PartialWitness<F>,
+    ) -> anyhow::Result<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>> {
+        let start = Instant::now();
+        let proof_with_pis = self.data.prove(inputs)?;
+        monitoring::record_proving_duration("user_transaction", start.elapsed());
+
+        Ok(MergeAndPurgeTransitionProofWithPublicInputs {
+            proof: proof_with_pis.proof,
+            public_inputs: MergeAndPurgeTransitionPublicInputs::decode(
+                &proof_with_pis.public_inputs,
+            )?,
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn verify(
+        &self,
+        proof_with_pis: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<()> {
+        let public_inputs = proof_with_pis.public_inputs.encode();
+
+        self.data.verify(ProofWithPublicInputs {
+            proof: proof_with_pis.proof,
+            public_inputs,
+        })
+    }
+}
+
+#[test]
+fn test_runtime_parameters_match_const_generics() {
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    use super::make_user_proof_circuit;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    let parameters = RollupParameters {
+        n_log_max_users: 3,
+        n_log_max_txs: 3,
+        n_log_max_contracts: 3,
+        n_log_max_variables: 3,
+        n_log_txs: 2,
+        n_log_recipients: 3,
+        n_log_contracts: 3,
+        n_log_variables: 3,
+        n_diffs: 2,
+        n_merges: 2,
+    };
+    let dyn_circuit = make_user_proof_circuit_with_parameters::<F, C, D>(parameters).unwrap();
+    let circuit = make_user_proof_circuit::<F, C, D, 3, 3, 3, 3, 2, 3, 3, 3, 2, 2>();
+    assert_eq!(
+        dyn_circuit.data.verifier_only.circuit_digest,
+        circuit.data.verifier_only.circuit_digest
+    );
+
+    let mut pw = PartialWitness::new();
+    dyn_circuit
+        .targets
+        .set_witness(
+            &mut pw,
+            Address::default(),
+            &[],
+            &[],
+            &[],
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+    let proof = dyn_circuit.prove(pw).unwrap();
+    circuit.verify(proof).unwrap();
+}
@@ -0,0 +1,443 @@
+//! Minting of new supply authorized by the issuer of the token.
+//!
+//! The mint circuit inserts the minted asset into the user asset tree of the issuer
+//! with `MintTransitionTarget`, i.e. only if the contract of `MintRequest::kind` is committed to the issuer
+//! in the issuer registry and the issuer's simple signature to `MintRequest::message` is verified recursively.
+//! `MintBlockProofTarget` verifies the mint proofs of a block recursively against its block number and
+//! the issuer registry root of its block header, and the block circuit applies them to the world state
+//! after the HTLC spend.
+
+use plonky2::{
+    field::{
+        extension::Extendable,
+        goldilocks_field::GoldilocksField,
+        types::{Field, PrimeField64},
+    },
+    hash::hash_types::{HashOut, HashOutTarget},
+    iop::{target::Target, witness::PartialWitness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CircuitData},
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::IntmaxError,
+    sparse_merkle_tree::{
+        gadgets::{
+            process::process_smt::LayeredLayeredSmtProcessProof,
+            verify::verify_smt::SmtInclusionProof,
+        },
+        goldilocks_poseidon::{
+            LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory, WrappedHashOut,
+        },
+    },
+    transaction::{
+        asset::TokenKind,
+        gadgets::mint::MintTransitionTarget,
+        issuance::{IssuerRegistry, MintRequest},
+        merge_key::mint_merge_key,
+    },
+    zkdsa::{
+        account::{private_key_to_account, Address},
+        circuits::{SimpleSignatureCircuit, SimpleSignatureProofWithPublicInputs},
+    },
+};
+
+type F = GoldilocksField;
+
+pub const MINT_PUBLIC_INPUTS_LEN: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintPublicInputs {
+    pub issuer_address: Address<F>,
+    pub old_user_asset_root: WrappedHashOut<F>,
+    pub new_user_asset_root: WrappedHashOut<F>,
+    pub issuer_registry_root: WrappedHashOut<F>,
+    pub request: MintRequest,
+}
+
+impl MintPublicInputs {
+    /// `[issuer_address, old_user_asset_root, new_user_asset_root, issuer_registry_root,
+    /// contract_address, variable_index, amount, nonce, block_number]`
+    pub fn encode(&self) -> Vec<F> {
+        [
+            self.issuer_address.0.elements.to_vec(),
+            self.old_user_asset_root.elements.to_vec(),
+            self.new_user_asset_root.elements.to_vec(),
+            self.issuer_registry_root.elements.to_vec(),
+            self.request.encode(),
+        ]
+        .concat()
+    }
+
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != MINT_PUBLIC_INPUTS_LEN {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: MINT_PUBLIC_INPUTS_LEN,
+                actual: public_inputs.len(),
+            });
+        }
+
+        let read_hash = |offset: usize| -> WrappedHashOut<F> {
+            HashOut::from_partial(&public_inputs[offset..offset + 4]).into()
+        };
+
+        Ok(Self {
+            issuer_address: Address(*read_hash(0)),
+            old_user_asset_root: read_hash(4),
+            new_user_asset_root: read_hash(8),
+            issuer_registry_root: read_hash(12),
+            request: MintRequest {
+                kind: TokenKind {
+                    contract_address: Address(*read_hash(16)),
+                    variable_index: read_hash(20),
+                },
+                amount: public_inputs[24].to_canonical_u64(),
+                nonce: read_hash(25),
+                block_number: public_inputs[29].to_canonical_u64() as u32,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MintPublicInputsTarget {
+    pub issuer_address: HashOutTarget,
+    pub old_user_asset_root: HashOutTarget,
+    pub new_user_asset_root: HashOutTarget,
+    pub issuer_registry_root: HashOutTarget,
+    pub contract_address: HashOutTarget,
+    pub variable_index: HashOutTarget,
+    pub amount: Target,
+    pub nonce: HashOutTarget,
+    pub block_number: Target,
+}
+
+/// The in-circuit counterpart of `MintPublicInputs::decode`.
+pub fn parse_mint_public_inputs(public_inputs_t: &[Target]) -> MintPublicInputsTarget {
+    assert_eq!(public_inputs_t.len(), MINT_PUBLIC_INPUTS_LEN);
+    let read_hash = |offset: usize| HashOutTarget {
+        elements: public_inputs_t[offset..offset + 4].try_into().unwrap(),
+    };
+
+    MintPublicInputsTarget {
+        issuer_address: read_hash(0),
+        old_user_asset_root: read_hash(4),
+        new_user_asset_root: read_hash(8),
+        issuer_registry_root: read_hash(12),
+        contract_address: read_hash(16),
+        variable_index: read_hash(20),
+        amount: public_inputs_t[24],
+        nonce: read_hash(25),
+        block_number: public_inputs_t[29],
+    }
+}
+
+pub struct MintCircuit<
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_ISSUERS: usize,
+> where
+    F: Extendable<D>,
+{
+    pub data: CircuitData<F, C, D>,
+    pub targets: MintTransitionTarget<
+        D,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_ISSUERS,
+    >,
+}
+
+/// The public inputs are `MintPublicInputs`.
+pub fn make_mint_circuit<
+    C: GenericConfig<D, F = F> + 'static,
+    const D: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_ISSUERS: usize,
+>(
+    simple_signature_circuit_data: &CircuitData<F, C, D>,
+) -> MintCircuit<C, D, N_LOG_MAX_TXS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_VARIABLES, N_LOG_ISSUERS>
+where
+    F: Extendable<D>,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let targets = MintTransitionTarget::add_virtual_to(&mut builder, simple_signature_circuit_data);
+
+    builder.register_public_inputs(&targets.issuer_address.0.elements);
+    builder.register_public_inputs(&targets.old_user_asset_root.elements);
+    builder.register_public_inputs(&targets.new_user_asset_root.elements);
+    builder.register_public_inputs(&targets.issuer_registry_root.elements);
+    builder.register_public_inputs(&targets.contract_address.elements);
+    builder.register_public_inputs(&targets.variable_index.elements);
+    builder.register_public_input(targets.amount);
+    builder.register_public_inputs(&targets.nonce.elements);
+    builder.register_public_input(targets.block_number);
+    let data = builder.build::<C>();
+
+    MintCircuit { data, targets }
+}
+
+impl<
+        C: GenericConfig<D, F = F>,
+        const D: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_ISSUERS: usize,
+    > MintCircuit<C, D, N_LOG_MAX_TXS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_VARIABLES, N_LOG_ISSUERS>
+where
+    F: Extendable<D>,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn prove(
+        &self,
+        request: &MintRequest,
+        asset_witness: &LayeredLayeredSmtProcessProof<F>,
+        issuer_proof: &SmtInclusionProof<F>,
+        issuer_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+        old_user_asset_root: WrappedHashOut<F>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let mut pw = PartialWitness::new();
+        self.targets.set_witness(
+            &mut pw,
+            request,
+            asset_witness,
+            issuer_proof,
+            issuer_signature,
+            old_user_asset_root,
+        )?;
+
+        self.data.prove(pw)
+    }
+
+    /// Proves a mint of a throwaway issuer, which fills the disabled mint slots of the block circuit.
+    /// The public inputs of a disabled slot are not checked, so any valid mint proof will do.
+    pub fn prove_default(
+        &self,
+        simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let issuer = private_key_to_account(HashOut::from_partial(&[F::ONE]));
+        let contract_address = Address(HashOut::from_partial(&[F::ONE]));
+        let mut registry = IssuerRegistry::new();
+        registry.register(contract_address, issuer.public_key)?;
+
+        let request = MintRequest {
+            kind: TokenKind {
+                contract_address,
+                variable_index: WrappedHashOut::ZERO,
+            },
+            amount: 1,
+            nonce: WrappedHashOut::ZERO,
+            block_number: 0,
+        };
+        let mut pw = PartialWitness::new();
+        simple_signature_circuit.targets.set_witness(
+            &mut pw,
+            issuer.private_key,
+            request.message(),
+        );
+        let issuer_signature = simple_signature_circuit.prove(pw)?;
+
+        let mut user_asset_tree =
+            LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+        let asset_witness = user_asset_tree.set(
+            mint_merge_key(request.message()),
+            contract_address.0.into(),
+            request.kind.variable_index,
+            HashOut::from_partial(&[F::from_canonical_u64(request.amount)]).into(),
+        )?;
+
+        self.prove(
+            &request,
+            &asset_witness,
+            &registry.prove(&contract_address)?,
+            &issuer_signature,
+            WrappedHashOut::default(),
+        )
+    }
+
+    pub fn verify(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<MintPublicInputs> {
+        let public_inputs = MintPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
+}
+
+#[test]
+fn test_mint_circuit() {
+    use plonky2::{
+        field::types::Sample, iop::witness::Witness, plonk::config::PoseidonGoldilocksConfig,
+    };
+
+    use crate::{
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+        },
+        transaction::{issuance::IssuerRegistry, merge_key::mint_merge_key},
+        zkdsa::{account::private_key_to_account, circuits::make_simple_signature_circuit},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let sign = |private_key, message| {
+        let mut pw = PartialWitness::new();
+        simple_signature_circuit
+            .targets
+            .set_witness(&mut pw, private_key, message);
+        simple_signature_circuit.prove(pw).unwrap()
+    };
+
+    let issuer = private_key_to_account(HashOut::rand());
+    let other = private_key_to_account(HashOut::rand());
+    let contract_address = Address::rand();
+    let mut registry = IssuerRegistry::new();
+    registry
+        .register(contract_address, issuer.public_key)
+        .unwrap();
+    registry
+        .register(Address::rand(), other.public_key)
+        .unwrap();
+
+    let request = MintRequest {
+        kind: TokenKind {
+            contract_address,
+            variable_index: WrappedHashOut::ZERO,
+        },
+        amount: 1000,
+        nonce: WrappedHashOut::rand(),
+        block_number: 1,
+    };
+    let issuer_proof = registry.prove(&contract_address).unwrap();
+    let issuer_signature = sign(issuer.private_key, request.message());
+
+    let mut user_asset_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    user_asset_tree
+        .set(
+            GoldilocksHashOut::rand(),
+            contract_address.0.into(),
+            WrappedHashOut::ZERO,
+            GoldilocksHashOut::from_u32(10),
+        )
+        .unwrap();
+    let old_user_asset_root = user_asset_tree.get_root();
+    let amount = HashOut::from_partial(&[F::from_canonical_u64(request.amount)]).into();
+    let mint_asset = |tree: &mut LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>,
+                      request: &MintRequest| {
+        tree.set(
+            mint_merge_key(request.message()),
+            request.kind.contract_address.0.into(),
+            request.kind.variable_index,
+            amount,
+        )
+        .unwrap()
+    };
+    let asset_witness = mint_asset(&mut user_asset_tree, &request);
+
+    let circuit = make_mint_circuit::<C, D, 32, 32, 32, 16>(&simple_signature_circuit.data);
+    let proof = circuit
+        .prove(
+            &request,
+            &asset_witness,
+            &issuer_proof,
+            &issuer_signature,
+            old_user_asset_root,
+        )
+        .unwrap();
+    assert_eq!(
+        circuit.verify(proof).unwrap(),
+        MintPublicInputs {
+            issuer_address: Address(issuer.public_key),
+            old_user_asset_root,
+            new_user_asset_root: user_asset_tree.get_root(),
+            issuer_registry_root: registry.root().into(),
+            request,
+        }
+    );
+
+    // Only the issuer of the contract can mint.
+    let other_signature = sign(other.private_key, request.message());
+    assert!(circuit
+        .prove(
+            &request,
+            &asset_witness,
+            &issuer_proof,
+            &other_signature,
+            old_user_asset_root,
+        )
+        .is_err());
+    let more = MintRequest {
+        amount: 1001,
+        ..request
+    };
+    assert!(circuit
+        .prove(
+            &more,
+            &asset_witness,
+            &issuer_proof,
+            &issuer_signature,
+            old_user_asset_root,
+        )
+        .is_err());
+
+    // The same signed mint cannot be inserted twice.
+    let new_user_asset_root = user_asset_tree.get_root();
+    let replayed_witness = mint_asset(&mut user_asset_tree, &request);
+    assert!(circuit
+        .prove(
+            &request,
+            &replayed_witness,
+            &issuer_proof,
+            &issuer_signature,
+            new_user_asset_root,
+        )
+        .is_err());
+
+    // The circuit rejects the signature of another key even without the native checks.
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut pw = PartialWitness::new();
+        let targets = &circuit.targets;
+        targets
+            .issuer_address
+            .set_witness(&mut pw, Address(issuer.public_key));
+        targets.asset_proof.0.set_witness(&mut pw, &asset_witness.0);
+        targets.asset_proof.1.set_witness(&mut pw, &asset_witness.1);
+        targets.asset_proof.2.set_witness(&mut pw, &asset_witness.2);
+        pw.set_hash_target(targets.nonce, *request.nonce);
+        pw.set_target(
+            targets.block_number,
+            F::from_canonical_u32(request.block_number),
+        );
+        targets
+            .issuer_proof
+            .set_witness(&mut pw, &issuer_proof, true);
+        let other_signature: ProofWithPublicInputs<F, C, D> = other_signature.clone().into();
+        targets
+            .issuer_signature
+            .set_witness(&mut pw, &other_signature, true);
+        pw.set_hash_target(targets.old_user_asset_root, *old_user_asset_root);
+        circuit
+            .data
+            .prove(pw)
+            .and_then(|proof| circuit.data.verify(proof))
+    }));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
@@ -1,3 +1,9 @@
+pub mod delegated;
+pub mod dynamic;
+pub mod mint;
+
+use std::time::Instant;
+
 use plonky2::{
     field::extension::Extendable,
     hash::hash_types::{HashOut, HashOutTarget, RichField},
@@ -15,13 +21,23 @@ use plonky2::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    poseidon::gadgets::poseidon_two_to_one,
+    diagnostics::prove_with_diagnostics,
+    errors::IntmaxError,
+    monitoring,
     sparse_merkle_tree::{
         gadgets::process::process_smt::SmtProcessProof, goldilocks_poseidon::WrappedHashOut,
     },
-    transaction::gadgets::{
-        merge::{MergeProof, MergeTransitionTarget},
-        purge::PurgeTransitionTarget,
+    transaction::{
+        burn::{get_burned_assets, get_burned_assets_digest},
+        gadgets::{
+            merge::{MergeProof, MergeTransitionTarget},
+            purge::PurgeTransitionTarget,
+        },
+        tx_hash::get_tx_hash_target,
+    },
+    verification::{
+        public_inputs::{protocol_version, UserTransactionPublicInputs, PROTOCOL_VERSION},
+        verifier::UserTransactionVerifier,
     },
     zkdsa::account::Address,
 };
@@ -96,10 +112,10 @@ impl<
         purge_output_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
         nonce: WrappedHashOut<F>,
         old_user_asset_root: WrappedHashOut<F>,
-    ) -> MergeAndPurgeTransitionPublicInputs<F> {
+    ) -> Result<MergeAndPurgeTransitionPublicInputs<F>, IntmaxError> {
         let middle_user_asset_root =
             self.merge_proof_target
-                .set_witness(pw, merge_witnesses, *old_user_asset_root);
+                .set_witness(pw, merge_witnesses, *old_user_asset_root)?;
         let (new_user_asset_root, diff_root, tx_hash) = self.purge_proof_target.set_witness(
             pw,
             sender_address,
@@ -107,19 +123,24 @@ impl<
             purge_output_witnesses,
             middle_user_asset_root,
             nonce,
-        );
+        )?;
 
-        MergeAndPurgeTransitionPublicInputs {
+        Ok(MergeAndPurgeTransitionPublicInputs {
             sender_address,
             old_user_asset_root,
             middle_user_asset_root,
             new_user_asset_root,
             diff_root,
             tx_hash,
-        }
+            burned_assets_digest: get_burned_assets_digest(&get_burned_assets(
+                purge_output_witnesses,
+            ))
+            .into(),
+        })
     }
 }
 
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
 pub fn make_user_proof_circuit<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -153,9 +174,84 @@ pub fn make_user_proof_circuit<
 where
     C::Hasher: AlgebraicHasher<F>,
 {
-    // let config = CircuitConfig::standard_recursion_zk_config(); // TODO
-    let config = CircuitConfig::standard_recursion_config();
+    make_user_proof_circuit_with_config(CircuitConfig::standard_recursion_config())
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_zk_config()`,
+/// so that the proofs do not reveal anything about the witness.
+/// They can be verified recursively as well as the proofs of the non-zk circuit.
+pub fn make_user_proof_circuit_zk<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+    const N_DIFFS: usize,
+    const N_MERGES: usize,
+>(// zkdsa_circuit: SimpleSignatureCircuit,
+) -> MergeAndPurgeTransitionCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_MAX_CONTRACTS,
+    N_LOG_MAX_VARIABLES,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+    N_DIFFS,
+    N_MERGES,
+>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
+    make_user_proof_circuit_with_config(CircuitConfig::standard_recursion_zk_config())
+}
 
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn make_user_proof_circuit_with_config<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+    const N_LOG_CONTRACTS: usize,
+    const N_LOG_VARIABLES: usize,
+    const N_DIFFS: usize,
+    const N_MERGES: usize,
+>(
+    // zkdsa_circuit: SimpleSignatureCircuit,
+    config: CircuitConfig,
+) -> MergeAndPurgeTransitionCircuit<
+    F,
+    C,
+    D,
+    N_LOG_MAX_USERS,
+    N_LOG_MAX_TXS,
+    N_LOG_MAX_CONTRACTS,
+    N_LOG_MAX_VARIABLES,
+    N_LOG_TXS,
+    N_LOG_RECIPIENTS,
+    N_LOG_CONTRACTS,
+    N_LOG_VARIABLES,
+    N_DIFFS,
+    N_MERGES,
+>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
     let mut builder = CircuitBuilder::<F, D>::new(config);
     // builder.debug_gate_row = Some(282);
 
@@ -181,7 +277,7 @@ where
         purge_proof_target.old_user_asset_root,
     );
 
-    let tx_hash = poseidon_two_to_one::<F, C::Hasher, D>(
+    let tx_hash = get_tx_hash_target::<F, C::Hasher, D>(
         &mut builder,
         purge_proof_target.diff_root,
         purge_proof_target.nonce,
@@ -193,6 +289,9 @@ where
     builder.register_public_inputs(&purge_proof_target.diff_root.elements); // public_inputs[12..16]
     builder.register_public_inputs(&purge_proof_target.sender_address.0.elements); // public_inputs[16..20]
     builder.register_public_inputs(&tx_hash.elements); // public_inputs[20..24]
+    builder.register_public_inputs(&purge_proof_target.burned_assets_digest.elements); // public_inputs[24..28]
+    let version = builder.constant(protocol_version());
+    builder.register_public_input(version); // public_inputs[28]
 
     let targets = MergeAndPurgeTransitionTarget {
         // old_user_asset_root: merge_proof_target.old_user_asset_root,
@@ -203,6 +302,10 @@ where
     };
 
     let merge_and_purge_circuit_data = builder.build::<C>();
+    monitoring::record_circuit_size(
+        "user_transaction",
+        merge_and_purge_circuit_data.common.degree_bits(),
+    );
 
     MergeAndPurgeTransitionCircuit {
         data: merge_and_purge_circuit_data,
@@ -249,6 +352,8 @@ pub struct MergeAndPurgeTransitionPublicInputs<F: RichField> {
     pub new_user_asset_root: WrappedHashOut<F>,
     pub diff_root: WrappedHashOut<F>,
     pub tx_hash: WrappedHashOut<F>,
+    /// The digest of the assets sent to `burn_address` by the transaction, i.e. `get_burned_assets_digest`.
+    pub burned_assets_digest: WrappedHashOut<F>,
 }
 
 impl<F: RichField> MergeAndPurgeTransitionPublicInputs<F> {
@@ -260,9 +365,67 @@ impl<F: RichField> MergeAndPurgeTransitionPublicInputs<F> {
         public_inputs.append(&mut self.diff_root.elements.into());
         public_inputs.append(&mut self.sender_address.elements.into());
         public_inputs.append(&mut self.tx_hash.elements.into());
+        public_inputs.append(&mut self.burned_assets_digest.elements.into());
+        public_inputs.push(protocol_version());
 
         public_inputs
     }
+
+    /// The inverse of `encode`. Rejects the public inputs of the wrong length or another protocol version,
+    /// e.g. of the proofs received from the network.
+    pub fn decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        ensure_public_inputs_length(public_inputs)?;
+
+        let version = public_inputs[PROTOCOL_VERSION_OFFSET];
+        if version != protocol_version() {
+            return Err(IntmaxError::UnsupportedVersion {
+                expected: PROTOCOL_VERSION,
+                actual: u32::try_from(version.to_canonical_u64()).unwrap_or(u32::MAX),
+            });
+        }
+
+        let read_hash = |offset: usize| HashOut {
+            elements: public_inputs[offset..(offset + 4)].try_into().unwrap(),
+        };
+
+        Ok(Self {
+            sender_address: Address(read_hash(SENDER_ADDRESS_OFFSET)),
+            old_user_asset_root: read_hash(OLD_USER_ASSET_ROOT_OFFSET).into(),
+            middle_user_asset_root: read_hash(MIDDLE_USER_ASSET_ROOT_OFFSET).into(),
+            new_user_asset_root: read_hash(NEW_USER_ASSET_ROOT_OFFSET).into(),
+            diff_root: read_hash(DIFF_ROOT_OFFSET).into(),
+            tx_hash: read_hash(TX_HASH_OFFSET).into(),
+            burned_assets_digest: read_hash(BURNED_ASSETS_DIGEST_OFFSET).into(),
+        })
+    }
+}
+
+impl<F: RichField> From<MergeAndPurgeTransitionPublicInputs<F>> for UserTransactionPublicInputs<F> {
+    fn from(value: MergeAndPurgeTransitionPublicInputs<F>) -> Self {
+        Self {
+            old_user_asset_root: *value.old_user_asset_root,
+            middle_user_asset_root: *value.middle_user_asset_root,
+            new_user_asset_root: *value.new_user_asset_root,
+            diff_root: *value.diff_root,
+            sender_address: value.sender_address.0,
+            tx_hash: *value.tx_hash,
+            burned_assets_digest: *value.burned_assets_digest,
+        }
+    }
+}
+
+impl<F: RichField> From<UserTransactionPublicInputs<F>> for MergeAndPurgeTransitionPublicInputs<F> {
+    fn from(value: UserTransactionPublicInputs<F>) -> Self {
+        Self {
+            sender_address: Address(value.sender_address),
+            old_user_asset_root: value.old_user_asset_root.into(),
+            middle_user_asset_root: value.middle_user_asset_root.into(),
+            new_user_asset_root: value.new_user_asset_root.into(),
+            diff_root: value.diff_root.into(),
+            tx_hash: value.tx_hash.into(),
+            burned_assets_digest: value.burned_assets_digest.into(),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -273,6 +436,7 @@ pub struct MergeAndPurgeTransitionPublicInputsTarget {
     pub new_user_asset_root: HashOutTarget,
     pub diff_root: HashOutTarget,
     pub tx_hash: HashOutTarget,
+    pub burned_assets_digest: HashOutTarget,
 }
 
 impl MergeAndPurgeTransitionPublicInputsTarget {
@@ -285,6 +449,7 @@ impl MergeAndPurgeTransitionPublicInputsTarget {
         let new_user_asset_root = builder.add_virtual_hash();
         let diff_root = builder.add_virtual_hash();
         let tx_hash = builder.add_virtual_hash();
+        let burned_assets_digest = builder.add_virtual_hash();
 
         Self {
             sender_address,
@@ -293,6 +458,7 @@ impl MergeAndPurgeTransitionPublicInputsTarget {
             new_user_asset_root,
             diff_root,
             tx_hash,
+            burned_assets_digest,
         }
     }
 
@@ -310,6 +476,10 @@ impl MergeAndPurgeTransitionPublicInputsTarget {
         pw.set_hash_target(self.new_user_asset_root, *public_inputs.new_user_asset_root);
         pw.set_hash_target(self.diff_root, *public_inputs.diff_root);
         pw.set_hash_target(self.tx_hash, *public_inputs.tx_hash);
+        pw.set_hash_target(
+            self.burned_assets_digest,
+            *public_inputs.burned_assets_digest,
+        );
     }
 }
 
@@ -337,36 +507,57 @@ impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
     }
 }
 
+/// The layout of the public inputs of the user transaction circuit.
+/// Each field except the protocol version is a hash of 4 elements.
+pub const OLD_USER_ASSET_ROOT_OFFSET: usize = 0;
+pub const MIDDLE_USER_ASSET_ROOT_OFFSET: usize = 4;
+pub const NEW_USER_ASSET_ROOT_OFFSET: usize = 8;
+pub const DIFF_ROOT_OFFSET: usize = 12;
+pub const SENDER_ADDRESS_OFFSET: usize = 16;
+pub const TX_HASH_OFFSET: usize = 20;
+pub const BURNED_ASSETS_DIGEST_OFFSET: usize = 24;
+pub const PROTOCOL_VERSION_OFFSET: usize = 28;
+pub const MERGE_AND_PURGE_PUBLIC_INPUTS_LEN: usize = 29;
+
+fn ensure_public_inputs_length<T>(public_inputs: &[T]) -> Result<(), IntmaxError> {
+    if public_inputs.len() != MERGE_AND_PURGE_PUBLIC_INPUTS_LEN {
+        return Err(IntmaxError::InvalidPublicInputsLength {
+            expected: MERGE_AND_PURGE_PUBLIC_INPUTS_LEN,
+            actual: public_inputs.len(),
+        });
+    }
+
+    Ok(())
+}
+
+impl MergeAndPurgeTransitionPublicInputsTarget {
+    /// Fails if `public_inputs_t` does not have `MERGE_AND_PURGE_PUBLIC_INPUTS_LEN` elements.
+    pub fn try_parse(public_inputs_t: &[Target]) -> Result<Self, IntmaxError> {
+        ensure_public_inputs_length(public_inputs_t)?;
+
+        let read_hash = |offset: usize| HashOutTarget {
+            elements: public_inputs_t[offset..(offset + 4)].try_into().unwrap(),
+        };
+
+        Ok(Self {
+            sender_address: read_hash(SENDER_ADDRESS_OFFSET),
+            old_user_asset_root: read_hash(OLD_USER_ASSET_ROOT_OFFSET),
+            middle_user_asset_root: read_hash(MIDDLE_USER_ASSET_ROOT_OFFSET),
+            new_user_asset_root: read_hash(NEW_USER_ASSET_ROOT_OFFSET),
+            diff_root: read_hash(DIFF_ROOT_OFFSET),
+            tx_hash: read_hash(TX_HASH_OFFSET),
+            burned_assets_digest: read_hash(BURNED_ASSETS_DIGEST_OFFSET),
+        })
+    }
+}
+
+/// Panics if the length of `public_inputs_t` is wrong.
+/// Use `MergeAndPurgeTransitionPublicInputsTarget::try_parse` for the public inputs of unknown circuits.
 pub fn parse_merge_and_purge_public_inputs(
     public_inputs_t: &[Target],
 ) -> MergeAndPurgeTransitionPublicInputsTarget {
-    let old_user_asset_root = HashOutTarget {
-        elements: public_inputs_t[0..4].try_into().unwrap(),
-    };
-    let middle_user_asset_root = HashOutTarget {
-        elements: public_inputs_t[4..8].try_into().unwrap(),
-    };
-    let new_user_asset_root = HashOutTarget {
-        elements: public_inputs_t[8..12].try_into().unwrap(),
-    };
-    let diff_root = HashOutTarget {
-        elements: public_inputs_t[12..16].try_into().unwrap(),
-    };
-    let sender_address = HashOutTarget {
-        elements: public_inputs_t[16..20].try_into().unwrap(),
-    };
-    let tx_hash = HashOutTarget {
-        elements: public_inputs_t[20..24].try_into().unwrap(),
-    };
-
-    MergeAndPurgeTransitionPublicInputsTarget {
-        sender_address,
-        old_user_asset_root,
-        middle_user_asset_root,
-        new_user_asset_root,
-        diff_root,
-        tx_hash,
-    }
+    MergeAndPurgeTransitionPublicInputsTarget::try_parse(public_inputs_t)
+        .expect("invalid public inputs of the user transaction circuit")
 }
 
 impl<
@@ -406,49 +597,31 @@ impl<
         parse_merge_and_purge_public_inputs(&public_inputs_t)
     }
 
+    /// Returns the verifier without the prover data.
+    pub fn verifier(&self) -> UserTransactionVerifier<F, C, D> {
+        UserTransactionVerifier {
+            verifier_only: self.data.verifier_only.clone(),
+            common: self.data.common.clone(),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn prove(
         &self,
         inputs: PartialWitness<F>,
     ) -> anyhow::Result<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>> {
-        let proof_with_pis = self.data.prove(inputs)?;
-        let public_inputs = proof_with_pis.public_inputs;
-        let old_user_asset_root = HashOut {
-            elements: public_inputs[0..4].try_into().unwrap(),
-        }
-        .into();
-        let middle_user_asset_root = HashOut {
-            elements: public_inputs[4..8].try_into().unwrap(),
-        }
-        .into();
-        let new_user_asset_root = HashOut {
-            elements: public_inputs[8..12].try_into().unwrap(),
-        }
-        .into();
-        let diff_root = HashOut {
-            elements: public_inputs[12..16].try_into().unwrap(),
-        }
-        .into();
-        let sender_address = Address(HashOut {
-            elements: public_inputs[16..20].try_into().unwrap(),
-        });
-        let tx_hash = HashOut {
-            elements: public_inputs[20..24].try_into().unwrap(),
-        }
-        .into();
-
+        let start = Instant::now();
+        let proof_with_pis = prove_with_diagnostics(&self.data, inputs, &self.targets)?;
+        monitoring::record_proving_duration("user_transaction", start.elapsed());
         Ok(MergeAndPurgeTransitionProofWithPublicInputs {
             proof: proof_with_pis.proof,
-            public_inputs: MergeAndPurgeTransitionPublicInputs {
-                sender_address,
-                old_user_asset_root,
-                middle_user_asset_root,
-                new_user_asset_root,
-                diff_root,
-                tx_hash,
-            },
+            public_inputs: MergeAndPurgeTransitionPublicInputs::decode(
+                &proof_with_pis.public_inputs,
+            )?,
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn verify(
         &self,
         proof_with_pis: MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>,
@@ -460,9 +633,26 @@ impl<
             public_inputs,
         })
     }
+
+    /// Verifies a plonky2-native proof, e.g. received from the network,
+    /// after checking the layout and the protocol version of its public inputs.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn verify_raw(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<MergeAndPurgeTransitionPublicInputs<F>> {
+        let public_inputs =
+            MergeAndPurgeTransitionPublicInputs::decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
 }
 
 /// witness を入力にとり、 user_tx_proof を返す関数
+/// `merge_and_purge_circuit` is built once by `make_user_proof_circuit` and shared between calls,
+/// since building it takes longer than proving.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
 pub fn prove_user_transaction<
     F: RichField + Extendable<D>,
     C: GenericConfig<D, F = F>,
@@ -478,17 +668,7 @@ pub fn prove_user_transaction<
     const N_DIFFS: usize,
     const N_MERGES: usize,
 >(
-    sender_address: Address<F>,
-    merge_witnesses: &[MergeProof<F>],
-    purge_input_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
-    purge_output_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
-    nonce: WrappedHashOut<F>,
-    old_user_asset_root: WrappedHashOut<F>,
-) -> anyhow::Result<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>
-where
-    C::Hasher: AlgebraicHasher<F>,
-{
-    let merge_and_purge_circuit = make_user_proof_circuit::<
+    merge_and_purge_circuit: &MergeAndPurgeTransitionCircuit<
         F,
         C,
         D,
@@ -502,8 +682,17 @@ where
         N_LOG_VARIABLES,
         N_DIFFS,
         N_MERGES,
-    >();
-
+    >,
+    sender_address: Address<F>,
+    merge_witnesses: &[MergeProof<F>],
+    purge_input_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+    purge_output_witnesses: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+    nonce: WrappedHashOut<F>,
+    old_user_asset_root: WrappedHashOut<F>,
+) -> anyhow::Result<MergeAndPurgeTransitionProofWithPublicInputs<F, C, D>>
+where
+    C::Hasher: AlgebraicHasher<F>,
+{
     let mut pw = PartialWitness::new();
     let _public_inputs = merge_and_purge_circuit.targets.set_witness(
         &mut pw,
@@ -513,7 +702,7 @@ where
         purge_output_witnesses,
         nonce,
         old_user_asset_root,
-    );
+    )?;
 
     let user_tx_proof = merge_and_purge_circuit
         .prove(pw)
@@ -521,3 +710,93 @@ where
 
     Ok(user_tx_proof)
 }
+
+#[test]
+fn test_decode_merge_and_purge_public_inputs() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Field};
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::GoldilocksHashOut;
+
+    type F = GoldilocksField;
+
+    let public_inputs = MergeAndPurgeTransitionPublicInputs::<F> {
+        sender_address: Address(GoldilocksHashOut::from_u32(1).0),
+        old_user_asset_root: GoldilocksHashOut::from_u32(2),
+        middle_user_asset_root: GoldilocksHashOut::from_u32(3),
+        new_user_asset_root: GoldilocksHashOut::from_u32(4),
+        diff_root: GoldilocksHashOut::from_u32(5),
+        tx_hash: GoldilocksHashOut::from_u32(6),
+        burned_assets_digest: GoldilocksHashOut::from_u32(7),
+    };
+    let encoded = public_inputs.encode();
+    assert_eq!(encoded.len(), MERGE_AND_PURGE_PUBLIC_INPUTS_LEN);
+    assert_eq!(
+        MergeAndPurgeTransitionPublicInputs::decode(&encoded).unwrap(),
+        public_inputs
+    );
+
+    assert!(matches!(
+        MergeAndPurgeTransitionPublicInputs::decode(&encoded[..28]),
+        Err(IntmaxError::InvalidPublicInputsLength {
+            expected: 29,
+            actual: 28
+        })
+    ));
+
+    let mut other_version = encoded;
+    other_version[PROTOCOL_VERSION_OFFSET] = F::from_canonical_u32(PROTOCOL_VERSION + 1);
+    assert!(matches!(
+        MergeAndPurgeTransitionPublicInputs::decode(&other_version),
+        Err(IntmaxError::UnsupportedVersion { .. })
+    ));
+
+    let targets = (0..MERGE_AND_PURGE_PUBLIC_INPUTS_LEN)
+        .map(|index| Target::VirtualTarget { index })
+        .collect::<Vec<_>>();
+    let parsed = MergeAndPurgeTransitionPublicInputsTarget::try_parse(&targets).unwrap();
+    assert_eq!(
+        parsed.sender_address.elements[0],
+        targets[SENDER_ADDRESS_OFFSET]
+    );
+    assert_eq!(
+        parsed.burned_assets_digest.elements[0],
+        targets[BURNED_ASSETS_DIGEST_OFFSET]
+    );
+    assert!(MergeAndPurgeTransitionPublicInputsTarget::try_parse(&targets[1..]).is_err());
+}
+
+#[test]
+fn test_user_proof_circuit_zk() {
+    use crate::fixtures::{
+        make_sample_accounts, make_sample_block, make_sample_user_tx, C, D, F, N_DIFFS,
+        N_LOG_CONTRACTS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_TXS, N_LOG_MAX_USERS, N_LOG_MAX_VARIABLES,
+        N_LOG_RECIPIENTS, N_LOG_TXS, N_LOG_VARIABLES, N_MERGES,
+    };
+
+    let user_tx_circuit = make_user_proof_circuit_zk::<
+        F,
+        C,
+        D,
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_MAX_CONTRACTS,
+        N_LOG_MAX_VARIABLES,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        N_LOG_CONTRACTS,
+        N_LOG_VARIABLES,
+        N_DIFFS,
+        N_MERGES,
+    >();
+    assert!(user_tx_circuit.data.common.config.zero_knowledge);
+
+    let accounts = make_sample_accounts();
+    let user_txs = make_sample_user_tx(&user_tx_circuit, &accounts).unwrap();
+    for user_tx_proof in user_txs.proofs.iter() {
+        user_tx_circuit.verify(user_tx_proof.clone()).unwrap();
+    }
+
+    // The proposal block verifies the zk proofs recursively.
+    let block = make_sample_block(&user_tx_circuit, &user_txs).unwrap();
+    block.circuit_data.verify(block.proof).unwrap();
+}
@@ -0,0 +1,106 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    iop::witness::Witness,
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CommonCircuitData, VerifierCircuitTarget, VerifierOnlyCircuitData},
+        config::{AlgebraicHasher, GenericConfig, Hasher},
+        proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget},
+    },
+};
+
+use crate::{
+    recursion::gadgets::{add_virtual_verifier_data, get_verifier_digest_target, Wrapper},
+    sparse_merkle_tree::gadgets::common::poseidon_two_to_one,
+};
+
+/// The digest of an attached proof, which commits to both the circuit and the proven statement.
+pub fn get_attachment_digest<F: RichField>(
+    verifier_digest: HashOut<F>,
+    public_inputs: &[F],
+) -> HashOut<F> {
+    PoseidonHash::two_to_one(verifier_digest, PoseidonHash::hash_no_pad(public_inputs))
+}
+
+/// The nonce of a user transaction carrying the attachment with `attachment_digest`.
+/// `salt` keeps the transaction hash unlinkable to other transactions with the same attachment.
+pub fn get_attached_nonce<F: RichField>(
+    salt: HashOut<F>,
+    attachment_digest: HashOut<F>,
+) -> HashOut<F> {
+    PoseidonHash::two_to_one(salt, attachment_digest)
+}
+
+/// A proof of an external circuit, e.g. an identity attestation, verified with the verifier data as a witness.
+#[derive(Clone)]
+pub struct AttachmentTarget<const D: usize> {
+    pub attachment_proof: Wrapper<ProofWithPublicInputsTarget<D>>,
+    pub verifier_data: VerifierCircuitTarget,
+    pub salt: HashOutTarget,
+
+    pub attachment_digest: HashOutTarget, // output
+    pub nonce: HashOutTarget,             // output
+}
+
+impl<const D: usize> AttachmentTarget<D> {
+    /// Every attached circuit must have `attachment_common_data`, e.g. be padded to the same degree.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        builder: &mut CircuitBuilder<F, D>,
+        attachment_common_data: &CommonCircuitData<F, D>,
+    ) -> Self
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let attachment_proof = builder.add_virtual_proof_with_pis::<C>(attachment_common_data);
+        let verifier_data = add_virtual_verifier_data(builder, attachment_common_data);
+        builder.verify_proof::<C>(
+            attachment_proof.clone(),
+            &verifier_data,
+            attachment_common_data,
+        );
+        let salt = builder.add_virtual_hash();
+
+        let verifier_digest = get_verifier_digest_target::<F, C, D>(builder, &verifier_data);
+        let public_inputs_digest =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(attachment_proof.public_inputs.clone());
+        let attachment_digest = poseidon_two_to_one::<F, PoseidonHash, D>(
+            builder,
+            verifier_digest,
+            public_inputs_digest,
+        );
+        let nonce = poseidon_two_to_one::<F, PoseidonHash, D>(builder, salt, attachment_digest);
+
+        Self {
+            attachment_proof: Wrapper(attachment_proof),
+            verifier_data,
+            salt,
+            attachment_digest,
+            nonce,
+        }
+    }
+
+    pub fn set_witness<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        attachment_proof: &ProofWithPublicInputs<F, C, D>,
+        verifier_only: &VerifierOnlyCircuitData<C, D>,
+        salt: HashOut<F>,
+    ) where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        pw.set_proof_with_pis_target(&self.attachment_proof, attachment_proof);
+        pw.set_cap_target(
+            &self.verifier_data.constants_sigmas_cap,
+            &verifier_only.constants_sigmas_cap,
+        );
+        pw.set_hash_target(
+            self.verifier_data.circuit_digest,
+            verifier_only.circuit_digest,
+        );
+        pw.set_hash_target(self.salt, salt);
+    }
+}
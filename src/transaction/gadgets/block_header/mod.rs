@@ -7,7 +7,7 @@ use plonky2::{
 
 use crate::poseidon::gadgets::poseidon_two_to_one;
 
-use super::super::block_header::BlockHeader;
+use super::super::block_header::{BlockHeader, N_LOG_MAX_TIMESTAMP};
 
 const N_LOG_MAX_BLOCKS: usize = 32;
 
@@ -20,6 +20,10 @@ pub struct BlockHeaderTarget {
     pub proposed_world_state_digest: HashOutTarget,
     pub approved_world_state_digest: HashOutTarget,
     pub latest_account_digest: HashOutTarget,
+    pub forced_transactions_digest: HashOutTarget,
+    pub issuer_registry_root: HashOutTarget,
+    pub timestamp: Target, // u48
+    pub aggregator_address: HashOutTarget,
 }
 
 impl BlockHeaderTarget {
@@ -34,6 +38,11 @@ impl BlockHeaderTarget {
         let proposed_world_state_digest = builder.add_virtual_hash();
         let approved_world_state_digest = builder.add_virtual_hash();
         let latest_account_digest = builder.add_virtual_hash();
+        let forced_transactions_digest = builder.add_virtual_hash();
+        let issuer_registry_root = builder.add_virtual_hash();
+        let timestamp = builder.add_virtual_target();
+        builder.range_check(timestamp, N_LOG_MAX_TIMESTAMP);
+        let aggregator_address = builder.add_virtual_hash();
 
         Self {
             block_number,
@@ -43,6 +52,10 @@ impl BlockHeaderTarget {
             proposed_world_state_digest,
             approved_world_state_digest,
             latest_account_digest,
+            forced_transactions_digest,
+            issuer_registry_root,
+            timestamp,
+            aggregator_address,
         }
     }
 
@@ -69,6 +82,16 @@ impl BlockHeaderTarget {
             self.latest_account_digest,
             block_header.latest_account_digest,
         );
+        pw.set_hash_target(
+            self.forced_transactions_digest,
+            block_header.forced_transactions_digest,
+        );
+        pw.set_hash_target(self.issuer_registry_root, block_header.issuer_registry_root);
+        pw.set_target(
+            self.timestamp,
+            F::from_canonical_u64(block_header.timestamp),
+        );
+        pw.set_hash_target(self.aggregator_address, block_header.aggregator_address);
     }
 }
 
@@ -99,6 +122,81 @@ pub fn get_block_hash_target<
         block_header.approved_world_state_digest,
     );
     let e = poseidon_two_to_one::<F, H, D>(builder, c, d);
+    let f = poseidon_two_to_one::<F, H, D>(
+        builder,
+        block_header.forced_transactions_digest,
+        block_header.issuer_registry_root,
+    );
+    let f = poseidon_two_to_one::<F, H, D>(builder, e, f);
+    let g = poseidon_two_to_one::<F, H, D>(
+        builder,
+        HashOutTarget::from_partial(&[block_header.timestamp], zero),
+        block_header.aggregator_address,
+    );
+    let h = poseidon_two_to_one::<F, H, D>(builder, f, g);
+
+    poseidon_two_to_one::<F, H, D>(builder, block_header.prev_block_header_digest, h)
+}
+
+#[test]
+fn test_block_hash_target() {
+    use plonky2::{
+        field::types::Sample,
+        hash::hash_types::HashOut,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::transaction::block_header::get_block_hash;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let block_header_t = BlockHeaderTarget::add_virtual_to::<F, H, D>(&mut builder);
+    let block_hash_t = get_block_hash_target::<F, H, D>(&mut builder, &block_header_t);
+    builder.register_public_inputs(&block_hash_t.elements);
+    let data = builder.build::<C>();
 
-    poseidon_two_to_one::<F, H, D>(builder, block_header.prev_block_header_digest, e)
+    let block_header = BlockHeader {
+        block_number: 3,
+        timestamp: 1_700_000_000,
+        aggregator_address: HashOut::rand(),
+        issuer_registry_root: HashOut::rand(),
+        ..BlockHeader::with_tree_depth(2)
+    };
+    let mut pw = PartialWitness::new();
+    block_header_t.set_witness(&mut pw, &block_header);
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(proof.public_inputs, get_block_hash(&block_header).elements);
+    data.verify(proof).unwrap();
+
+    // The timestamp, the aggregator and the issuer registry are committed in the block hash.
+    assert_ne!(
+        get_block_hash(&block_header),
+        get_block_hash(&BlockHeader {
+            timestamp: 1_700_000_001,
+            ..block_header.clone()
+        })
+    );
+    assert_ne!(
+        get_block_hash(&block_header),
+        get_block_hash(&BlockHeader {
+            aggregator_address: HashOut::ZERO,
+            ..block_header.clone()
+        })
+    );
+    assert_ne!(
+        get_block_hash(&block_header),
+        get_block_hash(&BlockHeader {
+            issuer_registry_root: HashOut::ZERO,
+            ..block_header
+        })
+    );
 }
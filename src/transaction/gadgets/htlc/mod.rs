@@ -0,0 +1,683 @@
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::{
+        target::{BoolTarget, Target},
+        witness::Witness,
+    },
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
+};
+
+use crate::{
+    errors::{ensure_witness, IntmaxError},
+    sparse_merkle_tree::{
+        gadgets::{
+            common::{conditionally_select, enforce_equal_if_enabled, logical_or},
+            process::{
+                process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+                utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
+            },
+        },
+        goldilocks_poseidon::WrappedHashOut,
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::{
+        gadgets::merge::{validate_merge_witness, MergeProof, MergeTransitionTarget},
+        htlc::{HtlcCondition, HtlcSpend},
+        merge_key::transfer_merge_key_target,
+    },
+    zkdsa::{account::Address, gadgets::account::AddressTarget},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct HtlcConditionTarget {
+    pub hash_lock: HashOutTarget,
+    pub timelock: Target,
+    pub recipient: AddressTarget,
+    pub refund_address: AddressTarget,
+}
+
+impl HtlcConditionTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let hash_lock = builder.add_virtual_hash();
+        let timelock = builder.add_virtual_target();
+        builder.range_check(timelock, 32);
+        let recipient = AddressTarget::add_virtual_to(builder);
+        let refund_address = AddressTarget::add_virtual_to(builder);
+
+        Self {
+            hash_lock,
+            timelock,
+            recipient,
+            refund_address,
+        }
+    }
+
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        condition: &HtlcCondition<F>,
+    ) {
+        pw.set_hash_target(self.hash_lock, *condition.hash_lock);
+        pw.set_target(self.timelock, F::from_canonical_u32(condition.timelock));
+        self.recipient.set_witness(pw, condition.recipient);
+        self.refund_address
+            .set_witness(pw, condition.refund_address);
+    }
+
+    /// The in-circuit counterpart of `HtlcCondition::address`.
+    pub fn address<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> AddressTarget {
+        let inputs = [
+            self.hash_lock.elements.to_vec(),
+            vec![self.timelock],
+            self.recipient.0.elements.to_vec(),
+            self.refund_address.0.elements.to_vec(),
+        ]
+        .concat();
+
+        AddressTarget(builder.hash_n_to_hash_no_pad::<H>(inputs))
+    }
+}
+
+/// The in-circuit counterpart of `HtlcCondition::beneficiary`.
+/// `preimage` is not checked in a refund, and nothing is checked unless `enabled`.
+pub fn verify_htlc_spend_condition<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    condition: &HtlcConditionTarget,
+    is_claim: BoolTarget,
+    preimage: HashOutTarget,
+    block_number: Target,
+    enabled: BoolTarget,
+) -> AddressTarget {
+    // claim のとき, preimage の hash が hash_lock に一致すること
+    let hash_lock = builder.hash_n_to_hash_no_pad::<H>(preimage.elements.to_vec());
+    let is_enabled_claim = builder.and(is_claim, enabled);
+    enforce_equal_if_enabled(builder, hash_lock, condition.hash_lock, is_enabled_claim);
+
+    // claim は block_number < timelock, refund は timelock <= block_number のときのみ可能
+    builder.range_check(block_number, 32);
+    let one = builder.one();
+    let claim_margin = builder.sub(condition.timelock, block_number);
+    let claim_margin = builder.sub(claim_margin, one);
+    let refund_margin = builder.sub(block_number, condition.timelock);
+    let margin = builder.select(is_claim, claim_margin, refund_margin);
+    let margin = builder.mul(margin, enabled.target);
+    builder.range_check(margin, 32);
+
+    AddressTarget(conditionally_select(
+        builder,
+        condition.recipient.0,
+        condition.refund_address.0,
+        is_claim,
+    ))
+}
+
+/// Checks that `w` sets `new_user_asset_root` of `address` in the world state tree,
+/// updating `old_user_asset_root` or creating the account from the empty user asset tree.
+/// If not `enabled`, `w` must be a no-op.
+fn enforce_user_asset_root_upsert<F: RichField + Extendable<D>, const D: usize, const N: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    w: &SparseMerkleProcessProofTarget<N>,
+    address: AddressTarget,
+    old_user_asset_root: HashOutTarget,
+    new_user_asset_root: HashOutTarget,
+    enabled: BoolTarget,
+) {
+    let zero = builder.zero();
+    let default_hash = HashOutTarget {
+        elements: [zero; 4],
+    };
+
+    let ProcessMerkleProofRoleTarget {
+        is_insert_op,
+        is_update_op,
+        ..
+    } = get_process_merkle_proof_role(builder, w.fnc);
+    let is_insert_or_update_op = logical_or(builder, is_insert_op, is_update_op);
+    builder.connect(is_insert_or_update_op.target, enabled.target);
+
+    enforce_equal_if_enabled(builder, w.new_key, address.0, enabled);
+    enforce_equal_if_enabled(builder, w.new_value, new_user_asset_root, enabled);
+    enforce_equal_if_enabled(builder, w.old_value, old_user_asset_root, is_update_op);
+    enforce_equal_if_enabled(builder, old_user_asset_root, default_hash, is_insert_op);
+}
+
+/// The spend of an escrow, which merges the escrowed assets into the user asset tree of the beneficiary
+/// and inserts the same merge key into the user asset tree of the escrow account.
+/// The block circuit has one slot of it, which is disabled in a block without any spend.
+#[derive(Clone, Debug)]
+pub struct HtlcSpendTarget<
+    const N_LOG_MAX_USERS: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_TXS: usize,
+    const N_LOG_RECIPIENTS: usize,
+> {
+    pub enabled: BoolTarget,            // input
+    pub condition: HtlcConditionTarget, // input
+    pub is_claim: BoolTarget,           // input
+
+    /// claim のときのみ検証される.
+    pub preimage: HashOutTarget, // input
+
+    /// `block_number` of the header of the block including the spend.
+    pub block_number: Target,
+
+    /// The merge of the transfer to the escrow account into the user asset tree of the beneficiary.
+    pub merge:
+        MergeTransitionTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS, 1>, // input
+
+    /// The insertion of the merge key into the user asset tree of the escrow account.
+    pub escrow_process_proof: SparseMerkleProcessProofTarget<N_LOG_MAX_TXS>, // input
+
+    /// The processes of the escrow account and the beneficiary in this order.
+    pub world_state_process_proofs: [SparseMerkleProcessProofTarget<N_LOG_MAX_USERS>; 2], // input
+
+    pub htlc_address: AddressTarget,         // output
+    pub beneficiary: AddressTarget,          // output
+    pub old_world_state_root: HashOutTarget, // output
+    pub new_world_state_root: HashOutTarget, // output
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+    > HtlcSpendTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>
+{
+    /// `block_number` is that of the block header, which the timelock is checked against.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        block_number: Target,
+    ) -> Self {
+        let constant_false = builder._false();
+
+        let enabled = builder.add_virtual_bool_target_safe();
+        let condition = HtlcConditionTarget::add_virtual_to(builder);
+        let is_claim = builder.add_virtual_bool_target_safe();
+        let preimage = builder.add_virtual_hash();
+        let htlc_address = condition.address::<F, H, D>(builder);
+        let beneficiary = verify_htlc_spend_condition::<F, H, D>(
+            builder,
+            &condition,
+            is_claim,
+            preimage,
+            block_number,
+            enabled,
+        );
+
+        // escrow account 宛の transfer を beneficiary の user asset tree に merge する
+        // (disabled のときは merge も no-op で padding される)
+        let merge = MergeTransitionTarget::add_virtual_to::<F, H, D>(builder);
+        let merge_proof = &merge.proofs[0];
+        builder.connect(
+            merge_proof.address_list_inclusion_proof.enabled.target,
+            enabled.target,
+        );
+        let merge_role =
+            get_process_merkle_proof_role(builder, merge_proof.merge_process_proof.fnc);
+        builder.connect(merge_role.is_insert_op.target, enabled.target);
+        let is_enabled_removal = builder.and(merge_proof.diff_tree_inclusion_proof.2.fnc, enabled);
+        builder.connect(is_enabled_removal.target, constant_false.target);
+        enforce_equal_if_enabled(
+            builder,
+            merge_proof.diff_tree_inclusion_proof.2.key,
+            htlc_address.0,
+            enabled,
+        );
+        let merge_key = transfer_merge_key_target(merge_proof.diff_tree_inclusion_proof.1.value);
+        enforce_equal_if_enabled(
+            builder,
+            merge_proof.merge_process_proof.new_key,
+            merge_key,
+            enabled,
+        );
+
+        // 同じ merge key を escrow account の user asset tree にも挿入して, 二重の spend を防ぐ
+        let escrow_process_proof =
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(builder);
+        let escrow_role = get_process_merkle_proof_role(builder, escrow_process_proof.fnc);
+        builder.connect(escrow_role.is_insert_op.target, enabled.target);
+        enforce_equal_if_enabled(builder, escrow_process_proof.new_key, merge_key, enabled);
+        enforce_equal_if_enabled(
+            builder,
+            escrow_process_proof.new_value,
+            merge_proof.diff_tree_inclusion_proof.2.value,
+            enabled,
+        );
+
+        let world_state_process_proofs = [
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(builder),
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(builder),
+        ];
+        enforce_user_asset_root_upsert(
+            builder,
+            &world_state_process_proofs[0],
+            htlc_address,
+            escrow_process_proof.old_root,
+            escrow_process_proof.new_root,
+            enabled,
+        );
+        enforce_user_asset_root_upsert(
+            builder,
+            &world_state_process_proofs[1],
+            beneficiary,
+            merge.old_user_asset_root,
+            merge.new_user_asset_root,
+            enabled,
+        );
+        builder.connect_hashes(
+            world_state_process_proofs[1].old_root,
+            world_state_process_proofs[0].new_root,
+        );
+        let old_world_state_root = world_state_process_proofs[0].old_root;
+        let new_world_state_root = world_state_process_proofs[1].new_root;
+
+        Self {
+            enabled,
+            condition,
+            is_claim,
+            preimage,
+            block_number,
+            merge,
+            escrow_process_proof,
+            world_state_process_proofs,
+            htlc_address,
+            beneficiary,
+            old_world_state_root,
+            new_world_state_root,
+        }
+    }
+
+    /// Returns `(beneficiary, new_world_state_root)`.
+    /// `block_number` is not set here, but as a part of the block header.
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        witness: &HtlcSpendWitness<F>,
+    ) -> Result<(Address<F>, WrappedHashOut<F>), IntmaxError> {
+        let result = validate_htlc_spend_witness(witness)?;
+
+        pw.set_bool_target(self.enabled, true);
+        self.condition.set_witness(pw, &witness.condition);
+        let preimage = match witness.spend {
+            HtlcSpend::Claim { preimage } => {
+                pw.set_bool_target(self.is_claim, true);
+                *preimage
+            }
+            HtlcSpend::Refund => {
+                pw.set_bool_target(self.is_claim, false);
+                HashOut::ZERO
+            }
+        };
+        pw.set_hash_target(self.preimage, preimage);
+        self.merge.set_witness(
+            pw,
+            std::slice::from_ref(&witness.merge_proof),
+            *witness.old_beneficiary_asset_root,
+        )?;
+        self.escrow_process_proof
+            .set_witness(pw, &witness.escrow_process_proof);
+        for (p_t, p) in self
+            .world_state_process_proofs
+            .iter()
+            .zip(witness.world_state_process_proofs.iter())
+        {
+            p_t.set_witness(pw, p);
+        }
+
+        Ok(result)
+    }
+
+    /// Disables the slot, which leaves `world_state_root` as it is.
+    pub fn set_disabled_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        world_state_root: HashOut<F>,
+    ) -> Result<(), IntmaxError> {
+        pw.set_bool_target(self.enabled, false);
+        self.condition.set_witness(
+            pw,
+            &HtlcCondition {
+                hash_lock: Default::default(),
+                timelock: 0,
+                recipient: Default::default(),
+                refund_address: Default::default(),
+            },
+        );
+        pw.set_bool_target(self.is_claim, false);
+        pw.set_hash_target(self.preimage, HashOut::ZERO);
+        self.merge.set_witness(pw, &[], HashOut::ZERO)?;
+        self.escrow_process_proof
+            .set_witness(pw, &SmtProcessProof::with_root(Default::default()));
+        let default_proof = SmtProcessProof::with_root(world_state_root.into());
+        for p_t in self.world_state_process_proofs.iter() {
+            p_t.set_witness(pw, &default_proof);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HtlcSpendWitness<F: RichField> {
+    pub condition: HtlcCondition<F>,
+    pub spend: HtlcSpend<F>,
+
+    /// The number of the block including the spend.
+    pub block_number: u32,
+
+    /// The merge proof of the transfer to `condition.address()`.
+    pub merge_proof: MergeProof<F>,
+    pub old_beneficiary_asset_root: WrappedHashOut<F>,
+    pub escrow_process_proof: SmtProcessProof<F>,
+    pub world_state_process_proofs: [SmtProcessProof<F>; 2],
+}
+
+/// Checks the same conditions as `HtlcSpendTarget` without proving,
+/// and returns `(beneficiary, new_world_state_root)`.
+pub fn validate_htlc_spend_witness<F: RichField>(
+    witness: &HtlcSpendWitness<F>,
+) -> Result<(Address<F>, WrappedHashOut<F>), IntmaxError> {
+    let htlc_address = witness.condition.address();
+    let beneficiary = witness
+        .condition
+        .beneficiary(&witness.spend, witness.block_number)
+        .map_err(|err| IntmaxError::InvalidWitness(err.to_string()))?;
+
+    let merge_proof = &witness.merge_proof;
+    ensure_witness!(
+        !merge_proof.is_deposit && *merge_proof.diff_tree_inclusion_proof.2.key == htlc_address.0,
+        "the escrow must be a transfer to {}",
+        htlc_address
+    );
+    let new_beneficiary_asset_root = validate_merge_witness(
+        std::slice::from_ref(merge_proof),
+        witness.old_beneficiary_asset_root,
+    )?;
+    ensure_witness!(
+        merge_proof.merge_process_proof.fnc == ProcessMerkleProofRole::ProcessInsert,
+        "the escrow must be merged as a new leaf"
+    );
+
+    let escrow_process_proof = &witness.escrow_process_proof;
+    ensure_witness!(
+        escrow_process_proof.fnc == ProcessMerkleProofRole::ProcessInsert
+            && escrow_process_proof.new_key == merge_proof.merge_process_proof.new_key
+            && escrow_process_proof.new_value == merge_proof.diff_tree_inclusion_proof.2.value,
+        "the escrow must be spent for the first time"
+    );
+
+    let [w0, w1] = &witness.world_state_process_proofs;
+    validate_user_asset_root_upsert(
+        w0,
+        htlc_address,
+        escrow_process_proof.old_root,
+        escrow_process_proof.new_root,
+    )
+    .map_err(|err| IntmaxError::InvalidWitness(format!("escrow account: {}", err)))?;
+    validate_user_asset_root_upsert(
+        w1,
+        beneficiary,
+        witness.old_beneficiary_asset_root,
+        new_beneficiary_asset_root,
+    )
+    .map_err(|err| IntmaxError::InvalidWitness(format!("beneficiary: {}", err)))?;
+    ensure_witness!(
+        w1.old_root == w0.new_root,
+        "world state process proof #1 old_root mismatch"
+    );
+
+    Ok((beneficiary, w1.new_root))
+}
+
+/// The native counterpart of `enforce_user_asset_root_upsert`.
+fn validate_user_asset_root_upsert<F: RichField>(
+    w: &SmtProcessProof<F>,
+    address: Address<F>,
+    old_user_asset_root: WrappedHashOut<F>,
+    new_user_asset_root: WrappedHashOut<F>,
+) -> Result<(), String> {
+    if *w.new_key != address.0 || w.new_value != new_user_asset_root {
+        return Err("the world state process proof must set the new user asset root".to_string());
+    }
+    match w.fnc {
+        ProcessMerkleProofRole::ProcessInsert
+            if old_user_asset_root == WrappedHashOut::default() =>
+        {
+            Ok(())
+        }
+        ProcessMerkleProofRole::ProcessUpdate if w.old_value == old_user_asset_root => Ok(()),
+        _ => Err("the world state process proof must update the old user asset root".to_string()),
+    }
+}
+
+#[test]
+fn test_htlc_spend_condition() {
+    use plonky2::{
+        field::goldilocks_field::GoldilocksField,
+        hash::poseidon::PoseidonHash,
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let condition_t = HtlcConditionTarget::add_virtual_to(&mut builder);
+    let is_claim_t = builder.add_virtual_bool_target_safe();
+    let preimage_t = builder.add_virtual_hash();
+    let block_number_t = builder.add_virtual_target();
+    let htlc_address_t = condition_t.address::<F, PoseidonHash, D>(&mut builder);
+    let beneficiary_t = verify_htlc_spend_condition::<F, PoseidonHash, D>(
+        &mut builder,
+        &condition_t,
+        is_claim_t,
+        preimage_t,
+        block_number_t,
+        builder._true(),
+    );
+    builder.register_public_inputs(&htlc_address_t.0.elements);
+    builder.register_public_inputs(&beneficiary_t.0.elements);
+    let data = builder.build::<C>();
+
+    let (condition, preimage) = HtlcCondition::generate(Address::rand(), Address::rand(), 10);
+    let prove = |spend: HtlcSpend<F>, block_number: u32| {
+        let mut pw = PartialWitness::new();
+        condition_t.set_witness(&mut pw, &condition);
+        let (is_claim, preimage) = match spend {
+            HtlcSpend::Claim { preimage } => (true, *preimage),
+            HtlcSpend::Refund => (false, HashOut::ZERO),
+        };
+        pw.set_bool_target(is_claim_t, is_claim);
+        pw.set_hash_target(preimage_t, preimage);
+        pw.set_target(block_number_t, F::from_canonical_u32(block_number));
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| {
+                data.verify(proof.clone())?;
+                Ok(proof.public_inputs)
+            })
+        }))
+    };
+
+    let claim = HtlcSpend::Claim { preimage };
+    let public_inputs = prove(claim, 9).unwrap().unwrap();
+    assert_eq!(
+        HashOut::from_partial(&public_inputs[0..4]),
+        condition.address().0
+    );
+    assert_eq!(
+        HashOut::from_partial(&public_inputs[4..8]),
+        condition.recipient.0
+    );
+    let public_inputs = prove(HtlcSpend::Refund, 10).unwrap().unwrap();
+    assert_eq!(
+        HashOut::from_partial(&public_inputs[4..8]),
+        condition.refund_address.0
+    );
+
+    // A claim after the timelock, a refund before it and a wrong preimage are rejected.
+    assert!(!matches!(prove(claim, 10), Ok(Ok(_))));
+    assert!(!matches!(prove(HtlcSpend::Refund, 9), Ok(Ok(_))));
+    let wrong_claim = HtlcSpend::Claim {
+        preimage: WrappedHashOut::rand(),
+    };
+    assert!(!matches!(prove(wrong_claim, 9), Ok(Ok(_))));
+}
+
+#[test]
+fn test_htlc_spend_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        hash::poseidon::PoseidonHash,
+        iop::witness::PartialWitness,
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonSparseMerkleTree,
+        },
+        transaction::{
+            block_header::BlockHeader, merge_key::transfer_merge_key, tx_hash::get_tx_hash,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    const N_LOG_MAX_USERS: usize = 3;
+    const N_LOG_MAX_TXS: usize = 3;
+    const N_LOG_TXS: usize = 3;
+    const N_LOG_RECIPIENTS: usize = 3;
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let block_number_t = builder.add_virtual_target();
+    let htlc_spend_t: HtlcSpendTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS> =
+        HtlcSpendTarget::add_virtual_to::<F, PoseidonHash, D>(&mut builder, block_number_t);
+    builder.register_public_inputs(&htlc_spend_t.new_world_state_root.elements);
+    let data = builder.build::<C>();
+
+    let (condition, _) = HtlcCondition::generate(Address::rand(), Address::rand(), 10);
+    let htlc_address: GoldilocksHashOut = condition.address().0.into();
+
+    // The escrow is a transfer to the escrow account in the block 1.
+    let sender_address = GoldilocksHashOut::rand();
+    let mut diff_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    diff_tree
+        .set(
+            htlc_address,
+            GoldilocksHashOut::from_u32(305),
+            GoldilocksHashOut::from_u32(0),
+            GoldilocksHashOut::from_u32(10),
+        )
+        .unwrap();
+    let diff_tree: PoseidonSparseMerkleTree<NodeDataMemory> = diff_tree.into();
+    let escrow_proof = diff_tree.find(&htlc_address).unwrap();
+    let nonce = GoldilocksHashOut::rand();
+    let tx_hash: WrappedHashOut<F> = get_tx_hash(*escrow_proof.root, *nonce).into();
+    let tx_proof = get_merkle_proof(&[tx_hash], 0, N_LOG_TXS);
+    let mut latest_account_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    latest_account_tree
+        .set(sender_address, GoldilocksHashOut::from_u32(1))
+        .unwrap();
+    let mut block_header = BlockHeader::<F>::with_tree_depth(N_LOG_TXS);
+    block_header.block_number = 1;
+    block_header.transactions_digest = *tx_proof.root;
+    block_header.latest_account_digest = *latest_account_tree.get_root();
+
+    // The refund address has no account yet.
+    let merge_key: GoldilocksHashOut = transfer_merge_key(*tx_hash).into();
+    let mut beneficiary_asset_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let merge_proof = MergeProof {
+        is_deposit: false,
+        diff_tree_inclusion_proof: (block_header, tx_proof, escrow_proof.clone()),
+        merge_process_proof: beneficiary_asset_tree
+            .set(merge_key, escrow_proof.value)
+            .unwrap(),
+        latest_account_tree_inclusion_proof: latest_account_tree.find(&sender_address).unwrap(),
+        nonce,
+    };
+    let mut escrow_asset_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let escrow_process_proof = escrow_asset_tree
+        .set(merge_key, escrow_proof.value)
+        .unwrap();
+    let mut world_state_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let world_state_process_proofs = [
+        world_state_tree
+            .set(htlc_address, escrow_process_proof.new_root)
+            .unwrap(),
+        world_state_tree
+            .set(
+                condition.refund_address.0.into(),
+                merge_proof.merge_process_proof.new_root,
+            )
+            .unwrap(),
+    ];
+    let witness = HtlcSpendWitness {
+        condition,
+        spend: HtlcSpend::Refund,
+        block_number: 10,
+        merge_proof,
+        old_beneficiary_asset_root: Default::default(),
+        escrow_process_proof,
+        world_state_process_proofs,
+    };
+
+    // `block_number` is that of the block header, which is set by the block circuit.
+    let prove = |block_number: u32| {
+        let mut pw = PartialWitness::new();
+        htlc_spend_t.set_witness(&mut pw, &witness).unwrap();
+        pw.set_target(block_number_t, F::from_canonical_u32(block_number));
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| {
+                data.verify(proof.clone())?;
+                Ok(proof.public_inputs)
+            })
+        }))
+    };
+
+    let public_inputs = prove(10).unwrap().unwrap();
+    assert_eq!(
+        HashOut::from_partial(&public_inputs[0..4]),
+        *world_state_tree.get_root()
+    );
+
+    // A refund before the timelock is rejected.
+    assert!(matches!(
+        validate_htlc_spend_witness(&HtlcSpendWitness {
+            block_number: 9,
+            ..witness.clone()
+        }),
+        Err(IntmaxError::InvalidWitness(_))
+    ));
+    assert!(!matches!(prove(9), Ok(Ok(_))));
+
+    // The disabled slot leaves the world state root as it is.
+    let mut pw = PartialWitness::new();
+    htlc_spend_t
+        .set_disabled_witness(&mut pw, *world_state_tree.get_root())
+        .unwrap();
+    pw.set_target(block_number_t, F::from_canonical_u32(9));
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(
+        HashOut::from_partial(&proof.public_inputs[0..4]),
+        *world_state_tree.get_root()
+    );
+}
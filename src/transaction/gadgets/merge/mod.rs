@@ -1,35 +1,44 @@
 use plonky2::{
     field::extension::Extendable,
-    hash::{
-        hash_types::{HashOut, HashOutTarget, RichField},
-        poseidon::PoseidonHash,
-    },
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
     iop::witness::Witness,
-    plonk::{
-        circuit_builder::CircuitBuilder,
-        config::{AlgebraicHasher, Hasher},
-    },
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    merkle_tree::{gadgets::MerkleProofTarget, tree::MerkleProof},
-    poseidon::gadgets::poseidon_two_to_one,
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
+    merkle_tree::{
+        gadgets::{DynMerkleProofTarget, MerkleProofTarget},
+        tree::MerkleProof,
+    },
     sparse_merkle_tree::{
         gadgets::{
             common::{conditionally_select, enforce_equal_if_enabled},
             process::{
-                process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+                process_smt::{
+                    DynSparseMerkleProcessProofTarget, SmtProcessProof,
+                    SparseMerkleProcessProofTarget,
+                },
                 utils::{get_process_merkle_proof_role, ProcessMerkleProofRoleTarget},
             },
-            verify::verify_smt::{SmtInclusionProof, SparseMerkleInclusionProofTarget},
+            verify::verify_smt::{
+                DynSparseMerkleInclusionProofTarget, SmtInclusionProof,
+                SparseMerkleInclusionProofTarget,
+            },
         },
         goldilocks_poseidon::WrappedHashOut,
         proof::ProcessMerkleProofRole,
     },
     transaction::{
+        asset::ReceivedAssetProof,
         block_header::{get_block_hash, BlockHeader},
         gadgets::block_header::{get_block_hash_target, BlockHeaderTarget},
+        merge_key::{
+            deposit_merge_key, deposit_merge_key_target, deposit_tx_hash, deposit_tx_hash_target,
+            transfer_merge_key, transfer_merge_key_target,
+        },
+        tx_hash::{get_tx_hash, get_tx_hash_target},
     },
 };
 
@@ -45,7 +54,8 @@ pub struct MergeProof<F: RichField> {
     /// asset を受け取った block の latest account tree から自身の address に関する inclusion proof を出す
     pub latest_account_tree_inclusion_proof: SmtInclusionProof<F>,
 
-    /// is_deposit が false のとき, 送信者から nonce の値を教えてもらう必要がある
+    /// is_deposit が false のとき, 送信者から nonce の値を教えてもらう必要がある.
+    /// is_deposit が true のとき, deposit transaction の nonce (`DepositTree::nonce`)
     pub nonce: WrappedHashOut<F>,
 }
 
@@ -81,6 +91,182 @@ pub struct MergeTransitionTarget<
     pub new_user_asset_root: HashOutTarget,
 }
 
+/// The same as `MergeProofTarget`, but the tree heights are decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynMergeProofTarget {
+    pub diff_tree_inclusion_proof: (
+        BlockHeaderTarget,
+        DynMerkleProofTarget,
+        DynSparseMerkleInclusionProofTarget,
+    ),
+    pub merge_process_proof: DynSparseMerkleProcessProofTarget,
+    pub address_list_inclusion_proof: DynSparseMerkleInclusionProofTarget,
+    pub nonce: HashOutTarget,
+}
+
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+    > From<MergeProofTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>>
+    for DynMergeProofTarget
+{
+    fn from(
+        value: MergeProofTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>,
+    ) -> Self {
+        Self {
+            diff_tree_inclusion_proof: (
+                value.diff_tree_inclusion_proof.0,
+                value.diff_tree_inclusion_proof.1.into(),
+                value.diff_tree_inclusion_proof.2.into(),
+            ),
+            merge_process_proof: value.merge_process_proof.into(),
+            address_list_inclusion_proof: value.address_list_inclusion_proof.into(),
+            nonce: value.nonce,
+        }
+    }
+}
+
+impl DynMergeProofTarget {
+    /// Sets `witness` without `validate_merge_witness`.
+    pub fn set_witness<F: RichField>(&self, pw: &mut impl Witness<F>, witness: &MergeProof<F>) {
+        self.diff_tree_inclusion_proof
+            .0
+            .set_witness(pw, &witness.diff_tree_inclusion_proof.0);
+        self.diff_tree_inclusion_proof.1.set_witness(
+            pw,
+            witness.diff_tree_inclusion_proof.1.index,
+            witness.diff_tree_inclusion_proof.1.value,
+            &witness.diff_tree_inclusion_proof.1.siblings,
+        );
+        self.diff_tree_inclusion_proof
+            .2
+            .set_proof_witness(pw, &witness.diff_tree_inclusion_proof.2);
+
+        self.merge_process_proof
+            .set_witness(pw, &witness.merge_process_proof);
+
+        // deposit でないときのみ検証する
+        self.address_list_inclusion_proof.set_witness(
+            pw,
+            &witness.latest_account_tree_inclusion_proof,
+            !witness.is_deposit,
+        );
+        pw.set_hash_target(self.nonce, *witness.nonce);
+    }
+}
+
+/// The same as `MergeTransitionTarget`, but the tree heights and
+/// the number of merges are decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynMergeTransitionTarget {
+    pub proofs: Vec<DynMergeProofTarget>,
+    pub old_user_asset_root: HashOutTarget,
+    pub new_user_asset_root: HashOutTarget,
+
+    /// The height of the tx diff tree of each block.
+    pub log_n_txs: usize,
+}
+
+impl DynMergeTransitionTarget {
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        log_max_n_users: usize,
+        log_max_n_txs: usize,
+        log_n_txs: usize,
+        log_n_recipients: usize,
+        n_merges: usize,
+    ) -> Self {
+        let mut proofs = vec![];
+        for _ in 0..n_merges {
+            let merge_process_proof = DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(
+                builder,
+                log_max_n_txs,
+            );
+            // The padded slots are no-ops, whose diff tree inclusion proofs are not checked.
+            let ProcessMerkleProofRoleTarget { is_not_no_op, .. } =
+                get_process_merkle_proof_role::<F, D>(builder, merge_process_proof.fnc);
+            let target = DynMergeProofTarget {
+                diff_tree_inclusion_proof: (
+                    BlockHeaderTarget::add_virtual_to::<F, H, D>(builder),
+                    DynMerkleProofTarget::add_virtual_to::<F, H, D>(builder, log_n_txs),
+                    DynSparseMerkleInclusionProofTarget::add_virtual_to_with_enabled::<F, H, D>(
+                        builder,
+                        log_n_recipients,
+                        is_not_no_op,
+                    ),
+                ),
+                merge_process_proof,
+                address_list_inclusion_proof: DynSparseMerkleInclusionProofTarget::add_virtual_to::<
+                    F,
+                    H,
+                    D,
+                >(builder, log_max_n_users),
+                nonce: builder.add_virtual_hash(),
+            };
+
+            proofs.push(target);
+        }
+
+        let old_user_asset_root = builder.add_virtual_hash();
+        let new_user_asset_root =
+            verify_dyn_user_asset_merge_proof::<F, H, D>(builder, &proofs, old_user_asset_root);
+
+        Self {
+            proofs,
+            old_user_asset_root,
+            new_user_asset_root,
+            log_n_txs,
+        }
+    }
+
+    /// Returns new_user_asset_root
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        proofs: &[MergeProof<F>],
+        old_user_asset_root: HashOut<F>,
+    ) -> Result<WrappedHashOut<F>, IntmaxError> {
+        pw.set_hash_target(self.old_user_asset_root, old_user_asset_root);
+
+        ensure_at_most("merge proofs", proofs.len(), self.proofs.len())?;
+        let new_user_asset_root = validate_merge_witness(proofs, old_user_asset_root.into())?;
+        for (target, witness) in self.proofs.iter().zip(proofs.iter()) {
+            target.set_witness(pw, witness);
+        }
+
+        let default_header = BlockHeader::with_tree_depth(self.log_n_txs);
+        let default_merkle_proof = MerkleProof::new(self.log_n_txs);
+        let default_inclusion_proof = SmtInclusionProof::with_root(Default::default());
+        let default_process_proof = SmtProcessProof::with_root(new_user_asset_root);
+        for target in self.proofs.iter().skip(proofs.len()) {
+            target
+                .diff_tree_inclusion_proof
+                .0
+                .set_witness(pw, &default_header);
+            target.diff_tree_inclusion_proof.1.set_witness(
+                pw,
+                default_merkle_proof.index,
+                default_merkle_proof.value,
+                &default_merkle_proof.siblings,
+            );
+            target.diff_tree_inclusion_proof.2.set_disabled_witness(pw);
+
+            target
+                .merge_process_proof
+                .set_witness(pw, &default_process_proof);
+
+            target
+                .address_list_inclusion_proof
+                .set_witness(pw, &default_inclusion_proof, false);
+            pw.set_hash_target(target.nonce, HashOut::ZERO);
+        }
+
+        Ok(new_user_asset_root)
+    }
+}
+
 impl<
         const N_LOG_MAX_USERS: usize,
         const N_LOG_MAX_TXS: usize,
@@ -94,16 +280,22 @@ impl<
     ) -> Self {
         let mut proofs = vec![];
         for _ in 0..N_MERGES {
+            let merge_process_proof =
+                SparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(builder);
+            // The padded slots are no-ops, whose diff tree inclusion proofs are not checked.
+            let ProcessMerkleProofRoleTarget { is_not_no_op, .. } =
+                get_process_merkle_proof_role::<F, D>(builder, merge_process_proof.fnc);
             let target = MergeProofTarget {
                 // is_deposit: builder.add_virtual_bool_target_safe(),
                 diff_tree_inclusion_proof: (
                     BlockHeaderTarget::add_virtual_to::<F, H, D>(builder),
                     MerkleProofTarget::add_virtual_to::<F, H, D>(builder),
-                    SparseMerkleInclusionProofTarget::add_virtual_to::<F, H, D>(builder),
-                ),
-                merge_process_proof: SparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(
-                    builder,
+                    SparseMerkleInclusionProofTarget::add_virtual_to_with_enabled::<F, H, D>(
+                        builder,
+                        is_not_no_op,
+                    ),
                 ),
+                merge_process_proof,
                 address_list_inclusion_proof: SparseMerkleInclusionProofTarget::add_virtual_to::<
                     F,
                     H,
@@ -139,145 +331,202 @@ impl<
         pw: &mut impl Witness<F>,
         proofs: &[MergeProof<F>],
         old_user_asset_root: HashOut<F>,
-    ) -> WrappedHashOut<F> {
-        pw.set_hash_target(self.old_user_asset_root, old_user_asset_root);
+    ) -> Result<WrappedHashOut<F>, IntmaxError> {
+        DynMergeTransitionTarget::from(self.clone()).set_witness(pw, proofs, old_user_asset_root)
+    }
+}
 
-        let first_root = old_user_asset_root.into();
-        if let Some(first_witness) = proofs.first() {
-            assert_eq!(first_witness.merge_process_proof.old_root, first_root);
+impl<
+        const N_LOG_MAX_USERS: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_TXS: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_MERGES: usize,
+    >
+    From<
+        MergeTransitionTarget<
+            N_LOG_MAX_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_MERGES,
+        >,
+    > for DynMergeTransitionTarget
+{
+    fn from(
+        value: MergeTransitionTarget<
+            N_LOG_MAX_USERS,
+            N_LOG_MAX_TXS,
+            N_LOG_TXS,
+            N_LOG_RECIPIENTS,
+            N_MERGES,
+        >,
+    ) -> Self {
+        Self {
+            proofs: value.proofs.into_iter().map(Into::into).collect(),
+            old_user_asset_root: value.old_user_asset_root,
+            new_user_asset_root: value.new_user_asset_root,
+            log_n_txs: N_LOG_TXS,
         }
+    }
+}
 
-        let mut new_user_asset_root = first_root;
-        assert!(proofs.len() <= self.proofs.len());
-        for (target, witness) in self.proofs.iter().zip(proofs.iter()) {
-            assert_ne!(
-                witness.merge_process_proof.fnc,
-                ProcessMerkleProofRole::ProcessNoOp
-            );
-
-            let block_header = &witness.diff_tree_inclusion_proof.0;
-            let root = if witness.is_deposit {
-                block_header.deposit_digest
-            } else {
-                block_header.transactions_digest
-            };
-            assert_eq!(root, *witness.diff_tree_inclusion_proof.1.root);
-            let block_hash = get_block_hash(block_header);
-
-            // purge のとき, latest_account_tree (active_account_tree) に正しい値が入っていることの検証
-            if !witness.is_deposit {
-                assert_eq!(
-                    witness.latest_account_tree_inclusion_proof.value.to_u32(),
-                    witness.diff_tree_inclusion_proof.0.block_number,
-                );
-            }
-
-            if witness.is_deposit {
-                assert_eq!(witness.nonce, Default::default());
-            };
-            let diff_root = witness.diff_tree_inclusion_proof.2.root;
-            let tx_hash = PoseidonHash::two_to_one(*diff_root, *witness.nonce).into();
-            assert_eq!(witness.diff_tree_inclusion_proof.1.value, tx_hash);
-
-            let merge_key = if witness.is_deposit {
-                // println!("deposit");
-                PoseidonHash::two_to_one(*tx_hash, block_hash).into()
-            } else {
-                // println!("purge");
-                tx_hash
-            };
+/// Checks the same conditions as the merge circuit without proving,
+/// and returns the new user asset root.
+pub fn validate_merge_witness<F: RichField>(
+    proofs: &[MergeProof<F>],
+    old_user_asset_root: WrappedHashOut<F>,
+) -> Result<WrappedHashOut<F>, IntmaxError> {
+    let mut new_user_asset_root = old_user_asset_root;
+    for (i, witness) in proofs.iter().enumerate() {
+        ensure_witness!(
+            witness.merge_process_proof.fnc != ProcessMerkleProofRole::ProcessNoOp,
+            "merge proof #{} must not be a no-op",
+            i
+        );
 
-            assert_eq!(witness.merge_process_proof.new_key, merge_key);
-            assert_eq!(witness.merge_process_proof.old_value, Default::default());
-            assert_eq!(
-                witness.merge_process_proof.new_value,
-                witness.diff_tree_inclusion_proof.2.value,
-            );
-            assert_eq!(
-                witness.diff_tree_inclusion_proof.0.latest_account_digest,
-                *witness.latest_account_tree_inclusion_proof.root,
-            ); // XXX
-            assert_eq!(witness.merge_process_proof.old_root, new_user_asset_root);
-
-            // deposit でないとき, latest_account_tree (active_account_tree) に正しい値が入っていることの検証
-            {
-                let is_not_no_op =
-                    witness.merge_process_proof.fnc != ProcessMerkleProofRole::ProcessNoOp;
-                let receiving_block_number = witness.diff_tree_inclusion_proof.0.block_number;
-                let confirmed_block_number = witness.latest_account_tree_inclusion_proof.value; // 最後に成功した block number
-                if is_not_no_op && !witness.is_deposit {
-                    assert_eq!(
-                        confirmed_block_number.0,
-                        HashOut::from_partial(&[F::from_canonical_u32(receiving_block_number)]),
-                    );
-                }
-            }
+        let block_header = &witness.diff_tree_inclusion_proof.0;
+        let root = if witness.is_deposit {
+            block_header.deposit_digest
+        } else {
+            block_header.transactions_digest
+        };
+        ensure_witness!(
+            root == *witness.diff_tree_inclusion_proof.1.root,
+            "merge proof #{} diff tree root mismatch",
+            i
+        );
+        let block_hash = get_block_hash(block_header);
 
-            // pw.set_bool_target(target.is_deposit, witness.is_deposit);
-            target
-                .diff_tree_inclusion_proof
-                .0
-                .set_witness(pw, &witness.diff_tree_inclusion_proof.0);
-            target.diff_tree_inclusion_proof.1.set_witness(
-                pw,
-                witness.diff_tree_inclusion_proof.1.index,
-                witness.diff_tree_inclusion_proof.1.value,
-                &witness.diff_tree_inclusion_proof.1.siblings,
+        // purge のとき, latest_account_tree (active_account_tree) に正しい値が入っていることの検証
+        if !witness.is_deposit {
+            ensure_witness!(
+                witness.latest_account_tree_inclusion_proof.value.to_u32()
+                    == witness.diff_tree_inclusion_proof.0.block_number,
+                "merge proof #{} block number mismatch",
+                i
             );
-            target.diff_tree_inclusion_proof.2.set_witness(
-                pw,
-                &witness.diff_tree_inclusion_proof.2,
-                true,
+            // 送信者が一度も block に含まれていないとき latest account tree の値は 0 なので,
+            // block 0 の transfer を受け取れないように block number は 0 より大きい.
+            ensure_witness!(
+                witness.diff_tree_inclusion_proof.0.block_number > 0,
+                "merge proof #{} transfer must be in a block after the genesis",
+                i
             );
+        }
 
-            target
-                .merge_process_proof
-                .set_witness(pw, &witness.merge_process_proof);
+        let diff_root = witness.diff_tree_inclusion_proof.2.root;
+        let tx_hash = if witness.is_deposit {
+            deposit_tx_hash(*diff_root, *witness.nonce)
+        } else {
+            get_tx_hash(*diff_root, *witness.nonce)
+        }
+        .into();
+        ensure_witness!(
+            witness.diff_tree_inclusion_proof.1.value == tx_hash,
+            "merge proof #{} tx_hash mismatch",
+            i
+        );
 
-            // deposit でないときのみ検証する
-            target.address_list_inclusion_proof.set_witness(
-                pw,
-                &witness.latest_account_tree_inclusion_proof,
-                !witness.is_deposit,
-            );
-            pw.set_hash_target(target.nonce, *witness.nonce);
+        let merge_key = if witness.is_deposit {
+            deposit_merge_key(*tx_hash, block_hash)
+        } else {
+            transfer_merge_key(*tx_hash)
+        };
+
+        ensure_witness!(
+            witness.merge_process_proof.new_key == merge_key,
+            "merge proof #{} merge key mismatch",
+            i
+        );
+        ensure_witness!(
+            witness.merge_process_proof.old_value == Default::default(),
+            "merge proof #{} must insert a new leaf",
+            i
+        );
+        ensure_witness!(
+            witness.merge_process_proof.new_value == witness.diff_tree_inclusion_proof.2.value,
+            "merge proof #{} new_value mismatch",
+            i
+        );
+        ensure_witness!(
+            witness.diff_tree_inclusion_proof.0.latest_account_digest
+                == *witness.latest_account_tree_inclusion_proof.root,
+            "merge proof #{} latest account root mismatch",
+            i
+        ); // XXX
+        ensure_witness!(
+            witness.merge_process_proof.old_root == new_user_asset_root,
+            "merge proof #{} old_root mismatch",
+            i
+        );
 
-            new_user_asset_root = witness.merge_process_proof.new_root
+        // deposit でないとき, latest_account_tree (active_account_tree) に正しい値が入っていることの検証
+        {
+            let is_not_no_op =
+                witness.merge_process_proof.fnc != ProcessMerkleProofRole::ProcessNoOp;
+            let receiving_block_number = witness.diff_tree_inclusion_proof.0.block_number;
+            let confirmed_block_number = witness.latest_account_tree_inclusion_proof.value; // 最後に成功した block number
+            if is_not_no_op && !witness.is_deposit {
+                ensure_witness!(
+                    confirmed_block_number.0
+                        == HashOut::from_partial(&[F::from_canonical_u32(receiving_block_number)]),
+                    "merge proof #{} confirmed block number mismatch",
+                    i
+                );
+            }
         }
 
-        let default_header = BlockHeader::with_tree_depth(N_LOG_TXS);
-        let default_merkle_proof = MerkleProof::new(N_LOG_TXS);
-        let default_inclusion_proof = SmtInclusionProof::with_root(Default::default());
-        let default_process_proof = SmtProcessProof::with_root(new_user_asset_root);
-        for target in self.proofs.iter().skip(proofs.len()) {
-            // pw.set_bool_target(target.is_deposit, true);
-            target
-                .diff_tree_inclusion_proof
-                .0
-                .set_witness(pw, &default_header);
-            target.diff_tree_inclusion_proof.1.set_witness(
-                pw,
-                default_merkle_proof.index,
-                default_merkle_proof.value,
-                &default_merkle_proof.siblings,
-            );
-            target
-                .diff_tree_inclusion_proof
-                .2
-                .set_witness(pw, &default_inclusion_proof, false);
+        new_user_asset_root = witness.merge_process_proof.new_root;
+    }
 
-            target
-                .merge_process_proof
-                .set_witness(pw, &default_process_proof);
+    Ok(new_user_asset_root)
+}
 
-            target
-                .address_list_inclusion_proof
-                .set_witness(pw, &default_inclusion_proof, false);
-            pw.set_hash_target(target.nonce, HashOut::ZERO);
-        }
+/// Groups `received` by merge key, keeping the order of the first asset of each group.
+/// The deposits of a block to a recipient are the leaves of one subtree of the deposit tree,
+/// which is merged as a whole under one merge key, so they take one merge slot
+/// however many `(contract, variable)` leaves they have.
+pub fn batch_received_assets<F: RichField>(
+    received: &[ReceivedAssetProof<F>],
+) -> Result<Vec<ReceivedAssetProof<F>>, IntmaxError> {
+    let mut batches: Vec<(WrappedHashOut<F>, ReceivedAssetProof<F>)> = vec![];
+    for (i, proof) in received.iter().enumerate() {
+        let merge_key = proof.merge_key();
+        let batch = match batches.iter_mut().find(|(key, _)| *key == merge_key) {
+            Some((_, batch)) => batch,
+            None => {
+                batches.push((
+                    merge_key,
+                    ReceivedAssetProof {
+                        assets: vec![],
+                        ..proof.clone()
+                    },
+                ));
+                &mut batches.last_mut().unwrap().1
+            }
+        };
 
-        new_user_asset_root
+        ensure_witness!(
+            batch.is_deposit == proof.is_deposit
+                && batch.diff_tree_inclusion_proof.2 == proof.diff_tree_inclusion_proof.2,
+            "received asset #{} has the same merge key as another one but a different subtree",
+            i
+        );
+        for asset in proof.assets.iter() {
+            match batch.assets.iter().find(|a| a.kind == asset.kind) {
+                // The same leaf may be reported twice.
+                Some(a) => ensure_witness!(
+                    a.amount == asset.amount,
+                    "received asset #{} has two amounts of the same token",
+                    i
+                ),
+                None => batch.assets.push(*asset),
+            }
+        }
     }
+
+    Ok(batches.into_iter().map(|(_, batch)| batch).collect())
 }
 
 pub fn verify_user_asset_merge_proof<
@@ -292,6 +541,24 @@ pub fn verify_user_asset_merge_proof<
     builder: &mut CircuitBuilder<F, D>,
     proofs: &[MergeProofTarget<N_LOG_MAX_USERS, N_LOG_MAX_TXS, N_LOG_TXS, N_LOG_RECIPIENTS>],
     old_user_asset_root: HashOutTarget,
+) -> HashOutTarget {
+    let proofs = proofs
+        .iter()
+        .cloned()
+        .map(DynMergeProofTarget::from)
+        .collect::<Vec<_>>();
+
+    verify_dyn_user_asset_merge_proof::<F, H, D>(builder, &proofs, old_user_asset_root)
+}
+
+pub fn verify_dyn_user_asset_merge_proof<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    proofs: &[DynMergeProofTarget],
+    old_user_asset_root: HashOutTarget,
 ) -> HashOutTarget {
     let zero = builder.zero();
     let default_hash = HashOutTarget {
@@ -299,7 +566,7 @@ pub fn verify_user_asset_merge_proof<
     };
 
     let mut new_user_asset_root = old_user_asset_root;
-    for MergeProofTarget {
+    for DynMergeProofTarget {
         // is_deposit: actual_is_deposit,
         merge_process_proof,
         diff_tree_inclusion_proof,
@@ -327,10 +594,27 @@ pub fn verify_user_asset_merge_proof<
         //     is_not_no_op,
         // ); // XXX
 
+        // deposit のとき, deposit block circuit が nonce を deposit_digest に含めているので,
+        // nonce は block header に commit された deposit transaction のものに限られる.
+        {
+            let is_deposit = builder.not(is_not_deposit);
+            let is_deposit_merge = builder.and(is_not_no_op, is_deposit);
+            enforce_equal_if_enabled(
+                builder,
+                root,
+                diff_tree_inclusion_proof.1.root,
+                is_deposit_merge,
+            );
+        }
+
         let receiving_block_number = diff_tree_inclusion_proof.0.block_number;
         let confirmed_block_number = address_list_inclusion_proof.value; // 最後に成功した block number
 
         // purge のとき, latest_account_tree (active_account_tree) に正しい値が入っていることの検証
+        // latest_account_tree は transfer を含む block の header に commit されたもので,
+        // 送信者がその block で承認すると last block number はその block number に更新される.
+        // したがって receiving_block_number > confirmed_block_number ではなく等号を拘束する.
+        // 前者は送信者が承認せず revert された transfer や, 以前の block の transfer の replay を許してしまう.
         {
             let check_block_number = builder.and(is_not_no_op, is_not_deposit);
             enforce_equal_if_enabled(
@@ -339,18 +623,26 @@ pub fn verify_user_asset_merge_proof<
                 HashOutTarget::from_partial(&[receiving_block_number], zero),
                 check_block_number,
             );
-        }
 
-        // deposit のとき, nonce は 0
-        {
-            let is_deposit = builder.not(is_not_deposit);
-            enforce_equal_if_enabled(builder, *nonce, default_hash, is_deposit);
+            // 送信者が一度も block に含まれていないとき latest account tree の値は 0 なので,
+            // 0 < receiving_block_number を拘束する.
+            let one = builder.one();
+            let diff = builder.sub(receiving_block_number, one);
+            let diff = builder.mul(diff, check_block_number.target);
+            builder.range_check(diff, 32);
         }
 
         // diff_tree_inclusion_proof.2.root と diff_tree_inclusion_proof.1.value の関係を拘束する
         {
+            let deposit_tx_hash = deposit_tx_hash_target::<F, H, D>(
+                builder,
+                diff_tree_inclusion_proof.2.root,
+                *nonce,
+            );
+            let transfer_tx_hash =
+                get_tx_hash_target::<F, H, D>(builder, diff_tree_inclusion_proof.2.root, *nonce);
             let inclusion1_proof_value =
-                poseidon_two_to_one::<F, H, D>(builder, diff_tree_inclusion_proof.2.root, *nonce);
+                conditionally_select(builder, transfer_tx_hash, deposit_tx_hash, is_not_deposit);
             enforce_equal_if_enabled(
                 builder,
                 diff_tree_inclusion_proof.1.value,
@@ -363,8 +655,9 @@ pub fn verify_user_asset_merge_proof<
         let block_hash = get_block_hash_target::<F, H, D>(builder, &diff_tree_inclusion_proof.0);
         let merge_key = {
             let tx_hash = diff_tree_inclusion_proof.1.value;
-            let deposit_merge_key = poseidon_two_to_one::<F, H, D>(builder, tx_hash, block_hash);
-            let purge_merge_key = tx_hash;
+            let deposit_merge_key =
+                deposit_merge_key_target::<F, H, D>(builder, tx_hash, block_hash);
+            let purge_merge_key = transfer_merge_key_target(tx_hash);
 
             conditionally_select(builder, purge_merge_key, deposit_merge_key, is_not_deposit)
         };
@@ -416,7 +709,7 @@ fn test_merge_proof_by_plonky2() {
     };
 
     use plonky2::{
-        field::types::Sample,
+        field::types::{Field, Sample},
         iop::witness::PartialWitness,
         plonk::{
             circuit_builder::CircuitBuilder,
@@ -506,9 +799,9 @@ fn test_merge_proof_by_plonky2() {
 
     let merge_inclusion_proof2 = deposit_sender2_tree.find(&sender2_address.into()).unwrap();
 
-    let deposit_nonce = HashOut::ZERO;
-    let deposit_tx_hash =
-        PoseidonHash::two_to_one(*merge_inclusion_proof2.root, deposit_nonce).into();
+    // The nonce of the deposit transaction, e.g. the L1 event index.
+    let deposit_nonce = HashOut::from_partial(&[F::from_canonical_u64(10), F::ONE]);
+    let deposit_tx_hash = deposit_tx_hash(*merge_inclusion_proof2.root, deposit_nonce).into();
 
     let merge_inclusion_proof1 = get_merkle_proof(&[deposit_tx_hash], 0, N_LOG_TXS);
 
@@ -523,10 +816,14 @@ fn test_merge_proof_by_plonky2() {
         proposed_world_state_digest: default_hash,
         approved_world_state_digest: default_hash,
         latest_account_digest: default_hash,
+        forced_transactions_digest: default_hash,
+        issuer_registry_root: default_hash,
+        timestamp: 0,
+        aggregator_address: default_hash,
     };
     let block_hash = get_block_hash(&prev_block_header);
 
-    let deposit_merge_key = PoseidonHash::two_to_one(*deposit_tx_hash, block_hash).into();
+    let deposit_merge_key = deposit_merge_key(*deposit_tx_hash, block_hash);
 
     let merge_process_proof = sender2_user_asset_tree
         .set(deposit_merge_key, merge_inclusion_proof2.value)
@@ -546,7 +843,9 @@ fn test_merge_proof_by_plonky2() {
 
     let mut pw = PartialWitness::new();
 
-    merge_proof_target.set_witness(&mut pw, &[merge_proof], default_hash);
+    merge_proof_target
+        .set_witness(&mut pw, &[merge_proof], default_hash)
+        .unwrap();
 
     println!("start proving: sender2_tx_proof");
     let start = Instant::now();
@@ -554,3 +853,290 @@ fn test_merge_proof_by_plonky2() {
     let end = start.elapsed();
     println!("prove: {}.{:03} sec", end.as_secs(), end.subsec_millis());
 }
+
+#[test]
+fn test_merge_proof_with_uncommitted_deposit_nonce() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::types::{Field, Sample},
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder,
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::{
+            goldilocks_poseidon::{
+                GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+                PoseidonSparseMerkleTree,
+            },
+            proof::SparseMerkleInclusionProof,
+        },
+        transaction::block_header::BlockHeader,
+    };
+
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+    const D: usize = 2;
+
+    const N_LOG_MAX_USERS: usize = 3;
+    const N_LOG_MAX_TXS: usize = 3;
+    const N_LOG_TXS: usize = 3;
+    const N_LOG_RECIPIENTS: usize = 3;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let merge_proof_target: MergeTransitionTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        1,
+    > = MergeTransitionTarget::add_virtual_to::<F, H, D>(&mut builder);
+    let data = builder.build::<C>();
+
+    let recipient_address = GoldilocksHashOut::rand();
+    let mut deposit_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    deposit_tree
+        .set(
+            recipient_address,
+            GoldilocksHashOut::from_u128(305),
+            GoldilocksHashOut::from_u128(0),
+            GoldilocksHashOut::from_u128(10),
+        )
+        .unwrap();
+    let deposit_tree: PoseidonSparseMerkleTree<NodeDataMemory> = deposit_tree.into();
+    let recipient_proof = deposit_tree.find(&recipient_address).unwrap();
+
+    // The block header commits the deposit transaction with `committed_nonce`.
+    let committed_nonce = HashOut::from_partial(&[F::from_canonical_u64(10), F::ONE]);
+    let committed_tx_hash: WrappedHashOut<F> =
+        deposit_tx_hash(*recipient_proof.root, committed_nonce).into();
+    let mut block_header = BlockHeader::<F>::with_tree_depth(N_LOG_TXS);
+    block_header.deposit_digest = *get_merkle_proof(&[committed_tx_hash], 0, N_LOG_TXS).root;
+    let block_hash = get_block_hash(&block_header);
+
+    // The same deposits with another nonce have another merge key,
+    // so they would be merged twice if the nonce were not committed.
+    let make_merge_proof = |nonce: HashOut<F>| {
+        let tx_hash: WrappedHashOut<F> = deposit_tx_hash(*recipient_proof.root, nonce).into();
+        let mut user_asset_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+
+        MergeProof {
+            is_deposit: true,
+            diff_tree_inclusion_proof: (
+                block_header.clone(),
+                get_merkle_proof(&[tx_hash], 0, N_LOG_TXS),
+                recipient_proof.clone(),
+            ),
+            merge_process_proof: user_asset_tree
+                .set(
+                    deposit_merge_key(*tx_hash, block_hash),
+                    recipient_proof.value,
+                )
+                .unwrap(),
+            latest_account_tree_inclusion_proof: SparseMerkleInclusionProof::with_root(
+                Default::default(),
+            ),
+            nonce: nonce.into(),
+        }
+    };
+
+    validate_merge_witness(&[make_merge_proof(committed_nonce)], Default::default()).unwrap();
+
+    let uncommitted_nonce = HashOut::from_partial(&[F::from_canonical_u64(10), F::TWO]);
+    let uncommitted_merge_proof = make_merge_proof(uncommitted_nonce);
+    assert!(matches!(
+        validate_merge_witness(&[uncommitted_merge_proof.clone()], Default::default()),
+        Err(IntmaxError::InvalidWitness(_))
+    ));
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(merge_proof_target.old_user_asset_root, HashOut::ZERO);
+    DynMergeProofTarget::from(merge_proof_target.proofs[0].clone())
+        .set_witness(&mut pw, &uncommitted_merge_proof);
+    let result = catch_unwind(AssertUnwindSafe(|| data.prove(pw)));
+    assert!(!matches!(result, Ok(Ok(_))));
+}
+
+#[test]
+fn test_validate_transfer_merge_witness() {
+    use std::sync::{Arc, Mutex};
+
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Sample};
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonSparseMerkleTree,
+        },
+    };
+
+    type F = GoldilocksField;
+    const N_LOG_TXS: usize = 3;
+
+    let sender_address = GoldilocksHashOut::rand();
+    let recipient_address = GoldilocksHashOut::rand();
+    let node_data = Arc::new(Mutex::new(NodeDataMemory::default()));
+    let mut diff_tree = LayeredLayeredPoseidonSparseMerkleTree::new(node_data, Default::default());
+    diff_tree
+        .set(
+            recipient_address,
+            GoldilocksHashOut::from_u32(305),
+            GoldilocksHashOut::from_u32(0),
+            GoldilocksHashOut::from_u32(10),
+        )
+        .unwrap();
+    let diff_tree: PoseidonSparseMerkleTree<NodeDataMemory> = diff_tree.into();
+    let recipient_proof = diff_tree.find(&recipient_address).unwrap();
+    let nonce = GoldilocksHashOut::rand();
+    let tx_hash: WrappedHashOut<F> = get_tx_hash(*recipient_proof.root, *nonce).into();
+    let tx_proof = get_merkle_proof(&[tx_hash], 0, N_LOG_TXS);
+
+    // The transfer is in `block_number`, where `latest_block_number` is of the sender.
+    let make_merge_proof = |block_number: u32, latest_block_number: Option<u32>| {
+        let mut latest_account_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+        if let Some(latest_block_number) = latest_block_number {
+            latest_account_tree
+                .set(
+                    sender_address,
+                    GoldilocksHashOut::from_u32(latest_block_number),
+                )
+                .unwrap();
+        }
+        let mut block_header = BlockHeader::<F>::with_tree_depth(N_LOG_TXS);
+        block_header.block_number = block_number;
+        block_header.transactions_digest = *tx_proof.root;
+        block_header.latest_account_digest = *latest_account_tree.get_root();
+        let mut user_asset_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+
+        MergeProof {
+            is_deposit: false,
+            diff_tree_inclusion_proof: (block_header, tx_proof.clone(), recipient_proof.clone()),
+            merge_process_proof: user_asset_tree
+                .set(transfer_merge_key(*tx_hash), recipient_proof.value)
+                .unwrap(),
+            latest_account_tree_inclusion_proof: latest_account_tree.find(&sender_address).unwrap(),
+            nonce,
+        }
+    };
+
+    validate_merge_witness(&[make_merge_proof(1, Some(1))], Default::default()).unwrap();
+    assert!(validate_merge_witness(&[make_merge_proof(2, Some(1))], Default::default()).is_err());
+
+    // The sender which has never been in a block has the latest block number 0.
+    assert!(validate_merge_witness(&[make_merge_proof(0, None)], Default::default()).is_err());
+}
+
+#[test]
+fn test_transfer_merge_proof_block_number_by_plonky2() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use plonky2::{
+        field::types::Sample,
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_builder::CircuitBuilder,
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::goldilocks_poseidon::{
+            GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+            PoseidonSparseMerkleTree,
+        },
+    };
+
+    type C = PoseidonGoldilocksConfig;
+    type H = <C as GenericConfig<D>>::InnerHasher;
+    type F = <C as GenericConfig<D>>::F;
+    const D: usize = 2;
+
+    const N_LOG_MAX_USERS: usize = 3;
+    const N_LOG_MAX_TXS: usize = 3;
+    const N_LOG_TXS: usize = 3;
+    const N_LOG_RECIPIENTS: usize = 3;
+
+    let config = CircuitConfig::standard_recursion_config();
+    let mut builder = CircuitBuilder::<F, D>::new(config);
+    let merge_proof_target: MergeTransitionTarget<
+        N_LOG_MAX_USERS,
+        N_LOG_MAX_TXS,
+        N_LOG_TXS,
+        N_LOG_RECIPIENTS,
+        1,
+    > = MergeTransitionTarget::add_virtual_to::<F, H, D>(&mut builder);
+    let data = builder.build::<C>();
+
+    let sender_address = GoldilocksHashOut::rand();
+    let recipient_address = GoldilocksHashOut::rand();
+    let mut diff_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    diff_tree
+        .set(
+            recipient_address,
+            GoldilocksHashOut::from_u32(305),
+            GoldilocksHashOut::from_u32(0),
+            GoldilocksHashOut::from_u32(10),
+        )
+        .unwrap();
+    let diff_tree: PoseidonSparseMerkleTree<NodeDataMemory> = diff_tree.into();
+    let recipient_proof = diff_tree.find(&recipient_address).unwrap();
+    let nonce = GoldilocksHashOut::rand();
+    let tx_hash: WrappedHashOut<F> = get_tx_hash(*recipient_proof.root, *nonce).into();
+    let tx_proof = get_merkle_proof(&[tx_hash], 0, N_LOG_TXS);
+
+    // The transfer is in `block_number`, where `latest_block_number` is of the sender.
+    let make_merge_proof = |block_number: u32, latest_block_number: u32| {
+        let mut latest_account_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+        latest_account_tree
+            .set(
+                sender_address,
+                GoldilocksHashOut::from_u32(latest_block_number),
+            )
+            .unwrap();
+        let mut block_header = BlockHeader::<F>::with_tree_depth(N_LOG_TXS);
+        block_header.block_number = block_number;
+        block_header.transactions_digest = *tx_proof.root;
+        block_header.latest_account_digest = *latest_account_tree.get_root();
+        let mut user_asset_tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+
+        MergeProof {
+            is_deposit: false,
+            diff_tree_inclusion_proof: (block_header, tx_proof.clone(), recipient_proof.clone()),
+            merge_process_proof: user_asset_tree
+                .set(transfer_merge_key(*tx_hash), recipient_proof.value)
+                .unwrap(),
+            latest_account_tree_inclusion_proof: latest_account_tree.find(&sender_address).unwrap(),
+            nonce,
+        }
+    };
+    let prove = |merge_proof: &MergeProof<F>| {
+        let mut pw = PartialWitness::new();
+        pw.set_hash_target(merge_proof_target.old_user_asset_root, HashOut::ZERO);
+        DynMergeProofTarget::from(merge_proof_target.proofs[0].clone())
+            .set_witness(&mut pw, merge_proof);
+        catch_unwind(AssertUnwindSafe(|| {
+            data.prove(pw).and_then(|proof| data.verify(proof))
+        }))
+    };
+
+    prove(&make_merge_proof(2, 2)).unwrap().unwrap();
+
+    // The sender approved a later block, but not the block of the transfer,
+    // so the transfer was reverted.
+    assert!(!matches!(prove(&make_merge_proof(2, 3)), Ok(Ok(_))));
+    // The sender did not approve the block of the transfer, which is after the last approval.
+    assert!(!matches!(prove(&make_merge_proof(2, 1)), Ok(Ok(_))));
+    // The transfers of the genesis block are never merged.
+    assert!(!matches!(prove(&make_merge_proof(0, 0)), Ok(Ok(_))));
+}
@@ -0,0 +1,332 @@
+//! The insertion of new supply into the user asset tree of the issuer.
+//!
+//! A mint inserts the leaf `(mint_merge_key(message), contract_address, variable_index) => amount`
+//! only with the issuer's simple signature to `message = MintRequest::message`,
+//! where the issuer is committed to `contract_address` in the issuer registry.
+//! The message commits `block_number`, which the mint block checks against the block of the mint,
+//! so the same signed mint is inserted at most once, even after the leaf is purged.
+//! NOTICE: The issuer must use a fresh nonce for each mint of the same amount in a block.
+
+use plonky2::{
+    field::{extension::Extendable, goldilocks_field::GoldilocksField, types::Field},
+    hash::{
+        hash_types::{HashOut, HashOutTarget},
+        poseidon::PoseidonHash,
+    },
+    iop::{target::Target, witness::Witness},
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::CircuitData,
+        config::{AlgebraicHasher, GenericConfig},
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use crate::{
+    errors::{ensure_witness, IntmaxError},
+    recursion::gadgets::RecursiveProofTarget,
+    sparse_merkle_tree::{
+        gadgets::{
+            process::{
+                process_smt::{LayeredLayeredSmtProcessProof, SparseMerkleProcessProofTarget},
+                utils::{get_process_merkle_proof_role, verify_layered_smt_connection},
+            },
+            verify::verify_smt::{SmtInclusionProof, SparseMerkleInclusionProofTarget},
+        },
+        goldilocks_poseidon::WrappedHashOut,
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::{
+        gadgets::purge::validate_layered_connection,
+        issuance::{MintRequest, MINT_AMOUNT_BITS},
+        merge_key::{mint_merge_key, mint_merge_key_target},
+    },
+    zkdsa::{
+        account::Address,
+        circuits::{parse_simple_signature_public_inputs, SimpleSignatureProofWithPublicInputs},
+        gadgets::account::AddressTarget,
+    },
+};
+
+type F = GoldilocksField;
+
+#[derive(Clone)]
+pub struct MintTransitionTarget<
+    const D: usize,
+    const N_LOG_MAX_TXS: usize,
+    const N_LOG_MAX_CONTRACTS: usize,
+    const N_LOG_MAX_VARIABLES: usize,
+    const N_LOG_ISSUERS: usize,
+> {
+    /// The issuer, who is also the owner of the user asset tree.
+    pub issuer_address: AddressTarget, // input
+
+    /// The insertion of the minted asset.
+    pub asset_proof: (
+        SparseMerkleProcessProofTarget<N_LOG_MAX_TXS>,
+        SparseMerkleProcessProofTarget<N_LOG_MAX_CONTRACTS>,
+        SparseMerkleProcessProofTarget<N_LOG_MAX_VARIABLES>,
+    ), // input
+
+    /// 同じ mint を区別するための値.
+    pub nonce: HashOutTarget, // input
+
+    /// The block which includes the mint.
+    pub block_number: Target, // input
+
+    /// The inclusion proof of `contract_address => issuer_address` in the issuer registry.
+    pub issuer_proof: SparseMerkleInclusionProofTarget<N_LOG_ISSUERS>, // input
+
+    /// The simple signature of the issuer to `MintRequest::message`.
+    pub issuer_signature: RecursiveProofTarget<D>, // input
+
+    pub old_user_asset_root: HashOutTarget,  // input
+    pub new_user_asset_root: HashOutTarget,  // output
+    pub issuer_registry_root: HashOutTarget, // output
+    pub contract_address: HashOutTarget,     // output
+    pub variable_index: HashOutTarget,       // output
+    pub amount: Target,                      // output
+}
+
+/// The hash of `[contract_address, variable_index, amount, nonce, block_number]` in the circuit,
+/// i.e. `MintRequest::message`.
+pub fn get_mint_message_target<const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    contract_address: HashOutTarget,
+    variable_index: HashOutTarget,
+    amount: Target,
+    nonce: HashOutTarget,
+    block_number: Target,
+) -> HashOutTarget
+where
+    F: Extendable<D>,
+{
+    let inputs = [
+        contract_address.elements.to_vec(),
+        variable_index.elements.to_vec(),
+        vec![amount],
+        nonce.elements.to_vec(),
+        vec![block_number],
+    ]
+    .concat();
+
+    builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs)
+}
+
+impl<
+        const D: usize,
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_ISSUERS: usize,
+    >
+    MintTransitionTarget<D, N_LOG_MAX_TXS, N_LOG_MAX_CONTRACTS, N_LOG_MAX_VARIABLES, N_LOG_ISSUERS>
+where
+    F: Extendable<D>,
+{
+    pub fn add_virtual_to<C: GenericConfig<D, F = F>>(
+        builder: &mut CircuitBuilder<F, D>,
+        simple_signature_circuit_data: &CircuitData<F, C, D>,
+    ) -> Self
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let constant_true = builder._true();
+        let constant_false = builder._false();
+        let zero = builder.zero();
+
+        let issuer_address = AddressTarget::add_virtual_to(builder);
+        let old_user_asset_root = builder.add_virtual_hash();
+        let nonce = builder.add_virtual_hash();
+        let block_number = builder.add_virtual_target();
+        builder.range_check(block_number, 32);
+        let proof0_t =
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, PoseidonHash, D>(builder);
+        let proof1_t =
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, PoseidonHash, D>(builder);
+        let proof2_t =
+            SparseMerkleProcessProofTarget::add_virtual_to::<F, PoseidonHash, D>(builder);
+
+        verify_layered_smt_connection::<F, D>(
+            builder,
+            proof0_t.fnc,
+            proof0_t.old_value,
+            proof0_t.new_value,
+            proof1_t.old_root,
+            proof1_t.new_root,
+        );
+        verify_layered_smt_connection::<F, D>(
+            builder,
+            proof1_t.fnc,
+            proof1_t.old_value,
+            proof1_t.new_value,
+            proof2_t.old_root,
+            proof2_t.new_root,
+        );
+
+        // 3 つの layer がすべて ProcessInsertOp であること
+        for fnc in [proof0_t.fnc, proof1_t.fnc, proof2_t.fnc] {
+            let role = get_process_merkle_proof_role(builder, fnc);
+            builder.connect(role.is_insert_op.target, constant_true.target);
+        }
+
+        // proof2_t.new_value (mint する asset) が 2^56 未満の値であること
+        let contract_address = proof1_t.new_key;
+        let variable_index = proof2_t.new_key;
+        let amount = proof2_t.new_value.elements[0];
+        builder.range_check(amount, MINT_AMOUNT_BITS);
+        builder.connect(proof2_t.new_value.elements[1], zero);
+        builder.connect(proof2_t.new_value.elements[2], zero);
+        builder.connect(proof2_t.new_value.elements[3], zero);
+
+        // The registry has the issuer of the contract.
+        let issuer_proof =
+            SparseMerkleInclusionProofTarget::add_virtual_to::<F, PoseidonHash, D>(builder);
+        builder.connect(issuer_proof.enabled.target, constant_true.target);
+        builder.connect(issuer_proof.fnc.target, constant_false.target);
+        builder.connect_hashes(issuer_proof.key, contract_address);
+        builder.connect_hashes(issuer_proof.value, issuer_address.0);
+
+        // The issuer signs the mint.
+        let issuer_signature =
+            RecursiveProofTarget::add_virtual_to(builder, simple_signature_circuit_data);
+        builder.connect(issuer_signature.enabled.target, constant_true.target);
+        let signature_public_inputs =
+            parse_simple_signature_public_inputs(&issuer_signature.inner.public_inputs);
+        builder.connect_hashes(signature_public_inputs.public_key, issuer_address.0);
+        let message = get_mint_message_target(
+            builder,
+            contract_address,
+            variable_index,
+            amount,
+            nonce,
+            block_number,
+        );
+        builder.connect_hashes(signature_public_inputs.message, message);
+
+        // 署名された mint を merge key として user asset tree に挿入する
+        builder.connect_hashes(proof0_t.new_key, mint_merge_key_target(message));
+        builder.connect_hashes(proof0_t.old_root, old_user_asset_root);
+        let new_user_asset_root = proof0_t.new_root;
+        let issuer_registry_root = issuer_proof.root;
+
+        Self {
+            issuer_address,
+            asset_proof: (proof0_t, proof1_t, proof2_t),
+            nonce,
+            block_number,
+            issuer_proof,
+            issuer_signature,
+            old_user_asset_root,
+            new_user_asset_root,
+            issuer_registry_root,
+            contract_address,
+            variable_index,
+            amount,
+        }
+    }
+
+    /// Returns the new user asset root.
+    pub fn set_witness<C: GenericConfig<D, F = F>>(
+        &self,
+        pw: &mut impl Witness<F>,
+        request: &MintRequest,
+        asset_witness: &LayeredLayeredSmtProcessProof<F>,
+        issuer_proof: &SmtInclusionProof<F>,
+        issuer_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+        old_user_asset_root: WrappedHashOut<F>,
+    ) -> Result<WrappedHashOut<F>, IntmaxError>
+    where
+        C::Hasher: AlgebraicHasher<F>,
+    {
+        let new_user_asset_root = validate_mint_transition_witness(
+            request,
+            asset_witness,
+            issuer_proof,
+            issuer_signature,
+            old_user_asset_root,
+        )?;
+
+        self.issuer_address
+            .set_witness(pw, Address(*issuer_proof.value));
+        self.asset_proof.0.set_witness(pw, &asset_witness.0);
+        self.asset_proof.1.set_witness(pw, &asset_witness.1);
+        self.asset_proof.2.set_witness(pw, &asset_witness.2);
+        pw.set_hash_target(self.nonce, *request.nonce);
+        pw.set_target(
+            self.block_number,
+            F::from_canonical_u32(request.block_number),
+        );
+        self.issuer_proof.set_witness(pw, issuer_proof, true);
+        let issuer_signature: ProofWithPublicInputs<F, C, D> = issuer_signature.clone().into();
+        self.issuer_signature
+            .set_witness(pw, &issuer_signature, true);
+        pw.set_hash_target(self.old_user_asset_root, *old_user_asset_root);
+
+        Ok(new_user_asset_root)
+    }
+}
+
+/// Checks the same conditions as `MintTransitionTarget` without proving,
+/// and returns the new user asset root.
+pub fn validate_mint_transition_witness<C: GenericConfig<D, F = F>, const D: usize>(
+    request: &MintRequest,
+    asset_witness: &LayeredLayeredSmtProcessProof<F>,
+    issuer_proof: &SmtInclusionProof<F>,
+    issuer_signature: &SimpleSignatureProofWithPublicInputs<F, C, D>,
+    old_user_asset_root: WrappedHashOut<F>,
+) -> Result<WrappedHashOut<F>, IntmaxError>
+where
+    F: Extendable<D>,
+{
+    ensure_witness!(
+        request.amount < 1 << MINT_AMOUNT_BITS,
+        "the amount {} of a mint must be less than 2^{}",
+        request.amount,
+        MINT_AMOUNT_BITS
+    );
+    ensure_witness!(
+        issuer_proof.found && *issuer_proof.key == request.kind.contract_address.0,
+        "{} has no issuer",
+        request.kind.contract_address
+    );
+    ensure_witness!(
+        issuer_signature.public_inputs.public_key == *issuer_proof.value,
+        "the mint is not signed by the issuer {}",
+        Address(*issuer_proof.value)
+    );
+    ensure_witness!(
+        issuer_signature.public_inputs.message == request.message(),
+        "the signature of the issuer is not for the mint"
+    );
+
+    let (w0, w1, w2) = asset_witness;
+    ensure_witness!(
+        [w0.fnc, w1.fnc, w2.fnc]
+            .iter()
+            .all(|fnc| *fnc == ProcessMerkleProofRole::ProcessInsert),
+        "the mint must insert a new asset"
+    );
+    ensure_witness!(
+        w0.old_root == old_user_asset_root,
+        "mint proof old_root mismatch"
+    );
+    ensure_witness!(
+        w0.new_key == mint_merge_key(request.message()),
+        "the minted asset must be merged with the mint message"
+    );
+    validate_layered_connection(w0, w1)
+        .map_err(|err| IntmaxError::InvalidWitness(format!("mint proof: {}", err)))?;
+    validate_layered_connection(w1, w2)
+        .map_err(|err| IntmaxError::InvalidWitness(format!("mint proof: {}", err)))?;
+    ensure_witness!(
+        *w1.new_key == request.kind.contract_address.0 && w2.new_key == request.kind.variable_index,
+        "the minted asset is not of the token of the mint"
+    );
+    ensure_witness!(
+        *w2.new_value == HashOut::from_partial(&[F::from_canonical_u64(request.amount)]),
+        "the minted amount is not the amount of the mint"
+    );
+
+    Ok(w0.new_root)
+}
@@ -1,5 +1,8 @@
 pub mod asset_mess;
+pub mod attachment;
 pub mod block_header;
+pub mod htlc;
 pub mod merge;
+pub mod mint;
 pub mod purge;
 pub mod utils;
@@ -1,27 +1,29 @@
 use plonky2::{
     field::extension::Extendable,
-    hash::{
-        hash_types::{HashOutTarget, RichField},
-        poseidon::PoseidonHash,
-    },
+    hash::hash_types::{HashOutTarget, RichField},
     iop::witness::Witness,
-    plonk::{
-        circuit_builder::CircuitBuilder,
-        config::{AlgebraicHasher, Hasher},
-    },
+    plonk::{circuit_builder::CircuitBuilder, config::AlgebraicHasher},
 };
 
 use crate::{
-    poseidon::gadgets::poseidon_two_to_one,
+    errors::{ensure_at_most, ensure_witness, IntmaxError},
     sparse_merkle_tree::{
         gadgets::{
             common::{enforce_equal_if_enabled, logical_or, logical_xor},
             process::{
-                process_smt::{SmtProcessProof, SparseMerkleProcessProofTarget},
+                process_smt::{
+                    DynSparseMerkleProcessProofTarget, SmtProcessProof,
+                    SparseMerkleProcessProofTarget,
+                },
                 utils::verify_layered_smt_connection,
             },
         },
         goldilocks_poseidon::WrappedHashOut,
+        proof::ProcessMerkleProofRole,
+    },
+    transaction::{
+        burn::get_burned_assets_digest_target,
+        tx_hash::{get_tx_hash, get_tx_hash_target},
     },
     zkdsa::{account::Address, gadgets::account::AddressTarget},
 };
@@ -44,6 +46,9 @@ pub struct PurgeTransitionTarget<
         SparseMerkleProcessProofTarget<LOG_MAX_N_CONTRACTS>,
         SparseMerkleProcessProofTarget<LOG_MAX_N_VARIABLES>,
     ); N_DIFFS], // input
+
+    /// The leaves of the diff tree, which may be addressed to the sender itself,
+    /// e.g. the change or a consolidation of the user asset tree.
     pub output_proofs: [(
         SparseMerkleProcessProofTarget<N_LOG_RECIPIENTS>,
         SparseMerkleProcessProofTarget<LOG_N_CONTRACTS>,
@@ -51,7 +56,7 @@ pub struct PurgeTransitionTarget<
     ); N_DIFFS], // input
     pub old_user_asset_root: HashOutTarget, // input
     pub new_user_asset_root: HashOutTarget, // output
-    pub diff_root: HashOutTarget,      // output
+    pub diff_root: HashOutTarget,           // output
 
     /// tx_hash が被らないようにするための値.
     pub nonce: HashOutTarget, // input
@@ -59,6 +64,9 @@ pub struct PurgeTransitionTarget<
     /// `hash(diff_root, nonce)` で計算される transaction ごとに unique な値
     /// NOTICE: deposit の場合は計算方法が異なる.
     pub tx_hash: HashOutTarget, // output
+
+    /// The digest of the assets sent to `burn_address`, i.e. `get_burned_assets_digest`.
+    pub burned_assets_digest: HashOutTarget, // output
 }
 
 impl<
@@ -104,33 +112,185 @@ impl<
             })
             .collect::<Vec<_>>();
 
-        let (new_user_asset_root, diff_root, tx_hash) = verify_user_asset_purge_proof::<
-            F,
-            H,
-            D,
+        let (new_user_asset_root, diff_root, tx_hash, burned_assets_digest) =
+            verify_user_asset_purge_proof::<
+                F,
+                H,
+                D,
+                N_LOG_MAX_TXS,
+                N_LOG_MAX_CONTRACTS,
+                N_LOG_MAX_VARIABLES,
+                N_LOG_RECIPIENTS,
+                N_LOG_CONTRACTS,
+                N_LOG_VARIABLES,
+            >(
+                builder,
+                &input_proofs_t,
+                &output_proofs_t,
+                old_user_asset_root,
+                nonce,
+            );
+
+        Self {
+            sender_address,
+            input_proofs: input_proofs_t.try_into().unwrap(),
+            output_proofs: output_proofs_t.try_into().unwrap(),
+            old_user_asset_root,
+            new_user_asset_root,
+            diff_root,
+            nonce,
+            tx_hash,
+            burned_assets_digest,
+        }
+    }
+
+    /// Returns (new_user_asset_root, tx_diff_root)
+    pub fn set_witness<F: RichField>(
+        &self,
+        pw: &mut impl Witness<F>,
+        sender_address: Address<F>,
+        input_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        output_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+        old_user_asset_root: WrappedHashOut<F>,
+        nonce: WrappedHashOut<F>,
+    ) -> Result<(WrappedHashOut<F>, WrappedHashOut<F>, WrappedHashOut<F>), IntmaxError> {
+        DynPurgeTransitionTarget::from(self.clone()).set_witness(
+            pw,
+            sender_address,
+            input_witness,
+            output_witness,
+            old_user_asset_root,
+            nonce,
+        )
+    }
+}
+
+impl<
+        const N_LOG_MAX_TXS: usize,
+        const N_LOG_MAX_CONTRACTS: usize,
+        const N_LOG_MAX_VARIABLES: usize,
+        const N_LOG_RECIPIENTS: usize,
+        const N_LOG_CONTRACTS: usize,
+        const N_LOG_VARIABLES: usize,
+        const N_DIFFS: usize,
+    >
+    From<
+        PurgeTransitionTarget<
             N_LOG_MAX_TXS,
             N_LOG_MAX_CONTRACTS,
             N_LOG_MAX_VARIABLES,
             N_LOG_RECIPIENTS,
             N_LOG_CONTRACTS,
             N_LOG_VARIABLES,
-        >(
-            builder,
-            &input_proofs_t,
-            &output_proofs_t,
-            old_user_asset_root,
-            nonce,
-        );
+            N_DIFFS,
+        >,
+    > for DynPurgeTransitionTarget
+{
+    fn from(
+        value: PurgeTransitionTarget<
+            N_LOG_MAX_TXS,
+            N_LOG_MAX_CONTRACTS,
+            N_LOG_MAX_VARIABLES,
+            N_LOG_RECIPIENTS,
+            N_LOG_CONTRACTS,
+            N_LOG_VARIABLES,
+            N_DIFFS,
+        >,
+    ) -> Self {
+        Self {
+            sender_address: value.sender_address,
+            input_proofs: value
+                .input_proofs
+                .into_iter()
+                .map(|(p0, p1, p2)| (p0.into(), p1.into(), p2.into()))
+                .collect(),
+            output_proofs: value
+                .output_proofs
+                .into_iter()
+                .map(|(p0, p1, p2)| (p0.into(), p1.into(), p2.into()))
+                .collect(),
+            old_user_asset_root: value.old_user_asset_root,
+            new_user_asset_root: value.new_user_asset_root,
+            diff_root: value.diff_root,
+            nonce: value.nonce,
+            tx_hash: value.tx_hash,
+            burned_assets_digest: value.burned_assets_digest,
+        }
+    }
+}
+
+pub type DynLayeredLayeredProcessProofTarget = (
+    DynSparseMerkleProcessProofTarget,
+    DynSparseMerkleProcessProofTarget,
+    DynSparseMerkleProcessProofTarget,
+);
+
+/// The same as `PurgeTransitionTarget`, but the tree heights and
+/// the number of diffs are decided at runtime.
+#[derive(Clone, Debug)]
+pub struct DynPurgeTransitionTarget {
+    pub sender_address: AddressTarget,
+    pub input_proofs: Vec<DynLayeredLayeredProcessProofTarget>,
+    pub output_proofs: Vec<DynLayeredLayeredProcessProofTarget>,
+    pub old_user_asset_root: HashOutTarget,
+    pub new_user_asset_root: HashOutTarget,
+    pub diff_root: HashOutTarget,
+    pub nonce: HashOutTarget,
+    pub tx_hash: HashOutTarget,
+    pub burned_assets_digest: HashOutTarget,
+}
+
+impl DynPurgeTransitionTarget {
+    /// `input_levels` and `output_levels` are the heights of the three layers
+    /// of the user asset tree and the tx diff tree respectively.
+    pub fn add_virtual_to<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+        builder: &mut CircuitBuilder<F, D>,
+        input_levels: [usize; 3],
+        output_levels: [usize; 3],
+        n_diffs: usize,
+    ) -> Self {
+        let sender_address = AddressTarget::add_virtual_to(builder);
+        let old_user_asset_root = builder.add_virtual_hash();
+        let nonce = builder.add_virtual_hash();
+        let add_layered_proofs = |builder: &mut CircuitBuilder<F, D>, levels: [usize; 3]| {
+            (0..n_diffs)
+                .map(|_| {
+                    (
+                        DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(
+                            builder, levels[0],
+                        ),
+                        DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(
+                            builder, levels[1],
+                        ),
+                        DynSparseMerkleProcessProofTarget::add_virtual_to::<F, H, D>(
+                            builder, levels[2],
+                        ),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let input_proofs = add_layered_proofs(builder, input_levels);
+        let output_proofs = add_layered_proofs(builder, output_levels);
+
+        let (new_user_asset_root, diff_root, tx_hash, burned_assets_digest) =
+            verify_dyn_user_asset_purge_proof::<F, H, D>(
+                builder,
+                &input_proofs,
+                &output_proofs,
+                old_user_asset_root,
+                nonce,
+            );
 
         Self {
             sender_address,
-            input_proofs: input_proofs_t.try_into().unwrap(),
-            output_proofs: output_proofs_t.try_into().unwrap(),
+            input_proofs,
+            output_proofs,
             old_user_asset_root,
             new_user_asset_root,
             diff_root,
             nonce,
             tx_hash,
+            burned_assets_digest,
         }
     }
 
@@ -143,11 +303,15 @@ impl<
         output_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
         old_user_asset_root: WrappedHashOut<F>,
         nonce: WrappedHashOut<F>,
-    ) -> (WrappedHashOut<F>, WrappedHashOut<F>, WrappedHashOut<F>) {
+    ) -> Result<(WrappedHashOut<F>, WrappedHashOut<F>, WrappedHashOut<F>), IntmaxError> {
         self.sender_address.set_witness(pw, sender_address);
         pw.set_hash_target(self.old_user_asset_root, *old_user_asset_root);
         pw.set_hash_target(self.nonce, *nonce);
-        assert!(input_witness.len() <= self.input_proofs.len());
+        ensure_at_most(
+            "purge input proofs",
+            input_witness.len(),
+            self.input_proofs.len(),
+        )?;
         for ((p0_t, p1_t, p2_t), (w0, w1, w2)) in self.input_proofs.iter().zip(input_witness.iter())
         {
             p0_t.set_witness(pw, w0);
@@ -157,7 +321,10 @@ impl<
 
         let first_input_root = old_user_asset_root;
         if let Some(first_input_witness) = input_witness.first() {
-            assert_eq!(first_input_witness.0.old_root, first_input_root);
+            ensure_witness!(
+                first_input_witness.0.old_root == first_input_root,
+                "purge input proof #0 old_root mismatch"
+            );
         }
 
         let (last_input_root0, last_input_root1, last_input_root2) =
@@ -180,7 +347,11 @@ impl<
             p2_t.set_witness(pw, &default_witness2);
         }
 
-        assert!(output_witness.len() <= self.output_proofs.len());
+        ensure_at_most(
+            "purge output proofs",
+            output_witness.len(),
+            self.output_proofs.len(),
+        )?;
         for ((p0_t, p1_t, p2_t), (w0, w1, w2)) in
             self.output_proofs.iter().zip(output_witness.iter())
         {
@@ -191,7 +362,10 @@ impl<
 
         let first_output_root = Default::default();
         if let Some(first_output_witness) = output_witness.first() {
-            assert_eq!(first_output_witness.0.old_root, first_output_root);
+            ensure_witness!(
+                first_output_witness.0.old_root == first_output_root,
+                "purge output proof #0 old_root must be zero"
+            );
         }
 
         let (last_output_root0, last_output_root1, last_output_root2) =
@@ -217,13 +391,151 @@ impl<
 
         let new_user_asset_root = last_input_root0;
         let diff_root = last_output_root0;
-        let tx_hash = PoseidonHash::two_to_one(*diff_root, *nonce).into();
+        let tx_hash = get_tx_hash(*diff_root, *nonce).into();
+
+        Ok((new_user_asset_root, diff_root, tx_hash))
+    }
+}
+
+/// Assets are amounts of a token specified by `(contract_address, token_id)`.
+type AssetKey<F> = (WrappedHashOut<F>, WrappedHashOut<F>);
+
+/// Checks the same conditions as the purge circuit without proving:
+/// the roots of the process proofs are chained, the layered trees are connected,
+/// amounts are less than 2^56 and the purged assets are equal to the sent assets.
+pub fn validate_purge_witness<F: RichField>(
+    input_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+    output_witness: &[(SmtProcessProof<F>, SmtProcessProof<F>, SmtProcessProof<F>)],
+    old_user_asset_root: WrappedHashOut<F>,
+) -> Result<(), IntmaxError> {
+    let mut input_assets: Vec<(AssetKey<F>, u64)> = vec![];
+    let mut prev_root = old_user_asset_root;
+    for (i, (w0, w1, w2)) in input_witness.iter().enumerate() {
+        if i == 0 || w0.fnc != ProcessMerkleProofRole::ProcessNoOp {
+            ensure_witness!(
+                w0.old_root == prev_root,
+                "purge input proof #{} old_root mismatch",
+                i
+            );
+        }
+        prev_root = w0.new_root;
+
+        validate_layered_connection(w0, w1).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge input proof #{}: {}", i, err))
+        })?;
+        validate_layered_connection(w1, w2).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge input proof #{}: {}", i, err))
+        })?;
+
+        let amount = validate_amount(w2.old_value).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge input proof #{}: {}", i, err))
+        })?;
+        add_asset(&mut input_assets, (w1.old_key, w2.old_key), amount);
+    }
+
+    let mut output_assets: Vec<(AssetKey<F>, u64)> = vec![];
+    let mut prev_root = Default::default();
+    for (i, (w0, w1, w2)) in output_witness.iter().enumerate() {
+        if i == 0 || w0.fnc != ProcessMerkleProofRole::ProcessNoOp {
+            ensure_witness!(
+                w0.old_root == prev_root,
+                "purge output proof #{} old_root mismatch",
+                i
+            );
+        }
+        prev_root = w0.new_root;
+
+        validate_layered_connection(w0, w1).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge output proof #{}: {}", i, err))
+        })?;
+        validate_layered_connection(w1, w2).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge output proof #{}: {}", i, err))
+        })?;
+        ensure_witness!(
+            w2.fnc == ProcessMerkleProofRole::ProcessInsert
+                || w2.fnc == ProcessMerkleProofRole::ProcessNoOp,
+            "purge output proof #{} must insert an asset",
+            i
+        );
+
+        let amount = validate_amount(w2.new_value).map_err(|err| {
+            IntmaxError::InvalidWitness(format!("purge output proof #{}: {}", i, err))
+        })?;
+        add_asset(&mut output_assets, (w1.new_key, w2.new_key), amount);
+    }
+
+    input_assets.retain(|(_, amount)| *amount != 0);
+    output_assets.retain(|(_, amount)| *amount != 0);
+    for (key, amount) in input_assets.iter() {
+        let sent_amount = output_assets
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default();
+        ensure_witness!(
+            sent_amount == *amount,
+            "purged amount {} of token (contract_address: {}, token_id: {}) is not equal to sent amount {}",
+            amount,
+            key.0,
+            key.1,
+            sent_amount
+        );
+    }
+    ensure_witness!(
+        input_assets.len() == output_assets.len(),
+        "sent assets contain a token which is not purged"
+    );
+
+    Ok(())
+}
 
-        (new_user_asset_root, diff_root, tx_hash)
+/// The native counterpart of `verify_layered_smt_connection`.
+pub(crate) fn validate_layered_connection<F: RichField>(
+    upper: &SmtProcessProof<F>,
+    lower: &SmtProcessProof<F>,
+) -> Result<(), String> {
+    let default_hash = WrappedHashOut::default();
+    let fnc = upper.fnc;
+    let is_insert = fnc == ProcessMerkleProofRole::ProcessInsert;
+    let is_update = fnc == ProcessMerkleProofRole::ProcessUpdate;
+    let is_remove = fnc == ProcessMerkleProofRole::ProcessDelete;
+    if is_insert && lower.old_root != default_hash {
+        return Err("the lower tree must be empty before insertion".to_string());
     }
+    if (is_insert || is_update) && lower.new_root != upper.new_value {
+        return Err("new root of the lower tree mismatch".to_string());
+    }
+    if is_remove && lower.new_root != default_hash {
+        return Err("the lower tree must be empty after removal".to_string());
+    }
+    if (is_remove || is_update) && lower.old_root != upper.old_value {
+        return Err("old root of the lower tree mismatch".to_string());
+    }
+    if fnc == ProcessMerkleProofRole::ProcessNoOp && lower.new_root != lower.old_root {
+        return Err("the lower tree must not change in no-op".to_string());
+    }
+
+    Ok(())
 }
 
-// Returns (`new_user_asset_root`, `tx_hash`)
+fn validate_amount<F: RichField>(value: WrappedHashOut<F>) -> Result<u64, String> {
+    let amount = value.elements[0].to_canonical_u64();
+    if amount >= 1 << 56 || value.elements[1..].iter().any(|e| !e.is_zero()) {
+        return Err(format!("amount must be less than 2^56: {}", value));
+    }
+
+    Ok(amount)
+}
+
+fn add_asset<F: RichField>(assets: &mut Vec<(AssetKey<F>, u64)>, key: AssetKey<F>, amount: u64) {
+    if let Some((_, total)) = assets.iter_mut().find(|(k, _)| *k == key) {
+        *total += amount;
+    } else {
+        assets.push((key, amount));
+    }
+}
+
+// Returns (`new_user_asset_root`, `diff_root`, `tx_hash`, `burned_assets_digest`)
 pub fn verify_user_asset_purge_proof<
     F: RichField + Extendable<D>,
     H: AlgebraicHasher<F>,
@@ -248,7 +560,37 @@ pub fn verify_user_asset_purge_proof<
     )],
     old_user_asset_root: HashOutTarget,
     nonce: HashOutTarget,
-) -> (HashOutTarget, HashOutTarget, HashOutTarget) {
+) -> (HashOutTarget, HashOutTarget, HashOutTarget, HashOutTarget) {
+    let input_proofs_t = input_proofs_t
+        .iter()
+        .map(|(p0, p1, p2)| (p0.clone().into(), p1.clone().into(), p2.clone().into()))
+        .collect::<Vec<_>>();
+    let output_proofs_t = output_proofs_t
+        .iter()
+        .map(|(p0, p1, p2)| (p0.clone().into(), p1.clone().into(), p2.clone().into()))
+        .collect::<Vec<_>>();
+
+    verify_dyn_user_asset_purge_proof::<F, H, D>(
+        builder,
+        &input_proofs_t,
+        &output_proofs_t,
+        old_user_asset_root,
+        nonce,
+    )
+}
+
+// Returns (`new_user_asset_root`, `diff_root`, `tx_hash`, `burned_assets_digest`)
+pub fn verify_dyn_user_asset_purge_proof<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    input_proofs_t: &[DynLayeredLayeredProcessProofTarget],
+    output_proofs_t: &[DynLayeredLayeredProcessProofTarget],
+    old_user_asset_root: HashOutTarget,
+    nonce: HashOutTarget,
+) -> (HashOutTarget, HashOutTarget, HashOutTarget, HashOutTarget) {
     let constant_true = builder.constant_bool(true);
     let constant_false = builder.constant_bool(false);
     let zero = builder.zero();
@@ -369,9 +711,17 @@ pub fn verify_user_asset_purge_proof<
     let new_user_asset_root = input_proofs_t.last().unwrap().0.new_root;
     builder.connect_hashes(output_proofs_t.first().unwrap().0.old_root, default_hash);
     let diff_root = output_proofs_t.last().unwrap().0.new_root;
-    let tx_hash = poseidon_two_to_one::<F, H, D>(builder, diff_root, nonce);
+    let tx_hash = get_tx_hash_target::<F, H, D>(builder, diff_root, nonce);
+
+    // 送金先が burn address である asset の digest
+    let burned_assets_digest = get_burned_assets_digest_target(builder, output_proofs_t);
 
-    (new_user_asset_root, diff_root, tx_hash)
+    (
+        new_user_asset_root,
+        diff_root,
+        tx_hash,
+        burned_assets_digest,
+    )
 }
 
 #[test]
@@ -481,14 +831,16 @@ fn test_purge_proof_by_plonky2() {
     let nonce = WrappedHashOut::rand();
 
     let mut pw = PartialWitness::new();
-    target.set_witness(
-        &mut pw,
-        sender_address,
-        &input_witness,
-        &output_witness,
-        input_witness.first().unwrap().0.old_root,
-        nonce,
-    );
+    target
+        .set_witness(
+            &mut pw,
+            sender_address,
+            &input_witness,
+            &output_witness,
+            input_witness.first().unwrap().0.old_root,
+            nonce,
+        )
+        .unwrap();
 
     println!("start proving");
     let start = Instant::now();
@@ -0,0 +1,149 @@
+//! Hash-time-locked conditional payments, e.g. for atomic swaps with another system.
+//!
+//! An escrow is a transfer to `HtlcCondition::address`, which nobody knows the private key of.
+//! Before `timelock`, the recipient claims the escrowed assets with the preimage of `hash_lock`,
+//! and from the block `timelock` on, the refund address takes them back.
+//! The HTLC spend slot of the block circuit (`transaction::gadgets::htlc`) merges the escrowed assets into the user asset tree
+//! of the beneficiary, and inserts the same merge key into the user asset tree of the escrow account,
+//! so that an escrow is spent only once, either claimed or refunded.
+//! NOTICE: The other system must lock with the same Poseidon hash of the preimage,
+//! and its timelock must expire earlier than that of the escrow here.
+
+use plonky2::{
+    field::types::{Field, Sample},
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut, zkdsa::account::Address};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "Address<F>: Deserialize<'de>, WrappedHashOut<F>: Deserialize<'de>"))]
+pub struct HtlcCondition<F: RichField> {
+    /// `htlc_hash_lock(preimage)`
+    pub hash_lock: WrappedHashOut<F>,
+
+    /// The block number from which the escrow can be refunded and no longer claimed.
+    pub timelock: u32,
+
+    pub recipient: Address<F>,
+    pub refund_address: Address<F>,
+}
+
+/// How an escrow is spent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "WrappedHashOut<F>: Deserialize<'de>"))]
+pub enum HtlcSpend<F: RichField> {
+    Claim { preimage: WrappedHashOut<F> },
+    Refund,
+}
+
+pub fn htlc_hash_lock<F: RichField>(preimage: HashOut<F>) -> HashOut<F> {
+    PoseidonHash::hash_no_pad(&preimage.elements)
+}
+
+impl<F: RichField> HtlcCondition<F> {
+    /// Makes a condition locked with a fresh preimage, which the wallet keeps secret
+    /// until it is revealed by the claim.
+    pub fn generate(
+        recipient: Address<F>,
+        refund_address: Address<F>,
+        timelock: u32,
+    ) -> (Self, WrappedHashOut<F>) {
+        let preimage = HashOut::rand();
+        let condition = Self {
+            hash_lock: htlc_hash_lock(preimage).into(),
+            timelock,
+            recipient,
+            refund_address,
+        };
+
+        (condition, preimage.into())
+    }
+
+    /// `[hash_lock, timelock, recipient, refund_address]`
+    pub fn encode(&self) -> Vec<F> {
+        let mut encoded = vec![];
+        encoded.extend(self.hash_lock.elements);
+        encoded.push(F::from_canonical_u32(self.timelock));
+        encoded.extend(self.recipient.0.elements);
+        encoded.extend(self.refund_address.0.elements);
+
+        encoded
+    }
+
+    /// The escrow account, which receives the escrowed assets.
+    pub fn address(&self) -> Address<F> {
+        Address(PoseidonHash::hash_no_pad(&self.encode()))
+    }
+
+    /// The address to which `spend` in the block `block_number` merges the escrowed assets.
+    pub fn beneficiary(
+        &self,
+        spend: &HtlcSpend<F>,
+        block_number: u32,
+    ) -> anyhow::Result<Address<F>> {
+        match spend {
+            HtlcSpend::Claim { preimage } => {
+                anyhow::ensure!(
+                    htlc_hash_lock(**preimage) == *self.hash_lock,
+                    "the preimage does not match the hash lock"
+                );
+                anyhow::ensure!(
+                    block_number < self.timelock,
+                    "the escrow expired at block {}",
+                    self.timelock
+                );
+
+                Ok(self.recipient)
+            }
+            HtlcSpend::Refund => {
+                anyhow::ensure!(
+                    block_number >= self.timelock,
+                    "the escrow cannot be refunded until block {}",
+                    self.timelock
+                );
+
+                Ok(self.refund_address)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_htlc_condition() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    type F = GoldilocksField;
+
+    let recipient = Address::<F>::rand();
+    let refund_address = Address::<F>::rand();
+    let (condition, preimage) = HtlcCondition::generate(recipient, refund_address, 10);
+    assert_ne!(condition.address(), recipient);
+    assert_ne!(
+        condition.address(),
+        HtlcCondition {
+            timelock: 11,
+            ..condition
+        }
+        .address()
+    );
+
+    let claim = HtlcSpend::Claim { preimage };
+    assert_eq!(condition.beneficiary(&claim, 9).unwrap(), recipient);
+    assert!(condition.beneficiary(&claim, 10).is_err());
+    let wrong_claim = HtlcSpend::Claim {
+        preimage: WrappedHashOut::rand(),
+    };
+    assert!(condition.beneficiary(&wrong_claim, 9).is_err());
+
+    assert!(condition.beneficiary(&HtlcSpend::Refund, 9).is_err());
+    assert_eq!(
+        condition.beneficiary(&HtlcSpend::Refund, 10).unwrap(),
+        refund_address
+    );
+}
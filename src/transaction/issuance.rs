@@ -0,0 +1,228 @@
+//! The mint authorities of the tokens.
+//!
+//! Any `contract_address` can be used to key the assets of a user asset tree, so without a registry
+//! anyone could claim to hold a token of any contract. `IssuerRegistry` commits each contract
+//! to the zkdsa public key of its issuer in a sparse Merkle tree, and the mint circuit
+//! (`transaction::circuits::mint`) accepts new supply only with the issuer's signature to `MintRequest::message`.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::{hash_types::HashOut, poseidon::PoseidonHash},
+    plonk::config::Hasher,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::{process::process_smt::SmtProcessProof, verify::verify_smt::SmtInclusionProof},
+        goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree, WrappedHashOut},
+    },
+    transaction::asset::TokenKind,
+    verification::merkle::verify_smt_inclusion_proof,
+    zkdsa::account::{Address, PublicKey},
+};
+
+type F = GoldilocksField;
+
+/// The amount of a mint must be less than `2^MINT_AMOUNT_BITS`, the same as an asset leaf.
+pub const MINT_AMOUNT_BITS: usize = 56;
+
+/// New supply of `amount` of `kind`. `nonce` distinguishes the mints of the same amount in a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintRequest {
+    pub kind: TokenKind<F>,
+    pub amount: u64,
+    pub nonce: WrappedHashOut<F>,
+
+    /// The block which includes the mint, so that the issuer's signature cannot be replayed
+    /// in another block after the minted asset is purged from the user asset tree.
+    pub block_number: u32,
+}
+
+impl MintRequest {
+    /// `[contract_address, variable_index, amount, nonce, block_number]`
+    pub fn encode(&self) -> Vec<F> {
+        let mut encoded = vec![];
+        encoded.extend(self.kind.contract_address.0.elements);
+        encoded.extend(self.kind.variable_index.elements);
+        encoded.push(F::from_canonical_u64(self.amount));
+        encoded.extend(self.nonce.elements);
+        encoded.push(F::from_canonical_u32(self.block_number));
+
+        encoded
+    }
+
+    /// The message the issuer signs to authorize the mint.
+    pub fn message(&self) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&self.encode())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.amount < 1 << MINT_AMOUNT_BITS,
+            "the amount {} of a mint must be less than 2^{}",
+            self.amount,
+            MINT_AMOUNT_BITS
+        );
+
+        Ok(())
+    }
+}
+
+/// Checks that `proof` shows `issuer` of `contract_address` in the registry with `root`.
+pub fn verify_issuer(
+    root: HashOut<F>,
+    contract_address: Address<F>,
+    issuer: PublicKey<F>,
+    proof: &SmtInclusionProof<F>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(proof.found, "the contract has no issuer");
+    anyhow::ensure!(*proof.root == root, "the proof is for another root");
+    anyhow::ensure!(
+        *proof.key == contract_address.0,
+        "the proof is for another contract"
+    );
+    anyhow::ensure!(
+        *proof.value == issuer,
+        "{} is not the issuer of {}",
+        Address(issuer),
+        contract_address
+    );
+
+    let siblings = proof.siblings.iter().map(|v| **v).collect::<Vec<_>>();
+    verify_smt_inclusion_proof(
+        root,
+        *proof.key,
+        *proof.value,
+        proof.found,
+        *proof.not_found_key,
+        *proof.not_found_value,
+        proof.is_old0,
+        &siblings,
+    )
+}
+
+#[derive(Debug)]
+pub struct IssuerRegistry {
+    issuers: HashMap<Address<F>, PublicKey<F>>,
+    tree: PoseidonSparseMerkleTree<NodeDataMemory>,
+}
+
+impl Default for IssuerRegistry {
+    fn default() -> Self {
+        Self {
+            issuers: HashMap::new(),
+            tree: PoseidonSparseMerkleTree::new(
+                Arc::new(Mutex::new(NodeDataMemory::default())),
+                Default::default(),
+            ),
+        }
+    }
+}
+
+impl IssuerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.issuers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issuers.is_empty()
+    }
+
+    /// The root of the registry tree, which the mint circuit takes as a public input.
+    pub fn root(&self) -> HashOut<F> {
+        *self.tree.get_root()
+    }
+
+    /// Commits `contract_address` to `issuer`. The issuer of a contract cannot be changed,
+    /// since the tokens already minted would be attributed to the new one.
+    pub fn register(
+        &mut self,
+        contract_address: Address<F>,
+        issuer: PublicKey<F>,
+    ) -> anyhow::Result<SmtProcessProof<F>> {
+        anyhow::ensure!(issuer != HashOut::ZERO, "the issuer must not be zero");
+        anyhow::ensure!(
+            !self.issuers.contains_key(&contract_address),
+            "{} already has the issuer {}",
+            contract_address,
+            Address(self.issuers[&contract_address])
+        );
+
+        let proof = self.tree.insert(contract_address.0.into(), issuer.into())?;
+        self.issuers.insert(contract_address, issuer);
+
+        Ok(proof)
+    }
+
+    pub fn get(&self, contract_address: &Address<F>) -> Option<PublicKey<F>> {
+        self.issuers.get(contract_address).copied()
+    }
+
+    /// The inclusion proof of the issuer of `contract_address`, or its non-inclusion proof.
+    pub fn prove(&self, contract_address: &Address<F>) -> anyhow::Result<SmtInclusionProof<F>> {
+        self.tree.find(&contract_address.0.into())
+    }
+}
+
+#[test]
+fn test_issuer_registry() {
+    use plonky2::field::types::Sample;
+
+    use crate::zkdsa::account::private_key_to_account;
+
+    let issuer = private_key_to_account(HashOut::rand());
+    let contract_address = Address::rand();
+    let mut registry = IssuerRegistry::new();
+    registry
+        .register(contract_address, issuer.public_key)
+        .unwrap();
+    assert!(registry
+        .register(contract_address, issuer.public_key)
+        .is_err());
+    assert!(registry.register(Address::rand(), HashOut::ZERO).is_err());
+    assert_eq!(registry.get(&contract_address), Some(issuer.public_key));
+
+    let proof = registry.prove(&contract_address).unwrap();
+    verify_issuer(registry.root(), contract_address, issuer.public_key, &proof).unwrap();
+    assert!(verify_issuer(registry.root(), contract_address, HashOut::rand(), &proof).is_err());
+    let unregistered = Address::rand();
+    let proof = registry.prove(&unregistered).unwrap();
+    assert!(verify_issuer(registry.root(), unregistered, issuer.public_key, &proof).is_err());
+
+    let request = MintRequest {
+        kind: TokenKind {
+            contract_address,
+            variable_index: WrappedHashOut::ZERO,
+        },
+        amount: 1 << MINT_AMOUNT_BITS,
+        nonce: WrappedHashOut::rand(),
+        block_number: 1,
+    };
+    assert!(request.validate().is_err());
+    assert_ne!(
+        request.message(),
+        MintRequest {
+            amount: 1,
+            ..request
+        }
+        .message()
+    );
+    assert_ne!(
+        request.message(),
+        MintRequest {
+            block_number: 2,
+            ..request
+        }
+        .message()
+    );
+}
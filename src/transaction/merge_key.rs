@@ -0,0 +1,119 @@
+//! The keys under which the received assets are merged into the user asset tree.
+//! A transfer is merged with its `tx_hash`, and a deposit with `hash(tx_hash, block_hash)`,
+//! where the `tx_hash` of a deposit is `hash(deposit_tree_root, nonce)`.
+//! The nonce distinguishes the deposit transactions of the same deposits, e.g. the L1 event index.
+//! A mint is merged with `MintRequest::message`, which commits the block of the mint,
+//! so the same signed mint cannot be inserted twice even after the minted asset is purged.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        config::{AlgebraicHasher, Hasher},
+    },
+};
+
+use crate::{
+    poseidon::gadgets::poseidon_two_to_one, sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut,
+};
+
+pub fn deposit_tx_hash<F: RichField>(
+    deposit_tree_root: HashOut<F>,
+    nonce: HashOut<F>,
+) -> HashOut<F> {
+    PoseidonHash::two_to_one(deposit_tree_root, nonce)
+}
+
+/// `block_hash` is the hash of the block header including the deposit.
+pub fn deposit_merge_key<F: RichField>(
+    deposit_tx_hash: HashOut<F>,
+    block_hash: HashOut<F>,
+) -> WrappedHashOut<F> {
+    PoseidonHash::two_to_one(deposit_tx_hash, block_hash).into()
+}
+
+pub fn transfer_merge_key<F: RichField>(tx_hash: HashOut<F>) -> WrappedHashOut<F> {
+    tx_hash.into()
+}
+
+/// `mint_message` is `MintRequest::message` signed by the issuer.
+pub fn mint_merge_key<F: RichField>(mint_message: HashOut<F>) -> WrappedHashOut<F> {
+    mint_message.into()
+}
+
+/// The in-circuit counterpart of `deposit_tx_hash`.
+pub fn deposit_tx_hash_target<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    deposit_tree_root: HashOutTarget,
+    nonce: HashOutTarget,
+) -> HashOutTarget {
+    poseidon_two_to_one::<F, H, D>(builder, deposit_tree_root, nonce)
+}
+
+/// The in-circuit counterpart of `deposit_merge_key`.
+pub fn deposit_merge_key_target<
+    F: RichField + Extendable<D>,
+    H: AlgebraicHasher<F>,
+    const D: usize,
+>(
+    builder: &mut CircuitBuilder<F, D>,
+    deposit_tx_hash: HashOutTarget,
+    block_hash: HashOutTarget,
+) -> HashOutTarget {
+    poseidon_two_to_one::<F, H, D>(builder, deposit_tx_hash, block_hash)
+}
+
+/// The in-circuit counterpart of `transfer_merge_key`.
+pub fn transfer_merge_key_target(tx_hash: HashOutTarget) -> HashOutTarget {
+    tx_hash
+}
+
+/// The in-circuit counterpart of `mint_merge_key`.
+pub fn mint_merge_key_target(mint_message: HashOutTarget) -> HashOutTarget {
+    mint_message
+}
+
+#[test]
+fn test_deposit_merge_key_target() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        iop::witness::{PartialWitness, Witness},
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    let tx_hash = HashOut::<F>::rand();
+    let block_hash = HashOut::<F>::rand();
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let tx_hash_t = builder.add_virtual_hash();
+    let block_hash_t = builder.add_virtual_hash();
+    let merge_key_t =
+        deposit_merge_key_target::<F, PoseidonHash, D>(&mut builder, tx_hash_t, block_hash_t);
+    builder.register_public_inputs(&merge_key_t.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(tx_hash_t, tx_hash);
+    pw.set_hash_target(block_hash_t, block_hash);
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(
+        HashOut::from_partial(&proof.public_inputs),
+        *deposit_merge_key(tx_hash, block_hash)
+    );
+    assert_ne!(
+        deposit_merge_key(tx_hash, block_hash),
+        transfer_merge_key(tx_hash)
+    );
+}
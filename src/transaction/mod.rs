@@ -1,4 +1,13 @@
 pub mod asset;
+pub mod attachment;
 pub mod block_header;
+pub mod burn;
 pub mod circuits;
 pub mod gadgets;
+pub mod htlc;
+pub mod issuance;
+pub mod merge_key;
+pub mod pow;
+pub mod simulation;
+pub mod token_registry;
+pub mod tx_hash;
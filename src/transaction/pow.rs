@@ -0,0 +1,93 @@
+//! The optional proof of work on `tx_hash`, a spam deterrent for the deployments without fees.
+//!
+//! The work of a transaction is the number of leading zero bits of the first element of `tx_hash`.
+//! The wallet grinds the nonce until the work reaches the difficulty of the aggregator,
+//! and the aggregator checks it on admission, before generating any world state proof.
+//! Since a Goldilocks element is less than `2^64`, each bit of difficulty doubles the expected
+//! number of hashes.
+
+use plonky2::{
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+
+use crate::{
+    errors::{ensure_at_most, IntmaxError},
+    transaction::tx_hash::get_tx_hash,
+};
+
+/// The difficulty which only the zero first element of `tx_hash` meets.
+pub const MAX_POW_DIFFICULTY: u32 = u64::BITS;
+
+pub fn get_tx_hash_work<F: RichField>(tx_hash: HashOut<F>) -> u32 {
+    tx_hash.elements[0].to_canonical_u64().leading_zeros()
+}
+
+/// Checks that `tx_hash` meets `difficulty`. The difficulty 0 requires no work.
+pub fn check_tx_hash_work<F: RichField>(
+    tx_hash: HashOut<F>,
+    difficulty: u32,
+) -> anyhow::Result<()> {
+    let work = get_tx_hash_work(tx_hash);
+    anyhow::ensure!(
+        work >= difficulty,
+        "the transaction {:?} has {} bits of work, but {} are required",
+        tx_hash,
+        work,
+        difficulty
+    );
+
+    Ok(())
+}
+
+/// Derives nonces from `nonce` until the transaction of `diff_root` meets `difficulty`,
+/// and returns the nonce with the resulting `tx_hash`.
+/// The `i`-th candidate is `two_to_one(nonce, i)`, so the random part of `nonce` is kept.
+pub fn grind_nonce<F: RichField>(
+    diff_root: HashOut<F>,
+    nonce: HashOut<F>,
+    difficulty: u32,
+) -> Result<(HashOut<F>, HashOut<F>), IntmaxError> {
+    ensure_at_most(
+        "bits of proof of work",
+        difficulty as usize,
+        MAX_POW_DIFFICULTY as usize,
+    )?;
+
+    for i in 0u64.. {
+        let candidate =
+            PoseidonHash::two_to_one(nonce, HashOut::from_partial(&[F::from_canonical_u64(i)]));
+        let tx_hash = get_tx_hash(diff_root, candidate);
+        if get_tx_hash_work(tx_hash) >= difficulty {
+            return Ok((candidate, tx_hash));
+        }
+    }
+
+    unreachable!()
+}
+
+#[test]
+fn test_grind_nonce() {
+    use plonky2::field::{
+        goldilocks_field::GoldilocksField,
+        types::{Field, Sample},
+    };
+
+    type F = GoldilocksField;
+
+    let diff_root = HashOut::<F>::rand();
+    let nonce = HashOut::<F>::rand();
+    let (ground_nonce, tx_hash) = grind_nonce(diff_root, nonce, 8).unwrap();
+    assert_eq!(get_tx_hash(diff_root, ground_nonce), tx_hash);
+    assert!(get_tx_hash_work(tx_hash) >= 8);
+    check_tx_hash_work(tx_hash, 8).unwrap();
+    assert_eq!(grind_nonce(diff_root, nonce, 8).unwrap().0, ground_nonce);
+
+    // No work is required without the difficulty.
+    check_tx_hash_work(HashOut::<F>::from_partial(&[F::NEG_ONE]), 0).unwrap();
+    assert!(check_tx_hash_work(HashOut::<F>::from_partial(&[F::NEG_ONE]), 1).is_err());
+    assert!(grind_nonce(diff_root, nonce, MAX_POW_DIFFICULTY + 1).is_err());
+}
@@ -0,0 +1,566 @@
+//! Dry-run of user transactions.
+//!
+//! `simulate_transaction` applies the merge and purge operations of a transaction to copies of
+//! the user trees without proving, so that wallets can preview the resulting roots and
+//! aggregators can reject an invalid transaction before spending the proving time.
+//! `simulate_consolidation` gathers the leaves of the same token into one leaf of the sender,
+//! and `Transfer::burn` sends an asset to the burn address.
+//! `Transfer::htlc` escrows an asset under a hash-time-locked condition.
+
+use std::sync::{Arc, Mutex};
+
+use plonky2::{
+    field::{goldilocks_field::GoldilocksField, types::Field},
+    hash::hash_types::HashOut,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    prover::backend::UserTransactionWitness,
+    sparse_merkle_tree::goldilocks_poseidon::{
+        GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+        PoseidonSparseMerkleTree,
+    },
+    transaction::{
+        asset::{Asset, ReceivedAssetProof, TokenKind},
+        burn::{burn_address, get_burned_assets},
+        gadgets::merge::{batch_received_assets, MergeProof},
+        htlc::HtlcCondition,
+    },
+    zkdsa::account::Address,
+};
+
+type F = GoldilocksField;
+
+/// An asset stored in the user asset tree under `merge_key`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OwnedAsset {
+    pub merge_key: GoldilocksHashOut,
+    pub asset: Asset<F>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transfer {
+    pub recipient: Address<F>,
+    pub asset: Asset<F>,
+}
+
+impl Transfer {
+    /// Sends `asset` to `burn_address`, e.g. to bridge it out of the rollup.
+    pub fn burn(asset: Asset<F>) -> Self {
+        Self {
+            recipient: burn_address(),
+            asset,
+        }
+    }
+
+    /// Sends `asset` to the escrow account of `condition`,
+    /// from which it is claimed by the recipient or refunded after the timelock.
+    pub fn htlc(condition: &HtlcCondition<F>, asset: Asset<F>) -> Self {
+        Self {
+            recipient: condition.address(),
+            asset,
+        }
+    }
+}
+
+/// What a wallet knows about its own state.
+#[derive(Debug)]
+pub struct UserState {
+    pub address: Address<F>,
+    pub user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>,
+
+    /// The leaves of `user_asset_tree`, which the tree itself cannot enumerate.
+    pub assets: Vec<OwnedAsset>,
+
+    /// The received assets which are not merged yet.
+    pub pending_assets: Vec<ReceivedAssetProof<F>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedTransaction {
+    pub old_user_asset_root: GoldilocksHashOut,
+    pub middle_user_asset_root: GoldilocksHashOut,
+    pub new_user_asset_root: GoldilocksHashOut,
+    pub diff_root: GoldilocksHashOut,
+    pub tx_hash: GoldilocksHashOut,
+
+    /// The assets of `diffs` sent to `burn_address`, which `burned_assets_digest` commits to.
+    pub burned_assets: Vec<Asset<F>>,
+    pub burned_assets_digest: GoldilocksHashOut,
+
+    /// The assets purged from the user asset tree.
+    pub purged_assets: Vec<OwnedAsset>,
+
+    /// The leaves of the diff tree, including the change sent back to the sender.
+    pub diffs: Vec<Transfer>,
+
+    /// The assets left in the user asset tree after the transaction.
+    pub remaining_assets: Vec<OwnedAsset>,
+
+    pub witness: UserTransactionWitness<F>,
+}
+
+fn amount_to_hash(amount: u64) -> GoldilocksHashOut {
+    HashOut::from_partial(&[F::from_canonical_u64(amount)]).into()
+}
+
+fn clone_tree(
+    tree: &LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>,
+) -> anyhow::Result<LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>> {
+    let nodes_db = tree
+        .nodes_db
+        .lock()
+        .map_err(|err| anyhow::anyhow!("mutex poison error: {}", err))?
+        .clone();
+
+    Ok(LayeredLayeredPoseidonSparseMerkleTree::new(
+        Arc::new(Mutex::new(nodes_db)),
+        tree.get_root(),
+    ))
+}
+
+/// Merges all pending assets of `user_state`, one merge per merge key, and sends `transfers` with `nonce`.
+/// The input assets are taken in the order of `user_state.assets` after merging,
+/// and the change is sent back to the sender.
+/// `user_state` is not modified.
+pub fn simulate_transaction(
+    user_state: &UserState,
+    transfers: &[Transfer],
+    nonce: GoldilocksHashOut,
+) -> anyhow::Result<SimulatedTransaction> {
+    let user_asset_tree = clone_tree(&user_state.user_asset_tree)?;
+    let old_user_asset_root = user_asset_tree.get_root();
+    let mut assets = user_state.assets.clone();
+
+    let mut merge_tree: PoseidonSparseMerkleTree<NodeDataMemory> = user_asset_tree.into();
+    let mut merge_witnesses = vec![];
+    let pending_assets = batch_received_assets(&user_state.pending_assets)?;
+    for (i, received) in pending_assets.iter().enumerate() {
+        let recipient_proof = &received.diff_tree_inclusion_proof.2;
+        anyhow::ensure!(
+            recipient_proof.key == GoldilocksHashOut::from(user_state.address.0),
+            "pending asset #{} is not sent to {}",
+            i,
+            user_state.address
+        );
+        let merge_key = received.merge_key();
+        let merge_process_proof = merge_tree.set(merge_key, recipient_proof.value)?;
+        merge_witnesses.push(MergeProof {
+            is_deposit: received.is_deposit,
+            diff_tree_inclusion_proof: received.diff_tree_inclusion_proof.clone(),
+            merge_process_proof,
+            latest_account_tree_inclusion_proof: received
+                .latest_account_tree_inclusion_proof
+                .clone(),
+            nonce: received.nonce,
+        });
+        assets.extend(received.assets.iter().map(|asset| OwnedAsset {
+            merge_key,
+            asset: *asset,
+        }));
+    }
+    let middle_user_asset_root = merge_tree.get_root();
+    let mut user_asset_tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory> =
+        merge_tree.into();
+
+    // The amounts of the same recipient and token are summed up,
+    // since the diff tree has one leaf for each of them.
+    let mut diffs: Vec<Transfer> = vec![];
+    let mut required: Vec<(TokenKind<F>, u64)> = vec![];
+    for transfer in transfers {
+        if transfer.asset.amount == 0 {
+            continue;
+        }
+
+        if let Some(diff) = diffs.iter_mut().find(|diff| {
+            diff.recipient == transfer.recipient && diff.asset.kind == transfer.asset.kind
+        }) {
+            diff.asset.amount += transfer.asset.amount;
+        } else {
+            diffs.push(*transfer);
+        }
+
+        if let Some((_, amount)) = required
+            .iter_mut()
+            .find(|(kind, _)| *kind == transfer.asset.kind)
+        {
+            *amount += transfer.asset.amount;
+        } else {
+            required.push((transfer.asset.kind, transfer.asset.amount));
+        }
+    }
+
+    let mut purged_assets = vec![];
+    for (kind, amount) in required {
+        let mut purged_amount = 0;
+        while purged_amount < amount {
+            let index = assets
+                .iter()
+                .position(|owned| owned.asset.kind == kind && owned.asset.amount != 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "insufficient balance of token (contract_address: {}, token_id: {}): {} < {}",
+                        kind.contract_address,
+                        kind.variable_index,
+                        purged_amount,
+                        amount
+                    )
+                })?;
+            let owned = assets.remove(index);
+            purged_amount += owned.asset.amount;
+            purged_assets.push(owned);
+        }
+
+        let change = purged_amount - amount;
+        if change != 0 {
+            let change_diff = diffs
+                .iter_mut()
+                .find(|diff| diff.recipient == user_state.address && diff.asset.kind == kind);
+            if let Some(diff) = change_diff {
+                diff.asset.amount += change;
+            } else {
+                diffs.push(Transfer {
+                    recipient: user_state.address,
+                    asset: Asset {
+                        kind,
+                        amount: change,
+                    },
+                });
+            }
+        }
+    }
+
+    let mut purge_input_witnesses = vec![];
+    for owned in purged_assets.iter() {
+        purge_input_witnesses.push(user_asset_tree.set(
+            owned.merge_key,
+            owned.asset.kind.contract_address.0.into(),
+            owned.asset.kind.variable_index,
+            Default::default(),
+        )?);
+    }
+
+    let mut diff_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let mut purge_output_witnesses = vec![];
+    for diff in diffs.iter() {
+        purge_output_witnesses.push(diff_tree.set(
+            diff.recipient.0.into(),
+            diff.asset.kind.contract_address.0.into(),
+            diff.asset.kind.variable_index,
+            amount_to_hash(diff.asset.amount),
+        )?);
+    }
+
+    let witness = UserTransactionWitness {
+        sender_address: user_state.address,
+        merge_witnesses,
+        purge_input_witnesses,
+        purge_output_witnesses,
+        nonce,
+        old_user_asset_root,
+    };
+    let public_inputs = witness.public_inputs()?;
+    debug_assert_eq!(
+        public_inputs.new_user_asset_root,
+        user_asset_tree.get_root()
+    );
+    debug_assert_eq!(public_inputs.diff_root, diff_tree.get_root());
+    let burned_assets = get_burned_assets(&witness.purge_output_witnesses);
+
+    Ok(SimulatedTransaction {
+        old_user_asset_root,
+        middle_user_asset_root,
+        new_user_asset_root: public_inputs.new_user_asset_root,
+        diff_root: public_inputs.diff_root,
+        tx_hash: public_inputs.tx_hash,
+        burned_assets,
+        burned_assets_digest: public_inputs.burned_assets_digest,
+        purged_assets,
+        diffs,
+        remaining_assets: assets,
+        witness,
+    })
+}
+
+/// The transfers to the sender itself which gather all the leaves of each token of `kinds`
+/// into one leaf, including the pending assets. The tokens with less than two leaves are skipped.
+pub fn consolidation_transfers(user_state: &UserState, kinds: &[TokenKind<F>]) -> Vec<Transfer> {
+    let pending_assets = user_state
+        .pending_assets
+        .iter()
+        .flat_map(|received| received.assets.iter());
+    let owned_assets = user_state.assets.iter().map(|owned| &owned.asset);
+
+    let mut transfers = vec![];
+    for kind in kinds {
+        let amounts = owned_assets
+            .clone()
+            .chain(pending_assets.clone())
+            .filter(|asset| asset.kind == *kind && asset.amount != 0)
+            .map(|asset| asset.amount)
+            .collect::<Vec<_>>();
+        if amounts.len() < 2
+            || transfers
+                .iter()
+                .any(|transfer: &Transfer| transfer.asset.kind == *kind)
+        {
+            continue;
+        }
+
+        transfers.push(Transfer {
+            recipient: user_state.address,
+            asset: Asset {
+                kind: *kind,
+                amount: amounts.iter().sum(),
+            },
+        });
+    }
+
+    transfers
+}
+
+/// Purges all the leaves of each token of `kinds` and sends the sum back to the sender,
+/// so that the next transactions need fewer purge slots.
+pub fn simulate_consolidation(
+    user_state: &UserState,
+    kinds: &[TokenKind<F>],
+    nonce: GoldilocksHashOut,
+) -> anyhow::Result<SimulatedTransaction> {
+    let transfers = consolidation_transfers(user_state, kinds);
+    anyhow::ensure!(
+        !transfers.is_empty(),
+        "{} has no token to consolidate",
+        user_state.address
+    );
+
+    simulate_transaction(user_state, &transfers, nonce)
+}
+
+#[test]
+fn test_simulate_transaction() {
+    use plonky2::{hash::poseidon::PoseidonHash, plonk::config::Hasher};
+
+    use crate::{
+        merkle_tree::tree::get_merkle_proof,
+        sparse_merkle_tree::proof::SparseMerkleInclusionProof,
+        transaction::{block_header::BlockHeader, burn::get_burned_assets_digest},
+    };
+
+    let sender = Address(*GoldilocksHashOut::from_u32(1));
+    let recipient = Address(*GoldilocksHashOut::from_u32(2));
+    let token1 = TokenKind {
+        contract_address: Address(*GoldilocksHashOut::from_u32(305)),
+        variable_index: GoldilocksHashOut::from_u32(8012),
+    };
+    let token2 = TokenKind {
+        contract_address: Address(*GoldilocksHashOut::from_u32(471)),
+        variable_index: GoldilocksHashOut::from_u32(8012),
+    };
+
+    // The sender already owns 30 of token1.
+    let owned_merge_key = GoldilocksHashOut::from_u32(12);
+    let mut user_asset_tree = LayeredLayeredPoseidonSparseMerkleTree::default();
+    user_asset_tree
+        .set(
+            owned_merge_key,
+            token1.contract_address.0.into(),
+            token1.variable_index,
+            amount_to_hash(30),
+        )
+        .unwrap();
+    let old_user_asset_root = user_asset_tree.get_root();
+
+    // The sender receives 20 of token1 and 5 of token2 by a deposit.
+    let deposited_assets = vec![
+        Asset {
+            kind: token1,
+            amount: 20,
+        },
+        Asset {
+            kind: token2,
+            amount: 5,
+        },
+    ];
+    let mut deposit_tree = LayeredLayeredPoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    for asset in deposited_assets.iter() {
+        deposit_tree
+            .set(
+                sender.0.into(),
+                asset.kind.contract_address.0.into(),
+                asset.kind.variable_index,
+                amount_to_hash(asset.amount),
+            )
+            .unwrap();
+    }
+    let deposit_tree: PoseidonSparseMerkleTree<NodeDataMemory> = deposit_tree.into();
+    let recipient_proof = deposit_tree.find(&sender.0.into()).unwrap();
+    let deposit_tx_hash = PoseidonHash::two_to_one(*recipient_proof.root, HashOut::ZERO);
+    let deposit_proof = get_merkle_proof(&[deposit_tx_hash.into()], 0, 1);
+    let block_header = BlockHeader {
+        block_number: 0,
+        prev_block_header_digest: HashOut::ZERO,
+        transactions_digest: *get_merkle_proof(&[], 0, 1).root,
+        deposit_digest: *deposit_proof.root,
+        proposed_world_state_digest: HashOut::ZERO,
+        approved_world_state_digest: HashOut::ZERO,
+        latest_account_digest: HashOut::ZERO,
+        forced_transactions_digest: HashOut::ZERO,
+        issuer_registry_root: HashOut::ZERO,
+        timestamp: 0,
+        aggregator_address: HashOut::ZERO,
+    };
+    let user_state = UserState {
+        address: sender,
+        user_asset_tree,
+        assets: vec![OwnedAsset {
+            merge_key: owned_merge_key,
+            asset: Asset {
+                kind: token1,
+                amount: 30,
+            },
+        }],
+        // The wallet is told of each deposited asset separately.
+        pending_assets: deposited_assets
+            .iter()
+            .map(|asset| ReceivedAssetProof {
+                is_deposit: true,
+                diff_tree_inclusion_proof: (
+                    block_header.clone(),
+                    deposit_proof.clone(),
+                    recipient_proof.clone(),
+                ),
+                latest_account_tree_inclusion_proof: SparseMerkleInclusionProof::with_root(
+                    Default::default(),
+                ),
+                assets: vec![*asset],
+                nonce: Default::default(),
+            })
+            .collect(),
+    };
+
+    let transfers = [
+        Transfer {
+            recipient,
+            asset: Asset {
+                kind: token1,
+                amount: 25,
+            },
+        },
+        Transfer {
+            recipient,
+            asset: Asset {
+                kind: token1,
+                amount: 10,
+            },
+        },
+        // No leaf is emitted for a zero amount.
+        Transfer {
+            recipient,
+            asset: Asset {
+                kind: token2,
+                amount: 0,
+            },
+        },
+    ];
+    let nonce = GoldilocksHashOut::from_u32(7);
+    let simulated = simulate_transaction(&user_state, &transfers, nonce).unwrap();
+    assert_eq!(simulated.old_user_asset_root, old_user_asset_root);
+    assert_ne!(simulated.middle_user_asset_root, old_user_asset_root);
+    assert_eq!(
+        simulated.diffs,
+        vec![
+            Transfer {
+                recipient,
+                asset: Asset {
+                    kind: token1,
+                    amount: 35,
+                },
+            },
+            Transfer {
+                recipient: sender,
+                asset: Asset {
+                    kind: token1,
+                    amount: 15,
+                },
+            },
+        ]
+    );
+    // Both deposited assets are merged in one merge slot.
+    assert_eq!(simulated.witness.merge_witnesses.len(), 1);
+    assert_eq!(simulated.purged_assets.len(), 2);
+    assert_eq!(simulated.remaining_assets.len(), 1);
+    assert_eq!(simulated.remaining_assets[0].asset.kind, token2);
+    assert_eq!(
+        simulated.witness.validate().unwrap(),
+        simulated.new_user_asset_root
+    );
+    assert_eq!(
+        simulated.witness.public_inputs().unwrap().tx_hash,
+        simulated.tx_hash
+    );
+
+    let split = simulated.witness.split_merges(1).unwrap();
+    assert_eq!(split, vec![simulated.witness.clone()]);
+    assert!(simulated.witness.split_merges(0).is_err());
+
+    // The user state is left unchanged.
+    assert_eq!(user_state.user_asset_tree.get_root(), old_user_asset_root);
+    assert_eq!(user_state.assets.len(), 1);
+
+    let too_much = [Transfer {
+        recipient,
+        asset: Asset {
+            kind: token2,
+            amount: 6,
+        },
+    }];
+    assert!(simulate_transaction(&user_state, &too_much, nonce).is_err());
+
+    assert_eq!(simulated.burned_assets, vec![]);
+    assert_eq!(simulated.burned_assets_digest, GoldilocksHashOut::ZERO);
+    let burn = [Transfer::burn(Asset {
+        kind: token2,
+        amount: 5,
+    })];
+    let burned = simulate_transaction(&user_state, &burn, nonce).unwrap();
+    assert_eq!(burned.burned_assets, vec![burn[0].asset]);
+    assert_eq!(
+        *burned.burned_assets_digest,
+        get_burned_assets_digest(&burned.burned_assets)
+    );
+    assert_eq!(burned.diffs, burn.to_vec());
+
+    // An escrow is a transfer to the escrow account of the condition.
+    let (condition, _preimage) = HtlcCondition::generate(recipient, sender, 10);
+    let escrow = [Transfer::htlc(
+        &condition,
+        Asset {
+            kind: token2,
+            amount: 5,
+        },
+    )];
+    let escrowed = simulate_transaction(&user_state, &escrow, nonce).unwrap();
+    assert_eq!(escrowed.diffs[0].recipient, condition.address());
+    assert_eq!(escrowed.burned_assets, vec![]);
+
+    // The owned and the deposited token1 are gathered into one leaf of the sender.
+    let consolidated = simulate_consolidation(&user_state, &[token1, token2], nonce).unwrap();
+    assert_eq!(
+        consolidated.diffs,
+        vec![Transfer {
+            recipient: sender,
+            asset: Asset {
+                kind: token1,
+                amount: 50,
+            },
+        }]
+    );
+    assert_eq!(consolidated.purged_assets.len(), 2);
+    assert_eq!(consolidated.remaining_assets.len(), 1);
+    assert_eq!(
+        consolidated.witness.validate().unwrap(),
+        consolidated.new_user_asset_root
+    );
+    assert!(simulate_consolidation(&user_state, &[token2], nonce).is_err());
+}
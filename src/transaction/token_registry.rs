@@ -0,0 +1,352 @@
+//! The metadata of the tokens held in user asset trees.
+//!
+//! A leaf of a user asset tree is keyed by `contract_address` and `variable_index`, which do not
+//! tell a wallet how to display the amount. `TokenRegistry` maps each `TokenKind` to its symbol,
+//! decimals and type, and commits to them in a sparse Merkle tree, so that a wallet fetching
+//! metadata from an untrusted server can check it against a published root.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use plonky2::{
+    field::{
+        goldilocks_field::GoldilocksField,
+        types::{Field, PrimeField64},
+    },
+    hash::{hash_types::HashOut, poseidon::PoseidonHash},
+    plonk::config::Hasher,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    sparse_merkle_tree::{
+        gadgets::{process::process_smt::SmtProcessProof, verify::verify_smt::SmtInclusionProof},
+        goldilocks_poseidon::{NodeDataMemory, PoseidonSparseMerkleTree, WrappedHashOut},
+    },
+    transaction::asset::TokenKind,
+    verification::merkle::verify_smt_inclusion_proof,
+};
+
+type F = GoldilocksField;
+
+pub const MAX_SYMBOL_LEN: usize = 32;
+
+/// `10^MAX_DECIMALS` is the largest power of ten which fits in `u64`.
+pub const MAX_DECIMALS: u8 = 19;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    /// An amount of the fungible token of `contract_address`.
+    Fungible,
+    /// `variable_index` is the token ID, whose amount is the number of copies.
+    NonFungible,
+}
+
+impl TokenType {
+    fn to_u64(self) -> u64 {
+        match self {
+            TokenType::Fungible => 0,
+            TokenType::NonFungible => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub symbol: String,
+    pub decimals: u8,
+    pub kind: TokenType,
+}
+
+impl TokenMetadata {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.symbol.is_empty(), "the symbol is empty");
+        anyhow::ensure!(
+            self.symbol.len() <= MAX_SYMBOL_LEN,
+            "the symbol {:?} is longer than {} bytes",
+            self.symbol,
+            MAX_SYMBOL_LEN
+        );
+        anyhow::ensure!(
+            self.symbol.bytes().all(|b| b.is_ascii_graphic()),
+            "the symbol {:?} has a non-printable character",
+            self.symbol
+        );
+        anyhow::ensure!(
+            self.decimals <= MAX_DECIMALS,
+            "the decimals {} exceed {}",
+            self.decimals,
+            MAX_DECIMALS
+        );
+        anyhow::ensure!(
+            self.kind == TokenType::Fungible || self.decimals == 0,
+            "a non-fungible token must have no decimals"
+        );
+
+        Ok(())
+    }
+
+    /// `[kind, decimals, symbol.len(), ...symbol]`, with one byte of the symbol per element.
+    pub fn encode(&self) -> Vec<F> {
+        let mut encoded = vec![
+            F::from_canonical_u64(self.kind.to_u64()),
+            F::from_canonical_u8(self.decimals),
+            F::from_canonical_usize(self.symbol.len()),
+        ];
+        encoded.extend(self.symbol.bytes().map(F::from_canonical_u8));
+
+        encoded
+    }
+
+    pub fn hash(&self) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&self.encode())
+    }
+
+    /// Renders `amount` with the decimal point, e.g. `1.5 ETH` for `1500000000000000000` with 18 decimals.
+    pub fn format_amount(&self, amount: u64) -> String {
+        let unit = 10u64.pow(self.decimals as u32);
+        let integer_part = amount / unit;
+        let fractional_part = amount % unit;
+        if fractional_part == 0 {
+            return format!("{} {}", integer_part, self.symbol);
+        }
+
+        let fractional_part = format!(
+            "{:0width$}",
+            fractional_part,
+            width = self.decimals as usize
+        );
+
+        format!(
+            "{}.{} {}",
+            integer_part,
+            fractional_part.trim_end_matches('0'),
+            self.symbol
+        )
+    }
+}
+
+/// The key of `kind` in the registry tree.
+pub fn get_token_key(kind: &TokenKind<F>) -> WrappedHashOut<F> {
+    PoseidonHash::two_to_one(kind.contract_address.0, *kind.variable_index).into()
+}
+
+/// Checks that `proof` shows `metadata` of `kind` in the registry with `root`.
+pub fn verify_token_metadata(
+    root: HashOut<F>,
+    kind: &TokenKind<F>,
+    metadata: &TokenMetadata,
+    proof: &SmtInclusionProof<F>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(proof.found, "the token is not registered");
+    anyhow::ensure!(*proof.root == root, "the proof is for another root");
+    anyhow::ensure!(
+        proof.key == get_token_key(kind),
+        "the proof is for another token"
+    );
+    anyhow::ensure!(
+        *proof.value == metadata.hash(),
+        "the metadata does not match"
+    );
+
+    let siblings = proof.siblings.iter().map(|v| **v).collect::<Vec<_>>();
+    verify_smt_inclusion_proof(
+        root,
+        *proof.key,
+        *proof.value,
+        proof.found,
+        *proof.not_found_key,
+        *proof.not_found_value,
+        proof.is_old0,
+        &siblings,
+    )
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenRegistryEntry {
+    pub kind: TokenKind<F>,
+    pub metadata: TokenMetadata,
+}
+
+#[derive(Debug)]
+pub struct TokenRegistry {
+    tokens: HashMap<TokenKind<F>, TokenMetadata>,
+    tree: PoseidonSparseMerkleTree<NodeDataMemory>,
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        Self {
+            tokens: HashMap::new(),
+            tree: PoseidonSparseMerkleTree::new(
+                Arc::new(Mutex::new(NodeDataMemory::default())),
+                Default::default(),
+            ),
+        }
+    }
+}
+
+impl TokenRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// The root of the registry tree, which is published for wallets.
+    pub fn root(&self) -> HashOut<F> {
+        *self.tree.get_root()
+    }
+
+    /// Registers `metadata` of `kind`. A registered token cannot be changed,
+    /// since wallets may have cached it.
+    pub fn register(
+        &mut self,
+        kind: TokenKind<F>,
+        metadata: TokenMetadata,
+    ) -> anyhow::Result<SmtProcessProof<F>> {
+        metadata.validate()?;
+        anyhow::ensure!(
+            !self.tokens.contains_key(&kind),
+            "the token is already registered as {}",
+            self.tokens[&kind].symbol
+        );
+
+        let proof = self
+            .tree
+            .insert(get_token_key(&kind), metadata.hash().into())?;
+        self.tokens.insert(kind, metadata);
+
+        Ok(proof)
+    }
+
+    pub fn get(&self, kind: &TokenKind<F>) -> Option<&TokenMetadata> {
+        self.tokens.get(kind)
+    }
+
+    /// The tokens with `symbol`. Different contracts may use the same symbol.
+    pub fn find_by_symbol(&self, symbol: &str) -> Vec<(&TokenKind<F>, &TokenMetadata)> {
+        self.tokens
+            .iter()
+            .filter(|(_, metadata)| metadata.symbol == symbol)
+            .collect()
+    }
+
+    /// Returns `None` if `kind` is not registered.
+    pub fn format_amount(&self, kind: &TokenKind<F>, amount: u64) -> Option<String> {
+        self.get(kind)
+            .map(|metadata| metadata.format_amount(amount))
+    }
+
+    /// The inclusion proof of `kind`, or its non-inclusion proof if it is not registered.
+    pub fn prove(&self, kind: &TokenKind<F>) -> anyhow::Result<SmtInclusionProof<F>> {
+        self.tree.find(&get_token_key(kind))
+    }
+
+    pub fn entries(&self) -> Vec<TokenRegistryEntry> {
+        self.tokens
+            .iter()
+            .map(|(kind, metadata)| TokenRegistryEntry {
+                kind: *kind,
+                metadata: metadata.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Serialize for TokenRegistry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries = self.entries();
+        // The order of `HashMap` is not deterministic.
+        entries.sort_by_key(|entry| {
+            get_token_key(&entry.kind)
+                .elements
+                .map(|e| e.to_canonical_u64())
+        });
+
+        entries.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenRegistry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<TokenRegistryEntry>::deserialize(deserializer)?;
+        let mut registry = Self::new();
+        for entry in entries {
+            registry
+                .register(entry.kind, entry.metadata)
+                .map_err(serde::de::Error::custom)?;
+        }
+
+        Ok(registry)
+    }
+}
+
+#[test]
+fn test_token_registry() {
+    use plonky2::field::types::Sample;
+
+    use crate::zkdsa::account::Address;
+
+    let eth = TokenKind {
+        contract_address: Address::rand(),
+        variable_index: WrappedHashOut::ZERO,
+    };
+    let nft = TokenKind {
+        contract_address: Address::rand(),
+        variable_index: HashOut::rand().into(),
+    };
+    let eth_metadata = TokenMetadata {
+        symbol: "ETH".to_string(),
+        decimals: 18,
+        kind: TokenType::Fungible,
+    };
+
+    let mut registry = TokenRegistry::new();
+    registry.register(eth, eth_metadata.clone()).unwrap();
+    registry
+        .register(
+            nft,
+            TokenMetadata {
+                symbol: "PUNK".to_string(),
+                decimals: 0,
+                kind: TokenType::NonFungible,
+            },
+        )
+        .unwrap();
+    assert!(registry.register(eth, eth_metadata.clone()).is_err());
+    assert_eq!(registry.len(), 2);
+
+    assert_eq!(
+        registry.format_amount(&eth, 1_500_000_000_000_000_000),
+        Some("1.5 ETH".to_string())
+    );
+    assert_eq!(
+        registry.format_amount(&eth, 1),
+        Some("0.000000000000000001 ETH".to_string())
+    );
+    assert_eq!(registry.format_amount(&nft, 3), Some("3 PUNK".to_string()));
+    assert_eq!(registry.find_by_symbol("ETH").len(), 1);
+
+    let proof = registry.prove(&eth).unwrap();
+    verify_token_metadata(registry.root(), &eth, &eth_metadata, &proof).unwrap();
+    let wrong_metadata = TokenMetadata {
+        decimals: 6,
+        ..eth_metadata
+    };
+    assert!(verify_token_metadata(registry.root(), &eth, &wrong_metadata, &proof).is_err());
+
+    let encoded = serde_json::to_string(&registry).unwrap();
+    let decoded: TokenRegistry = serde_json::from_str(&encoded).unwrap();
+    assert_eq!(decoded.root(), registry.root());
+    assert_eq!(decoded.get(&nft), registry.get(&nft));
+}
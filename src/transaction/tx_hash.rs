@@ -0,0 +1,133 @@
+//! The hash of a user transaction, which is the leaf of the diff tree of the block.
+//!
+//! `tx_hash` is `hash(TX_HASH_DOMAIN_TAG, diff_root, nonce)`, so that it cannot collide with the
+//! other two-to-one hashes of the protocol, e.g. the merge keys of deposits.
+//! With the `legacy-tx-hash` feature, `two_to_one(diff_root, nonce)` without the tag is kept
+//! for the chains whose blocks were proven before. The deposit transactions, whose hashes are
+//! computed by the L1 contract, always use `two_to_one(deposit_root, 0)`.
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::{
+        hash_types::{HashOut, HashOutTarget, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        config::{AlgebraicHasher, Hasher},
+    },
+};
+
+use crate::poseidon::gadgets::poseidon_two_to_one;
+
+/// `"tx_hash"` as a big-endian integer.
+pub const TX_HASH_DOMAIN_TAG: u64 = u64::from_be_bytes(*b"\0tx_hash");
+
+pub fn get_tx_hash<F: RichField>(diff_root: HashOut<F>, nonce: HashOut<F>) -> HashOut<F> {
+    get_tx_hash_with::<F, PoseidonHash>(diff_root, nonce)
+}
+
+/// `get_tx_hash` with the hasher `H`, which must be the `H` of `get_tx_hash_target`,
+/// e.g. `Poseidon2Hash`.
+pub fn get_tx_hash_with<F: RichField, H: Hasher<F, Hash = HashOut<F>>>(
+    diff_root: HashOut<F>,
+    nonce: HashOut<F>,
+) -> HashOut<F> {
+    if cfg!(feature = "legacy-tx-hash") {
+        return H::two_to_one(diff_root, nonce);
+    }
+
+    let mut inputs = vec![F::from_canonical_u64(TX_HASH_DOMAIN_TAG)];
+    inputs.extend(diff_root.elements);
+    inputs.extend(nonce.elements);
+
+    H::hash_no_pad(&inputs)
+}
+
+/// The in-circuit counterpart of `get_tx_hash`.
+pub fn get_tx_hash_target<F: RichField + Extendable<D>, H: AlgebraicHasher<F>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    diff_root: HashOutTarget,
+    nonce: HashOutTarget,
+) -> HashOutTarget {
+    if cfg!(feature = "legacy-tx-hash") {
+        return poseidon_two_to_one::<F, H, D>(builder, diff_root, nonce);
+    }
+
+    let mut inputs = vec![builder.constant(F::from_canonical_u64(TX_HASH_DOMAIN_TAG))];
+    inputs.extend(diff_root.elements);
+    inputs.extend(nonce.elements);
+
+    builder.hash_n_to_hash_no_pad::<H>(inputs)
+}
+
+#[test]
+fn test_tx_hash_target() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        iop::witness::{PartialWitness, Witness},
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    let diff_root = HashOut::<F>::rand();
+    let nonce = HashOut::<F>::rand();
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let diff_root_t = builder.add_virtual_hash();
+    let nonce_t = builder.add_virtual_hash();
+    let tx_hash_t = get_tx_hash_target::<F, PoseidonHash, D>(&mut builder, diff_root_t, nonce_t);
+    builder.register_public_inputs(&tx_hash_t.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(diff_root_t, diff_root);
+    pw.set_hash_target(nonce_t, nonce);
+    let proof = data.prove(pw).unwrap();
+    assert_eq!(
+        HashOut::from_partial(&proof.public_inputs),
+        get_tx_hash(diff_root, nonce)
+    );
+    if cfg!(not(feature = "legacy-tx-hash")) {
+        assert_ne!(
+            get_tx_hash(diff_root, nonce),
+            PoseidonHash::two_to_one(diff_root, nonce)
+        );
+    }
+}
+
+#[test]
+fn test_tx_hash_with_poseidon2() {
+    use plonky2::{
+        field::{goldilocks_field::GoldilocksField, types::Sample},
+        iop::witness::{PartialWitness, Witness},
+        plonk::{circuit_data::CircuitConfig, config::PoseidonGoldilocksConfig},
+    };
+
+    use crate::poseidon::poseidon2::Poseidon2Hash;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    let diff_root = HashOut::<F>::rand();
+    let nonce = HashOut::<F>::rand();
+
+    let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+    let diff_root_t = builder.add_virtual_hash();
+    let nonce_t = builder.add_virtual_hash();
+    let tx_hash_t = get_tx_hash_target::<F, Poseidon2Hash, D>(&mut builder, diff_root_t, nonce_t);
+    builder.register_public_inputs(&tx_hash_t.elements);
+    let data = builder.build::<C>();
+
+    let mut pw = PartialWitness::new();
+    pw.set_hash_target(diff_root_t, diff_root);
+    pw.set_hash_target(nonce_t, nonce);
+    let proof = data.prove(pw).unwrap();
+    let tx_hash = get_tx_hash_with::<F, Poseidon2Hash>(diff_root, nonce);
+    assert_eq!(HashOut::from_partial(&proof.public_inputs), tx_hash);
+    assert_ne!(tx_hash, get_tx_hash(diff_root, nonce));
+}
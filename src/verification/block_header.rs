@@ -0,0 +1,199 @@
+use alloc::vec::Vec;
+
+use plonky2::{
+    field::types::Field,
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BlockHeader<F: Field> {
+    pub block_number: u32,
+    pub prev_block_header_digest: HashOut<F>, // block header tree root
+    pub transactions_digest: HashOut<F>,      // state diff tree root
+    pub deposit_digest: HashOut<F>,           // deposit transaction tree root
+    pub proposed_world_state_digest: HashOut<F>,
+    pub approved_world_state_digest: HashOut<F>,
+    pub latest_account_digest: HashOut<F>, // latest account tree
+    pub forced_transactions_digest: HashOut<F>, // forced transaction queue processed so far
+    pub issuer_registry_root: HashOut<F>,  // issuer registry which the mints are verified against
+    pub timestamp: u64,                    // unix time in seconds, less than 2^48
+    pub aggregator_address: HashOut<F>,    // address of the aggregator who produced the block
+}
+
+/// The number of the bits of `BlockHeader::timestamp`.
+pub const N_LOG_MAX_TIMESTAMP: usize = 48;
+
+pub fn get_block_hash<F: RichField>(block_header: &BlockHeader<F>) -> HashOut<F> {
+    let a = PoseidonHash::two_to_one(
+        HashOut::from_partial(&[F::from_canonical_u32(block_header.block_number)]),
+        block_header.latest_account_digest,
+    );
+    let b = PoseidonHash::two_to_one(
+        block_header.deposit_digest,
+        block_header.transactions_digest,
+    );
+    let c = PoseidonHash::two_to_one(a, b);
+    let d = PoseidonHash::two_to_one(
+        block_header.proposed_world_state_digest,
+        block_header.approved_world_state_digest,
+    );
+    let e = PoseidonHash::two_to_one(c, d);
+    let f = PoseidonHash::two_to_one(
+        block_header.forced_transactions_digest,
+        block_header.issuer_registry_root,
+    );
+    let f = PoseidonHash::two_to_one(e, f);
+    let g = PoseidonHash::two_to_one(
+        HashOut::from_partial(&[F::from_canonical_u64(block_header.timestamp)]),
+        block_header.aggregator_address,
+    );
+    let h = PoseidonHash::two_to_one(f, g);
+
+    PoseidonHash::two_to_one(block_header.prev_block_header_digest, h)
+}
+
+/// The length of `BlockHeader::to_bytes`.
+pub const BLOCK_HEADER_BYTES_LEN: usize = 4 + 8 * 32 + 8 + 32;
+
+/// `elements[3]` first and each element as a big-endian `u64`, i.e. the 256-bit big-endian integer.
+fn hash_to_bytes<F: RichField>(value: HashOut<F>, bytes: &mut Vec<u8>) {
+    for element in value.elements.iter().rev() {
+        bytes.extend_from_slice(&element.to_canonical_u64().to_be_bytes());
+    }
+}
+
+fn hash_from_bytes<F: RichField>(bytes: &[u8]) -> anyhow::Result<HashOut<F>> {
+    let mut elements = [F::ZERO; 4];
+    for (element, chunk) in elements.iter_mut().rev().zip(bytes.chunks(8)) {
+        let value = u64::from_be_bytes(chunk.try_into().unwrap());
+        anyhow::ensure!(value < F::ORDER, "non-canonical field element: {}", value);
+        *element = F::from_canonical_u64(value);
+    }
+
+    Ok(HashOut { elements })
+}
+
+impl<F: RichField> BlockHeader<F> {
+    /// The canonical encoding for the L1 contracts and the other clients, in the order of the fields:
+    ///
+    /// | field                         | bytes |
+    /// |-------------------------------|-------|
+    /// | `block_number`                | 4     |
+    /// | `prev_block_header_digest`    | 32    |
+    /// | `transactions_digest`         | 32    |
+    /// | `deposit_digest`              | 32    |
+    /// | `proposed_world_state_digest` | 32    |
+    /// | `approved_world_state_digest` | 32    |
+    /// | `latest_account_digest`       | 32    |
+    /// | `forced_transactions_digest`  | 32    |
+    /// | `issuer_registry_root`        | 32    |
+    /// | `timestamp`                   | 8     |
+    /// | `aggregator_address`          | 32    |
+    ///
+    /// The integers are big-endian, and a hash is the big-endian integer of `elements[3]` to `elements[0]`,
+    /// the same as `WrappedHashOut::to_bytes_be`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(BLOCK_HEADER_BYTES_LEN);
+        bytes.extend_from_slice(&self.block_number.to_be_bytes());
+        for digest in [
+            self.prev_block_header_digest,
+            self.transactions_digest,
+            self.deposit_digest,
+            self.proposed_world_state_digest,
+            self.approved_world_state_digest,
+            self.latest_account_digest,
+            self.forced_transactions_digest,
+            self.issuer_registry_root,
+        ] {
+            hash_to_bytes(digest, &mut bytes);
+        }
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        hash_to_bytes(self.aggregator_address, &mut bytes);
+
+        bytes
+    }
+
+    /// The inverse of `to_bytes`. Rejects the bytes of another length, a non-canonical field element
+    /// or a timestamp of more than `N_LOG_MAX_TIMESTAMP` bits.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bytes.len() == BLOCK_HEADER_BYTES_LEN,
+            "invalid length of a block header: expected {}, actual {}",
+            BLOCK_HEADER_BYTES_LEN,
+            bytes.len()
+        );
+
+        let block_number = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let mut digests = [HashOut::ZERO; 8];
+        for (i, digest) in digests.iter_mut().enumerate() {
+            *digest = hash_from_bytes(&bytes[4 + 32 * i..4 + 32 * (i + 1)])?;
+        }
+        let offset = 4 + 8 * 32;
+        let timestamp = u64::from_be_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        anyhow::ensure!(
+            timestamp >> N_LOG_MAX_TIMESTAMP == 0,
+            "the timestamp {} exceeds {} bits",
+            timestamp,
+            N_LOG_MAX_TIMESTAMP
+        );
+        let aggregator_address = hash_from_bytes(&bytes[offset + 8..])?;
+
+        Ok(Self {
+            block_number,
+            prev_block_header_digest: digests[0],
+            transactions_digest: digests[1],
+            deposit_digest: digests[2],
+            proposed_world_state_digest: digests[3],
+            approved_world_state_digest: digests[4],
+            latest_account_digest: digests[5],
+            forced_transactions_digest: digests[6],
+            issuer_registry_root: digests[7],
+            timestamp,
+            aggregator_address,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_block_header_bytes() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Sample};
+
+    type F = GoldilocksField;
+
+    let block_header = BlockHeader::<F> {
+        block_number: 7,
+        prev_block_header_digest: HashOut::rand(),
+        transactions_digest: HashOut::rand(),
+        deposit_digest: HashOut::rand(),
+        proposed_world_state_digest: HashOut::rand(),
+        approved_world_state_digest: HashOut::rand(),
+        latest_account_digest: HashOut::rand(),
+        forced_transactions_digest: HashOut::rand(),
+        issuer_registry_root: HashOut::rand(),
+        timestamp: 1_700_000_000,
+        aggregator_address: HashOut::rand(),
+    };
+    let bytes = block_header.to_bytes();
+    assert_eq!(bytes.len(), BLOCK_HEADER_BYTES_LEN);
+    assert_eq!(bytes[0..4], [0, 0, 0, 7]);
+    assert_eq!(
+        bytes[4..12],
+        block_header.prev_block_header_digest.elements[3]
+            .to_canonical_u64()
+            .to_be_bytes()
+    );
+    assert_eq!(BlockHeader::from_bytes(&bytes).unwrap(), block_header);
+
+    assert!(BlockHeader::<F>::from_bytes(&bytes[1..]).is_err());
+    let mut non_canonical_bytes = bytes.clone();
+    non_canonical_bytes[4..12].copy_from_slice(&u64::MAX.to_be_bytes());
+    assert!(BlockHeader::<F>::from_bytes(&non_canonical_bytes).is_err());
+    let mut large_timestamp_bytes = bytes;
+    large_timestamp_bytes[4 + 8 * 32] = 1;
+    assert!(BlockHeader::<F>::from_bytes(&large_timestamp_bytes).is_err());
+}
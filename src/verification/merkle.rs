@@ -0,0 +1,342 @@
+use plonky2::{
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+
+/// Calculates the root of a Merkle tree from the leaf at `index` and its `siblings`.
+/// The siblings are ordered from the leaf side.
+pub fn get_merkle_root<F: RichField>(
+    index: usize,
+    value: HashOut<F>,
+    siblings: &[HashOut<F>],
+) -> HashOut<F> {
+    let mut root = value;
+    let mut rest_index = index;
+    for sibling in siblings {
+        let (left, right) = if rest_index & 1 == 0 {
+            (root, *sibling)
+        } else {
+            (*sibling, root)
+        };
+        root = PoseidonHash::two_to_one(left, right);
+        rest_index >>= 1;
+    }
+
+    root
+}
+
+pub fn verify_merkle_proof<F: RichField>(
+    index: usize,
+    value: HashOut<F>,
+    siblings: &[HashOut<F>],
+    root: HashOut<F>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        siblings.len() >= usize::BITS as usize || index >> siblings.len() == 0,
+        "index {} is out of a tree of depth {}",
+        index,
+        siblings.len()
+    );
+    anyhow::ensure!(
+        get_merkle_root(index, value, siblings) == root,
+        "invalid Merkle proof"
+    );
+
+    Ok(())
+}
+
+/// The same as `PoseidonNodeHash` for a leaf node.
+pub fn get_smt_leaf_hash<F: RichField>(key: HashOut<F>, value: HashOut<F>) -> HashOut<F> {
+    PoseidonHash::hash_pad(&[
+        key.elements[0],
+        key.elements[1],
+        key.elements[2],
+        key.elements[3],
+        value.elements[0],
+        value.elements[1],
+        value.elements[2],
+        value.elements[3],
+        F::ONE,
+    ])
+}
+
+fn get_smt_path_bit<F: RichField>(key: HashOut<F>, level: usize) -> bool {
+    let element = key.elements[level / 64].to_canonical_u64();
+
+    (element >> (level % 64)) & 1 == 1
+}
+
+/// Calculates the root of a Poseidon sparse Merkle tree from a leaf hash
+/// (the zero hash for an empty node) placed on the path of `key`.
+/// The siblings are ordered from the root side, and trailing zero hashes are regarded as padding.
+pub fn get_smt_root<F: RichField>(
+    key: HashOut<F>,
+    leaf_hash: HashOut<F>,
+    siblings: &[HashOut<F>],
+) -> HashOut<F> {
+    let depth = siblings
+        .iter()
+        .rposition(|sibling| *sibling != HashOut::ZERO)
+        .map_or(0, |i| i + 1);
+
+    let mut root = leaf_hash;
+    for (level, sibling) in siblings[..depth].iter().enumerate().rev() {
+        root = if get_smt_path_bit(key, level) {
+            PoseidonHash::two_to_one(*sibling, root)
+        } else {
+            PoseidonHash::two_to_one(root, *sibling)
+        };
+    }
+
+    root
+}
+
+/// Checks the fields of a `SparseMerkleInclusionProof` of a Poseidon sparse Merkle tree.
+/// If `found` is false, the proof shows that `key` is not in the tree,
+/// through the leaf (`not_found_key`, `not_found_value`) or an empty node if `is_old0` is true.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_smt_inclusion_proof<F: RichField>(
+    root: HashOut<F>,
+    key: HashOut<F>,
+    value: HashOut<F>,
+    found: bool,
+    not_found_key: HashOut<F>,
+    not_found_value: HashOut<F>,
+    is_old0: bool,
+    siblings: &[HashOut<F>],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        siblings.len() <= 256,
+        "too many siblings: {}",
+        siblings.len()
+    );
+
+    let leaf_hash = if found {
+        get_smt_leaf_hash(key, value)
+    } else if is_old0 {
+        HashOut::ZERO
+    } else {
+        anyhow::ensure!(not_found_key != key, "the found leaf has the searching key");
+        get_smt_leaf_hash(not_found_key, not_found_value)
+    };
+    anyhow::ensure!(
+        get_smt_root(key, leaf_hash, siblings) == root,
+        "invalid sparse Merkle proof"
+    );
+
+    Ok(())
+}
+
+/// Checks the fields of a `SparseMerkleProcessProof` of a Poseidon sparse Merkle tree.
+/// `fnc` is `[false, false]` (no-op), `[false, true]` (update), `[true, false]` (insert)
+/// or `[true, true]` (remove). A remove proof is an insert proof with old and new swapped.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_smt_process_proof<F: RichField>(
+    old_root: HashOut<F>,
+    old_key: HashOut<F>,
+    old_value: HashOut<F>,
+    new_root: HashOut<F>,
+    new_key: HashOut<F>,
+    new_value: HashOut<F>,
+    is_old0: bool,
+    fnc: [bool; 2],
+    siblings: &[HashOut<F>],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        siblings.len() <= 256,
+        "too many siblings: {}",
+        siblings.len()
+    );
+
+    match fnc {
+        [false, false] => {
+            anyhow::ensure!(old_root == new_root, "the root changed by a no-op");
+        }
+        [false, true] => {
+            anyhow::ensure!(old_key == new_key, "the key changed by an update");
+            anyhow::ensure!(
+                get_smt_root(old_key, get_smt_leaf_hash(old_key, old_value), siblings) == old_root,
+                "invalid old root"
+            );
+            anyhow::ensure!(
+                get_smt_root(new_key, get_smt_leaf_hash(new_key, new_value), siblings) == new_root,
+                "invalid new root"
+            );
+        }
+        [true, false] => verify_smt_insertion(
+            old_root, old_key, old_value, new_root, new_key, new_value, is_old0, siblings,
+        )?,
+        [true, true] => verify_smt_insertion(
+            new_root, new_key, new_value, old_root, old_key, old_value, is_old0, siblings,
+        )?,
+    }
+
+    Ok(())
+}
+
+/// (`old_key`, `old_value`) is the leaf found while searching for `new_key` before the insertion,
+/// or an empty node if `is_old0` is true.
+/// If it is a leaf, the new leaf is placed with it at the level where their paths diverge.
+#[allow(clippy::too_many_arguments)]
+fn verify_smt_insertion<F: RichField>(
+    old_root: HashOut<F>,
+    old_key: HashOut<F>,
+    old_value: HashOut<F>,
+    new_root: HashOut<F>,
+    new_key: HashOut<F>,
+    new_value: HashOut<F>,
+    is_old0: bool,
+    siblings: &[HashOut<F>],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        new_value != HashOut::ZERO,
+        "the inserted value must be non-zero"
+    );
+    let new_leaf_hash = get_smt_leaf_hash(new_key, new_value);
+
+    if is_old0 {
+        anyhow::ensure!(
+            get_smt_root(new_key, HashOut::ZERO, siblings) == old_root,
+            "invalid old root"
+        );
+        anyhow::ensure!(
+            get_smt_root(new_key, new_leaf_hash, siblings) == new_root,
+            "invalid new root"
+        );
+
+        return Ok(());
+    }
+
+    anyhow::ensure!(old_key != new_key, "the inserted key already exists");
+    let depth = siblings.len();
+    anyhow::ensure!(
+        (0..depth)
+            .all(|level| get_smt_path_bit(old_key, level) == get_smt_path_bit(new_key, level)),
+        "the found leaf is not on the path of the inserted key"
+    );
+    let old_leaf_hash = get_smt_leaf_hash(old_key, old_value);
+    anyhow::ensure!(
+        get_smt_root(old_key, old_leaf_hash, siblings) == old_root,
+        "invalid old root"
+    );
+
+    let diverging_level = (depth..256)
+        .find(|&level| get_smt_path_bit(old_key, level) != get_smt_path_bit(new_key, level))
+        .ok_or_else(|| anyhow::anyhow!("the paths of the keys do not diverge"))?;
+    let mut new_siblings = siblings.to_vec();
+    new_siblings.resize(diverging_level, HashOut::ZERO);
+    new_siblings.push(old_leaf_hash);
+    anyhow::ensure!(
+        get_smt_root(new_key, new_leaf_hash, &new_siblings) == new_root,
+        "invalid new root"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_verify_smt_inclusion_proof() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::sparse_merkle_tree::goldilocks_poseidon::{
+        GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree,
+    };
+
+    type F = GoldilocksField;
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    for i in 1..10u32 {
+        tree.set(
+            GoldilocksHashOut::from_u32(i),
+            GoldilocksHashOut::from_u32(i * 100),
+        )
+        .unwrap();
+    }
+
+    for i in 1..12u32 {
+        let proof = tree.find(&GoldilocksHashOut::from_u32(i)).unwrap();
+        let siblings = proof
+            .siblings
+            .iter()
+            .map(|v| **v)
+            .collect::<Vec<HashOut<F>>>();
+        verify_smt_inclusion_proof(
+            *proof.root,
+            *proof.key,
+            *proof.value,
+            proof.found,
+            *proof.not_found_key,
+            *proof.not_found_value,
+            proof.is_old0,
+            &siblings,
+        )
+        .unwrap();
+        assert_eq!(proof.found, i < 10);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_verify_smt_process_proof() {
+    use plonky2::field::goldilocks_field::GoldilocksField;
+
+    use crate::sparse_merkle_tree::{
+        gadgets::process::process_smt::SmtProcessProof,
+        goldilocks_poseidon::{GoldilocksHashOut, NodeDataMemory, PoseidonSparseMerkleTree},
+    };
+
+    type F = GoldilocksField;
+
+    let verify = |proof: &SmtProcessProof<F>| {
+        let siblings = proof
+            .siblings
+            .iter()
+            .map(|v| **v)
+            .collect::<Vec<HashOut<F>>>();
+        verify_smt_process_proof(
+            *proof.old_root,
+            *proof.old_key,
+            *proof.old_value,
+            *proof.new_root,
+            *proof.new_key,
+            *proof.new_value,
+            proof.is_old0,
+            proof.fnc.into(),
+            &siblings,
+        )
+    };
+
+    let mut tree = PoseidonSparseMerkleTree::<NodeDataMemory>::default();
+    let mut proofs = vec![];
+    for i in 1..20u32 {
+        proofs.push(
+            tree.set(GoldilocksHashOut::rand(), GoldilocksHashOut::from_u32(i))
+                .unwrap(),
+        );
+    }
+    for i in 1..10u32 {
+        let key = GoldilocksHashOut::from_u32(i);
+        proofs.push(tree.set(key, GoldilocksHashOut::from_u32(i)).unwrap());
+        proofs.push(tree.set(key, GoldilocksHashOut::from_u32(i * 100)).unwrap());
+    }
+    for i in 1..12u32 {
+        proofs.push(
+            tree.set(GoldilocksHashOut::from_u32(i), GoldilocksHashOut::ZERO)
+                .unwrap(),
+        );
+    }
+
+    for proof in proofs {
+        verify(&proof).unwrap();
+
+        if proof.old_root != proof.new_root {
+            let mut wrong_proof = proof.clone();
+            wrong_proof.new_root = GoldilocksHashOut::from_u32(12345);
+            assert!(verify(&wrong_proof).is_err());
+        }
+    }
+}
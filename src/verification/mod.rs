@@ -0,0 +1,11 @@
+//! The parts of the crate needed to check intmax data without the prover stack.
+//!
+//! This module only depends on `core`, `alloc`, `anyhow` and plonky2, so it builds with
+//! `--no-default-features` for `no_std` targets.
+//! The other modules require the `std` feature and re-export the types defined here.
+
+pub mod block_header;
+pub mod merkle;
+pub mod proof;
+pub mod public_inputs;
+pub mod verifier;
@@ -0,0 +1,130 @@
+use alloc::vec::Vec;
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    plonk::{
+        circuit_data::{CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData},
+        config::GenericConfig,
+        proof::ProofWithPublicInputs,
+    },
+};
+
+use super::public_inputs::{
+    BlockCommitmentPublicInputs, BlockPublicInputs, SignaturePublicInputs,
+    UserTransactionPublicInputs,
+};
+
+/// Parses a proof serialized by `ProofWithPublicInputs::to_bytes`.
+pub fn parse_proof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    bytes: &[u8],
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+    ProofWithPublicInputs::from_bytes(bytes.to_vec(), common)
+}
+
+/// Parses and verifies a proof, and returns its public inputs.
+pub fn verify_proof<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>(
+    bytes: &[u8],
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<Vec<F>> {
+    let proof_with_pis = parse_proof::<F, C, D>(bytes, common)?;
+    let public_inputs = proof_with_pis.public_inputs.clone();
+
+    let verifier_data = VerifierCircuitData {
+        verifier_only: verifier_only.clone(),
+        common: common.clone(),
+    };
+    verifier_data.verify(proof_with_pis)?;
+
+    Ok(public_inputs)
+}
+
+pub fn verify_user_transaction_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: &[u8],
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<UserTransactionPublicInputs<F>> {
+    let public_inputs = verify_proof::<F, C, D>(bytes, verifier_only, common)?;
+
+    UserTransactionPublicInputs::try_decode(&public_inputs)
+}
+
+pub fn verify_signature_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: &[u8],
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<SignaturePublicInputs<F>> {
+    let public_inputs = verify_proof::<F, C, D>(bytes, verifier_only, common)?;
+
+    SignaturePublicInputs::try_decode(&public_inputs)
+}
+
+pub fn verify_block_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: &[u8],
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+    n_txs: usize,
+    n_deposits: usize,
+) -> anyhow::Result<BlockPublicInputs<F>> {
+    let public_inputs = verify_proof::<F, C, D>(bytes, verifier_only, common)?;
+
+    BlockPublicInputs::try_decode(&public_inputs, n_txs, n_deposits)
+}
+
+/// Verifies a proof of the block commitment circuit, whose layout does not depend on the block size.
+pub fn verify_block_commitment_proof<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: &[u8],
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<BlockCommitmentPublicInputs<F>> {
+    let public_inputs = verify_proof::<F, C, D>(bytes, verifier_only, common)?;
+
+    BlockCommitmentPublicInputs::try_decode(&public_inputs)
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_verify_signature_proof() {
+    use plonky2::{field::types::Sample, hash::hash_types::HashOut, iop::witness::PartialWitness};
+
+    use crate::zkdsa::{
+        account::private_key_to_public_key, circuits::make_simple_signature_circuit,
+    };
+
+    let circuit = make_simple_signature_circuit();
+    let private_key = HashOut::rand();
+    let message = HashOut::rand();
+    let mut pw = PartialWitness::new();
+    circuit.targets.set_witness(&mut pw, private_key, message);
+    let proof: ProofWithPublicInputs<_, _, 2> = circuit.prove(pw).unwrap().into();
+
+    let public_inputs = verify_signature_proof(
+        &proof.to_bytes().unwrap(),
+        &circuit.data.verifier_only,
+        &circuit.data.common,
+    )
+    .unwrap();
+    assert_eq!(public_inputs.message, message);
+    assert_eq!(
+        public_inputs.public_key,
+        private_key_to_public_key(private_key)
+    );
+}
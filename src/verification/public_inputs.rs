@@ -0,0 +1,417 @@
+//! The public input layouts of the circuits, using only plonky2 types.
+
+use alloc::vec::Vec;
+
+use plonky2::{
+    field::types::Field,
+    hash::{
+        hash_types::{HashOut, RichField},
+        poseidon::PoseidonHash,
+    },
+    plonk::config::Hasher,
+};
+
+/// The protocol version committed as the last public input of the user transaction
+/// and block circuits. The block circuit rejects the user transaction proofs of other versions.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+pub fn protocol_version<F: Field>() -> F {
+    F::from_canonical_u32(PROTOCOL_VERSION)
+}
+
+fn ensure_protocol_version<F: Field>(version: F) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        version == protocol_version(),
+        "unsupported protocol version: expected {}",
+        PROTOCOL_VERSION
+    );
+
+    Ok(())
+}
+
+fn read_hash<F: Field>(public_inputs: &[F], offset: usize) -> HashOut<F> {
+    HashOut::from_partial(&public_inputs[offset..(offset + 4)])
+}
+
+fn ensure_length<F>(public_inputs: &[F], expected: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        public_inputs.len() == expected,
+        "invalid length of public inputs: expected {}, actual {}",
+        expected,
+        public_inputs.len()
+    );
+
+    Ok(())
+}
+
+/// The public inputs of the user transaction (merge and purge) circuit,
+/// followed by `PROTOCOL_VERSION`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserTransactionPublicInputs<F: Field> {
+    pub old_user_asset_root: HashOut<F>,
+    pub middle_user_asset_root: HashOut<F>,
+    pub new_user_asset_root: HashOut<F>,
+    pub diff_root: HashOut<F>,
+    pub sender_address: HashOut<F>,
+    pub tx_hash: HashOut<F>,
+    /// The digest of the assets sent to the burn address by the transaction.
+    pub burned_assets_digest: HashOut<F>,
+}
+
+impl<F: Field> UserTransactionPublicInputs<F> {
+    pub const LEN: usize = 29;
+
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = Vec::with_capacity(Self::LEN);
+        public_inputs.extend_from_slice(&self.old_user_asset_root.elements);
+        public_inputs.extend_from_slice(&self.middle_user_asset_root.elements);
+        public_inputs.extend_from_slice(&self.new_user_asset_root.elements);
+        public_inputs.extend_from_slice(&self.diff_root.elements);
+        public_inputs.extend_from_slice(&self.sender_address.elements);
+        public_inputs.extend_from_slice(&self.tx_hash.elements);
+        public_inputs.extend_from_slice(&self.burned_assets_digest.elements);
+        public_inputs.push(protocol_version());
+
+        public_inputs
+    }
+
+    pub fn try_decode(public_inputs: &[F]) -> anyhow::Result<Self> {
+        ensure_length(public_inputs, Self::LEN)?;
+        ensure_protocol_version(public_inputs[28])?;
+
+        Ok(Self {
+            old_user_asset_root: read_hash(public_inputs, 0),
+            middle_user_asset_root: read_hash(public_inputs, 4),
+            new_user_asset_root: read_hash(public_inputs, 8),
+            diff_root: read_hash(public_inputs, 12),
+            sender_address: read_hash(public_inputs, 16),
+            tx_hash: read_hash(public_inputs, 20),
+            burned_assets_digest: read_hash(public_inputs, 24),
+        })
+    }
+}
+
+/// The public inputs of the simple signature circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignaturePublicInputs<F: Field> {
+    pub message: HashOut<F>,
+    pub public_key: HashOut<F>,
+    pub signature: HashOut<F>,
+}
+
+impl<F: Field> SignaturePublicInputs<F> {
+    pub const LEN: usize = 12;
+
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = Vec::with_capacity(Self::LEN);
+        public_inputs.extend_from_slice(&self.message.elements);
+        public_inputs.extend_from_slice(&self.public_key.elements);
+        public_inputs.extend_from_slice(&self.signature.elements);
+
+        public_inputs
+    }
+
+    pub fn try_decode(public_inputs: &[F]) -> anyhow::Result<Self> {
+        ensure_length(public_inputs, Self::LEN)?;
+
+        Ok(Self {
+            message: read_hash(public_inputs, 0),
+            public_key: read_hash(public_inputs, 4),
+            signature: read_hash(public_inputs, 8),
+        })
+    }
+}
+
+/// The public inputs of the block commitment circuit, which verifies a block proof
+/// and exposes only `BlockPublicInputs::commitment` of it, followed by `PROTOCOL_VERSION`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockCommitmentPublicInputs<F: Field> {
+    pub commitment: HashOut<F>,
+}
+
+impl<F: RichField> BlockCommitmentPublicInputs<F> {
+    pub const LEN: usize = 5;
+
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = Vec::with_capacity(Self::LEN);
+        public_inputs.extend_from_slice(&self.commitment.elements);
+        public_inputs.push(protocol_version());
+
+        public_inputs
+    }
+
+    pub fn try_decode(public_inputs: &[F]) -> anyhow::Result<Self> {
+        ensure_length(public_inputs, Self::LEN)?;
+        ensure_protocol_version(public_inputs[4])?;
+
+        Ok(Self {
+            commitment: read_hash(public_inputs, 0),
+        })
+    }
+
+    /// Checks that `block_public_inputs` are the ones committed by the proof.
+    pub fn open(&self, block_public_inputs: &BlockPublicInputs<F>) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            block_public_inputs.commitment() == self.commitment,
+            "the block public inputs do not match the commitment"
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositPublicInputs<F: Field> {
+    pub receiver_address: HashOut<F>,
+    pub contract_address: HashOut<F>,
+    pub variable_index: HashOut<F>,
+    pub amount: F,
+}
+
+/// The public inputs of the block (proposal and approval) circuit,
+/// followed by `PROTOCOL_VERSION`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockPublicInputs<F: Field> {
+    /// The pairs of the sender address and whether the sender approved the block.
+    pub address_list: Vec<(HashOut<F>, bool)>,
+    pub deposit_list: Vec<DepositPublicInputs<F>>,
+    pub old_account_tree_root: HashOut<F>,
+    pub new_account_tree_root: HashOut<F>,
+    pub old_world_state_root: HashOut<F>,
+    pub new_world_state_root: HashOut<F>,
+    pub old_prev_block_header_digest: HashOut<F>,
+    pub new_prev_block_header_digest: HashOut<F>,
+    pub block_hash: HashOut<F>,
+    pub proposed_world_state_digest: HashOut<F>,
+    pub approved_world_state_digest: HashOut<F>,
+    pub latest_account_digest: HashOut<F>,
+    /// `get_tx_hash_list_digest` of the `tx_hash` of the user transactions in the block.
+    pub tx_hash_list_digest: HashOut<F>,
+    /// The digests of the L1 forced transaction queue before and after the block.
+    pub old_forced_transactions_digest: HashOut<F>,
+    pub new_forced_transactions_digest: HashOut<F>,
+    /// The hash chain of the burned assets digests of the approved transactions of the block.
+    pub burned_assets_digest: HashOut<F>,
+    /// The Merkle root of the withdrawals of the block, i.e. the burned assets of the approved transactions.
+    pub withdrawal_root: HashOut<F>,
+}
+
+/// The hash of `tx_hashes` in the order of the slots, padded with zeros to `n_txs` hashes.
+pub fn get_tx_hash_list_digest<F: RichField>(tx_hashes: &[HashOut<F>], n_txs: usize) -> HashOut<F> {
+    let mut inputs = Vec::with_capacity(4 * n_txs);
+    for tx_hash in tx_hashes {
+        inputs.extend_from_slice(&tx_hash.elements);
+    }
+    inputs.resize(4 * n_txs, F::ZERO);
+
+    PoseidonHash::hash_no_pad(&inputs)
+}
+
+impl<F: RichField> BlockPublicInputs<F> {
+    pub fn public_inputs_len(n_txs: usize, n_deposits: usize) -> usize {
+        5 * n_txs + 13 * n_deposits + 61
+    }
+
+    /// Checks that `tx_hashes` are the transactions of the block in order,
+    /// so that a transaction can be looked up by its hash without trusting the aggregator.
+    pub fn verify_tx_hash_list(&self, tx_hashes: &[HashOut<F>]) -> anyhow::Result<()> {
+        let n_txs = self.address_list.len();
+        anyhow::ensure!(
+            tx_hashes.len() <= n_txs,
+            "too many transaction hashes: expected at most {}, actual {}",
+            n_txs,
+            tx_hashes.len()
+        );
+        anyhow::ensure!(
+            get_tx_hash_list_digest(tx_hashes, n_txs) == self.tx_hash_list_digest,
+            "the transaction hashes do not match the block"
+        );
+
+        Ok(())
+    }
+
+    /// The hash of all the public inputs but `PROTOCOL_VERSION`,
+    /// which the block commitment circuit exposes instead of them.
+    pub fn commitment(&self) -> HashOut<F> {
+        let mut public_inputs = self.encode();
+        public_inputs.pop();
+
+        PoseidonHash::hash_no_pad(&public_inputs)
+    }
+
+    pub fn encode(&self) -> Vec<F> {
+        let mut public_inputs = Vec::with_capacity(Self::public_inputs_len(
+            self.address_list.len(),
+            self.deposit_list.len(),
+        ));
+        for (sender_address, is_valid) in &self.address_list {
+            public_inputs.extend_from_slice(&sender_address.elements);
+            public_inputs.push(F::from_bool(*is_valid));
+        }
+
+        for deposit in &self.deposit_list {
+            public_inputs.extend_from_slice(&deposit.receiver_address.elements);
+            public_inputs.extend_from_slice(&deposit.contract_address.elements);
+            public_inputs.extend_from_slice(&deposit.variable_index.elements);
+            public_inputs.push(deposit.amount);
+        }
+
+        public_inputs.extend_from_slice(&self.old_account_tree_root.elements);
+        public_inputs.extend_from_slice(&self.new_account_tree_root.elements);
+        public_inputs.extend_from_slice(&self.old_world_state_root.elements);
+        public_inputs.extend_from_slice(&self.new_world_state_root.elements);
+        public_inputs.extend_from_slice(&self.old_prev_block_header_digest.elements);
+        public_inputs.extend_from_slice(&self.new_prev_block_header_digest.elements);
+        public_inputs.extend_from_slice(&self.block_hash.elements);
+        public_inputs.extend_from_slice(&self.proposed_world_state_digest.elements);
+        public_inputs.extend_from_slice(&self.approved_world_state_digest.elements);
+        public_inputs.extend_from_slice(&self.latest_account_digest.elements);
+        public_inputs.extend_from_slice(&self.tx_hash_list_digest.elements);
+        public_inputs.extend_from_slice(&self.old_forced_transactions_digest.elements);
+        public_inputs.extend_from_slice(&self.new_forced_transactions_digest.elements);
+        public_inputs.extend_from_slice(&self.burned_assets_digest.elements);
+        public_inputs.extend_from_slice(&self.withdrawal_root.elements);
+        public_inputs.push(protocol_version());
+
+        public_inputs
+    }
+
+    pub fn try_decode(
+        public_inputs: &[F],
+        n_txs: usize,
+        n_deposits: usize,
+    ) -> anyhow::Result<Self> {
+        ensure_length(public_inputs, Self::public_inputs_len(n_txs, n_deposits))?;
+
+        let mut offset = 0;
+        let mut address_list = Vec::with_capacity(n_txs);
+        for _ in 0..n_txs {
+            let sender_address = read_hash(public_inputs, offset);
+            let is_valid = public_inputs[offset + 4];
+            anyhow::ensure!(
+                is_valid == F::ZERO || is_valid == F::ONE,
+                "the validity of a sender must be boolean"
+            );
+            address_list.push((sender_address, is_valid == F::ONE));
+            offset += 5;
+        }
+
+        let mut deposit_list = Vec::with_capacity(n_deposits);
+        for _ in 0..n_deposits {
+            deposit_list.push(DepositPublicInputs {
+                receiver_address: read_hash(public_inputs, offset),
+                contract_address: read_hash(public_inputs, offset + 4),
+                variable_index: read_hash(public_inputs, offset + 8),
+                amount: public_inputs[offset + 12],
+            });
+            offset += 13;
+        }
+
+        ensure_protocol_version(public_inputs[offset + 60])?;
+
+        Ok(Self {
+            address_list,
+            deposit_list,
+            old_account_tree_root: read_hash(public_inputs, offset),
+            new_account_tree_root: read_hash(public_inputs, offset + 4),
+            old_world_state_root: read_hash(public_inputs, offset + 8),
+            new_world_state_root: read_hash(public_inputs, offset + 12),
+            old_prev_block_header_digest: read_hash(public_inputs, offset + 16),
+            new_prev_block_header_digest: read_hash(public_inputs, offset + 20),
+            block_hash: read_hash(public_inputs, offset + 24),
+            proposed_world_state_digest: read_hash(public_inputs, offset + 28),
+            approved_world_state_digest: read_hash(public_inputs, offset + 32),
+            latest_account_digest: read_hash(public_inputs, offset + 36),
+            tx_hash_list_digest: read_hash(public_inputs, offset + 40),
+            old_forced_transactions_digest: read_hash(public_inputs, offset + 44),
+            new_forced_transactions_digest: read_hash(public_inputs, offset + 48),
+            burned_assets_digest: read_hash(public_inputs, offset + 52),
+            withdrawal_root: read_hash(public_inputs, offset + 56),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_reject_other_protocol_version() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Sample};
+
+    let value = UserTransactionPublicInputs::<GoldilocksField> {
+        old_user_asset_root: HashOut::rand(),
+        middle_user_asset_root: HashOut::rand(),
+        new_user_asset_root: HashOut::rand(),
+        diff_root: HashOut::rand(),
+        sender_address: HashOut::rand(),
+        tx_hash: HashOut::rand(),
+        burned_assets_digest: HashOut::rand(),
+    };
+    let mut public_inputs = value.encode();
+    assert_eq!(
+        public_inputs.len(),
+        UserTransactionPublicInputs::<GoldilocksField>::LEN
+    );
+    assert_eq!(
+        UserTransactionPublicInputs::try_decode(&public_inputs).unwrap(),
+        value
+    );
+
+    *public_inputs.last_mut().unwrap() = GoldilocksField::from_canonical_u32(PROTOCOL_VERSION + 1);
+    assert!(UserTransactionPublicInputs::try_decode(&public_inputs).is_err());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_block_public_inputs_commitment() {
+    use plonky2::field::{goldilocks_field::GoldilocksField, types::Sample};
+
+    type F = GoldilocksField;
+
+    let tx_hashes = vec![HashOut::<F>::rand(), HashOut::rand()];
+    let value = BlockPublicInputs {
+        address_list: vec![(HashOut::rand(), true); 4],
+        deposit_list: vec![],
+        old_account_tree_root: HashOut::rand(),
+        new_account_tree_root: HashOut::rand(),
+        old_world_state_root: HashOut::rand(),
+        new_world_state_root: HashOut::rand(),
+        old_prev_block_header_digest: HashOut::rand(),
+        new_prev_block_header_digest: HashOut::rand(),
+        block_hash: HashOut::rand(),
+        proposed_world_state_digest: HashOut::rand(),
+        approved_world_state_digest: HashOut::rand(),
+        latest_account_digest: HashOut::rand(),
+        tx_hash_list_digest: get_tx_hash_list_digest(&tx_hashes, 4),
+        old_forced_transactions_digest: HashOut::rand(),
+        new_forced_transactions_digest: HashOut::rand(),
+        burned_assets_digest: HashOut::rand(),
+        withdrawal_root: HashOut::rand(),
+    };
+    let public_inputs = value.encode();
+    assert_eq!(
+        public_inputs.len(),
+        BlockPublicInputs::<F>::public_inputs_len(4, 0)
+    );
+    let decoded = BlockPublicInputs::try_decode(&public_inputs, 4, 0).unwrap();
+    assert_eq!(decoded, value);
+
+    decoded.verify_tx_hash_list(&tx_hashes).unwrap();
+    let commitment = BlockCommitmentPublicInputs {
+        commitment: value.commitment(),
+    };
+    assert_eq!(
+        BlockCommitmentPublicInputs::try_decode(&commitment.encode()).unwrap(),
+        commitment
+    );
+    commitment.open(&decoded).unwrap();
+    let mut other = decoded.clone();
+    other.address_list[3].1 = false;
+    assert!(commitment.open(&other).is_err());
+    // The zero padding is the same as omitting the trailing slots.
+    decoded
+        .verify_tx_hash_list(&[tx_hashes[0], tx_hashes[1], HashOut::ZERO])
+        .unwrap();
+    assert!(decoded
+        .verify_tx_hash_list(&[tx_hashes[1], tx_hashes[0]])
+        .is_err());
+    assert!(decoded.verify_tx_hash_list(&[tx_hashes[0]]).is_err());
+}
@@ -0,0 +1,246 @@
+//! Verifiers holding only the verifier data of the circuits, for light clients and the L1 bridge.
+//! They are built from the data serialized by `to_bytes` (or taken from a built circuit),
+//! so the prover keys are not needed.
+
+use alloc::vec::Vec;
+
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    plonk::{
+        circuit_data::{CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData},
+        config::GenericConfig,
+    },
+    util::serialization::GateSerializer,
+};
+
+use super::{
+    proof::{
+        verify_block_commitment_proof, verify_block_proof, verify_signature_proof,
+        verify_user_transaction_proof,
+    },
+    public_inputs::{
+        BlockCommitmentPublicInputs, BlockPublicInputs, SignaturePublicInputs,
+        UserTransactionPublicInputs,
+    },
+};
+
+/// Serializes the verifier data with plonky2's format.
+/// `gate_serializer` must support all the gates used by the circuit.
+pub fn write_verifier_data<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+    gate_serializer: &dyn GateSerializer<F, D>,
+) -> anyhow::Result<Vec<u8>> {
+    let verifier_data = VerifierCircuitData {
+        verifier_only: verifier_only.clone(),
+        common: common.clone(),
+    };
+
+    verifier_data
+        .to_bytes(gate_serializer)
+        .map_err(|_| anyhow::anyhow!("fail to serialize verifier data"))
+}
+
+pub fn read_verifier_data<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+>(
+    bytes: &[u8],
+    gate_serializer: &dyn GateSerializer<F, D>,
+) -> anyhow::Result<(VerifierOnlyCircuitData<C, D>, CommonCircuitData<F, D>)> {
+    let verifier_data = VerifierCircuitData::<F, C, D>::from_bytes(bytes.to_vec(), gate_serializer)
+        .map_err(|_| anyhow::anyhow!("invalid verifier data"))?;
+
+    Ok((verifier_data.verifier_only, verifier_data.common))
+}
+
+/// Verifies the proofs of the user transaction (merge and purge) circuit.
+#[derive(Clone, Debug)]
+pub struct UserTransactionVerifier<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+    pub common: CommonCircuitData<F, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    UserTransactionVerifier<F, C, D>
+{
+    pub fn from_bytes(
+        bytes: &[u8],
+        gate_serializer: &dyn GateSerializer<F, D>,
+    ) -> anyhow::Result<Self> {
+        let (verifier_only, common) = read_verifier_data(bytes, gate_serializer)?;
+
+        Ok(Self {
+            verifier_only,
+            common,
+        })
+    }
+
+    pub fn to_bytes(&self, gate_serializer: &dyn GateSerializer<F, D>) -> anyhow::Result<Vec<u8>> {
+        write_verifier_data(&self.verifier_only, &self.common, gate_serializer)
+    }
+
+    /// Verifies a proof serialized by `ProofWithPublicInputs::to_bytes` and returns its public inputs.
+    pub fn verify(&self, proof: &[u8]) -> anyhow::Result<UserTransactionPublicInputs<F>> {
+        verify_user_transaction_proof::<F, C, D>(proof, &self.verifier_only, &self.common)
+    }
+}
+
+/// Verifies the proofs of the simple signature circuit.
+#[derive(Clone, Debug)]
+pub struct SimpleSignatureVerifier<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+    pub common: CommonCircuitData<F, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    SimpleSignatureVerifier<F, C, D>
+{
+    pub fn from_bytes(
+        bytes: &[u8],
+        gate_serializer: &dyn GateSerializer<F, D>,
+    ) -> anyhow::Result<Self> {
+        let (verifier_only, common) = read_verifier_data(bytes, gate_serializer)?;
+
+        Ok(Self {
+            verifier_only,
+            common,
+        })
+    }
+
+    pub fn to_bytes(&self, gate_serializer: &dyn GateSerializer<F, D>) -> anyhow::Result<Vec<u8>> {
+        write_verifier_data(&self.verifier_only, &self.common, gate_serializer)
+    }
+
+    pub fn verify(&self, proof: &[u8]) -> anyhow::Result<SignaturePublicInputs<F>> {
+        verify_signature_proof::<F, C, D>(proof, &self.verifier_only, &self.common)
+    }
+}
+
+/// Verifies the proofs of the block (proposal and approval) circuit.
+/// `n_txs` and `n_deposits` are the parameters of the circuit,
+/// which determine the layout of the public inputs.
+#[derive(Clone, Debug)]
+pub struct BlockVerifier<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+    pub common: CommonCircuitData<F, D>,
+    pub n_txs: usize,
+    pub n_deposits: usize,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    BlockVerifier<F, C, D>
+{
+    pub fn from_bytes(
+        bytes: &[u8],
+        gate_serializer: &dyn GateSerializer<F, D>,
+        n_txs: usize,
+        n_deposits: usize,
+    ) -> anyhow::Result<Self> {
+        let (verifier_only, common) = read_verifier_data(bytes, gate_serializer)?;
+        anyhow::ensure!(
+            common.num_public_inputs
+                == BlockPublicInputs::<F>::public_inputs_len(n_txs, n_deposits),
+            "the verifier data does not match n_txs and n_deposits"
+        );
+
+        Ok(Self {
+            verifier_only,
+            common,
+            n_txs,
+            n_deposits,
+        })
+    }
+
+    pub fn to_bytes(&self, gate_serializer: &dyn GateSerializer<F, D>) -> anyhow::Result<Vec<u8>> {
+        write_verifier_data(&self.verifier_only, &self.common, gate_serializer)
+    }
+
+    pub fn verify(&self, proof: &[u8]) -> anyhow::Result<BlockPublicInputs<F>> {
+        verify_block_proof::<F, C, D>(
+            proof,
+            &self.verifier_only,
+            &self.common,
+            self.n_txs,
+            self.n_deposits,
+        )
+    }
+}
+
+/// Verifies the proofs of the block commitment circuit.
+#[derive(Clone, Debug)]
+pub struct BlockCommitmentVerifier<
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F>,
+    const D: usize,
+> {
+    pub verifier_only: VerifierOnlyCircuitData<C, D>,
+    pub common: CommonCircuitData<F, D>,
+}
+
+impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
+    BlockCommitmentVerifier<F, C, D>
+{
+    pub fn from_bytes(
+        bytes: &[u8],
+        gate_serializer: &dyn GateSerializer<F, D>,
+    ) -> anyhow::Result<Self> {
+        let (verifier_only, common) = read_verifier_data(bytes, gate_serializer)?;
+        anyhow::ensure!(
+            common.num_public_inputs == BlockCommitmentPublicInputs::<F>::LEN,
+            "the verifier data is not of the block commitment circuit"
+        );
+
+        Ok(Self {
+            verifier_only,
+            common,
+        })
+    }
+
+    pub fn to_bytes(&self, gate_serializer: &dyn GateSerializer<F, D>) -> anyhow::Result<Vec<u8>> {
+        write_verifier_data(&self.verifier_only, &self.common, gate_serializer)
+    }
+
+    pub fn verify(&self, proof: &[u8]) -> anyhow::Result<BlockCommitmentPublicInputs<F>> {
+        verify_block_commitment_proof::<F, C, D>(proof, &self.verifier_only, &self.common)
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_simple_signature_verifier() {
+    use plonky2::{
+        field::types::Sample, hash::hash_types::HashOut, iop::witness::PartialWitness,
+        plonk::proof::ProofWithPublicInputs, util::serialization::DefaultGateSerializer,
+    };
+
+    use crate::zkdsa::circuits::make_simple_signature_circuit;
+
+    let circuit = make_simple_signature_circuit();
+    let bytes = circuit.verifier().to_bytes(&DefaultGateSerializer).unwrap();
+    let verifier = SimpleSignatureVerifier::from_bytes(&bytes, &DefaultGateSerializer).unwrap();
+
+    let message = HashOut::rand();
+    let mut pw = PartialWitness::new();
+    circuit
+        .targets
+        .set_witness(&mut pw, HashOut::rand(), message);
+    let proof: ProofWithPublicInputs<_, _, 2> = circuit.prove(pw).unwrap().into();
+
+    let public_inputs = verifier.verify(&proof.to_bytes().unwrap()).unwrap();
+    assert_eq!(public_inputs.message, message);
+}
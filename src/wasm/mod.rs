@@ -0,0 +1,207 @@
+//! `wasm-bindgen` bindings to generate user proofs in the browser, enabled by the `wasm` feature.
+//!
+//! Every value crosses the boundary as a JSON string (or a `0x`-prefixed hex string for hashes),
+//! using the same serde representation as the remote prover.
+//! wasm32 has no threads, so plonky2 runs on the calling thread
+//! (build with `--no-default-features --features wasm` to leave out the `native` feature);
+//! call these functions from a Web Worker to keep the page responsive.
+//! The randomness of the keys and nonces is derived from a seed given by the caller.
+
+use std::str::FromStr;
+
+use plonky2::{
+    field::types::Sample,
+    hash::hash_types::HashOut,
+    iop::witness::PartialWitness,
+    plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    prover::backend::{CircuitWitness, SimpleSignatureWitness, UserTransactionWitness},
+    sparse_merkle_tree::goldilocks_poseidon::{
+        GoldilocksHashOut, LayeredLayeredPoseidonSparseMerkleTree, NodeDataMemory,
+    },
+    transaction::circuits::dynamic::{
+        make_user_proof_circuit_with_parameters, DynMergeAndPurgeTransitionCircuit,
+        RollupParameters,
+    },
+    zkdsa::{
+        account::private_key_to_account,
+        circuits::{make_simple_signature_circuit, SimpleSignatureCircuit},
+    },
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+fn to_js_error(err: impl std::fmt::Display) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn parse_hash(value: &str) -> Result<GoldilocksHashOut, JsError> {
+    GoldilocksHashOut::from_str(value.trim_start_matches("0x")).map_err(to_js_error)
+}
+
+fn format_hash(value: GoldilocksHashOut) -> String {
+    format!("0x{}", value)
+}
+
+fn seeded_rng(seed: &[u8]) -> Result<StdRng, JsError> {
+    let seed: [u8; 32] = seed
+        .try_into()
+        .map_err(|_| JsError::new("seed must be 32 bytes"))?;
+
+    Ok(StdRng::from_seed(seed))
+}
+
+#[derive(Serialize)]
+struct WasmAccount {
+    private_key: GoldilocksHashOut,
+    public_key: GoldilocksHashOut,
+    address: GoldilocksHashOut,
+}
+
+/// Returns the JSON of `{ private_key, public_key, address }` derived from the 32-byte `seed`.
+#[wasm_bindgen(js_name = generateAccount)]
+pub fn generate_account(seed: &[u8]) -> Result<String, JsError> {
+    let mut rng = seeded_rng(seed)?;
+    let account = private_key_to_account(HashOut::<F>::sample(&mut rng));
+
+    serde_json::to_string(&WasmAccount {
+        private_key: account.private_key.into(),
+        public_key: account.public_key.into(),
+        address: account.address.0.into(),
+    })
+    .map_err(to_js_error)
+}
+
+/// Returns a random hash derived from the 32-byte `seed`, e.g. for the nonce of a transaction.
+#[wasm_bindgen(js_name = generateNonce)]
+pub fn generate_nonce(seed: &[u8]) -> Result<String, JsError> {
+    let mut rng = seeded_rng(seed)?;
+
+    Ok(format_hash(HashOut::<F>::sample(&mut rng).into()))
+}
+
+#[wasm_bindgen]
+pub struct UserTransactionProver {
+    circuit: DynMergeAndPurgeTransitionCircuit<F, C, D>,
+}
+
+#[wasm_bindgen]
+impl UserTransactionProver {
+    /// `parameters` is the JSON of `RollupParameters`.
+    /// Building the circuit takes a while, so reuse the prover for every transaction.
+    #[wasm_bindgen(constructor)]
+    pub fn new(parameters: &str) -> Result<UserTransactionProver, JsError> {
+        let parameters: RollupParameters = serde_json::from_str(parameters).map_err(to_js_error)?;
+        let circuit =
+            make_user_proof_circuit_with_parameters::<F, C, D>(parameters).map_err(to_js_error)?;
+
+        Ok(Self { circuit })
+    }
+
+    /// Proves the JSON of `UserTransactionWitness` and returns the JSON of the proof.
+    pub fn prove(&self, witness: &str) -> Result<String, JsError> {
+        let witness: UserTransactionWitness<F> =
+            serde_json::from_str(witness).map_err(to_js_error)?;
+        witness.validate().map_err(to_js_error)?;
+
+        let mut pw = PartialWitness::new();
+        witness
+            .set_witness(&self.circuit, &mut pw)
+            .map_err(to_js_error)?;
+        let proof = self.circuit.prove(pw).map_err(to_js_error)?;
+
+        serde_json::to_string(&proof).map_err(to_js_error)
+    }
+}
+
+#[wasm_bindgen]
+pub struct SimpleSignatureProver {
+    circuit: SimpleSignatureCircuit<F, C, D>,
+}
+
+#[wasm_bindgen]
+impl SimpleSignatureProver {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> SimpleSignatureProver {
+        Self {
+            circuit: make_simple_signature_circuit(),
+        }
+    }
+
+    /// Signs `message` (a hex string) and returns the JSON of the signature proof.
+    pub fn sign(&self, private_key: &str, message: &str) -> Result<String, JsError> {
+        let witness = SimpleSignatureWitness::<F> {
+            private_key: parse_hash(private_key)?,
+            message: parse_hash(message)?,
+        };
+
+        let mut pw = PartialWitness::new();
+        witness
+            .set_witness(&self.circuit, &mut pw)
+            .map_err(to_js_error)?;
+        let proof = self.circuit.prove(pw).map_err(to_js_error)?;
+
+        serde_json::to_string(&proof).map_err(to_js_error)
+    }
+}
+
+/// The user asset tree kept by the wallet in memory.
+#[wasm_bindgen]
+pub struct UserAssetTree {
+    tree: LayeredLayeredPoseidonSparseMerkleTree<NodeDataMemory>,
+}
+
+#[wasm_bindgen]
+impl UserAssetTree {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> UserAssetTree {
+        Self {
+            tree: Default::default(),
+        }
+    }
+
+    pub fn root(&self) -> String {
+        format_hash(self.tree.get_root())
+    }
+
+    /// Sets `value` and returns the JSON of the process proofs used as purge witnesses.
+    /// The entry is removed if `value` is zero.
+    pub fn set(
+        &mut self,
+        key1: &str,
+        key2: &str,
+        key3: &str,
+        value: &str,
+    ) -> Result<String, JsError> {
+        let proof = self
+            .tree
+            .set(
+                parse_hash(key1)?,
+                parse_hash(key2)?,
+                parse_hash(key3)?,
+                parse_hash(value)?,
+            )
+            .map_err(to_js_error)?;
+
+        serde_json::to_string(&proof).map_err(to_js_error)
+    }
+
+    /// Returns the JSON of the inclusion proofs of the entry.
+    pub fn find(&self, key1: &str, key2: &str, key3: &str) -> Result<String, JsError> {
+        let proof = self
+            .tree
+            .find(&parse_hash(key1)?, &parse_hash(key2)?, &parse_hash(key3)?)
+            .map_err(to_js_error)?;
+
+        serde_json::to_string(&proof).map_err(to_js_error)
+    }
+}
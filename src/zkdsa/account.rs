@@ -1,4 +1,4 @@
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, hash::Hash, str::FromStr};
 
 use plonky2::{
     field::{
@@ -14,15 +14,41 @@ use plonky2::{
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_hex::{SerHexSeq, StrictPfx};
 
-use crate::sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut;
+use crate::{
+    sparse_merkle_tree::goldilocks_poseidon::{parse_prefixed_hex, WrappedHashOut},
+    zkdsa::circuits::SimpleSignaturePublicInputs,
+};
 
 pub type SecretKey<F> = HashOut<F>;
 pub type PublicKey<F> = HashOut<F>;
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+/// A signature is the public inputs of the simple signature circuit proving it.
+pub type SimpleSignature<F> = SimpleSignaturePublicInputs<F>;
+
+#[derive(Clone, Copy, Default, Debug)]
 #[repr(transparent)]
 pub struct Address<F: Field>(pub HashOut<F>);
 
+/// Compares all the elements without short-circuiting,
+/// so that the time does not depend on where the addresses differ.
+impl<F: Field> PartialEq for Address<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0
+            .elements
+            .iter()
+            .zip(other.0.elements.iter())
+            .fold(true, |acc, (a, b)| acc & std::hint::black_box(a == b))
+    }
+}
+
+impl<F: Field> Eq for Address<F> {}
+
+impl<F: Field> Hash for Address<F> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SerializableAddress(#[serde(with = "SerHexSeq::<StrictPfx>")] pub Vec<u8>);
 
@@ -72,6 +98,10 @@ impl<F: RichField> Serialize for Address<F> {
     where
         S: Serializer,
     {
+        if !serializer.is_human_readable() {
+            return self.0.serialize(serializer);
+        }
+
         let raw = format!("0x{}", self);
 
         serializer.serialize_str(&raw)
@@ -83,9 +113,14 @@ impl<'de, F: RichField> Deserialize<'de> for Address<F> {
     where
         D: Deserializer<'de>,
     {
+        if !deserializer.is_human_readable() {
+            return HashOut::deserialize(deserializer).map(Address);
+        }
+
         let raw = String::deserialize(deserializer)?;
+        let value = parse_prefixed_hex(&raw).map_err(serde::de::Error::custom)?;
 
-        Ok(Address::from_str(&raw[2..]).unwrap())
+        Ok(Address(value.0))
     }
 }
 
@@ -110,6 +145,30 @@ fn test_serialize_address() {
     assert_eq!(decoded_value, value);
 }
 
+#[test]
+fn test_address_as_map_key() {
+    use std::collections::HashMap;
+
+    let address: Address<GoldilocksField> = Address::from_str("01").unwrap();
+    let other_address: Address<GoldilocksField> = Address::from_str("0100").unwrap();
+    assert_ne!(address, other_address);
+
+    let mut balances = HashMap::new();
+    balances.insert(address, 1u64);
+    balances.insert(other_address, 2u64);
+    assert_eq!(balances[&Address::from_str("01").unwrap()], 1);
+    assert_eq!(balances.len(), 2);
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, F: RichField> arbitrary::Arbitrary<'a> for Address<F> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let value: WrappedHashOut<F> = u.arbitrary()?;
+
+        Ok(Address(value.0))
+    }
+}
+
 impl<F: Field> std::ops::Deref for Address<F> {
     type Target = HashOut<F>;
 
@@ -151,6 +210,39 @@ pub fn public_key_to_address<F: RichField>(public_key: PublicKey<F>) -> Address<
     Address(public_key)
 }
 
+/// The same signature as `SimpleSignatureTarget` computes.
+pub fn sign_message<F: RichField>(
+    private_key: SecretKey<F>,
+    message: HashOut<F>,
+) -> SimpleSignature<F> {
+    SimpleSignature {
+        message,
+        public_key: private_key_to_public_key(private_key),
+        signature: PoseidonHash::two_to_one(private_key, message),
+    }
+}
+
+/// Checks off-circuit that `signature` is of `message` by `address`, e.g. before proving it
+/// or against the public inputs of a signature proof. The signature is a hash of the private key,
+/// so only the proof shows that the signer knows the private key.
+pub fn verify_signature<F: RichField>(
+    address: Address<F>,
+    message: HashOut<F>,
+    signature: &SimpleSignature<F>,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        public_key_to_address(signature.public_key) == address,
+        "the signature is not by {}",
+        address
+    );
+    anyhow::ensure!(
+        signature.message == message,
+        "the signature is of another message"
+    );
+
+    Ok(())
+}
+
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Account<F: RichField> {
     pub private_key: SecretKey<F>,
@@ -230,4 +322,23 @@ impl<F: RichField> Account<F> {
 
         Account::new(private_key)
     }
+
+    pub fn public_key(&self) -> PublicKey<F> {
+        self.public_key
+    }
+
+    pub fn sign(&self, message: HashOut<F>) -> SimpleSignature<F> {
+        sign_message(self.private_key, message)
+    }
+}
+
+#[test]
+fn test_verify_signature() {
+    let account: Account<GoldilocksField> = Account::rand();
+    let message = HashOut::rand();
+    let signature = account.sign(message);
+    assert_eq!(signature.public_key, account.public_key());
+    verify_signature(account.address, message, &signature).unwrap();
+    assert!(verify_signature(Address::rand(), message, &signature).is_err());
+    assert!(verify_signature(account.address, HashOut::rand(), &signature).is_err());
 }
@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use plonky2::{
     field::{extension::Extendable, types::Field},
     hash::hash_types::{HashOut, HashOutTarget, RichField},
@@ -9,9 +11,15 @@ use plonky2::{
         proof::{Proof, ProofWithPublicInputs},
     },
 };
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::sparse_merkle_tree::goldilocks_poseidon::WrappedHashOut;
+use crate::{
+    errors::IntmaxError,
+    monitoring,
+    sparse_merkle_tree::goldilocks_poseidon::{hash_out_hex, WrappedHashOut},
+    verification::{public_inputs::SignaturePublicInputs, verifier::SimpleSignatureVerifier},
+};
 
 use super::gadgets::signature::SimpleSignatureTarget;
 
@@ -20,9 +28,22 @@ type H = <C as GenericConfig<D>>::InnerHasher;
 type F = <C as GenericConfig<D>>::F;
 const D: usize = 2;
 
+/// Builds the circuit with `CircuitConfig::standard_recursion_config()`.
 pub fn make_simple_signature_circuit() -> SimpleSignatureCircuit<F, C, D> {
-    // let config = CircuitConfig::standard_recursion_zk_config(); // TODO
-    let config = CircuitConfig::standard_recursion_config();
+    make_simple_signature_circuit_with_config(CircuitConfig::standard_recursion_config())
+}
+
+/// Builds the circuit with `CircuitConfig::standard_recursion_zk_config()`,
+/// so that the proofs do not reveal anything about the witness.
+/// They can be verified recursively as well as the proofs of the non-zk circuit.
+pub fn make_simple_signature_circuit_zk() -> SimpleSignatureCircuit<F, C, D> {
+    make_simple_signature_circuit_with_config(CircuitConfig::standard_recursion_zk_config())
+}
+
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+pub fn make_simple_signature_circuit_with_config(
+    config: CircuitConfig,
+) -> SimpleSignatureCircuit<F, C, D> {
     let mut builder = CircuitBuilder::<F, D>::new(config);
 
     let targets = SimpleSignatureTarget::add_virtual_to::<F, H, D>(&mut builder);
@@ -30,6 +51,7 @@ pub fn make_simple_signature_circuit() -> SimpleSignatureCircuit<F, C, D> {
     builder.register_public_inputs(&targets.public_key.elements); // public_inputs[4..8]
     builder.register_public_inputs(&targets.signature.elements); // public_inputs[8..12]
     let zkdsa_circuit_data = builder.build::<C>();
+    monitoring::record_circuit_size("simple_signature", zkdsa_circuit_data.common.degree_bits());
 
     SimpleSignatureCircuit {
         data: zkdsa_circuit_data,
@@ -47,10 +69,13 @@ pub struct SimpleSignatureCircuit<
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(bound = "")]
+#[serde(bound = "F: RichField")]
 pub struct SimpleSignaturePublicInputs<F: Field> {
+    #[serde(with = "hash_out_hex")]
     pub message: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub public_key: HashOut<F>,
+    #[serde(with = "hash_out_hex")]
     pub signature: HashOut<F>,
 }
 
@@ -64,6 +89,18 @@ impl<F: Field> SimpleSignaturePublicInputs<F> {
         public_inputs
     }
 
+    /// Same as `decode`, but returns an error if the length of `public_inputs` is wrong.
+    pub fn try_decode(public_inputs: &[F]) -> Result<Self, IntmaxError> {
+        if public_inputs.len() != 12 {
+            return Err(IntmaxError::InvalidPublicInputsLength {
+                expected: 12,
+                actual: public_inputs.len(),
+            });
+        }
+
+        Ok(Self::decode(public_inputs))
+    }
+
     pub fn decode(public_inputs: &[F]) -> Self {
         let message = HashOut::from_partial(&public_inputs[0..4]);
         let public_key = HashOut::from_partial(&public_inputs[4..8]);
@@ -77,6 +114,26 @@ impl<F: Field> SimpleSignaturePublicInputs<F> {
     }
 }
 
+impl<F: Field> From<SimpleSignaturePublicInputs<F>> for SignaturePublicInputs<F> {
+    fn from(value: SimpleSignaturePublicInputs<F>) -> Self {
+        Self {
+            message: value.message,
+            public_key: value.public_key,
+            signature: value.signature,
+        }
+    }
+}
+
+impl<F: Field> From<SignaturePublicInputs<F>> for SimpleSignaturePublicInputs<F> {
+    fn from(value: SignaturePublicInputs<F>) -> Self {
+        Self {
+            message: value.message,
+            public_key: value.public_key,
+            signature: value.signature,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SimpleSignaturePublicInputsTarget {
     pub message: HashOutTarget,
@@ -150,15 +207,27 @@ impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
         parse_simple_signature_public_inputs(&public_inputs_t)
     }
 
+    /// Returns the verifier without the prover data.
+    pub fn verifier(&self) -> SimpleSignatureVerifier<F, C, D> {
+        SimpleSignatureVerifier {
+            verifier_only: self.data.verifier_only.clone(),
+            common: self.data.common.clone(),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn prove(
         &self,
         inputs: PartialWitness<F>,
     ) -> anyhow::Result<SimpleSignatureProofWithPublicInputs<F, C, D>> {
+        let start = Instant::now();
         let proof_with_pis = self.data.prove(inputs)?;
+        monitoring::record_proving_duration("simple_signature", start.elapsed());
 
         Ok(proof_with_pis.into())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
     pub fn verify(
         &self,
         proof_with_pis: SimpleSignatureProofWithPublicInputs<F, C, D>,
@@ -170,6 +239,18 @@ impl<F: RichField + Extendable<D>, C: GenericConfig<D, F = F>, const D: usize>
             public_inputs,
         })
     }
+
+    /// Verifies a plonky2-native proof after checking the length of its public inputs.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn verify_raw(
+        &self,
+        proof_with_pis: ProofWithPublicInputs<F, C, D>,
+    ) -> anyhow::Result<SimpleSignaturePublicInputs<F>> {
+        let public_inputs = SimpleSignaturePublicInputs::try_decode(&proof_with_pis.public_inputs)?;
+        self.data.verify(proof_with_pis)?;
+
+        Ok(public_inputs)
+    }
 }
 
 #[test]
@@ -182,7 +263,7 @@ fn test_verify_simple_signature_by_plonky2() {
         plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
     };
 
-    use super::account::private_key_to_account;
+    use super::account::{private_key_to_account, verify_signature};
 
     const D: usize = 2; // extension degree
     type C = PoseidonGoldilocksConfig;
@@ -210,6 +291,22 @@ fn test_verify_simple_signature_by_plonky2() {
 
     assert_eq!(account.public_key, proof.public_inputs.public_key);
 
+    let raw_proof = ProofWithPublicInputs {
+        proof: proof.proof.clone(),
+        public_inputs: proof.public_inputs.encode(),
+    };
+    let public_inputs = simple_signature_circuit
+        .verify_raw(raw_proof.clone())
+        .unwrap();
+    assert_eq!(public_inputs, proof.public_inputs);
+    let truncated_proof = ProofWithPublicInputs {
+        public_inputs: raw_proof.public_inputs[..8].to_vec(),
+        ..raw_proof
+    };
+    assert!(simple_signature_circuit
+        .verify_raw(truncated_proof)
+        .is_err());
+
     match simple_signature_circuit.verify(proof) {
         Ok(()) => println!("Ok!"),
         Err(x) => println!("{}", x),
@@ -243,3 +340,42 @@ pub fn prove_simple_signature<
 
     Ok(simple_signature_proof)
 }
+
+/// Proves the signatures of `(private_key, message)` in parallel with `simple_signature_circuit`,
+/// e.g. of the senders approving a block. The proofs are in the same order as `signatures`.
+pub fn prove_signatures_batch(
+    simple_signature_circuit: &SimpleSignatureCircuit<F, C, D>,
+    signatures: &[(WrappedHashOut<F>, WrappedHashOut<F>)],
+) -> anyhow::Result<Vec<SimpleSignatureProofWithPublicInputs<F, C, D>>> {
+    signatures
+        .par_iter()
+        .map(|(private_key, message)| {
+            let mut pw = PartialWitness::new();
+            simple_signature_circuit
+                .targets
+                .set_witness(&mut pw, **private_key, **message);
+
+            simple_signature_circuit.prove(pw)
+        })
+        .collect()
+}
+
+#[test]
+fn test_prove_signatures_batch() {
+    use plonky2::field::types::Sample;
+
+    use super::account::private_key_to_account;
+
+    let simple_signature_circuit = make_simple_signature_circuit();
+    let signatures = (0..3)
+        .map(|_| (WrappedHashOut::rand(), WrappedHashOut::rand()))
+        .collect::<Vec<_>>();
+    let proofs = prove_signatures_batch(&simple_signature_circuit, &signatures).unwrap();
+    assert_eq!(proofs.len(), signatures.len());
+    for ((private_key, message), proof) in signatures.iter().zip(proofs) {
+        let account = private_key_to_account(**private_key);
+        assert_eq!(proof.public_inputs, account.sign(**message));
+        verify_signature(account.address, **message, &proof.public_inputs).unwrap();
+        simple_signature_circuit.verify(proof).unwrap();
+    }
+}
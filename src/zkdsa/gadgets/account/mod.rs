@@ -20,6 +20,11 @@ impl AddressTarget {
         Self(target)
     }
 
+    /// The in-circuit counterpart of `public_key_to_address`.
+    pub fn from_public_key(public_key: HashOutTarget) -> Self {
+        Self(public_key)
+    }
+
     pub fn set_witness<F: Field>(&self, pw: &mut impl Witness<F>, value: Address<F>) {
         pw.set_hash_target(self.0, value.0);
     }